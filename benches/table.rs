@@ -0,0 +1,194 @@
+//! timings of the table operations the performance work is measured against, run
+//! with `cargo bench` or `cargo bench -- NAME` for the benches whose name contains
+//! NAME.
+
+extern crate rdb;
+
+use std::env;
+use std::time::{Duration, Instant};
+
+use rdb::internals::btree::{BTree, BTreeTrait};
+use rdb::internals::pager::Pager;
+use rdb::internals::table::Table;
+use rdb::{DbOption, Row, SyncMode};
+use rdb::{Database, MEMORY_DB};
+
+const NUM_ROWS: u32 = 50_000;
+
+/// a bench returns the time taken by what it measures, without its setup.
+type Bench = fn() -> Duration;
+
+fn row(id: u32) -> Row {
+    Row {
+        id,
+        username: format!("user{}", id),
+        email: format!("person{}@example.com", id),
+    }
+}
+
+/// the ids from 1 to `NUM_ROWS` shuffled the same way on every run.
+fn shuffled_ids() -> Vec<u32> {
+    let mut ids: Vec<u32> = (1..=NUM_ROWS).collect();
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    for i in (1..ids.len()).rev() {
+        // xorshift64
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        ids.swap(i, (seed % (i as u64 + 1)) as usize);
+    }
+    ids
+}
+
+fn single_row_inserts() -> Duration {
+    let mut table = Table::in_memory_for_test();
+    let start = Instant::now();
+    for id in 1..=NUM_ROWS {
+        table.insert_cursor(id).save(&row(id)).unwrap();
+        table.commit().unwrap();
+    }
+    start.elapsed()
+}
+
+fn insert_many_sorted() -> Duration {
+    let mut table = Table::in_memory_for_test();
+    let rows: Vec<Row> = (1..=NUM_ROWS).map(row).collect();
+    let start = Instant::now();
+    assert_eq!(table.insert_many(rows).unwrap().inserted, NUM_ROWS as usize);
+    start.elapsed()
+}
+
+fn insert_many_random() -> Duration {
+    let mut table = Table::in_memory_for_test();
+    let rows: Vec<Row> = shuffled_ids().into_iter().map(row).collect();
+    let start = Instant::now();
+    assert_eq!(table.insert_many(rows).unwrap().inserted, NUM_ROWS as usize);
+    start.elapsed()
+}
+
+fn full_scan() -> Duration {
+    let mut table = Table::in_memory_for_test();
+    table.insert_many((1..=NUM_ROWS).map(row)).unwrap();
+    let start = Instant::now();
+    let mut cursor = table.select_cursor().unwrap();
+    let mut num_rows = 0;
+    while !cursor.end_of_table() {
+        cursor.get();
+        cursor.advance().unwrap();
+        num_rows += 1;
+    }
+    assert_eq!(num_rows, NUM_ROWS);
+    start.elapsed()
+}
+
+fn cursor_inserts() -> Duration {
+    let mut table = Table::in_memory_for_test();
+    let start = Instant::now();
+    for id in 1..=NUM_ROWS {
+        table.insert_cursor(id).save(&row(id)).unwrap();
+    }
+    start.elapsed()
+}
+
+fn btree_sequential_inserts() -> Duration {
+    let db_option = DbOption {
+        sync_mode: SyncMode::Off,
+        ..Default::default()
+    };
+    let mut tree = BTree::new(Pager::new(MEMORY_DB, db_option).unwrap());
+    let start = Instant::now();
+    for key in 1..=NUM_ROWS {
+        tree.insert_key(key).unwrap();
+    }
+    start.elapsed()
+}
+
+/// `select` decodes whole rows where `select id from users` reads the key only.
+fn query(sql: &str) -> Duration {
+    let db = Database::open(MEMORY_DB).unwrap();
+    let mut conn = db.connect();
+    conn.insert_many("users", (1..=NUM_ROWS).map(row)).unwrap();
+    let start = Instant::now();
+    assert_eq!(conn.query(sql).unwrap().count(), NUM_ROWS as usize);
+    start.elapsed()
+}
+
+fn select_whole_rows() -> Duration {
+    query("select")
+}
+
+fn select_id() -> Duration {
+    query("select id from users")
+}
+
+/// a full scan of a db file written and reopened with given backend.
+#[cfg(feature = "file")]
+fn file_scan_with(use_mmap: bool) -> Duration {
+    use std::fs;
+    use std::process;
+
+    let path = env::temp_dir().join(format!("rdb-bench-scan-{}-{}.rdb", use_mmap, process::id()));
+    let path = path.to_str().unwrap();
+    let _ = fs::remove_file(path);
+    let db_option = DbOption {
+        sync_mode: SyncMode::Off,
+        use_mmap,
+        ..Default::default()
+    };
+    {
+        let mut table = Table::new(BTree::new(Pager::new(path, db_option.clone()).unwrap()));
+        table.insert_many((1..=NUM_ROWS).map(row)).unwrap();
+        table.close().unwrap();
+    }
+    let table = Table::new(BTree::new(Pager::new(path, db_option).unwrap()));
+    let start = Instant::now();
+    let mut cursor = table.select_cursor().unwrap();
+    let mut num_rows = 0;
+    while !cursor.end_of_table() {
+        cursor.get();
+        cursor.advance().unwrap();
+        num_rows += 1;
+    }
+    assert_eq!(num_rows, NUM_ROWS);
+    let elapsed = start.elapsed();
+    let _ = fs::remove_file(path);
+    elapsed
+}
+
+#[cfg(feature = "file")]
+fn file_scan() -> Duration {
+    file_scan_with(false)
+}
+
+#[cfg(feature = "mmap")]
+fn mmap_scan() -> Duration {
+    file_scan_with(true)
+}
+
+fn main() {
+    #[allow(unused_mut)]
+    let mut benches: Vec<(&str, Bench)> = vec![
+        ("single_row_inserts", single_row_inserts),
+        ("cursor_inserts", cursor_inserts),
+        ("btree_sequential_inserts", btree_sequential_inserts),
+        ("insert_many_sorted", insert_many_sorted),
+        ("insert_many_random", insert_many_random),
+        ("full_scan", full_scan),
+        ("select_whole_rows", select_whole_rows),
+        ("select_id", select_id),
+    ];
+    #[cfg(feature = "file")]
+    benches.push(("file_scan", file_scan));
+    #[cfg(feature = "mmap")]
+    benches.push(("mmap_scan", mmap_scan));
+    // cargo passes `--bench`
+    let filters: Vec<String> = env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .collect();
+    for (name, bench) in benches {
+        if filters.is_empty() || filters.iter().any(|filter| name.contains(filter.as_str())) {
+            println!("{} ({} rows): {:?}", name, NUM_ROWS, bench());
+        }
+    }
+}
@@ -0,0 +1,9 @@
+//! hands the target triple to tests/ffi.rs, which builds a C program against the
+//! static library with the cc crate.
+
+use std::env;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rustc-env=RDB_TARGET={}", env::var("TARGET").unwrap());
+}
@@ -0,0 +1,42 @@
+//! open a db file, fill it and read the rows back without the REPL.
+
+extern crate rdb;
+
+use std::env;
+use std::fs;
+
+use rdb::Database;
+
+fn main() {
+    let path = env::temp_dir().join(format!("rdb-embed-{}.rdb", std::process::id()));
+    let path = path.to_str().unwrap().to_owned();
+
+    let db = Database::open(&path).unwrap();
+    println!("tables: {:?}", db.tables().unwrap());
+    {
+        let mut conn = db.connect();
+        {
+            let mut insert = conn.prepare("insert ? ? ?").unwrap();
+            for (id, name) in ["ada", "grace", "linus"].iter().enumerate() {
+                insert.bind(1, id as i64 + 1).unwrap();
+                insert.bind(2, *name).unwrap();
+                insert.bind(3, format!("{}@example.com", name)).unwrap();
+                insert.execute().unwrap();
+            }
+        }
+        let rows = conn.query("select").unwrap();
+        println!("{}", rows.columns().join(" | "));
+        for row in rows {
+            let values: Vec<String> = row
+                .values()
+                .iter()
+                .map(|value| format!("{}", value))
+                .collect();
+            println!("{}", values.join(" | "));
+        }
+        let row = conn.query("select 40 + 2").unwrap().next().unwrap();
+        println!("40 + 2 = {}", row.get::<i64, _>(0).unwrap());
+    }
+    db.close().unwrap();
+    fs::remove_file(&path).unwrap();
+}
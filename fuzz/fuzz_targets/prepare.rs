@@ -0,0 +1,16 @@
+//! prepares arbitrary bytes as a statement, run with `cargo fuzz run prepare`.
+//! inputs that made it panic belong in tests/prepare.rs once fixed.
+
+#![no_main]
+
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate rdb;
+
+use rdb::table::schema::Schema;
+use rdb::vm::Statement;
+
+fuzz_target!(|data: &[u8]| {
+    let input = String::from_utf8_lossy(data);
+    let _ = Statement::prepare(&input, &Schema::new());
+});
@@ -0,0 +1,125 @@
+#ifndef RDB_H
+#define RDB_H
+
+/* generated by cbindgen from src/ffi.rs, do not edit */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define RDB_OK 0
+
+// a statement failed, see `rdb_errmsg`.
+#define RDB_ERROR 1
+
+// rdb panicked, the handle should be closed.
+#define RDB_INTERNAL 2
+
+// the callback of `rdb_exec` asked to stop.
+#define RDB_ABORT 4
+
+// the db stayed locked by another connection for the whole busy timeout.
+#define RDB_BUSY 5
+
+// a NULL handle or string, or a string that is not UTF-8.
+#define RDB_MISUSE 21
+
+// a parameter index out of range.
+#define RDB_RANGE 25
+
+// `rdb_step` has a row ready.
+#define RDB_ROW 100
+
+// `rdb_step` ran the statement to its end.
+#define RDB_DONE 101
+
+// an open db, made by `rdb_open` and freed by `rdb_close`.
+typedef struct rdb rdb;
+
+// a prepared statement, made by `rdb_prepare` and freed by `rdb_finalize`. it
+// must be finalized before its db is closed.
+typedef struct rdb_stmt rdb_stmt;
+
+// the callback of `rdb_exec`, called with the values and names of each row.
+// NULL values are NULL pointers, a non-zero return stops the statement.
+typedef int (*rdb_callback)(void *userdata, int columns, char **values, char **names);
+
+// open the db file at given path, `:memory:` for an in-memory db. `*db` is set
+// even on error so `rdb_errmsg` can tell why, it must be closed either way.
+//
+// # Safety
+// `path` must be NULL or a NUL-terminated string, `db` a valid pointer.
+int rdb_open(const char *path, struct rdb **db);
+
+// write all changes and free the db, NULL is a no-op.
+//
+// # Safety
+// `db` must be NULL or made by `rdb_open`, its statements finalized.
+int rdb_close(struct rdb *db);
+
+// the message of the last failed call on `db`, it stays valid until the next
+// call on `db`.
+//
+// # Safety
+// `db` must be NULL or made by `rdb_open`.
+const char *rdb_errmsg(struct rdb *db);
+
+// run a statement, its rows are passed to `callback` if it is not NULL.
+//
+// # Safety
+// `db` must be made by `rdb_open`, `sql` a NUL-terminated string.
+int rdb_exec(struct rdb *db, const char *sql, rdb_callback callback, void *userdata);
+
+// prepare a statement with `?` parameters to bind before `rdb_step`.
+//
+// # Safety
+// `db` must be made by `rdb_open`, `sql` a NUL-terminated string and `stmt` a
+// valid pointer.
+int rdb_prepare(struct rdb *db, const char *sql, struct rdb_stmt **stmt);
+
+// # Safety
+// `stmt` must be made by `rdb_prepare`.
+int rdb_bind_int(struct rdb_stmt *stmt, int index, int64_t value);
+
+// the text is copied.
+//
+// # Safety
+// `stmt` must be made by `rdb_prepare`, `value` a NUL-terminated string.
+int rdb_bind_text(struct rdb_stmt *stmt, int index, const char *value);
+
+// run the statement up to its next row. returns `RDB_ROW` while there are
+// rows and `RDB_DONE` at the end, the step after that runs it again.
+//
+// # Safety
+// `stmt` must be made by `rdb_prepare`.
+int rdb_step(struct rdb_stmt *stmt);
+
+// the number of columns of the statement's rows.
+//
+// # Safety
+// `stmt` must be made by `rdb_prepare`.
+int rdb_column_count(struct rdb_stmt *stmt);
+
+// the value at given column of the current row, starting at 0. 0 for text,
+// NULL or a column out of range.
+//
+// # Safety
+// `stmt` must be made by `rdb_prepare`.
+int64_t rdb_column_int(struct rdb_stmt *stmt, int column);
+
+// the value at given column of the current row as text, NULL for NULL or a
+// column out of range. it stays valid until the next step or the finalize.
+//
+// # Safety
+// `stmt` must be made by `rdb_prepare`.
+const char *rdb_column_text(struct rdb_stmt *stmt, int column);
+
+// free a statement, NULL is a no-op.
+//
+// # Safety
+// `stmt` must be NULL or made by `rdb_prepare`.
+int rdb_finalize(struct rdb_stmt *stmt);
+
+#endif  /* RDB_H */
@@ -16,7 +16,7 @@ describe 'database' do
       # Read entire output
       raw_output = pipe.gets(nil)
     end
-    raw_output.split("\n")
+    (raw_output || "").split("\n")
   end
 
   it 'inserts and retreives a row' do
@@ -26,10 +26,7 @@ describe 'database' do
       ".exit",
     ])
     expect(result).to eq([
-      "rdb > Executed.",
-      "rdb > (1, user1, person1@example.com)",
-      "Executed.",
-      "rdb > ",
+      "(1, 'user1', 'person1@example.com')",
     ])
   end
 
@@ -43,10 +40,7 @@ describe 'database' do
     ]
     result = run_script(script)
     expect(result).to eq([
-      "rdb > Executed.",
-      "rdb > (1, #{long_username}, #{long_email})",
-      "Executed.",
-      "rdb > ",
+      "(1, '#{long_username}', '#{long_email}')",
     ])
   end
 
@@ -60,10 +54,9 @@ describe 'database' do
     ]
     result = run_script(script)
     expect(result).to eq([
-      "rdb > String is too long.",
-      "rdb > Executed.",
-      "rdb > ",
+      "String is too long.",
     ])
+    expect($?.exitstatus).to eq(1)
   end
 
   it 'prints an error message if id is negative' do
@@ -74,9 +67,45 @@ describe 'database' do
     ]
     result = run_script(script)
     expect(result).to eq([
-      "rdb > ID must be positive.",
-      "rdb > Executed.",
-      "rdb > ",
+      "ID must be positive.",
+    ])
+    expect($?.exitstatus).to eq(1)
+  end
+
+  it 'prints an error message if id is not a number' do
+    result = run_script([
+      "insert abc cstack foo@bar.com",
+      ".exit",
+    ])
+    expect(result).to eq([
+      "invalid id 'abc': not a number",
+    ])
+    expect($?.exitstatus).to eq(1)
+  end
+
+  it 'allows quoted usernames with spaces' do
+    result = run_script([
+      "insert 1 'Ada Lovelace' ada@example.com",
+      "select",
+      ".exit",
+    ])
+    expect(result).to eq([
+      "(1, 'Ada Lovelace', 'ada@example.com')",
+    ])
+  end
+
+  it 'flushes and exits at the end of input' do
+    result = run_script([
+      "insert 1 user1 person1@example.com",
+    ])
+    expect(result).to eq([])
+    expect($?.exitstatus).to eq(0)
+    result = run_script([
+      "select",
+      ".quit",
+    ])
+    expect(result).to eq([
+      "(1, 'user1', 'person1@example.com')",
     ])
   end
 
@@ -86,17 +115,48 @@ describe 'database' do
       ".exit",
     ])
     expect(result1).to eq([
-      "rdb > Executed.",
-      "rdb > ",
     ])
     result2 = run_script([
       "select",
       ".exit",
     ])
     expect(result2).to eq([
-      "rdb > (1, user1, person1@example.com)",
-      "Executed.",
-      "rdb > ",
+      "(1, 'user1', 'person1@example.com')",
+    ])
+  end
+
+  it 'refuses to open a file that is not an rdb database' do
+    File.write("test.rdb", "hello, rdb!\n" * 10)
+    result = run_script([])
+    expect(result).to eq([
+      "not an rdb database.",
+    ])
+  end
+
+  it 'refuses to open a database used by another process' do
+    IO.popen("./target/debug/rdb test.rdb 2>&1", "r+") do |first|
+      first.puts "insert 1 user1 person1@example.com"
+      expect(first.gets).to eq("created a new database with page_size=4096\n")
+      expect(first.gets).to eq("1 row inserted\n")
+
+      result = run_script([])
+      expect(result).to eq([
+        "database is locked.",
+      ])
+
+      first.puts ".exit"
+    end
+  end
+
+  it 'syncs changes to disk without exiting' do
+    result = run_script([
+      "insert 1 user1 person1@example.com",
+      ".sync",
+      "select",
+      ".exit",
+    ])
+    expect(result).to eq([
+      "(1, 'user1', 'person1@example.com')",
     ])
   end
 
@@ -109,12 +169,9 @@ describe 'database' do
     ]
     result = run_script(script)
     expect(result).to eq([
-      "rdb > Executed.",
-      "rdb > Error: Duplicate key.",
-      "rdb > (1, user1, person1@example.com)",
-      "Executed.",
-      "rdb > ",
+      "Error: Duplicate key.",
     ])
+    expect($?.exitstatus).to eq(1)
   end
 
   it 'allows printing out the structure of a one-node btree' do
@@ -126,15 +183,11 @@ describe 'database' do
     result = run_script(script)
 
     expect(result).to eq([
-      "rdb > Executed.",
-      "rdb > Executed.",
-      "rdb > Executed.",
-      "rdb > Tree:",
+      "Tree:",
       "- leaf (size 3)",
       "  - 1",
       "  - 2",
       "  - 3",
-      "rdb > "
     ])
   end
 
@@ -146,7 +199,7 @@ describe 'database' do
     result = run_script(script)
 
     expect(result).to eq([
-      "rdb > Constants:",
+      "Constants:",
       "PAGE_SIZE: 4096",
       "ROW_SIZE: 292",
       "COMMON_NODE_HEADER_SIZE: 6",
@@ -156,8 +209,6 @@ describe 'database' do
       "LEAF_NODE_MAX_CELLS: 13",
       "INTERNAL_NODE_HEADER_SIZE: 14",
       "INTERNAL_NODE_CELL_SIZE: 8",
-
-      "rdb > ",
     ])
   end
 
@@ -170,8 +221,8 @@ describe 'database' do
     script << ".exit"
     result = run_script(script)
 
-    expect(result[14...(result.length)]).to eq([
-      "rdb > Tree:",
+    expect(result).to eq([
+      "Tree:",
       "- internal (size 1)",
       "  - leaf (size 7)",
       "    - 1",
@@ -190,8 +241,6 @@ describe 'database' do
       "    - 12",
       "    - 13",
       "    - 14",
-      "rdb > Executed.",
-      "rdb > ",
       ])
     end
 
@@ -204,23 +253,22 @@ describe 'database' do
       script << ".exit"
       result = run_script(script)
 
-      expect(result[15...result.length]).to eq([
-        "rdb > (1, user1, person1@example.com)",
-        "(2, user2, person2@example.com)",
-        "(3, user3, person3@example.com)",
-        "(4, user4, person4@example.com)",
-        "(5, user5, person5@example.com)",
-        "(6, user6, person6@example.com)",
-        "(7, user7, person7@example.com)",
-        "(8, user8, person8@example.com)",
-        "(9, user9, person9@example.com)",
-        "(10, user10, person10@example.com)",
-        "(11, user11, person11@example.com)",
-        "(12, user12, person12@example.com)",
-        "(13, user13, person13@example.com)",
-        "(14, user14, person14@example.com)",
-        "(15, user15, person15@example.com)",
-        "Executed.", "rdb > ",
+      expect(result).to eq([
+        "(1, 'user1', 'person1@example.com')",
+        "(2, 'user2', 'person2@example.com')",
+        "(3, 'user3', 'person3@example.com')",
+        "(4, 'user4', 'person4@example.com')",
+        "(5, 'user5', 'person5@example.com')",
+        "(6, 'user6', 'person6@example.com')",
+        "(7, 'user7', 'person7@example.com')",
+        "(8, 'user8', 'person8@example.com')",
+        "(9, 'user9', 'person9@example.com')",
+        "(10, 'user10', 'person10@example.com')",
+        "(11, 'user11', 'person11@example.com')",
+        "(12, 'user12', 'person12@example.com')",
+        "(13, 'user13', 'person13@example.com')",
+        "(14, 'user14', 'person14@example.com')",
+        "(15, 'user15', 'person15@example.com')",
       ])
     end
 
@@ -262,8 +310,8 @@ describe 'database' do
       ]
       result = run_script(script)
 
-      expect(result[30...(result.length)]).to eq([
-        "rdb > Tree:",
+      expect(result).to eq([
+        "Tree:",
         "- internal (size 3)",
         "  - leaf (size 7)",
         "    - 1",
@@ -302,7 +350,6 @@ describe 'database' do
         "    - 28",
         "    - 29",
         "    - 30",
-        "rdb > ",
       ])
     end
 
@@ -314,13 +361,12 @@ describe 'database' do
       script << ".exit"
       result = run_script(script, 1024)
 
-      expect(result[382...(result.length)]).to eq([
-        "rdb > Tree:",
+      expect(result).to eq([
+        "Tree:",
         "- internal (size 2)",
         "  - internal (size 63)",
         "  - internal (size 63)",
         "  - internal (size 62)",
-        "rdb > ",
         ])
     end
 
@@ -331,9 +377,7 @@ describe 'database' do
       result = run_script(script)
 
       expect(result).to eq([
-        "rdb > (42)",
-        "Executed.",
-        "rdb > ",
+        "(42)",
         ])
     end
 
@@ -344,9 +388,95 @@ describe 'database' do
       result = run_script(script)
 
       expect(result).to eq([
-        "rdb > (42, 'nihao, rdb!')",
-        "Executed.",
-        "rdb > ",
+        "(42, 'nihao, rdb!')",
         ])
     end
+
+    it 'runs piped input without prompts and exits at the end of input' do
+      output = `echo "select 40 + 2" | ./target/debug/rdb test.rdb`
+      expect(output).to eq("(42)\n")
+      expect($?.exitstatus).to eq(0)
+    end
+
+    it 'runs a script file given after the db file' do
+      File.write("test.sql", "insert 1 user1 person1@example.com\nselect\n")
+      output = `./target/debug/rdb test.rdb test.sql < /dev/null`
+      expect(output).to eq("(1, 'user1', 'person1@example.com')\n")
+      expect($?.exitstatus).to eq(0)
+      `rm -f test.sql`
+    end
+
+    it 'reads a script with .read and names the failing line' do
+      File.write("test.sql", "insert 1 user1 person1@example.com\ninsert -1 a b\n")
+      result = run_script([
+        ".read test.sql",
+        "select",
+      ])
+      expect(result).to eq([
+        "test.sql:2: ID must be positive.",
+      ])
+      expect($?.exitstatus).to eq(1)
+      `rm -f test.sql`
+    end
+
+    it 'reports changes on stderr when input is piped' do
+      output = `printf "insert 1 a b\\nselect\\n" | ./target/debug/rdb test.rdb 2>/dev/null`
+      expect(output).to eq("(1, 'a', 'b')\n")
+      output = `printf "insert 2 c d\\nselect\\n.changes\\n" | ./target/debug/rdb test.rdb 2>&1`
+      expect(output).to eq(
+        "1 row inserted\n(1, 'a', 'b')\n(2, 'c', 'd')\n2 rows returned\n2 rows returned\n"
+      )
+    end
+
+    it 'stops a script at the first error and exits with 1' do
+      output = `printf "insert 1 a b\\n.foo\\ninsert 2 c d\\n" | ./target/debug/rdb test.rdb`
+      expect(output).to eq("Unrecognized command: .foo, see .help for the available ones.\n")
+      expect($?.exitstatus).to eq(1)
+      result = run_script(["select"])
+      expect(result).to eq([
+        "(1, 'a', 'b')",
+      ])
+    end
+
+    it 'runs -c commands in order without reading stdin' do
+      output = `./target/debug/rdb -c "insert 1 a b" -c ".mode csv" -c "select" test.rdb`
+      expect(output).to eq("id,username,email\r\n1,a,b\r\n")
+      expect($?.exitstatus).to eq(0)
+      `./target/debug/rdb test.rdb -c "insert 1 a b"`
+      expect($?.exitstatus).to eq(1)
+    end
+
+    it 'rolls back to an inner savepoint only' do
+      result = run_script([
+        "insert 1 a b",
+        "savepoint outer",
+        "insert 2 c d",
+        "savepoint inner",
+        "insert 3 e f",
+        "rollback to inner",
+        "release outer",
+        "select",
+      ])
+      expect(result).to eq([
+        "(1, 'a', 'b')",
+        "(2, 'c', 'd')",
+      ])
+      result = run_script(["rollback to outer"])
+      expect(result).to eq([
+        "cannot roll back to outer: no transaction is active.",
+      ])
+    end
+
+    it 'exits cleanly on SIGTERM while waiting for input' do
+      IO.popen("./target/debug/rdb test.rdb 2>&1", "r+") do |pipe|
+        pipe.puts "insert 1 user1 person1@example.com"
+        expect(pipe.gets).to eq("created a new database with page_size=4096\n")
+        expect(pipe.gets).to eq("1 row inserted\n")
+        Process.kill("TERM", pipe.pid)
+      end
+      expect($?.exitstatus).to eq(143)
+      expect(run_script(["select"])).to eq([
+        "(1, 'user1', 'person1@example.com')",
+      ])
+    end
 end
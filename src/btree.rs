@@ -1,4 +1,5 @@
-use pager::{Page, PageTrait, Pager};
+use pager::{Page, PageTrait, Pager, PagerError};
+use std::mem;
 use std::ops::{Index, IndexMut, Range, RangeFrom};
 
 use byteorder::{BigEndian, ByteOrder};
@@ -74,17 +75,39 @@ impl BTreeConfig {
         (self.page_size - INTERNAL_NODE_HEADER_SIZE - RIGHT_PAGE_INDEX_SIZE)
             / INTERNAL_NODE_CELL_SIZE
     }
+
+    /**
+     * tells whether a page found outside of the tree looks like a leaf: the leaf
+     * type byte, a sane root flag, a cell count that fits and strictly ascending keys.
+     **/
+    pub fn is_plausible_leaf(&self, page: &Page) -> bool {
+        if page[PAGE_TYPE_OFFSET] != PageType::Leaf as u8 || page[IS_ROOT_OFFSET] > 1 {
+            return false;
+        }
+        let num_cells = page.get_num_cells() as usize;
+        if num_cells > self.get_max_num_cells_for_leaf() {
+            return false;
+        }
+        (1..num_cells).all(|i| page.get_key_for_cell(i - 1) < page.get_key_for_cell(i))
+    }
 }
 
 
 
 pub trait BTreeTrait {
-    fn search_key(&self, key: u32) -> CellIndex;
+    fn search_key(&self, key: u32) -> Result<CellIndex, String>;
 
     /**
      * this method will insert key and return the inserted cell index.
      **/
     fn insert_key(&mut self, key: u32) -> Result<CellIndex, String>;
+
+    /**
+     * remove the cell of a key from its leaf, false if there is no such key.
+     * a leaf emptied by the delete is freed, and so is an internal page left
+     * with a single child, the child takes its place.
+     **/
+    fn delete_key(&mut self, key: u32) -> Result<bool, String>;
 }
 
 pub trait BTreePage {
@@ -226,7 +249,12 @@ impl<'a> SplitHelper<'a> {
         }
     }
 
-    fn set_internal_cell(&mut self, cell_index: usize, page_index: usize, key: Option<u32>) {
+    fn set_internal_cell(
+        &mut self,
+        cell_index: usize,
+        page_index: usize,
+        key: Option<u32>,
+    ) -> Result<(), String> {
         let real_cell_index = self.translate_cell_index(cell_index);
         let real_page_index = self.get_page_index(cell_index);
         {
@@ -237,11 +265,12 @@ impl<'a> SplitHelper<'a> {
             page.set_page_index(real_cell_index, page_index);
         }
         // update parent page index
-        let rc_page = self.pager.page_for_write(page_index);
+        let rc_page = self.pager.page_for_write(page_index).map_err(pager_error)?;
         match rc_page.try_borrow_mut() {
-            Result::Ok(mut page) => page.set_parent_page_index(real_page_index),
-            Result::Err(_) => panic!("cannot borrow page {}", page_index),
+            Some(mut page) => page.set_parent_page_index(real_page_index),
+            None => panic!("cannot borrow page {}", page_index),
         };
+        Result::Ok(())
     }
 
     fn get_internal_page_split_result(&self) -> (usize, u32, usize, usize) {
@@ -279,7 +308,7 @@ impl<'a> SplitHelper<'a> {
         key: u32,
         left_page_index: usize,
         right_page_index: usize,
-    ) -> (usize, u32, usize, usize) {
+    ) -> Result<(usize, u32, usize, usize), String> {
         let num_cells = self.original.get_num_cells() as usize;
         let mut inserted = false;
         // let N = num_cells + 1;
@@ -294,22 +323,22 @@ impl<'a> SplitHelper<'a> {
                 // found insertion position
                 if j < num_cells {
                     let k = self.original.get_key_for_cell(j);
-                    self.set_internal_cell(new_cell_index, right_page_index, Some(k));
+                    self.set_internal_cell(new_cell_index, right_page_index, Some(k))?;
                 } else {
-                    self.set_internal_cell(new_cell_index, right_page_index, None);
+                    self.set_internal_cell(new_cell_index, right_page_index, None)?;
                 }
 
                 let next_cell_index = new_cell_index - 1;
-                self.set_internal_cell(next_cell_index, left_page_index, Some(key));
+                self.set_internal_cell(next_cell_index, left_page_index, Some(key))?;
 
                 inserted = true;
             } else {
                 let original_page_index = self.original.get_page_index(j);
                 if j < num_cells {
                     let k = self.original.get_key_for_cell(j);
-                    self.set_internal_cell(new_cell_index, original_page_index, Some(k));
+                    self.set_internal_cell(new_cell_index, original_page_index, Some(k))?;
                 } else {
-                    self.set_internal_cell(new_cell_index, original_page_index, None);
+                    self.set_internal_cell(new_cell_index, original_page_index, None)?;
                 }
             }
         }
@@ -320,7 +349,7 @@ impl<'a> SplitHelper<'a> {
             None => self.original.set_num_cells(self.split_position as u32),
         };
 
-        return self.get_internal_page_split_result();
+        Result::Ok(self.get_internal_page_split_result())
     }
 }
 
@@ -328,6 +357,9 @@ pub struct BTree {
     pub pager: Pager,
     root_page_index: usize,
     pub config: BTreeConfig,
+    /// the leaf the last key went into, the next insert tries it before descending
+    /// from the root. forgotten on splits and rollbacks.
+    last_leaf: Option<usize>,
 }
 
 impl BTree {
@@ -338,14 +370,137 @@ impl BTree {
             pager: pager,
             root_page_index: 0,
             config: config,
+            last_leaf: None,
+        }
+    }
+
+    /**
+     * allocate the root of a new tree, an empty leaf. the tree of page 0 gets its
+     * root first, no other tree can have that page.
+     **/
+    pub fn create_tree(&mut self) -> Result<usize, String> {
+        if self.pager.num_pages == 0 {
+            let rc_page = self.pager.page_for_write(0).map_err(pager_error)?;
+            rc_page.borrow_mut().init_as_leaf_page(true, 0);
         }
+        let page_index = self.pager.allocate_page().map_err(pager_error)?;
+        let rc_page = self.pager.page_for_write(page_index).map_err(pager_error)?;
+        let mut page = rc_page.borrow_mut();
+        page.init_as_leaf_page(true, 0);
+        page.set_next_page(0);
+        Result::Ok(page_index)
     }
 
-    fn search_key_in_page(&self, key: u32, page_index: usize) -> CellIndex {
-        let rc_page = self.pager.page_for_read(page_index);
+    /// put every page of the tree with its root at given page onto the free list,
+    /// the number of pages freed. nothing may refer to the tree anymore.
+    pub fn drop_tree(&mut self, root_page_index: usize) -> Result<usize, String> {
+        let pages = self.tree_pages_from(root_page_index)?;
+        self.last_leaf = None;
+        for &page_index in &pages {
+            self.pager.free_page(page_index).map_err(pager_error)?;
+        }
+        Result::Ok(pages.len())
+    }
+
+    /// run `f` on the tree with its root at given page, like the one of a created
+    /// table. the tree of page 0 is back afterwards.
+    pub fn with_root<T, F: FnOnce(&mut BTree) -> T>(&mut self, root_page_index: usize, f: F) -> T {
+        let root_page_index = mem::replace(&mut self.root_page_index, root_page_index);
+        self.last_leaf = None;
+        let result = f(self);
+        self.root_page_index = root_page_index;
+        self.last_leaf = None;
+        result
+    }
+
+    /// the cell for a key in the tree with its root at given page.
+    pub fn search_key_from(&self, root_page_index: usize, key: u32) -> Result<CellIndex, String> {
+        if self.pager.num_pages == 0 {
+            Result::Ok(CellIndex::new(0, 0))
+        } else {
+            self.search_key_in_page(key, root_page_index)
+        }
+    }
+
+    /// the highest key of the tree with its root at given page, `None` if it is empty.
+    pub fn last_key_from(&self, root_page_index: usize) -> Result<Option<u32>, String> {
+        let mut page_index = root_page_index;
+        loop {
+            let rc_page = self.pager.page_for_read(page_index).map_err(pager_error)?;
+            let page = rc_page.borrow();
+            let num_cells = page.get_num_cells() as usize;
+            match page.get_page_type() {
+                PageType::Internal => page_index = page.get_page_index(num_cells),
+                PageType::Leaf if num_cells == 0 => return Result::Ok(None),
+                PageType::Leaf => return Result::Ok(Some(page.get_key_for_cell(num_cells - 1))),
+            }
+        }
+    }
+
+    /// undo the changes since the last commit, see `Pager::rollback`.
+    pub fn rollback(&mut self) -> Result<(), String> {
+        self.last_leaf = None;
+        self.pager.rollback().map_err(pager_error)
+    }
+
+    /// undo the changes since a savepoint, see `Pager::rollback_to_savepoint`.
+    pub fn rollback_to_savepoint(&mut self, index: usize) {
+        self.last_leaf = None;
+        self.pager.rollback_to_savepoint(index);
+    }
+
+    /// whether the tree holds a key, the leaf of the last insert is looked at first.
+    pub fn contains_key(&self, key: u32) -> Result<bool, String> {
+        if self.pager.num_pages == 0 {
+            return Result::Ok(false);
+        }
+        let CellIndex {
+            page_index,
+            cell_index,
+        } = match self.cell_in_last_leaf(key)? {
+            Some(cell) => cell,
+            None => self.search_key(key)?,
+        };
+        let rc_page = self.pager.page_for_read(page_index).map_err(pager_error)?;
+        let page = rc_page.borrow();
+        Result::Ok(
+            cell_index < page.get_num_cells() as usize && page.get_key_for_cell(cell_index) == key,
+        )
+    }
+
+    /**
+     * the cell for a key in the leaf of the last insert. the key has to fall within
+     * the keys of the leaf, or after them if it is the right most leaf, and the
+     * leaf must have room for it. `None` otherwise, the key is then looked up
+     * from the root.
+     **/
+    fn cell_in_last_leaf(&self, key: u32) -> Result<Option<CellIndex>, String> {
+        let page_index = match self.last_leaf {
+            Some(page_index) => page_index,
+            None => return Result::Ok(None),
+        };
+        let rc_page = self.pager.page_for_read(page_index).map_err(pager_error)?;
         let page = rc_page.borrow();
+        let num_cells = page.get_num_cells() as usize;
+        let has_room = num_cells < self.config.get_max_num_cells_for_leaf();
         match page.get_page_type() {
-            PageType::Leaf => CellIndex::new(page_index, page.find_cell_for_key(key)),
+            PageType::Leaf if num_cells > 0 && has_room => {}
+            _ => return Result::Ok(None),
+        }
+        let last_key = page.get_key_for_cell(num_cells - 1);
+        let within = page.get_key_for_cell(0) <= key && key <= last_key;
+        if within || (key > last_key && !page.has_next_page()) {
+            Result::Ok(Some(CellIndex::new(page_index, page.find_cell_for_key(key))))
+        } else {
+            Result::Ok(None)
+        }
+    }
+
+    fn search_key_in_page(&self, key: u32, page_index: usize) -> Result<CellIndex, String> {
+        let rc_page = self.pager.page_for_read(page_index).map_err(pager_error)?;
+        let page = rc_page.borrow();
+        match page.get_page_type() {
+            PageType::Leaf => Result::Ok(CellIndex::new(page_index, page.find_cell_for_key(key))),
             PageType::Internal => self.search_key_in_page(key, page.find_page_for_key(key)),
         }
     }
@@ -356,24 +511,23 @@ impl BTree {
         key: u32,
         left_page_index: usize,
         right_page_index: usize,
-    ) {
+    ) -> Result<(), String> {
         let num_cells = {
-            let rc_page = self.pager.page_for_read(page_index);
+            let rc_page = self.pager.page_for_read(page_index).map_err(pager_error)?;
             let page = rc_page.borrow();
             page.get_num_cells() as usize
         };
 
         if num_cells >= self.config.get_max_num_cells_for_internal() {
-            self.split_internal_page_and_insert_key(
+            return self.split_internal_page_and_insert_key(
                 page_index,
                 key,
                 left_page_index,
                 right_page_index,
             );
-            return;
         }
 
-        let rc_page = self.pager.page_for_write(page_index);
+        let rc_page = self.pager.page_for_write(page_index).map_err(pager_error)?;
         let mut page = rc_page.borrow_mut();
         let cell_index = page.find_cell_for_key(key);
         if cell_index < num_cells {
@@ -393,6 +547,7 @@ impl BTree {
         page.set_key_for_cell(cell_index, key);
         page.set_page_index(cell_index, left_page_index);
         page.set_page_index(cell_index + 1, right_page_index);
+        Result::Ok(())
     }
 
     fn split_internal_page_and_insert_key(
@@ -401,25 +556,25 @@ impl BTree {
         key: u32,
         left_page_index: usize,
         right_page_index: usize,
-    ) {
+    ) -> Result<(), String> {
         let internal_node_max_cells = self.config.get_max_num_cells_for_internal();
         let first_half_num_cells = (internal_node_max_cells + 1) / 2;
         let second_half_num_cells = internal_node_max_cells - first_half_num_cells;
 
         let (parent_page_index, max_left_key, new_left_page_index, new_right_page_index) = {
             let original_page_index = page_index;
-            let rc_original_page = self.pager.page_for_write(original_page_index);
+            let rc_original_page = self.pager.page_for_write(original_page_index).map_err(pager_error)?;
             let original_page = &mut rc_original_page.borrow_mut();
 
-            let new_right_page_index = self.pager.next_page_index();
-            let rc_new_right_page = self.pager.page_for_write(new_right_page_index);
+            let new_right_page_index = self.pager.allocate_page().map_err(pager_error)?;
+            let rc_new_right_page = self.pager.page_for_write(new_right_page_index).map_err(pager_error)?;
             let new_right_page = &mut rc_new_right_page.borrow_mut();
             new_right_page.init_as_internal_page(false, second_half_num_cells as u32);
 
             let is_root = original_page.is_root();
             if is_root {
-                let new_left_page_index = self.pager.next_page_index();
-                let rc_new_left_page = self.pager.page_for_write(new_left_page_index);
+                let new_left_page_index = self.pager.allocate_page().map_err(pager_error)?;
+                let rc_new_left_page = self.pager.page_for_write(new_left_page_index).map_err(pager_error)?;
                 let new_left_page = &mut rc_new_left_page.borrow_mut();
                 new_left_page.init_as_internal_page(false, first_half_num_cells as u32);
 
@@ -436,7 +591,7 @@ impl BTree {
                     first_half_num_cells,
                     &mut self.pager,
                 );
-                selector.split_internal_page(key, left_page_index, right_page_index)
+                selector.split_internal_page(key, left_page_index, right_page_index)?
             } else {
                 let parent_page_index = original_page.get_parent_page_index();
                 new_right_page.set_parent_page_index(parent_page_index);
@@ -452,7 +607,7 @@ impl BTree {
                     &mut self.pager,
                 );
 
-                selector.split_internal_page(key, left_page_index, right_page_index)
+                selector.split_internal_page(key, left_page_index, right_page_index)?
             }
         };
         // update parent
@@ -461,7 +616,7 @@ impl BTree {
             max_left_key,
             new_left_page_index,
             new_right_page_index,
-        );
+        )
     }
 
     /**
@@ -474,7 +629,8 @@ impl BTree {
      * TODO: bytes move not efficient!
      * TODO: move to SplitHelper
      **/
-    fn split_leaf_page(&mut self, page_index: usize) {
+    fn split_leaf_page(&mut self, page_index: usize) -> Result<(), String> {
+        self.last_leaf = None;
         let leaf_node_max_cells = self.config.get_max_num_cells_for_leaf();
         let first_half_num_cells = (leaf_node_max_cells + 1) / 2;
         let second_half_num_cells = leaf_node_max_cells - first_half_num_cells;
@@ -482,20 +638,29 @@ impl BTree {
         let second_half_cells_offset = CELL_OFFSET + first_half_page_size;
         let second_half_page_size = second_half_num_cells * LEAF_NODE_CELL_SIZE;
 
+        // the root page stays in place, its cells are moved into a new left page
+        let is_root = self.pager.page_for_read(page_index).map_err(pager_error)?.borrow().is_root();
+        let new_left_page_index = match is_root {
+            true => Some(self.pager.allocate_page().map_err(pager_error)?),
+            false => None,
+        };
+        let right_page_index = self.pager.allocate_page().map_err(pager_error)?;
+
         let mut second_half_buf = vec![0u8; second_half_page_size];
         let mut first_half_buf: Option<Vec<u8>> = None;
+        let mut original_next_page_index = 0;
         let new_key;
         // copy bytes into vectors, which is inefficient
         //TODO: inefficient copy of bytes
         {
-            let rc_page = self.pager.page_for_write(page_index);
+            let rc_page = self.pager.page_for_write(page_index).map_err(pager_error)?;
             let mut original_page = rc_page.borrow_mut();
             new_key = original_page.get_key_for_cell(first_half_num_cells - 1);
             second_half_buf.clone_from_slice(original_page.index(Range {
                 start: second_half_cells_offset,
                 end: second_half_cells_offset + second_half_page_size,
             }));
-            if original_page.is_root() {
+            if is_root {
                 let mut buf = vec![0u8; first_half_page_size];
                 buf.clone_from_slice(original_page.index(Range {
                     start: CELL_OFFSET,
@@ -507,37 +672,37 @@ impl BTree {
                 original_page.init_as_internal_page(true, 0);
             } else {
                 original_page.set_num_cells(first_half_num_cells as u32);
-                original_page.set_next_page(self.pager.next_page_index());
+                original_next_page_index = original_page.get_next_page();
+                original_page.set_next_page(right_page_index);
             }
         }
 
         // create a new leaf page if the original page is root
         let (parent_page_index, left_page_index, next_page_index) = match first_half_buf {
             None => {
-                let rc_page = self.pager.page_for_read(page_index);
+                let rc_page = self.pager.page_for_read(page_index).map_err(pager_error)?;
                 let page = rc_page.borrow();
                 (
                     page.get_parent_page_index(),
                     page_index,
-                    page.get_next_page(),
+                    original_next_page_index,
                 )
             }
             Some(buf) => {
-                let left_page_index = self.pager.next_page_index();
-                let rc_page = self.pager.page_for_write(left_page_index);
+                let left_page_index = new_left_page_index.unwrap();
+                let rc_page = self.pager.page_for_write(left_page_index).map_err(pager_error)?;
                 let mut left_page = rc_page.borrow_mut();
                 left_page.init_as_leaf_page(false, first_half_num_cells as u32);
                 left_page.wrap_slice(CELL_OFFSET, &buf);
-                left_page.set_next_page(left_page_index + 1);
+                left_page.set_next_page(right_page_index);
                 left_page.set_parent_page_index(page_index);
                 (page_index, left_page_index, 0)
             }
         };
 
-        // create a splitted page, and copy second half of page data into it
-        let right_page_index = self.pager.next_page_index();
+        // copy second half of page data into the splitted page
         {
-            let rc_page = self.pager.page_for_write(right_page_index);
+            let rc_page = self.pager.page_for_write(right_page_index).map_err(pager_error)?;
             let mut right_page = rc_page.borrow_mut();
             right_page.init_as_leaf_page(false, second_half_num_cells as u32);
             right_page.wrap_slice(CELL_OFFSET, &second_half_buf);
@@ -551,27 +716,200 @@ impl BTree {
             new_key,
             left_page_index,
             right_page_index,
-        );
+        )
+    }
+
+    /// the pages reachable from the root, the others of the db should be free.
+    pub fn num_tree_pages(&self) -> Result<usize, String> {
+        self.tree_pages_from(self.root_page_index).map(|pages| pages.len())
+    }
+
+    /// the pages of the tree with its root at given page, the root first.
+    pub fn tree_pages_from(&self, root_page_index: usize) -> Result<Vec<usize>, String> {
+        if self.pager.num_pages == 0 {
+            return Result::Ok(Vec::new());
+        }
+        let mut pages = Vec::new();
+        let mut pending = vec![root_page_index];
+        while let Some(page_index) = pending.pop() {
+            pages.push(page_index);
+            let rc_page = self.pager.page_for_read(page_index).map_err(pager_error)?;
+            let page = rc_page.borrow();
+            if let PageType::Internal = page.get_page_type() {
+                pending.extend((0..page.get_num_cells() as usize + 1).map(|index| page.get_page_index(index)));
+            }
+        }
+        Result::Ok(pages)
+    }
+
+    /**
+     * the internal pages from the root down to the leaf of a key, each with the
+     * position of the child taken on the way down.
+     **/
+    fn path_to_leaf(&self, key: u32) -> Result<Vec<(usize, usize)>, String> {
+        let mut path = Vec::new();
+        let mut page_index = self.root_page_index;
+        loop {
+            let rc_page = self.pager.page_for_read(page_index).map_err(pager_error)?;
+            let page = rc_page.borrow();
+            match page.get_page_type() {
+                PageType::Leaf => return Result::Ok(path),
+                PageType::Internal => {
+                    let position = page.find_cell_for_key(key);
+                    path.push((page_index, position));
+                    page_index = page.get_page_index(position);
+                }
+            }
+        }
+    }
+
+    /// the leaf linking to the leaf at the end of a path, `None` for the left most leaf.
+    fn previous_leaf(&self, path: &[(usize, usize)]) -> Result<Option<usize>, String> {
+        let (page_index, position) = match path.iter().rev().find(|&&(_, position)| position > 0) {
+            Some(&step) => step,
+            None => return Result::Ok(None),
+        };
+        // the right most leaf below the child left of the path
+        let mut page_index = {
+            let rc_page = self.pager.page_for_read(page_index).map_err(pager_error)?;
+            let page = rc_page.borrow();
+            page.get_page_index(position - 1)
+        };
+        loop {
+            let rc_page = self.pager.page_for_read(page_index).map_err(pager_error)?;
+            let page = rc_page.borrow();
+            match page.get_page_type() {
+                PageType::Leaf => return Result::Ok(Some(page_index)),
+                PageType::Internal => page_index = page.get_page_index(page.get_num_cells() as usize),
+            }
+        }
+    }
+
+    /// unlink a leaf without cells from the leaf chain and its parent, then free it.
+    fn free_empty_leaf(&mut self, key: u32, page_index: usize) -> Result<(), String> {
+        self.last_leaf = None;
+        let path = self.path_to_leaf(key)?;
+        if let Some(previous_index) = self.previous_leaf(&path)? {
+            let next_index = {
+                let rc_page = self.pager.page_for_read(page_index).map_err(pager_error)?;
+                let page = rc_page.borrow();
+                page.get_next_page()
+            };
+            let rc_page = self.pager.page_for_write(previous_index).map_err(pager_error)?;
+            rc_page.borrow_mut().set_next_page(next_index);
+        }
+        self.pager.free_page(page_index).map_err(pager_error)?;
+        self.remove_child(&path)
+    }
+
+    /**
+     * drop the child at the end of a path from its parent. a parent left without
+     * children is freed as well, the root becomes an empty leaf instead. a parent
+     * left with a single child is collapsed into it.
+     **/
+    fn remove_child(&mut self, path: &[(usize, usize)]) -> Result<(), String> {
+        let (page_index, position) = path[path.len() - 1];
+        let num_cells = {
+            let rc_page = self.pager.page_for_write(page_index).map_err(pager_error)?;
+            let mut page = rc_page.borrow_mut();
+            let num_cells = page.get_num_cells() as usize;
+            if num_cells == 0 {
+                if page.is_root() {
+                    page.init_as_leaf_page(true, 0);
+                    page.set_next_page(0);
+                    return Result::Ok(());
+                }
+                drop(page);
+                self.pager.free_page(page_index).map_err(pager_error)?;
+                return self.remove_child(&path[..path.len() - 1]);
+            }
+            // the cells after the child move over it up to the right most page index,
+            // the key left of a removed right most child is dropped with the count.
+            if position < num_cells {
+                let from = KEY_INDEX_OFFSET + (position + 1) * INTERNAL_NODE_CELL_SIZE;
+                let to = KEY_INDEX_OFFSET + position * INTERNAL_NODE_CELL_SIZE;
+                let len = (num_cells - position) * INTERNAL_NODE_CELL_SIZE - KEY_SIZE;
+                page.move_slice_internally(from, to, len);
+            }
+            page.set_num_cells((num_cells - 1) as u32);
+            num_cells - 1
+        };
+        if num_cells == 0 {
+            self.collapse_into_child(path)?;
+        }
+        Result::Ok(())
+    }
+
+    /// replace the internal page at the end of a path with its only child.
+    fn collapse_into_child(&mut self, path: &[(usize, usize)]) -> Result<(), String> {
+        let (page_index, _) = path[path.len() - 1];
+        let child_index = {
+            let rc_page = self.pager.page_for_read(page_index).map_err(pager_error)?;
+            let page = rc_page.borrow();
+            page.get_page_index(0)
+        };
+        if path.len() > 1 {
+            let (parent_index, position) = path[path.len() - 2];
+            {
+                let rc_page = self.pager.page_for_write(parent_index).map_err(pager_error)?;
+                rc_page.borrow_mut().set_page_index(position, child_index);
+            }
+            let rc_page = self.pager.page_for_write(child_index).map_err(pager_error)?;
+            rc_page.borrow_mut().set_parent_page_index(parent_index);
+            return self.pager.free_page(page_index).map_err(pager_error);
+        }
+
+        // the root page stays in place, the child is copied into it
+        let (buf, grandchildren) = {
+            let rc_page = self.pager.page_for_read(child_index).map_err(pager_error)?;
+            let page = rc_page.borrow();
+            let grandchildren: Vec<usize> = match page.get_page_type() {
+                PageType::Leaf => Vec::new(),
+                PageType::Internal => (0..page.get_num_cells() as usize + 1)
+                    .map(|index| page.get_page_index(index))
+                    .collect(),
+            };
+            (page.to_vec(), grandchildren)
+        };
+        {
+            let rc_page = self.pager.page_for_write(page_index).map_err(pager_error)?;
+            let mut page = rc_page.borrow_mut();
+            page.wrap_slice(0, &buf);
+            page.set_is_root(true);
+            page.set_parent_page_index(0);
+        }
+        for grandchild_index in grandchildren {
+            let rc_page = self.pager.page_for_write(grandchild_index).map_err(pager_error)?;
+            rc_page.borrow_mut().set_parent_page_index(page_index);
+        }
+        self.pager.free_page(child_index).map_err(pager_error)
     }
 
-    fn write_key(&mut self, key: u32, page_index: usize, cell_index: usize) {
-        let rc_page = self.pager.page_for_write(page_index);
+    fn write_key(&mut self, key: u32, page_index: usize, cell_index: usize) -> Result<(), String> {
+        let rc_page = self.pager.page_for_write(page_index).map_err(pager_error)?;
         let mut page = rc_page.borrow_mut();
         page.set_key_for_cell(cell_index, key);
         let num_cells = page.get_num_cells();
         page.set_num_cells((num_cells + 1) as u32);
+        Result::Ok(())
     }
 
     // this method is designed for dev or test purpose only.
-    pub fn debug_print(&self, only_internal: bool) {
+    pub fn debug_print(&self, only_internal: bool) -> Result<(), String> {
         println!("Tree:");
         if self.pager.num_pages > 0 {
-            self.debug_print_page(0, "", only_internal);
+            self.debug_print_page(0, "", only_internal)?;
         }
+        Result::Ok(())
     }
 
-    fn debug_print_page(&self, page_index: usize, padding: &str, only_internal: bool) {
-        let rc_page = self.pager.page_for_read(page_index);
+    fn debug_print_page(
+        &self,
+        page_index: usize,
+        padding: &str,
+        only_internal: bool,
+    ) -> Result<(), String> {
+        let rc_page = self.pager.page_for_read(page_index).map_err(pager_error)?;
         let page = rc_page.borrow();
         match page.get_page_type() {
             PageType::Leaf => {
@@ -589,7 +927,7 @@ impl BTree {
                 let new_padding = &format!("{}  ", padding);
                 for index in 0..num_keys + 1 {
                     let child_index = page.get_page_index(index);
-                    self.debug_print_page(child_index, new_padding, only_internal);
+                    self.debug_print_page(child_index, new_padding, only_internal)?;
                     if !only_internal && index < num_keys {
                         let key = page.get_key_for_cell(index);
                         println!("{}- key {}", new_padding, key);
@@ -597,22 +935,19 @@ impl BTree {
                 }
             }
         }
+        Result::Ok(())
     }
 }
 
 impl BTreeTrait for BTree {
-    fn search_key(&self, key: u32) -> CellIndex {
-        if self.pager.num_pages == 0 {
-            CellIndex::new(0, 0)
-        } else {
-            self.search_key_in_page(key, self.root_page_index)
-        }
+    fn search_key(&self, key: u32) -> Result<CellIndex, String> {
+        self.search_key_from(self.root_page_index, key)
     }
 
     fn insert_key(&mut self, key: u32) -> Result<CellIndex, String> {
         // create page first.
         if self.pager.num_pages == 0 {
-            let rc_page = self.pager.page_for_write(self.root_page_index);
+            let rc_page = self.pager.page_for_write(self.root_page_index).map_err(pager_error)?;
             let mut first_page = rc_page.borrow_mut();
             first_page.init_as_leaf_page(true, 0);
         }
@@ -620,19 +955,22 @@ impl BTreeTrait for BTree {
         let CellIndex {
             page_index,
             cell_index,
-        } = self.search_key(key);
+        } = match self.cell_in_last_leaf(key)? {
+            Some(cell) => cell,
+            None => self.search_key(key)?,
+        };
         let num_cells = {
-            let rc_page = self.pager.page_for_read(page_index);
+            let rc_page = self.pager.page_for_read(page_index).map_err(pager_error)?;
             let page = rc_page.borrow();
             page.get_num_cells() as usize
         };
 
         if num_cells >= self.config.get_max_num_cells_for_leaf() {
             // split page
-            self.split_leaf_page(page_index);
+            self.split_leaf_page(page_index)?;
             return self.insert_key(key);
         } else if cell_index < num_cells {
-            let rc_page = self.pager.page_for_write(page_index);
+            let rc_page = self.pager.page_for_write(page_index).map_err(pager_error)?;
             let mut page = rc_page.borrow_mut();
             if page.get_key_for_cell(cell_index) == key {
                 return Result::Err("Error: Duplicate key.".to_owned());
@@ -644,9 +982,36 @@ impl BTreeTrait for BTree {
                 page.move_slice_internally(cell_pos, new_cell_pos, LEAF_NODE_CELL_SIZE);
             }
         }
-        self.write_key(key, page_index, cell_index);
+        self.write_key(key, page_index, cell_index)?;
+        self.last_leaf = Some(page_index);
         Result::Ok(CellIndex::new(page_index, cell_index))
     }
+
+    fn delete_key(&mut self, key: u32) -> Result<bool, String> {
+        if self.pager.num_pages == 0 {
+            return Result::Ok(false);
+        }
+        let CellIndex {
+            page_index,
+            cell_index,
+        } = self.search_key(key)?;
+        let rc_page = self.pager.page_for_write(page_index).map_err(pager_error)?;
+        let mut page = rc_page.borrow_mut();
+        let num_cells = page.get_num_cells() as usize;
+        if cell_index >= num_cells || page.get_key_for_cell(cell_index) != key {
+            return Result::Ok(false);
+        }
+        // move the cells after it over the removed one
+        let cell_pos = Page::pos_for_cell(cell_index);
+        let cells_after = (num_cells - cell_index - 1) * LEAF_NODE_CELL_SIZE;
+        page.move_slice_internally(cell_pos + LEAF_NODE_CELL_SIZE, cell_pos, cells_after);
+        page.set_num_cells((num_cells - 1) as u32);
+        if num_cells == 1 && !page.is_root() {
+            drop(page);
+            self.free_empty_leaf(key, page_index)?;
+        }
+        Result::Ok(true)
+    }
 }
 
 const RANGE_FOR_NUM_CELLS: RangeFrom<usize> = RangeFrom {
@@ -660,6 +1025,10 @@ const RANGE_FOR_NEXT_PAGE: RangeFrom<usize> = RangeFrom {
 };
 
 
+fn pager_error(e: PagerError) -> String {
+    format!("Error: {}", e)
+}
+
 fn range_for_internal_page_key(index: usize) -> RangeFrom<usize> {
     RangeFrom {
         start: KEY_INDEX_OFFSET + INDEX_SIZE + index * INTERNAL_NODE_CELL_SIZE,
@@ -807,3 +1176,257 @@ impl BTreeInternalPage for Page {
         self.get_page_index(self.find_cell_for_key(key))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pager::{DbOption, PagerStats, SyncMode};
+    use storage::MEMORY_DB;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    fn memory_tree(page_size: usize) -> BTree {
+        let pager = Pager::new(
+            MEMORY_DB,
+            DbOption {
+                page_size,
+                sync_mode: SyncMode::Off,
+                ..Default::default()
+            },
+        ).unwrap();
+        BTree::new(pager)
+    }
+
+    fn collect_keys(tree: &BTree) -> Vec<u32> {
+        let mut keys = Vec::new();
+        let CellIndex { mut page_index, .. } = tree.search_key(0).unwrap();
+        loop {
+            let rc_page = tree.pager.page_for_read(page_index).unwrap();
+            let page = rc_page.borrow();
+            for cell_index in 0..page.get_num_cells() as usize {
+                keys.push(page.get_key_for_cell(cell_index));
+            }
+            if !page.has_next_page() {
+                break;
+            }
+            page_index = page.get_next_page();
+        }
+        keys
+    }
+
+    #[test]
+    fn inserted_keys_are_kept_in_order() {
+        let mut tree = memory_tree(4096);
+        for key in &[3, 1, 2] {
+            assert!(tree.insert_key(*key).is_ok());
+        }
+        assert_eq!(collect_keys(&tree), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn duplicate_key_is_rejected() {
+        let mut tree = memory_tree(4096);
+        assert!(tree.insert_key(1).is_ok());
+        assert_eq!(
+            tree.insert_key(1).err(),
+            Some("Error: Duplicate key.".to_owned())
+        );
+    }
+
+    #[test]
+    fn deleted_keys_are_gone_from_every_leaf() {
+        let mut tree = memory_tree(1024);
+        for key in 1..400 {
+            tree.insert_key(key).unwrap();
+        }
+        // whole leaves are emptied along the way
+        for key in (1..400).filter(|key| key % 3 != 0 || (100..200).contains(key)) {
+            assert_eq!(tree.delete_key(key), Result::Ok(true), "{}", key);
+        }
+        assert_eq!(tree.delete_key(1), Result::Ok(false));
+        assert_eq!(tree.delete_key(1000), Result::Ok(false));
+        let expected: Vec<u32> = (1..400)
+            .filter(|key| key % 3 == 0 && !(100..200).contains(key))
+            .collect();
+        assert_eq!(collect_keys(&tree), expected);
+        assert!(!tree.contains_key(150).unwrap());
+
+        for key in (100..200).rev() {
+            tree.insert_key(key).unwrap();
+        }
+        assert!(tree.contains_key(150).unwrap());
+        assert_eq!(collect_keys(&tree).len(), expected.len() + 100);
+    }
+
+    #[test]
+    fn emptied_leaves_and_parents_are_freed() {
+        let mut seed = 0x2545_f491_4f6c_dd1d;
+        let mut tree = memory_tree(1024);
+        let mut keys: Vec<u32> = (1..2000).collect();
+        for &key in &keys {
+            tree.insert_key(key).unwrap();
+        }
+        let num_pages = tree.pager.num_pages;
+        // delete in a random order until a handful of keys are left
+        while keys.len() > 5 {
+            let key = keys.remove((next_random(&mut seed) % keys.len() as u64) as usize);
+            assert_eq!(tree.delete_key(key), Result::Ok(true), "{}", key);
+        }
+        assert_eq!(collect_keys(&tree), keys);
+        // at worst a leaf for each key left under the root
+        assert!(tree.num_tree_pages().unwrap() <= keys.len() + 1);
+        assert_eq!(tree.num_tree_pages().unwrap() + tree.pager.num_free_pages(), num_pages);
+        for &key in &keys {
+            assert!(tree.contains_key(key).unwrap(), "{}", key);
+        }
+
+        for &key in &keys.clone() {
+            assert_eq!(tree.delete_key(key), Result::Ok(true));
+        }
+        assert_eq!(collect_keys(&tree), Vec::<u32>::new());
+        assert_eq!(tree.num_tree_pages(), Result::Ok(1));
+
+        // the freed pages take the keys again
+        for key in 1..2000 {
+            tree.insert_key(key).unwrap();
+        }
+        assert_eq!(tree.pager.num_pages, num_pages);
+        assert_eq!(collect_keys(&tree), (1..2000).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn leaf_and_internal_pages_split_on_overflow() {
+        let mut tree = memory_tree(1024);
+        for key in (1..400).rev() {
+            assert!(tree.insert_key(key).is_ok());
+        }
+        assert_eq!(collect_keys(&tree), (1..400).collect::<Vec<u32>>());
+
+        let rc_root = tree.pager.page_for_read(0).unwrap();
+        let root = rc_root.borrow();
+        match root.get_page_type() {
+            PageType::Internal => assert!(root.is_root()),
+            PageType::Leaf => panic!("root page should be split"),
+        }
+    }
+
+    #[test]
+    fn point_lookup_misses_once_per_level_on_a_cold_cache() {
+        let path = env::temp_dir().join(format!("rdb-btree-stats-{}.rdb", process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        let db_option = DbOption {
+            page_size: 1024,
+            sync_mode: SyncMode::Off,
+            ..Default::default()
+        };
+        let mut height = 1;
+        {
+            let mut tree = BTree::new(Pager::new(path, db_option.clone()).unwrap());
+            for key in 1..400 {
+                assert!(tree.insert_key(key).is_ok());
+            }
+            tree.pager.flush_all().unwrap();
+            let mut page_index = 0;
+            loop {
+                let rc_page = tree.pager.page_for_read(page_index).unwrap();
+                let page = rc_page.borrow();
+                match page.get_page_type() {
+                    PageType::Internal => page_index = page.get_page_index(0),
+                    PageType::Leaf => break,
+                }
+                height += 1;
+            }
+        }
+
+        let tree = BTree::new(Pager::new(path, db_option).unwrap());
+        assert_eq!(tree.pager.stats(), PagerStats::default());
+        tree.search_key(200).unwrap();
+        assert_eq!(tree.pager.stats().cache_misses, height);
+        assert_eq!(tree.pager.stats().cache_hits, 0);
+        tree.pager.reset_stats();
+
+        tree.search_key(200).unwrap();
+        assert_eq!(tree.pager.stats().cache_misses, 0);
+        assert_eq!(tree.pager.stats().cache_hits, height);
+        let _ = fs::remove_file(path);
+    }
+
+    fn lookups_to_insert(tree: &mut BTree, key: u32) -> u64 {
+        tree.pager.reset_stats();
+        assert!(tree.insert_key(key).is_ok());
+        let stats = tree.pager.stats();
+        stats.cache_hits + stats.cache_misses
+    }
+
+    #[test]
+    fn sequential_inserts_skip_the_descent_from_the_root() {
+        let mut tree = memory_tree(1024);
+        assert!(tree.insert_key(1).is_ok());
+        let shallow = lookups_to_insert(&mut tree, 2);
+        for key in 3..400 {
+            assert!(tree.insert_key(key).is_ok());
+        }
+        // most inserts fit in the right most leaf, the others split it
+        let deep = (400..500)
+            .map(|key| lookups_to_insert(&mut tree, key))
+            .min()
+            .unwrap();
+        assert_eq!(deep, shallow);
+        assert_eq!(collect_keys(&tree), (1..500).collect::<Vec<u32>>());
+    }
+
+    /// xorshift64, good enough to shuffle keys the same way on every run.
+    fn next_random(seed: &mut u64) -> u64 {
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 7;
+        *seed ^= *seed << 17;
+        *seed
+    }
+
+    #[test]
+    fn random_and_sequential_inserts_keep_every_key() {
+        let mut seed = 0x9e37_79b9_7f4a_7c15;
+        let mut tree = memory_tree(1024);
+        let mut keys = ::std::collections::BTreeSet::new();
+        for round in 0..40 {
+            // runs of ascending keys from random starting points between random keys
+            let start = (next_random(&mut seed) % 5000) as u32;
+            for key in start..start + round {
+                assert_eq!(tree.insert_key(key).is_ok(), keys.insert(key), "key {}", key);
+            }
+            for _ in 0..20 {
+                let key = (next_random(&mut seed) % 5000) as u32;
+                assert_eq!(tree.insert_key(key).is_ok(), keys.insert(key), "key {}", key);
+            }
+        }
+        assert_eq!(collect_keys(&tree), keys.iter().cloned().collect::<Vec<u32>>());
+        for &key in &keys {
+            let CellIndex {
+                page_index,
+                cell_index,
+            } = tree.search_key(key).unwrap();
+            let rc_page = tree.pager.page_for_read(page_index).unwrap();
+            assert_eq!(rc_page.borrow().get_key_for_cell(cell_index), key);
+        }
+    }
+
+    #[test]
+    fn inserts_after_a_rollback_do_not_use_undone_leaves() {
+        let mut tree = memory_tree(1024);
+        for key in 1..10 {
+            assert!(tree.insert_key(key).is_ok());
+        }
+        tree.pager.flush_all().unwrap();
+        // the leaves these splits allocate are gone after the rollback
+        for key in 10..100 {
+            assert!(tree.insert_key(key).is_ok());
+        }
+        tree.rollback().unwrap();
+        for key in 10..20 {
+            assert!(tree.insert_key(key).is_ok());
+        }
+        assert_eq!(collect_keys(&tree), (1..20).collect::<Vec<u32>>());
+    }
+}
@@ -0,0 +1,146 @@
+//! the dbs attached to a session by `attach database`, next to its main db. every
+//! attached db has a pager of its own, statements reach it through tables qualified
+//! with its name like `aux.users`.
+
+use std::sync::atomic::AtomicBool;
+
+use btree::BTree;
+use pager::{DbOption, Pager};
+use table::index::IndexEntry;
+use table::schema::Schema;
+use table::Table;
+use vm::{Attachment, Schemas};
+
+/// the name the main db goes by, it can be neither attached nor detached.
+pub const MAIN_DB: &str = "main";
+
+struct AttachedDb {
+    name: String,
+    path: String,
+    table: Table,
+}
+
+/// the attached dbs in the order they were attached.
+#[derive(Default)]
+pub struct Catalog {
+    attached: Vec<AttachedDb>,
+}
+
+impl Catalog {
+    pub fn new() -> Catalog {
+        Catalog::default()
+    }
+
+    /// run an `attach` or `detach`, a db is attached with the options of the main
+    /// db given. a raised `cancel` flag stops the wait for a locked db file.
+    pub fn run(
+        &mut self,
+        attachment: &Attachment,
+        db_option: DbOption,
+        cancel: Option<&AtomicBool>,
+    ) -> Result<(), String> {
+        match attachment {
+            Attachment::Attach { path, name } => self.attach(path, name, db_option, cancel),
+            Attachment::Detach(name) => self.detach(name),
+        }
+    }
+
+    /// open or create the db file at given path under a name.
+    pub fn attach(
+        &mut self,
+        path: &str,
+        name: &str,
+        db_option: DbOption,
+        cancel: Option<&AtomicBool>,
+    ) -> Result<(), String> {
+        if name == MAIN_DB || self.position(name).is_some() {
+            return Result::Err(format!("database {} is already in use.", name));
+        }
+        let pager = Pager::new_with_cancel(path, db_option, cancel)
+            .map_err(|e| format!("cannot attach {}: {}", path, e))?;
+        self.attached.push(AttachedDb {
+            name: name.to_owned(),
+            path: path.to_owned(),
+            table: Table::new(BTree::new(pager)),
+        });
+        Result::Ok(())
+    }
+
+    /// write the changes of an attached db and close it.
+    pub fn detach(&mut self, name: &str) -> Result<(), String> {
+        if name == MAIN_DB {
+            return Result::Err(format!("cannot detach database {}.", MAIN_DB));
+        }
+        let index = self
+            .position(name)
+            .ok_or_else(|| format!("no such database: {}", name))?;
+        let db = self.attached.remove(index);
+        db.table
+            .close()
+            .map_err(|e| format!("cannot detach {}: {}", name, e))
+    }
+
+    /// the table of the attached db with given name, `None` for the main db.
+    pub fn table(&mut self, name: Option<&str>) -> Result<Option<&mut Table>, String> {
+        match name {
+            None | Some(MAIN_DB) => Result::Ok(None),
+            Some(name) => match self.position(name) {
+                Some(index) => Result::Ok(Some(&mut self.attached[index].table)),
+                None => Result::Err(format!("no such database: {}", name)),
+            },
+        }
+    }
+
+    /// the names and paths of the attached dbs.
+    pub fn databases(&self) -> Vec<(&str, &str)> {
+        self.attached
+            .iter()
+            .map(|db| (db.name.as_str(), db.path.as_str()))
+            .collect()
+    }
+
+    /// write the changes of every attached db and close them, the first error is
+    /// returned once all are closed.
+    pub fn close(self) -> Result<(), String> {
+        let mut result = Result::Ok(());
+        for AttachedDb { name, table, .. } in self.attached {
+            let closed = table
+                .close()
+                .map_err(|e| format!("cannot close {}: {}", name, e));
+            result = result.and(closed);
+        }
+        result
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.attached.iter().position(|db| db.name == name)
+    }
+}
+
+/// the main db of a session with the dbs attached to it, statements are prepared
+/// for the tables of the db they name.
+pub struct Databases<'a> {
+    pub main: &'a Table,
+    pub attached: &'a Catalog,
+}
+
+impl<'a> Schemas for Databases<'a> {
+    fn schema(&self, database: Option<&str>, table: &str) -> Result<Schema, String> {
+        match database {
+            None | Some(MAIN_DB) => self.main.schema(None, table),
+            Some(name) => match self.attached.position(name) {
+                Some(index) => self.attached.attached[index].table.schema(None, table),
+                None => Result::Err(format!("no such database: {}", name)),
+            },
+        }
+    }
+    fn indexes(&self, database: Option<&str>, table: &str) -> Result<Vec<IndexEntry>, String> {
+        match database {
+            None | Some(MAIN_DB) => self.main.indexes_of(table),
+            Some(name) => match self.attached.position(name) {
+                Some(index) => self.attached.attached[index].table.indexes_of(table),
+                None => Result::Err(format!("no such database: {}", name)),
+            },
+        }
+    }
+}
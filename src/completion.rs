@@ -0,0 +1,146 @@
+//! tab completion of the REPL: meta commands at the start of a line, elsewhere SQL
+//! keywords, table names and the columns of the tables the line mentions. it sees
+//! half typed lines all the time, so nothing in here may fail.
+
+use meta::META_COMMANDS;
+use table::{Table, TABLE_NAME};
+
+const KEYWORDS: &[&str] = &["from", "insert", "select"];
+
+/// the names a line can be completed with.
+pub struct Vocabulary {
+    /// the tables with their column names.
+    tables: Vec<(String, Vec<String>)>,
+}
+
+impl Vocabulary {
+    /// the users table and the tables of the catalog, the users table alone if the
+    /// catalog cannot be read.
+    pub fn new(table: &Table) -> Vocabulary {
+        let tables = match table.tables() {
+            Result::Ok(tables) => tables
+                .into_iter()
+                .map(|table| (table.name, table.schema.column_names()))
+                .collect(),
+            Result::Err(_) => vec![(TABLE_NAME.to_owned(), table.schema.column_names())],
+        };
+        Vocabulary { tables }
+    }
+}
+
+/// the candidates for the word before `pos` in `line` and the byte offset that
+/// word starts at.
+pub fn complete(line: &str, pos: usize, vocabulary: &Vocabulary) -> (usize, Vec<String>) {
+    let pos = pos.min(line.len());
+    if !line.is_char_boundary(pos) {
+        return (pos, Vec::new());
+    }
+    let before = &line[..pos];
+    let start = before
+        .rfind(|c: char| !is_word_char(c))
+        .map(|i| i + before[i..].chars().next().map_or(1, char::len_utf8))
+        .unwrap_or(0);
+    let word = &before[start..];
+    let candidates: Vec<&str> = if word.starts_with('.') {
+        if !before[..start].trim().is_empty() {
+            return (start, Vec::new());
+        }
+        META_COMMANDS
+            .iter()
+            .flat_map(|command| Some(&command.name).into_iter().chain(command.aliases.iter()))
+            .cloned()
+            .collect()
+    } else {
+        let mentioned: Vec<&str> = line
+            .split(|c: char| !is_word_char(c))
+            .filter(|other| !other.is_empty())
+            .collect();
+        let mut candidates: Vec<&str> = KEYWORDS.to_vec();
+        for (table, columns) in &vocabulary.tables {
+            candidates.push(table);
+            if mentioned.contains(&table.as_str()) {
+                candidates.extend(columns.iter().map(|column| column.as_str()));
+            }
+        }
+        candidates
+    };
+    let word_lowercase = word.to_lowercase();
+    let mut matches: Vec<String> = candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(&word_lowercase) && *candidate != word)
+        .map(|candidate| candidate.to_owned())
+        .collect();
+    matches.sort();
+    matches.dedup();
+    (start, matches)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vocabulary() -> Vocabulary {
+        Vocabulary {
+            tables: vec![(
+                "users".to_owned(),
+                vec!["email".to_owned(), "id".to_owned(), "name".to_owned()],
+            )],
+        }
+    }
+
+    fn candidates(line: &str) -> (usize, Vec<String>) {
+        complete(line, line.len(), &vocabulary())
+    }
+
+    #[test]
+    fn completes_meta_commands_at_the_start() {
+        assert_eq!(
+            candidates(".ti"),
+            (0, vec![".timeout".to_owned(), ".timer".to_owned()])
+        );
+        assert_eq!(
+            candidates("  .btr"),
+            (2, vec![".btree".to_owned(), ".btree_internal".to_owned()])
+        );
+        assert_eq!(candidates("select .ti"), (7, Vec::new()));
+    }
+
+    #[test]
+    fn completes_keywords_and_tables() {
+        assert_eq!(candidates("sel"), (0, vec!["select".to_owned()]));
+        assert_eq!(candidates("SEL"), (0, vec!["select".to_owned()]));
+        assert_eq!(candidates("select * fr"), (9, vec!["from".to_owned()]));
+        assert_eq!(candidates("select * from u"), (14, vec!["users".to_owned()]));
+        // columns only show up once their table is in the line
+        assert_eq!(candidates("select e"), (7, Vec::new()));
+    }
+
+    #[test]
+    fn completes_columns_of_mentioned_tables() {
+        let line = "select i from users";
+        assert_eq!(
+            complete(line, 8, &vocabulary()),
+            (7, vec!["id".to_owned(), "insert".to_owned()])
+        );
+        assert_eq!(candidates("select id, e from users").1, Vec::<String>::new());
+        assert_eq!(
+            complete("select id, e from users", 12, &vocabulary()),
+            (11, vec!["email".to_owned()])
+        );
+    }
+
+    #[test]
+    fn never_fails_on_garbage() {
+        let lines = ["", " ", "'", "select ('", "ä.ö", "..", "select\t\u{1F600}fr", ".ti ä"];
+        for line in &lines {
+            for pos in 0..line.len() + 3 {
+                let (start, _) = complete(line, pos, &vocabulary());
+                assert!(start <= line.len(), "{:?} at {}", line, pos);
+            }
+        }
+    }
+}
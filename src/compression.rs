@@ -0,0 +1,56 @@
+//! page compression of the pager, pages are compressed on their way to the
+//! db file only, everything above the pager works on uncompressed pages.
+//!
+//! slot layout in a compressed db: slot header (4 bytes) | payload, the slot
+//! header keeps the payload length with the highest bit set for compressed
+//! payloads. pages that don't shrink are stored raw, so a slot is always
+//! `SLOT_HEADER_SIZE` bytes larger than a page.
+
+use byteorder::{BigEndian, ByteOrder};
+use lz4_flex::block;
+
+pub const SLOT_HEADER_SIZE: usize = 4;
+const COMPRESSED_FLAG: u32 = 1 << 31;
+
+pub fn slot_size(page_size: usize) -> usize {
+    page_size + SLOT_HEADER_SIZE
+}
+
+/// encode a page into a slot of `slot_size(page.len())` bytes.
+pub fn compress_page(page: &[u8]) -> Vec<u8> {
+    let mut slot = vec![0u8; slot_size(page.len())];
+    let compressed = block::compress(page);
+    if compressed.len() < page.len() {
+        BigEndian::write_u32(&mut slot, COMPRESSED_FLAG | compressed.len() as u32);
+        slot[SLOT_HEADER_SIZE..SLOT_HEADER_SIZE + compressed.len()].copy_from_slice(&compressed);
+    } else {
+        BigEndian::write_u32(&mut slot, page.len() as u32);
+        slot[SLOT_HEADER_SIZE..].copy_from_slice(page);
+    }
+    slot
+}
+
+/// decode a slot into `page`, returns false if the slot is corrupted.
+pub fn decompress_page(slot: &[u8], page: &mut [u8]) -> bool {
+    let header = BigEndian::read_u32(slot);
+    let payload = &slot[SLOT_HEADER_SIZE..];
+    if header & COMPRESSED_FLAG == 0 {
+        // raw pages, including the zeroed slots never written
+        page.copy_from_slice(&payload[..page.len()]);
+        return true;
+    }
+    let len = (header & !COMPRESSED_FLAG) as usize;
+    if len > payload.len() {
+        return false;
+    }
+    match block::decompress_into(&payload[..len], page) {
+        Result::Ok(size) => size == page.len(),
+        Result::Err(_) => false,
+    }
+}
+
+/// only the pager tests, which need db files, look into slots.
+#[cfg(all(test, feature = "file"))]
+pub fn is_compressed(slot: &[u8]) -> bool {
+    BigEndian::read_u32(slot) & COMPRESSED_FLAG != 0
+}
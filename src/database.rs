@@ -0,0 +1,2361 @@
+//! the embedding api: a `Database` owns the db file, a `Connection` runs statements
+//! on it.
+
+use std::collections::{HashMap, VecDeque};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+use std::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use btree::BTree;
+use catalog::{Catalog, Databases, MAIN_DB};
+use error::RdbError;
+use pager::{self, DbOption, Pager, PagerError};
+use row::{FromRow, RowView};
+use storage::MEMORY_DB;
+#[cfg(feature = "serde")]
+use serialize;
+use table::{InsertReport, Row, Table, TABLE_NAME};
+use vm::{self, RowSink, ToValue, Value, VM};
+
+/// the statements `Connection::execute` and `Connection::query` keep prepared.
+const STATEMENT_CACHE_SIZE: usize = 16;
+
+/// the rows a query reads at a time, the table is only held while they are read.
+const ROWS_PER_BATCH: usize = 256;
+
+/// an open db, its connections share one pager and its page cache.
+pub struct Database {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    /// queries read the table together, other statements have it to themselves.
+    table: RwLock<Table>,
+    /// the connection with an open transaction, the others can neither read its
+    /// uncommitted changes nor write until it ends.
+    writer: Mutex<Option<usize>>,
+    next_connection: AtomicUsize,
+    /// the busy timeout new connections start with, see `DbOption::busy_timeout`.
+    busy_timeout: Duration,
+}
+
+impl Database {
+    /// open or create the db file at given path, `:memory:` opens an empty
+    /// in-memory db.
+    pub fn open(path: &str) -> Result<Database, RdbError> {
+        Database::open_with(path, DbOption::default())
+    }
+
+    pub fn open_with(path: &str, db_option: DbOption) -> Result<Database, RdbError> {
+        let pager = Pager::new(path, db_option)?;
+        Result::Ok(Database::from_pager(pager))
+    }
+
+    /// a db over an open pager.
+    pub fn from_pager(pager: Pager) -> Database {
+        let busy_timeout = pager.get_db_option().busy_timeout;
+        Database {
+            shared: Arc::new(Shared {
+                table: RwLock::new(Table::new(BTree::new(pager))),
+                writer: Mutex::new(None),
+                next_connection: AtomicUsize::new(0),
+                busy_timeout,
+            }),
+        }
+    }
+
+    /// the names of the tables, the users table and the ones of the catalog.
+    pub fn tables(&self) -> Result<Vec<String>, RdbError> {
+        let tables = self.shared.read_table().tables()?;
+        Result::Ok(tables.into_iter().map(|table| table.name).collect())
+    }
+
+    /// the column names of a table, `None` if there is no such table.
+    pub fn columns(&self, table: &str) -> Result<Option<Vec<String>>, RdbError> {
+        let table = self.shared.read_table().find_table(table)?;
+        Result::Ok(table.map(|table| table.schema.column_names()))
+    }
+
+    /// a new connection, it may move to another thread and outlive this handle
+    /// but the db is only closed once it is dropped.
+    pub fn connect(&self) -> Connection {
+        Connection {
+            shared: self.shared.clone(),
+            id: self.shared.next_connection.fetch_add(1, Ordering::Relaxed),
+            statements: HashMap::new(),
+            attached: Catalog::new(),
+            busy_timeout: self.shared.busy_timeout,
+            transactions: 0,
+        }
+    }
+
+    /// write all changes and release the db file, all connections must be dropped.
+    /// dropping the last handle writes the changes as well but can only log the
+    /// errors, see `Table::close`.
+    pub fn close(self) -> Result<(), RdbError> {
+        let shared = Arc::try_unwrap(self.shared).map_err(|shared| {
+            format!(
+                "cannot close the db: {} connections are open.",
+                Arc::strong_count(&shared) - 1
+            )
+        })?;
+        shared
+            .table
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+            .close()
+            .map_err(RdbError::from)
+    }
+}
+
+impl Shared {
+    fn read_table(&self) -> ::std::sync::RwLockReadGuard<'_, Table> {
+        self.table.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn writer(&self) -> MutexGuard<'_, Option<usize>> {
+        self.writer.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// run a statement for the connection with given id. a query runs alongside
+    /// other queries, it reads its first batch of rows here and the others as its
+    /// rows are iterated. everything else waits for the table. while another
+    /// connection has a transaction open the statement waits up to `busy_timeout`
+    /// for it to end.
+    fn run(
+        shared: &Arc<Shared>,
+        connection: usize,
+        busy_timeout: Duration,
+        statement: &mut vm::Statement,
+    ) -> Result<Rows, RdbError> {
+        let writer = shared.wait_for_writer(connection, busy_timeout, statement.cancel_flag())?;
+        let mut collector = Collector::default();
+        if statement.is_query() {
+            let table = shared.read_table();
+            drop(writer);
+            statement.rewind();
+            if !statement.query_batch(&table, &mut collector, ROWS_PER_BATCH)? {
+                // the rows keep their own copy, the statement may run again meanwhile
+                let rest = Rest {
+                    shared: shared.clone(),
+                    connection,
+                    busy_timeout,
+                    statement: statement.clone(),
+                };
+                statement.rewind();
+                return Result::Ok(Rows::new(statement, collector, Some(rest)));
+            }
+        } else {
+            shared.write(connection, writer, |table| {
+                execute(table, statement, &mut collector)
+            })?;
+        }
+        Result::Ok(Rows::new(statement, collector, None))
+    }
+
+    /// wait up to `busy_timeout` until no other connection has a transaction open.
+    fn wait_for_writer(
+        &self,
+        connection: usize,
+        busy_timeout: Duration,
+        cancel: Option<&AtomicBool>,
+    ) -> Result<MutexGuard<'_, Option<usize>>, RdbError> {
+        pager::retry_while_locked(busy_timeout, cancel, || {
+            let writer = self.writer();
+            if writer.is_some_and(|owner| owner != connection) {
+                Result::Err(PagerError::Locked)
+            } else {
+                Result::Ok(writer)
+            }
+        })
+        .map_err(RdbError::from)
+    }
+
+    /// change the table for the connection holding `writer`, which owns the table
+    /// afterwards if it left a transaction open.
+    fn write<T, E, F: FnOnce(&mut Table) -> Result<T, E>>(
+        &self,
+        connection: usize,
+        mut writer: MutexGuard<'_, Option<usize>>,
+        write: F,
+    ) -> Result<T, E> {
+        let mut table = self.table.write().unwrap_or_else(PoisonError::into_inner);
+        let result = write(&mut table);
+        *writer = if table.in_transaction() {
+            Some(connection)
+        } else {
+            None
+        };
+        result
+    }
+}
+
+/// execute and commit a statement, a failed one is rolled back.
+fn execute(
+    table: &mut Table,
+    statement: &mut vm::Statement,
+    collector: &mut Collector,
+) -> Result<(), String> {
+    statement
+        .execute(table, collector)
+        .and_then(|_| table.commit())
+        .map_err(|e| table.rollback_failed(e))
+}
+
+/// runs statements, every statement is committed on its own unless a savepoint is
+/// set. while it has a transaction open the other connections get `database is
+/// locked.`, dropping it rolls the transaction back.
+///
+/// `attach database` opens a db for this connection only, a savepoint covers the
+/// main db only.
+pub struct Connection {
+    shared: Arc<Shared>,
+    id: usize,
+    /// prepared statements by their sql, see `STATEMENT_CACHE_SIZE`.
+    statements: HashMap<String, vm::Statement>,
+    /// the dbs attached by this connection, closed when it is dropped.
+    attached: Catalog,
+    /// how long a statement waits for the transaction of another connection.
+    busy_timeout: Duration,
+    /// the `Transaction` guards alive, they name their savepoints after it.
+    transactions: usize,
+}
+
+impl Connection {
+    /// prepare a statement to run it more than once, with values bound to its `?`
+    /// parameters like `insert ? ? ?` or `select * from users where id = ?`.
+    pub fn prepare(&mut self, sql: &str) -> Result<Statement<'_>, RdbError> {
+        let statement = self.prepare_owned(sql)?;
+        Result::Ok(Statement {
+            connection: self,
+            statement,
+        })
+    }
+
+    /// a prepared statement that doesn't borrow the connection, for handles kept
+    /// apart from it like the ones of the C api.
+    pub(crate) fn prepare_owned(&self, sql: &str) -> Result<vm::Statement, String> {
+        let main = self.shared.read_table();
+        let databases = Databases {
+            main: &main,
+            attached: &self.attached,
+        };
+        vm::Statement::prepare(sql, &databases)
+    }
+
+    pub(crate) fn run(&mut self, statement: &mut vm::Statement) -> Result<Rows, RdbError> {
+        if let Some(attachment) = statement.attachment() {
+            if *self.shared.writer() == Some(self.id) {
+                return Result::Err(
+                    "cannot attach or detach a database within a transaction.".into(),
+                );
+            }
+            let mut db_option = self.shared.read_table().tree.pager.get_db_option();
+            db_option.busy_timeout = self.busy_timeout;
+            self.attached
+                .run(attachment, db_option, statement.cancel_flag())?;
+            return Result::Ok(Rows::new(statement, Collector::default(), None));
+        }
+        match self.attached.table(statement.database())? {
+            Some(table) => {
+                let mut collector = Collector::default();
+                execute(table, statement, &mut collector)?;
+                Result::Ok(Rows::new(statement, collector, None))
+            }
+            None => Shared::run(&self.shared, self.id, self.busy_timeout, statement),
+        }
+    }
+
+    /// start a transaction, it is rolled back unless `Transaction::commit` is
+    /// called. a transaction started inside another one is a savepoint of it.
+    pub fn transaction(&mut self) -> Result<Transaction<'_>, RdbError> {
+        let savepoint = format!("rdb_transaction_{}", self.transactions + 1);
+        self.execute(&format!("savepoint {}", savepoint))?;
+        self.transactions += 1;
+        Result::Ok(Transaction {
+            connection: self,
+            savepoint,
+            committed: false,
+        })
+    }
+
+    /// how long statements wait for a db locked by another connection or process
+    /// before failing with `RdbError::Busy`, zero fails right away with `database
+    /// is locked.`
+    pub fn set_busy_timeout(&mut self, timeout: Duration) {
+        self.busy_timeout = timeout;
+    }
+
+    /// the names and paths of the main and the attached dbs.
+    pub fn databases(&self) -> Vec<(String, String)> {
+        let main = self
+            .shared
+            .read_table()
+            .tree
+            .pager
+            .get_path()
+            .unwrap_or(MEMORY_DB)
+            .to_owned();
+        let mut databases = vec![(MAIN_DB.to_owned(), main)];
+        for (name, path) in self.attached.databases() {
+            databases.push((name.to_owned(), path.to_owned()));
+        }
+        databases
+    }
+
+    /// run a statement, its result rows are dropped. returns the number of rows
+    /// inserted, deleted or returned.
+    pub fn execute(&mut self, sql: &str) -> Result<usize, RdbError> {
+        self.query(sql)?.finish()
+    }
+
+    /// run a statement, its result rows are read as they are iterated.
+    pub fn query(&mut self, sql: &str) -> Result<Rows, RdbError> {
+        let mut statement = match self.statements.remove(sql) {
+            Some(statement) => statement,
+            None => self.prepare_owned(sql)?,
+        };
+        let rows = self.run(&mut statement);
+        if statement.parameter_count() == 0 {
+            if self.statements.len() >= STATEMENT_CACHE_SIZE {
+                self.statements.clear();
+            }
+            self.statements.insert(sql.to_owned(), statement);
+        }
+        rows
+    }
+
+    /// run a statement and map its result rows with `T::from_row`.
+    pub fn query_map<T: FromRow>(
+        &mut self,
+        sql: &str,
+    ) -> Result<impl Iterator<Item = Result<T, RdbError>>, RdbError> {
+        self.query(sql).map(|rows| rows.map(|row| T::from_row(&row)))
+    }
+
+    /// insert a struct or map whose fields are named after the columns of the
+    /// table, missing fields are NULL.
+    #[cfg(feature = "serde")]
+    pub fn insert<T: Serialize>(&mut self, table: &str, row: &T) -> Result<usize, RdbError> {
+        let schema = self
+            .shared
+            .read_table()
+            .find_table(table)?
+            .ok_or_else(|| format!("no such table: {}", table))?
+            .schema;
+        let columns = schema.row_columns();
+        let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+        let values = serialize::insert_values(table, &columns, row)?;
+        self.execute(&vm::insert_statement(table, &schema, &values))
+    }
+
+    /// insert a batch of rows as one statement, see `Table::insert_many`.
+    pub fn insert_many<I: IntoIterator<Item = Row>>(
+        &mut self,
+        table: &str,
+        rows: I,
+    ) -> Result<InsertReport, RdbError> {
+        if table != TABLE_NAME {
+            return Result::Err(format!("no such table: {}", table).into());
+        }
+        let writer = self.shared.wait_for_writer(self.id, self.busy_timeout, None)?;
+        self.shared.write(self.id, writer, |table| table.insert_many(rows))
+    }
+
+    /// run a statement and decode its result rows into `T`, see `RowView::decode`.
+    #[cfg(feature = "serde")]
+    pub fn query_as<T: DeserializeOwned>(&mut self, sql: &str) -> Result<Vec<T>, RdbError> {
+        Result::Ok(self.query(sql)?.map(|row| row.decode()).collect::<Result<_, _>>()?)
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let mut writer = self.shared.writer();
+        if *writer == Some(self.id) {
+            warn!("rolling back the open transaction of a dropped connection");
+            let rolled_back = self
+                .shared
+                .table
+                .write()
+                .unwrap_or_else(PoisonError::into_inner)
+                .rollback_transaction();
+            if let Result::Err(e) = rolled_back {
+                warn!("cannot roll back the transaction of a dropped connection: {}", e);
+            }
+            *writer = None;
+        }
+    }
+}
+
+/// a transaction of a connection, statements run on it through `Deref`. dropping
+/// it without a commit rolls it back, also when a panic unwinds through it.
+pub struct Transaction<'a> {
+    connection: &'a mut Connection,
+    savepoint: String,
+    committed: bool,
+}
+
+impl<'a> Transaction<'a> {
+    /// keep the changes, they are written unless this is a transaction inside
+    /// another one.
+    pub fn commit(mut self) -> Result<(), RdbError> {
+        let release = format!("release {}", self.savepoint);
+        self.connection.execute(&release)?;
+        self.committed = true;
+        Result::Ok(())
+    }
+}
+
+impl<'a> Deref for Transaction<'a> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.connection
+    }
+}
+
+impl<'a> DerefMut for Transaction<'a> {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.connection
+    }
+}
+
+impl<'a> Drop for Transaction<'a> {
+    fn drop(&mut self) {
+        self.connection.transactions -= 1;
+        if self.committed {
+            return;
+        }
+        let rollback = format!("rollback to {}", self.savepoint);
+        let release = format!("release {}", self.savepoint);
+        let result = self
+            .connection
+            .execute(&rollback)
+            .and_then(|_| self.connection.execute(&release));
+        if let Result::Err(msg) = result {
+            warn!("cannot roll back {}: {}", self.savepoint, msg);
+        }
+    }
+}
+
+/// a prepared statement, its parameters stay bound between executions.
+pub struct Statement<'a> {
+    connection: &'a mut Connection,
+    statement: vm::Statement,
+}
+
+impl<'a> Statement<'a> {
+    /// the highest parameter index, see `vm::Statement::parameter_count`.
+    pub fn parameter_count(&self) -> usize {
+        self.statement.parameter_count()
+    }
+
+    /// bind a value to the parameter at given index, starting at 1.
+    pub fn bind<T: ToValue>(&mut self, index: usize, value: T) -> Result<(), RdbError> {
+        Result::Ok(self.statement.bind(index, value.to_value())?)
+    }
+
+    pub fn bind_null(&mut self, index: usize) -> Result<(), RdbError> {
+        Result::Ok(self.statement.bind(index, Value::Null)?)
+    }
+
+    pub fn clear_bindings(&mut self) {
+        self.statement.clear_bindings();
+    }
+
+    /// run the statement, its result rows are dropped. returns the number of rows
+    /// inserted, deleted or returned.
+    pub fn execute(&mut self) -> Result<usize, RdbError> {
+        self.query()?.finish()
+    }
+
+    /// run the statement, its result rows are read as they are iterated.
+    pub fn query(&mut self) -> Result<Rows, RdbError> {
+        self.connection.run(&mut self.statement)
+    }
+
+    /// run the statement and map its result rows with `T::from_row`.
+    pub fn query_map<T: FromRow>(
+        &mut self,
+    ) -> Result<impl Iterator<Item = Result<T, RdbError>>, RdbError> {
+        self.query().map(|rows| rows.map(|row| T::from_row(&row)))
+    }
+}
+
+/// the rows of a query. they are read a batch at a time as they are iterated, the
+/// table is only held while a batch is read so the rows after it see the changes
+/// made in between.
+pub struct Rows {
+    columns: Arc<Vec<String>>,
+    /// rows inserted, deleted or returned so far, see `Connection::execute`.
+    changes: usize,
+    /// locked to read the rows left through a shared borrow, see `len`.
+    batches: Mutex<Batches>,
+}
+
+impl Rows {
+    /// the rows a statement collected, `rest` reads the others.
+    fn new(statement: &vm::Statement, collector: Collector, rest: Option<Rest>) -> Rows {
+        Rows {
+            columns: Arc::new(collector.columns),
+            changes: statement.changed_rows().unwrap_or(0),
+            batches: Mutex::new(Batches {
+                rows: collector.rows.into(),
+                rest,
+                error: None,
+            }),
+        }
+    }
+
+    fn batches(&self) -> MutexGuard<'_, Batches> {
+        self.batches.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// the rows inserted or deleted, or the rows returned so far.
+    pub fn changes(&self) -> usize {
+        self.changes
+    }
+
+    /// the number of rows left, the rest of the query is read to count them.
+    pub fn len(&self) -> usize {
+        let mut batches = self.batches();
+        batches.read_all();
+        batches.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let mut batches = self.batches();
+        while batches.rows.is_empty() && batches.read() {}
+        batches.rows.is_empty()
+    }
+
+    /// the error that stopped the query before its last row, the iteration ends
+    /// with it.
+    pub fn error(&self) -> Option<RdbError> {
+        self.batches().error.clone()
+    }
+
+    /// read the rows left, returns the number of rows inserted, deleted or
+    /// returned.
+    pub fn finish(mut self) -> Result<usize, RdbError> {
+        while self.next().is_some() {}
+        match self.error() {
+            Some(e) => Result::Err(e),
+            None => Result::Ok(self.changes),
+        }
+    }
+
+    /// the rows left, without using them up.
+    #[cfg(feature = "serde")]
+    pub(crate) fn remaining(&self) -> Result<Vec<RowView>, RdbError> {
+        let mut batches = self.batches();
+        batches.read_all();
+        if let Some(ref e) = batches.error {
+            return Result::Err(e.clone());
+        }
+        Result::Ok(
+            batches
+                .rows
+                .iter()
+                .map(|values| RowView::new(self.columns.clone(), values.clone()))
+                .collect(),
+        )
+    }
+}
+
+impl Iterator for Rows {
+    type Item = RowView;
+
+    fn next(&mut self) -> Option<RowView> {
+        let batches = self.batches.get_mut().unwrap_or_else(PoisonError::into_inner);
+        while batches.rows.is_empty() && batches.read() {}
+        let values = batches.rows.pop_front()?;
+        self.changes += 1;
+        Some(RowView::new(self.columns.clone(), values))
+    }
+}
+
+/// the rows of a query read but not returned yet.
+struct Batches {
+    rows: VecDeque<Vec<Value>>,
+    /// the query the other rows come from, `None` once it is done.
+    rest: Option<Rest>,
+    error: Option<RdbError>,
+}
+
+impl Batches {
+    /// read the next batch of rows, false if the query is done.
+    fn read(&mut self) -> bool {
+        let mut rest = match self.rest.take() {
+            Some(rest) => rest,
+            None => return false,
+        };
+        let mut collector = Collector::default();
+        match rest.read(&mut collector) {
+            Result::Ok(done) => {
+                if !done {
+                    self.rest = Some(rest);
+                }
+            }
+            Result::Err(e) => self.error = Some(e),
+        }
+        self.rows.extend(collector.rows);
+        true
+    }
+
+    fn read_all(&mut self) {
+        while self.read() {}
+    }
+}
+
+/// a query paused after a batch of rows.
+struct Rest {
+    shared: Arc<Shared>,
+    connection: usize,
+    busy_timeout: Duration,
+    statement: vm::Statement,
+}
+
+impl Rest {
+    /// read the next batch of rows, true once the query is done. like any query
+    /// it waits for the transaction of another connection to end.
+    fn read(&mut self, collector: &mut Collector) -> Result<bool, RdbError> {
+        let writer = self.shared.wait_for_writer(
+            self.connection,
+            self.busy_timeout,
+            self.statement.cancel_flag(),
+        )?;
+        let table = self.shared.read_table();
+        drop(writer);
+        Result::Ok(self.statement.query_batch(&table, collector, ROWS_PER_BATCH)?)
+    }
+}
+
+#[derive(Default)]
+struct Collector {
+    columns: Vec<String>,
+    rows: Vec<Vec<Value>>,
+}
+
+impl RowSink for Collector {
+    fn columns(&mut self, names: &[String]) -> Result<(), String> {
+        self.columns = names.to_vec();
+        Result::Ok(())
+    }
+
+    fn row(&mut self, values: &[Value]) -> Result<(), String> {
+        self.rows.push(values.to_vec());
+        Result::Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::{env, fs, process, thread};
+
+    #[test]
+    fn queries_return_the_inserted_rows() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        {
+            let mut conn = db.connect();
+            assert_eq!(conn.execute("insert 2 bob bob@example.com"), Result::Ok(1));
+            assert_eq!(conn.execute("insert 1 'Ada Lovelace' ada@example.com"), Result::Ok(1));
+            assert!(conn.execute("insert 1 ada ada@example.com").is_err());
+            let rows = conn.query("select").unwrap();
+            assert_eq!(rows.columns(), ["id", "username", "email"]);
+            let rows: Vec<Vec<Value>> = rows.map(RowView::into_values).collect();
+            assert_eq!(
+                rows,
+                vec![
+                    vec![
+                        Value::Integer(1),
+                        Value::Text("Ada Lovelace".to_owned()),
+                        Value::Text("ada@example.com".to_owned()),
+                    ],
+                    vec![
+                        Value::Integer(2),
+                        Value::Text("bob".to_owned()),
+                        Value::Text("bob@example.com".to_owned()),
+                    ],
+                ]
+            );
+            assert_eq!(conn.execute("select"), Result::Ok(2));
+        }
+        assert_eq!(db.tables(), Result::Ok(vec!["users".to_owned()]));
+        assert!(db.columns("users").unwrap().unwrap().contains(&"email".to_owned()));
+        assert_eq!(db.columns("accounts"), Result::Ok(None));
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn queries_select_columns_of_every_row() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        conn.execute("insert 2 bob bob@example.com").unwrap();
+        conn.execute("insert 1 ada ada@example.com").unwrap();
+        let rows = conn.query("select email, id + 10, 'x' from users").unwrap();
+        assert_eq!(rows.columns(), ["email", "id + 10", "'x'"]);
+        let rows: Vec<Vec<Value>> = rows.map(RowView::into_values).collect();
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    Value::Text("ada@example.com".to_owned()),
+                    Value::Integer(11),
+                    Value::Text("x".to_owned()),
+                ],
+                vec![
+                    Value::Text("bob@example.com".to_owned()),
+                    Value::Integer(12),
+                    Value::Text("x".to_owned()),
+                ],
+            ]
+        );
+        assert_eq!(
+            conn.query("select name from users").err(),
+            Some("no such column: name".into())
+        );
+    }
+
+    #[test]
+    fn queries_filter_rows_by_where() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        for id in 1..21 {
+            conn.execute(&format!("insert {} user{} user{}@example.com", id, id, id))
+                .unwrap();
+        }
+        let ids = |conn: &mut Connection, sql: &str| -> Vec<Value> {
+            conn.query(sql)
+                .unwrap()
+                .map(|row| row.into_values().remove(0))
+                .collect()
+        };
+        let expected: Vec<Value> = (11..21).map(Value::Integer).collect();
+        assert_eq!(ids(&mut conn, "select * from users where id > 10"), expected);
+        assert_eq!(
+            ids(&mut conn, "select id from users where username = 'user7'"),
+            vec![Value::Integer(7)]
+        );
+        assert_eq!(
+            ids(&mut conn, "select id from users where id + 1 <= 3"),
+            vec![Value::Integer(1), Value::Integer(2)]
+        );
+        assert!(ids(&mut conn, "select id from users where id <> id").is_empty());
+        assert_eq!(ids(&mut conn, "select 1 where 2 >= 2"), vec![Value::Integer(1)]);
+
+        let rows = conn.query("select * from users where id = 3").unwrap();
+        assert_eq!(rows.columns(), ["id", "username", "email"]);
+        assert_eq!(
+            conn.query("select id from users where email > 1").err(),
+            Some("cannot compare email with 1.".into())
+        );
+        assert_eq!(
+            conn.query("select * from users where nosuch = 1").err(),
+            Some("no such column: nosuch".into())
+        );
+        assert_eq!(
+            conn.query("select id from users where upper(nosuch) like 'A%'").err(),
+            Some("no such column: nosuch".into())
+        );
+    }
+
+    #[test]
+    fn queries_page_through_rows_with_limit_and_offset() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        for id in 1..21 {
+            conn.execute(&format!("insert {} user{} user{}@example.com", id, id, id))
+                .unwrap();
+        }
+        let ids = |conn: &mut Connection, sql: &str| -> Vec<Value> {
+            conn.query(sql)
+                .unwrap()
+                .map(|row| row.into_values().remove(0))
+                .collect()
+        };
+        let range =
+            |ids: std::ops::Range<i64>| -> Vec<Value> { ids.map(Value::Integer).collect() };
+        assert_eq!(ids(&mut conn, "select * from users limit 3"), range(1..4));
+        assert_eq!(ids(&mut conn, "select id from users limit 3 offset 18"), range(19..21));
+        assert_eq!(
+            ids(&mut conn, "select id from users where id > 10 limit 2 offset 2"),
+            range(13..15)
+        );
+        assert!(ids(&mut conn, "select * from users limit 0").is_empty());
+        assert!(ids(&mut conn, "select 1 limit 1 offset 1").is_empty());
+        assert_eq!(ids(&mut conn, "select 1 limit 5"), range(1..2));
+    }
+
+    #[test]
+    fn queries_aggregate_groups_of_rows() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        for id in 1..11 {
+            let name = if id % 3 == 0 { "bob" } else { "ada" };
+            conn.execute(&format!("insert {} {} {}{}@example.com", id, name, name, id))
+                .unwrap();
+        }
+        let rows = |conn: &mut Connection, sql: &str| -> Vec<Vec<Value>> {
+            conn.query(sql).unwrap().map(RowView::into_values).collect()
+        };
+        let group = |name: &str, count| vec![Value::Text(name.to_owned()), Value::Integer(count)];
+        let rows_by_name = rows(&mut conn, "select username, count(*) from users group by username");
+        assert_eq!(rows_by_name, vec![group("ada", 7), group("bob", 3)]);
+        assert_eq!(
+            rows(&mut conn, "select username, count(*) from users where id > 5 group by username"),
+            vec![group("bob", 2), group("ada", 3)]
+        );
+        assert_eq!(
+            rows(&mut conn, "select username, count(*) from users group by username limit 1 offset 1"),
+            vec![group("bob", 3)]
+        );
+        assert_eq!(rows(&mut conn, "select count(*) from users"), vec![vec![Value::Integer(10)]]);
+        assert_eq!(
+            rows(&mut conn, "select count(*) from users where id > 10"),
+            vec![vec![Value::Integer(0)]]
+        );
+        assert!(rows(&mut conn, "select id from users where id > 10 group by id").is_empty());
+        assert_eq!(rows(&mut conn, "select count(*) + 1"), vec![vec![Value::Integer(2)]]);
+        assert_eq!(
+            rows(&mut conn, "select username from users group by username having count(*) < 5"),
+            vec![vec![Value::Text("bob".to_owned())]]
+        );
+        assert_eq!(
+            rows(&mut conn, "select count(*) from users having count(*) > 5"),
+            vec![vec![Value::Integer(10)]]
+        );
+        assert!(rows(&mut conn, "select count(*) from users having count(*) > 10").is_empty());
+        assert_eq!(
+            conn.query("select count(*) from users group by username having id > 1").err(),
+            Some("id must appear in the group by clause or be used in an aggregate.".into())
+        );
+        assert_eq!(
+            conn.query("select id, count(*) from users group by username").err(),
+            Some("id must appear in the group by clause or be used in an aggregate.".into())
+        );
+    }
+
+    #[test]
+    fn queries_sum_average_and_bound_values() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        let rows = |conn: &mut Connection, sql: &str| -> Vec<Vec<Value>> {
+            conn.query(sql).unwrap().map(RowView::into_values).collect()
+        };
+        assert_eq!(
+            rows(&mut conn, "select count(*), count(id), sum(id), avg(id), max(username) from users"),
+            vec![vec![Value::Integer(0), Value::Integer(0), Value::Null, Value::Null, Value::Null]]
+        );
+        for id in 1..6 {
+            let name = if id % 2 == 0 { "bob" } else { "ada" };
+            conn.execute(&format!("insert {} {} {}{}@example.com", id, name, name, id))
+                .unwrap();
+        }
+        assert_eq!(
+            rows(&mut conn, "select count(*), max(id), min(id), sum(id), avg(id), count(email) from users"),
+            vec![vec![
+                Value::Integer(5),
+                Value::Integer(5),
+                Value::Integer(1),
+                Value::Integer(15),
+                Value::Real(3.0),
+                Value::Integer(5)
+            ]]
+        );
+        assert_eq!(
+            rows(&mut conn, "select username, sum(id * 0.5), max(email) as last from users group by username"),
+            vec![
+                vec![Value::Text("ada".to_owned()), Value::Real(4.5), Value::Text("ada5@example.com".to_owned())],
+                vec![Value::Text("bob".to_owned()), Value::Real(3.0), Value::Text("bob4@example.com".to_owned())],
+            ]
+        );
+        assert_eq!(
+            rows(&mut conn, "select username from users group by username having min(id) > 1"),
+            vec![vec![Value::Text("bob".to_owned())]]
+        );
+        assert_eq!(
+            rows(&mut conn, "select count(null), sum(null), max(id) - min(id) from users"),
+            vec![vec![Value::Integer(0), Value::Null, Value::Integer(4)]]
+        );
+        assert_eq!(
+            conn.query("select sum(username) from users").err(),
+            Some("cannot take the sum of username.".into())
+        );
+        assert_eq!(
+            conn.query("select max(count(*)) from users").err(),
+            Some("misuse of aggregate count(*).".into())
+        );
+    }
+
+    #[test]
+    fn queries_read_the_rows_of_subqueries() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        for id in 1..11 {
+            let name = if id % 3 == 0 { "bob" } else { "ada" };
+            conn.execute(&format!("insert {} {} {}{}@example.com", id, name, name, id))
+                .unwrap();
+        }
+        let rows = |conn: &mut Connection, sql: &str| -> Vec<Vec<Value>> {
+            conn.query(sql).unwrap().map(RowView::into_values).collect()
+        };
+        let ints = |ints: &[i64]| -> Vec<Vec<Value>> {
+            ints.iter().map(|&v| vec![Value::Integer(v)]).collect()
+        };
+        assert_eq!(
+            rows(&mut conn, "select x from (select id as x from users where id > 5) where x < 8"),
+            ints(&[6, 7])
+        );
+        assert_eq!(
+            rows(&mut conn, "select y + 1 from (select x as y from (select id as x from users limit 2))"),
+            ints(&[2, 3])
+        );
+        assert_eq!(
+            rows(&mut conn, "select n from (select count(*) as n from users)"),
+            ints(&[10])
+        );
+        let query = conn
+            .query("select * from (select username as name, count(*) as n from users group by username)")
+            .unwrap();
+        assert_eq!(query.columns(), &["name".to_owned(), "n".to_owned()][..]);
+        assert_eq!(
+            query.map(RowView::into_values).collect::<Vec<_>>(),
+            vec![
+                vec![Value::Text("ada".to_owned()), Value::Integer(7)],
+                vec![Value::Text("bob".to_owned()), Value::Integer(3)],
+            ]
+        );
+        assert_eq!(
+            conn.query("select id from (select id as x from users)").err(),
+            Some("no such column: id".into())
+        );
+    }
+
+    #[test]
+    fn queries_probe_the_rows_of_in_and_exists_subqueries() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        for id in 1..11 {
+            let name = if id % 3 == 0 { "bob" } else { "ada" };
+            conn.execute(&format!("insert {} {} {}{}@example.com", id, name, name, id))
+                .unwrap();
+        }
+        let ids = |conn: &mut Connection, sql: &str| -> Vec<Value> {
+            conn.query(sql)
+                .unwrap()
+                .map(|row| row.into_values().remove(0))
+                .collect()
+        };
+        let ints = |ints: &[i64]| -> Vec<Value> { ints.iter().map(|&v| Value::Integer(v)).collect() };
+        assert_eq!(
+            ids(&mut conn, "select id from users where id in (select id + 5 from users where id < 3)"),
+            ints(&[6, 7])
+        );
+        assert_eq!(
+            ids(&mut conn, "select id from users where username in (select username from users where id = 3)"),
+            ints(&[3, 6, 9])
+        );
+        assert_eq!(
+            ids(&mut conn, "select id from users where exists (select id from users where id > 9) limit 2"),
+            ints(&[1, 2])
+        );
+        assert!(ids(&mut conn, "select id from users where exists (select id from users where id > 10)").is_empty());
+        assert_eq!(ids(&mut conn, "select 3 in (select id from users)"), vec![Value::Boolean(true)]);
+        assert_eq!(conn.execute("delete from users where id in (select id from users where id > 8)").unwrap(), 2);
+        assert_eq!(ids(&mut conn, "select count(*) from users"), ints(&[8]));
+        assert_eq!(
+            conn.query("select id from users where id in (select id, email from users)").err(),
+            Some("the select after in must return a single column.".into())
+        );
+    }
+
+    #[test]
+    fn queries_qualify_columns_with_the_alias_or_name_of_the_table() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        for id in 1..6 {
+            conn.execute(&format!("insert {} user{} user{}@example.com", id, id, id))
+                .unwrap();
+        }
+        let ids = |conn: &mut Connection, sql: &str| -> Vec<Value> {
+            conn.query(sql)
+                .unwrap()
+                .map(|row| row.into_values().remove(0))
+                .collect()
+        };
+        let ints = |ints: &[i64]| -> Vec<Value> { ints.iter().map(|&v| Value::Integer(v)).collect() };
+        assert_eq!(ids(&mut conn, "select u.id from users u where u.id > 3"), ints(&[4, 5]));
+        assert_eq!(ids(&mut conn, "select u.id + id from users as u limit 1"), ints(&[2]));
+        assert_eq!(
+            ids(&mut conn, "select t.x from (select id as x from users where id < 3) t"),
+            ints(&[1, 2])
+        );
+        assert_eq!(
+            conn.query("select v.id from users u").err(),
+            Some("no such column: v.id".into())
+        );
+        assert_eq!(
+            ids(&mut conn, "select users.id from users where users.id * 2 = 6"),
+            ints(&[3])
+        );
+        assert_eq!(
+            conn.query("select users.id from users u").err(),
+            Some("no such column: users.id".into())
+        );
+        assert_eq!(conn.execute("delete from users where users.id > 4"), Result::Ok(1));
+        assert_eq!(
+            conn.execute("delete from users where u.id > 4").err(),
+            Some("no such column: u.id".into())
+        );
+    }
+
+    #[test]
+    fn queries_compare_datetimes() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        let row = conn
+            .query("select date '2024-01-02', date '2024-01-02' > datetime '2024-01-01 23:59:59'")
+            .unwrap()
+            .next()
+            .unwrap();
+        assert_eq!(
+            row.columns(),
+            &[
+                "datetime '2024-01-02 00:00:00'",
+                "datetime '2024-01-02 00:00:00' > datetime '2024-01-01 23:59:59'"
+            ]
+        );
+        let values = row.into_values();
+        assert_eq!(values, vec![Value::DateTime(1_704_153_600), Value::Boolean(true)]);
+        assert_eq!(values[0].to_string(), "2024-01-02 00:00:00");
+
+        let sql = "select d from (select timestamp '2024-03-01 08:00:00' as d) \
+                   where d >= date '2024-03-01' and d in (datetime '2024-03-01 08:00:00')";
+        assert_eq!(conn.query(sql).unwrap().count(), 1);
+        let error = conn.query("select date '2024-01-01' < 1").err().unwrap();
+        assert_eq!(error, "cannot compare datetime '2024-01-01 00:00:00' with 1.".into());
+        assert!(conn.query("select date '2024-02-30'").is_err());
+    }
+
+    #[test]
+    fn queries_quote_names() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        conn.execute("insert into \"users\" (`id`, \"username\", email) values (1, 'ada', 'a@example.com')")
+            .unwrap();
+        let row = conn
+            .query("select \"username\" as \"the name\", `u`.email from users as \"u\" where \"u\".\"id\" = 1")
+            .unwrap()
+            .next()
+            .unwrap();
+        assert_eq!(row.columns(), &["the name", "u.email"]);
+        assert_eq!(
+            row.into_values(),
+            vec![Value::Text("ada".to_owned()), Value::Text("a@example.com".to_owned())]
+        );
+        assert_eq!(
+            conn.query("select \"no such\" from users").err(),
+            Some("no such column: no such".into())
+        );
+    }
+
+    #[test]
+    fn queries_run_operands_nested_as_deep_as_they_parse() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        conn.execute("insert 1 ada ada@example.com").unwrap();
+        let sum = format!("select 1{} from users", " + 1".repeat(98));
+        assert_eq!(conn.query(&sum).unwrap().next().unwrap().into_values(), vec![Value::Integer(99)]);
+        let nots = format!("select id from users where {}true", "not ".repeat(98));
+        assert_eq!(conn.query(&nots).unwrap().count(), 1);
+        let too_deep = format!("select 1{}", " + 1".repeat(3_000));
+        assert_eq!(
+            conn.query(&too_deep).err(),
+            Some("syntax error near 1, expected operands nested at most 100 levels deep.".into())
+        );
+    }
+
+    #[test]
+    fn explain_shows_the_plan_instead_of_running() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        conn.execute("insert 1 ada ada@example.com").unwrap();
+        let rows = conn
+            .query("explain query plan delete from users where id in (select id from users)")
+            .unwrap();
+        assert_eq!(rows.columns(), &["plan"]);
+        let steps: Vec<Vec<Value>> = rows.map(RowView::into_values).collect();
+        assert_eq!(
+            steps,
+            vec![
+                vec![Value::Text("scan table users".to_owned())],
+                vec![Value::Text("collect subquery 1 before the scan".to_owned())],
+                vec![Value::Text("  scan table users".to_owned())],
+            ]
+        );
+        assert_eq!(conn.execute("select"), Result::Ok(1));
+        assert_eq!(conn.execute("explain select * from users where id = ?"), Result::Ok(1));
+        assert_eq!(
+            conn.execute("explain select nope from users").err(),
+            Some("no such column: nope".into())
+        );
+        assert_eq!(
+            conn.execute("explain insert 2 bob bob@example.com").err(),
+            Some("only a select or a delete has a query plan.".into())
+        );
+    }
+
+    #[test]
+    fn rows_are_looked_up_by_the_id_they_are_compared_to() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        for id in 1..50 {
+            conn.execute(&format!("insert {} user{} user{}@example.com", id, id, id)).unwrap();
+        }
+        let plan: Vec<String> = conn
+            .query("explain select username from users where id = 7")
+            .unwrap()
+            .map(|row| row.get(0).unwrap())
+            .collect();
+        assert_eq!(plan, vec!["search by the primary key id = 7 in table users"]);
+        let plan: Vec<String> = conn
+            .query("explain select username from users where id < 7")
+            .unwrap()
+            .map(|row| row.get(0).unwrap())
+            .collect();
+        assert_eq!(plan, vec!["scan table users"]);
+
+        let usernames = |conn: &mut Connection, sql: &str| -> Vec<String> {
+            conn.query(sql).unwrap().map(|row| row.get(0).unwrap()).collect()
+        };
+        assert_eq!(usernames(&mut conn, "select username from users where id = 7"), vec!["user7"]);
+        assert_eq!(
+            usernames(&mut conn, "select username from users where 7 = id and username = 'x'"),
+            Vec::<String>::new()
+        );
+        assert!(usernames(&mut conn, "select username from users where id = 99").is_empty());
+        assert!(usernames(&mut conn, "select username from users where id = 4294967303").is_empty());
+        let mut select = conn.prepare("select username from users where id = ?").unwrap();
+        select.bind(1, 9).unwrap();
+        let rows: Vec<String> = select.query().unwrap().map(|row| row.get(0).unwrap()).collect();
+        assert_eq!(rows, vec!["user9"]);
+        select.bind(1, 99).unwrap();
+        assert!(select.query().unwrap().next().is_none());
+
+        assert_eq!(conn.execute("delete from users where id = 3 and username = 'x'"), Result::Ok(0));
+        assert_eq!(conn.execute("delete from users where id = 3"), Result::Ok(1));
+        assert_eq!(conn.execute("select * from users"), Result::Ok(48));
+    }
+
+    #[test]
+    fn stars_select_every_column_of_the_table() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        conn.execute("insert 1 ada ada@example.com").unwrap();
+        let row = conn
+            .query("select id * 10, u.* from users u where id = 1")
+            .unwrap()
+            .next()
+            .unwrap();
+        assert_eq!(row.columns(), &["id * 10", "id", "username", "email"]);
+        assert_eq!(row.into_values()[..2], [Value::Integer(10), Value::Integer(1)]);
+        let rows = conn.query("select * from (select id as x, username from users) t").unwrap();
+        assert_eq!(rows.columns(), &["x", "username"]);
+        assert_eq!(
+            conn.query("select v.* from users u").err(),
+            Some("no such table: v".into())
+        );
+        assert_eq!(
+            conn.query("select *").err(),
+            Some("no table to read the columns of * from.".into())
+        );
+        assert_eq!(
+            conn.query("select * from users group by username").err(),
+            Some("id must appear in the group by clause or be used in an aggregate.".into())
+        );
+    }
+
+    #[test]
+    fn statements_ignore_comments() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        conn.execute("insert into users values (1, 'ada', 'a@example.com') -- the first")
+            .unwrap();
+        let sql = "select /* the name */ username, '-- kept' from users\n-- every row\nwhere id = 1";
+        let row = conn.query(sql).unwrap().next().unwrap();
+        assert_eq!(
+            row.into_values(),
+            vec![Value::Text("ada".to_owned()), Value::Text("-- kept".to_owned())]
+        );
+        assert_eq!(conn.query("select 1 /* no end").err(), Some("unterminated comment.".into()));
+    }
+
+    #[test]
+    fn queries_unescape_doubled_quotes_in_strings() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        let row = conn
+            .query("select 'it''s', 'it''s' like 'it''%', ''")
+            .unwrap()
+            .next()
+            .unwrap();
+        assert_eq!(row.columns(), &["'it''s'", "'it''s' like 'it''%'", "''"]);
+        assert_eq!(
+            row.into_values(),
+            vec![
+                Value::Text("it's".to_owned()),
+                Value::Boolean(true),
+                Value::Text(String::new())
+            ]
+        );
+    }
+
+    #[test]
+    fn queries_evaluate_nulls() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        for id in 1..4 {
+            conn.execute(&format!("insert {} user{} user{}@example.com", id, id, id))
+                .unwrap();
+        }
+        let rows = conn.query("select null, 1, null + 1, not null, id = null from users where id = 1").unwrap();
+        assert_eq!(rows.columns(), &["null", "1", "null + 1", "not null", "id = null"]);
+        let row = rows.map(|row| row.into_values()).next().unwrap();
+        assert_eq!(row, vec![Value::Null, Value::Integer(1), Value::Null, Value::Null, Value::Null]);
+
+        // a null condition holds for no row
+        assert_eq!(conn.query("select id from users where id = null").unwrap().count(), 0);
+        assert_eq!(conn.query("select id from users where not null").unwrap().count(), 0);
+        assert_eq!(conn.query("select id from users where null or id > 1").unwrap().count(), 2);
+
+        let error = conn.execute("insert into users (id, username, email) values (4, null, 'a')");
+        assert_eq!(error.err().unwrap(), "username expects text, got NULL.".into());
+    }
+
+    #[test]
+    fn queries_evaluate_booleans() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        for id in 1..4 {
+            conn.execute(&format!("insert {} user{} user{}@example.com", id, id, id))
+                .unwrap();
+        }
+        let rows = conn.query("select true, false, 1 < 2, not true, (1 = 1) = true").unwrap();
+        assert_eq!(rows.columns(), &["true", "false", "1 < 2", "not true", "(1 = 1) = true"]);
+        let row = rows.map(|row| row.into_values()).next().unwrap();
+        let expected: Vec<Value> = [true, false, true, false, true].iter().map(|&v| Value::Boolean(v)).collect();
+        assert_eq!(row, expected);
+
+        let ids: Vec<Value> = conn
+            .query("select id from users where true and id > 1")
+            .unwrap()
+            .map(|row| row.into_values().remove(0))
+            .collect();
+        assert_eq!(ids, vec![Value::Integer(2), Value::Integer(3)]);
+        assert_eq!(conn.query("select id from users where false").unwrap().count(), 0);
+        let row = conn.query("select id > 2 as big from users where id = 3").unwrap().next().unwrap();
+        assert_eq!(row.get::<bool, _>("big"), Result::Ok(true));
+
+        let error = conn.query("select true + 1").err().unwrap();
+        assert_eq!(error, "cannot infer the type of true + 1.".into());
+    }
+
+    #[test]
+    fn queries_evaluate_reals() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        let rows = conn.query("select 2.75, 1 + 0.5, 7 / 2.0, -1.5 * 2, 1 < 1.5").unwrap();
+        assert_eq!(rows.columns(), &["2.75", "1 + 0.5", "7 / 2.0", "-1.5 * 2", "1 < 1.5"]);
+        let values = rows.map(|row| row.into_values()).next().unwrap();
+        assert_eq!(
+            values,
+            vec![
+                Value::Real(2.75),
+                Value::Real(1.5),
+                Value::Real(3.5),
+                Value::Real(-3.0),
+                Value::Boolean(true)
+            ]
+        );
+
+        let rows: Vec<Vec<Value>> = conn
+            .query("select x * 2 from (select 1.25 as x) where x in (1, 1.25)")
+            .unwrap()
+            .map(|row| row.into_values())
+            .collect();
+        assert_eq!(rows, vec![vec![Value::Real(2.5)]]);
+        assert_eq!(format!("{}", Value::Real(2.0)), "2.0");
+
+        let error = conn.execute("insert into users (id, username, email) values (1.5, 'a', 'b')");
+        assert_eq!(error.err().unwrap(), "id expects an integer, got real 1.5.".into());
+    }
+
+    #[test]
+    fn queries_evaluate_arithmetic_by_precedence() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        conn.execute("insert 7 user7 user7@example.com").unwrap();
+        let row = conn
+            .query("select 1 + 2 * 3, (1 + 2) * 3, 10 - 4 - 3, 7 / 2, -7 % 3, id * id - 1 from users")
+            .unwrap()
+            .next()
+            .unwrap();
+        let expected: Vec<Value> = [7, 9, 3, 3, -1, 48].iter().map(|&v| Value::Integer(v)).collect();
+        assert_eq!(row.into_values(), expected);
+
+        let error = conn.query("select id / (id - 7) from users").err().unwrap();
+        assert_eq!(error, "vm execute error: division by zero.".into());
+
+        let row = conn
+            .query("select -id, -(id + 1), - -id, -id * 2.5 from users where -id < 0")
+            .unwrap()
+            .next()
+            .unwrap();
+        let expected = vec![Value::Integer(-7), Value::Integer(-8), Value::Integer(7), Value::Real(-17.5)];
+        assert_eq!(row.into_values(), expected);
+        let error = conn.query("select -username from users").err().unwrap();
+        assert_eq!(error, "cannot negate username.".into());
+    }
+
+    #[test]
+    fn queries_check_values_against_in_lists() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        for id in 1..6 {
+            conn.execute(&format!("insert {} user{} user{}@example.com", id, id, id))
+                .unwrap();
+        }
+        let ids = |conn: &mut Connection, sql: &str| -> Vec<Value> {
+            conn.query(sql)
+                .unwrap()
+                .map(|row| row.into_values().remove(0))
+                .collect()
+        };
+        let ints = |ints: &[i64]| -> Vec<Value> { ints.iter().map(|&v| Value::Integer(v)).collect() };
+        assert_eq!(ids(&mut conn, "select id from users where id in (4, 2, 9)"), ints(&[2, 4]));
+        assert_eq!(
+            ids(&mut conn, "select id from users where username not in ('user1', 'user5')"),
+            ints(&[2, 3, 4])
+        );
+        let error = conn.query("select id from users where id in (1, 'user1')").err().unwrap();
+        assert_eq!(error, "cannot compare id with 'user1'.".into());
+        let error = conn.query("select id from users where id in (1, id)").err().unwrap();
+        assert_eq!(error, "the values after in must be literals, not id.".into());
+        // the rows not listed would be neither in nor not in the list
+        for sql in &["select id from users where id in (1, null)", "select id not in (null) from users"] {
+            let error = conn.query(sql).err().unwrap();
+            assert_eq!(error, "the values after in cannot be null.".into());
+        }
+    }
+
+    #[test]
+    fn queries_take_the_first_value_that_is_not_null() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        let rows = |conn: &mut Connection, sql: &str| -> Vec<Vec<Value>> {
+            conn.query(sql).unwrap().map(RowView::into_values).collect()
+        };
+        assert_eq!(
+            rows(&mut conn, "select coalesce(null, 2, 3), ifnull(null, 'x'), coalesce(null, null), coalesce(1, 2.5)"),
+            vec![vec![Value::Integer(2), Value::Text("x".to_owned()), Value::Null, Value::Real(1.0)]]
+        );
+        assert_eq!(
+            rows(&mut conn, "select coalesce(max(id), -1), ifnull(sum(id), 0) + 1 from users"),
+            vec![vec![Value::Integer(-1), Value::Integer(1)]]
+        );
+        conn.execute("insert 3 ada ada@example.com").unwrap();
+        assert_eq!(
+            rows(&mut conn, "select coalesce(max(id), -1), ifnull(sum(id), 0) + 1 from users"),
+            vec![vec![Value::Integer(3), Value::Integer(4)]]
+        );
+        // the arguments after the one taken are not evaluated
+        assert_eq!(rows(&mut conn, "select coalesce(id, 1 / 0) from users"), vec![vec![Value::Integer(3)]]);
+
+        let error = conn.query("select ifnull(1)").err().unwrap();
+        assert_eq!(error, "wrong arguments for ifnull(1).".into());
+        let error = conn.query("select coalesce(1, 'x')").err().unwrap();
+        assert_eq!(error, "wrong arguments for coalesce(1, 'x').".into());
+    }
+
+    #[test]
+    fn queries_call_scalar_functions() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        conn.execute("insert 1 alice alice@example.com").unwrap();
+        conn.execute("insert 2 Bob bob@example.org").unwrap();
+        let rows: Vec<Vec<Value>> = conn
+            .query("select upper(username), length(email), abs(id - 2) from users where lower(username) = 'bob'")
+            .unwrap()
+            .map(|row| row.into_values())
+            .collect();
+        assert_eq!(
+            rows,
+            vec![vec![Value::Text("BOB".to_owned()), Value::Integer(15), Value::Integer(0)]]
+        );
+        let row = conn.query("select abs(-2.5), length(upper('ß')), abs(null)").unwrap().next().unwrap();
+        assert_eq!(row.columns(), &["abs(-2.5)", "length(upper('ß'))", "abs(null)"]);
+        assert_eq!(row.into_values(), vec![Value::Real(2.5), Value::Integer(2), Value::Null]);
+
+        let error = conn.query("select nope(id) from users").err().unwrap();
+        assert_eq!(error, "no such function: nope".into());
+        let error = conn.query("select upper(id) from users").err().unwrap();
+        assert_eq!(error, "wrong arguments for upper(id).".into());
+        let error = conn.query("select abs(-9223372036854775807 - 1)").err().unwrap();
+        assert_eq!(error, "vm execute error: integer overflow.".into());
+    }
+
+    #[test]
+    fn queries_match_strings_with_like() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        for (id, name) in ["alice", "alan", "bob", "Alba"].iter().enumerate() {
+            conn.execute(&format!("insert {} {} {}@example.com", id + 1, name, name))
+                .unwrap();
+        }
+        let names = |conn: &mut Connection, sql: &str| -> Vec<Value> {
+            conn.query(sql)
+                .unwrap()
+                .map(|row| row.into_values().remove(0))
+                .collect()
+        };
+        let texts = |texts: &[&str]| -> Vec<Value> {
+            texts.iter().map(|text| Value::Text(text.to_string())).collect()
+        };
+        assert_eq!(
+            names(&mut conn, "select username from users where username like 'al%'"),
+            texts(&["alice", "alan", "Alba"])
+        );
+        assert_eq!(
+            names(&mut conn, "select username from users where username like '_l_n'"),
+            texts(&["alan"])
+        );
+        assert_eq!(
+            names(&mut conn, "select username from users where email not like 'a%'"),
+            texts(&["bob"])
+        );
+        let error = conn.query("select id from users where id like '1'").err().unwrap();
+        assert_eq!(error, "cannot match id with like.".into());
+    }
+
+    #[test]
+    fn queries_filter_rows_by_and_or_not() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        for id in 1..11 {
+            conn.execute(&format!("insert {} user{} user{}@example.com", id, id, id))
+                .unwrap();
+        }
+        let ids = |conn: &mut Connection, sql: &str| -> Vec<Value> {
+            conn.query(sql)
+                .unwrap()
+                .map(|row| row.into_values().remove(0))
+                .collect()
+        };
+        let ints = |ints: &[i64]| -> Vec<Value> { ints.iter().map(|&v| Value::Integer(v)).collect() };
+        assert_eq!(
+            ids(&mut conn, "select id from users where id > 2 and id < 5 or id = 9"),
+            ints(&[3, 4, 9])
+        );
+        assert_eq!(
+            ids(&mut conn, "select id from users where not (id < 9 or username = 'user9')"),
+            ints(&[10])
+        );
+        assert_eq!(
+            ids(&mut conn, "select id from users where id not in (select id from users where id > 2)"),
+            ints(&[1, 2])
+        );
+        let row = conn.query("select 2 and 3, 0 or 0, not 5").unwrap().next().unwrap();
+        assert_eq!(
+            row.into_values(),
+            vec![Value::Boolean(true), Value::Boolean(false), Value::Boolean(false)]
+        );
+    }
+
+    #[test]
+    fn and_or_not_follow_the_truth_tables_of_nulls() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        for id in 1..4 {
+            conn.execute(&format!("insert {} user{} user{}@example.com", id, id, id))
+                .unwrap();
+        }
+        // the operands are conditions on id so the nulls are only known per row
+        let truth = [
+            ("id = 1", Value::Boolean(true)),
+            ("id = 2", Value::Boolean(false)),
+            ("null", Value::Null),
+        ];
+        let and = |a: &Value, b: &Value| match (a, b) {
+            (Value::Boolean(false), _) | (_, Value::Boolean(false)) => Value::Boolean(false),
+            (Value::Null, _) | (_, Value::Null) => Value::Null,
+            _ => Value::Boolean(true),
+        };
+        let or = |a: &Value, b: &Value| match (a, b) {
+            (Value::Boolean(true), _) | (_, Value::Boolean(true)) => Value::Boolean(true),
+            (Value::Null, _) | (_, Value::Null) => Value::Null,
+            _ => Value::Boolean(false),
+        };
+        let not = |a: &Value| match a {
+            Value::Boolean(v) => Value::Boolean(!v),
+            _ => Value::Null,
+        };
+        for (a, a_value) in &truth {
+            for (b, b_value) in &truth {
+                let sql = format!(
+                    "select ({a}) and ({b}), ({a}) or ({b}), not (({a}) and ({b})), not (({a}) or ({b})) \
+                     from users where id = 1",
+                    a = a,
+                    b = b
+                );
+                let row = conn.query(&sql).unwrap().next().unwrap().into_values();
+                let expected = vec![
+                    and(a_value, b_value),
+                    or(a_value, b_value),
+                    not(&and(a_value, b_value)),
+                    not(&or(a_value, b_value)),
+                ];
+                assert_eq!(row, expected, "{}", sql);
+            }
+        }
+
+        // a null condition holds for no row, and neither does its negation
+        let ids = |conn: &mut Connection, sql: &str| -> Vec<Value> {
+            conn.query(sql).unwrap().map(|row| row.into_values().remove(0)).collect()
+        };
+        assert_eq!(
+            ids(&mut conn, "select id from users where not (id = 1 and null)"),
+            vec![Value::Integer(2), Value::Integer(3)]
+        );
+        assert!(ids(&mut conn, "select id from users where not (id = 1 or null)").is_empty());
+        assert_eq!(
+            ids(&mut conn, "select count(*) from users where (id > 1 and null) = false"),
+            vec![Value::Integer(1)]
+        );
+    }
+
+    #[test]
+    fn deletes_remove_the_rows_they_match() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        for id in 1..41 {
+            conn.execute(&format!("insert {} user{} user{}@example.com", id, id, id))
+                .unwrap();
+        }
+        let ids = |conn: &mut Connection| -> Vec<Value> {
+            conn.query("select id from users")
+                .unwrap()
+                .map(|row| row.into_values().remove(0))
+                .collect()
+        };
+        assert_eq!(conn.execute("delete from users where id > 10").unwrap(), 30);
+        assert_eq!(ids(&mut conn), (1..11).map(Value::Integer).collect::<Vec<_>>());
+        assert_eq!(conn.execute("delete from users where id = 42").unwrap(), 0);
+        conn.execute("insert 20 user20 user20@example.com").unwrap();
+        assert_eq!(conn.execute("delete from users").unwrap(), 11);
+        assert!(ids(&mut conn).is_empty());
+        conn.execute("insert 1 user1 user1@example.com").unwrap();
+        assert_eq!(ids(&mut conn), vec![Value::Integer(1)]);
+        assert_eq!(
+            conn.execute("delete from users where email > 1").err(),
+            Some("cannot compare email with 1.".into())
+        );
+    }
+
+    #[test]
+    fn created_tables_keep_their_rows_apart_from_the_users_table() {
+        let path = env::temp_dir().join(format!("rdb-created-tables-{}.rdb", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        let titles = |conn: &mut Connection, sql: &str| -> Vec<Vec<Value>> {
+            conn.query(sql).unwrap().map(RowView::into_values).collect()
+        };
+        {
+            let db = Database::open(path).unwrap();
+            let mut conn = db.connect();
+            assert_eq!(
+                conn.execute("create table users (id integer, username text, email text)").err(),
+                Some("table users already exists.".into())
+            );
+            conn.execute("create table books (title text, pages integer, rating real)").unwrap();
+            assert_eq!(
+                conn.execute("create table books (title text)").err(),
+                Some("table books already exists.".into())
+            );
+            conn.execute("insert 1 ada ada@example.com").unwrap();
+            conn.execute("insert into books values ('dune', 412, 4)").unwrap();
+            {
+                let mut insert = conn.prepare("insert into books (pages, title, rating) values (?, ?, ?)").unwrap();
+                for (title, pages) in [("emma", 474), ("ulysses", 730)].iter() {
+                    insert.bind(1, *pages as i64).unwrap();
+                    insert.bind(2, *title).unwrap();
+                    insert.bind(3, 3.5).unwrap();
+                    assert_eq!(insert.execute(), Result::Ok(1));
+                }
+            }
+            assert_eq!(
+                conn.execute("insert into books values ('it', 'long', 1)").err(),
+                Some("column pages takes integer values, not text.".into())
+            );
+            assert_eq!(
+                titles(&mut conn, "select title, pages from books where pages > 420"),
+                vec![
+                    vec![Value::Text("emma".to_owned()), Value::Integer(474)],
+                    vec![Value::Text("ulysses".to_owned()), Value::Integer(730)],
+                ]
+            );
+            assert_eq!(
+                titles(&mut conn, "select * from books where title = 'dune'"),
+                vec![vec![
+                    Value::Text("dune".to_owned()),
+                    Value::Integer(412),
+                    Value::Real(4.0),
+                ]]
+            );
+            assert_eq!(conn.execute("delete from books where title = 'emma'"), Result::Ok(1));
+            assert_eq!(
+                conn.execute("select id from books").err(),
+                Some("no such column: id".into())
+            );
+            assert_eq!(
+                conn.execute("select title from books where title in (select username from users)").err(),
+                Some("cannot read tables books and users in one statement.".into())
+            );
+            assert_eq!(conn.execute("select * from users"), Result::Ok(1));
+            assert_eq!(db.tables(), Result::Ok(vec!["users".to_owned(), "books".to_owned()]));
+            assert_eq!(
+                db.columns("books"),
+                Result::Ok(Some(vec!["pages".to_owned(), "rating".to_owned(), "title".to_owned()]))
+            );
+            drop(conn);
+            db.close().unwrap();
+        }
+        let db = Database::open(path).unwrap();
+        let mut conn = db.connect();
+        conn.execute("insert into books values ('emma', 474, 3.5)").unwrap();
+        // rows come in the order they were inserted, after the ones of the last session
+        assert_eq!(
+            titles(&mut conn, "select title from books"),
+            vec![
+                vec![Value::Text("dune".to_owned())],
+                vec![Value::Text("ulysses".to_owned())],
+                vec![Value::Text("emma".to_owned())],
+            ]
+        );
+        let rows = conn.query("pragma table_info(books)").unwrap();
+        assert_eq!(
+            rows.map(RowView::into_values).collect::<Vec<_>>()[2],
+            vec![
+                Value::Integer(2),
+                Value::Text("rating".to_owned()),
+                Value::Text("real".to_owned()),
+                Value::Integer(0),
+            ]
+        );
+        drop(conn);
+        db.close().unwrap();
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn pragmas_read_and_write_settings() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        let rows = conn.query("pragma page_size").unwrap();
+        assert_eq!(rows.columns(), ["page_size".to_owned()]);
+        let rows: Vec<Vec<Value>> = rows.map(RowView::into_values).collect();
+        assert_eq!(rows, vec![vec![Value::Integer(4096)]]);
+        conn.execute("pragma cache_size = 100").unwrap();
+        assert_eq!(
+            conn.query("pragma cache_size").unwrap().map(RowView::into_values).collect::<Vec<_>>(),
+            vec![vec![Value::Integer(100)]]
+        );
+        assert_eq!(
+            conn.execute("pragma cache_size = -1").err(),
+            Some("cache_size takes a number of pages, 0 for no limit.".into())
+        );
+        assert_eq!(
+            conn.execute("pragma page_size = 8192").err(),
+            Some("page_size cannot be changed.".into())
+        );
+        assert_eq!(
+            conn.execute("pragma journal_mode").err(),
+            Some("no such pragma: journal_mode".into())
+        );
+        let rows = conn.query("pragma table_info(users)").unwrap();
+        assert_eq!(rows.columns(), ["cid", "name", "type", "pk"]);
+        assert_eq!(
+            rows.map(RowView::into_values).collect::<Vec<_>>()[1],
+            vec![
+                Value::Integer(1),
+                Value::Text("username".to_owned()),
+                Value::Text("text".to_owned()),
+                Value::Integer(0),
+            ]
+        );
+        assert_eq!(
+            conn.execute("pragma table_info(books)").err(),
+            Some("no such table: books".into())
+        );
+    }
+
+    #[test]
+    fn if_exists_clauses_make_schema_statements_idempotent() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        conn.execute("create table if not exists users (id integer, username text, email text)")
+            .unwrap();
+        conn.execute("create table if not exists books (id integer)").unwrap();
+        conn.execute("create table if not exists books (title text)").unwrap();
+        assert_eq!(db.columns("books"), Result::Ok(Some(vec!["id".to_owned()])));
+        conn.execute("drop table if exists accounts").unwrap();
+        assert_eq!(
+            conn.execute("drop table accounts").err(),
+            Some("no such table: accounts".into())
+        );
+        assert_eq!(
+            conn.execute("drop table if exists users").err(),
+            Some("cannot drop table users, every db has the users table.".into())
+        );
+        conn.execute("drop table if exists books").unwrap();
+        conn.execute("drop table if exists books").unwrap();
+        assert_eq!(db.tables(), Result::Ok(vec!["users".to_owned()]));
+    }
+
+    #[test]
+    fn dropped_tables_can_be_created_again() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        conn.execute("create table books (title text, pages integer)").unwrap();
+        for pages in 0..100 {
+            conn.execute(&format!("insert into books values ('book {}', {})", pages, pages))
+                .unwrap();
+        }
+        let mut select = conn.prepare_owned("select title from books").unwrap();
+        conn.execute("drop table books").unwrap();
+        assert_eq!(
+            conn.run(&mut select).err(),
+            Some("no such table: books".into())
+        );
+        assert_eq!(
+            conn.execute("select title from books").err(),
+            Some("no such table: books".into())
+        );
+        assert_eq!(
+            conn.execute("drop table books").err(),
+            Some("no such table: books".into())
+        );
+
+        conn.execute("create table books (title text, rating real)").unwrap();
+        assert_eq!(
+            conn.run(&mut select).err(),
+            Some("table books changed since the statement was prepared.".into())
+        );
+        conn.execute("insert into books values ('dune', 4.5)").unwrap();
+        let rows: Vec<Vec<Value>> = conn
+            .query("select * from books")
+            .unwrap()
+            .map(RowView::into_values)
+            .collect();
+        assert_eq!(rows, vec![vec![Value::Text("dune".to_owned()), Value::Real(4.5)]]);
+    }
+
+    #[test]
+    fn indexes_are_built_from_the_rows_and_follow_their_changes() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        let rows = (1..301).map(|id| Row {
+            id,
+            username: format!("user{}", id % 7),
+            email: format!("user{}@example.com", id),
+        });
+        conn.insert_many("users", rows).unwrap();
+        conn.execute("create index by_name on users (username)").unwrap();
+        let ids = |conn: &mut Connection, sql: &str| -> Vec<Value> {
+            conn.query(sql).unwrap().map(|row| row.into_values().remove(0)).collect()
+        };
+        let expected: Vec<Value> = (1..301)
+            .filter(|id| id % 7 == 3)
+            .map(|id| Value::Integer(id as i64))
+            .collect();
+        assert_eq!(ids(&mut conn, "select id from users where username = 'user3'"), expected);
+        assert_eq!(
+            ids(&mut conn, "explain query plan select id from users where username = 'user3'"),
+            vec![Value::Text(
+                "search by the index by_name username = 'user3' in table users".to_owned()
+            )]
+        );
+
+        conn.execute("insert 400 user3 late@example.com").unwrap();
+        assert_eq!(conn.execute("delete from users where username = 'user3' and id < 20"), Result::Ok(3));
+        let mut select = conn.prepare_owned("select id from users where username = ?").unwrap();
+        select.bind(1, Value::Text("user3".to_owned())).unwrap();
+        let found: Vec<Value> = conn
+            .run(&mut select)
+            .unwrap()
+            .map(|row| row.into_values().remove(0))
+            .collect();
+        let mut expected: Vec<Value> = expected.into_iter().skip(3).collect();
+        expected.push(Value::Integer(400));
+        assert_eq!(found, expected);
+
+        conn.execute("create table books (title text, pages integer)").unwrap();
+        conn.execute("create index by_pages on books (pages)").unwrap();
+        conn.execute("insert into books values ('dune', 412)").unwrap();
+        conn.execute("insert into books values ('emma', 474)").unwrap();
+        assert_eq!(
+            ids(&mut conn, "select title from books where pages = 474"),
+            vec![Value::Text("emma".to_owned())]
+        );
+        conn.execute("drop table books").unwrap();
+
+        assert_eq!(
+            conn.execute("create index by_name on users (email)").err(),
+            Some("index by_name already exists.".into())
+        );
+        assert_eq!(
+            conn.execute("create index by_age on users (age)").err(),
+            Some("no such column: age".into())
+        );
+        assert_eq!(
+            conn.execute("create index by_title on books (title)").err(),
+            Some("no such table: books".into())
+        );
+    }
+
+    #[test]
+    fn connections_insert_batches_of_rows() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        conn.execute("insert 2 bob bob@example.com").unwrap();
+        let rows = (1..4).map(|id| Row {
+            id,
+            username: format!("user{}", id),
+            email: format!("user{}@example.com", id),
+        });
+        let report = conn.insert_many("users", rows).unwrap();
+        assert_eq!(
+            report,
+            InsertReport {
+                inserted: 2,
+                failed_keys: vec![2],
+            }
+        );
+        let ids: Vec<i64> = conn
+            .query("select id from users")
+            .unwrap()
+            .map(|row| row.get::<i64, _>(0).unwrap())
+            .collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+        assert_eq!(
+            conn.insert_many("accounts", Vec::new()).err(),
+            Some("no such table: accounts".into())
+        );
+    }
+
+
+    #[test]
+    fn attached_dbs_are_reached_by_their_name() {
+        let path = env::temp_dir().join(format!("rdb-attach-{}.rdb", process::id()));
+        let path = path.to_str().unwrap().to_owned();
+        let _ = fs::remove_file(&path);
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        conn.execute("insert 1 ada ada@example.com").unwrap();
+        conn.execute(&format!("attach database '{}' as aux", path)).unwrap();
+        assert_eq!(
+            conn.execute(&format!("attach database '{}' as aux", path)),
+            Result::Err("database aux is already in use.".into())
+        );
+        assert_eq!(conn.execute("insert into aux.users 2 bob bob@example.com"), Result::Ok(1));
+        assert_eq!(
+            conn.databases(),
+            vec![
+                ("main".to_owned(), MEMORY_DB.to_owned()),
+                ("aux".to_owned(), path.clone()),
+            ]
+        );
+        let ids = |conn: &mut Connection, sql: &str| -> Result<Vec<i64>, RdbError> {
+            conn.query(sql).map(|rows| rows.map(|row| row.get::<i64, _>(0).unwrap()).collect())
+        };
+        assert_eq!(ids(&mut conn, "select * from users"), Result::Ok(vec![1]));
+        assert_eq!(ids(&mut conn, "select * from main.users"), Result::Ok(vec![1]));
+        assert_eq!(ids(&mut conn, "select * from aux.users"), Result::Ok(vec![2]));
+        // attached dbs belong to the connection
+        assert_eq!(
+            ids(&mut db.connect(), "select * from aux.users"),
+            Result::Err("no such database: aux".into())
+        );
+
+        conn.execute("detach aux").unwrap();
+        assert_eq!(
+            ids(&mut conn, "select * from aux.users"),
+            Result::Err("no such database: aux".into())
+        );
+        assert_eq!(conn.databases().len(), 1);
+        // the detached db was written to its file
+        conn.execute(&format!("attach '{}' as other", path)).unwrap();
+        assert_eq!(ids(&mut conn, "select * from other.users"), Result::Ok(vec![2]));
+        drop(conn);
+        db.close().unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn prepared_statements_run_with_new_bindings() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        {
+            let mut insert = conn.prepare("insert ? ? ?").unwrap();
+            assert_eq!(insert.parameter_count(), 3);
+            for id in 1..=20 {
+                insert.bind(1, id).unwrap();
+                insert.bind(2, format!("user{}", id)).unwrap();
+                insert.bind(3, "same@example.com").unwrap();
+                assert_eq!(insert.execute(), Result::Ok(1));
+            }
+            // bindings stay until they are cleared
+            insert.bind(1, 21).unwrap();
+            assert_eq!(insert.execute(), Result::Ok(1));
+            insert.clear_bindings();
+            assert_eq!(insert.execute(), Result::Err("parameter ?1 is not bound.".into()));
+        }
+        let rows: Vec<Vec<Value>> = conn.query("select").unwrap().map(RowView::into_values).collect();
+        assert_eq!(rows.len(), 21);
+        assert_eq!(rows[20][1], Value::Text("user20".to_owned()));
+
+        let mut select = conn.prepare("select 40 + 2").unwrap();
+        for _ in 0..3 {
+            let rows: Vec<Vec<Value>> = select.query().unwrap().map(RowView::into_values).collect();
+            assert_eq!(rows, vec![vec![Value::Integer(42)]]);
+        }
+    }
+
+    #[test]
+    fn prepared_queries_and_deletes_bind_parameters() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        for id in 1..=5 {
+            conn.execute(&format!("insert {} user{} user{}@example.com", id, id, id))
+                .unwrap();
+        }
+        {
+            let mut select = conn
+                .prepare("select username, ? * 2 from users where id > $1 and username != ?")
+                .unwrap();
+            assert_eq!(select.parameter_count(), 2);
+            assert_eq!(select.query().err(), Some("parameter ?1 is not bound.".into()));
+            select.bind(1, 3).unwrap();
+            select.bind(2, "user5").unwrap();
+            let rows: Vec<Vec<Value>> = select.query().unwrap().map(RowView::into_values).collect();
+            assert_eq!(rows, vec![vec![Value::Text("user4".to_owned()), Value::Integer(6)]]);
+            assert_eq!(select.query().unwrap().columns(), &["username", "?1 * 2"]);
+            // `?1` takes the type of `id` it is compared with
+            select.bind(1, 0.5).unwrap();
+            assert_eq!(
+                select.query().err(),
+                Some("vm execute error: parameter ?1 takes integer values, not 0.5.".into())
+            );
+            select.bind(1, "x").unwrap();
+            assert_eq!(
+                select.query().err(),
+                Some("vm execute error: parameter ?1 takes integer values, not 'x'.".into())
+            );
+        }
+        {
+            // an integer is taken for a real
+            let mut select = conn.prepare("select id from users where id * 1.5 > ?").unwrap();
+            select.bind(1, 6).unwrap();
+            assert_eq!(select.query().unwrap().count(), 1);
+            select.bind(1, 4.5).unwrap();
+            assert_eq!(select.query().unwrap().count(), 2);
+        }
+        {
+            let mut select = conn.prepare("select * from users where id in (select ?)").unwrap();
+            select.bind(1, 2).unwrap();
+            let rows: Vec<Vec<Value>> = select.query().unwrap().map(RowView::into_values).collect();
+            assert_eq!(rows[0][1], Value::Text("user2".to_owned()));
+            assert_eq!(select.query().unwrap().columns(), &["id", "username", "email"]);
+        }
+        {
+            let mut delete = conn.prepare("delete from users where id = ?").unwrap();
+            for id in &[1, 2, 9] {
+                delete.bind(1, *id).unwrap();
+                assert_eq!(delete.execute(), Result::Ok(if *id < 5 { 1 } else { 0 }));
+            }
+        }
+        assert_eq!(conn.query("select").unwrap().count(), 3);
+    }
+
+    fn assert_send<T: Send>() {}
+
+    fn assert_sync<T: Sync>() {}
+
+    #[test]
+    fn handles_are_send() {
+        assert_send::<Database>();
+        assert_sync::<Database>();
+        assert_send::<Connection>();
+        assert_send::<Statement>();
+        assert_send::<Rows>();
+        assert_send::<RowView>();
+    }
+
+    #[test]
+    fn connections_move_between_threads() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        thread::scope(|scope| {
+            for worker in 0..4 {
+                let mut conn = db.connect();
+                scope.spawn(move || {
+                    for i in 0..10 {
+                        let id = worker * 10 + i + 1;
+                        conn.execute(&format!("insert {} user{} user{}@example.com", id, id, id))
+                            .unwrap();
+                    }
+                });
+            }
+        });
+
+        let mut conn = db.connect();
+        let mut select = conn.prepare("select").unwrap();
+        let rows = thread::scope(|scope| scope.spawn(move || select.query()).join().unwrap());
+        assert_eq!(rows.unwrap().len(), 40);
+        drop(conn);
+        db.close().unwrap();
+    }
+
+    #[test]
+    fn queries_see_the_inserts_of_another_connection() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut writer = db.connect();
+        let mut reader = db.connect();
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                for id in 1..=200 {
+                    writer
+                        .execute(&format!("insert {} user{} user{}@example.com", id, id, id))
+                        .unwrap();
+                }
+            });
+            scope.spawn(move || {
+                let mut seen = 0;
+                while seen < 200 {
+                    let ids: Vec<i64> = reader
+                        .query("select")
+                        .unwrap()
+                        .map(|row| row.get(0).unwrap())
+                        .collect();
+                    // every query sees a whole number of inserts, in order
+                    assert!(ids.len() >= seen);
+                    assert_eq!(ids, (1..=ids.len() as i64).collect::<Vec<_>>());
+                    seen = ids.len();
+                }
+            });
+        });
+        assert_eq!(db.connect().execute("select"), Result::Ok(200));
+    }
+
+    #[test]
+    fn rows_are_read_a_batch_at_a_time() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut reader = db.connect();
+        let mut writer = db.connect();
+        let total = ROWS_PER_BATCH * 2 + 10;
+        for id in 1..=total {
+            let insert = format!("insert {} user{} user{}@example.com", id, id, id);
+            assert_eq!(writer.execute(&insert), Result::Ok(1));
+        }
+        let mut rows = reader.query("select id from users").unwrap();
+        assert_eq!(rows.next().unwrap().values(), &[Value::Integer(1)]);
+        // the rows after the first batch are read once the delete is done
+        let kept = ROWS_PER_BATCH + 5;
+        let delete = format!("delete from users where id > {}", kept);
+        assert_eq!(writer.execute(&delete), Result::Ok(total - kept));
+        assert_eq!(rows.finish(), Result::Ok(kept));
+
+        // a transaction of another connection stops the rows after the first batch
+        let mut rows = reader.query("select").unwrap();
+        writer.execute("savepoint a").unwrap();
+        assert_eq!(rows.by_ref().count(), ROWS_PER_BATCH);
+        assert_eq!(rows.error(), Some("database is locked.".into()));
+        writer.execute("release a").unwrap();
+        assert_eq!(reader.execute("select"), Result::Ok(kept));
+    }
+
+    #[test]
+    fn transactions_lock_out_other_connections() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut first = db.connect();
+        let mut second = db.connect();
+        first.execute("insert 1 ada ada@example.com").unwrap();
+        first.execute("savepoint a").unwrap();
+        first.execute("insert 2 bob bob@example.com").unwrap();
+        assert_eq!(first.execute("select"), Result::Ok(2));
+        let locked = Result::Err("database is locked.".into());
+        assert_eq!(second.execute("select"), locked);
+        assert_eq!(second.execute("insert 3 eve eve@example.com"), locked);
+        first.execute("release a").unwrap();
+        assert_eq!(second.execute("select"), Result::Ok(2));
+        assert_eq!(
+            db.close(),
+            Result::Err("cannot close the db: 2 connections are open.".into())
+        );
+    }
+
+    #[test]
+    fn busy_connections_wait_for_the_transaction_to_end() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut first = db.connect();
+        let mut second = db.connect();
+        first.execute("savepoint a").unwrap();
+        first.execute("insert 1 ada ada@example.com").unwrap();
+
+        second.set_busy_timeout(Duration::from_millis(30));
+        let busy = second.execute("insert 2 bob bob@example.com").unwrap_err();
+        assert_eq!(busy, RdbError::Busy(Duration::from_millis(30)));
+        assert_eq!(format!("{}", busy), "database is locked, still busy after 30 ms.");
+        second.set_busy_timeout(Duration::from_secs(10));
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                first.execute("release a").unwrap();
+            });
+            assert_eq!(second.execute("insert 2 bob bob@example.com"), Result::Ok(1));
+        });
+        assert_eq!(second.execute("select"), Result::Ok(2));
+    }
+
+    /// inserts bob, then fails on the duplicate id of ada.
+    fn insert_bob_and_ada_again(conn: &mut Connection) -> Result<(), RdbError> {
+        let mut tx = conn.transaction()?;
+        tx.execute("insert 2 bob bob@example.com")?;
+        tx.execute("insert 1 ada ada@example.com")?;
+        tx.commit()
+    }
+
+    #[test]
+    fn transactions_roll_back_unless_committed() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        conn.execute("insert 1 ada ada@example.com").unwrap();
+        assert_eq!(
+            insert_bob_and_ada_again(&mut conn),
+            Result::Err("Error: Duplicate key.".into())
+        );
+        assert_eq!(conn.execute("select"), Result::Ok(1));
+
+        let mut tx = conn.transaction().unwrap();
+        tx.execute("insert 2 bob bob@example.com").unwrap();
+        tx.commit().unwrap();
+        assert_eq!(conn.execute("select"), Result::Ok(2));
+        // committed for the other connections too
+        assert_eq!(db.connect().execute("select"), Result::Ok(2));
+
+        let panicked = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut tx = conn.transaction().unwrap();
+            tx.execute("insert 3 eve eve@example.com").unwrap();
+            panic!("in the middle of a transaction");
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(conn.execute("select"), Result::Ok(2));
+        conn.execute("insert 3 eve eve@example.com").unwrap();
+        assert_eq!(db.connect().execute("select"), Result::Ok(3));
+    }
+
+    #[test]
+    fn transactions_inside_transactions_are_savepoints() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        {
+            let mut outer = conn.transaction().unwrap();
+            outer.execute("insert 1 ada ada@example.com").unwrap();
+            {
+                let mut inner = outer.transaction().unwrap();
+                inner.execute("insert 2 bob bob@example.com").unwrap();
+            }
+            assert_eq!(outer.execute("select"), Result::Ok(1));
+            let mut inner = outer.transaction().unwrap();
+            inner.execute("insert 3 eve eve@example.com").unwrap();
+            inner.commit().unwrap();
+            // the inner commit is only written with the outer one
+            assert_eq!(
+                db.connect().execute("select"),
+                Result::Err("database is locked.".into())
+            );
+            outer.commit().unwrap();
+        }
+        assert_eq!(db.connect().execute("select"), Result::Ok(2));
+    }
+
+    #[test]
+    fn begin_starts_a_transaction_until_commit_or_rollback() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        conn.execute("begin").unwrap();
+        conn.execute("insert 1 ada ada@example.com").unwrap();
+        assert_eq!(
+            conn.execute("begin transaction"),
+            Result::Err("cannot begin a transaction within a transaction.".into())
+        );
+        conn.execute("rollback").unwrap();
+        assert_eq!(conn.execute("select"), Result::Ok(0));
+
+        conn.execute("begin").unwrap();
+        conn.execute("insert 2 bob bob@example.com").unwrap();
+        conn.execute("savepoint a").unwrap();
+        conn.execute("insert 3 eve eve@example.com").unwrap();
+        assert_eq!(
+            db.connect().execute("select"),
+            Result::Err("database is locked.".into())
+        );
+        // the commit releases the savepoints as well
+        conn.execute("commit transaction").unwrap();
+        assert_eq!(db.connect().execute("select"), Result::Ok(2));
+
+        assert_eq!(
+            conn.execute("commit"),
+            Result::Err("cannot commit: no transaction is active.".into())
+        );
+        assert_eq!(
+            conn.execute("rollback"),
+            Result::Err("cannot roll back: no transaction is active.".into())
+        );
+    }
+
+    #[test]
+    fn dropping_a_connection_rolls_back_its_transaction() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut second = db.connect();
+        {
+            let mut first = db.connect();
+            first.execute("insert 1 ada ada@example.com").unwrap();
+            first.execute("savepoint a").unwrap();
+            first.execute("insert 2 bob bob@example.com").unwrap();
+        }
+        assert_eq!(second.execute("select"), Result::Ok(1));
+        second.execute("insert 3 eve eve@example.com").unwrap();
+        assert_eq!(second.execute("select"), Result::Ok(2));
+        drop(second);
+        db.close().unwrap();
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct User {
+        id: u32,
+        username: String,
+        email: Option<String>,
+    }
+
+    impl FromRow for User {
+        fn from_row(row: &RowView) -> Result<User, RdbError> {
+            Result::Ok(User {
+                id: row.get(0)?,
+                username: row.get("username")?,
+                email: row.get("email")?,
+            })
+        }
+    }
+
+    #[test]
+    fn rows_map_into_structs() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        conn.execute("insert 1 ada ada@example.com").unwrap();
+        conn.execute("insert 2 bob bob@example.com").unwrap();
+        let users: Result<Vec<User>, RdbError> = conn.query_map("select").unwrap().collect();
+        assert_eq!(
+            users.unwrap(),
+            vec![
+                User {
+                    id: 1,
+                    username: "ada".to_owned(),
+                    email: Some("ada@example.com".to_owned()),
+                },
+                User {
+                    id: 2,
+                    username: "bob".to_owned(),
+                    email: Some("bob@example.com".to_owned()),
+                },
+            ]
+        );
+
+        let row = conn.query("select").unwrap().next().unwrap();
+        assert_eq!(row.get::<i64, _>("id"), Result::Ok(1));
+        assert_eq!(row.get::<Value, _>(1), Result::Ok(Value::Text("ada".to_owned())));
+        assert_eq!(
+            row.get::<i64, _>("email"),
+            Result::Err(RdbError::TypeMismatch {
+                column: "email".to_owned(),
+                expected: "i64",
+                found: "text",
+            })
+        );
+        assert_eq!(
+            row.get::<String, _>(0),
+            Result::Err(RdbError::TypeMismatch {
+                column: "id".to_owned(),
+                expected: "String",
+                found: "integer",
+            })
+        );
+        assert_eq!(row.get::<i64, _>("name"), Result::Err("no such column: name".into()));
+        assert_eq!(
+            row.get::<i64, _>(3),
+            Result::Err("column index 3 out of range, the row has 3 columns.".into())
+        );
+
+        let mut sums = conn.query_map::<User>("select 1 + 1").unwrap();
+        assert_eq!(
+            sums.next(),
+            Some(Result::Err("no such column: username".into()))
+        );
+    }
+
+    #[test]
+    fn parameters_are_numbered_and_checked() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        let mut insert = conn.prepare("insert ?2 'literal ?' ?1").unwrap();
+        assert_eq!(insert.parameter_count(), 2);
+        assert_eq!(
+            insert.bind(3, 1),
+            Result::Err("parameter index 3 out of range, the statement has 2 parameters.".into())
+        );
+        assert!(insert.bind(0, 1).is_err());
+        insert.bind(1, "a@example.com").unwrap();
+        insert.bind(2, "ada").unwrap();
+        assert_eq!(
+            insert.execute(),
+            Result::Err("id expects an integer, got text 'ada'.".into())
+        );
+        insert.bind(2, 7).unwrap();
+        insert.bind_null(1).unwrap();
+        assert_eq!(insert.execute(), Result::Err("email expects text, got NULL.".into()));
+        insert.bind(1, Some("a@example.com")).unwrap();
+        assert_eq!(insert.execute(), Result::Ok(1));
+        insert.bind(2, -1).unwrap();
+        assert_eq!(insert.execute(), Result::Err("ID must be positive.".into()));
+
+        let rows: Vec<Vec<Value>> = conn.query("select").unwrap().map(RowView::into_values).collect();
+        assert_eq!(rows[0][1], Value::Text("literal ?".to_owned()));
+        assert_eq!(
+            conn.prepare("insert ?0 a b").err(),
+            Some("invalid parameter: ?0".into())
+        );
+        assert!(conn.prepare("insert ?1000 a b").is_err());
+    }
+}
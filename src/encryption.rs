@@ -0,0 +1,134 @@
+//! page encryption of the pager, requires the `encryption` feature.
+//!
+//! pages are sealed with XChaCha20-Poly1305 under a key derived from the
+//! passphrase and a random salt kept in the db header, the page index is
+//! authenticated as well so pages cannot be swapped around.
+//!
+//! slot layout in an encrypted db: nonce (24 bytes) | sealed page | tag (16 bytes).
+
+use pager::PagerError;
+
+pub const NONCE_SIZE: usize = 24;
+pub const TAG_SIZE: usize = 16;
+pub const SALT_SIZE: usize = 16;
+pub const KEY_CHECK_SIZE: usize = 16;
+
+pub fn slot_size(page_size: usize) -> usize {
+    NONCE_SIZE + page_size + TAG_SIZE
+}
+
+#[cfg(feature = "encryption")]
+mod imp {
+    use byteorder::{BigEndian, ByteOrder};
+    use chacha20poly1305::aead::rand_core::RngCore;
+    use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+    use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+    use pbkdf2::pbkdf2_hmac;
+    use sha2::{Digest, Sha256};
+
+    use super::{KEY_CHECK_SIZE, NONCE_SIZE, SALT_SIZE};
+    use pager::PagerError;
+
+    const KDF_ROUNDS: u32 = 10_000;
+
+    pub struct Cipher {
+        key: [u8; 32],
+        aead: XChaCha20Poly1305,
+    }
+
+    impl Cipher {
+        pub fn new(passphrase: &str, salt: &[u8]) -> Result<Cipher, PagerError> {
+            let mut key = [0u8; 32];
+            pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, KDF_ROUNDS, &mut key);
+            let aead = XChaCha20Poly1305::new(&key.into());
+            Result::Ok(Cipher { key, aead })
+        }
+
+        pub fn random_salt() -> Result<[u8; SALT_SIZE], PagerError> {
+            let mut salt = [0u8; SALT_SIZE];
+            OsRng.fill_bytes(&mut salt);
+            Result::Ok(salt)
+        }
+
+        /// tells whether a key is the right one without touching any page.
+        pub fn key_check(&self) -> [u8; KEY_CHECK_SIZE] {
+            let mut hasher = Sha256::new();
+            hasher.update(self.key);
+            hasher.update(b"rdb key check");
+            let mut key_check = [0u8; KEY_CHECK_SIZE];
+            key_check.copy_from_slice(&hasher.finalize()[..KEY_CHECK_SIZE]);
+            key_check
+        }
+
+        pub fn encrypt_page(&self, page_index: usize, buf: &[u8]) -> Vec<u8> {
+            let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+            let mut aad = [0u8; 4];
+            BigEndian::write_u32(&mut aad, page_index as u32);
+            let payload = Payload { msg: buf, aad: &aad };
+            let sealed = self.aead.encrypt(&nonce, payload).unwrap();
+            let mut slot = Vec::with_capacity(NONCE_SIZE + sealed.len());
+            slot.extend_from_slice(&nonce);
+            slot.extend_from_slice(&sealed);
+            slot
+        }
+
+        pub fn decrypt_page(&self, page_index: usize, slot: &[u8]) -> Result<Vec<u8>, PagerError> {
+            let nonce = XNonce::from_slice(&slot[..NONCE_SIZE]);
+            let mut aad = [0u8; 4];
+            BigEndian::write_u32(&mut aad, page_index as u32);
+            let payload = Payload {
+                msg: &slot[NONCE_SIZE..],
+                aad: &aad,
+            };
+            self.aead
+                .decrypt(nonce, payload)
+                .map_err(|_| PagerError::PageAuthentication(page_index))
+        }
+    }
+}
+
+#[cfg(not(feature = "encryption"))]
+mod imp {
+    use super::{KEY_CHECK_SIZE, SALT_SIZE};
+    use pager::PagerError;
+
+    /// cannot be constructed without the `encryption` feature.
+    pub enum Cipher {}
+
+    impl Cipher {
+        pub fn new(_passphrase: &str, _salt: &[u8]) -> Result<Cipher, PagerError> {
+            Result::Err(PagerError::EncryptionUnsupported)
+        }
+
+        pub fn random_salt() -> Result<[u8; SALT_SIZE], PagerError> {
+            Result::Err(PagerError::EncryptionUnsupported)
+        }
+
+        pub fn key_check(&self) -> [u8; KEY_CHECK_SIZE] {
+            match *self {}
+        }
+
+        pub fn encrypt_page(&self, _page_index: usize, _buf: &[u8]) -> Vec<u8> {
+            match *self {}
+        }
+
+        pub fn decrypt_page(&self, _page_index: usize, _slot: &[u8]) -> Result<Vec<u8>, PagerError> {
+            match *self {}
+        }
+    }
+}
+
+pub use self::imp::Cipher;
+
+/// the passphrase is checked against the key check value from the db header.
+pub fn open_cipher(
+    passphrase: &str,
+    salt: &[u8],
+    key_check: &[u8],
+) -> Result<Cipher, PagerError> {
+    let cipher = Cipher::new(passphrase, salt)?;
+    if cipher.key_check()[..] != key_check[..] {
+        return Result::Err(PagerError::InvalidKey);
+    }
+    Result::Ok(cipher)
+}
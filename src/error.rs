@@ -0,0 +1,83 @@
+//! the errors of the embedding api.
+
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use pager::PagerError;
+
+/// why a `Database`, `Connection` or `Statement` call failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RdbError {
+    /// another connection or process kept the db locked for the whole busy
+    /// timeout, see `Connection::set_busy_timeout`.
+    Busy(Duration),
+    /// the value of a column could not be read as the type asked for, see
+    /// `RowView::get`.
+    TypeMismatch {
+        column: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// any other failure, with its message.
+    Message(String),
+}
+
+impl fmt::Display for RdbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RdbError::Busy(timeout) => write!(f, "{}", PagerError::Busy(timeout)),
+            RdbError::TypeMismatch {
+                ref column,
+                expected,
+                found,
+            } => write!(f, "cannot read column {} as {}: it holds {}.", column, expected, found),
+            RdbError::Message(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl Error for RdbError {}
+
+impl From<PagerError> for RdbError {
+    fn from(e: PagerError) -> RdbError {
+        match e {
+            PagerError::Busy(timeout) => RdbError::Busy(timeout),
+            e => RdbError::Message(format!("{}", e)),
+        }
+    }
+}
+
+impl From<String> for RdbError {
+    fn from(msg: String) -> RdbError {
+        RdbError::Message(msg)
+    }
+}
+
+impl<'a> From<&'a str> for RdbError {
+    fn from(msg: &'a str) -> RdbError {
+        RdbError::Message(msg.to_owned())
+    }
+}
+
+impl From<RdbError> for String {
+    fn from(e: RdbError) -> String {
+        format!("{}", e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn busy_errors_keep_their_timeout() {
+        let busy = RdbError::from(PagerError::Busy(Duration::from_millis(30)));
+        assert_eq!(busy, RdbError::Busy(Duration::from_millis(30)));
+        assert_eq!(String::from(busy), "database is locked, still busy after 30 ms.");
+        assert_eq!(
+            RdbError::from(PagerError::Locked),
+            RdbError::Message("database is locked.".to_owned())
+        );
+    }
+}
@@ -0,0 +1,463 @@
+//! the C api, shaped like the one of sqlite3. see `include/rdb.h`, generated from
+//! this file with `cbindgen --config cbindgen.toml --output include/rdb.h src/ffi.rs`.
+//!
+//! every function returns a result code and catches panics, a panic is
+//! `RDB_INTERNAL`. strings are UTF-8 and owned by the side that made them: rdb
+//! copies the strings it is given, the strings it returns stay valid until the
+//! next call on the same handle.
+
+#![allow(non_camel_case_types)]
+
+use std::convert::TryFrom;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use database::{Connection, Database, Rows};
+use error::RdbError;
+use row::RowView;
+use vm::{self, Value};
+
+/// the message of `rdb_errmsg` for a NULL handle.
+const NO_DB_HANDLE: &[u8] = b"no db handle.\0";
+
+pub const RDB_OK: c_int = 0;
+/// a statement failed, see `rdb_errmsg`.
+pub const RDB_ERROR: c_int = 1;
+/// rdb panicked, the handle should be closed.
+pub const RDB_INTERNAL: c_int = 2;
+/// the callback of `rdb_exec` asked to stop.
+pub const RDB_ABORT: c_int = 4;
+/// the db stayed locked by another connection for the whole busy timeout.
+pub const RDB_BUSY: c_int = 5;
+/// a NULL handle or string, or a string that is not UTF-8.
+pub const RDB_MISUSE: c_int = 21;
+/// a parameter index out of range.
+pub const RDB_RANGE: c_int = 25;
+/// `rdb_step` has a row ready.
+pub const RDB_ROW: c_int = 100;
+/// `rdb_step` ran the statement to its end.
+pub const RDB_DONE: c_int = 101;
+
+/// an open db, made by `rdb_open` and freed by `rdb_close`.
+pub struct rdb {
+    /// `None` if the db could not be opened.
+    connection: Option<Connection>,
+    database: Option<Database>,
+    /// the message of the last error, see `rdb_errmsg`.
+    errmsg: CString,
+}
+
+/// a prepared statement, made by `rdb_prepare` and freed by `rdb_finalize`. it
+/// must be finalized before its db is closed.
+pub struct rdb_stmt {
+    db: *mut rdb,
+    statement: vm::Statement,
+    /// the rows of a running statement, `None` before the first step and after
+    /// the last.
+    rows: Option<Rows>,
+    row: Option<RowView>,
+    /// the strings handed out by `rdb_column_text` for the current row.
+    texts: Vec<Option<CString>>,
+}
+
+impl rdb {
+    fn fail(&mut self, code: c_int, msg: &str) -> c_int {
+        self.errmsg = to_c_string(msg);
+        code
+    }
+
+    /// fail with the code of given error, `RDB_BUSY` or `RDB_ERROR`.
+    fn fail_with(&mut self, e: RdbError) -> c_int {
+        let code = match e {
+            RdbError::Busy(_) => RDB_BUSY,
+            RdbError::Message(_) | RdbError::TypeMismatch { .. } => RDB_ERROR,
+        };
+        self.fail(code, &format!("{}", e))
+    }
+
+    fn succeed(&mut self) -> c_int {
+        self.errmsg = to_c_string("not an error");
+        RDB_OK
+    }
+}
+
+/// a C string of given text, without the NUL bytes it cannot hold.
+fn to_c_string(text: &str) -> CString {
+    CString::new(text.replace('\0', "")).unwrap_or_default()
+}
+
+/// run `f`, a panic becomes `on_panic`.
+fn guard<T, F: FnOnce() -> T>(on_panic: T, f: F) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(on_panic)
+}
+
+/// the text of a C string, `None` for NULL or a string that is not UTF-8.
+unsafe fn to_str<'a>(text: *const c_char) -> Option<&'a str> {
+    if text.is_null() {
+        return None;
+    }
+    CStr::from_ptr(text).to_str().ok()
+}
+
+/// open the db file at given path, `:memory:` for an in-memory db. `*db` is set
+/// even on error so `rdb_errmsg` can tell why, it must be closed either way.
+///
+/// # Safety
+/// `path` must be NULL or a NUL-terminated string, `db` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn rdb_open(path: *const c_char, db: *mut *mut rdb) -> c_int {
+    guard(RDB_INTERNAL, || {
+        if db.is_null() {
+            return RDB_MISUSE;
+        }
+        let mut handle = Box::new(rdb {
+            connection: None,
+            database: None,
+            errmsg: CString::default(),
+        });
+        let code = match to_str(path) {
+            None => handle.fail(RDB_MISUSE, "path is NULL or not UTF-8."),
+            Some(path) => match Database::open(path) {
+                Result::Ok(database) => {
+                    handle.connection = Some(database.connect());
+                    handle.database = Some(database);
+                    handle.succeed()
+                }
+                Result::Err(e) => handle.fail_with(e),
+            },
+        };
+        *db = Box::into_raw(handle);
+        code
+    })
+}
+
+/// write all changes and free the db, NULL is a no-op.
+///
+/// # Safety
+/// `db` must be NULL or made by `rdb_open`, its statements finalized.
+#[no_mangle]
+pub unsafe extern "C" fn rdb_close(db: *mut rdb) -> c_int {
+    guard(RDB_INTERNAL, || {
+        if db.is_null() {
+            return RDB_OK;
+        }
+        let mut handle = Box::from_raw(db);
+        drop(handle.connection.take());
+        match handle.database.take().map(Database::close) {
+            Some(Result::Err(msg)) => {
+                warn!("cannot close the db: {}", msg);
+                RDB_ERROR
+            }
+            _ => RDB_OK,
+        }
+    })
+}
+
+/// the message of the last failed call on `db`, it stays valid until the next
+/// call on `db`.
+///
+/// # Safety
+/// `db` must be NULL or made by `rdb_open`.
+#[no_mangle]
+pub unsafe extern "C" fn rdb_errmsg(db: *mut rdb) -> *const c_char {
+    if db.is_null() {
+        return NO_DB_HANDLE.as_ptr() as *const c_char;
+    }
+    (*db).errmsg.as_ptr()
+}
+
+/// the callback of `rdb_exec`, called with the values and names of each row.
+/// NULL values are NULL pointers, a non-zero return stops the statement.
+pub type rdb_callback = Option<
+    unsafe extern "C" fn(
+        userdata: *mut c_void,
+        columns: c_int,
+        values: *mut *mut c_char,
+        names: *mut *mut c_char,
+    ) -> c_int,
+>;
+
+/// run a statement, its rows are passed to `callback` if it is not NULL.
+///
+/// # Safety
+/// `db` must be made by `rdb_open`, `sql` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rdb_exec(
+    db: *mut rdb,
+    sql: *const c_char,
+    callback: rdb_callback,
+    userdata: *mut c_void,
+) -> c_int {
+    guard(RDB_INTERNAL, || {
+        let handle = match db.as_mut() {
+            Some(handle) => handle,
+            None => return RDB_MISUSE,
+        };
+        let sql = match to_str(sql) {
+            Some(sql) => sql,
+            None => return handle.fail(RDB_MISUSE, "sql is NULL or not UTF-8."),
+        };
+        let rows = match handle.connection.as_mut().map(|conn| conn.query(sql)) {
+            None => return handle.fail(RDB_MISUSE, "the db is not open."),
+            Some(Result::Err(e)) => return handle.fail_with(e),
+            Some(Result::Ok(rows)) => rows,
+        };
+        let callback = match callback {
+            Some(callback) => callback,
+            None => return handle.succeed(),
+        };
+        let names: Vec<CString> = rows
+            .columns()
+            .iter()
+            .map(|name| to_c_string(name))
+            .collect();
+        let mut name_ptrs: Vec<*mut c_char> = names
+            .iter()
+            .map(|name| name.as_ptr() as *mut c_char)
+            .collect();
+        for row in rows {
+            let values: Vec<Option<CString>> = row.values().iter().map(value_text).collect();
+            let mut value_ptrs: Vec<*mut c_char> = values
+                .iter()
+                .map(|value| {
+                    value
+                        .as_ref()
+                        .map_or(ptr::null_mut(), |v| v.as_ptr() as *mut c_char)
+                })
+                .collect();
+            let stop = callback(
+                userdata,
+                value_ptrs.len() as c_int,
+                value_ptrs.as_mut_ptr(),
+                name_ptrs.as_mut_ptr(),
+            );
+            if stop != 0 {
+                return handle.fail(RDB_ABORT, "the callback stopped the statement.");
+            }
+        }
+        handle.succeed()
+    })
+}
+
+fn value_text(value: &Value) -> Option<CString> {
+    match value {
+        Value::Null => None,
+        Value::Text(text) => Some(to_c_string(text)),
+        value => Some(to_c_string(&format!("{}", value))),
+    }
+}
+
+/// prepare a statement with `?` parameters to bind before `rdb_step`.
+///
+/// # Safety
+/// `db` must be made by `rdb_open`, `sql` a NUL-terminated string and `stmt` a
+/// valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn rdb_prepare(
+    db: *mut rdb,
+    sql: *const c_char,
+    stmt: *mut *mut rdb_stmt,
+) -> c_int {
+    guard(RDB_INTERNAL, || {
+        let handle = match db.as_mut() {
+            Some(handle) if !stmt.is_null() => handle,
+            _ => return RDB_MISUSE,
+        };
+        *stmt = ptr::null_mut();
+        let sql = match to_str(sql) {
+            Some(sql) => sql,
+            None => return handle.fail(RDB_MISUSE, "sql is NULL or not UTF-8."),
+        };
+        let statement = match handle
+            .connection
+            .as_ref()
+            .map(|conn| conn.prepare_owned(sql))
+        {
+            None => return handle.fail(RDB_MISUSE, "the db is not open."),
+            Some(Result::Err(msg)) => return handle.fail(RDB_ERROR, &msg),
+            Some(Result::Ok(statement)) => statement,
+        };
+        *stmt = Box::into_raw(Box::new(rdb_stmt {
+            db,
+            statement,
+            rows: None,
+            row: None,
+            texts: Vec::new(),
+        }));
+        handle.succeed()
+    })
+}
+
+/// bind a value to the parameter at given index, starting at 1. a running
+/// statement starts over on its next step.
+unsafe fn bind(stmt: *mut rdb_stmt, index: c_int, value: Option<Value>) -> c_int {
+    guard(RDB_INTERNAL, || {
+        let stmt = match stmt.as_mut() {
+            Some(stmt) => stmt,
+            None => return RDB_MISUSE,
+        };
+        let handle = &mut *stmt.db;
+        let value = match value {
+            Some(value) => value,
+            None => return handle.fail(RDB_MISUSE, "text is NULL or not UTF-8."),
+        };
+        let index = match usize::try_from(index) {
+            Result::Ok(index) => index,
+            Result::Err(_) => {
+                let msg = format!(
+                    "parameter index {} out of range, the statement has {} parameters.",
+                    index,
+                    stmt.statement.parameter_count()
+                );
+                return handle.fail(RDB_RANGE, &msg);
+            }
+        };
+        stmt.reset();
+        match stmt.statement.bind(index, value) {
+            Result::Ok(()) => handle.succeed(),
+            Result::Err(msg) => handle.fail(RDB_RANGE, &msg),
+        }
+    })
+}
+
+/// # Safety
+/// `stmt` must be made by `rdb_prepare`.
+#[no_mangle]
+pub unsafe extern "C" fn rdb_bind_int(stmt: *mut rdb_stmt, index: c_int, value: i64) -> c_int {
+    bind(stmt, index, Some(Value::Integer(value)))
+}
+
+/// the text is copied.
+///
+/// # Safety
+/// `stmt` must be made by `rdb_prepare`, `value` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rdb_bind_text(
+    stmt: *mut rdb_stmt,
+    index: c_int,
+    value: *const c_char,
+) -> c_int {
+    let value = to_str(value).map(|text| Value::Text(text.to_owned()));
+    bind(stmt, index, value)
+}
+
+impl rdb_stmt {
+    fn reset(&mut self) {
+        self.rows = None;
+        self.row = None;
+        self.texts.clear();
+    }
+}
+
+/// run the statement up to its next row. returns `RDB_ROW` while there are
+/// rows and `RDB_DONE` at the end, the step after that runs it again.
+///
+/// # Safety
+/// `stmt` must be made by `rdb_prepare`.
+#[no_mangle]
+pub unsafe extern "C" fn rdb_step(stmt: *mut rdb_stmt) -> c_int {
+    guard(RDB_INTERNAL, || {
+        let stmt = match stmt.as_mut() {
+            Some(stmt) => stmt,
+            None => return RDB_MISUSE,
+        };
+        let handle = &mut *stmt.db;
+        if stmt.rows.is_none() {
+            let result = match handle.connection.as_mut() {
+                Some(conn) => conn.run(&mut stmt.statement),
+                None => return handle.fail(RDB_MISUSE, "the db is not open."),
+            };
+            match result {
+                Result::Ok(rows) => stmt.rows = Some(rows),
+                Result::Err(e) => return handle.fail_with(e),
+            }
+        }
+        stmt.texts.clear();
+        stmt.row = stmt.rows.as_mut().and_then(Iterator::next);
+        match stmt.row {
+            Some(ref row) => {
+                stmt.texts = vec![None; row.values().len()];
+                RDB_ROW
+            }
+            None => match stmt.rows.take().and_then(|rows| rows.error()) {
+                Some(e) => handle.fail_with(e),
+                None => RDB_DONE,
+            },
+        }
+    })
+}
+
+/// the number of columns of the statement's rows.
+///
+/// # Safety
+/// `stmt` must be made by `rdb_prepare`.
+#[no_mangle]
+pub unsafe extern "C" fn rdb_column_count(stmt: *mut rdb_stmt) -> c_int {
+    guard(0, || match stmt.as_ref() {
+        Some(stmt) => stmt.statement.columns().len() as c_int,
+        None => 0,
+    })
+}
+
+/// the value at given column of the current row, starting at 0, 1 or 0 for a
+/// boolean. 0 for text, NULL or a column out of range.
+///
+/// # Safety
+/// `stmt` must be made by `rdb_prepare`.
+#[no_mangle]
+pub unsafe extern "C" fn rdb_column_int(stmt: *mut rdb_stmt, column: c_int) -> i64 {
+    guard(0, || {
+        let value = stmt
+            .as_ref()
+            .and_then(|stmt| stmt.row.as_ref())
+            .and_then(|row| row.values().get(usize::try_from(column).ok()?));
+        match value {
+            Some(Value::Integer(value)) => *value,
+            Some(Value::Boolean(value)) => *value as i64,
+            _ => 0,
+        }
+    })
+}
+
+/// the value at given column of the current row as text, NULL for NULL or a
+/// column out of range. it stays valid until the next step or the finalize.
+///
+/// # Safety
+/// `stmt` must be made by `rdb_prepare`.
+#[no_mangle]
+pub unsafe extern "C" fn rdb_column_text(stmt: *mut rdb_stmt, column: c_int) -> *const c_char {
+    guard(ptr::null(), || {
+        let stmt = match stmt.as_mut() {
+            Some(stmt) => stmt,
+            None => return ptr::null(),
+        };
+        let column = match usize::try_from(column) {
+            Result::Ok(column) => column,
+            Result::Err(_) => return ptr::null(),
+        };
+        let text = match stmt.row.as_ref().and_then(|row| row.values().get(column)) {
+            Some(value) => value_text(value),
+            None => return ptr::null(),
+        };
+        stmt.texts[column] = text;
+        stmt.texts[column]
+            .as_ref()
+            .map_or(ptr::null(), |text| text.as_ptr())
+    })
+}
+
+/// free a statement, NULL is a no-op.
+///
+/// # Safety
+/// `stmt` must be NULL or made by `rdb_prepare`.
+#[no_mangle]
+pub unsafe extern "C" fn rdb_finalize(stmt: *mut rdb_stmt) -> c_int {
+    guard(RDB_INTERNAL, || {
+        if !stmt.is_null() {
+            drop(Box::from_raw(stmt));
+        }
+        RDB_OK
+    })
+}
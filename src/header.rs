@@ -0,0 +1,163 @@
+//! the database header stored in front of the first page.
+//!
+//! header layout (100 bytes):
+//! - magic (16 bytes) | format version (4) | page size (4)
+//! - free list head (4) | free list count (4) | flags (4)
+//! - key salt (16) | key check (16), both zeroed unless the db is encrypted
+//! - row count (8), since format version 4
+//! - catalog page (4), since format version 5
+//! - reserved, zeroed
+//! - checksum (4), the CRC32 of everything before it, since format version 3
+
+use byteorder::{BigEndian, ByteOrder};
+use crc32fast::Hasher;
+
+use encryption::{KEY_CHECK_SIZE, SALT_SIZE};
+use pager::{validate_page_size, PagerError};
+
+pub const DB_HEADER_SIZE: usize = 100;
+pub const DB_MAGIC: &[u8] = b"rdb database\0\0\0\0";
+pub const DB_MAGIC_OFFSET: usize = 0;
+pub const DB_VERSION_OFFSET: usize = DB_MAGIC_OFFSET + 16;
+pub const DB_PAGE_SIZE_OFFSET: usize = DB_VERSION_OFFSET + 4;
+/// free pages are chained through their first 4 bytes, 0 terminates the list
+/// as the root page is never freed. both fields are zero for databases without
+/// free pages, so older files of the same format version read fine.
+pub const DB_FREE_LIST_HEAD_OFFSET: usize = DB_PAGE_SIZE_OFFSET + 4;
+pub const DB_FREE_LIST_COUNT_OFFSET: usize = DB_FREE_LIST_HEAD_OFFSET + 4;
+pub const DB_FLAGS_OFFSET: usize = DB_FREE_LIST_COUNT_OFFSET + 4;
+/// pages are stored compressed, see `compression`.
+pub const DB_FLAG_COMPRESSION: u32 = 1;
+/// pages are stored encrypted, see `encryption`.
+pub const DB_FLAG_ENCRYPTION: u32 = 2;
+/// set while the db is opened for writing and cleared by a clean shutdown, a db
+/// found with it set was not closed and its row count may be stale.
+pub const DB_FLAG_IN_USE: u32 = 4;
+/// salt of the key derivation and the key check value of encrypted databases.
+pub const DB_KEY_SALT_OFFSET: usize = DB_FLAGS_OFFSET + 4;
+pub const DB_KEY_CHECK_OFFSET: usize = DB_KEY_SALT_OFFSET + SALT_SIZE;
+/// the number of rows in the table, see `Pager::row_count`.
+pub const DB_ROW_COUNT_OFFSET: usize = DB_KEY_CHECK_OFFSET + KEY_CHECK_SIZE;
+/// the root page of the catalog of the created tables, 0 until a table is
+/// created as the root page of the users table is never part of it.
+pub const DB_CATALOG_PAGE_OFFSET: usize = DB_ROW_COUNT_OFFSET + 8;
+pub const DB_HEADER_CHECKSUM_OFFSET: usize = DB_HEADER_SIZE - 4;
+
+/// format version written into new databases, format changes should bump it
+/// and key off `Pager::get_format_version` for older files.
+/// version 2 adds the flags field, version 1 files have it zeroed.
+/// version 3 adds the header checksum, older files go unchecked.
+/// version 4 adds the row count, older files need a `.check` to get one.
+/// version 5 adds the catalog page, older files have no created tables.
+pub const DB_FORMAT_VERSION: u32 = 5;
+/// the oldest format version this build can still open.
+pub const DB_MIN_FORMAT_VERSION: u32 = 1;
+const DB_CHECKSUM_FORMAT_VERSION: u32 = 3;
+pub const DB_ROW_COUNT_FORMAT_VERSION: u32 = 4;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DbHeader {
+    pub format_version: u32,
+    pub page_size: usize,
+    pub free_list_head: usize,
+    pub free_list_count: usize,
+    pub flags: u32,
+    pub key_salt: [u8; SALT_SIZE],
+    pub key_check: [u8; KEY_CHECK_SIZE],
+    pub row_count: u64,
+    pub catalog_page: usize,
+}
+
+impl DbHeader {
+    /// the header of a new and empty db.
+    pub fn new(page_size: usize) -> DbHeader {
+        DbHeader {
+            format_version: DB_FORMAT_VERSION,
+            page_size,
+            free_list_head: 0,
+            free_list_count: 0,
+            flags: 0,
+            key_salt: [0u8; SALT_SIZE],
+            key_check: [0u8; KEY_CHECK_SIZE],
+            row_count: 0,
+            catalog_page: 0,
+        }
+    }
+
+    pub fn has_flag(&self, flag: u32) -> bool {
+        self.flags & flag != 0
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = vec![0u8; DB_HEADER_SIZE];
+        buf[DB_MAGIC_OFFSET..DB_MAGIC_OFFSET + DB_MAGIC.len()].copy_from_slice(DB_MAGIC);
+        BigEndian::write_u32(&mut buf[DB_VERSION_OFFSET..], self.format_version);
+        BigEndian::write_u32(&mut buf[DB_PAGE_SIZE_OFFSET..], self.page_size as u32);
+        BigEndian::write_u32(&mut buf[DB_FREE_LIST_HEAD_OFFSET..], self.free_list_head as u32);
+        BigEndian::write_u32(&mut buf[DB_FREE_LIST_COUNT_OFFSET..], self.free_list_count as u32);
+        BigEndian::write_u32(&mut buf[DB_FLAGS_OFFSET..], self.flags);
+        buf[DB_KEY_SALT_OFFSET..DB_KEY_CHECK_OFFSET].copy_from_slice(&self.key_salt);
+        buf[DB_KEY_CHECK_OFFSET..DB_KEY_CHECK_OFFSET + KEY_CHECK_SIZE]
+            .copy_from_slice(&self.key_check);
+        BigEndian::write_u64(&mut buf[DB_ROW_COUNT_OFFSET..], self.row_count);
+        BigEndian::write_u32(&mut buf[DB_CATALOG_PAGE_OFFSET..], self.catalog_page as u32);
+        let checksum = checksum(&buf);
+        BigEndian::write_u32(&mut buf[DB_HEADER_CHECKSUM_OFFSET..], checksum);
+        buf
+    }
+
+    /// parse and validate a header, `buf` may be short if the db file is. the version
+    /// is checked before the checksum, a newer format may checksum differently.
+    pub fn deserialize(buf: &[u8]) -> Result<DbHeader, PagerError> {
+        // a short header still tells foreign files apart from truncated ones
+        if !buf[DB_MAGIC_OFFSET..].starts_with(DB_MAGIC) {
+            return Result::Err(PagerError::NotADatabase);
+        }
+        if buf.len() < DB_HEADER_SIZE {
+            return Result::Err(PagerError::Corrupted);
+        }
+        let mut key_salt = [0u8; SALT_SIZE];
+        key_salt.copy_from_slice(&buf[DB_KEY_SALT_OFFSET..DB_KEY_CHECK_OFFSET]);
+        let mut key_check = [0u8; KEY_CHECK_SIZE];
+        key_check
+            .copy_from_slice(&buf[DB_KEY_CHECK_OFFSET..DB_KEY_CHECK_OFFSET + KEY_CHECK_SIZE]);
+        let header = DbHeader {
+            format_version: BigEndian::read_u32(&buf[DB_VERSION_OFFSET..]),
+            page_size: BigEndian::read_u32(&buf[DB_PAGE_SIZE_OFFSET..]) as usize,
+            free_list_head: BigEndian::read_u32(&buf[DB_FREE_LIST_HEAD_OFFSET..]) as usize,
+            free_list_count: BigEndian::read_u32(&buf[DB_FREE_LIST_COUNT_OFFSET..]) as usize,
+            flags: BigEndian::read_u32(&buf[DB_FLAGS_OFFSET..]),
+            key_salt,
+            key_check,
+            row_count: BigEndian::read_u64(&buf[DB_ROW_COUNT_OFFSET..]),
+            catalog_page: BigEndian::read_u32(&buf[DB_CATALOG_PAGE_OFFSET..]) as usize,
+        };
+        header.validate_version()?;
+        if header.format_version >= DB_CHECKSUM_FORMAT_VERSION
+            && BigEndian::read_u32(&buf[DB_HEADER_CHECKSUM_OFFSET..]) != checksum(buf)
+        {
+            return Result::Err(PagerError::CorruptedHeader);
+        }
+        header.validate()?;
+        Result::Ok(header)
+    }
+
+    pub fn validate(&self) -> Result<(), PagerError> {
+        self.validate_version()?;
+        validate_page_size(self.page_size).map_err(|_| PagerError::Corrupted)
+    }
+
+    fn validate_version(&self) -> Result<(), PagerError> {
+        if !(DB_MIN_FORMAT_VERSION..=DB_FORMAT_VERSION).contains(&self.format_version) {
+            return Result::Err(PagerError::UnsupportedVersion(self.format_version));
+        }
+        Result::Ok(())
+    }
+}
+
+/// the checksum of a serialized header.
+pub fn checksum(buf: &[u8]) -> u32 {
+    let mut hasher = Hasher::new();
+    hasher.update(&buf[..DB_HEADER_CHECKSUM_OFFSET]);
+    hasher.finalize()
+}
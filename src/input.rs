@@ -0,0 +1,205 @@
+//! where the REPL reads its lines from: a line editor with history on a terminal,
+//! plain stdin or a script file otherwise.
+
+use std::collections::VecDeque;
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use completion::{self, Vocabulary};
+
+const HISTORY_FILE: &str = ".rdb_history";
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Input {
+    /// a line without its line break.
+    Line(String),
+    /// the line was cancelled by Ctrl-C.
+    Interrupted,
+    Eof,
+}
+
+pub trait LineReader {
+    fn read_line(&mut self, prompt: &str) -> Input;
+
+    /// where the last line came from, like `init.sql:3`, for the error messages
+    /// of scripts.
+    fn location(&self) -> Option<String> {
+        None
+    }
+}
+
+/// reads lines from stdin or a script, for input that's not a terminal.
+pub struct PlainReader<R> {
+    input: R,
+    /// the path of a script file.
+    name: Option<String>,
+    /// the number of lines read.
+    line_number: usize,
+}
+
+impl<R: BufRead> PlainReader<R> {
+    pub fn new(input: R) -> PlainReader<R> {
+        PlainReader {
+            input,
+            name: None,
+            line_number: 0,
+        }
+    }
+
+    /// a reader of the script file at given path.
+    pub fn named(input: R, name: &str) -> PlainReader<R> {
+        PlainReader {
+            name: Some(name.to_owned()),
+            ..PlainReader::new(input)
+        }
+    }
+}
+
+impl<R: BufRead> LineReader for PlainReader<R> {
+    fn read_line(&mut self, prompt: &str) -> Input {
+        if !prompt.is_empty() {
+            print!("{}", prompt);
+            io::stdout().flush().unwrap();
+        }
+        let mut line = String::new();
+        match self.input.read_line(&mut line) {
+            Result::Ok(0) => Input::Eof,
+            Result::Ok(_) => {
+                self.line_number += 1;
+                let len = line.trim_end_matches(['\r', '\n']).len();
+                line.truncate(len);
+                Input::Line(line)
+            }
+            Result::Err(e) => panic!("read input error: {}", e),
+        }
+    }
+
+    fn location(&self) -> Option<String> {
+        self.name
+            .as_ref()
+            .map(|name| format!("{}:{}", name, self.line_number))
+    }
+}
+
+/// completes the words of a line on tab, see `completion::complete`.
+struct CompletionHelper {
+    vocabulary: Vocabulary,
+}
+
+impl Completer for CompletionHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Result::Ok(completion::complete(line, pos, &self.vocabulary))
+    }
+}
+
+impl Hinter for CompletionHelper {
+    type Hint = String;
+}
+
+impl Highlighter for CompletionHelper {}
+
+impl Validator for CompletionHelper {}
+
+impl Helper for CompletionHelper {}
+
+/// a line editor with tab completion and a history kept in `~/.rdb_history`, a
+/// history that cannot be read or written only costs the history.
+pub struct EditorReader {
+    editor: Editor<CompletionHelper, DefaultHistory>,
+    history_path: Option<PathBuf>,
+}
+
+impl EditorReader {
+    pub fn new(vocabulary: Vocabulary) -> Result<EditorReader, String> {
+        let mut editor: Editor<CompletionHelper, DefaultHistory> =
+            Editor::new().map_err(|e| format!("cannot start line editor: {}", e))?;
+        editor.set_helper(Some(CompletionHelper { vocabulary }));
+        let history_path = env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+        if let Some(ref path) = history_path {
+            if path.exists() {
+                if let Result::Err(e) = editor.load_history(path) {
+                    warn!("cannot load history from {}: {}", path.display(), e);
+                }
+            }
+        }
+        Result::Ok(EditorReader {
+            editor,
+            history_path,
+        })
+    }
+
+    fn add_history(&mut self, line: &str) {
+        if line.trim().is_empty() {
+            return;
+        }
+        let _ = self.editor.add_history_entry(line);
+        let failed = match self.history_path {
+            Some(ref path) => self.editor.append_history(path).err().map(|e| (path.clone(), e)),
+            None => None,
+        };
+        if let Some((path, e)) = failed {
+            warn!("cannot save history to {}: {}", path.display(), e);
+            // don't warn on every line
+            self.history_path = None;
+        }
+    }
+}
+
+impl LineReader for EditorReader {
+    fn read_line(&mut self, prompt: &str) -> Input {
+        match self.editor.readline(prompt) {
+            Result::Ok(line) => {
+                self.add_history(&line);
+                Input::Line(line)
+            }
+            Result::Err(ReadlineError::Interrupted) => Input::Interrupted,
+            Result::Err(ReadlineError::Eof) => Input::Eof,
+            Result::Err(e) => panic!("read input error: {}", e),
+        }
+    }
+}
+
+/// feeds given lines to the REPL, the commands of `-c` and tests.
+pub struct ScriptReader {
+    lines: VecDeque<Input>,
+}
+
+impl ScriptReader {
+    pub fn new<S: AsRef<str>>(lines: &[S]) -> ScriptReader {
+        ScriptReader {
+            lines: lines
+                .iter()
+                .map(|line| Input::Line(line.as_ref().to_owned()))
+                .collect(),
+        }
+    }
+
+    /// queue a Ctrl-C.
+    #[cfg(test)]
+    pub fn interrupt(mut self) -> ScriptReader {
+        self.lines.push_back(Input::Interrupted);
+        self
+    }
+}
+
+impl LineReader for ScriptReader {
+    fn read_line(&mut self, _prompt: &str) -> Input {
+        self.lines.pop_front().unwrap_or(Input::Eof)
+    }
+}
@@ -0,0 +1,34 @@
+//! the internals the `rdb` binary, the benches and the integration tests are built
+//! on, laid out like the modules they come from. they are not part of the api and
+//! change without notice, embedders use `Database` and `Connection`.
+
+pub mod btree {
+    pub use btree::{BTree, BTreeTrait};
+}
+
+pub mod catalog {
+    pub use catalog::{Catalog, Databases, MAIN_DB};
+}
+
+pub mod pager {
+    pub use pager::{validate_page_size, DbOption, Pager, SyncMode};
+}
+
+pub mod render {
+    pub use render::{write_rows, Mode, Renderer, DEFAULT_MAX_ROWS, DEFAULT_NULL_VALUE};
+}
+
+pub mod table {
+    pub use table::{Row, Table, TABLE_NAME};
+
+    pub mod schema {
+        pub use table::schema::Schema;
+    }
+}
+
+pub mod vm {
+    pub use vm::{
+        create_table_statement, insert_statement, is_blank, split_values, syntax_error, RowSink,
+        Statement, Value, VM,
+    };
+}
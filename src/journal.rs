@@ -0,0 +1,215 @@
+//! A simple rollback journal, the database file is only modified while
+//! a complete journal of the original page contents is present next to it.
+//!
+//! journal layout:
+//! - header: magic (8 bytes) | page size (4) | db num pages (4) | num records (4) | checksum (4)
+//! - original db header (100 bytes)
+//! - records: page index (4) | checksum (4) | original page content (page size)
+//!
+//! The header is left zeroed until all records are written and synced, so a
+//! journal without the magic string is an incomplete one and can be discarded.
+//!
+//! The journal doubles as the protection against torn pages: pages are only
+//! written to their home locations after the journal is sealed, so a page
+//! torn by a crash is restored from its record. checksums cover the header
+//! with the saved db header and every record, records torn themselves are
+//! skipped by the recovery scanner.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use byteorder::{BigEndian, ByteOrder};
+use crc32fast::Hasher;
+
+use header::DB_HEADER_SIZE;
+use pager::Page;
+use storage::Storage;
+
+const JOURNAL_MAGIC: &[u8] = b"rdbjrnl\0";
+const JOURNAL_HEADER_SIZE: usize = 24;
+const PAGE_SIZE_OFFSET: usize = 8;
+const DB_NUM_PAGES_OFFSET: usize = 12;
+const NUM_RECORDS_OFFSET: usize = 16;
+const HEADER_CHECKSUM_OFFSET: usize = 20;
+const DB_HEADER_OFFSET: usize = JOURNAL_HEADER_SIZE;
+const RECORDS_OFFSET: usize = DB_HEADER_OFFSET + DB_HEADER_SIZE;
+const RECORD_CHECKSUM_OFFSET: usize = 4;
+const RECORD_HEADER_SIZE: usize = 8;
+
+pub fn journal_path(db_path: &str) -> String {
+    format!("{}.journal", db_path)
+}
+
+pub struct Journal {
+    file: File,
+    path: String,
+    page_size: usize,
+    db_num_pages: usize,
+    num_records: usize,
+    db_header: Vec<u8>,
+}
+
+/// a sealed journal read back by `scan`.
+struct ScannedJournal<'a> {
+    page_size: usize,
+    db_num_pages: usize,
+    db_header: &'a [u8],
+    /// (page index, original content) of the records with valid checksums.
+    records: Vec<(usize, &'a [u8])>,
+}
+
+fn checksum(parts: &[&[u8]]) -> u32 {
+    let mut hasher = Hasher::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize()
+}
+
+impl Journal {
+    /// create a new journal for a database file of `db_num_pages` pages. a journal
+    /// that fails half written is an incomplete one.
+    pub fn create(
+        path: &str,
+        page_size: usize,
+        db_num_pages: usize,
+        db_header: &[u8],
+    ) -> io::Result<Journal> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.write_all(&[0u8; JOURNAL_HEADER_SIZE])?;
+        file.write_all(db_header)?;
+        Result::Ok(Journal {
+            file,
+            path: path.to_owned(),
+            page_size,
+            db_num_pages,
+            num_records: 0,
+            db_header: db_header.to_vec(),
+        })
+    }
+
+    /// save the original content of a page before it's modified. a record that
+    /// fails half written is not counted, the next one is written over it.
+    pub fn append(&mut self, page_index: usize, page: &Page) -> io::Result<()> {
+        let mut record_header = [0u8; RECORD_HEADER_SIZE];
+        BigEndian::write_u32(&mut record_header, page_index as u32);
+        let record_checksum = checksum(&[&record_header[..RECORD_CHECKSUM_OFFSET], page]);
+        BigEndian::write_u32(&mut record_header[RECORD_CHECKSUM_OFFSET..], record_checksum);
+        let offset = RECORDS_OFFSET + self.num_records * (RECORD_HEADER_SIZE + page.len());
+        self.file.seek(SeekFrom::Start(offset as u64))?;
+        self.file.write_all(&record_header)?;
+        self.file.write_all(page)?;
+        self.num_records += 1;
+        Result::Ok(())
+    }
+
+    /// mark the journal as complete, records should be synced before this.
+    pub fn write_header(&mut self) -> io::Result<()> {
+        let mut header = [0u8; JOURNAL_HEADER_SIZE];
+        header[..JOURNAL_MAGIC.len()].copy_from_slice(JOURNAL_MAGIC);
+        BigEndian::write_u32(&mut header[PAGE_SIZE_OFFSET..], self.page_size as u32);
+        BigEndian::write_u32(&mut header[DB_NUM_PAGES_OFFSET..], self.db_num_pages as u32);
+        BigEndian::write_u32(&mut header[NUM_RECORDS_OFFSET..], self.num_records as u32);
+        let header_checksum = checksum(&[&header[..HEADER_CHECKSUM_OFFSET], &self.db_header]);
+        BigEndian::write_u32(&mut header[HEADER_CHECKSUM_OFFSET..], header_checksum);
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header)
+    }
+
+    pub fn sync(&self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    pub fn delete(self) -> io::Result<()> {
+        let Journal { file, path, .. } = self;
+        drop(file);
+        fs::remove_file(path)
+    }
+
+    /// close the journal file and leave it for `rollback`.
+    pub fn close(self) -> String {
+        self.path
+    }
+
+    /**
+     * roll back the database storage with the journal at given path if there is one.
+     * a complete journal is replayed into the db file, an incomplete journal means
+     * the db file was never touched, in both cases the journal is deleted.
+     * returns true if the db file was rolled back, the journal is kept on errors.
+     **/
+    pub fn rollback(path: &str, storage: &mut dyn Storage) -> io::Result<bool> {
+        if !Path::new(path).exists() {
+            return Result::Ok(false);
+        }
+
+        let mut buf = Vec::new();
+        File::open(path)?.read_to_end(&mut buf)?;
+
+        let rolled_back = match Journal::scan(&buf) {
+            Some(journal) => {
+                storage.write_page(0, journal.db_header)?;
+                for (page_index, page) in journal.records {
+                    let offset =
+                        page_index as u64 * journal.page_size as u64 + DB_HEADER_SIZE as u64;
+                    storage.write_page(offset, page)?;
+                }
+                let page_size = journal.page_size as u64;
+                storage.set_len(journal.db_num_pages as u64 * page_size + DB_HEADER_SIZE as u64)?;
+                storage.sync()?;
+                true
+            }
+            None => false,
+        };
+
+        fs::remove_file(path)?;
+        Result::Ok(rolled_back)
+    }
+
+    /**
+     * the recovery scanner, returns nothing unless the journal is sealed and its
+     * header is intact. records failing their checksums are left out, they were
+     * torn before reaching the disk, which only happens without syncs.
+     **/
+    fn scan(buf: &[u8]) -> Option<ScannedJournal<'_>> {
+        if buf.len() < RECORDS_OFFSET || !buf.starts_with(JOURNAL_MAGIC) {
+            return None;
+        }
+        let header_checksum = BigEndian::read_u32(&buf[HEADER_CHECKSUM_OFFSET..]);
+        if checksum(&[&buf[..HEADER_CHECKSUM_OFFSET], &buf[DB_HEADER_OFFSET..RECORDS_OFFSET]])
+            != header_checksum
+        {
+            return None;
+        }
+        let page_size = BigEndian::read_u32(&buf[PAGE_SIZE_OFFSET..]) as usize;
+        let db_num_pages = BigEndian::read_u32(&buf[DB_NUM_PAGES_OFFSET..]) as usize;
+        let num_records = BigEndian::read_u32(&buf[NUM_RECORDS_OFFSET..]) as usize;
+        let record_size = RECORD_HEADER_SIZE + page_size;
+        if page_size == 0 || buf.len() < RECORDS_OFFSET + num_records * record_size {
+            return None;
+        }
+
+        let mut records = Vec::with_capacity(num_records);
+        for i in 0..num_records {
+            let record = &buf[RECORDS_OFFSET + i * record_size..][..record_size];
+            let page = &record[RECORD_HEADER_SIZE..];
+            let record_checksum = BigEndian::read_u32(&record[RECORD_CHECKSUM_OFFSET..]);
+            if checksum(&[&record[..RECORD_CHECKSUM_OFFSET], page]) != record_checksum {
+                warn!("skip torn journal record {}.", i);
+                continue;
+            }
+            records.push((BigEndian::read_u32(record) as usize, page));
+        }
+        Some(ScannedJournal {
+            page_size,
+            db_num_pages,
+            db_header: &buf[DB_HEADER_OFFSET..RECORDS_OFFSET],
+            records,
+        })
+    }
+}
@@ -0,0 +1,58 @@
+//! rdb, a small sqlite clone. `Database` opens a db file, its `Connection` runs
+//! statements and hands the rows of queries out as an iterator. the `rdb` binary is
+//! a REPL on top of it.
+
+extern crate byteorder;
+#[macro_use]
+extern crate log;
+extern crate lz4_flex;
+extern crate crc32fast;
+#[macro_use]
+extern crate nom;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+#[cfg(feature = "encryption")]
+extern crate chacha20poly1305;
+#[cfg(feature = "encryption")]
+extern crate pbkdf2;
+#[cfg(feature = "encryption")]
+extern crate sha2;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
+
+mod table;
+mod pager;
+mod header;
+mod journal;
+mod storage;
+mod compression;
+mod encryption;
+mod btree;
+mod database;
+mod error;
+mod catalog;
+mod ffi;
+mod row;
+#[cfg(feature = "serde")]
+mod serialize;
+mod vm;
+mod sql;
+mod render;
+#[cfg(feature = "wasm")]
+mod wasm;
+#[doc(hidden)]
+pub mod internals;
+
+pub use database::{Connection, Database, Rows, Statement, Transaction};
+pub use error::RdbError;
+pub use table::{InsertReport, Row};
+pub use pager::{DbOption, SyncMode};
+pub use row::{ColumnIndex, FromRow, RowView};
+pub use storage::MEMORY_DB;
+pub use vm::{FromValue, ToValue, Value};
+#[cfg(feature = "wasm")]
+pub use wasm::WasmDatabase;
@@ -1,103 +1,1199 @@
-extern crate byteorder;
+extern crate rdb;
 #[macro_use]
 extern crate log;
-#[macro_use]
-extern crate nom;
+extern crate rustyline;
+extern crate signal_hook;
 
-use std::io;
+use std::fs::File;
+use std::io::{self, BufReader, IsTerminal};
 use std::process;
-use std::io::Write;
 use std::env;
+use std::fmt;
+use std::mem;
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+mod input;
+mod completion;
+mod meta;
+mod server;
+mod session;
+mod signals;
 
-mod table;
-mod pager;
-mod btree;
-mod vm;
-mod sql;
+use rdb::internals::{btree, catalog, pager, render, table, vm};
 
+use catalog::Databases;
 use table::Table;
-use pager::{DbOption, Pager};
+use pager::{DbOption, Pager, SyncMode};
+use rdb::{Database, MEMORY_DB};
 use btree::BTree;
+use completion::Vocabulary;
+use input::{EditorReader, Input, LineReader, PlainReader, ScriptReader};
+use meta::{do_meta_command, MetaAction};
+use render::Renderer;
+use session::{ChangeKind, Changes, Session};
+use signals::Signals;
 use vm::{Statement, VM};
 
 const DEFAULT_PAGE_SIZE: usize = 4096;
 const DEFAULT_DB_FILE: &str = "default.rdb";
 const ENV_PAGE_SIZE: &str = "RDB_PAGE_SIZE";
+const ENV_MMAP: &str = "RDB_MMAP";
+const ENV_COMPRESSION: &str = "RDB_COMPRESSION";
+const ENV_KEY: &str = "RDB_KEY";
+const ENV_CACHE_PAGES: &str = "RDB_CACHE_PAGES";
+const ENV_SYNC: &str = "RDB_SYNC";
+const ENV_READ_ONLY: &str = "RDB_READ_ONLY";
+const ENV_EXTENT_SIZE: &str = "RDB_EXTENT_SIZE";
+/// milliseconds to wait for a db file locked by another process.
+const ENV_BUSY_TIMEOUT: &str = "RDB_BUSY_TIMEOUT";
+/// 0 keeps every page in the cache.
+const DEFAULT_CACHE_PAGES: usize = 0;
+const DEFAULT_EXTENT_SIZE: usize = 1;
+/// how deep `.read` may run scripts from scripts.
+const MAX_READ_DEPTH: usize = 8;
+
+/// the command line: `rdb [-q] [-c COMMAND]... [DB_FILE [SCRIPT]]`,
+/// `rdb --serve ADDR [DB_FILE]` or `rdb --client ADDR`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Args {
+    db: String,
+    script: Option<String>,
+    /// statements and meta commands of `-c`/`--exec`, run in order.
+    commands: Vec<String>,
+    /// `-q`/`--quiet`, no banner on an interactive start.
+    quiet: bool,
+    /// `--serve`, the address to serve the db on instead of running the REPL.
+    serve: Option<String>,
+    /// `--client`, the address of a server to send the lines of stdin to.
+    client: Option<String>,
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Args, String> {
+    let mut positional = Vec::new();
+    let mut commands = Vec::new();
+    let mut quiet = false;
+    let mut serve = None;
+    let mut client = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-q" | "--quiet" => quiet = true,
+            "-c" | "--exec" => match args.next() {
+                Some(command) => commands.push(command),
+                None => return Result::Err(format!("{} needs a command.", arg)),
+            },
+            "--serve" | "--client" => match args.next() {
+                Some(addr) if arg == "--serve" => serve = Some(addr),
+                Some(addr) => client = Some(addr),
+                None => return Result::Err(format!("{} needs an address.", arg)),
+            },
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return Result::Err(format!("unknown option {}.", arg))
+            }
+            _ => positional.push(arg),
+        }
+    }
+    if positional.len() > 2 {
+        return Result::Err(format!("unexpected argument {}.", positional[2]));
+    }
+    if client.is_some() && (serve.is_some() || !positional.is_empty() || !commands.is_empty()) {
+        return Result::Err("--client takes no db file, script, -c or --serve.".to_owned());
+    }
+    if serve.is_some() && (positional.len() > 1 || !commands.is_empty()) {
+        return Result::Err("--serve cannot be combined with a script or -c.".to_owned());
+    }
+    let mut positional = positional.into_iter();
+    let args = Args {
+        db: positional.next().unwrap_or_else(|| DEFAULT_DB_FILE.to_owned()),
+        script: positional.next(),
+        commands,
+        quiet,
+        serve,
+        client,
+    };
+    if args.script.is_some() && !args.commands.is_empty() {
+        return Result::Err("a script cannot be combined with -c.".to_owned());
+    }
+    Result::Ok(args)
+}
 
 fn main() {
-    let pager = create_pager();
+    let args = match parse_args(env::args().skip(1)) {
+        Result::Ok(args) => args,
+        Result::Err(msg) => exit_with_error(&msg),
+    };
+    if let Some(addr) = args.client {
+        if let Result::Err(msg) = server::run_client(&addr, io::stdin().lock()) {
+            eprintln!("{}", msg);
+            process::exit(1);
+        }
+        return;
+    }
+    if let Some(addr) = args.serve {
+        serve(&addr, &args.db);
+    }
+    let pager = create_pager(&args.db);
     let tree = BTree::new(pager);
-
-    //TODO: print rdb info
     let mut table = Table::new(tree);
 
-    let mut input_buffer = String::new();
-    loop {
-        print_prompt();
+    let mut session = Session::new();
+    session.busy_timeout = table.tree.pager.get_db_option().busy_timeout;
+    match Signals::install() {
+        Result::Ok(signals) => session.signals = signals,
+        Result::Err(e) => warn!("cannot install signal handlers: {}", e),
+    }
+    // a script file or piped input runs without prompts and stops at the first error
+    let (mut reader, batch): (Box<dyn LineReader>, bool) = match args.script {
+        _ if !args.commands.is_empty() => {
+            // a command may hold several lines
+            let lines: Vec<&str> = args.commands.iter().flat_map(|c| c.lines()).collect();
+            (Box::new(ScriptReader::new(&lines)), true)
+        }
+        Some(script) => match File::open(&script) {
+            Result::Ok(file) => (Box::new(PlainReader::named(BufReader::new(file), &script)), true),
+            Result::Err(e) => {
+                drop(table);
+                exit_with_error(&format!("cannot open {}: {}", script, e))
+            }
+        },
+        None if io::stdin().is_terminal() => match EditorReader::new(Vocabulary::new(&table)) {
+            Result::Ok(reader) => (Box::new(reader), false),
+            Result::Err(msg) => {
+                warn!("{}, falling back to plain input.", msg);
+                (Box::new(PlainReader::new(io::stdin().lock())), false)
+            }
+        },
+        None => (Box::new(PlainReader::new(io::stdin().lock())), true),
+    };
+    if !batch && !args.quiet {
+        print!("{}", meta::banner(&table));
+    }
+    let result = repl(reader.as_mut(), &mut table, &mut session, batch);
+    if let Result::Err(ref msg) = result {
+        println!("{}", msg);
+    }
+    if let Result::Err(msg) = mem::take(&mut session.attached).close() {
+        eprintln!("{}", msg);
+    }
+    if let Result::Err(e) = table.close() {
+        exit_with_error(&format!("{}", e));
+    }
+    if result.is_err() {
+        process::exit(1);
+    }
+}
 
-        read_input(&mut input_buffer);
+/// serve the db at given path until the process is killed.
+fn serve(addr: &str, db: &str) -> ! {
+    let db = Database::from_pager(create_pager(db));
+    let listener = match TcpListener::bind(addr) {
+        Result::Ok(listener) => listener,
+        Result::Err(e) => exit_with_error(&format!("cannot listen on {}: {}", addr, e)),
+    };
+    eprintln!("serving on {}", addr);
+    match server::serve(listener, Arc::new(db)) {
+        Result::Ok(()) => process::exit(0),
+        Result::Err(e) => exit_with_error(&format!("cannot accept clients on {}: {}", addr, e)),
+    }
+}
 
-        if input_buffer.starts_with(".") {
-            match do_meta_command(&input_buffer.trim(), &mut table) {
-                Result::Ok(_) => {}
-                Result::Err(msg) => println!("{}", &msg),
+/**
+ * run statements and meta commands until `.exit` or the end of input. errors are
+ * printed, in batch mode the first one stops the loop and is returned instead,
+ * prefixed with the line it happened on.
+ **/
+fn repl(
+    reader: &mut dyn LineReader,
+    table: &mut Table,
+    session: &mut Session,
+    batch: bool,
+) -> Result<(), String> {
+    let mut interrupted = false;
+    loop {
+        let prompt = if batch { String::new() } else { prompt(table, session) };
+        session.signals.set_idle(true);
+        let line = reader.read_line(&prompt);
+        session.signals.set_idle(false);
+        let line = match line {
+            Input::Line(line) => line,
+            // Ctrl-C drops the line, another one on the empty prompt exits
+            Input::Interrupted if interrupted => return Result::Ok(()),
+            Input::Interrupted => {
+                interrupted = true;
+                println!("(press Ctrl-C again to exit)");
+                continue;
             }
+            Input::Eof => {
+                // keep the shell prompt off ours
+                if !batch {
+                    println!();
+                }
+                return Result::Ok(());
+            }
+        };
+        interrupted = false;
+        let input = line.trim();
+        // a line of nothing but a comment is skipped like an empty one
+        if vm::is_blank(input) {
             continue;
         }
 
-        match Statement::prepare(&input_buffer.trim(), &table.schema) {
-            Result::Ok(mut statement) => match statement.execute(&mut table) {
-                Result::Ok(_) => println!("Executed."),
-                Result::Err(msg) => println!("{}", &msg),
-            },
-            Result::Err(msg) => println!("{}", &msg),
+        let echoed = if session.echo {
+            session.echo_input(input)
+        } else {
+            Result::Ok(())
+        };
+        let result = if echoed.is_err() {
+            echoed
+        } else if input.starts_with(".") {
+            match do_meta_command(input, table, session) {
+                Result::Ok(MetaAction::Continue) => Result::Ok(()),
+                Result::Ok(MetaAction::Exit) => return Result::Ok(()),
+                Result::Ok(MetaAction::Read(path)) => read_script(&path, table, session),
+                Result::Err(msg) => Result::Err(msg),
+            }
+        } else {
+            run_statement(input, table, session).map(|outcome| {
+                // piped results stay clean of feedback
+                let feedback = |msg: String| {
+                    if batch {
+                        eprintln!("{}", msg)
+                    } else {
+                        println!("{}", msg)
+                    }
+                };
+                if let Some(max_rows) = outcome.truncated_at {
+                    feedback(format!(
+                        "… output truncated at {} rows (use .maxrows 0 to disable)",
+                        max_rows
+                    ));
+                }
+                if let Some(changes) = outcome.changes {
+                    feedback(format!("{}", changes));
+                    session.changes = Some(changes);
+                }
+                if session.timer {
+                    feedback(format!("{}", outcome));
+                }
+            })
+        };
+        if let Result::Err(msg) = result {
+            let syntax_error = vm::syntax_error(input, &msg);
+            let msg = match reader.location() {
+                Some(location) => format!("{}: {}", location, msg),
+                None => msg,
+            };
+            if batch {
+                return Result::Err(msg);
+            }
+            println!("{}", &msg);
+            if let Some(syntax_error) = syntax_error {
+                println!("{}", point_at(input, syntax_error.offset));
+            }
+        }
+        if session.signals.terminated() {
+            return Result::Err("terminated.".to_owned());
         }
     }
 }
 
-fn create_pager() -> Pager {
-    let db = match env::args().nth(1) {
-        Some(file) => file,
-        None => String::from(DEFAULT_DB_FILE),
-    };
-    let mut page_size = DEFAULT_PAGE_SIZE;
-    if let Some((_, v)) = env::vars().find(|r| r.0.eq(ENV_PAGE_SIZE)) {
-        page_size = u32::from_str_radix(&v, 10)
-            .expect(&format!("invalid value for {}", ENV_PAGE_SIZE)) as usize;
+/// the line of the input holding given byte offset, with a caret under the offset.
+fn point_at(input: &str, mut offset: usize) -> String {
+    offset = offset.min(input.len());
+    while !input.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let start = input[..offset].rfind('\n').map_or(0, |newline| newline + 1);
+    let end = input[offset..].find('\n').map_or(input.len(), |newline| offset + newline);
+    let column = input[start..offset].chars().count();
+    format!("{}\n{}^", &input[start..end], " ".repeat(column))
+}
+
+/// run the script at given path for `.read`, it stops at its first error. `.exit`
+/// in the script only ends the script.
+fn read_script(path: &str, table: &mut Table, session: &mut Session) -> Result<(), String> {
+    if session.read_depth >= MAX_READ_DEPTH {
+        return Result::Err(format!(
+            "cannot read {}: .read is nested more than {} levels deep.",
+            path, MAX_READ_DEPTH
+        ));
+    }
+    let file = File::open(path).map_err(|e| format!("cannot open {}: {}", path, e))?;
+    let mut reader = PlainReader::named(BufReader::new(file), path);
+    session.read_depth += 1;
+    let result = repl(&mut reader, table, session, true);
+    session.read_depth -= 1;
+    result
+}
+
+/// `rdb > `, or `rdb*> ` while the db has changes a crash may lose.
+fn prompt(table: &Table, session: &Session) -> String {
+    session.prompt(table.tree.pager.has_unsaved_changes())
+}
+
+/// what `.timer` reports about a statement.
+struct ExecOutcome {
+    /// `None` for statements that don't change or return rows.
+    changes: Option<Changes>,
+    /// the rows shown when there were more than `.maxrows`.
+    truncated_at: Option<usize>,
+    /// parsing and code generation.
+    prepare_time: Duration,
+    /// execution, rendering of the results and the commit.
+    execute_time: Duration,
+}
+
+impl fmt::Display for ExecOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let millis = |d: Duration| d.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "prepare: {:.2} ms, execute: {:.2} ms, rows: {}",
+            millis(self.prepare_time),
+            millis(self.execute_time),
+            self.changes.map_or(0, |changes| changes.rows)
+        )
+    }
+}
+
+/// prepare, execute and commit a statement, its results go to the session output.
+fn run_statement(
+    input: &str,
+    table: &mut Table,
+    session: &mut Session,
+) -> Result<ExecOutcome, String> {
+    let started = Instant::now();
+    let databases = Databases {
+        main: table,
+        attached: &session.attached,
     };
+    let mut statement = Statement::prepare(input, &databases)?;
+    statement.set_cancel_flag(session.signals.cancel_flag());
+    session.signals.clear_interrupt();
+    let prepare_time = started.elapsed();
 
-    let db_option = DbOption {
-        page_size: page_size,
+    let started = Instant::now();
+    if let Some(attachment) = statement.attachment() {
+        if table.in_transaction() {
+            return Result::Err(
+                "cannot attach or detach a database within a transaction.".to_owned(),
+            );
+        }
+        let mut db_option = table.tree.pager.get_db_option();
+        db_option.busy_timeout = session.busy_timeout;
+        session
+            .attached
+            .run(attachment, db_option, statement.cancel_flag())?;
+        return Result::Ok(ExecOutcome {
+            changes: None,
+            truncated_at: None,
+            prepare_time,
+            execute_time: started.elapsed(),
+        });
+    }
+    let max_rows = session.max_rows;
+    // the rows go to the output of the session while the statement runs on one of
+    // its attached dbs
+    let mut attached = mem::take(&mut session.attached);
+    let result = match attached.table(statement.database()) {
+        Result::Ok(target) => execute_statement(&mut statement, target.unwrap_or(table), session),
+        Result::Err(msg) => Result::Err(msg),
     };
-    Pager::new(db.as_str(), db_option)
-}
-
-fn do_meta_command(input_buffer: &str, table: &mut Table) -> Result<(), String> {
-    if input_buffer.eq(".exit") {
-        table.close();
-        process::exit(0)
-    } else if input_buffer.eq(".constants") {
-        table.tree.config.print_constants();
-        Result::Ok(())
-    } else if input_buffer.eq(".btree_internal") {
-        table.debug_print(true);
-        Result::Ok(())
-    } else if input_buffer.eq(".btree") {
-        table.debug_print(false);
-        Result::Ok(())
+    session.attached = attached;
+    let rows = result?;
+    let changes = if statement.is_query() {
+        Some(Changes {
+            kind: ChangeKind::Returned,
+            rows,
+        })
     } else {
-        Result::Err(format!("Unrecognized command: {}", input_buffer))
+        let kind = if statement.is_insert() {
+            ChangeKind::Inserted
+        } else {
+            ChangeKind::Deleted
+        };
+        statement.changed_rows().map(|rows| Changes { kind, rows })
+    };
+    Result::Ok(ExecOutcome {
+        changes,
+        truncated_at: if max_rows > 0 && rows > max_rows { Some(max_rows) } else { None },
+        prepare_time,
+        execute_time: started.elapsed(),
+    })
+}
+
+/// execute and commit a statement, its rows are rendered to the session output.
+/// returns the number of rows.
+fn execute_statement(
+    statement: &mut Statement,
+    table: &mut Table,
+    session: &mut Session,
+) -> Result<usize, String> {
+    let mode = session.mode;
+    let null_value = session.null_value.clone();
+    let widths = session.widths.clone();
+    let max_rows = session.max_rows;
+    session
+        .write_output(|out| {
+            let mut renderer = Renderer::new(mode, out)
+                .null_value(&null_value)
+                .fixed_widths(&widths)
+                .max_rows(max_rows);
+            statement.execute(table, &mut renderer)?;
+            renderer.finish()
+        })
+        .and_then(|rows| table.commit().map(|_| rows))
+        .map_err(|e| table.rollback_failed(e))
+}
+
+fn create_pager(db: &str) -> Pager {
+    let db_option = match parse_db_option(|name| env::var(name).ok()) {
+        Result::Ok(db_option) => db_option,
+        Result::Err(msg) => exit_with_error(&msg),
+    };
+    let requested_page_size = env::var(ENV_PAGE_SIZE).ok().map(|_| db_option.page_size);
+    let new_file = db != MEMORY_DB && !Path::new(db).exists();
+    let pager = match Pager::new(db, db_option) {
+        Result::Ok(pager) => pager,
+        Result::Err(e) => exit_with_error(&format!("{}", e)),
+    };
+    match page_size_notice(requested_page_size, pager.get_page_size(), new_file) {
+        Some(notice @ PageSizeNotice::Ignored { .. }) => {
+            warn!("{}", notice);
+            eprintln!("warning: {}", notice);
+        }
+        Some(notice @ PageSizeNotice::Created { .. }) => {
+            info!("{}", notice);
+            eprintln!("{}", notice);
+        }
+        None => {}
     }
+    pager
 }
 
-fn print_prompt() {
-    print!("rdb > ");
-    io::stdout().flush().unwrap();
+/// what to tell about the page size of a freshly opened db.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum PageSizeNotice {
+    /// the page size in the header of an existing db always wins.
+    Ignored { requested: usize, page_size: usize },
+    Created { page_size: usize },
 }
 
-fn read_input(input_buffer: &mut String) {
-    input_buffer.clear();
-    io::stdin()
-        .read_line(input_buffer)
-        .expect("read input error.");
+impl fmt::Display for PageSizeNotice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PageSizeNotice::Ignored {
+                requested,
+                page_size,
+            } => write!(
+                f,
+                "ignoring {}={}: database was created with page_size={}",
+                ENV_PAGE_SIZE, requested, page_size
+            ),
+            PageSizeNotice::Created { page_size } => {
+                write!(f, "created a new database with page_size={}", page_size)
+            }
+        }
+    }
+}
+
+/// `requested` is the page size of `RDB_PAGE_SIZE` if set, `page_size` the one the
+/// db was opened with.
+fn page_size_notice(
+    requested: Option<usize>,
+    page_size: usize,
+    new_file: bool,
+) -> Option<PageSizeNotice> {
+    match requested {
+        _ if new_file => Some(PageSizeNotice::Created { page_size }),
+        Some(requested) if requested != page_size => Some(PageSizeNotice::Ignored {
+            requested,
+            page_size,
+        }),
+        _ => None,
+    }
+}
+
+/// pager settings from the `RDB_*` variables, `var` looks up a variable by name.
+fn parse_db_option<F>(var: F) -> Result<DbOption, String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let page_size = parse_number(&var, ENV_PAGE_SIZE, DEFAULT_PAGE_SIZE)?;
+    if let Result::Err(e) = pager::validate_page_size(page_size) {
+        return Result::Err(format!("invalid value for {}: {}", ENV_PAGE_SIZE, e));
+    }
+    let cache_pages = parse_number(&var, ENV_CACHE_PAGES, DEFAULT_CACHE_PAGES)?;
+    let extent_size = parse_number(&var, ENV_EXTENT_SIZE, DEFAULT_EXTENT_SIZE)?;
+    if extent_size == 0 {
+        return Result::Err(format!(
+            "invalid value for {}: 0, an extent holds at least one page.",
+            ENV_EXTENT_SIZE
+        ));
+    }
+    let busy_timeout = parse_number(&var, ENV_BUSY_TIMEOUT, 0)?;
+    let sync_mode = match var(ENV_SYNC) {
+        None => SyncMode::OnCommit,
+        Some(v) => match v.as_str() {
+            "off" => SyncMode::Off,
+            "close" => SyncMode::OnClose,
+            "commit" => SyncMode::OnCommit,
+            _ => {
+                return Result::Err(format!(
+                    "invalid value for {}: {}, expected off, close or commit.",
+                    ENV_SYNC, v
+                ))
+            }
+        },
+    };
+
+    Result::Ok(DbOption {
+        page_size,
+        sync_mode,
+        use_mmap: parse_flag(&var, ENV_MMAP)?,
+        compression: parse_flag(&var, ENV_COMPRESSION)?,
+        encryption_key: var(ENV_KEY),
+        cache_pages,
+        read_only: parse_flag(&var, ENV_READ_ONLY)?,
+        extent_size,
+        busy_timeout: Duration::from_millis(busy_timeout as u64),
+    })
+}
+
+fn parse_number<F>(var: &F, name: &str, default: usize) -> Result<usize, String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    match var(name) {
+        Some(v) => v
+            .parse::<usize>()
+            .map_err(|_| format!("invalid value for {}: {}", name, v)),
+        None => Result::Ok(default),
+    }
+}
+
+fn parse_flag<F>(var: &F, name: &str) -> Result<bool, String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    match var(name).as_deref() {
+        Some("1") => Result::Ok(true),
+        Some("0") | None => Result::Ok(false),
+        Some(v) => Result::Err(format!("invalid value for {}: {}, expected 0 or 1.", name, v)),
+    }
+}
+
+fn exit_with_error(msg: &str) -> ! {
+    println!("{}", msg);
+    process::exit(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use render::Mode;
+    use std::collections::HashMap;
+    use std::fs;
+
+    fn parse(vars: &[(&str, &str)]) -> Result<DbOption, String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+            .collect();
+        parse_db_option(|name| vars.get(name).cloned())
+    }
+
+    fn parse_error(name: &str, value: &str) -> String {
+        match parse(&[(name, value)]) {
+            Result::Ok(_) => panic!("{}={} should be refused", name, value),
+            Result::Err(msg) => msg,
+        }
+    }
+
+    #[test]
+    fn defaults_without_any_variable() {
+        let db_option = parse(&[]).unwrap();
+        assert_eq!(db_option.page_size, DEFAULT_PAGE_SIZE);
+        assert_eq!(db_option.sync_mode, SyncMode::OnCommit);
+        assert_eq!(db_option.cache_pages, DEFAULT_CACHE_PAGES);
+        assert_eq!(db_option.extent_size, DEFAULT_EXTENT_SIZE);
+        assert!(!db_option.read_only);
+        assert!(!db_option.use_mmap);
+        assert!(!db_option.compression);
+        assert_eq!(db_option.encryption_key, None);
+    }
+
+    #[test]
+    fn parse_errors_name_the_variable_and_the_value() {
+        assert_eq!(
+            parse_error("RDB_PAGE_SIZE", "big"),
+            "invalid value for RDB_PAGE_SIZE: big"
+        );
+        assert_eq!(
+            parse_error("RDB_PAGE_SIZE", "1000"),
+            "invalid value for RDB_PAGE_SIZE: invalid page size 1000, must be a power of two between 512 and 65536."
+        );
+        assert_eq!(
+            parse_error("RDB_CACHE_PAGES", "-1"),
+            "invalid value for RDB_CACHE_PAGES: -1"
+        );
+        assert_eq!(
+            parse_error("RDB_EXTENT_SIZE", "x"),
+            "invalid value for RDB_EXTENT_SIZE: x"
+        );
+        assert_eq!(
+            parse_error("RDB_EXTENT_SIZE", "0"),
+            "invalid value for RDB_EXTENT_SIZE: 0, an extent holds at least one page."
+        );
+        assert_eq!(
+            parse_error("RDB_SYNC", "always"),
+            "invalid value for RDB_SYNC: always, expected off, close or commit."
+        );
+        for &name in &["RDB_MMAP", "RDB_COMPRESSION", "RDB_READ_ONLY"] {
+            assert_eq!(
+                parse_error(name, "yes"),
+                format!("invalid value for {}: yes, expected 0 or 1.", name)
+            );
+        }
+    }
+
+    #[test]
+    fn settings_reach_the_pager() {
+        let db_option = parse(&[
+            ("RDB_PAGE_SIZE", "1024"),
+            ("RDB_CACHE_PAGES", "16"),
+            ("RDB_SYNC", "close"),
+            ("RDB_EXTENT_SIZE", "8"),
+            ("RDB_COMPRESSION", "1"),
+            ("RDB_BUSY_TIMEOUT", "250"),
+        ]).unwrap();
+        let pager = Pager::new(MEMORY_DB, db_option).unwrap();
+        let db_option = pager.get_db_option();
+        assert_eq!(db_option.page_size, 1024);
+        assert_eq!(db_option.cache_pages, 16);
+        assert_eq!(db_option.sync_mode, SyncMode::OnClose);
+        assert_eq!(db_option.extent_size, 8);
+        assert_eq!(db_option.busy_timeout, Duration::from_millis(250));
+        assert!(db_option.compression);
+        assert!(!db_option.read_only);
+    }
+
+    fn run(input: &str, table: &mut Table, session: &mut Session) -> Result<(), String> {
+        if input.starts_with(".") {
+            do_meta_command(input, table, session).map(|_| ())
+        } else {
+            run_statement(input, table, session).map(|_| ())
+        }
+    }
+
+    #[test]
+    fn output_and_once_redirect_select_results() {
+        let path = env::temp_dir().join(format!("rdb-output-{}.txt", process::id()));
+        let path = path.to_str().unwrap();
+        let once_path = format!("{}.once", path);
+        fs::write(path, "left over\n").unwrap();
+        let mut table = Table::in_memory_for_test();
+        let mut session = Session::new();
+        for input in &[
+            "insert 1 user1 person1@example.com",
+            "insert 2 user2 person2@example.com",
+            &format!(".output {}", path),
+            "select",
+            &format!(".once {}", once_path),
+            "select 42",
+            "select 'hello'",
+            // like every other query, unlike the legacy `select`
+            "select * from users",
+            ".output stdout",
+            "select",
+        ] {
+            assert_eq!(run(input, &mut table, &mut session), Result::Ok(()), "{}", input);
+        }
+
+        assert_eq!(
+            fs::read_to_string(path).unwrap(),
+            concat!(
+                "(1, 'user1', 'person1@example.com')\n(2, 'user2', 'person2@example.com')\n('hello')\n",
+                "(1, 'user1', 'person1@example.com')\n(2, 'user2', 'person2@example.com')\n"
+            )
+        );
+        assert_eq!(fs::read_to_string(&once_path).unwrap(), "(42)\n");
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(&once_path);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn output_write_errors_keep_the_session() {
+        let mut table = Table::in_memory_for_test();
+        let mut session = Session::new();
+        run("insert 1 user1 person1@example.com", &mut table, &mut session).unwrap();
+        run(".output /dev/full", &mut table, &mut session).unwrap();
+        let msg = run("select", &mut table, &mut session).unwrap_err();
+        assert!(msg.starts_with("cannot write to /dev/full: "), "{}", msg);
+        run(".output stdout", &mut table, &mut session).unwrap();
+        assert_eq!(run("select", &mut table, &mut session), Result::Ok(()));
+        run(".output /dev/full", &mut table, &mut session).unwrap();
+        // a bare .output goes back to the terminal
+        run(".output", &mut table, &mut session).unwrap();
+        assert_eq!(run("select", &mut table, &mut session), Result::Ok(()));
+        assert!(run(".once /nonexistent/dir/out.txt", &mut table, &mut session).is_err());
+    }
+
+    #[test]
+    fn statements_report_their_changes() {
+        let mut table = Table::in_memory_for_test();
+        let mut session = Session::new();
+        let mut reader = ScriptReader::new(&[
+            "insert 1 user1 person1@example.com",
+            "insert 2 user2 person2@example.com",
+        ]);
+        repl(&mut reader, &mut table, &mut session, false).unwrap();
+        let changes = session.changes.unwrap();
+        assert_eq!(format!("{}", changes), "1 row inserted");
+
+        let mut reader = ScriptReader::new(&["select 1", "select"]);
+        repl(&mut reader, &mut table, &mut session, false).unwrap();
+        assert_eq!(format!("{}", session.changes.unwrap()), "2 rows returned");
+        // a failed statement keeps the changes of the last one
+        assert!(run("insert 1 user1 person1@example.com", &mut table, &mut session).is_err());
+        assert_eq!(format!("{}", session.changes.unwrap()), "2 rows returned");
+        assert_eq!(
+            format!(
+                "{}",
+                Changes {
+                    kind: ChangeKind::Returned,
+                    rows: 0,
+                }
+            ),
+            "0 rows returned"
+        );
+    }
+
+    #[test]
+    fn max_rows_truncates_the_output() {
+        let path = env::temp_dir().join(format!("rdb-maxrows-{}.txt", process::id()));
+        let path = path.to_str().unwrap();
+        let mut table = Table::in_memory_for_test();
+        let mut session = Session::new();
+        for id in 1..4 {
+            let input = format!("insert {} user{} person{}@example.com", id, id, id);
+            run(&input, &mut table, &mut session).unwrap();
+        }
+        run(".maxrows 2", &mut table, &mut session).unwrap();
+        run(&format!(".once {}", path), &mut table, &mut session).unwrap();
+        let outcome = run_statement("select", &mut table, &mut session).unwrap();
+        assert_eq!(outcome.truncated_at, Some(2));
+        assert_eq!(outcome.changes.unwrap().rows, 3);
+        assert_eq!(
+            fs::read_to_string(path).unwrap(),
+            "(1, 'user1', 'person1@example.com')\n(2, 'user2', 'person2@example.com')\n"
+        );
+
+        run(".maxrows 0", &mut table, &mut session).unwrap();
+        let outcome = run_statement("select", &mut table, &mut session).unwrap();
+        assert_eq!(outcome.truncated_at, None);
+        assert!(run(".maxrows many", &mut table, &mut session).is_err());
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn timer_reports_milliseconds_and_rows() {
+        let outcome = ExecOutcome {
+            changes: Some(Changes {
+                kind: ChangeKind::Returned,
+                rows: 3,
+            }),
+            truncated_at: None,
+            prepare_time: Duration::from_micros(1500),
+            execute_time: Duration::from_micros(12_345),
+        };
+        assert_eq!(
+            format!("{}", outcome),
+            "prepare: 1.50 ms, execute: 12.35 ms, rows: 3"
+        );
+
+        let mut table = Table::in_memory_for_test();
+        let mut session = Session::new();
+        run("insert 1 user1 person1@example.com", &mut table, &mut session).unwrap();
+        run(".timer on", &mut table, &mut session).unwrap();
+        assert!(session.timer);
+        let outcome = run_statement("select", &mut table, &mut session).unwrap();
+        assert_eq!(outcome.changes.unwrap().rows, 1);
+        run(".timer off", &mut table, &mut session).unwrap();
+        assert!(!session.timer);
+        assert!(run(".timer yes", &mut table, &mut session).is_err());
+    }
+
+    fn collect_rows(table: &Table) -> Vec<(u32, String, String)> {
+        let mut rows = Vec::new();
+        let mut cursor = table.select_cursor().unwrap();
+        while !cursor.end_of_table() {
+            let row = cursor.get();
+            rows.push((row.id, row.username, row.email));
+            cursor.advance().unwrap();
+        }
+        rows
+    }
+
+    #[test]
+    fn dump_loads_back_into_a_fresh_db() {
+        let path = env::temp_dir().join(format!("rdb-dump-{}.sql", process::id()));
+        let path = path.to_str().unwrap();
+        let mut table = Table::in_memory_for_test();
+        let mut session = Session::new();
+        for id in (1..50).rev() {
+            let input = format!("insert {} user{} 'person {}@example.com'", id, id, id);
+            run(&input, &mut table, &mut session).unwrap();
+        }
+        run("insert 50 '' empty@example.com", &mut table, &mut session).unwrap();
+        run("insert 51 'O''Brien' ob@example.com", &mut table, &mut session).unwrap();
+        run(&format!(".once {}", path), &mut table, &mut session).unwrap();
+        run(".dump users", &mut table, &mut session).unwrap();
+        assert!(run(".dump accounts", &mut table, &mut session).is_err());
+
+        let script = fs::read_to_string(path).unwrap();
+        assert!(script.starts_with(
+            "create table if not exists users (id integer, username text, email text);\n\
+             insert into users (id, username, email) values (1, 'user1', 'person 1@example.com');\n"
+        ));
+        let mut loaded = Table::in_memory_for_test();
+        for line in script.lines() {
+            assert_eq!(run(line, &mut loaded, &mut session), Result::Ok(()), "{}", line);
+        }
+        assert!(script.ends_with("values (51, 'O''Brien', 'ob@example.com');\n"));
+        let rows = collect_rows(&table);
+        assert_eq!(rows.len(), 51);
+        assert_eq!(collect_rows(&loaded), rows);
+
+        run("create table books (title text, pages integer)", &mut table, &mut session).unwrap();
+        run("insert into books values ('dune', 412)", &mut table, &mut session).unwrap();
+        run("create index by_pages on books (pages)", &mut table, &mut session).unwrap();
+        run(&format!(".once {}", path), &mut table, &mut session).unwrap();
+        run(".dump books", &mut table, &mut session).unwrap();
+        assert_eq!(
+            fs::read_to_string(path).unwrap(),
+            "create table if not exists books (title text, pages integer);\n\
+             insert into books (title, pages) values ('dune', 412);\n\
+             create index by_pages on books (pages);\n"
+        );
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn repl_runs_scripted_input_until_exit() {
+        let path = env::temp_dir().join(format!("rdb-repl-{}.txt", process::id()));
+        let path = path.to_str().unwrap();
+        let mut table = Table::in_memory_for_test();
+        let mut session = Session::new();
+        let output = format!(".output {}", path);
+        let mut reader = ScriptReader::new(&[
+            "insert 1 user1 person1@example.com",
+            &output,
+            "  select  ",
+        ]).interrupt();
+        repl(&mut reader, &mut table, &mut session, false).unwrap();
+        run(".output stdout", &mut table, &mut session).unwrap();
+        assert_eq!(
+            fs::read_to_string(path).unwrap(),
+            "(1, 'user1', 'person1@example.com')\n"
+        );
+
+        let mut reader = ScriptReader::new(&[".exit", "insert 2 user2 person2@example.com"]);
+        repl(&mut reader, &mut table, &mut session, false).unwrap();
+        assert_eq!(collect_rows(&table).len(), 1);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn echo_writes_input_lines_ahead_of_their_results() {
+        let path = env::temp_dir().join(format!("rdb-echo-{}.txt", process::id()));
+        let path = path.to_str().unwrap();
+        let mut table = Table::in_memory_for_test();
+        let mut session = Session::new();
+        let output = format!(".output {}", path);
+        let mut reader = ScriptReader::new(&[
+            &output,
+            ".echo on",
+            "insert 1 user1 person1@example.com",
+            "  select  ",
+            ".mode csv",
+            "select",
+            ".echo off",
+            "select",
+        ]);
+        repl(&mut reader, &mut table, &mut session, true).unwrap();
+        run(".output stdout", &mut table, &mut session).unwrap();
+        assert_eq!(
+            fs::read_to_string(path).unwrap(),
+            concat!(
+                "> insert 1 user1 person1@example.com\n",
+                "> select\n",
+                "(1, 'user1', 'person1@example.com')\n",
+                "> .mode csv\n",
+                "> select\n",
+                "id,username,email\r\n",
+                "1,user1,person1@example.com\r\n",
+                "> .echo off\n",
+                "id,username,email\r\n",
+                "1,user1,person1@example.com\r\n",
+            )
+        );
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn page_size_conflicts_are_reported() {
+        assert_eq!(page_size_notice(None, 4096, false), None);
+        assert_eq!(page_size_notice(Some(4096), 4096, false), None);
+        let ignored = page_size_notice(Some(8192), 4096, false).unwrap();
+        assert_eq!(
+            ignored,
+            PageSizeNotice::Ignored {
+                requested: 8192,
+                page_size: 4096,
+            }
+        );
+        assert_eq!(
+            format!("{}", ignored),
+            "ignoring RDB_PAGE_SIZE=8192: database was created with page_size=4096"
+        );
+        let created = page_size_notice(Some(8192), 8192, true).unwrap();
+        assert_eq!(created, PageSizeNotice::Created { page_size: 8192 });
+        assert_eq!(format!("{}", created), "created a new database with page_size=8192");
+        assert_eq!(
+            page_size_notice(None, 4096, true),
+            Some(PageSizeNotice::Created { page_size: 4096 })
+        );
+    }
+
+    #[test]
+    fn prompt_marks_unsaved_changes() {
+        let pager = Pager::new(MEMORY_DB, parse(&[(ENV_SYNC, "close")]).unwrap()).unwrap();
+        let mut table = Table::new(BTree::new(pager));
+        let mut session = Session::new();
+        assert_eq!(prompt(&table, &session), "rdb > ");
+        run("insert 1 user1 person1@example.com", &mut table, &mut session).unwrap();
+        assert_eq!(prompt(&table, &session), "rdb*> ");
+        run(".sync", &mut table, &mut session).unwrap();
+        assert_eq!(prompt(&table, &session), "rdb > ");
+        run(".prompt 'test db'", &mut table, &mut session).unwrap();
+        assert_eq!(prompt(&table, &session), "test db > ");
+    }
+
+    /// write a script to a temp file named after the test, returns its path.
+    fn write_script(name: &str, lines: &[&str]) -> String {
+        let path = env::temp_dir().join(format!("rdb-{}-{}.sql", name, process::id()));
+        let path = path.to_str().unwrap().to_owned();
+        fs::write(&path, lines.join("\n")).unwrap();
+        path
+    }
+
+    #[test]
+    fn read_runs_a_script() {
+        let path = write_script(
+            "read",
+            &[
+                "-- copied from another db",
+                "insert 1 user1 person1@example.com",
+                "",
+                ".mode csv",
+                "/* the second */ insert 2 user2 person2@example.com -- and the last",
+            ],
+        );
+        let mut table = Table::in_memory_for_test();
+        let mut session = Session::new();
+        let read = format!(".read {}", path);
+        let mut reader = ScriptReader::new(&[&read]);
+        assert_eq!(repl(&mut reader, &mut table, &mut session, true), Result::Ok(()));
+        assert_eq!(collect_rows(&table).len(), 2);
+        assert_eq!(session.mode, Mode::Csv);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn read_stops_at_the_failing_line() {
+        let path = write_script(
+            "read-error",
+            &[
+                "insert 1 user1 person1@example.com",
+                "insert -2 user2 person2@example.com",
+                "insert 3 user3 person3@example.com",
+            ],
+        );
+        let mut table = Table::in_memory_for_test();
+        let mut session = Session::new();
+        assert_eq!(
+            read_script(&path, &mut table, &mut session),
+            Result::Err(format!("{}:2: ID must be positive.", path))
+        );
+        assert_eq!(collect_rows(&table).len(), 1);
+
+        // the session goes on after a failed script or one that cannot be opened
+        let read = format!(".read {}", path);
+        let mut reader = ScriptReader::new(&[
+            &read,
+            ".read /nonexistent/rdb.sql",
+            "insert 4 user4 person4@example.com",
+        ]);
+        assert_eq!(repl(&mut reader, &mut table, &mut session, false), Result::Ok(()));
+        assert_eq!(collect_rows(&table).len(), 2);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn read_can_be_nested_a_few_levels() {
+        let inner = write_script("read-inner", &["insert 1 user1 person1@example.com"]);
+        let outer = write_script(
+            "read-outer",
+            &[&format!(".read {}", inner), "insert 2 user2 person2@example.com"],
+        );
+        let mut table = Table::in_memory_for_test();
+        let mut session = Session::new();
+        assert_eq!(read_script(&outer, &mut table, &mut session), Result::Ok(()));
+        assert_eq!(collect_rows(&table).len(), 2);
+
+        let path = env::temp_dir().join(format!("rdb-read-loop-{}.sql", process::id()));
+        let path = path.to_str().unwrap();
+        let looping = write_script("read-loop", &[&format!(".read {}", path)]);
+        let msg = read_script(&looping, &mut table, &mut session).unwrap_err();
+        assert!(
+            msg.ends_with(&format!(
+                "cannot read {}: .read is nested more than {} levels deep.",
+                path, MAX_READ_DEPTH
+            )),
+            "{}",
+            msg
+        );
+        assert_eq!(session.read_depth, 0);
+        for path in &[inner, outer, looping] {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    #[test]
+    fn batch_mode_stops_at_the_first_error() {
+        let mut table = Table::in_memory_for_test();
+        let mut session = Session::new();
+        let mut reader = ScriptReader::new(&[
+            "insert 1 user1 person1@example.com",
+            "insert 1 user1 person1@example.com",
+            "insert 2 user2 person2@example.com",
+        ]);
+        assert_eq!(
+            repl(&mut reader, &mut table, &mut session, true),
+            Result::Err("Error: Duplicate key.".to_owned())
+        );
+        assert_eq!(collect_rows(&table).len(), 1);
+
+        let mut reader = ScriptReader::new(&[".foo", "insert 2 user2 person2@example.com"]);
+        assert!(repl(&mut reader, &mut table, &mut session, false).is_ok());
+        assert_eq!(collect_rows(&table).len(), 2);
+    }
+
+    fn args(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|arg| (*arg).to_owned()))
+    }
+
+    #[test]
+    fn args_take_commands_around_the_db_path() {
+        assert_eq!(
+            args(&[]),
+            Result::Ok(Args {
+                db: DEFAULT_DB_FILE.to_owned(),
+                script: None,
+                commands: Vec::new(),
+                quiet: false,
+                serve: None,
+                client: None,
+            })
+        );
+        assert_eq!(
+            args(&["-c", ".mode csv", "test.rdb", "--exec", "select", "-q"]),
+            Result::Ok(Args {
+                db: "test.rdb".to_owned(),
+                script: None,
+                commands: vec![".mode csv".to_owned(), "select".to_owned()],
+                quiet: true,
+                serve: None,
+                client: None,
+            })
+        );
+        assert_eq!(
+            args(&["test.rdb", "init.sql"]).map(|args| args.script),
+            Result::Ok(Some("init.sql".to_owned()))
+        );
+        assert_eq!(args(&["test.rdb", "-c"]), Result::Err("-c needs a command.".to_owned()));
+        assert_eq!(args(&["-x"]), Result::Err("unknown option -x.".to_owned()));
+        assert_eq!(
+            args(&["a.rdb", "b.sql", "c.sql"]),
+            Result::Err("unexpected argument c.sql.".to_owned())
+        );
+        assert!(args(&["test.rdb", "init.sql", "-c", "select"]).is_err());
+    }
+
+    #[test]
+    fn args_serve_a_db_or_connect_to_a_server() {
+        let serve = args(&["--serve", "127.0.0.1:4000", "test.rdb"]).unwrap();
+        assert_eq!(serve.serve, Some("127.0.0.1:4000".to_owned()));
+        assert_eq!(serve.db, "test.rdb");
+        assert_eq!(
+            args(&["--client", "127.0.0.1:4000"]).map(|args| args.client),
+            Result::Ok(Some("127.0.0.1:4000".to_owned()))
+        );
+        assert_eq!(args(&["--serve"]), Result::Err("--serve needs an address.".to_owned()));
+        assert!(args(&["--serve", "127.0.0.1:4000", "test.rdb", "init.sql"]).is_err());
+        assert!(args(&["--serve", "127.0.0.1:4000", "-c", "select"]).is_err());
+        assert!(args(&["--client", "127.0.0.1:4000", "test.rdb"]).is_err());
+    }
+
+    #[test]
+    fn ctrl_c_twice_exits_the_repl() {
+        let mut table = Table::in_memory_for_test();
+        let mut session = Session::new();
+        let mut reader = ScriptReader::new(&["insert 1 user1 person1@example.com"])
+            .interrupt()
+            .interrupt();
+        assert!(repl(&mut reader, &mut table, &mut session, false).is_ok());
+        assert_eq!(reader.read_line(""), Input::Eof);
+        assert_eq!(collect_rows(&table).len(), 1);
+    }
+
+    #[test]
+    fn cancelled_table_scans_are_rolled_back() {
+        let mut table = Table::in_memory_for_test();
+        let mut session = Session::new();
+        run("insert 1 user1 person1@example.com", &mut table, &mut session).unwrap();
+        let mut statement = Statement::prepare("select", &table.schema).unwrap();
+        let cancel = session.signals.cancel_flag();
+        statement.set_cancel_flag(cancel.clone());
+        cancel.store(true, std::sync::atomic::Ordering::SeqCst);
+        let mut out = Vec::new();
+        let mut renderer = Renderer::new(session.mode, &mut out);
+        assert_eq!(
+            statement.execute(&mut table, &mut renderer),
+            Result::Err("interrupted.".to_owned())
+        );
+        // the next statement starts without the stale interrupt
+        assert_eq!(run("select", &mut table, &mut session), Result::Ok(()));
+    }
+
+    #[test]
+    fn syntax_errors_point_at_the_token_found() {
+        let mut table = Table::in_memory_for_test();
+        let mut session = Session::new();
+        let input = "/* é */ select id frm users";
+        let msg = run(input, &mut table, &mut session).unwrap_err();
+        assert_eq!(msg, "syntax error near frm, expected the end of the statement.");
+        let error = vm::syntax_error(input, &msg).unwrap();
+        assert_eq!(point_at(input, error.offset), format!("{}\n{}^", input, " ".repeat(18)));
+        assert_eq!(
+            point_at("select 1\nfrom (", 14),
+            "from (\n     ^"
+        );
+        let msg = run("insert 1 'ab", &mut table, &mut session).unwrap_err();
+        assert!(vm::syntax_error("insert 1 'ab", &msg).is_none());
+    }
 }
@@ -0,0 +1,888 @@
+//! meta commands of the REPL, every command is registered in `META_COMMANDS`
+//! which drives the dispatch, the argument checks and `.help`.
+
+use std::fs;
+use std::mem;
+use std::time::Duration;
+
+use render::Mode;
+use session::Session;
+use rdb::internals::catalog::MAIN_DB;
+use rdb::MEMORY_DB;
+use table::{Row, Table, TABLE_NAME};
+use vm;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// what the REPL does after a meta command.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum MetaAction {
+    Continue,
+    Exit,
+    /// run the script at the path.
+    Read(String),
+}
+
+/// everything a meta command may look at or change.
+pub struct MetaContext<'a> {
+    pub table: &'a mut Table,
+    pub session: &'a mut Session,
+}
+
+pub struct MetaCommand {
+    pub name: &'static str,
+    /// other names the command answers to.
+    pub aliases: &'static [&'static str],
+    /// the arguments as shown by `.help`, one word per argument, `[]` around
+    /// optional ones and `...` after repeated ones, which also gives the number of
+    /// arguments accepted.
+    pub args: &'static str,
+    pub help: &'static str,
+    /// called with the arguments once their number is checked.
+    handler: fn(&mut MetaContext, &[String]) -> Result<MetaAction, String>,
+}
+
+impl MetaCommand {
+    fn check_args(&self, args: &[String]) -> Result<(), String> {
+        let specs: Vec<&str> = self.args.split_whitespace().collect();
+        let required = specs.iter().filter(|spec| !spec.starts_with('[')).count();
+        let repeated = specs.iter().any(|spec| spec.contains("..."));
+        if required <= args.len() && (repeated || args.len() <= specs.len()) {
+            Result::Ok(())
+        } else if specs.is_empty() {
+            Result::Err(format!("{} takes no arguments.", self.name))
+        } else {
+            Result::Err(format!("usage: {}", usage(self)))
+        }
+    }
+
+    fn answers_to(&self, name: &str) -> bool {
+        self.name == name || self.aliases.contains(&name)
+    }
+}
+
+pub const META_COMMANDS: &[MetaCommand] = &[
+    MetaCommand {
+        name: ".exit",
+        aliases: &[".quit", ".q"],
+        args: "",
+        help: "flush all changes and exit",
+        handler: exit,
+    },
+    MetaCommand {
+        name: ".help",
+        aliases: &[],
+        args: "",
+        help: "show this message",
+        handler: help,
+    },
+    MetaCommand {
+        name: ".mode",
+        aliases: &[],
+        args: "[list|column|csv|json]",
+        help: "show or set how result rows are rendered",
+        handler: mode,
+    },
+    MetaCommand {
+        name: ".width",
+        aliases: &[],
+        args: "[WIDTH...]",
+        help: "set the column widths of column mode, 0 or none sizes by the values",
+        handler: width,
+    },
+    MetaCommand {
+        name: ".maxrows",
+        aliases: &[],
+        args: "[N]",
+        help: "show or set the most rows a statement shows, 0 shows all",
+        handler: maxrows,
+    },
+    MetaCommand {
+        name: ".nullvalue",
+        aliases: &[],
+        args: "[TEXT]",
+        help: "show or set how NULL is rendered, NULL by default and null in json",
+        handler: nullvalue,
+    },
+    MetaCommand {
+        name: ".changes",
+        aliases: &[],
+        args: "",
+        help: "show the rows inserted or returned by the last statement",
+        handler: changes,
+    },
+    MetaCommand {
+        name: ".timer",
+        aliases: &[],
+        args: "on|off",
+        help: "print the time taken by each statement",
+        handler: timer,
+    },
+    MetaCommand {
+        name: ".timeout",
+        aliases: &[],
+        args: "[MS]",
+        help: "show or set how long to wait for a db locked by another process",
+        handler: timeout,
+    },
+    MetaCommand {
+        name: ".echo",
+        aliases: &[],
+        args: "on|off",
+        help: "write each input line to the output before its results",
+        handler: echo,
+    },
+    MetaCommand {
+        name: ".prompt",
+        aliases: &[],
+        args: "[TEXT]",
+        help: "show or set the prompt text, a * follows it while changes are unsaved",
+        handler: prompt,
+    },
+    MetaCommand {
+        name: ".output",
+        aliases: &[],
+        args: "[FILE|stdout]",
+        help: "write results to a file, or back to the terminal without FILE",
+        handler: output,
+    },
+    MetaCommand {
+        name: ".once",
+        aliases: &[],
+        args: "FILE",
+        help: "write the results of the next statement to a file",
+        handler: once,
+    },
+    MetaCommand {
+        name: ".read",
+        aliases: &[],
+        args: "FILE",
+        help: "run the statements of a script, stopping at its first error",
+        handler: read,
+    },
+    MetaCommand {
+        name: ".dump",
+        aliases: &[],
+        args: "[TABLE]",
+        help: "write the table and its rows as sql statements to the output",
+        handler: dump,
+    },
+    MetaCommand {
+        name: ".import",
+        aliases: &[],
+        args: "FILE",
+        help: "insert the rows of a csv file with id, username and email columns",
+        handler: import,
+    },
+    MetaCommand {
+        name: ".sync",
+        aliases: &[],
+        args: "",
+        help: "write all changes to disk and sync",
+        handler: sync,
+    },
+    MetaCommand {
+        name: ".vacuum",
+        aliases: &[],
+        args: "",
+        help: "rebuild the db file without free pages",
+        handler: vacuum,
+    },
+    MetaCommand {
+        name: ".key",
+        aliases: &[],
+        args: "[KEY]",
+        help: "encrypt the db with a new key, decrypt it without one",
+        handler: key,
+    },
+    MetaCommand {
+        name: ".recover",
+        aliases: &[],
+        args: "PATH",
+        help: "salvage the rows of a damaged db into a new db file",
+        handler: recover,
+    },
+    MetaCommand {
+        name: ".check",
+        aliases: &[],
+        args: "",
+        help: "count the rows and correct the stored row count",
+        handler: check,
+    },
+    MetaCommand {
+        name: ".info",
+        aliases: &[],
+        args: "",
+        help: "show the db file, its size and the pending changes",
+        handler: info,
+    },
+    MetaCommand {
+        name: ".databases",
+        aliases: &[],
+        args: "",
+        help: "list the main and the attached dbs with their files",
+        handler: databases,
+    },
+    MetaCommand {
+        name: ".options",
+        aliases: &[],
+        args: "",
+        help: "show the options the db was opened with",
+        handler: options,
+    },
+    MetaCommand {
+        name: ".stats",
+        aliases: &[],
+        args: "[reset]",
+        help: "show or reset the pager statistics",
+        handler: stats,
+    },
+    MetaCommand {
+        name: ".constants",
+        aliases: &[],
+        args: "",
+        help: "show the b+tree layout constants",
+        handler: constants,
+    },
+    MetaCommand {
+        name: ".btree",
+        aliases: &[],
+        args: "",
+        help: "print the b+tree",
+        handler: btree,
+    },
+    MetaCommand {
+        name: ".btree_internal",
+        aliases: &[],
+        args: "",
+        help: "print the internal nodes of the b+tree",
+        handler: btree_internal,
+    },
+];
+
+/// run the meta command in `input_buffer`, a line starting with a dot. arguments
+/// are split on whitespace, single quotes keep one with spaces together.
+pub fn do_meta_command(
+    input_buffer: &str,
+    table: &mut Table,
+    session: &mut Session,
+) -> Result<MetaAction, String> {
+    let mut args = vm::split_values(input_buffer)?;
+    if args.is_empty() {
+        return Result::Err(format!("Unrecognized command: {}", input_buffer));
+    }
+    let name = args.remove(0);
+    let command = match META_COMMANDS.iter().find(|command| command.answers_to(&name)) {
+        Some(command) => command,
+        None => {
+            return Result::Err(match suggest(&name) {
+                Some(suggestion) => format!(
+                    "Unrecognized command: {}, did you mean {}?",
+                    name, suggestion
+                ),
+                None => format!(
+                    "Unrecognized command: {}, see .help for the available ones.",
+                    name
+                ),
+            })
+        }
+    };
+    command.check_args(&args)?;
+    let mut ctx = MetaContext { table, session };
+    (command.handler)(&mut ctx, &args)
+}
+
+/// the registered name closest to a mistyped one, if any is close enough.
+fn suggest(name: &str) -> Option<&'static str> {
+    // a third of the name may be wrong, but at least one character
+    let max_distance = (name.chars().count() / 3).max(1);
+    META_COMMANDS
+        .iter()
+        .flat_map(|command| Some(&command.name).into_iter().chain(command.aliases.iter()))
+        .map(|candidate| (edit_distance(name, candidate), *candidate))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// the Levenshtein distance of two strings, counted in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+pub fn help_text() -> String {
+    let usages: Vec<String> = META_COMMANDS.iter().map(usage).collect();
+    let width = usages.iter().map(|usage| usage.len()).max().unwrap_or(0);
+    let mut text = String::new();
+    for (command, usage) in META_COMMANDS.iter().zip(usages.iter()) {
+        text.push_str(&format!("{:width$}  {}", usage, command.help, width = width));
+        if !command.aliases.is_empty() {
+            text.push_str(&format!(", also {}", command.aliases.join(", ")));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// what the REPL prints on start: the version and the db it is attached to.
+pub fn banner(table: &Table) -> String {
+    let pager = &table.tree.pager;
+    format!(
+        "rdb {}\ndb: {} (page size {}, {} pages, {})\n",
+        VERSION,
+        display_path(pager.get_path().unwrap_or(MEMORY_DB)),
+        pager.get_page_size(),
+        pager.num_pages,
+        if pager.get_db_option().read_only { "read-only" } else { "read-write" }
+    )
+}
+
+/// the absolute path of a db file, relative paths are easy to attach to the wrong
+/// file.
+fn display_path(path: &str) -> String {
+    fs::canonicalize(path)
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|_| path.to_owned())
+}
+
+fn usage(command: &MetaCommand) -> String {
+    if command.args.is_empty() {
+        command.name.to_owned()
+    } else {
+        format!("{} {}", command.name, command.args)
+    }
+}
+
+fn exit(_ctx: &mut MetaContext, _args: &[String]) -> Result<MetaAction, String> {
+    Result::Ok(MetaAction::Exit)
+}
+
+fn help(_ctx: &mut MetaContext, _args: &[String]) -> Result<MetaAction, String> {
+    print!("{}", help_text());
+    Result::Ok(MetaAction::Continue)
+}
+
+fn mode(ctx: &mut MetaContext, args: &[String]) -> Result<MetaAction, String> {
+    match args.first() {
+        Some(mode) => ctx.session.mode = mode.parse::<Mode>()?,
+        None => println!("{}", ctx.session.mode),
+    }
+    Result::Ok(MetaAction::Continue)
+}
+
+fn width(ctx: &mut MetaContext, args: &[String]) -> Result<MetaAction, String> {
+    let mut widths = Vec::new();
+    for arg in args {
+        match arg.parse::<usize>() {
+            Result::Ok(width) => widths.push(width),
+            Result::Err(_) => {
+                return Result::Err(format!("invalid width {}, expected a number.", arg))
+            }
+        }
+    }
+    ctx.session.widths = widths;
+    Result::Ok(MetaAction::Continue)
+}
+
+fn maxrows(ctx: &mut MetaContext, args: &[String]) -> Result<MetaAction, String> {
+    match args.first() {
+        Some(arg) => {
+            ctx.session.max_rows = arg
+                .parse::<usize>()
+                .map_err(|_| format!("invalid row count {}, expected a number.", arg))?
+        }
+        None => println!("{}", ctx.session.max_rows),
+    }
+    Result::Ok(MetaAction::Continue)
+}
+
+fn nullvalue(ctx: &mut MetaContext, args: &[String]) -> Result<MetaAction, String> {
+    match args.first() {
+        Some(text) => ctx.session.null_value = text.clone(),
+        None => println!("{}", ctx.session.null_value),
+    }
+    Result::Ok(MetaAction::Continue)
+}
+
+fn changes(ctx: &mut MetaContext, _args: &[String]) -> Result<MetaAction, String> {
+    match ctx.session.changes {
+        Some(changes) => println!("{}", changes),
+        None => println!("no statement has run yet."),
+    }
+    Result::Ok(MetaAction::Continue)
+}
+
+fn timer(ctx: &mut MetaContext, args: &[String]) -> Result<MetaAction, String> {
+    ctx.session.timer = match args[0].as_str() {
+        "on" => true,
+        "off" => false,
+        _ => return Result::Err("usage: .timer on|off".to_owned()),
+    };
+    Result::Ok(MetaAction::Continue)
+}
+
+fn timeout(ctx: &mut MetaContext, args: &[String]) -> Result<MetaAction, String> {
+    match args.first() {
+        Some(arg) => {
+            let millis = arg
+                .parse::<u64>()
+                .map_err(|_| format!("invalid timeout {}, expected milliseconds.", arg))?;
+            ctx.session.busy_timeout = Duration::from_millis(millis);
+        }
+        None => println!("{}", ctx.session.busy_timeout.as_millis()),
+    }
+    Result::Ok(MetaAction::Continue)
+}
+
+fn echo(ctx: &mut MetaContext, args: &[String]) -> Result<MetaAction, String> {
+    ctx.session.echo = match args[0].as_str() {
+        "on" => true,
+        "off" => false,
+        _ => return Result::Err("usage: .echo on|off".to_owned()),
+    };
+    Result::Ok(MetaAction::Continue)
+}
+
+fn prompt(ctx: &mut MetaContext, args: &[String]) -> Result<MetaAction, String> {
+    match args.first() {
+        Some(text) => ctx.session.prompt = text.clone(),
+        None => println!("{}", ctx.session.prompt),
+    }
+    Result::Ok(MetaAction::Continue)
+}
+
+fn output(ctx: &mut MetaContext, args: &[String]) -> Result<MetaAction, String> {
+    let target = args.first().map_or("stdout", |target| target.as_str());
+    ctx.session.set_output(target).map(|_| MetaAction::Continue)
+}
+
+fn once(ctx: &mut MetaContext, args: &[String]) -> Result<MetaAction, String> {
+    ctx.session.set_once(&args[0]).map(|_| MetaAction::Continue)
+}
+
+fn read(_ctx: &mut MetaContext, args: &[String]) -> Result<MetaAction, String> {
+    Result::Ok(MetaAction::Read(args[0].clone()))
+}
+
+fn dump(ctx: &mut MetaContext, args: &[String]) -> Result<MetaAction, String> {
+    let mut tables = ctx.table.tables()?;
+    if let Some(name) = args.first() {
+        tables.retain(|table| table.name == *name);
+        if tables.is_empty() {
+            return Result::Err(format!("no such table: {}", name));
+        }
+    }
+    let table = &mut *ctx.table;
+    ctx.session.write_output(|out| {
+        let write_error = |e| format!("cannot write dump: {}", e);
+        for entry in &tables {
+            let create = vm::create_table_statement(&entry.name, &entry.schema);
+            writeln!(out, "{};", create).map_err(write_error)?;
+            let mut cursor = table.select_cursor_in(entry.root, 0)?;
+            while !cursor.end_of_table() {
+                let values = if entry.name == TABLE_NAME {
+                    cursor.get().values()
+                } else {
+                    cursor.row().values(&entry.schema)
+                };
+                writeln!(out, "{};", vm::insert_statement(&entry.name, &entry.schema, &values))
+                    .map_err(write_error)?;
+                cursor.advance()?;
+            }
+            // the indexes are built from the rows once they are in
+            for index in table.indexes_of(&entry.name)? {
+                writeln!(out, "create index {} on {} ({});", index.name, index.table, index.column)
+                    .map_err(write_error)?;
+            }
+        }
+        Result::Ok(MetaAction::Continue)
+    })
+}
+
+/// the failed ids `.import` lists, the others are only counted.
+const MAX_FAILED_KEYS_SHOWN: usize = 10;
+
+fn import(ctx: &mut MetaContext, args: &[String]) -> Result<MetaAction, String> {
+    let path = &args[0];
+    let text = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let rows = csv_rows(&text).map_err(|e| format!("{}:{}", path, e))?;
+    let report = ctx.table.insert_many(rows)?;
+    let imported = format!("imported {} {}", report.inserted, rows_word(report.inserted as u64));
+    let message = if report.failed_keys.is_empty() {
+        format!("{}.", imported)
+    } else {
+        let mut shown: Vec<String> = report
+            .failed_keys
+            .iter()
+            .take(MAX_FAILED_KEYS_SHOWN)
+            .map(|id| id.to_string())
+            .collect();
+        if report.failed_keys.len() > MAX_FAILED_KEYS_SHOWN {
+            shown.push("...".to_owned());
+        }
+        format!(
+            "{}, {} failed: {}",
+            imported,
+            report.failed_keys.len(),
+            shown.join(", ")
+        )
+    };
+    ctx.session.write_output(|out| {
+        writeln!(out, "{}", message).map_err(|e| format!("cannot write output: {}", e))?;
+        Result::Ok(MetaAction::Continue)
+    })
+}
+
+/// `row` or `rows`, as the count of them asks.
+fn rows_word(count: u64) -> &'static str {
+    if count == 1 {
+        "row"
+    } else {
+        "rows"
+    }
+}
+
+/// the rows of csv text, the first line is skipped if it names the columns.
+/// errors start with the line the record starts on.
+fn csv_rows(text: &str) -> Result<Vec<Row>, String> {
+    let mut rows = Vec::new();
+    for (index, (line, record)) in csv_records(text)?.into_iter().enumerate() {
+        if index == 0 && record == ["id", "username", "email"] {
+            continue;
+        }
+        match record.as_slice() {
+            [id, username, email] => rows.push(Row {
+                id: id
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("{}: invalid id '{}'", line, id))?,
+                username: username.clone(),
+                email: email.clone(),
+            }),
+            _ => {
+                return Result::Err(format!(
+                    "{}: expected 3 fields, got {}",
+                    line,
+                    record.len()
+                ))
+            }
+        }
+    }
+    Result::Ok(rows)
+}
+
+/// split csv text into records and the lines they start on, fields are quoted the
+/// way `.mode csv` quotes them. blank lines are skipped.
+fn csv_records(text: &str) -> Result<Vec<(usize, Vec<String>)>, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let (mut line, mut start) = (1, 1);
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\n' {
+            line += 1;
+        }
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => quoted = false,
+                c => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => quoted = true,
+            ',' => record.push(mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                if !record.is_empty() || !field.is_empty() {
+                    record.push(mem::take(&mut field));
+                    records.push((start, mem::take(&mut record)));
+                }
+                start = line;
+            }
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Result::Err(format!("{}: unterminated quote", start));
+    }
+    if !record.is_empty() || !field.is_empty() {
+        record.push(field);
+        records.push((start, record));
+    }
+    Result::Ok(records)
+}
+
+fn databases(ctx: &mut MetaContext, _args: &[String]) -> Result<MetaAction, String> {
+    let main = ctx.table.tree.pager.get_path().unwrap_or(MEMORY_DB);
+    println!("{}: {}", MAIN_DB, display_path(main));
+    for (name, path) in ctx.session.attached.databases() {
+        println!("{}: {}", name, display_path(path));
+    }
+    Result::Ok(MetaAction::Continue)
+}
+
+fn sync(ctx: &mut MetaContext, _args: &[String]) -> Result<MetaAction, String> {
+    ctx.table.sync().map(|_| MetaAction::Continue)
+}
+
+fn vacuum(ctx: &mut MetaContext, _args: &[String]) -> Result<MetaAction, String> {
+    ctx.table.vacuum().map(|_| MetaAction::Continue)
+}
+
+fn key(ctx: &mut MetaContext, args: &[String]) -> Result<MetaAction, String> {
+    // no key decrypts the database
+    let key = args.first().filter(|key| !key.is_empty()).cloned();
+    ctx.table.rekey(key).map(|_| MetaAction::Continue)
+}
+
+fn recover(ctx: &mut MetaContext, args: &[String]) -> Result<MetaAction, String> {
+    let path = &args[0];
+    let report = ctx.table.recover(path)?;
+    println!(
+        "recovered {} rows into {}, {} pages skipped.",
+        report.rows_recovered, path, report.pages_skipped
+    );
+    Result::Ok(MetaAction::Continue)
+}
+
+fn check(ctx: &mut MetaContext, _args: &[String]) -> Result<MetaAction, String> {
+    let report = ctx.table.check()?;
+    if report.rows == report.row_count_before {
+        println!("ok, {} {}.", report.rows, rows_word(report.rows));
+    } else {
+        println!(
+            "row count corrected from {} to {}.",
+            report.row_count_before, report.rows
+        );
+    }
+    Result::Ok(MetaAction::Continue)
+}
+
+fn info(ctx: &mut MetaContext, _args: &[String]) -> Result<MetaAction, String> {
+    print!("{}", banner(ctx.table));
+    println!("tables: 1, dirty pages: {}", ctx.table.tree.pager.num_dirty_pages());
+    Result::Ok(MetaAction::Continue)
+}
+
+fn options(ctx: &mut MetaContext, _args: &[String]) -> Result<MetaAction, String> {
+    println!("{}", ctx.table.tree.pager.get_db_option());
+    Result::Ok(MetaAction::Continue)
+}
+
+fn stats(ctx: &mut MetaContext, args: &[String]) -> Result<MetaAction, String> {
+    let table = &mut *ctx.table;
+    match args.first().map(|arg| arg.as_str()) {
+        None => {
+            println!("{}", table.tree.pager.stats());
+            match table.exact_row_count() {
+                Some(rows) => println!("rows: {}", rows),
+                None => println!(
+                    "rows: ~{} (last shutdown was not clean, .check recounts them)",
+                    table.estimated_row_count()
+                ),
+            }
+        }
+        Some("reset") => table.tree.pager.reset_stats(),
+        Some(_) => return Result::Err("usage: .stats [reset]".to_owned()),
+    }
+    Result::Ok(MetaAction::Continue)
+}
+
+fn constants(ctx: &mut MetaContext, _args: &[String]) -> Result<MetaAction, String> {
+    ctx.table.tree.config.print_constants();
+    Result::Ok(MetaAction::Continue)
+}
+
+fn btree(ctx: &mut MetaContext, _args: &[String]) -> Result<MetaAction, String> {
+    ctx.table.debug_print(false)?;
+    Result::Ok(MetaAction::Continue)
+}
+
+fn btree_internal(ctx: &mut MetaContext, _args: &[String]) -> Result<MetaAction, String> {
+    ctx.table.debug_print(true)?;
+    Result::Ok(MetaAction::Continue)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn help_lists_every_registered_command() {
+        let text = help_text();
+        assert_eq!(text.lines().count(), META_COMMANDS.len());
+        for command in META_COMMANDS {
+            assert!(
+                text.lines()
+                    .any(|line| line.starts_with(&format!("{} ", usage(command)))),
+                "{} is missing from .help",
+                command.name
+            );
+            assert!(text.contains(command.help));
+        }
+    }
+
+    #[test]
+    fn dispatches_by_the_first_word() {
+        let mut table = Table::in_memory_for_test();
+        let mut session = Session::new();
+        for name in &[".exit", ".quit", ".q"] {
+            assert_eq!(do_meta_command(name, &mut table, &mut session), Result::Ok(MetaAction::Exit));
+        }
+        assert_eq!(
+            do_meta_command(".stats   reset", &mut table, &mut session),
+            Result::Ok(MetaAction::Continue)
+        );
+        assert_eq!(
+            do_meta_command(".stats now", &mut table, &mut session),
+            Result::Err("usage: .stats [reset]".to_owned())
+        );
+        assert_eq!(
+            do_meta_command(".sync now", &mut table, &mut session),
+            Result::Err(".sync takes no arguments.".to_owned())
+        );
+    }
+
+    #[test]
+    fn banner_names_the_db() {
+        let table = Table::in_memory_for_test();
+        assert_eq!(
+            banner(&table),
+            format!(
+                "rdb {}\ndb: :memory: (page size 4096, {} pages, read-write)\n",
+                VERSION, table.tree.pager.num_pages
+            )
+        );
+    }
+
+    #[test]
+    fn arguments_are_counted_against_the_usage() {
+        let mut table = Table::in_memory_for_test();
+        let mut session = Session::new();
+        assert_eq!(
+            do_meta_command(".timer", &mut table, &mut session),
+            Result::Err("usage: .timer on|off".to_owned())
+        );
+        assert_eq!(
+            do_meta_command(".mode csv json", &mut table, &mut session),
+            Result::Err("usage: .mode [list|column|csv|json]".to_owned())
+        );
+        assert_eq!(
+            do_meta_command(".mode  'csv' ", &mut table, &mut session),
+            Result::Ok(MetaAction::Continue)
+        );
+        assert_eq!(session.mode, Mode::Csv);
+        assert_eq!(
+            do_meta_command(".width 4 0 30", &mut table, &mut session),
+            Result::Ok(MetaAction::Continue)
+        );
+        assert_eq!(session.widths, vec![4, 0, 30]);
+        assert_eq!(
+            do_meta_command(".width 4 wide", &mut table, &mut session),
+            Result::Err("invalid width wide, expected a number.".to_owned())
+        );
+        do_meta_command(".width", &mut table, &mut session).unwrap();
+        assert!(session.widths.is_empty());
+    }
+
+    #[test]
+    fn quoted_arguments_keep_their_spaces() {
+        let path = ::std::env::temp_dir().join(format!("rdb meta {}.txt", ::std::process::id()));
+        let path = path.to_str().unwrap().to_owned();
+        let mut table = Table::in_memory_for_test();
+        let mut session = Session::new();
+        let command = format!(".once '{}'", path);
+        assert_eq!(do_meta_command(&command, &mut table, &mut session), Result::Ok(MetaAction::Continue));
+        assert!(fs::metadata(&path).is_ok());
+        let _ = fs::remove_file(&path);
+        assert_eq!(
+            do_meta_command(".once 'no end", &mut table, &mut session),
+            Result::Err("unterminated string: 'no end".to_owned())
+        );
+    }
+
+    #[test]
+    fn import_inserts_the_rows_of_a_csv_file() {
+        let path = ::std::env::temp_dir().join(format!("rdb-import-{}.csv", ::std::process::id()));
+        let path = path.to_str().unwrap().to_owned();
+        fs::write(
+            &path,
+            "id,username,email\r\n2,bob,bob@example.com\r\n\n1,\"Lovelace, \"\"Ada\"\"\",ada@example.com\n2,bobby,bobby@example.com",
+        ).unwrap();
+        let report = format!("{}.out", path);
+        let mut table = Table::in_memory_for_test();
+        let mut session = Session::new();
+        let command = format!(".import {}", path);
+        do_meta_command(&format!(".once {}", report), &mut table, &mut session).unwrap();
+        assert_eq!(do_meta_command(&command, &mut table, &mut session), Result::Ok(MetaAction::Continue));
+        assert_eq!(fs::read_to_string(&report).unwrap(), "imported 2 rows, 1 failed: 2\n");
+        let _ = fs::remove_file(&report);
+        let mut cursor = table.select_cursor().unwrap();
+        let mut rows = Vec::new();
+        while !cursor.end_of_table() {
+            let row = cursor.get();
+            rows.push((row.id, row.username));
+            cursor.advance().unwrap();
+        }
+        assert_eq!(
+            rows,
+            vec![(1, "Lovelace, \"Ada\"".to_owned()), (2, "bob".to_owned())]
+        );
+
+        fs::write(&path, "3,carol,carol@example.com\n4,\"dave\n").unwrap();
+        assert_eq!(
+            do_meta_command(&command, &mut table, &mut session),
+            Result::Err(format!("{}:2: unterminated quote", path))
+        );
+        fs::write(&path, "3,carol\n").unwrap();
+        assert_eq!(
+            do_meta_command(&command, &mut table, &mut session),
+            Result::Err(format!("{}:1: expected 3 fields, got 2", path))
+        );
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn unknown_commands_suggest_the_closest_one() {
+        let mut table = Table::in_memory_for_test();
+        let mut session = Session::new();
+        assert_eq!(
+            do_meta_command(".exitt", &mut table, &mut session),
+            Result::Err("Unrecognized command: .exitt, did you mean .exit?".to_owned())
+        );
+        assert_eq!(
+            do_meta_command(".btre_internal now", &mut table, &mut session),
+            Result::Err("Unrecognized command: .btre_internal, did you mean .btree_internal?".to_owned())
+        );
+        assert_eq!(
+            do_meta_command(".frobnicate", &mut table, &mut session),
+            Result::Err("Unrecognized command: .frobnicate, see .help for the available ones.".to_owned())
+        );
+    }
+
+    #[test]
+    fn edit_distance_counts_chars() {
+        assert_eq!(edit_distance(".exit", ".exit"), 0);
+        assert_eq!(edit_distance(".exti", ".exit"), 2);
+        assert_eq!(edit_distance(".mod", ".mode"), 1);
+        assert_eq!(edit_distance("", ".q"), 2);
+        assert_eq!(edit_distance("ä", "a"), 1);
+    }
+}
@@ -1,20 +1,308 @@
-use std::fs::{File, OpenOptions};
-use std::io::{Read, Seek, SeekFrom, Write};
-use std::ops::{Index, IndexMut, Range, RangeFrom};
-use std::cell::RefCell;
-use std::rc::Rc;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cmp;
+#[cfg(feature = "file")]
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{self, ErrorKind};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError as LockError};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+use std::panic::{self, AssertUnwindSafe};
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
 
 use byteorder::{BigEndian, ByteOrder};
 
-pub const DB_HEADER_SIZE: usize = 100;
-// pub const DB_VERSION_OFFSET: usize = 0;
-// pub const DB_VERSION_SIZE: usize = 4;
-pub const DB_PAGE_SIZE_OFFSET: usize = 0;
-// pub const DB_PAGE_SIZE_SIZE: usize = 4;
+use compression;
+use header;
+use encryption::{self, Cipher};
+use header::{
+    DbHeader, DB_FLAG_COMPRESSION, DB_FLAG_ENCRYPTION, DB_FLAG_IN_USE, DB_HEADER_SIZE,
+    DB_ROW_COUNT_FORMAT_VERSION,
+};
+#[cfg(feature = "file")]
+use journal::journal_path;
+use journal::Journal;
+#[cfg(feature = "mmap")]
+use storage::MmapStorage;
+#[cfg(feature = "file")]
+use storage::FileStorage;
+use storage::{MemoryStorage, Storage, MEMORY_DB};
 
+/// page indices are stored as u32 in the db file.
+pub const MAX_NUM_PAGES: u64 = u32::MAX as u64 + 1;
+
+pub const MIN_PAGE_SIZE: usize = 512;
+pub const MAX_PAGE_SIZE: usize = 65536;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum PagerError {
+    Io(String),
+    InvalidPageSize(usize),
+    NotADatabase,
+    UnsupportedVersion(u32),
+    Corrupted,
+    Locked,
+    /// still locked once the busy timeout ran out.
+    Busy(Duration),
+    /// the wait for a lock was cancelled.
+    Interrupted,
+    EncryptionUnsupported,
+    KeyRequired,
+    InvalidKey,
+    CorruptedPage(usize),
+    TruncatedPage(usize),
+    TooManyPages,
+    ReadOnly,
+    CorruptedHeader,
+    /// a flush stopped at the first failed write.
+    WriteFailed { pages_written: usize, msg: String },
+    #[cfg_attr(not(feature = "encryption"), allow(dead_code))]
+    PageAuthentication(usize),
+}
+
+impl fmt::Display for PagerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PagerError::Io(ref msg) => write!(f, "{}", msg),
+            PagerError::InvalidPageSize(page_size) => write!(
+                f,
+                "invalid page size {}, must be a power of two between {} and {}.",
+                page_size, MIN_PAGE_SIZE, MAX_PAGE_SIZE
+            ),
+            PagerError::NotADatabase => write!(f, "not an rdb database."),
+            PagerError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported db format version {}, supported versions are {} to {}.",
+                version,
+                header::DB_MIN_FORMAT_VERSION,
+                header::DB_FORMAT_VERSION
+            ),
+            PagerError::Corrupted => write!(f, "db file is corrupted."),
+            PagerError::Locked => write!(f, "database is locked."),
+            PagerError::Busy(timeout) => write!(
+                f,
+                "database is locked, still busy after {} ms.",
+                timeout.as_millis()
+            ),
+            PagerError::Interrupted => write!(f, "interrupted."),
+            PagerError::EncryptionUnsupported => {
+                write!(f, "rdb is built without the encryption feature.")
+            }
+            PagerError::KeyRequired => write!(f, "database is encrypted, a key is required."),
+            PagerError::InvalidKey => write!(f, "invalid encryption key."),
+            PagerError::CorruptedPage(page_index) => write!(f, "page {} is corrupted.", page_index),
+            PagerError::TooManyPages => {
+                write!(f, "db file is full, at most {} pages are supported.", MAX_NUM_PAGES)
+            }
+            PagerError::ReadOnly => write!(f, "database is opened read-only."),
+            PagerError::CorruptedHeader => write!(f, "database header is corrupt."),
+            PagerError::WriteFailed {
+                pages_written,
+                ref msg,
+            } => write!(f, "{}, {} pages were written before the error.", msg, pages_written),
+            PagerError::TruncatedPage(page_index) => {
+                write!(f, "page {} is truncated, db file is corrupted.", page_index)
+            }
+            PagerError::PageAuthentication(page_index) => {
+                write!(f, "page {} failed authentication.", page_index)
+            }
+        }
+    }
+}
+
+pub fn validate_page_size(page_size: usize) -> Result<(), PagerError> {
+    if page_size.is_power_of_two() && (MIN_PAGE_SIZE..=MAX_PAGE_SIZE).contains(&page_size) {
+        Result::Ok(())
+    } else {
+        Result::Err(PagerError::InvalidPageSize(page_size))
+    }
+}
+
+/// when the pager syncs data onto disk, pages are always written on commit.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum SyncMode {
+    /// never sync, a power loss may corrupt the database.
+    Off,
+    /// sync only on close or an explicit `sync_all`, suits bulk loads.
+    OnClose,
+    /// sync the journal and the db file on every commit.
+    OnCommit,
+}
+
+impl fmt::Display for SyncMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SyncMode::Off => write!(f, "off"),
+            SyncMode::OnClose => write!(f, "close"),
+            SyncMode::OnCommit => write!(f, "commit"),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct DbOption {
     pub page_size: usize,
+    pub sync_mode: SyncMode,
+    /// access the db file through a memory mapping, requires the `mmap` feature.
+    pub use_mmap: bool,
+    /// compress pages in the db file, only honored when a db is created.
+    pub compression: bool,
+    /// encrypt pages with a key derived from this passphrase, only honored when a db
+    /// is created, requires the `encryption` feature.
+    pub encryption_key: Option<String>,
+    /// pages kept in the cache, 0 for no limit. pages in use or not yet written
+    /// are never evicted, so the cache may still grow beyond it.
+    pub cache_pages: usize,
+    /// refuse any write, the db file is locked shared and has to exist.
+    pub read_only: bool,
+    /// the db file grows by this many pages at once while pages are appended, it is
+    /// trimmed to the pages in use before a commit completes.
+    pub extent_size: usize,
+    /// how long to wait for a locked db before giving up with `PagerError::Busy`,
+    /// zero fails right away with `PagerError::Locked`.
+    pub busy_timeout: Duration,
+}
+
+/// the options `Database::open` uses: 4096 byte pages synced on commit, an
+/// unlimited cache, no mmap, compression or encryption, and no busy timeout.
+impl Default for DbOption {
+    fn default() -> DbOption {
+        DbOption {
+            page_size: 4096,
+            sync_mode: SyncMode::OnCommit,
+            use_mmap: false,
+            compression: false,
+            encryption_key: None,
+            cache_pages: 0,
+            read_only: false,
+            extent_size: 1,
+            busy_timeout: Duration::ZERO,
+        }
+    }
+}
+
+impl fmt::Display for DbOption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "page size: {}", self.page_size)?;
+        match self.cache_pages {
+            0 => writeln!(f, "cache pages: unlimited")?,
+            cache_pages => writeln!(f, "cache pages: {}", cache_pages)?,
+        }
+        writeln!(f, "sync: {}", self.sync_mode)?;
+        writeln!(f, "read only: {}", self.read_only)?;
+        writeln!(f, "extent size: {}", self.extent_size)?;
+        writeln!(f, "busy timeout: {} ms", self.busy_timeout.as_millis())?;
+        writeln!(f, "mmap: {}", self.use_mmap)?;
+        writeln!(f, "compression: {}", self.compression)?;
+        write!(f, "encryption: {}", self.encryption_key.is_some())
+    }
+}
+
+/// the longest sleep between two attempts to take a lock.
+const MAX_BUSY_SLEEP: Duration = Duration::from_millis(100);
+
+/// run `attempt` until it takes its lock, it fails with `PagerError::Locked` while
+/// the lock is held elsewhere. a free lock or a zero `timeout` returns the first
+/// attempt without looking at the clock. otherwise the sleeps between attempts
+/// double up to `MAX_BUSY_SLEEP`, after `timeout` the result is `PagerError::Busy`
+/// and a raised `cancel` flag stops the wait with `PagerError::Interrupted`.
+pub fn retry_while_locked<T, F>(
+    timeout: Duration,
+    cancel: Option<&AtomicBool>,
+    mut attempt: F,
+) -> Result<T, PagerError>
+where
+    F: FnMut() -> Result<T, PagerError>,
+{
+    match attempt() {
+        Result::Err(PagerError::Locked) if !timeout.is_zero() => {
+            wait_while_locked(timeout, cancel, attempt)
+        }
+        result => result,
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn wait_while_locked<T, F>(
+    timeout: Duration,
+    cancel: Option<&AtomicBool>,
+    mut attempt: F,
+) -> Result<T, PagerError>
+where
+    F: FnMut() -> Result<T, PagerError>,
+{
+    let deadline = Instant::now() + timeout;
+    let mut sleep = Duration::from_millis(1);
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return Result::Err(PagerError::Busy(timeout));
+        }
+        if cancel.is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+            return Result::Err(PagerError::Interrupted);
+        }
+        thread::sleep(sleep.min(deadline - now));
+        sleep = (sleep * 2).min(MAX_BUSY_SLEEP);
+        match attempt() {
+            Result::Err(PagerError::Locked) => {}
+            result => return result,
+        }
+    }
+}
+
+/// wasm32 has neither a clock nor a sleep, and with a single thread nothing could
+/// release the lock while waiting for it.
+#[cfg(target_arch = "wasm32")]
+fn wait_while_locked<T, F>(
+    _timeout: Duration,
+    _cancel: Option<&AtomicBool>,
+    _attempt: F,
+) -> Result<T, PagerError>
+where
+    F: FnMut() -> Result<T, PagerError>,
+{
+    Result::Err(PagerError::Locked)
+}
+
+/// counters of the pager, they are never reset unless asked to.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct PagerStats {
+    /// pages found in the cache.
+    pub cache_hits: u64,
+    /// pages loaded from the storage.
+    pub cache_misses: u64,
+    pub pages_written: u64,
+    pub pages_allocated: u64,
+    /// pages dropped from the cache to stay within `DbOption::cache_pages`.
+    pub evictions: u64,
+}
+
+impl PagerStats {
+    pub fn reset(&mut self) {
+        *self = PagerStats::default();
+    }
+}
+
+impl fmt::Display for PagerStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "cache hits: {}", self.cache_hits)?;
+        writeln!(f, "cache misses: {}", self.cache_misses)?;
+        writeln!(f, "pages written: {}", self.pages_written)?;
+        writeln!(f, "pages allocated: {}", self.pages_allocated)?;
+        write!(f, "evictions: {}", self.evictions)
+    }
+}
+
+/// outcome of a successful `Pager::flush_all`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct FlushReport {
+    pub pages_written: usize,
 }
 
 pub type Page = Vec<u8>;
@@ -23,7 +311,7 @@ pub trait PageTrait {
 
     fn move_slice_internally(&mut self, from: usize, to: usize, len: usize);
 
-    fn wrap_slice(&mut self, from: usize, buf: &Vec<u8>);
+    fn wrap_slice(&mut self, from: usize, buf: &[u8]);
 }
 
 impl PageTrait for Page {
@@ -32,152 +320,2231 @@ impl PageTrait for Page {
     }
 
     fn move_slice_internally(&mut self, from: usize, to: usize, len: usize) {
-        let mut vec = vec![0; len];
-        {
-            let slice = self.index(Range {
-                start: from,
-                end: from + len,
-            }).clone();
-            vec.clone_from_slice(slice);
+        debug_assert!(from + len <= self.len() && to + len <= self.len());
+        self.copy_within(from..from + len, to);
+    }
+
+    fn wrap_slice(&mut self, from: usize, buf: &[u8]) {
+        debug_assert!(from + buf.len() <= self.len());
+        self[from..from + buf.len()].copy_from_slice(buf);
+    }
+}
+
+/// a cached page shared by the cache and the guards handed out. borrowing it against
+/// another borrow panics like a `RefCell`, unlike one it moves between threads along
+/// with its pager.
+pub struct PageCell(RwLock<Page>);
+
+impl PageCell {
+    fn new(page: Page) -> PageCell {
+        PageCell(RwLock::new(page))
+    }
+
+    pub fn borrow(&self) -> RwLockReadGuard<'_, Page> {
+        match self.0.try_read() {
+            Result::Ok(page) => page,
+            Result::Err(LockError::Poisoned(e)) => e.into_inner(),
+            Result::Err(LockError::WouldBlock) => panic!("page already mutably borrowed"),
+        }
+    }
+
+    pub fn borrow_mut(&self) -> RwLockWriteGuard<'_, Page> {
+        self.try_borrow_mut().expect("page already borrowed")
+    }
+
+    /// `None` while the page is borrowed.
+    pub fn try_borrow_mut(&self) -> Option<RwLockWriteGuard<'_, Page>> {
+        match self.0.try_write() {
+            Result::Ok(page) => Some(page),
+            Result::Err(LockError::Poisoned(e)) => Some(e.into_inner()),
+            Result::Err(LockError::WouldBlock) => None,
+        }
+    }
+}
+
+/// a cached page that is pinned until the guard is dropped, so the cache never
+/// evicts and reloads a page somebody is still looking at.
+pub struct PageGuard {
+    page_index: usize,
+    page: Arc<PageCell>,
+    pins: Arc<Mutex<HashMap<usize, usize>>>,
+}
+
+impl PageGuard {
+    /// the page has to be pinned already, the guard takes over that pin.
+    fn new(
+        page_index: usize,
+        page: Arc<PageCell>,
+        pins: &Arc<Mutex<HashMap<usize, usize>>>,
+    ) -> PageGuard {
+        PageGuard {
+            page_index,
+            page,
+            pins: pins.clone(),
         }
-        let mut i = 0;
-        for b in vec {
-            self[to + i] = b;
-            i += 1;
+    }
+}
+
+/// another pin of the page.
+impl Clone for PageGuard {
+    fn clone(&self) -> PageGuard {
+        *lock(&self.pins).entry(self.page_index).or_insert(0) += 1;
+        PageGuard::new(self.page_index, self.page.clone(), &self.pins)
+    }
+}
+
+impl Deref for PageGuard {
+    type Target = PageCell;
+
+    fn deref(&self) -> &PageCell {
+        &self.page
+    }
+}
+
+impl Drop for PageGuard {
+    fn drop(&mut self) {
+        unpin(&self.pins, self.page_index);
+    }
+}
+
+/// the parts of a pager stay usable when a thread holding them panics, a pager
+/// dropped while unwinding still gets flushed.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn unpin(pins: &Mutex<HashMap<usize, usize>>, page_index: usize) {
+    let mut pins = lock(pins);
+    let unpinned = match pins.get_mut(&page_index) {
+        Some(count) => {
+            *count -= 1;
+            *count == 0
         }
+        None => panic!("unpin page {} which is not pinned", page_index),
+    };
+    if unpinned {
+        pins.remove(&page_index);
     }
+}
 
-    fn wrap_slice(&mut self, from: usize, buf: &Vec<u8>) {
-        let mut i = 0;
-        for b in buf {
-            self[from + i] = *b;
-            i += 1;
+/// see `Pager::scan_pages`, unreadable pages are yielded as errors.
+pub struct PageScan<'a> {
+    pager: &'a Pager,
+    page_index: usize,
+}
+
+impl<'a> Iterator for PageScan<'a> {
+    type Item = (usize, Result<Page, PagerError>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.page_index >= self.pager.num_committed_pages {
+            return None;
         }
+        let page_index = self.page_index;
+        self.page_index += 1;
+        let page = Pager::read_page(
+            lock(&self.pager.storage).as_mut(),
+            &self.pager.db_option,
+            self.pager.cipher.as_ref(),
+            page_index,
+        );
+        Some((page_index, page))
     }
 }
 
+/// the state of the transaction when a savepoint was set, see `rollback_to_savepoint`.
+struct Savepoint {
+    name: String,
+    /// pages as they were before their first change after the savepoint, pages
+    /// appended since are dropped instead.
+    pages: HashMap<usize, Page>,
+    num_pages: usize,
+    header: DbHeader,
+    header_dirty: bool,
+    free_pages: Vec<usize>,
+}
+
+/**
+ * pages modified by a transaction (a statement, or the statements up to the release
+ * of the outermost savepoint) are kept in memory, and their original contents are
+ * saved in the rollback journal before the first modification. `commit` writes them
+ * back to the database file.
+ **/
 pub struct Pager {
-    file: RefCell<File>,
-    pages: RefCell<HashMap<usize, Rc<RefCell<Page>>>>,
+    storage: Mutex<Box<dyn Storage>>,
+    pages: Mutex<HashMap<usize, Arc<PageCell>>>,
+    /// pin counts of pages that must stay cached, shared with the `PageGuard`s.
+    pins: Arc<Mutex<HashMap<usize, usize>>>,
     pub num_pages: usize,
     db_option: DbOption,
+    header: DbHeader,
+    cipher: Option<Cipher>,
+    /// the db file path, `None` for in-memory databases.
+    path: Option<String>,
+    /// in-memory databases go without a journal.
+    journal_path: Option<String>,
+    journal: Option<Journal>,
+    dirty_pages: BTreeSet<usize>,
+    num_committed_pages: usize,
+    /// free pages in stack order, the last one is the head of the list.
+    free_pages: Vec<usize>,
+    /// the free list fields in db header need to be written on commit.
+    header_dirty: bool,
+    /// the row count in the header is known to be right, see `row_count_is_exact`.
+    row_count_exact: bool,
+    /// the db header on disk has `DB_FLAG_IN_USE` set.
+    in_use_on_disk: bool,
+    /// pages of the current transaction reached the db file before a failed flush.
+    partially_flushed: bool,
+    /// the last `rollback` could not restore the db file, it stays unwritten.
+    rollback_failed: bool,
+    /// set by `close`, a pager dropped without it flushes its changes.
+    closed: bool,
+    /// the savepoints of the current transaction, innermost last.
+    savepoints: Vec<Savepoint>,
+    /// committed writes are not synced to disk yet.
+    unsynced: AtomicBool,
+    stats: Mutex<PagerStats>,
+    #[cfg(test)]
+    commit_trace: Mutex<Vec<&'static str>>,
 }
 
 impl Pager {
-    pub fn new(file: &str, mut db_option: DbOption) -> Pager {
-        let mut file = OpenOptions::new()
+    /// open the db file at given path, or an empty in-memory db for `:memory:`.
+    pub fn new(path: &str, db_option: DbOption) -> Result<Pager, PagerError> {
+        Pager::new_with_cancel(path, db_option, None)
+    }
+
+    /// like `new`, a raised `cancel` flag stops the wait for a locked db file.
+    pub fn new_with_cancel(
+        path: &str,
+        db_option: DbOption,
+        cancel: Option<&AtomicBool>,
+    ) -> Result<Pager, PagerError> {
+        validate_page_size(db_option.page_size)?;
+        let (storage, journal_path): (Box<dyn Storage>, Option<String>) = if path == MEMORY_DB
+        {
+            (Box::new(MemoryStorage::new()), None)
+        } else {
+            Pager::open_file(path, &db_option, cancel)?
+        };
+        Pager::with_storage(storage, path, journal_path, db_option)
+    }
+
+    /// open and lock the db file at given path, with the path of its journal.
+    #[cfg(feature = "file")]
+    fn open_file(
+        path: &str,
+        db_option: &DbOption,
+        cancel: Option<&AtomicBool>,
+    ) -> Result<(Box<dyn Storage>, Option<String>), PagerError> {
+        let read_only = db_option.read_only;
+        let file = OpenOptions::new()
             .read(true)
-            .write(true)
-            .create(true)
-            .open(file)
-            .unwrap();
+            .write(!read_only)
+            .create(!read_only)
+            .open(path)
+            .map_err(|e| PagerError::Io(format!("cannot open {}: {}", path, e)))?;
+        // the lock is released when the file is closed, readers share it
+        retry_while_locked(db_option.busy_timeout, cancel, || {
+            let locked = if read_only {
+                file.try_lock_shared()
+            } else {
+                file.try_lock()
+            };
+            locked.map_err(|e| match e {
+                TryLockError::WouldBlock => PagerError::Locked,
+                TryLockError::Error(e) => PagerError::Io(format!("cannot lock {}: {}", path, e)),
+            })
+        })?;
+        let storage = Pager::file_storage(file, db_option.use_mmap)
+            .map_err(|e| PagerError::Io(format!("cannot map {}: {}", path, e)))?;
+        Result::Ok((storage, Some(journal_path(path))))
+    }
 
-        let file_size = file.metadata().unwrap().len();
+    #[cfg(not(feature = "file"))]
+    fn open_file(
+        path: &str,
+        _db_option: &DbOption,
+        _cancel: Option<&AtomicBool>,
+    ) -> Result<(Box<dyn Storage>, Option<String>), PagerError> {
+        Result::Err(PagerError::Io(format!(
+            "cannot open {}: rdb is built without the file feature, only {} dbs can be opened.",
+            path, MEMORY_DB
+        )))
+    }
 
-        if file_size > 0 {
-            Pager::read_db_options(&mut file, &mut db_option);
-            if Pager::is_db_corrupted(file_size, db_option.page_size) {
-                panic!("db file is corrupted.");
+    /// open a db on top of given storage, the journal is skipped without a journal path.
+    fn with_storage(
+        mut storage: Box<dyn Storage>,
+        path: &str,
+        journal_path: Option<String>,
+        mut db_option: DbOption,
+    ) -> Result<Pager, PagerError> {
+        if let Some(ref journal_path) = journal_path {
+            if db_option.read_only && Path::new(journal_path).exists() {
+                return Result::Err(PagerError::Io(format!(
+                    "{} needs to be rolled back, open it read-write first.",
+                    journal_path
+                )));
             }
-        } else {
-            Pager::persist_db_options(&mut file, &db_option);
+            Journal::rollback(journal_path, storage.as_mut())
+                .map_err(|e| PagerError::Io(format!("cannot roll back {}: {}", journal_path, e)))?;
         }
 
+        let file_size = storage.len();
+
+        let (mut header, cipher) = if file_size > 0 {
+            let header = Pager::read_header(storage.as_mut())?;
+            let cipher = Pager::read_db_options(&header, &mut db_option)?;
+            if Pager::is_db_corrupted(file_size, Pager::slot_size(&db_option)) {
+                return Result::Err(PagerError::Corrupted);
+            }
+            (header, cipher)
+        } else if db_option.read_only {
+            // an empty db cannot even get a header
+            return Result::Err(PagerError::ReadOnly);
+        } else {
+            Pager::persist_db_options(storage.as_mut(), &db_option)?
+        };
+
         let num_pages = match file_size {
             0 => 0,
-            _ => ((file_size - DB_HEADER_SIZE as u64) / (db_option.page_size as u64)) as usize,
+            _ => (file_size - DB_HEADER_SIZE as u64) / (Pager::slot_size(&db_option) as u64),
         };
-        Pager {
-            file: RefCell::new(file),
-            pages: RefCell::new(HashMap::new()),
+        if num_pages > MAX_NUM_PAGES || num_pages > usize::MAX as u64 {
+            return Result::Err(PagerError::Corrupted);
+        }
+        let num_pages = num_pages as usize;
+        let free_pages = Pager::read_free_list(
+            storage.as_mut(),
+            &db_option,
+            cipher.as_ref(),
+            &header,
+            num_pages,
+        )?;
+        let in_use_on_disk = header.has_flag(DB_FLAG_IN_USE);
+        let row_count_exact =
+            header.format_version >= DB_ROW_COUNT_FORMAT_VERSION && !in_use_on_disk;
+        if !db_option.read_only {
+            // reaches the disk with the first commit changing the header
+            header.flags |= DB_FLAG_IN_USE;
+        }
+        Result::Ok(Pager {
+            storage: Mutex::new(storage),
+            pages: Mutex::new(HashMap::new()),
+            pins: Arc::new(Mutex::new(HashMap::new())),
             num_pages: num_pages,
             db_option: db_option,
+            header,
+            cipher,
+            path: journal_path.as_ref().map(|_| path.to_owned()),
+            journal_path,
+            journal: None,
+            dirty_pages: BTreeSet::new(),
+            num_committed_pages: num_pages,
+            free_pages,
+            header_dirty: false,
+            row_count_exact,
+            in_use_on_disk,
+            partially_flushed: false,
+            rollback_failed: false,
+            closed: false,
+            savepoints: Vec::new(),
+            unsynced: AtomicBool::new(false),
+            stats: Mutex::new(PagerStats::default()),
+            #[cfg(test)]
+            commit_trace: Mutex::new(Vec::new()),
+        })
+    }
+
+    #[cfg(all(feature = "file", feature = "mmap"))]
+    fn file_storage(file: File, use_mmap: bool) -> io::Result<Box<dyn Storage>> {
+        if use_mmap {
+            Result::Ok(Box::new(MmapStorage::new(file)?))
+        } else {
+            Result::Ok(Box::new(FileStorage::new(file)))
+        }
+    }
+
+    #[cfg(all(feature = "file", not(feature = "mmap")))]
+    fn file_storage(file: File, use_mmap: bool) -> io::Result<Box<dyn Storage>> {
+        if use_mmap {
+            warn!("rdb is built without the mmap feature, fall back to file io.");
         }
+        Result::Ok(Box::new(FileStorage::new(file)))
     }
 
-    fn is_db_corrupted(file_size: u64, page_size: usize) -> bool {
+    fn is_db_corrupted(file_size: u64, slot_size: usize) -> bool {
         file_size < DB_HEADER_SIZE as u64
-            || (file_size - DB_HEADER_SIZE as u64) % (page_size as u64) != 0
-    }
-
-    fn persist_db_options(file: &mut File, db_option: &DbOption) {
-        // write database header
-        let mut header_buf = vec![0u8; DB_HEADER_SIZE];
-        let page_size = db_option.page_size as u32;
-        BigEndian::write_u32(
-            header_buf.index_mut(RangeFrom {
-                start: DB_PAGE_SIZE_OFFSET,
-            }),
-            page_size,
-        );
-        file.write_all(header_buf.as_mut_slice()).unwrap();
+            || !(file_size - DB_HEADER_SIZE as u64).is_multiple_of(slot_size as u64)
+    }
+
+    /// the space a page takes in the db file.
+    fn slot_size(db_option: &DbOption) -> usize {
+        let mut slot_size = db_option.page_size;
+        if db_option.compression {
+            slot_size = compression::slot_size(slot_size);
+        }
+        if db_option.encryption_key.is_some() {
+            slot_size = encryption::slot_size(slot_size);
+        }
+        slot_size
+    }
+
+    /// read a page from the storage, decrypting and decompressing it if needed.
+    fn read_page(
+        storage: &mut dyn Storage,
+        db_option: &DbOption,
+        cipher: Option<&Cipher>,
+        page_index: usize,
+    ) -> Result<Page, PagerError> {
+        let slot_size = Pager::slot_size(db_option);
+        let offset = Pager::page_offset(db_option, page_index);
+        // a mapped db file is decoded in place, other storages are read into a buffer
+        let mut slot = match storage.page_slice(offset, slot_size) {
+            Some(bytes) => Cow::Borrowed(bytes),
+            None => {
+                let mut buf = vec![0u8; slot_size];
+                storage.read_page(offset, &mut buf).map_err(|e| match e.kind() {
+                    ErrorKind::UnexpectedEof => PagerError::TruncatedPage(page_index),
+                    _ => PagerError::Io(format!("cannot read page {}: {}", page_index, e)),
+                })?;
+                Cow::Owned(buf)
+            }
+        };
+        if let Some(cipher) = cipher {
+            slot = Cow::Owned(cipher.decrypt_page(page_index, &slot)?);
+        }
+        if db_option.compression {
+            let mut page = Page::new_page(db_option.page_size);
+            if !compression::decompress_page(&slot, &mut page) {
+                return Result::Err(PagerError::CorruptedPage(page_index));
+            }
+            slot = Cow::Owned(page);
+        }
+        Result::Ok(slot.into_owned())
+    }
+
+    /// the bytes of a page as stored in the db file.
+    fn encode_page(&self, page_index: usize, page: &Page) -> Vec<u8> {
+        let buf = if self.db_option.compression {
+            compression::compress_page(page)
+        } else {
+            page.clone()
+        };
+        match self.cipher {
+            Some(ref cipher) => cipher.encrypt_page(page_index, &buf),
+            None => buf,
+        }
+    }
+
+    /// returns the header written and the cipher of an encrypted db.
+    fn persist_db_options(
+        storage: &mut dyn Storage,
+        db_option: &DbOption,
+    ) -> Result<(DbHeader, Option<Cipher>), PagerError> {
+        let mut header = DbHeader::new(db_option.page_size);
+        if db_option.compression {
+            header.flags |= DB_FLAG_COMPRESSION;
+        }
+        let cipher = match db_option.encryption_key {
+            Some(ref key) => {
+                header.flags |= DB_FLAG_ENCRYPTION;
+                header.key_salt = Cipher::random_salt()?;
+                let cipher = Cipher::new(key, &header.key_salt)?;
+                header.key_check = cipher.key_check();
+                Some(cipher)
+            }
+            None => None,
+        };
+        storage
+            .write_page(0, &header.serialize())
+            .map_err(|e| PagerError::Io(format!("cannot write db header: {}", e)))?;
+        Result::Ok((header, cipher))
+    }
+
+    fn read_header(storage: &mut dyn Storage) -> Result<DbHeader, PagerError> {
+        let available = cmp::min(storage.len(), DB_HEADER_SIZE as u64) as usize;
+        let mut header_buf = vec![0u8; available];
+        storage
+            .read_page(0, &mut header_buf)
+            .map_err(|e| PagerError::Io(format!("cannot read db header: {}", e)))?;
+        DbHeader::deserialize(&header_buf)
+    }
+
+    /// override given options with the ones of the db file, returns the cipher
+    /// of an encrypted db.
+    fn read_db_options(
+        header: &DbHeader,
+        db_option: &mut DbOption,
+    ) -> Result<Option<Cipher>, PagerError> {
+        db_option.page_size = header.page_size;
+        db_option.compression = header.has_flag(DB_FLAG_COMPRESSION);
+        if !header.has_flag(DB_FLAG_ENCRYPTION) {
+            db_option.encryption_key = None;
+            return Result::Ok(None);
+        }
+        match db_option.encryption_key {
+            Some(ref key) => {
+                encryption::open_cipher(key, &header.key_salt, &header.key_check).map(Some)
+            }
+            None => Result::Err(PagerError::KeyRequired),
+        }
+    }
+
+    /// walk the free list chained from the db header.
+    fn read_free_list(
+        storage: &mut dyn Storage,
+        db_option: &DbOption,
+        cipher: Option<&Cipher>,
+        header: &DbHeader,
+        num_pages: usize,
+    ) -> Result<Vec<usize>, PagerError> {
+        let mut page_index = header.free_list_head;
+        let count = header.free_list_count;
+
+        let mut free_pages = Vec::with_capacity(count);
+        let mut seen = HashSet::with_capacity(count);
+        for _ in 0..count {
+            if page_index == 0 || page_index >= num_pages || !seen.insert(page_index) {
+                return Result::Err(PagerError::Corrupted);
+            }
+            free_pages.push(page_index);
+            let page = Pager::read_page(storage, db_option, cipher, page_index)?;
+            page_index = BigEndian::read_u32(&page[0..4]) as usize;
+        }
+        free_pages.reverse();
+        Result::Ok(free_pages)
     }
 
-    fn read_db_options(file: &mut File, db_option: &mut DbOption) {
-        // read db options from file and override given options
-        let mut header_buf = vec![0u8; DB_HEADER_SIZE];
-        file.read(header_buf.as_mut_slice()).unwrap();
-        let page_size = BigEndian::read_u32(header_buf.index(RangeFrom {
-            start: DB_PAGE_SIZE_OFFSET,
-        })) as usize;
-        db_option.page_size = page_size;
+    fn write_header(&mut self) -> io::Result<()> {
+        self.header.free_list_head = self.free_pages.last().cloned().unwrap_or(0);
+        self.header.free_list_count = self.free_pages.len();
+        lock(&self.storage)
+            .write_page(0, &self.header.serialize())?;
+        self.in_use_on_disk = self.header.has_flag(DB_FLAG_IN_USE);
+        Result::Ok(())
     }
 
     pub fn get_page_size(&self) -> usize {
         self.db_option.page_size
     }
 
-    pub fn next_page_index(&self) -> usize {
-        self.num_pages
+    pub fn get_path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    pub fn get_db_option(&self) -> DbOption {
+        self.db_option.clone()
+    }
+
+    /// keep up to given pages in the cache, 0 for no limit. a smaller cache drops
+    /// its pages as others are loaded.
+    pub fn set_cache_pages(&mut self, cache_pages: usize) {
+        self.db_option.cache_pages = cache_pages;
+    }
+
+    pub fn get_format_version(&self) -> u32 {
+        self.header.format_version
+    }
+
+    /// pages changed by the current transaction.
+    pub fn num_dirty_pages(&self) -> usize {
+        self.dirty_pages.len()
+    }
+
+    /// changes that a crash of the machine may lose, uncommitted or not synced yet.
+    pub fn has_unsaved_changes(&self) -> bool {
+        !self.dirty_pages.is_empty() || self.header_dirty || self.unsynced.load(Ordering::SeqCst)
+    }
+
+    pub fn num_free_pages(&self) -> usize {
+        self.free_pages.len()
+    }
+
+    /// the number of rows as maintained by the table, approximate unless
+    /// `row_count_is_exact`.
+    pub fn row_count(&self) -> u64 {
+        self.header.row_count
+    }
+
+    /// false if the db was not closed cleanly or predates the row count, in
+    /// which case only a full scan gives the right count.
+    pub fn row_count_is_exact(&self) -> bool {
+        self.row_count_exact
+    }
+
+    /// count rows added or removed by the current transaction.
+    pub fn adjust_row_count(&mut self, delta: i64) {
+        let row_count = self.header.row_count as i64 + delta;
+        self.header.row_count = row_count.max(0) as u64;
+        self.header_dirty = true;
+    }
+
+    /// replace the row count with the one of a full scan.
+    pub fn set_row_count(&mut self, row_count: u64) {
+        self.header.row_count = row_count;
+        self.row_count_exact = true;
+        self.header_dirty = true;
+    }
+
+    /// the root page of the catalog of the created tables, 0 for a db without any.
+    pub fn catalog_page(&self) -> usize {
+        self.header.catalog_page
+    }
+
+    pub fn set_catalog_page(&mut self, page_index: usize) {
+        self.header.catalog_page = page_index;
+        self.header_dirty = true;
+    }
+
+    pub fn stats(&self) -> PagerStats {
+        *lock(&self.stats)
+    }
+
+    pub fn reset_stats(&self) {
+        lock(&self.stats).reset();
+    }
+
+    /// get a zeroed page for writing, pages on the free list are reused first.
+    pub fn allocate_page(&mut self) -> Result<usize, PagerError> {
+        let page_index = match self.free_pages.pop() {
+            Some(page_index) => {
+                self.header_dirty = true;
+                lock(&self.stats).pages_allocated += 1;
+                let rc_page = self.page_for_write(page_index)?;
+                for b in rc_page.borrow_mut().iter_mut() {
+                    *b = 0;
+                }
+                page_index
+            }
+            None => {
+                let page_index = self.num_pages;
+                if page_index as u64 >= MAX_NUM_PAGES {
+                    return Result::Err(PagerError::TooManyPages);
+                }
+                self.page_for_write(page_index)?;
+                page_index
+            }
+        };
+        Result::Ok(page_index)
+    }
+
+    /// put a page onto the free list, it must not be referenced anymore. the header
+    /// page and pages past the end of the db are refused as corruption, they come
+    /// from a broken page reference.
+    pub fn free_page(&mut self, page_index: usize) -> Result<(), PagerError> {
+        if page_index == 0 || page_index >= self.num_pages {
+            return Result::Err(PagerError::Corrupted);
+        }
+        let head = self.free_pages.last().cloned().unwrap_or(0);
+        let rc_page = self.page_for_write(page_index)?;
+        {
+            let mut page = rc_page.borrow_mut();
+            for b in page.iter_mut() {
+                *b = 0;
+            }
+            BigEndian::write_u32(&mut page[0..4], head as u32);
+        }
+        self.free_pages.push(page_index);
+        self.header_dirty = true;
+        Result::Ok(())
+    }
+
+    /// offsets are computed in u64, they go beyond 4GiB even on 32-bit targets.
+    fn page_offset(db_option: &DbOption, page_index: usize) -> u64 {
+        page_index as u64 * Pager::slot_size(db_option) as u64 + DB_HEADER_SIZE as u64
     }
 
     fn page_offset_in_file(&self, page_index: usize) -> u64 {
-        (page_index * self.get_page_size() + DB_HEADER_SIZE) as u64
+        Pager::page_offset(&self.db_option, page_index)
     }
 
-    pub fn flush(self: &mut Pager, page_index: usize) {
+    fn flush(&self, page_index: usize) -> io::Result<()> {
         let offset = self.page_offset_in_file(page_index);
-        if let Some(page) = self.pages.borrow().get(&page_index) {
-            let mut file = self.file.borrow_mut();
-            file.seek(SeekFrom::Start(offset as u64)).unwrap();
-            file.write_all(&page.borrow()).unwrap();
+        if let Some(page) = lock(&self.pages).get(&page_index) {
+            let buf = self.encode_page(page_index, &page.borrow());
+            lock(&self.storage).write_page(offset, &buf)?;
+            lock(&self.stats).pages_written += 1;
         }
+        Result::Ok(())
     }
 
-    fn load(&self, page_index: usize) {
-        let offset = self.page_offset_in_file(page_index);
-        let mut buf = vec![0; self.get_page_size()];
+    /// sync the db file to disk.
+    pub fn sync(&self) -> Result<(), PagerError> {
+        lock(&self.storage)
+            .sync()
+            .map_err(|e| PagerError::Io(format!("cannot sync db file: {}", e)))?;
+        self.unsynced.store(false, Ordering::SeqCst);
+        self.trace("sync db");
+        Result::Ok(())
+    }
+
+    /**
+     * commit the current transaction:
+     * journal written and synced -> pages written -> db synced -> journal invalidated.
+     * syncs are skipped unless the sync mode is `OnCommit`.
+     * it stops at the first failed write, pages still dirty are written by the next
+     * call, or the partially written transaction is restored by `rollback`.
+     **/
+    pub fn flush_all(&mut self) -> Result<FlushReport, PagerError> {
+        if self.dirty_pages.is_empty() && !self.header_dirty && self.journal.is_none() {
+            return Result::Ok(FlushReport { pages_written: 0 });
+        }
+        if self.db_option.read_only {
+            return Result::Err(PagerError::ReadOnly);
+        }
+        if self.rollback_failed {
+            return Result::Err(PagerError::Io(
+                "cannot write the db file, it was not rolled back yet.".to_owned(),
+            ));
+        }
+        if self.header_dirty {
+            // the journal keeps the db header as well
+            self.create_journal()?;
+        }
+        self.write_journal()
+            .map_err(|e| PagerError::Io(format!("cannot write journal: {}", e)))?;
+        self.unsynced.store(true, Ordering::SeqCst);
+        let pages_written = self.write_dirty_pages()?;
+        let write_failed = |msg: String| PagerError::WriteFailed { pages_written, msg };
+        if self.header_dirty {
+            self.write_header()
+                .map_err(|e| write_failed(format!("cannot write db header: {}", e)))?;
+            self.header_dirty = false;
+            self.partially_flushed = true;
+        }
+        if self.db_option.sync_mode == SyncMode::OnCommit {
+            self.sync().map_err(|e| write_failed(format!("{}", e)))?;
+        }
+        self.finish_commit()
+            .map_err(|e| write_failed(format!("cannot delete journal: {}", e)))?;
+        Result::Ok(FlushReport { pages_written })
+    }
+
+    /// commit and sync regardless of the sync mode.
+    pub fn sync_all(&mut self) -> Result<FlushReport, PagerError> {
+        let report = self.flush_all()?;
+        self.sync()?;
+        Result::Ok(report)
+    }
+
+    /// flush all changes and release the db file, errors are reported unlike a drop.
+    pub fn close(mut self) -> Result<(), PagerError> {
+        self.closed = true;
+        self.flush_before_close().map(|_| ())
+    }
+
+    fn flush_before_close(&mut self) -> Result<FlushReport, PagerError> {
+        if !self.savepoints.is_empty() {
+            warn!(
+                "roll back the transaction of savepoint {}, it was never released.",
+                self.savepoints[0].name
+            );
+            self.rollback()?;
+        }
+        // a stale row count keeps the flag until it's corrected
+        if self.row_count_exact && !self.db_option.read_only {
+            self.header.flags &= !DB_FLAG_IN_USE;
+            self.header_dirty |= self.in_use_on_disk;
+        }
+        let report = self.flush_all()?;
+        if self.db_option.sync_mode == SyncMode::OnClose {
+            self.sync()?;
+        }
+        Result::Ok(report)
+    }
+
+    /// drop the pager without flushing anything, as if the process was killed.
+    #[cfg(test)]
+    pub fn simulate_crash(mut self) {
+        self.closed = true;
+    }
+
+    /// discard all changes of the current transaction. a db file that cannot be
+    /// restored keeps its journal, nothing is written until another rollback
+    /// restores it or the next open rolls it back.
+    pub fn rollback(&mut self) -> Result<(), PagerError> {
+        if self.partially_flushed {
+            // the db file has to be restored from the journal, cached pages may be stale
+            lock(&self.pages).clear();
+            if let Some(journal) = self.journal.take() {
+                journal.close();
+            }
+            if let Result::Err(e) = self.restore_db_file() {
+                self.rollback_failed = true;
+                return Result::Err(PagerError::Io(format!("cannot roll back the db file: {}", e)));
+            }
+            self.rollback_failed = false;
+            self.partially_flushed = false;
+            self.header_dirty = true;
+        } else {
+            let mut pages = lock(&self.pages);
+            for page_index in &self.dirty_pages {
+                pages.remove(page_index);
+            }
+        }
+        self.dirty_pages.clear();
+        self.savepoints.clear();
+        self.num_pages = self.num_committed_pages;
+        if let Some(journal) = self.journal.take() {
+            // the db file is untouched, a journal left behind does no harm
+            let _ = journal.delete();
+        }
+        if self.header_dirty {
+            self.header_dirty = false;
+            let num_pages = self.num_pages;
+            let mut storage = lock(&self.storage);
+            self.header = Pager::read_header(storage.as_mut())?;
+            self.in_use_on_disk = self.header.has_flag(DB_FLAG_IN_USE);
+            if !self.db_option.read_only {
+                self.header.flags |= DB_FLAG_IN_USE;
+            }
+            self.free_pages = Pager::read_free_list(
+                storage.as_mut(),
+                &self.db_option,
+                self.cipher.as_ref(),
+                &self.header,
+                num_pages,
+            )?;
+        }
+        Result::Ok(())
+    }
+
+    /// replay the journal left by a partial flush into the db file, without a journal
+    /// only appended pages or extents reached the db file.
+    fn restore_db_file(&mut self) -> io::Result<()> {
+        let mut storage = lock(&self.storage);
+        let rolled_back = match self.journal_path {
+            Some(ref path) => Journal::rollback(path, storage.as_mut())?,
+            None => false,
+        };
+        if !rolled_back {
+            storage.set_len(self.page_offset_in_file(self.num_committed_pages))?;
+        }
+        Result::Ok(())
+    }
+
+    /// remember the state of the transaction under given name, changes made from
+    /// now on can be undone by `rollback_to_savepoint`.
+    pub fn savepoint(&mut self, name: &str) {
+        self.savepoints.push(Savepoint {
+            name: name.to_owned(),
+            pages: HashMap::new(),
+            num_pages: self.num_pages,
+            header: self.header.clone(),
+            header_dirty: self.header_dirty,
+            free_pages: self.free_pages.clone(),
+        });
+    }
+
+    pub fn num_savepoints(&self) -> usize {
+        self.savepoints.len()
+    }
+
+    /// the position of the innermost savepoint with given name.
+    pub fn find_savepoint(&self, name: &str) -> Option<usize> {
+        self.savepoints.iter().rposition(|savepoint| savepoint.name == name)
+    }
+
+    /// forget the savepoint at given position and the ones set after it, their
+    /// changes stay part of the transaction.
+    pub fn release_savepoint(&mut self, index: usize) {
+        let released = self.savepoints.split_off(index);
+        if let Some(outer) = self.savepoints.last_mut() {
+            // the outer savepoint keeps the oldest copy of a page
+            for savepoint in released {
+                for (page_index, page) in savepoint.pages {
+                    if page_index < outer.num_pages {
+                        outer.pages.entry(page_index).or_insert(page);
+                    }
+                }
+            }
+        }
+    }
+
+    /// undo the changes made since the savepoint at given position, which stays set
+    /// while the ones set after it are gone.
+    pub fn rollback_to_savepoint(&mut self, index: usize) {
+        let mut name = String::new();
+        while self.savepoints.len() > index {
+            let savepoint = self.savepoints.pop().unwrap();
+            self.restore(&savepoint);
+            name = savepoint.name;
+        }
+        self.savepoint(&name);
+    }
+
+    fn restore(&mut self, savepoint: &Savepoint) {
         {
-            let mut file = self.file.borrow_mut();
-            file.seek(SeekFrom::Start(offset as u64)).unwrap();
-            file.read(buf.as_mut_slice()).unwrap();
+            let mut pages = lock(&self.pages);
+            for (page_index, page) in &savepoint.pages {
+                // dirty pages are never evicted
+                *pages[page_index].borrow_mut() = page.clone();
+            }
+            pages.retain(|page_index, _| *page_index < savepoint.num_pages);
         }
-        self.pages
-            .borrow_mut()
-            .insert(page_index, Rc::new(RefCell::new(buf)));
+        self.dirty_pages
+            .retain(|page_index| *page_index < savepoint.num_pages);
+        self.num_pages = savepoint.num_pages;
+        self.header = savepoint.header.clone();
+        self.header_dirty = savepoint.header_dirty;
+        self.free_pages = savepoint.free_pages.clone();
     }
 
-    pub fn page_for_read(self: &Pager, page_index: usize) -> Rc<RefCell<Page>> {
-        if page_index >= self.num_pages {
-            panic!("read EOF");
-        } else if !self.pages.borrow().contains_key(&page_index) {
-            self.load(page_index);
+    /// keep the content of a page before the first change after the savepoint.
+    fn save_for_savepoint(&mut self, page_index: usize) {
+        if let Some(savepoint) = self.savepoints.last_mut() {
+            if page_index < savepoint.num_pages && !savepoint.pages.contains_key(&page_index) {
+                let page = lock(&self.pages)[&page_index].borrow().clone();
+                savepoint.pages.insert(page_index, page);
+            }
         }
-        self.pages.borrow().get(&page_index).unwrap().clone()
     }
 
-    pub fn page_for_write(self: &mut Pager, page_index: usize) -> Rc<RefCell<Page>> {
-        if page_index > self.num_pages {
-            panic!("skipped write to a page");
-        } else if page_index == self.num_pages {
-            // need a new page
-            let new_page = Rc::new(RefCell::new(Page::new_page(self.get_page_size())));
-            self.pages.borrow_mut().insert(page_index, new_page);
-            self.num_pages += 1;
-        } else if !self.pages.borrow().contains_key(&page_index) {
-            // load page from file
-            self.load(page_index);
+    fn write_journal(&mut self) -> io::Result<()> {
+        let mut journal = match self.journal.take() {
+            Some(journal) => journal,
+            None => return Result::Ok(()),
+        };
+        let result = self.seal_journal(&mut journal);
+        self.journal = Some(journal);
+        result
+    }
+
+    fn seal_journal(&self, journal: &mut Journal) -> io::Result<()> {
+        let sync = self.db_option.sync_mode == SyncMode::OnCommit;
+        if sync {
+            journal.sync()?;
+            self.trace("sync journal records");
         }
-        self.pages.borrow().get(&page_index).unwrap().clone()
+        journal.write_header()?;
+        self.trace("write journal header");
+        if sync {
+            journal.sync()?;
+            self.trace("sync journal");
+        }
+        Result::Ok(())
+    }
+
+    /// write dirty pages in order, a page is clean once it reaches the db file.
+    fn write_dirty_pages(&mut self) -> Result<usize, PagerError> {
+        let mut pages_written = 0;
+        if self.reserve_extents().map_err(|e| PagerError::WriteFailed {
+            pages_written,
+            msg: format!("cannot grow db file: {}", e),
+        })? {
+            self.partially_flushed = true;
+        }
+        while let Some(page_index) = self.dirty_pages.iter().next().cloned() {
+            self.flush(page_index).map_err(|e| PagerError::WriteFailed {
+                pages_written,
+                msg: format!("cannot write page {}: {}", page_index, e),
+            })?;
+            self.dirty_pages.remove(&page_index);
+            self.partially_flushed = true;
+            pages_written += 1;
+            self.trace("write page");
+        }
+        self.trim_extents().map_err(|e| PagerError::WriteFailed {
+            pages_written,
+            msg: format!("cannot trim db file: {}", e),
+        })?;
+        Result::Ok(pages_written)
+    }
+
+    /// grow the db file by whole extents up front instead of page by page,
+    /// returns true if the file was grown.
+    fn reserve_extents(&self) -> io::Result<bool> {
+        let extent_size = self.db_option.extent_size;
+        let mut storage = lock(&self.storage);
+        if extent_size <= 1 || storage.len() >= self.page_offset_in_file(self.num_pages) {
+            return Result::Ok(false);
+        }
+        let num_slots = self.num_pages.div_ceil(extent_size) * extent_size;
+        storage.set_len(self.page_offset_in_file(num_slots))?;
+        Result::Ok(true)
+    }
+
+    /// slots past the last page are not part of the db.
+    fn trim_extents(&self) -> io::Result<()> {
+        let len = self.page_offset_in_file(self.num_pages);
+        let mut storage = lock(&self.storage);
+        if self.db_option.extent_size > 1 && storage.len() > len {
+            storage.set_len(len)?;
+        }
+        Result::Ok(())
+    }
+
+    fn finish_commit(&mut self) -> io::Result<()> {
+        if let Some(journal) = self.journal.take() {
+            journal.delete()?;
+            self.trace("delete journal");
+        }
+        self.partially_flushed = false;
+        self.num_committed_pages = self.num_pages;
+        self.savepoints.clear();
+        Result::Ok(())
+    }
+
+    #[cfg(test)]
+    fn trace(&self, event: &'static str) {
+        lock(&self.commit_trace).push(event);
+    }
+
+    #[cfg(not(test))]
+    fn trace(&self, _event: &'static str) {}
+
+    fn create_journal(&mut self) -> Result<(), PagerError> {
+        // read-only pagers never get to write the changes the journal is for
+        if self.journal.is_some() || self.db_option.read_only {
+            return Result::Ok(());
+        }
+        if let Some(ref path) = self.journal_path {
+            let mut db_header = vec![0u8; DB_HEADER_SIZE];
+            lock(&self.storage)
+                .read_page(0, &mut db_header)
+                .map_err(|e| PagerError::Io(format!("cannot read db header: {}", e)))?;
+            // journal records are kept in the db file format
+            let journal = Journal::create(
+                path,
+                Pager::slot_size(&self.db_option),
+                self.num_committed_pages,
+                &db_header,
+            ).map_err(|e| PagerError::Io(format!("cannot create journal: {}", e)))?;
+            self.journal = Some(journal);
+        }
+        Result::Ok(())
+    }
+
+    /// save the original content of a committed page before its first modification.
+    fn journal_page(&mut self, page_index: usize) -> Result<(), PagerError> {
+        if page_index >= self.num_committed_pages || self.dirty_pages.contains(&page_index) {
+            return Result::Ok(());
+        }
+        self.create_journal()?;
+        let page = lock(&self.pages).get(&page_index).unwrap().clone();
+        let buf = self.encode_page(page_index, &page.borrow());
+        if let Some(ref mut journal) = self.journal {
+            journal
+                .append(page_index, &buf)
+                .map_err(|e| PagerError::Io(format!("cannot write journal: {}", e)))?;
+        }
+        Result::Ok(())
+    }
+
+    /// pages past the committed ones live only in the cache until they are written,
+    /// they are never read from the db file.
+    fn load(&self, page_index: usize) -> Result<(), PagerError> {
+        let buf = if page_index >= self.num_committed_pages {
+            Page::new_page(self.get_page_size())
+        } else {
+            Pager::read_page(
+                lock(&self.storage).as_mut(),
+                &self.db_option,
+                self.cipher.as_ref(),
+                page_index,
+            )?
+        };
+        lock(&self.stats).cache_misses += 1;
+        lock(&self.pages)
+            .insert(page_index, Arc::new(PageCell::new(buf)));
+        self.evict(page_index);
+        Result::Ok(())
+    }
+
+    /// drop clean and unpinned pages until the cache fits `cache_pages` again,
+    /// the cache overflows while too many pages are pinned or dirty.
+    fn evict(&self, loaded_page_index: usize) {
+        let cache_pages = self.db_option.cache_pages;
+        let mut pages = lock(&self.pages);
+        if cache_pages == 0 || pages.len() <= cache_pages {
+            return;
+        }
+        let evictable: Vec<usize> = {
+            let pins = lock(&self.pins);
+            pages
+                .keys()
+                .filter(|&page_index| {
+                    *page_index != loaded_page_index
+                        && !self.dirty_pages.contains(page_index)
+                        && !pins.contains_key(page_index)
+                })
+                .cloned()
+                .take(pages.len() - cache_pages)
+                .collect()
+        };
+        lock(&self.stats).evictions += evictable.len() as u64;
+        for page_index in evictable {
+            pages.remove(&page_index);
+        }
+        if pages.len() > cache_pages {
+            warn!(
+                "page cache holds {} pages, over its budget of {}, too many pages are pinned or dirty.",
+                pages.len(),
+                cache_pages
+            );
+        }
+    }
+
+    /// read the committed pages straight from the db file in file order, bypassing
+    /// the cache, so pages the tree lost track of can be looked at as well.
+    pub fn scan_pages(&self) -> PageScan<'_> {
+        PageScan {
+            pager: self,
+            page_index: 0,
+        }
+    }
+
+    /// keep a page in the cache until `unpin`, pins are counted.
+    pub fn pin(&self, page_index: usize) {
+        *lock(&self.pins).entry(page_index).or_insert(0) += 1;
+    }
+
+    pub fn unpin(&self, page_index: usize) {
+        unpin(&self.pins, page_index);
+    }
+
+    /// a page read from the cache, or from the db file if it is not cached. reading
+    /// past the pages of the db or a free page is a bug of the caller, the free list
+    /// is only searched in debug builds.
+    pub fn page_for_read(self: &Pager, page_index: usize) -> Result<PageGuard, PagerError> {
+        if page_index >= self.num_pages {
+            panic!("read EOF");
+        } else if cfg!(debug_assertions) && self.free_pages.contains(&page_index) {
+            panic!("read free page {}", page_index);
+        } else if lock(&self.pages).contains_key(&page_index) {
+            lock(&self.stats).cache_hits += 1;
+        } else {
+            self.load(page_index)?;
+        }
+        Result::Ok(self.guard(page_index))
+    }
+
+    /// a page to change, the page right after the last one is a new page.
+    pub fn page_for_write(self: &mut Pager, page_index: usize) -> Result<PageGuard, PagerError> {
+        if page_index > self.num_pages {
+            panic!("skipped write to a page");
+        } else if page_index == self.num_pages {
+            // need a new page
+            let new_page = Arc::new(PageCell::new(Page::new_page(self.get_page_size())));
+            lock(&self.pages).insert(page_index, new_page);
+            self.num_pages += 1;
+            lock(&self.stats).pages_allocated += 1;
+        } else if lock(&self.pages).contains_key(&page_index) {
+            lock(&self.stats).cache_hits += 1;
+        } else {
+            // load page from file
+            self.load(page_index)?;
+        }
+        self.save_for_savepoint(page_index);
+        self.journal_page(page_index)?;
+        self.dirty_pages.insert(page_index);
+        Result::Ok(self.guard(page_index))
+    }
+
+    fn guard(&self, page_index: usize) -> PageGuard {
+        let page = lock(&self.pages).get(&page_index).unwrap().clone();
+        self.pin(page_index);
+        PageGuard::new(page_index, page, &self.pins)
+    }
+}
+
+impl Drop for Pager {
+    /// best-effort flush of a pager that was not closed, failures can only be logged.
+    fn drop(&mut self) {
+        if self.closed {
+            return;
+        }
+        match panic::catch_unwind(AssertUnwindSafe(|| self.flush_before_close())) {
+            Result::Ok(Result::Ok(_)) => {}
+            Result::Ok(Result::Err(e)) => error!("cannot flush pages on drop: {}", e),
+            Result::Err(_) => error!("cannot flush pages on drop: flush panicked"),
+        }
+    }
+}
+
+// the tests work on db files
+#[cfg(all(test, feature = "file"))]
+mod tests {
+    use super::*;
+    use header::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::env;
+    use std::fs::{self, OpenOptions};
+    use std::io::{Seek, SeekFrom, Write};
+    use std::path::Path;
+    use std::process;
+
+    fn db_path(name: &str) -> String {
+        let path = env::temp_dir().join(format!("rdb-pager-{}-{}.rdb", name, process::id()));
+        let path = path.to_str().unwrap().to_owned();
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(journal_path(&path));
+        path
+    }
+
+    fn open_with_sync_mode(path: &str, sync_mode: SyncMode) -> Pager {
+        try_open(path, sync_mode).unwrap()
+    }
+
+    fn try_open(path: &str, sync_mode: SyncMode) -> Result<Pager, PagerError> {
+        open_with(path, |db_option| db_option.sync_mode = sync_mode)
+    }
+
+    /// open with 512 bytes pages and no sync, tweaked by `configure`.
+    fn open_with<F: FnOnce(&mut DbOption)>(path: &str, configure: F) -> Result<Pager, PagerError> {
+        let mut db_option = DbOption {
+            page_size: 512,
+            sync_mode: SyncMode::Off,
+            ..Default::default()
+        };
+        configure(&mut db_option);
+        Pager::new(path, db_option)
+    }
+
+    fn open(path: &str) -> Pager {
+        open_with_sync_mode(path, SyncMode::Off)
+    }
+
+    fn write_byte(pager: &mut Pager, page_index: usize, value: u8) {
+        let rc_page = pager.page_for_write(page_index).unwrap();
+        rc_page.borrow_mut()[0] = value;
+    }
+
+    fn read_byte(pager: &Pager, page_index: usize) -> u8 {
+        pager.page_for_read(page_index).unwrap().borrow()[0]
+    }
+
+    /// two committed pages with content 1 and 2.
+    fn prepare_db(name: &str) -> String {
+        let path = db_path(name);
+        let mut pager = open(&path);
+        write_byte(&mut pager, 0, 1);
+        write_byte(&mut pager, 1, 2);
+        pager.flush_all().unwrap();
+        path
+    }
+
+    #[test]
+    fn committed_pages_survive_reopen() {
+        let path = prepare_db("commit");
+        let pager = open(&path);
+        assert_eq!(pager.num_pages, 2);
+        assert_eq!(read_byte(&pager, 0), 1);
+        assert_eq!(read_byte(&pager, 1), 2);
+        assert!(!Path::new(&journal_path(&path)).exists());
+    }
+
+    #[test]
+    fn rollback_discards_uncommitted_changes() {
+        let path = prepare_db("rollback");
+        let mut pager = open(&path);
+        write_byte(&mut pager, 0, 42);
+        write_byte(&mut pager, 2, 42);
+        pager.rollback().unwrap();
+        assert_eq!(pager.num_pages, 2);
+        assert_eq!(read_byte(&pager, 0), 1);
+        assert!(!Path::new(&journal_path(&path)).exists());
+    }
+
+    #[test]
+    fn changes_are_unsaved_until_synced() {
+        let path = prepare_db("unsaved");
+        {
+            let mut pager = open_with_sync_mode(&path, SyncMode::OnClose);
+            assert!(!pager.has_unsaved_changes());
+            write_byte(&mut pager, 1, 42);
+            assert!(pager.has_unsaved_changes());
+            pager.flush_all().unwrap();
+            assert!(pager.has_unsaved_changes());
+            pager.sync_all().unwrap();
+            assert!(!pager.has_unsaved_changes());
+        }
+
+        let mut pager = open_with_sync_mode(&path, SyncMode::OnCommit);
+        write_byte(&mut pager, 1, 43);
+        pager.flush_all().unwrap();
+        assert!(!pager.has_unsaved_changes());
+    }
+
+    #[test]
+    fn crash_between_journal_and_db_write_is_rolled_back() {
+        let path = prepare_db("crash-after-journal");
+        {
+            let mut pager = open(&path);
+            write_byte(&mut pager, 0, 42);
+            write_byte(&mut pager, 2, 42);
+            pager.write_journal().unwrap();
+            // simulate a crash after only part of the pages reached the db file
+            pager.flush(2).unwrap();
+            pager.flush(0).unwrap();
+            pager.simulate_crash();
+        }
+        assert!(Path::new(&journal_path(&path)).exists());
+
+        let pager = open(&path);
+        assert_eq!(pager.num_pages, 2);
+        assert_eq!(read_byte(&pager, 0), 1);
+        assert_eq!(read_byte(&pager, 1), 2);
+        assert!(!Path::new(&journal_path(&path)).exists());
+    }
+
+    #[test]
+    fn crash_before_journal_is_sealed_leaves_db_untouched() {
+        let path = prepare_db("crash-before-seal");
+        {
+            let mut pager = open(&path);
+            write_byte(&mut pager, 1, 42);
+            pager.simulate_crash();
+        }
+        assert!(Path::new(&journal_path(&path)).exists());
+
+        let pager = open(&path);
+        assert_eq!(pager.num_pages, 2);
+        assert_eq!(read_byte(&pager, 1), 2);
+        assert!(!Path::new(&journal_path(&path)).exists());
+    }
+
+    /// a sealed journal for pages 0 and 1 set to 42 and a new page 2, the db file untouched.
+    fn prepare_sealed_journal(name: &str) -> (String, Vec<u8>) {
+        let path = prepare_db(name);
+        {
+            let mut pager = open(&path);
+            write_page(&mut pager, 0, &vec![42u8; 512]);
+            write_page(&mut pager, 1, &vec![42u8; 512]);
+            write_page(&mut pager, 2, &vec![42u8; 512]);
+            pager.write_journal().unwrap();
+            pager.simulate_crash();
+        }
+        let journal = fs::read(journal_path(&path)).unwrap();
+        (path, journal)
+    }
+
+    fn assert_original_db(path: &str) {
+        let pager = open(path);
+        assert_eq!(pager.num_pages, 2);
+        assert_eq!(read_byte(&pager, 0), 1);
+        assert_eq!(*pager.page_for_read(1).unwrap().borrow(), {
+            let mut page = vec![0u8; 512];
+            page[0] = 2;
+            page
+        });
+        assert!(!Path::new(&journal_path(path)).exists());
+    }
+
+    #[test]
+    fn torn_page_writes_are_restored_from_the_journal() {
+        let (path, _) = prepare_sealed_journal("torn-page");
+        {
+            // crash after page 0, in the middle of page 1, and while extending the file with page 2
+            let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+            file.seek(SeekFrom::Start(DB_HEADER_SIZE as u64)).unwrap();
+            file.write_all(&[42u8; 2 * 512 + 256]).unwrap();
+        }
+        assert_original_db(&path);
+    }
+
+    #[test]
+    fn db_opens_cleanly_with_a_journal_truncated_at_any_point() {
+        let (path, journal) = prepare_sealed_journal("truncated-journal");
+        for &cut in &[0, 10, 24, 124, 130, 124 + 8 + 512, journal.len() - 1] {
+            fs::write(journal_path(&path), &journal[..cut]).unwrap();
+            assert_original_db(&path);
+        }
+    }
+
+    #[test]
+    fn journal_records_failing_their_checksums_are_skipped() {
+        let (path, mut journal) = prepare_sealed_journal("torn-record");
+        // tear the record of page 1, the last one, and let both pages reach the db file
+        let last = journal.len() - 1;
+        journal[last] ^= 0xff;
+        fs::write(journal_path(&path), &journal).unwrap();
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(DB_HEADER_SIZE as u64)).unwrap();
+        file.write_all(&[42u8; 1024]).unwrap();
+        drop(file);
+
+        let pager = open(&path);
+        assert_eq!(pager.num_pages, 2);
+        assert_eq!(read_byte(&pager, 0), 1);
+        assert_eq!(read_byte(&pager, 1), 42);
+        assert!(!Path::new(&journal_path(&path)).exists());
+    }
+
+    #[test]
+    fn commit_syncs_journal_before_writing_pages() {
+        let path = prepare_db("commit-order");
+        let mut pager = open_with_sync_mode(&path, SyncMode::OnCommit);
+        write_byte(&mut pager, 0, 42);
+        write_byte(&mut pager, 2, 42);
+        pager.flush_all().unwrap();
+        assert_eq!(
+            *lock(&pager.commit_trace),
+            vec![
+                "sync journal records",
+                "write journal header",
+                "sync journal",
+                "write page",
+                "write page",
+                "sync db",
+                "delete journal",
+            ]
+        );
+    }
+
+    #[test]
+    fn commit_without_sync_skips_all_syncs() {
+        let path = prepare_db("commit-no-sync");
+        let mut pager = open(&path);
+        write_byte(&mut pager, 1, 42);
+        pager.flush_all().unwrap();
+        assert_eq!(
+            *lock(&pager.commit_trace),
+            vec!["write journal header", "write page", "delete journal"]
+        );
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mmap_backend_writes_identical_bytes() {
+        let write_db = |name: &str, use_mmap: bool| {
+            let path = db_path(name);
+            {
+                let mut pager = Pager::new(
+                    &path,
+                    DbOption {
+                        page_size: 512,
+                        use_mmap,
+                        ..Default::default()
+                    },
+                ).unwrap();
+                for page_index in 0..5 {
+                    write_byte(&mut pager, page_index, page_index as u8 + 1);
+                }
+                pager.flush_all().unwrap();
+                write_byte(&mut pager, 2, 42);
+                pager.flush_all().unwrap();
+                assert_eq!(read_byte(&pager, 2), 42);
+            }
+            fs::read(&path).unwrap()
+        };
+
+        let file_bytes = write_db("bytes-file", false);
+        let mmap_bytes = write_db("bytes-mmap", true);
+        assert_eq!(file_bytes.len(), DB_HEADER_SIZE + 5 * 512);
+        assert_eq!(file_bytes, mmap_bytes);
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mmap_backend_hands_out_slices_of_the_mapping() {
+        let path = prepare_db("mmap-slices");
+        let file = OpenOptions::new().read(true).write(true).open(&path).unwrap();
+        let mut storage = MmapStorage::new(file).unwrap();
+        let offset = (DB_HEADER_SIZE + 512) as u64;
+        let mut buf = vec![0u8; 512];
+        storage.read_page(offset, &mut buf).unwrap();
+        assert_eq!(storage.page_slice(offset, 512), Some(&buf[..]));
+        assert_eq!(storage.page_slice(offset + 1, 512 * 2), None);
+
+        // a file opened only for reading cannot be mapped for writes, an error not a panic
+        let file = OpenOptions::new().read(true).open(&path).unwrap();
+        assert!(MmapStorage::new(file).is_err());
+    }
+
+    fn open_error(path: &str) -> PagerError {
+        match try_open(path, SyncMode::Off) {
+            Result::Ok(_) => panic!("db should not be opened"),
+            Result::Err(msg) => msg,
+        }
+    }
+
+    #[test]
+    fn new_db_is_stamped_with_magic_and_format_version() {
+        let path = prepare_db("header");
+        let bytes = fs::read(&path).unwrap();
+        assert!(bytes.starts_with(DB_MAGIC));
+        assert_eq!(
+            BigEndian::read_u32(&bytes[DB_VERSION_OFFSET..]),
+            DB_FORMAT_VERSION
+        );
+        assert_eq!(open(&path).get_format_version(), DB_FORMAT_VERSION);
+    }
+
+    #[test]
+    fn refuses_to_open_a_text_file() {
+        let path = db_path("text");
+        let mut text = String::new();
+        for i in 0..100 {
+            text.push_str(&format!("line {}\n", i));
+        }
+        fs::write(&path, text).unwrap();
+        assert_eq!(open_error(&path), PagerError::NotADatabase);
+    }
+
+    #[test]
+    fn refuses_to_open_a_truncated_header() {
+        let path = prepare_db("truncated");
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..DB_HEADER_SIZE / 2]).unwrap();
+        assert_eq!(open_error(&path), PagerError::Corrupted);
+    }
+
+    #[test]
+    fn refuses_to_open_a_future_format_version() {
+        let path = prepare_db("future-version");
+        let mut bytes = fs::read(&path).unwrap();
+        BigEndian::write_u32(&mut bytes[DB_VERSION_OFFSET..], DB_FORMAT_VERSION + 1);
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(
+            open_error(&path),
+            PagerError::UnsupportedVersion(DB_FORMAT_VERSION + 1)
+        );
+    }
+
+    #[test]
+    fn refuses_invalid_page_sizes() {
+        for &page_size in &[0, 64, 256, 1000, 4097, 131072] {
+            let path = db_path("invalid-page-size");
+            let result = Pager::new(
+                &path,
+                DbOption {
+                    page_size,
+                    sync_mode: SyncMode::Off,
+                    ..Default::default()
+                },
+            );
+            match result {
+                Result::Ok(_) => panic!("page size {} should be refused", page_size),
+                Result::Err(e) => assert_eq!(e, PagerError::InvalidPageSize(page_size)),
+            }
+        }
+        for &page_size in &[512, 1024, 4096, 65536] {
+            assert_eq!(validate_page_size(page_size), Result::Ok(()));
+        }
+    }
+
+    #[test]
+    fn page_size_in_header_wins_over_option() {
+        let path = prepare_db("header-page-size");
+        let pager = Pager::new(
+            &path,
+            DbOption {
+                sync_mode: SyncMode::Off,
+                ..Default::default()
+            },
+        ).unwrap();
+        assert_eq!(pager.get_page_size(), 512);
+    }
+
+    #[test]
+    fn refuses_invalid_page_size_in_header() {
+        let path = prepare_db("header-invalid-page-size");
+        let mut bytes = fs::read(&path).unwrap();
+        BigEndian::write_u32(&mut bytes[DB_PAGE_SIZE_OFFSET..], 0);
+        let checksum = header::checksum(&bytes);
+        BigEndian::write_u32(&mut bytes[DB_HEADER_CHECKSUM_OFFSET..], checksum);
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(open_error(&path), PagerError::Corrupted);
+    }
+
+    #[test]
+    fn refuses_a_header_with_a_flipped_byte() {
+        let path = prepare_db("header-checksum");
+        let bytes = fs::read(&path).unwrap();
+        // the magic and the version are checked before the checksum
+        for offset in DB_PAGE_SIZE_OFFSET..DB_HEADER_SIZE {
+            let mut bytes = bytes.clone();
+            bytes[offset] ^= 0x10;
+            fs::write(&path, &bytes).unwrap();
+            assert_eq!(open_error(&path), PagerError::CorruptedHeader, "offset {}", offset);
+        }
+    }
+
+    #[test]
+    fn headers_before_format_version_3_are_not_checksummed() {
+        let path = prepare_db_with_free_pages("header-v2");
+        let mut bytes = fs::read(&path).unwrap();
+        BigEndian::write_u32(&mut bytes[DB_VERSION_OFFSET..], 2);
+        BigEndian::write_u32(&mut bytes[DB_HEADER_CHECKSUM_OFFSET..], 0);
+        fs::write(&path, &bytes).unwrap();
+        {
+            let mut pager = open(&path);
+            assert_eq!(pager.get_format_version(), 2);
+            assert_eq!(pager.num_free_pages(), 2);
+            pager.allocate_page().unwrap();
+            pager.close().unwrap();
+        }
+        assert_eq!(open(&path).num_free_pages(), 1);
+    }
+
+    #[test]
+    fn a_locked_db_is_opened_once_it_is_released_within_the_busy_timeout() {
+        let path = prepare_db("busy");
+        let pager = open(&path);
+        let released = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            drop(pager);
+        });
+        let pager = open_with(&path, |db_option| {
+            db_option.busy_timeout = Duration::from_secs(10)
+        });
+        assert_eq!(pager.unwrap().num_pages, 2);
+        released.join().unwrap();
+
+        let _pager = open(&path);
+        let busy = open_with(&path, |db_option| {
+            db_option.busy_timeout = Duration::from_millis(30)
+        });
+        assert_eq!(busy.err(), Some(PagerError::Busy(Duration::from_millis(30))));
+    }
+
+    #[test]
+    fn waiting_for_a_lock_can_be_cancelled() {
+        let cancel = AtomicBool::new(true);
+        let waited = retry_while_locked(Duration::from_secs(10), Some(&cancel), || {
+            Result::Err::<(), _>(PagerError::Locked)
+        });
+        assert_eq!(waited, Result::Err(PagerError::Interrupted));
+        let no_wait = retry_while_locked(Duration::ZERO, None, || {
+            Result::Err::<(), _>(PagerError::Locked)
+        });
+        assert_eq!(no_wait, Result::Err(PagerError::Locked));
+    }
+
+    #[test]
+    fn second_open_of_a_locked_db_fails() {
+        let path = prepare_db("locked");
+        let pager = open(&path);
+        assert_eq!(open_error(&path), PagerError::Locked);
+
+        drop(pager);
+        assert_eq!(open(&path).num_pages, 2);
+    }
+
+    #[test]
+    fn on_close_mode_syncs_only_on_close_or_sync_all() {
+        let path = prepare_db("on-close");
+        {
+            let mut pager = open_with_sync_mode(&path, SyncMode::OnClose);
+            write_byte(&mut pager, 0, 42);
+            pager.flush_all().unwrap();
+            write_byte(&mut pager, 1, 43);
+            pager.sync_all().unwrap();
+            assert_eq!(
+                *lock(&pager.commit_trace),
+                vec![
+                    "write journal header",
+                    "write page",
+                    "delete journal",
+                    "write journal header",
+                    "write page",
+                    "delete journal",
+                    "sync db",
+                ]
+            );
+            // a change that never got synced, the process dies without closing
+            write_byte(&mut pager, 2, 44);
+            pager.simulate_crash();
+        }
+
+        let pager = open(&path);
+        assert_eq!(pager.num_pages, 2);
+        assert_eq!(read_byte(&pager, 0), 42);
+        assert_eq!(read_byte(&pager, 1), 43);
+    }
+
+    /// four committed pages, page 1 and 2 are freed.
+    fn prepare_db_with_free_pages(name: &str) -> String {
+        let path = prepare_db(name);
+        let mut pager = open(&path);
+        write_byte(&mut pager, 2, 3);
+        write_byte(&mut pager, 3, 4);
+        pager.flush_all().unwrap();
+        pager.free_page(1).unwrap();
+        pager.free_page(2).unwrap();
+        pager.flush_all().unwrap();
+        path
+    }
+
+    #[test]
+    fn free_pages_are_reused_after_reopen() {
+        let path = prepare_db_with_free_pages("free-list-reopen");
+        let file_size = fs::metadata(&path).unwrap().len();
+
+        let mut pager = open(&path);
+        assert_eq!(pager.num_free_pages(), 2);
+        assert_eq!(pager.allocate_page(), Result::Ok(2));
+        assert_eq!(pager.allocate_page(), Result::Ok(1));
+        assert_eq!(read_byte(&pager, 1), 0);
+        assert_eq!(pager.allocate_page(), Result::Ok(4));
+        pager.rollback().unwrap();
+
+        assert_eq!(pager.num_free_pages(), 2);
+        assert_eq!(pager.allocate_page(), Result::Ok(2));
+        write_byte(&mut pager, 2, 42);
+        pager.flush_all().unwrap();
+        drop(pager);
+
+        assert_eq!(fs::metadata(&path).unwrap().len(), file_size);
+        let pager = open(&path);
+        assert_eq!(pager.num_free_pages(), 1);
+        assert_eq!(read_byte(&pager, 2), 42);
+        assert_eq!(read_byte(&pager, 3), 4);
+    }
+
+    #[test]
+    fn a_free_list_with_a_loop_is_corrupted() {
+        let path = prepare_db_with_free_pages("free-list-loop");
+        let page_size = open(&path).get_page_size();
+        let mut bytes = fs::read(&path).unwrap();
+        // page 2 is the head, it links to itself instead of to page 1
+        BigEndian::write_u32(&mut bytes[DB_HEADER_SIZE + 2 * page_size..], 2);
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(open_error(&path), PagerError::Corrupted);
+    }
+
+    #[test]
+    fn freeing_the_header_page_or_past_the_end_is_corrupted() {
+        let path = prepare_db("free-out-of-range");
+        let mut pager = open(&path);
+        assert_eq!(pager.free_page(0), Result::Err(PagerError::Corrupted));
+        assert_eq!(pager.free_page(2), Result::Err(PagerError::Corrupted));
+        assert_eq!(pager.num_free_pages(), 0);
+    }
+
+    #[test]
+    fn crash_after_freeing_pages_restores_the_free_list() {
+        let path = prepare_db("free-list-crash");
+        {
+            let mut pager = open(&path);
+            pager.free_page(1).unwrap();
+            pager.create_journal().unwrap();
+            pager.write_journal().unwrap();
+            pager.write_dirty_pages().unwrap();
+            pager.write_header().unwrap();
+            pager.simulate_crash();
+        }
+
+        let pager = open(&path);
+        assert_eq!(pager.num_free_pages(), 0);
+        assert_eq!(read_byte(&pager, 1), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "read free page 1")]
+    fn free_pages_cannot_be_read() {
+        let path = prepare_db_with_free_pages("free-list-read");
+        let pager = open(&path);
+        let _ = pager.page_for_read(1);
+    }
+
+    fn open_compressed(path: &str) -> Pager {
+        Pager::new(
+            path,
+            DbOption {
+                page_size: 512,
+                sync_mode: SyncMode::Off,
+                compression: true,
+                ..Default::default()
+            },
+        ).unwrap()
+    }
+
+    fn repeated_strings_page() -> Page {
+        b"hello rdb, ".iter().cloned().cycle().take(512).collect()
+    }
+
+    /// xorshift bytes, they don't compress.
+    fn random_page() -> Page {
+        let mut state = 0x2545_f491u32;
+        (0..512)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn write_page(pager: &mut Pager, page_index: usize, content: &Page) {
+        let rc_page = pager.page_for_write(page_index).unwrap();
+        rc_page.borrow_mut().copy_from_slice(content);
+    }
+
+    #[test]
+    fn compressed_pages_round_trip() {
+        let path = db_path("compressed");
+        {
+            let mut pager = open_compressed(&path);
+            write_page(&mut pager, 0, &repeated_strings_page());
+            write_page(&mut pager, 1, &random_page());
+            pager.flush_all().unwrap();
+        }
+
+        let slot_size = compression::slot_size(512);
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(bytes.len(), DB_HEADER_SIZE + 2 * slot_size);
+        assert!(compression::is_compressed(&bytes[DB_HEADER_SIZE..]));
+        assert!(!compression::is_compressed(&bytes[DB_HEADER_SIZE + slot_size..]));
+
+        // the flag in the header wins over the option
+        let pager = open(&path);
+        assert_eq!(*pager.page_for_read(0).unwrap().borrow(), repeated_strings_page());
+        assert_eq!(*pager.page_for_read(1).unwrap().borrow(), random_page());
+    }
+
+    #[test]
+    fn compressed_db_is_rolled_back_with_journal() {
+        let path = db_path("compressed-crash");
+        {
+            let mut pager = open_compressed(&path);
+            write_page(&mut pager, 0, &repeated_strings_page());
+            write_page(&mut pager, 1, &random_page());
+            pager.flush_all().unwrap();
+        }
+        {
+            let mut pager = open_compressed(&path);
+            write_page(&mut pager, 0, &random_page());
+            write_page(&mut pager, 1, &repeated_strings_page());
+            pager.write_journal().unwrap();
+            // simulate a crash after the pages reached the db file
+            pager.write_dirty_pages().unwrap();
+            pager.simulate_crash();
+        }
+        assert!(Path::new(&journal_path(&path)).exists());
+
+        let pager = open_compressed(&path);
+        assert_eq!(*pager.page_for_read(0).unwrap().borrow(), repeated_strings_page());
+        assert_eq!(*pager.page_for_read(1).unwrap().borrow(), random_page());
+    }
+
+    fn open_with_key(path: &str, key: Option<&str>) -> Result<Pager, PagerError> {
+        Pager::new(
+            path,
+            DbOption {
+                page_size: 512,
+                sync_mode: SyncMode::Off,
+                encryption_key: key.map(|key| key.to_owned()),
+                ..Default::default()
+            },
+        )
+    }
+
+    #[cfg(feature = "encryption")]
+    fn prepare_encrypted_db(name: &str) -> String {
+        let path = db_path(name);
+        let mut pager = open_with_key(&path, Some("secret")).unwrap();
+        write_page(&mut pager, 0, &repeated_strings_page());
+        write_page(&mut pager, 1, &random_page());
+        pager.flush_all().unwrap();
+        path
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn encrypted_db_opens_with_the_right_key() {
+        let path = prepare_encrypted_db("encrypted");
+        let bytes = fs::read(&path).unwrap();
+        assert!(!bytes.windows(11).any(|w| w == b"hello rdb, "));
+
+        let pager = open_with_key(&path, Some("secret")).unwrap();
+        assert_eq!(pager.num_pages, 2);
+        assert_eq!(*pager.page_for_read(0).unwrap().borrow(), repeated_strings_page());
+        assert_eq!(*pager.page_for_read(1).unwrap().borrow(), random_page());
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn encrypted_db_refuses_a_wrong_or_missing_key() {
+        let path = prepare_encrypted_db("encrypted-key");
+        assert_eq!(
+            open_with_key(&path, Some("guess")).err(),
+            Some(PagerError::InvalidKey)
+        );
+        assert_eq!(open_with_key(&path, None).err(), Some(PagerError::KeyRequired));
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn tampered_page_fails_authentication() {
+        let path = prepare_encrypted_db("encrypted-tampered");
+        let mut bytes = fs::read(&path).unwrap();
+        let slot_size = encryption::slot_size(512);
+        bytes[DB_HEADER_SIZE + slot_size + 100] ^= 1;
+        fs::write(&path, &bytes).unwrap();
+
+        let pager = open_with_key(&path, Some("secret")).unwrap();
+        assert_eq!(pager.page_for_read(1).err(), Some(PagerError::PageAuthentication(1)));
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    #[test]
+    fn encryption_requires_the_feature() {
+        let path = db_path("encrypted-unsupported");
+        assert_eq!(
+            open_with_key(&path, Some("secret")).err(),
+            Some(PagerError::EncryptionUnsupported)
+        );
+    }
+
+    #[test]
+    fn stats_count_allocated_and_written_pages() {
+        let path = db_path("stats");
+        let mut pager = open(&path);
+        write_byte(&mut pager, 0, 1);
+        write_byte(&mut pager, 1, 2);
+        write_byte(&mut pager, 1, 3);
+        pager.flush_all().unwrap();
+        let stats = pager.stats();
+        assert_eq!(stats.pages_allocated, 2);
+        assert_eq!(stats.pages_written, 2);
+        assert_eq!(stats.cache_hits, 1);
+        assert_eq!(stats.cache_misses, 0);
+
+        pager.reset_stats();
+        assert_eq!(pager.stats(), PagerStats::default());
+    }
+
+    /// eight committed pages, page i starts with i.
+    fn prepare_db_with_pages(name: &str) -> String {
+        let path = db_path(name);
+        let mut pager = open(&path);
+        for page_index in 0..8 {
+            write_byte(&mut pager, page_index, page_index as u8);
+        }
+        pager.close().unwrap();
+        path
+    }
+
+    #[test]
+    fn cache_is_bounded_by_cache_pages() {
+        let path = prepare_db_with_pages("cache-pages");
+        let pager = open_with(&path, |db_option| db_option.cache_pages = 3).unwrap();
+        for page_index in 0..8 {
+            assert_eq!(read_byte(&pager, page_index), page_index as u8);
+            assert!(lock(&pager.pages).len() <= 3);
+        }
+        assert_eq!(pager.stats().evictions, 5);
+        // evicted pages are loaded again
+        let evicted = (0..8).find(|i| !lock(&pager.pages).contains_key(i)).unwrap();
+        assert_eq!(read_byte(&pager, evicted), evicted as u8);
+        assert_eq!(pager.stats().cache_misses, 9);
+    }
+
+    #[test]
+    fn pages_in_use_or_dirty_are_not_evicted() {
+        let path = prepare_db_with_pages("cache-pages-in-use");
+        let mut pager = open_with(&path, |db_option| db_option.cache_pages = 2).unwrap();
+        let held = pager.page_for_read(0).unwrap();
+        write_byte(&mut pager, 1, 42);
+        for page_index in 2..8 {
+            read_byte(&pager, page_index);
+        }
+        assert!(lock(&pager.pages).contains_key(&0));
+        assert!(lock(&pager.pages).contains_key(&1));
+        drop(held);
+        pager.close().unwrap();
+
+        let pager = open(&path);
+        assert_eq!(read_byte(&pager, 1), 42);
+    }
+
+    #[test]
+    fn pinned_pages_survive_eviction_until_unpinned() {
+        let path = prepare_db_with_pages("pinned-pages");
+        let pager = open_with(&path, |db_option| db_option.cache_pages = 2).unwrap();
+        pager.pin(0);
+        read_byte(&pager, 0);
+        for page_index in 1..8 {
+            read_byte(&pager, page_index);
+        }
+        assert!(lock(&pager.pages).contains_key(&0));
+
+        pager.unpin(0);
+        // page 0 is the only page left to evict
+        let held = pager.page_for_read(7).unwrap();
+        read_byte(&pager, 1);
+        assert!(!lock(&pager.pages).contains_key(&0));
+        drop(held);
+        assert!(lock(&pager.pins).is_empty());
+    }
+
+    #[test]
+    fn guards_beyond_the_cache_budget_overflow_it() {
+        let path = prepare_db_with_pages("pinned-overflow");
+        let pager = open_with(&path, |db_option| db_option.cache_pages = 2).unwrap();
+        let guards: Vec<PageGuard> = (0..4).map(|i| pager.page_for_read(i).unwrap()).collect();
+        assert_eq!(lock(&pager.pages).len(), 4);
+        for (page_index, guard) in guards.iter().enumerate() {
+            assert_eq!(guard.borrow()[0], page_index as u8);
+        }
+
+        drop(guards);
+        read_byte(&pager, 4);
+        assert_eq!(lock(&pager.pages).len(), 2);
+    }
+
+    #[test]
+    fn read_only_pager_refuses_writes() {
+        let path = prepare_db("read-only");
+        let mut pager = open_with(&path, |db_option| db_option.read_only = true).unwrap();
+        assert_eq!(read_byte(&pager, 1), 2);
+        // readers share the lock, writers are kept out
+        assert!(open_with(&path, |db_option| db_option.read_only = true).is_ok());
+        assert_eq!(try_open(&path, SyncMode::Off).err(), Some(PagerError::Locked));
+
+        write_byte(&mut pager, 1, 42);
+        assert_eq!(pager.flush_all(), Result::Err(PagerError::ReadOnly));
+        assert!(!Path::new(&journal_path(&path)).exists());
+        pager.rollback().unwrap();
+        assert_eq!(read_byte(&pager, 1), 2);
+        pager.close().unwrap();
+
+        let missing = db_path("read-only-missing");
+        assert!(open_with(&missing, |db_option| db_option.read_only = true).is_err());
+        assert!(!Path::new(&missing).exists());
+    }
+
+    #[test]
+    fn db_file_grows_by_extents_and_is_trimmed_on_commit() {
+        let path = prepare_db("extents");
+        let writes_left = Arc::new(AtomicUsize::new(10));
+        let mut pager = open_failing(&path, &writes_left);
+        pager.db_option.extent_size = 4;
+        for page_index in 2..5 {
+            write_byte(&mut pager, page_index, 7);
+        }
+        writes_left.store(1, Ordering::SeqCst);
+        assert!(pager.flush_all().is_err());
+        // the whole extent was reserved before the first page got written
+        let slot_size = 512;
+        let extent_len = DB_HEADER_SIZE as u64 + 8 * slot_size;
+        assert_eq!(lock(&pager.storage).len(), extent_len);
+
+        pager.rollback().unwrap();
+        assert_eq!(lock(&pager.storage).len(), DB_HEADER_SIZE as u64 + 2 * slot_size);
+
+        writes_left.store(10, Ordering::SeqCst);
+        for page_index in 2..5 {
+            write_byte(&mut pager, page_index, 7);
+        }
+        pager.flush_all().unwrap();
+        assert_eq!(lock(&pager.storage).len(), DB_HEADER_SIZE as u64 + 5 * slot_size);
+    }
+
+    #[test]
+    fn refuses_to_open_a_truncated_last_page() {
+        let path = prepare_db("truncated-page");
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 100]).unwrap();
+        assert_eq!(open_error(&path), PagerError::Corrupted);
+    }
+
+    #[test]
+    fn reading_a_page_truncated_after_open_is_an_error() {
+        let path = prepare_db("truncated-after-open");
+        let pager = open(&path);
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len((DB_HEADER_SIZE + 512 + 100) as u64).unwrap();
+        let error = pager.page_for_read(1).err().unwrap();
+        assert_eq!(error, PagerError::TruncatedPage(1));
+        assert_eq!(format!("{}", error), "page 1 is truncated, db file is corrupted.");
+    }
+
+    /// file storage that fails writes once its budget is used up, like a full disk.
+    struct FailingStorage {
+        inner: FileStorage,
+        writes_left: Arc<AtomicUsize>,
+    }
+
+    impl Storage for FailingStorage {
+        fn read_page(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+            self.inner.read_page(offset, buf)
+        }
+
+        fn write_page(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+            if self.writes_left.load(Ordering::SeqCst) == 0 {
+                return Result::Err(io::Error::other("no space left"));
+            }
+            self.writes_left.fetch_sub(1, Ordering::SeqCst);
+            self.inner.write_page(offset, buf)
+        }
+
+        fn len(&self) -> u64 {
+            self.inner.len()
+        }
+
+        fn set_len(&mut self, len: u64) -> io::Result<()> {
+            self.inner.set_len(len)
+        }
+
+        fn sync(&mut self) -> io::Result<()> {
+            self.inner.sync()
+        }
+    }
+
+    fn open_failing(path: &str, writes_left: &Arc<AtomicUsize>) -> Pager {
+        let file = OpenOptions::new().read(true).write(true).open(path).unwrap();
+        let storage = FailingStorage {
+            inner: FileStorage::new(file),
+            writes_left: writes_left.clone(),
+        };
+        let db_option = DbOption {
+            page_size: 512,
+            sync_mode: SyncMode::Off,
+            ..Default::default()
+        };
+        Pager::with_storage(Box::new(storage), path, Some(journal_path(path)), db_option).unwrap()
+    }
+
+    #[test]
+    fn flush_all_stops_at_the_first_failed_write_and_can_be_retried() {
+        let path = prepare_db("flush-failure");
+        let writes_left = Arc::new(AtomicUsize::new(1));
+        {
+            let mut pager = open_failing(&path, &writes_left);
+            write_byte(&mut pager, 0, 7);
+            write_byte(&mut pager, 1, 8);
+            write_byte(&mut pager, 2, 9);
+            assert_eq!(
+                pager.flush_all(),
+                Result::Err(PagerError::WriteFailed {
+                    pages_written: 1,
+                    msg: "cannot write page 1: no space left".to_owned(),
+                })
+            );
+            assert_eq!(pager.dirty_pages.iter().cloned().collect::<Vec<_>>(), vec![1, 2]);
+
+            writes_left.store(10, Ordering::SeqCst);
+            assert_eq!(pager.flush_all(), Result::Ok(FlushReport { pages_written: 2 }));
+        }
+
+        let pager = open(&path);
+        assert_eq!(pager.num_pages, 3);
+        assert_eq!(read_byte(&pager, 0), 7);
+        assert_eq!(read_byte(&pager, 1), 8);
+        assert_eq!(read_byte(&pager, 2), 9);
+        assert!(!Path::new(&journal_path(&path)).exists());
+    }
+
+    #[test]
+    fn rollback_after_a_failed_flush_restores_the_db_file() {
+        let path = prepare_db("flush-failure-rollback");
+        let writes_left = Arc::new(AtomicUsize::new(1));
+        let mut pager = open_failing(&path, &writes_left);
+        write_byte(&mut pager, 0, 42);
+        write_byte(&mut pager, 1, 42);
+        write_byte(&mut pager, 2, 42);
+        assert!(pager.flush_all().is_err());
+
+        writes_left.store(10, Ordering::SeqCst);
+        pager.rollback().unwrap();
+        assert_eq!(pager.num_pages, 2);
+        assert_eq!(read_byte(&pager, 0), 1);
+        assert_eq!(read_byte(&pager, 1), 2);
+        assert!(!Path::new(&journal_path(&path)).exists());
+        drop(pager);
+
+        let pager = open(&path);
+        assert_eq!(pager.num_pages, 2);
+        assert_eq!(read_byte(&pager, 0), 1);
+        assert_eq!(read_byte(&pager, 1), 2);
+    }
+
+    #[test]
+    fn a_page_is_not_changed_before_its_journal_record_is_written() {
+        let path = prepare_db("journal-failure");
+        let mut pager = open(&path);
+        // a directory in the way of the journal file
+        fs::create_dir(journal_path(&path)).unwrap();
+        let error = pager.page_for_write(1).err().unwrap();
+        assert!(format!("{}", error).starts_with("cannot create journal: "), "{}", error);
+        assert!(pager.dirty_pages.is_empty());
+
+        fs::remove_dir(journal_path(&path)).unwrap();
+        write_byte(&mut pager, 1, 42);
+        pager.flush_all().unwrap();
+        drop(pager);
+        assert_eq!(read_byte(&open(&path), 1), 42);
+    }
+
+    #[test]
+    fn failed_rollback_keeps_the_journal_and_can_be_retried() {
+        let path = prepare_db("failed-rollback");
+        let writes_left = Arc::new(AtomicUsize::new(1));
+        let mut pager = open_failing(&path, &writes_left);
+        write_byte(&mut pager, 0, 42);
+        write_byte(&mut pager, 1, 42);
+        assert!(pager.flush_all().is_err());
+
+        assert_eq!(
+            pager.rollback(),
+            Result::Err(PagerError::Io(
+                "cannot roll back the db file: no space left".to_owned()
+            ))
+        );
+        assert!(Path::new(&journal_path(&path)).exists());
+        assert!(pager.flush_all().is_err());
+
+        writes_left.store(10, Ordering::SeqCst);
+        pager.rollback().unwrap();
+        assert!(!Path::new(&journal_path(&path)).exists());
+        assert_eq!(read_byte(&pager, 0), 1);
+        assert_eq!(read_byte(&pager, 1), 2);
+        drop(pager);
+
+        let pager = open(&path);
+        assert_eq!(read_byte(&pager, 0), 1);
+        assert_eq!(read_byte(&pager, 1), 2);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn page_indices_beyond_the_pointer_width_are_refused() {
+        let mut pager = Pager::new(
+            MEMORY_DB,
+            DbOption {
+                page_size: 512,
+                sync_mode: SyncMode::Off,
+                ..Default::default()
+            },
+        ).unwrap();
+        pager.num_pages = MAX_NUM_PAGES as usize;
+        assert_eq!(pager.allocate_page(), Result::Err(PagerError::TooManyPages));
+    }
+
+    #[cfg(feature = "large-file-tests")]
+    #[test]
+    fn pages_beyond_4gib_are_read_and_written() {
+        let path = prepare_db("large");
+        // the first page whose offset doesn't fit in u32, the pages in between are holes
+        let page_index = (1usize << 32) / 512 + 1;
+        {
+            let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+            let offset = page_index as u64 * 512 + DB_HEADER_SIZE as u64;
+            assert!(offset > u32::MAX as u64);
+            file.set_len(offset + 512).unwrap();
+            file.seek(SeekFrom::Start(offset)).unwrap();
+            file.write_all(&[5u8; 512]).unwrap();
+        }
+
+        let mut pager = open(&path);
+        assert_eq!(pager.num_pages, page_index + 1);
+        assert_eq!(read_byte(&pager, page_index), 5);
+        assert_eq!(read_byte(&pager, page_index - 1), 0);
+        write_byte(&mut pager, page_index, 6);
+        pager.flush_all().unwrap();
+        drop(pager);
+
+        let pager = open(&path);
+        assert_eq!(read_byte(&pager, page_index), 6);
+        assert_eq!(read_byte(&pager, 1), 2);
+        drop(pager);
+        let _ = fs::remove_file(&path);
     }
 }
@@ -0,0 +1,546 @@
+//! rendering of result rows for the output modes of `.mode`.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use database::Rows;
+use sql;
+use vm::{RowSink, Value};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Mode {
+    /// `(1, 'user1', 'person1@example.com')`, one row per line with texts quoted
+    /// like sql string literals.
+    List,
+    /// values padded to aligned columns under a header. the widths are taken from
+    /// the first `COLUMN_BUFFER_ROWS` rows, later rows are written as they come and
+    /// a longer value pushes the rest of its line to the right.
+    Column,
+    /// RFC 4180, a header line first.
+    Csv,
+    /// one object per line keyed by the column names.
+    Json,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Mode, String> {
+        match s {
+            "list" => Result::Ok(Mode::List),
+            "column" => Result::Ok(Mode::Column),
+            "csv" => Result::Ok(Mode::Csv),
+            "json" => Result::Ok(Mode::Json),
+            _ => Result::Err(format!(
+                "unknown mode {}, expected list, column, csv or json.",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Mode::List => "list",
+            Mode::Column => "column",
+            Mode::Csv => "csv",
+            Mode::Json => "json",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// a `RowSink` writing rows in the given mode, `finish` must be called after the
+/// last row as column mode can only size its columns by then.
+pub struct Renderer<'a> {
+    mode: Mode,
+    out: &'a mut dyn Write,
+    columns: Vec<String>,
+    /// how NULL is shown, json always uses `null`.
+    null_value: String,
+    num_rows: usize,
+    /// rows of column mode, rendered once the widths are known.
+    buffered: Vec<Vec<String>>,
+    buffer_rows: usize,
+    /// the widths of column mode once the header is written.
+    widths: Option<Vec<usize>>,
+    /// the widths set by `.width` by position, 0 sizes a column by its values.
+    fixed_widths: Vec<usize>,
+    /// rows past it are counted but not written, 0 writes all.
+    max_rows: usize,
+}
+
+/// how many rows column mode holds back to size its columns.
+pub const COLUMN_BUFFER_ROWS: usize = 1000;
+
+/// how many rows a statement shows unless set by `.maxrows`.
+pub const DEFAULT_MAX_ROWS: usize = 10_000;
+
+/// ends values cut to the width set by `.width`.
+const TRUNCATION_MARKER: char = '…';
+
+/// what NULL looks like unless set by `.nullvalue`, the same in every mode.
+pub const DEFAULT_NULL_VALUE: &str = "NULL";
+
+fn write_error(e: io::Error) -> String {
+    format!("cannot write result: {}", e)
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(mode: Mode, out: &'a mut dyn Write) -> Renderer<'a> {
+        Renderer {
+            mode,
+            out,
+            columns: Vec::new(),
+            null_value: DEFAULT_NULL_VALUE.to_owned(),
+            num_rows: 0,
+            buffered: Vec::new(),
+            buffer_rows: COLUMN_BUFFER_ROWS,
+            widths: None,
+            fixed_widths: Vec::new(),
+            max_rows: 0,
+        }
+    }
+
+    pub fn null_value(mut self, text: &str) -> Renderer<'a> {
+        self.null_value = text.to_owned();
+        self
+    }
+
+    /// the widths of the columns in column mode, see `.width`.
+    pub fn fixed_widths(mut self, widths: &[usize]) -> Renderer<'a> {
+        self.fixed_widths = widths.to_vec();
+        self
+    }
+
+    /// write no more than given number of rows, 0 for all of them.
+    pub fn max_rows(mut self, max_rows: usize) -> Renderer<'a> {
+        self.max_rows = max_rows;
+        self
+    }
+
+    fn fixed_width(&self, column: usize) -> Option<usize> {
+        self.fixed_widths.get(column).cloned().filter(|&width| width > 0)
+    }
+
+    /// cut the values longer than the fixed width of their column.
+    fn fit(&self, values: Vec<String>) -> Vec<String> {
+        values
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| match self.fixed_width(i) {
+                Some(width) if value.chars().count() > width => {
+                    let mut cut: String = value.chars().take(width - 1).collect();
+                    cut.push(TRUNCATION_MARKER);
+                    cut
+                }
+                _ => value,
+            })
+            .collect()
+    }
+
+    fn text(&self, value: &Value) -> String {
+        match value {
+            Value::Null => self.null_value.clone(),
+            value => format!("{}", value),
+        }
+    }
+
+    fn texts(&self, values: &[Value]) -> Vec<String> {
+        values.iter().map(|value| self.text(value)).collect()
+    }
+
+    /// returns the number of rows given, including the ones past `max_rows`.
+    pub fn finish(mut self) -> Result<usize, String> {
+        if self.mode == Mode::Column && self.widths.is_none() && !self.buffered.is_empty() {
+            self.write_buffered()?;
+        }
+        Result::Ok(self.num_rows)
+    }
+
+    /// size the columns by the buffered rows and write them under the header.
+    fn write_buffered(&mut self) -> Result<(), String> {
+        let columns = self.fit(self.columns.clone());
+        let mut widths: Vec<usize> = columns.iter().map(|name| name.chars().count()).collect();
+        for row in &self.buffered {
+            for (i, value) in row.iter().enumerate() {
+                if i < widths.len() {
+                    widths[i] = widths[i].max(value.chars().count());
+                }
+            }
+        }
+        for (i, width) in widths.iter_mut().enumerate() {
+            if let Some(fixed) = self.fixed_width(i) {
+                *width = fixed;
+            }
+        }
+        let separator: Vec<String> = widths.iter().map(|&width| "-".repeat(width)).collect();
+        write_aligned(self.out, &columns, &widths)?;
+        write_aligned(self.out, &separator, &widths)?;
+        for row in self.buffered.drain(..) {
+            write_aligned(self.out, &row, &widths)?;
+        }
+        self.widths = Some(widths);
+        Result::Ok(())
+    }
+
+    fn write_csv_line(&mut self, fields: &[String]) -> Result<(), String> {
+        let fields: Vec<String> = fields.iter().map(|field| csv_quote(field)).collect();
+        write!(self.out, "{}\r\n", fields.join(",")).map_err(write_error)
+    }
+}
+
+impl<'a> RowSink for Renderer<'a> {
+    fn columns(&mut self, names: &[String]) -> Result<(), String> {
+        self.columns = names.to_vec();
+        Result::Ok(())
+    }
+
+    fn row(&mut self, values: &[Value]) -> Result<(), String> {
+        self.num_rows += 1;
+        if self.max_rows > 0 && self.num_rows > self.max_rows {
+            return Result::Ok(());
+        }
+        match self.mode {
+            Mode::List => {
+                let values: Vec<String> = values
+                    .iter()
+                    .map(|value| match value {
+                        Value::Text(text) => sql::quote(text),
+                        value => self.text(value),
+                    })
+                    .collect();
+                writeln!(self.out, "({})", values.join(", ")).map_err(write_error)
+            }
+            Mode::Column => {
+                let values = self.fit(self.texts(values));
+                match self.widths {
+                    Some(ref widths) => write_aligned(self.out, &values, widths),
+                    None => {
+                        self.buffered.push(values);
+                        if self.buffered.len() >= self.buffer_rows {
+                            self.write_buffered()?;
+                        }
+                        Result::Ok(())
+                    }
+                }
+            }
+            Mode::Csv => {
+                if self.num_rows == 1 {
+                    let columns = self.columns.clone();
+                    self.write_csv_line(&columns)?;
+                }
+                let values = self.texts(values);
+                self.write_csv_line(&values)
+            }
+            Mode::Json => {
+                let fields: Vec<String> = self
+                    .columns
+                    .iter()
+                    .zip(values.iter())
+                    .map(|(name, value)| {
+                        let value = match value {
+                            Value::Integer(v) => format!("{}", v),
+                            Value::Real(v) if v.is_finite() => format!("{:?}", v),
+                            Value::Real(_) => "null".to_owned(),
+                            Value::Boolean(v) => format!("{}", v),
+                            Value::DateTime(_) => json_quote(&value.to_string()),
+                            Value::Text(str) => json_quote(str),
+                            Value::Null => "null".to_owned(),
+                        };
+                        format!("{}:{}", json_quote(name), value)
+                    })
+                    .collect();
+                writeln!(self.out, "{{{}}}", fields.join(",")).map_err(write_error)
+            }
+        }
+    }
+}
+
+/// write the rows of a query in given mode, returns the number of rows.
+pub fn write_rows(mode: Mode, rows: &mut Rows, out: &mut dyn Write) -> Result<usize, String> {
+    let mut renderer = Renderer::new(mode, out);
+    renderer.columns(rows.columns())?;
+    for row in rows.by_ref() {
+        renderer.row(row.values())?;
+    }
+    if let Some(e) = rows.error() {
+        return Result::Err(e.into());
+    }
+    renderer.finish()
+}
+
+/// pad every value but the last to its column width.
+fn write_aligned(out: &mut dyn Write, values: &[String], widths: &[usize]) -> Result<(), String> {
+    let mut line = String::new();
+    for (i, value) in values.iter().enumerate() {
+        if i + 1 == values.len() {
+            line.push_str(value);
+        } else {
+            let width = widths.get(i).cloned().unwrap_or(0);
+            let padding = width.saturating_sub(value.chars().count());
+            line.push_str(value);
+            line.push_str(&" ".repeat(padding + 2));
+        }
+    }
+    writeln!(out, "{}", line).map_err(write_error)
+}
+
+fn csv_quote(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+fn json_quote(str: &str) -> String {
+    let mut quoted = String::with_capacity(str.len() + 2);
+    quoted.push('"');
+    for c in str.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(mode: Mode) -> String {
+        let mut out = Vec::new();
+        {
+            let mut renderer = Renderer::new(mode, &mut out);
+            let columns = vec!["id".to_owned(), "username".to_owned(), "email".to_owned()];
+            renderer.columns(&columns).unwrap();
+            renderer
+                .row(&[
+                    Value::Integer(1),
+                    Value::Text("alice".to_owned()),
+                    Value::Text("alice@example.com".to_owned()),
+                ])
+                .unwrap();
+            renderer
+                .row(&[
+                    Value::Integer(200),
+                    Value::Text("bob".to_owned()),
+                    Value::Text("bob, \"the builder\"".to_owned()),
+                ])
+                .unwrap();
+            assert_eq!(renderer.finish(), Result::Ok(2));
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn renders_list() {
+        assert_eq!(
+            render(Mode::List),
+            "(1, 'alice', 'alice@example.com')\n(200, 'bob', 'bob, \"the builder\"')\n"
+        );
+        let mut out = Vec::new();
+        {
+            let mut renderer = Renderer::new(Mode::List, &mut out);
+            renderer.columns(&["42".to_owned(), "'it''s'".to_owned()]).unwrap();
+            renderer.row(&[Value::Integer(42), Value::Text("it's".to_owned())]).unwrap();
+        }
+        assert_eq!(String::from_utf8(out).unwrap(), "(42, 'it''s')\n");
+    }
+
+    #[test]
+    fn renders_column() {
+        assert_eq!(
+            render(Mode::Column),
+            concat!(
+                "id   username  email\n",
+                "---  --------  ------------------\n",
+                "1    alice     alice@example.com\n",
+                "200  bob       bob, \"the builder\"\n",
+            )
+        );
+    }
+
+    #[test]
+    fn column_widths_come_from_the_buffered_rows() {
+        let mut out = Vec::new();
+        {
+            let mut renderer = Renderer::new(Mode::Column, &mut out);
+            renderer.buffer_rows = 2;
+            renderer.columns(&["id".to_owned(), "name".to_owned()]).unwrap();
+            for &(id, name) in &[(1, "al"), (22, "bo"), (12345, "christopher"), (4, "di")] {
+                renderer
+                    .row(&[Value::Integer(id), Value::Text(name.to_owned())])
+                    .unwrap();
+                if id == 22 {
+                    // the first rows are written once the buffer is full
+                    assert_eq!(renderer.buffered.len(), 0);
+                }
+            }
+            assert_eq!(renderer.finish(), Result::Ok(4));
+        }
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                "id  name\n",
+                "--  ----\n",
+                "1   al\n",
+                "22  bo\n",
+                "12345  christopher\n",
+                "4   di\n",
+            )
+        );
+    }
+
+    fn render_widths(fixed_widths: &[usize]) -> String {
+        let mut out = Vec::new();
+        {
+            let mut renderer = Renderer::new(Mode::Column, &mut out).fixed_widths(fixed_widths);
+            let columns = vec!["id".to_owned(), "username".to_owned(), "email".to_owned()];
+            renderer.columns(&columns).unwrap();
+            let rows = [(1, "Zoë", "zoe@example.com"), (200, "李小龍", "bruce@example.com")];
+            for &(id, name, email) in &rows {
+                renderer
+                    .row(&[
+                        Value::Integer(id),
+                        Value::Text(name.to_owned()),
+                        Value::Text(email.to_owned()),
+                    ])
+                    .unwrap();
+            }
+            renderer.finish().unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn fixed_widths_pad_and_truncate() {
+        assert_eq!(
+            render_widths(&[5, 4, 8]),
+            concat!(
+                "id     use…  email\n",
+                "-----  ----  --------\n",
+                "1      Zoë   zoe@exa…\n",
+                "200    李小龍   bruce@e…\n",
+            )
+        );
+    }
+
+    #[test]
+    fn unset_widths_fall_back_to_auto() {
+        assert_eq!(
+            render_widths(&[0, 3]),
+            concat!(
+                "id   us…  email\n",
+                "---  ---  -----------------\n",
+                "1    Zoë  zoe@example.com\n",
+                "200  李小龍  bruce@example.com\n",
+            )
+        );
+        assert_eq!(render_widths(&[]), render_widths(&[0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn rows_past_max_rows_are_counted_only() {
+        for &(mode, expected) in &[
+            (Mode::List, "(1)\n(2)\n"),
+            (Mode::Column, "n\n-\n1\n2\n"),
+            (Mode::Csv, "n\r\n1\r\n2\r\n"),
+        ] {
+            let mut out = Vec::new();
+            {
+                let mut renderer = Renderer::new(mode, &mut out).max_rows(2);
+                renderer.columns(&["n".to_owned()]).unwrap();
+                for n in 1..6 {
+                    renderer.row(&[Value::Integer(n)]).unwrap();
+                }
+                assert_eq!(renderer.finish(), Result::Ok(5));
+            }
+            assert_eq!(String::from_utf8(out).unwrap(), expected, "{}", mode);
+        }
+    }
+
+    #[test]
+    fn renders_csv() {
+        assert_eq!(
+            render(Mode::Csv),
+            concat!(
+                "id,username,email\r\n",
+                "1,alice,alice@example.com\r\n",
+                "200,bob,\"bob, \"\"the builder\"\"\"\r\n",
+            )
+        );
+    }
+
+    #[test]
+    fn renders_json() {
+        assert_eq!(
+            render(Mode::Json),
+            concat!(
+                "{\"id\":1,\"username\":\"alice\",\"email\":\"alice@example.com\"}\n",
+                "{\"id\":200,\"username\":\"bob\",\"email\":\"bob, \\\"the builder\\\"\"}\n",
+            )
+        );
+    }
+
+    fn render_null(mode: Mode, null_value: Option<&str>) -> String {
+        let mut out = Vec::new();
+        {
+            let mut renderer = Renderer::new(mode, &mut out);
+            if let Some(text) = null_value {
+                renderer = renderer.null_value(text);
+            }
+            renderer.columns(&["id".to_owned(), "email".to_owned()]).unwrap();
+            renderer.row(&[Value::Integer(1), Value::Null]).unwrap();
+            renderer.finish().unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn renders_null_as_set() {
+        assert_eq!(render_null(Mode::List, None), "(1, NULL)\n");
+        assert_eq!(render_null(Mode::List, Some("\\N")), "(1, \\N)\n");
+        assert_eq!(
+            render_null(Mode::Column, Some("")),
+            "id  email\n--  -----\n1   \n"
+        );
+        assert_eq!(render_null(Mode::Csv, Some("")), "id,email\r\n1,\r\n");
+        assert_eq!(render_null(Mode::Csv, Some("a,b")), "id,email\r\n1,\"a,b\"\r\n");
+        assert_eq!(
+            render_null(Mode::Json, Some("NULL")),
+            "{\"id\":1,\"email\":null}\n"
+        );
+    }
+
+    #[test]
+    fn nothing_is_rendered_without_rows() {
+        for &mode in &[Mode::List, Mode::Column, Mode::Csv, Mode::Json] {
+            let mut out = Vec::new();
+            {
+                let mut renderer = Renderer::new(mode, &mut out);
+                renderer.columns(&["id".to_owned()]).unwrap();
+                assert_eq!(renderer.finish(), Result::Ok(0));
+            }
+            assert!(out.is_empty(), "{}", mode);
+        }
+    }
+
+    #[test]
+    fn modes_parse_from_their_names() {
+        for &mode in &[Mode::List, Mode::Column, Mode::Csv, Mode::Json] {
+            assert_eq!(format!("{}", mode).parse::<Mode>(), Result::Ok(mode));
+        }
+        assert!("table".parse::<Mode>().is_err());
+    }
+}
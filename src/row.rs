@@ -0,0 +1,76 @@
+//! typed access to the rows of a query.
+
+use std::sync::Arc;
+
+use error::RdbError;
+use vm::{FromValue, Value};
+
+/// a result row, its values are read by column position or name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowView {
+    columns: Arc<Vec<String>>,
+    values: Vec<Value>,
+}
+
+impl RowView {
+    pub(crate) fn new(columns: Arc<Vec<String>>, values: Vec<Value>) -> RowView {
+        RowView { columns, values }
+    }
+
+    /// the value of a column converted to `T`, like `row.get::<i64, _>(0)` or
+    /// `row.get::<String, _>("email")`.
+    pub fn get<T: FromValue, I: ColumnIndex>(&self, index: I) -> Result<T, RdbError> {
+        let position = index.position(&self.columns)?;
+        let value = &self.values[position];
+        T::from_value(value).ok_or_else(|| RdbError::TypeMismatch {
+            column: self.columns[position].clone(),
+            expected: T::type_name(),
+            found: value.type_name(),
+        })
+    }
+
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    pub fn into_values(self) -> Vec<Value> {
+        self.values
+    }
+}
+
+/// picks a column of a row, by position from 0 or by name.
+pub trait ColumnIndex {
+    fn position(&self, columns: &[String]) -> Result<usize, String>;
+}
+
+impl ColumnIndex for usize {
+    fn position(&self, columns: &[String]) -> Result<usize, String> {
+        if *self < columns.len() {
+            Result::Ok(*self)
+        } else {
+            Result::Err(format!(
+                "column index {} out of range, the row has {} columns.",
+                self,
+                columns.len()
+            ))
+        }
+    }
+}
+
+impl ColumnIndex for &str {
+    fn position(&self, columns: &[String]) -> Result<usize, String> {
+        columns
+            .iter()
+            .position(|column| column == self)
+            .ok_or_else(|| format!("no such column: {}", self))
+    }
+}
+
+/// builds a value of the program out of a result row, see `Connection::query_map`.
+pub trait FromRow: Sized {
+    fn from_row(row: &RowView) -> Result<Self, RdbError>;
+}
@@ -0,0 +1,272 @@
+//! serde support, behind the `serde` feature: result rows serialize as maps of their
+//! columns, rows decode into and insert from the program's own types.
+
+use serde::de::{self, DeserializeOwned, Visitor};
+use serde::ser::{self, SerializeMap, SerializeSeq};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{self, Number};
+use std::collections::HashSet;
+use std::fmt;
+
+use database::Rows;
+use row::RowView;
+use vm::Value;
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Integer(v) => serializer.serialize_i64(*v),
+            Value::Real(v) => serializer.serialize_f64(*v),
+            Value::Boolean(v) => serializer.serialize_bool(*v),
+            Value::DateTime(_) => serializer.serialize_str(&self.to_string()),
+            Value::Text(text) => serializer.serialize_str(text),
+            Value::Null => serializer.serialize_none(),
+        }
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a number, a boolean, a string or null")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Value, E> {
+        Result::Ok(Value::Boolean(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Value, E> {
+        Result::Ok(Value::Integer(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+        if v > i64::MAX as u64 {
+            return Result::Err(E::custom(format!("integer {} out of range", v)));
+        }
+        Result::Ok(Value::Integer(v as i64))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Value, E> {
+        Result::Ok(Value::Real(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+        Result::Ok(Value::Text(v.to_owned()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Value, E> {
+        Result::Ok(Value::Text(v))
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+        Result::Ok(Value::Null)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Result::Ok(Value::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+/// rows whose columns have distinct names serialize as maps, others as sequences.
+impl Serialize for RowView {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if has_distinct_names(self.columns()) {
+            let mut map = serializer.serialize_map(Some(self.values().len()))?;
+            for (column, value) in self.columns().iter().zip(self.values()) {
+                map.serialize_entry(column, value)?;
+            }
+            map.end()
+        } else {
+            let mut seq = serializer.serialize_seq(Some(self.values().len()))?;
+            for value in self.values() {
+                seq.serialize_element(value)?;
+            }
+            seq.end()
+        }
+    }
+}
+
+/// the rows not iterated yet, as a sequence. the rest of the query is read first.
+impl Serialize for Rows {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let rows = self.remaining().map_err(ser::Error::custom)?;
+        let mut seq = serializer.serialize_seq(Some(rows.len()))?;
+        for row in rows {
+            seq.serialize_element(&row)?;
+        }
+        seq.end()
+    }
+}
+
+fn has_distinct_names(columns: &[String]) -> bool {
+    let mut names = HashSet::new();
+    columns
+        .iter()
+        .all(|column| !column.is_empty() && names.insert(column.as_str()))
+}
+
+impl RowView {
+    /// decode the row into `T`, its fields named after the columns.
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T, String> {
+        let json = serde_json::to_value(self).map_err(|e| format!("cannot decode row: {}", e))?;
+        serde_json::from_value(json).map_err(|e| format!("cannot decode row: {}", e))
+    }
+}
+
+/// the values of `row` for given columns, `row` has to serialize as a map with a
+/// field per column, missing fields are NULL.
+pub fn insert_values<T: Serialize>(
+    table: &str,
+    columns: &[&str],
+    row: &T,
+) -> Result<Vec<Value>, String> {
+    let fields = match serde_json::to_value(row) {
+        Result::Ok(serde_json::Value::Object(fields)) => fields,
+        Result::Ok(_) => return Result::Err("rows to insert must be structs or maps.".to_owned()),
+        Result::Err(e) => return Result::Err(format!("cannot insert row: {}", e)),
+    };
+    if let Some(field) = fields.keys().find(|field| !columns.contains(&field.as_str())) {
+        return Result::Err(format!("table {} has no column named {}", table, field));
+    }
+    columns
+        .iter()
+        .map(|column| match fields.get(*column) {
+            None | Some(serde_json::Value::Null) => Result::Ok(Value::Null),
+            Some(serde_json::Value::String(text)) => Result::Ok(Value::Text(text.clone())),
+            Some(serde_json::Value::Number(number)) => to_integer(column, number),
+            Some(value) => Result::Err(format!("cannot insert {} into column {}", value, column)),
+        })
+        .collect()
+}
+
+fn to_integer(column: &str, number: &Number) -> Result<Value, String> {
+    number
+        .as_i64()
+        .map(Value::Integer)
+        .ok_or_else(|| format!("cannot insert {} into column {}", number, column))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use database::Database;
+    use std::sync::Arc;
+    use storage::MEMORY_DB;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct User {
+        id: u32,
+        username: String,
+        email: Option<String>,
+    }
+
+    #[test]
+    fn values_round_trip() {
+        let values = vec![
+            Value::Integer(-7),
+            Value::Real(-0.25),
+            Value::Text("Ada".to_owned()),
+            Value::Null,
+        ];
+        let json = serde_json::to_string(&values).unwrap();
+        assert_eq!(json, r#"[-7,-0.25,"Ada",null]"#);
+        assert_eq!(serde_json::from_str::<Vec<Value>>(&json).unwrap(), values);
+        assert_eq!(serde_json::from_str::<Value>("1.5").unwrap(), Value::Real(1.5));
+    }
+
+    #[test]
+    fn rows_serialize_by_column_name_or_position() {
+        let columns = Arc::new(vec!["id".to_owned(), "email".to_owned()]);
+        let row = RowView::new(columns, vec![Value::Integer(1), Value::Null]);
+        assert_eq!(serde_json::to_string(&row).unwrap(), r#"{"id":1,"email":null}"#);
+
+        let columns = Arc::new(vec!["1".to_owned(), "1".to_owned()]);
+        let row = RowView::new(columns, vec![Value::Integer(1), Value::Integer(1)]);
+        assert_eq!(serde_json::to_string(&row).unwrap(), "[1,1]");
+    }
+
+    #[test]
+    fn structs_round_trip_through_a_table() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        let users = vec![
+            User {
+                id: 1,
+                username: "ada".to_owned(),
+                email: Some("ada@example.com".to_owned()),
+            },
+            User {
+                id: 2,
+                username: "O'Brien".to_owned(),
+                email: Some("".to_owned()),
+            },
+        ];
+        for user in &users {
+            assert_eq!(conn.insert("users", user), Result::Ok(1));
+        }
+        assert_eq!(conn.query_as::<User>("select").unwrap(), users);
+
+        let rows = conn.query("select").unwrap();
+        assert_eq!(
+            serde_json::to_string(&rows).unwrap(),
+            r#"[{"id":1,"username":"ada","email":"ada@example.com"},"#.to_owned()
+                + r#"{"id":2,"username":"O'Brien","email":""}]"#
+        );
+
+        let row = RowView::new(
+            Arc::new(vec!["id".to_owned(), "username".to_owned(), "email".to_owned()]),
+            vec![Value::Integer(3), Value::Text("bob".to_owned()), Value::Null],
+        );
+        assert_eq!(
+            row.decode::<User>().unwrap(),
+            User {
+                id: 3,
+                username: "bob".to_owned(),
+                email: None,
+            }
+        );
+    }
+
+    #[test]
+    fn inserts_check_the_fields() {
+        let db = Database::open(MEMORY_DB).unwrap();
+        let mut conn = db.connect();
+        let user = User {
+            id: 1,
+            username: "ada".to_owned(),
+            email: None,
+        };
+        assert_eq!(
+            conn.insert("users", &user),
+            Result::Err("email expects text, got NULL.".into())
+        );
+        assert_eq!(
+            conn.insert("accounts", &user),
+            Result::Err("no such table: accounts".into())
+        );
+        let mut row = serde_json::Map::new();
+        row.insert("id".to_owned(), 1.into());
+        row.insert("name".to_owned(), "ada".into());
+        assert_eq!(
+            conn.insert("users", &row),
+            Result::Err("table users has no column named name".into())
+        );
+        assert_eq!(
+            conn.insert("users", &"ada"),
+            Result::Err("rows to insert must be structs or maps.".into())
+        );
+    }
+}
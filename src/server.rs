@@ -0,0 +1,268 @@
+//! `--serve` and `--client`: a line protocol over TCP. a client sends one
+//! statement per line, the server replies with its result rows in csv or json,
+//! every line of them after `ROW `, and a last line of `OK <n> rows` or
+//! `ERR <message>`. every client has a connection of its own, see
+//! `rdb::Connection` for how their statements are serialized.
+//!
+//! the only meta command a client may send is `.mode csv|json`.
+
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+use rdb::{Connection, Database};
+
+use render::{self, Mode};
+
+const ROW_PREFIX: &str = "ROW ";
+const OK_PREFIX: &str = "OK ";
+const ERR_PREFIX: &str = "ERR ";
+
+/// accept clients until the listener fails, each one is served on a thread of its own.
+pub fn serve(listener: TcpListener, db: Arc<Database>) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let peer = stream
+            .peer_addr()
+            .map_or_else(|_| "unknown peer".to_owned(), |addr| format!("{}", addr));
+        let conn = db.connect();
+        thread::spawn(move || {
+            info!("{} connected", peer);
+            match handle_client(stream, conn) {
+                Result::Ok(()) => info!("{} disconnected", peer),
+                Result::Err(e) => warn!("{} dropped: {}", peer, e),
+            }
+        });
+    }
+    Result::Ok(())
+}
+
+/// the settings of a client, changed by `.mode`.
+struct ClientSession {
+    mode: Mode,
+}
+
+fn handle_client(stream: TcpStream, mut conn: Connection) -> io::Result<()> {
+    let mut session = ClientSession { mode: Mode::Csv };
+    let reader = BufReader::new(stream.try_clone()?);
+    let mut out = BufWriter::new(stream);
+    for line in reader.lines() {
+        let line = line?;
+        let input = line.trim();
+        if input.is_empty() {
+            continue;
+        }
+        match reply(input, &mut conn, &mut session) {
+            Result::Ok((rows, n)) => {
+                // the prefix keeps a value like `OK 1 row` from ending the reply
+                for line in rows.split_inclusive(|&c| c == b'\n') {
+                    out.write_all(ROW_PREFIX.as_bytes())?;
+                    out.write_all(line)?;
+                    if !line.ends_with(b"\n") {
+                        writeln!(out)?;
+                    }
+                }
+                writeln!(
+                    out,
+                    "{}{} {}",
+                    OK_PREFIX,
+                    n,
+                    if n == 1 { "row" } else { "rows" }
+                )?;
+            }
+            // a message of more than one line would end the reply early
+            Result::Err(msg) => writeln!(out, "{}{}", ERR_PREFIX, msg.replace('\n', " "))?,
+        }
+        out.flush()?;
+    }
+    Result::Ok(())
+}
+
+/// run a line of a client, returns the rendered rows and the number of rows
+/// inserted or returned.
+fn reply(
+    input: &str,
+    conn: &mut Connection,
+    session: &mut ClientSession,
+) -> Result<(Vec<u8>, usize), String> {
+    if input.starts_with('.') {
+        session.mode = match input.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [".mode", mode @ ("csv" | "json")] => mode.parse()?,
+            [".mode", ..] => return Result::Err("usage: .mode csv|json".to_owned()),
+            _ => {
+                return Result::Err(format!(
+                    "Unrecognized command: {}, only .mode is served.",
+                    input
+                ))
+            }
+        };
+        return Result::Ok((Vec::new(), 0));
+    }
+    let mut rows = conn.query(input)?;
+    let mut out = Vec::new();
+    render::write_rows(session.mode, &mut rows, &mut out)?;
+    Result::Ok((out, rows.changes()))
+}
+
+/// send the lines of `input` to the server at given address, the rows of the
+/// replies go to stdout and their `OK` lines to stderr. stops at the first `ERR`
+/// or a line that is none of these.
+pub fn run_client<R: BufRead>(addr: &str, input: R) -> Result<(), String> {
+    let stream =
+        TcpStream::connect(addr).map_err(|e| format!("cannot connect to {}: {}", addr, e))?;
+    let lost = |e: io::Error| format!("connection to {} lost: {}", addr, e);
+    let mut replies = BufReader::new(stream.try_clone().map_err(lost)?);
+    let mut requests = stream;
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for line in input.lines() {
+        let line = line.map_err(|e| format!("cannot read input: {}", e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        writeln!(requests, "{}", line).map_err(lost)?;
+        loop {
+            let mut reply = String::new();
+            if replies.read_line(&mut reply).map_err(lost)? == 0 {
+                return Result::Err(format!("{} closed the connection.", addr));
+            }
+            let reply = reply.trim_end_matches(['\r', '\n']);
+            if reply.starts_with(OK_PREFIX) {
+                eprintln!("{}", reply);
+                break;
+            } else if let Some(msg) = reply.strip_prefix(ERR_PREFIX) {
+                return Result::Err(msg.to_owned());
+            }
+            let row = reply
+                .strip_prefix(ROW_PREFIX)
+                .ok_or_else(|| format!("unexpected reply from {}: {}", addr, reply))?;
+            writeln!(stdout, "{}", row).map_err(|e| format!("cannot write result: {}", e))?;
+        }
+    }
+    Result::Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rdb::MEMORY_DB;
+
+    /// a server on a free port of localhost, its thread outlives the test.
+    fn start_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = format!("{}", listener.local_addr().unwrap());
+        let db = Arc::new(Database::open(MEMORY_DB).unwrap());
+        thread::spawn(move || serve(listener, db));
+        addr
+    }
+
+    struct Client {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl Client {
+        fn connect(addr: &str) -> Client {
+            let writer = TcpStream::connect(addr).unwrap();
+            Client {
+                reader: BufReader::new(writer.try_clone().unwrap()),
+                writer,
+            }
+        }
+
+        /// send a line and read the reply up to and including its last line.
+        fn send(&mut self, line: &str) -> Vec<String> {
+            writeln!(self.writer, "{}", line).unwrap();
+            let mut reply = Vec::new();
+            loop {
+                let mut line = String::new();
+                assert!(
+                    self.reader.read_line(&mut line).unwrap() > 0,
+                    "server hung up"
+                );
+                let line = line.trim_end_matches(['\r', '\n']).to_owned();
+                let last = line.starts_with(OK_PREFIX) || line.starts_with(ERR_PREFIX);
+                reply.push(line);
+                if last {
+                    return reply;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn replies_are_rows_and_a_status_line() {
+        let addr = start_server();
+        let mut client = Client::connect(&addr);
+        assert_eq!(
+            client.send("insert 1 ada ada@example.com"),
+            vec!["OK 1 row"]
+        );
+        assert_eq!(
+            client.send("insert 2 bob bob@example.com"),
+            vec!["OK 1 row"]
+        );
+        assert_eq!(
+            client.send("select"),
+            vec![
+                "ROW id,username,email",
+                "ROW 1,ada,ada@example.com",
+                "ROW 2,bob,bob@example.com",
+                "OK 2 rows",
+            ]
+        );
+        // values that read like the last line of a reply stay rows
+        assert_eq!(
+            client.send("select 'OK 5 rows' as status, 'ERR no' as error"),
+            vec!["ROW status,error", "ROW OK 5 rows,ERR no", "OK 1 row"]
+        );
+        assert_eq!(
+            client.send("insert 1 eve eve@example.com"),
+            vec!["ERR Error: Duplicate key."]
+        );
+        assert_eq!(
+            client.send("selec"),
+            vec!["ERR Unrecognized command: selec"]
+        );
+    }
+
+    #[test]
+    fn every_client_has_its_own_mode() {
+        let addr = start_server();
+        let mut json = Client::connect(&addr);
+        let mut csv = Client::connect(&addr);
+        assert_eq!(json.send(".mode json"), vec!["OK 0 rows"]);
+        assert_eq!(json.send(".mode list"), vec!["ERR usage: .mode csv|json"]);
+        assert_eq!(
+            json.send(".tables"),
+            vec!["ERR Unrecognized command: .tables, only .mode is served."]
+        );
+        assert_eq!(csv.send("insert 7 ada ada@example.com"), vec!["OK 1 row"]);
+        assert_eq!(
+            json.send("select"),
+            vec![
+                r#"ROW {"id":7,"username":"ada","email":"ada@example.com"}"#,
+                "OK 1 row",
+            ]
+        );
+        assert_eq!(
+            csv.send("select"),
+            vec!["ROW id,username,email", "ROW 7,ada,ada@example.com", "OK 1 row"]
+        );
+    }
+
+    #[test]
+    fn the_client_stops_at_the_first_error() {
+        let addr = start_server();
+        let input =
+            "insert 1 ada ada@example.com\n\nselect\ninsert 1 ada ada@example.com\nselect\n";
+        assert_eq!(
+            run_client(&addr, input.as_bytes()),
+            Result::Err("Error: Duplicate key.".to_owned())
+        );
+        assert!(run_client("127.0.0.1:1", "select\n".as_bytes())
+            .unwrap_err()
+            .starts_with("cannot connect to 127.0.0.1:1: "));
+    }
+}
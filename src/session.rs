@@ -0,0 +1,176 @@
+//! settings of a REPL session, changed by meta commands.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::Duration;
+
+use rdb::internals::catalog::Catalog;
+use render::{Mode, DEFAULT_MAX_ROWS, DEFAULT_NULL_VALUE};
+use signals::Signals;
+
+/// the prompt without its `.prompt` text.
+const DEFAULT_PROMPT: &str = "rdb";
+
+/// marks echoed input lines apart from result rows.
+const ECHO_PREFIX: &str = "> ";
+
+/// where the results of statements go, errors and prompts always go to the terminal.
+enum Sink {
+    Stdout,
+    File { path: String, writer: BufWriter<File> },
+}
+
+impl Sink {
+    /// create or truncate the file at given path.
+    fn file(path: &str) -> Result<Sink, String> {
+        File::create(path)
+            .map(|file| Sink::File {
+                path: path.to_owned(),
+                writer: BufWriter::new(file),
+            })
+            .map_err(|e| format!("cannot open {}: {}", path, e))
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Sink::Stdout => "stdout",
+            Sink::File { path, .. } => path,
+        }
+    }
+
+    fn write_with<F, T>(&mut self, f: F) -> Result<T, String>
+    where
+        F: FnOnce(&mut dyn Write) -> Result<T, String>,
+    {
+        let (result, flushed) = match self {
+            Sink::Stdout => {
+                let stdout = io::stdout();
+                let mut out = stdout.lock();
+                (f(&mut out)?, out.flush())
+            }
+            Sink::File { writer, .. } => (f(writer)?, writer.flush()),
+        };
+        flushed
+            .map(|_| result)
+            .map_err(|e| format!("cannot write to {}: {}", self.name(), e))
+    }
+}
+
+/// what a statement did, reported after it and again by `.changes`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Changes {
+    pub kind: ChangeKind,
+    pub rows: usize,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ChangeKind {
+    Inserted,
+    Returned,
+    Deleted,
+}
+
+impl fmt::Display for Changes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let verb = match self.kind {
+            ChangeKind::Inserted => "inserted",
+            ChangeKind::Returned => "returned",
+            ChangeKind::Deleted => "deleted",
+        };
+        let rows = if self.rows == 1 { "row" } else { "rows" };
+        write!(f, "{} {} {}", self.rows, rows, verb)
+    }
+}
+
+pub struct Session {
+    pub mode: Mode,
+    /// the column widths of column mode, set by `.width`.
+    pub widths: Vec<usize>,
+    /// how NULL is shown, set by `.nullvalue`.
+    pub null_value: String,
+    /// the most rows a statement shows, 0 for no limit, set by `.maxrows`.
+    pub max_rows: usize,
+    /// print the time taken by each statement.
+    pub timer: bool,
+    /// the changes of the last statement.
+    pub changes: Option<Changes>,
+    /// write each line to the output before running it.
+    pub echo: bool,
+    /// the text of the prompt, set by `.prompt`.
+    pub prompt: String,
+    output: Sink,
+    /// set by `.once`, used up by the next statement.
+    once: Option<Sink>,
+    pub signals: Signals,
+    /// how many scripts run by `.read` are running.
+    pub read_depth: usize,
+    /// the dbs attached by `attach database`.
+    pub attached: Catalog,
+    /// how long `attach database` waits for a db file locked by another process,
+    /// set by `.timeout`.
+    pub busy_timeout: Duration,
+}
+
+impl Session {
+    pub fn new() -> Session {
+        Session {
+            mode: Mode::List,
+            widths: Vec::new(),
+            null_value: DEFAULT_NULL_VALUE.to_owned(),
+            max_rows: DEFAULT_MAX_ROWS,
+            timer: false,
+            changes: None,
+            echo: false,
+            prompt: DEFAULT_PROMPT.to_owned(),
+            output: Sink::Stdout,
+            once: None,
+            signals: Signals::none(),
+            read_depth: 0,
+            attached: Catalog::new(),
+            busy_timeout: Duration::ZERO,
+        }
+    }
+
+    /// the prompt text followed by `*` while there are unsaved changes.
+    pub fn prompt(&self, unsaved: bool) -> String {
+        format!("{}{}> ", self.prompt, if unsaved { "*" } else { " " })
+    }
+
+    /// send the results to the file at given path, or back to the terminal with `stdout`.
+    pub fn set_output(&mut self, target: &str) -> Result<(), String> {
+        self.output = match target {
+            "stdout" => Sink::Stdout,
+            path => Sink::file(path)?,
+        };
+        Result::Ok(())
+    }
+
+    /// send the results of the next statement only to the file at given path.
+    pub fn set_once(&mut self, path: &str) -> Result<(), String> {
+        self.once = Some(Sink::file(path)?);
+        Result::Ok(())
+    }
+
+    /// write an input line ahead of its results, to where they will go.
+    pub fn echo_input(&mut self, line: &str) -> Result<(), String> {
+        let sink = match self.once {
+            Some(ref mut once) => once,
+            None => &mut self.output,
+        };
+        sink.write_with(|out| {
+            writeln!(out, "{}{}", ECHO_PREFIX, line).map_err(|e| format!("cannot echo: {}", e))
+        })
+    }
+
+    /// let `f` write the results of a statement to the active sink.
+    pub fn write_output<F, T>(&mut self, f: F) -> Result<T, String>
+    where
+        F: FnOnce(&mut dyn Write) -> Result<T, String>,
+    {
+        match self.once.take() {
+            Some(mut once) => once.write_with(f),
+            None => self.output.write_with(f),
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! SIGINT and SIGTERM only raise flags, the REPL and the VM check them at safe
+//! points so a statement is rolled back and the db closed properly. while the REPL
+//! waits for input everything is committed, so the signals exit right away.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::flag;
+
+/// exit status of a process killed by the signal, as shells report it.
+const SIGINT_STATUS: i32 = 128 + SIGINT;
+const SIGTERM_STATUS: i32 = 128 + SIGTERM;
+
+pub struct Signals {
+    /// set by both signals, cancels the running statement.
+    interrupted: Arc<AtomicBool>,
+    terminated: Arc<AtomicBool>,
+    /// the REPL waits for input.
+    idle: Arc<AtomicBool>,
+}
+
+impl Signals {
+    /// flags no signal will ever raise.
+    pub fn none() -> Signals {
+        Signals {
+            interrupted: Arc::new(AtomicBool::new(false)),
+            terminated: Arc::new(AtomicBool::new(false)),
+            idle: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn install() -> io::Result<Signals> {
+        let signals = Signals::none();
+        // the shutdowns go first, a signal raising the flags sees `idle` before it
+        flag::register_conditional_shutdown(SIGINT, SIGINT_STATUS, signals.idle.clone())?;
+        flag::register_conditional_shutdown(SIGTERM, SIGTERM_STATUS, signals.idle.clone())?;
+        flag::register(SIGINT, signals.interrupted.clone())?;
+        flag::register(SIGTERM, signals.interrupted.clone())?;
+        flag::register(SIGTERM, signals.terminated.clone())?;
+        Result::Ok(signals)
+    }
+
+    /// the flag statements poll to stop early, see `Statement::set_cancel_flag`.
+    pub fn cancel_flag(&self) -> Arc<AtomicBool> {
+        self.interrupted.clone()
+    }
+
+    /// forget a Ctrl-C that came in before the next statement.
+    pub fn clear_interrupt(&self) {
+        self.interrupted.store(false, Ordering::SeqCst);
+    }
+
+    pub fn terminated(&self) -> bool {
+        self.terminated.load(Ordering::SeqCst)
+    }
+
+    pub fn set_idle(&self, idle: bool) {
+        self.idle.store(idle, Ordering::SeqCst);
+    }
+}
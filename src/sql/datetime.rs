@@ -0,0 +1,128 @@
+//! datetimes are seconds since `1970-01-01 00:00:00`, without a time zone. they
+//! are ordered like the integers they are stored as.
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// the days since 1970-01-01 of a date of the proleptic gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = (if year >= 0 { year } else { year - 399 }) / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// the year, month and day of given days since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = (if days >= 0 { days } else { days - 146_096 }) / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// the number of given digits at the start of the text, and the rest of it.
+fn take_number(text: &str, digits: usize) -> Option<(i64, &str)> {
+    let number = text.get(..digits)?;
+    if !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((number.parse().ok()?, &text[digits..]))
+}
+
+fn take_separator<'a>(text: &'a str, separators: &[char]) -> Option<&'a str> {
+    let first = text.chars().next()?;
+    if separators.contains(&first) {
+        Some(&text[first.len_utf8()..])
+    } else {
+        None
+    }
+}
+
+/// the seconds of `YYYY-MM-DD` or `YYYY-MM-DD HH:MM:SS`, where a `T` may stand
+/// for the space. `None` if it is not a valid date or time.
+pub fn parse(text: &str) -> Option<i64> {
+    let (year, rest) = take_number(text, 4)?;
+    let (month, rest) = take_number(take_separator(rest, &['-'])?, 2)?;
+    let (day, rest) = take_number(take_separator(rest, &['-'])?, 2)?;
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+    let (hour, minute, second) = if rest.is_empty() {
+        (0, 0, 0)
+    } else {
+        let (hour, rest) = take_number(take_separator(rest, &[' ', 'T'])?, 2)?;
+        let (minute, rest) = take_number(take_separator(rest, &[':'])?, 2)?;
+        let (second, rest) = take_number(take_separator(rest, &[':'])?, 2)?;
+        if !rest.is_empty() || hour > 23 || minute > 59 || second > 59 {
+            return None;
+        }
+        (hour, minute, second)
+    };
+    let days = days_from_civil(year, month, day);
+    Some(days * SECONDS_PER_DAY + hour * 3600 + minute * 60 + second)
+}
+
+/// the seconds as `YYYY-MM-DD HH:MM:SS`.
+pub fn format(seconds: i64) -> String {
+    let days = seconds.div_euclid(SECONDS_PER_DAY);
+    let time = seconds.rem_euclid(SECONDS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn datetimes_are_seconds_since_1970() {
+        assert_eq!(parse("1970-01-01"), Some(0));
+        assert_eq!(parse("1970-01-02 00:00:01"), Some(86_401));
+        assert_eq!(parse("1969-12-31T23:59:59"), Some(-1));
+        assert_eq!(parse("2024-02-29 12:30:00"), Some(1_709_209_800));
+        for text in &["2024-02-29 12:30:00", "1969-12-31 23:59:59", "2000-01-01 00:00:00"] {
+            assert_eq!(format(parse(text).unwrap()), *text);
+        }
+    }
+
+    #[test]
+    fn invalid_dates_and_times_are_refused() {
+        for text in &[
+            "2023-02-29",
+            "2024-13-01",
+            "2024-04-31",
+            "2024-01-01 24:00:00",
+            "2024-01-01 12:00",
+            "2024-1-1",
+            "2024-01-01x",
+            "",
+        ] {
+            assert_eq!(parse(text), None, "{}", text);
+        }
+    }
+}
@@ -2,125 +2,1509 @@
 //! #parse will be the entrance and
 //! ParsedSQL will be the final result.
 
-use nom::{alphanumeric, IResult};
+use nom::{digit, multispace, Context, Err, ErrorKind, IResult};
+use nom::types::CompleteByteSlice;
+use std::fmt;
 use std::str;
+use std::str::FromStr;
+pub mod datetime;
 pub mod operands;
 use self::operands::{parse_operand, Operand};
 
+/// the parsers take all of their input at once, running out of it is not
+/// `Incomplete` but the end of the statement.
+pub type Input<'a> = CompleteByteSlice<'a>;
+
+/// the text of a parsed token.
+fn input_str<'a>(input: Input<'a>) -> Result<&'a str, str::Utf8Error> {
+    str::from_utf8(input.0)
+}
+
+/// the deepest parentheses may nest, the parsers recurse once per level.
+const MAX_NESTING: usize = 64;
+
+/// the deepest operands may nest within each other, like the sums of a long
+/// `1 + 1 + ...`. the passes over a statement recurse once per level, a thread
+/// with 2 MiB of stack has room for about 250 levels in a debug build.
+const MAX_DEPTH: usize = 100;
+
+// the codes of the errors a statement fails with once its first words are parsed,
+// see `expected`
+const EXPECT_OPERANDS: u32 = 1;
+const EXPECT_FROM: u32 = 2;
+const EXPECT_CONDITION: u32 = 3;
+const EXPECT_GROUP_BY: u32 = 4;
+const EXPECT_COUNT: u32 = 5;
+const EXPECT_TABLE: u32 = 6;
+const EXPECT_VALUES: u32 = 7;
+const EXPECT_COLUMNS: u32 = 8;
+const EXPECT_INDEX: u32 = 9;
+const EXPECT_CLOSE: u32 = 10;
+const EXPECT_PRAGMA: u32 = 11;
+const EXPECT_SHALLOWER: u32 = 12;
+const EXPECT_I64: u32 = 13;
+
+/// what the parsers expected where they failed with given code.
+fn expected(code: u32) -> String {
+    let expected = match code {
+        EXPECT_OPERANDS => "operands or *",
+        EXPECT_FROM => "a table or (subquery)",
+        EXPECT_CONDITION => "a condition",
+        EXPECT_GROUP_BY => "by OPERAND, ...",
+        EXPECT_COUNT => "a number of rows",
+        EXPECT_TABLE => "a table name",
+        EXPECT_VALUES => "values (VALUE, ...)",
+        EXPECT_COLUMNS => "(COLUMN TYPE, ...)",
+        EXPECT_INDEX => "INDEX on TABLE (COLUMN)",
+        EXPECT_CLOSE => ")",
+        EXPECT_PRAGMA => "a pragma name",
+        EXPECT_SHALLOWER => {
+            return format!("operands nested at most {} levels deep", MAX_DEPTH);
+        }
+        EXPECT_I64 => {
+            return format!("an integer from {} to {}", i64::MIN, i64::MAX);
+        }
+        _ => "a statement",
+    };
+    expected.to_owned()
+}
+
+/// why a statement cannot be parsed, `offset` is the byte offset of the token
+/// found in the statement.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseError {
+    pub offset: usize,
+    /// empty at the end of the statement.
+    pub found: String,
+    pub expected: String,
+}
+
+impl ParseError {
+    /// the error at the start of `rest`, the part of `statement` left unparsed.
+    fn new(statement: &[u8], rest: &[u8], expected: &str) -> ParseError {
+        let spaces = rest.iter().take_while(|c| c.is_ascii_whitespace()).count();
+        let rest = &rest[spaces..];
+        ParseError {
+            offset: statement.len() - rest.len(),
+            found: first_token(rest),
+            expected: expected.to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.found.is_empty() {
+            write!(f, "syntax error at the end, expected {}.", self.expected)
+        } else {
+            write!(f, "syntax error near {}, expected {}.", self.found, self.expected)
+        }
+    }
+}
+
+impl From<ParseError> for String {
+    fn from(error: ParseError) -> String {
+        error.to_string()
+    }
+}
+
+/// the result of the parser, where it fails the statement fails with given code
+/// rather than another parser being tried. a failure within it keeps its code.
+fn expect<'a, O, F>(input: Input<'a>, code: u32, parser: F) -> IResult<Input<'a>, O>
+where
+    F: Fn(Input<'a>) -> IResult<Input<'a>, O>,
+{
+    match parser(input) {
+        Result::Err(Err::Error(_)) => Result::Err(Err::Failure(Context::Code(input, ErrorKind::Custom(code)))),
+        result => result,
+    }
+}
+
+/// a word, a quoted text or else a single character.
+fn first_token(input: &[u8]) -> String {
+    let len = match input.first() {
+        None => 0,
+        Some(&c) if is_identifier_char(c) => input.iter().take_while(|&&c| is_identifier_char(c)).count(),
+        Some(&quote) if quote == b'\'' || quote == b'"' || quote == b'`' => input[1..]
+            .iter()
+            .position(|&c| c == quote)
+            .map_or(input.len(), |end| end + 2),
+        Some(_) => {
+            let text = String::from_utf8_lossy(&input[..input.len().min(4)]);
+            return text.chars().next().map(String::from).unwrap_or_default();
+        }
+    };
+    String::from_utf8_lossy(&input[..len]).into_owned()
+}
+
 pub type TableName = String;
 
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub enum SQLType {
     Integer,
-    // Float,
-    // Boolean,
+    Real,
+    Boolean,
     String,
     // Text,
-    // DateTime
+    /// seconds since 1970, see `datetime`
+    DateTime,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum ParsedSQL {
     Select {
+        /// the db the table is qualified with, `aux` of `aux.users`.
+        database: Option<String>,
         table: Option<TableName>,
+        /// the select in parentheses the rows come from rather than a table.
+        subquery: Option<Box<ParsedSQL>>,
+        /// the name the table or subquery goes by, `u` of `from users u`.
+        alias: Option<String>,
         operands: Vec<Operand>,
+        /// the `where` a row has to meet to be selected.
+        condition: Option<Operand>,
+        /// the operands rows are grouped by, none unless there is a `group by`.
+        group_by: Vec<Operand>,
+        /// the `having` a group has to meet to be selected.
+        having: Option<Operand>,
+        limit: Option<Limit>,
+    },
+    /// `insert into users (id, username, email) values (1, 'ada', ?)`, no
+    /// columns stand for all of them in the order of the table.
+    Insert {
+        database: Option<String>,
+        table: TableName,
+        columns: Vec<String>,
+        values: Vec<Operand>,
+    },
+    /// `delete from users where ...`, all rows without a condition.
+    Delete {
+        database: Option<String>,
+        table: TableName,
+        condition: Option<Operand>,
     },
+    /// `create table [if not exists] books (title text, pages integer)`. the columns
+    /// are checked and built into a schema, which goes into the catalog of the db.
+    CreateTable {
+        database: Option<String>,
+        table: TableName,
+        columns: Vec<(String, SQLType)>,
+        if_not_exists: bool,
+    },
+    /// `drop table [if exists] books`, the users table cannot be dropped.
+    DropTable {
+        database: Option<String>,
+        table: TableName,
+        if_exists: bool,
+    },
+    /// `create index users_by_name on users (username)`, an index of a column of a
+    /// table built from its rows.
+    CreateIndex {
+        database: Option<String>,
+        name: String,
+        table: TableName,
+        column: String,
+    },
+    /// `begin`, `commit` or `rollback`, `transaction` may follow each of them.
+    Transaction(Transaction),
+    /// `pragma NAME`, `pragma NAME = VALUE` or `pragma NAME(VALUE)`, the last two
+    /// are the same.
+    Pragma { name: String, value: Option<Operand> },
+}
+
+/// what a transaction statement does to the transaction of a connection.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Transaction {
+    Begin,
+    /// written `commit` or `end`.
+    Commit,
+    Rollback,
+}
+
+impl fmt::Display for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Transaction::Begin => "begin",
+            Transaction::Commit => "commit",
+            Transaction::Rollback => "rollback",
+        })
+    }
+}
+
+/// `limit count offset offset`, the rows after the first `offset` ones and no
+/// more than `count` of them.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Limit {
+    pub count: u64,
+    pub offset: u64,
 }
 
-named!(parse_multiple_operands(&[u8]) -> Vec<Operand>,
+/// a text as a string literal, in single quotes with a quote inside written twice.
+pub fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+/// writes the items separated by commas.
+fn write_list<T: fmt::Display>(f: &mut fmt::Formatter, items: &[T]) -> fmt::Result {
+    for (index, item) in items.iter().enumerate() {
+        if index > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Result::Ok(())
+}
+
+impl ParsedSQL {
+    /// call given function with every operand of the statement and the operands
+    /// within them, in the order they are written.
+    pub fn visit_operands_mut(
+        &mut self,
+        visit: &mut dyn FnMut(&mut Operand) -> Result<(), String>,
+    ) -> Result<(), String> {
+        match self {
+            ParsedSQL::Select {
+                operands,
+                subquery,
+                condition,
+                group_by,
+                having,
+                ..
+            } => {
+                for op in operands.iter_mut() {
+                    op.visit_mut(visit)?;
+                }
+                if let Some(subquery) = subquery {
+                    subquery.visit_operands_mut(visit)?;
+                }
+                let ops = condition.iter_mut().chain(group_by).chain(having);
+                for op in ops {
+                    op.visit_mut(visit)?;
+                }
+                Result::Ok(())
+            }
+            ParsedSQL::Insert { values, .. } => {
+                for op in values.iter_mut() {
+                    op.visit_mut(visit)?;
+                }
+                Result::Ok(())
+            }
+            ParsedSQL::Delete { condition, .. } => match condition {
+                Some(condition) => condition.visit_mut(visit),
+                None => Result::Ok(()),
+            },
+            ParsedSQL::CreateTable { .. }
+            | ParsedSQL::DropTable { .. }
+            | ParsedSQL::CreateIndex { .. }
+            | ParsedSQL::Transaction(_)
+            | ParsedSQL::Pragma { .. } => Result::Ok(()),
+        }
+    }
+
+    /// the depth of the deepest operand of the statement, see `Operand::depth`.
+    pub fn depth(&self) -> usize {
+        let operands: Vec<&Operand> = match self {
+            ParsedSQL::Select {
+                operands,
+                subquery,
+                condition,
+                group_by,
+                having,
+                ..
+            } => {
+                let depth = subquery.as_ref().map_or(0, |subquery| subquery.depth());
+                let ops = operands.iter().chain(condition).chain(group_by).chain(having);
+                return ops.map(Operand::depth).fold(depth, usize::max);
+            }
+            ParsedSQL::Insert { values, .. } => values.iter().collect(),
+            ParsedSQL::Delete { condition, .. } => condition.iter().collect(),
+            ParsedSQL::Pragma { value, .. } => value.iter().collect(),
+            ParsedSQL::CreateTable { .. }
+            | ParsedSQL::DropTable { .. }
+            | ParsedSQL::CreateIndex { .. }
+            | ParsedSQL::Transaction(_) => Vec::new(),
+        };
+        operands.into_iter().map(Operand::depth).max().unwrap_or(0)
+    }
+}
+
+/// the statement as it could have been written, like the operands.
+impl fmt::Display for ParsedSQL {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParsedSQL::Select {
+                database,
+                table,
+                subquery,
+                alias,
+                operands,
+                condition,
+                group_by,
+                having,
+                limit,
+            } => {
+                f.write_str("select ")?;
+                write_list(f, operands)?;
+                if let Some(subquery) = subquery {
+                    write!(f, " from ({})", subquery)?;
+                }
+                if let Some(table) = table {
+                    write!(f, " from {}", QualifiedName(database, table))?;
+                }
+                if let Some(alias) = alias {
+                    write!(f, " as {}", alias)?;
+                }
+                if let Some(condition) = condition {
+                    write!(f, " where {}", condition)?;
+                }
+                if !group_by.is_empty() {
+                    f.write_str(" group by ")?;
+                    write_list(f, group_by)?;
+                }
+                if let Some(having) = having {
+                    write!(f, " having {}", having)?;
+                }
+                if let Some(limit) = limit {
+                    write!(f, " limit {}", limit.count)?;
+                    if limit.offset > 0 {
+                        write!(f, " offset {}", limit.offset)?;
+                    }
+                }
+                Result::Ok(())
+            }
+            ParsedSQL::Insert {
+                database,
+                table,
+                columns,
+                values,
+            } => {
+                write!(f, "insert into {}", QualifiedName(database, table))?;
+                if !columns.is_empty() {
+                    f.write_str(" (")?;
+                    write_list(f, columns)?;
+                    f.write_str(")")?;
+                }
+                f.write_str(" values (")?;
+                write_list(f, values)?;
+                f.write_str(")")
+            }
+            ParsedSQL::Delete {
+                database,
+                table,
+                condition,
+            } => {
+                write!(f, "delete from {}", QualifiedName(database, table))?;
+                match condition {
+                    Some(condition) => write!(f, " where {}", condition),
+                    None => Result::Ok(()),
+                }
+            }
+            ParsedSQL::CreateTable {
+                database,
+                table,
+                columns,
+                if_not_exists,
+            } => {
+                f.write_str("create table ")?;
+                if *if_not_exists {
+                    f.write_str("if not exists ")?;
+                }
+                write!(f, "{} (", QualifiedName(database, table))?;
+                for (index, (name, sql_type)) in columns.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{} {}", name, sql_type)?;
+                }
+                f.write_str(")")
+            }
+            ParsedSQL::DropTable {
+                database,
+                table,
+                if_exists,
+            } => {
+                f.write_str("drop table ")?;
+                if *if_exists {
+                    f.write_str("if exists ")?;
+                }
+                write!(f, "{}", QualifiedName(database, table))
+            }
+            ParsedSQL::CreateIndex {
+                database,
+                name,
+                table,
+                column,
+            } => write!(
+                f,
+                "create index {} on {} ({})",
+                name,
+                QualifiedName(database, table),
+                column
+            ),
+            ParsedSQL::Transaction(transaction) => write!(f, "{}", transaction),
+            ParsedSQL::Pragma { name, value } => match value {
+                Some(value) => write!(f, "pragma {} = {}", name, value),
+                None => write!(f, "pragma {}", name),
+            },
+        }
+    }
+}
+
+/// `aux.users`, or `users` in the main db.
+struct QualifiedName<'a>(&'a Option<String>, &'a str);
+
+impl<'a> fmt::Display for QualifiedName<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(database) => write!(f, "{}.{}", database, self.1),
+            None => f.write_str(self.1),
+        }
+    }
+}
+
+impl fmt::Display for SQLType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SQLType::Integer => f.write_str("integer"),
+            SQLType::Real => f.write_str("real"),
+            SQLType::Boolean => f.write_str("boolean"),
+            SQLType::DateTime => f.write_str("datetime"),
+            SQLType::String => f.write_str("text"),
+        }
+    }
+}
+
+// a result column, named by its alias if there is one
+named!(parse_result_operand<Input, Operand>,
+    map!(
+        pair!(parse_operand, opt!(complete!(ws!(preceded!(tag!("as"), parse_table_name))))),
+        |(op, alias)| match alias {
+            Some(name) => Operand::Alias(Box::new(op), name),
+            None => op,
+        }
+    )
+);
+
+// `*` or `u.*`
+named!(parse_star<Input, Operand>,
+    ws!(map!(terminated!(opt!(complete!(terminated!(name, tag!(".")))), tag!("*")), Operand::Star))
+);
+
+named!(parse_multiple_operands<Input, Vec<Operand>>,
+    separated_nonempty_list_complete!(tag!(","), alt!(parse_star | parse_result_operand))
+);
+
+fn is_identifier_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+// the name of a table, column or index, letters, digits and underscores not
+// starting with a digit, which starts a number
+named!(pub identifier<Input, Input>,
+    verify!(take_while1!(is_identifier_char), |word: Input| !word.0[0].is_ascii_digit())
+);
+
+/// a word of the grammar, but not the start of a longer name like `andrew` is
+/// for `and`.
+pub fn keyword<'a>(input: Input<'a>, word: &'static str) -> IResult<Input<'a>, Input<'a>> {
+    verify!(input, identifier, |found: Input| found.0 == word.as_bytes())
+}
+
+// `"a name"` or `` `a name` ``, the quote is written twice within it
+named!(quoted_name<Input, String>,
+    alt!(
+        map_res!(
+            delimited!(tag!("\""), recognize!(many0!(alt!(is_not!("\"") | tag!("\"\"")))), tag!("\"")),
+            |bytes| input_str(bytes).map(|str| str.replace("\"\"", "\""))
+        ) |
+        map_res!(
+            delimited!(tag!("`"), recognize!(many0!(alt!(is_not!("`") | tag!("``")))), tag!("`")),
+            |bytes| input_str(bytes).map(|str| str.replace("``", "`"))
+        )
+    )
+);
+
+// the name of a table, column or alias, quoted if it has other characters than an
+// identifier or is a word of the grammar
+named!(pub name<Input, String>,
+    alt!(
+        quoted_name |
+        map_res!(identifier, |bytes| input_str(bytes).map(|str| str.to_owned()))
+    )
+);
+
+named!(parse_table_name<Input, TableName>,
+    ws!(name)
+);
+
+// a table name with the db it is in, if given
+named!(parse_qualified_table_name<Input, (Option<String>, TableName)>,
+    pair!(
+        opt!(complete!(terminated!(parse_table_name, tag!(".")))),
+        parse_table_name
+    )
+);
+
+// a select in parentheses
+named!(pub parse_subquery<Input, ParsedSQL>,
+    ws!(delimited!(tag!("("), parse_select, tag!(")")))
+);
+
+// the rows of a table, or the result rows of a subquery
+named!(parse_from<Input, (Option<String>, Option<TableName>, Option<Box<ParsedSQL>>)>,
     alt!(
-        map!(ws!(tag!("*")), |_| Vec::new()) |
-        separated_list_complete!(tag!(","), parse_operand)
+        map!(parse_subquery, |subquery| (None, None, Some(Box::new(subquery)))) |
+        map!(parse_qualified_table_name, |(database, table)| (database, Some(table), None))
     )
 );
 
-named!(parse_table_name(&[u8]) -> TableName,
-    ws!(map_res!(alphanumeric, |bytes| str::from_utf8(bytes).map(|str| str.to_owned())))
+/// the words of the grammar that may follow a table, they are not taken for its alias.
+const KEYWORDS: &[&str] = &["as", "where", "group", "having", "limit", "offset", "order", "on", "join"];
+
+fn is_keyword(word: Input) -> bool {
+    KEYWORDS.iter().any(|keyword| keyword.as_bytes() == word.0)
+}
+
+named!(parse_alias_name<Input, String>,
+    preceded!(
+        opt!(multispace),
+        alt!(
+            quoted_name |
+            map_res!(
+                verify!(identifier, |word: Input| !is_keyword(word)),
+                |bytes| input_str(bytes).map(|str| str.to_owned())
+            )
+        )
+    )
 );
 
-named!(parse_sql(&[u8]) -> ParsedSQL,
+// `users u` or `users as u`
+named!(parse_alias<Input, String>,
+    ws!(alt!(
+        preceded!(verify!(identifier, |word: Input| word.0 == b"as"), parse_alias_name) |
+        parse_alias_name
+    ))
+);
+
+named!(parse_group_by<Input, Vec<Operand>>,
+    ws!(preceded!(
+        call!(keyword, "group"),
+        call!(expect, EXPECT_GROUP_BY, parse_group_by_operands)
+    ))
+);
+
+named!(parse_group_by_operands<Input, Vec<Operand>>,
+    ws!(preceded!(tag!("by"), separated_nonempty_list_complete!(tag!(","), parse_operand)))
+);
+
+named!(parse_count<Input, u64>,
+    ws!(map_res!(map_res!(digit, input_str), FromStr::from_str))
+);
+
+named!(parse_limit<Input, Limit>,
+    map!(
+        ws!(pair!(
+            preceded!(call!(keyword, "limit"), call!(expect, EXPECT_COUNT, parse_count)),
+            opt!(complete!(preceded!(
+                call!(keyword, "offset"),
+                call!(expect, EXPECT_COUNT, parse_count)
+            )))
+        )),
+        |(count, offset)| Limit {count, offset: offset.unwrap_or(0)}
+    )
+);
+
+named!(parse_from_and_alias<Input, ((Option<String>, Option<TableName>, Option<Box<ParsedSQL>>), Option<String>)>,
+    ws!(pair!(parse_from, opt!(complete!(parse_alias))))
+);
+
+named!(parse_select<Input, ParsedSQL>,
     ws!(map!(
         tuple!(
-            tag!("select"),
-            parse_multiple_operands,
-            opt!(complete!(preceded!(tag!("from"), parse_table_name)))
+            call!(keyword, "select"),
+            call!(expect, EXPECT_OPERANDS, parse_multiple_operands),
+            opt!(complete!(preceded!(
+                call!(keyword, "from"),
+                call!(expect, EXPECT_FROM, parse_from_and_alias)
+            ))),
+            opt!(complete!(preceded!(
+                call!(keyword, "where"),
+                call!(expect, EXPECT_CONDITION, parse_operand)
+            ))),
+            opt!(complete!(parse_group_by)),
+            opt!(complete!(preceded!(
+                call!(keyword, "having"),
+                call!(expect, EXPECT_CONDITION, parse_operand)
+            ))),
+            opt!(complete!(parse_limit))
         ),
-        |(_, op, table)| ParsedSQL::Select {operands: op, table: table}
+        |(_, op, from, condition, group_by, having, limit)| {
+            let ((database, table, subquery), alias) = from.unwrap_or(((None, None, None), None));
+            ParsedSQL::Select {
+                operands: op,
+                database,
+                table,
+                subquery,
+                alias,
+                condition,
+                group_by: group_by.unwrap_or_default(),
+                having,
+                limit,
+            }
+        }
+    ))
+);
+
+named!(close<Input, Input>, ws!(tag!(")")));
+
+named!(parse_column_names<Input, Vec<String>>,
+    ws!(delimited!(
+        tag!("("),
+        separated_nonempty_list_complete!(tag!(","), parse_table_name),
+        call!(expect, EXPECT_CLOSE, close)
+    ))
+);
+
+named!(parse_values<Input, Vec<Operand>>,
+    ws!(delimited!(
+        tag!("("),
+        separated_nonempty_list_complete!(tag!(","), parse_operand),
+        call!(expect, EXPECT_CLOSE, close)
     ))
 );
 
-pub fn parse(inputs: &[u8]) -> Result<ParsedSQL, String> {
-    parse_sql(inputs)
-        .to_result()
-        .map_err(|_| "parse failed.".to_owned())
+named!(parse_values_clause<Input, Vec<Operand>>,
+    ws!(preceded!(tag!("values"), parse_values))
+);
+
+named!(parse_insert<Input, ParsedSQL>,
+    map!(
+        ws!(tuple!(
+            tag!("insert"),
+            tag!("into"),
+            call!(expect, EXPECT_TABLE, parse_qualified_table_name),
+            opt!(complete!(parse_column_names)),
+            call!(expect, EXPECT_VALUES, parse_values_clause)
+        )),
+        |(_, _, (database, table), columns, values)| ParsedSQL::Insert {
+            database,
+            table,
+            columns: columns.unwrap_or_default(),
+            values,
+        }
+    )
+);
+
+named!(parse_delete<Input, ParsedSQL>,
+    map!(
+        ws!(tuple!(
+            tag!("delete"),
+            tag!("from"),
+            call!(expect, EXPECT_TABLE, parse_qualified_table_name),
+            opt!(complete!(preceded!(
+                call!(keyword, "where"),
+                call!(expect, EXPECT_CONDITION, parse_operand)
+            )))
+        )),
+        |(_, _, (database, table), condition)| ParsedSQL::Delete {database, table, condition}
+    )
+);
+
+// `int`, `float`, `double`, `bool`, `timestamp`, `date` and `varchar` are the names other dbs give the types
+named!(parse_column_type<Input, SQLType>,
+    ws!(alt!(
+        map!(tag!("integer"), |_| SQLType::Integer) |
+        map!(tag!("int"), |_| SQLType::Integer) |
+        map!(tag!("real"), |_| SQLType::Real) |
+        map!(tag!("float"), |_| SQLType::Real) |
+        map!(tag!("double"), |_| SQLType::Real) |
+        map!(tag!("boolean"), |_| SQLType::Boolean) |
+        map!(tag!("bool"), |_| SQLType::Boolean) |
+        map!(tag!("datetime"), |_| SQLType::DateTime) |
+        map!(tag!("timestamp"), |_| SQLType::DateTime) |
+        map!(tag!("date"), |_| SQLType::DateTime) |
+        map!(tag!("text"), |_| SQLType::String) |
+        map!(tag!("varchar"), |_| SQLType::String)
+    ))
+);
+
+named!(parse_column_definitions<Input, Vec<(String, SQLType)>>,
+    ws!(delimited!(
+        tag!("("),
+        separated_nonempty_list_complete!(tag!(","), pair!(parse_table_name, parse_column_type)),
+        call!(expect, EXPECT_CLOSE, close)
+    ))
+);
+
+named!(parse_create_table<Input, ParsedSQL>,
+    map!(
+        ws!(tuple!(
+            tag!("create"),
+            tag!("table"),
+            opt!(complete!(parse_if_not_exists)),
+            call!(expect, EXPECT_TABLE, parse_qualified_table_name),
+            call!(expect, EXPECT_COLUMNS, parse_column_definitions)
+        )),
+        |(_, _, if_not_exists, (database, table), columns)| ParsedSQL::CreateTable {
+            database,
+            table,
+            columns,
+            if_not_exists: if_not_exists.is_some(),
+        }
+    )
+);
+
+// `if not exists` makes creating a table that exists already change nothing
+named!(parse_if_not_exists<Input, ()>,
+    value!((), ws!(tuple!(call!(keyword, "if"), call!(keyword, "not"), call!(keyword, "exists"))))
+);
+
+// `if exists` makes dropping a table that does not exist change nothing
+named!(parse_if_exists<Input, ()>,
+    value!((), ws!(tuple!(call!(keyword, "if"), call!(keyword, "exists"))))
+);
+
+named!(parse_drop_table<Input, ParsedSQL>,
+    map!(
+        ws!(tuple!(
+            call!(keyword, "drop"),
+            tag!("table"),
+            opt!(complete!(parse_if_exists)),
+            call!(expect, EXPECT_TABLE, parse_qualified_table_name)
+        )),
+        |(_, _, if_exists, (database, table))| ParsedSQL::DropTable {
+            database,
+            table,
+            if_exists: if_exists.is_some(),
+        }
+    )
+);
+
+// `users_by_name on users (username)`
+named!(parse_index_definition<Input, (String, (Option<String>, TableName), String)>,
+    ws!(tuple!(
+        parse_table_name,
+        preceded!(tag!("on"), parse_qualified_table_name),
+        delimited!(tag!("("), parse_table_name, tag!(")"))
+    ))
+);
+
+named!(parse_create_index<Input, ParsedSQL>,
+    map!(
+        ws!(tuple!(
+            tag!("create"),
+            tag!("index"),
+            call!(expect, EXPECT_INDEX, parse_index_definition)
+        )),
+        |(_, _, (name, (database, table), column))| ParsedSQL::CreateIndex {
+            database,
+            name,
+            table,
+            column,
+        }
+    )
+);
+
+// `begin`, `commit` or `end` and `rollback`, the `transaction` after them changes nothing
+named!(parse_transaction<Input, ParsedSQL>,
+    map!(
+        ws!(terminated!(
+            alt!(
+                value!(Transaction::Begin, call!(keyword, "begin")) |
+                value!(Transaction::Commit, alt!(call!(keyword, "commit") | call!(keyword, "end"))) |
+                value!(Transaction::Rollback, call!(keyword, "rollback"))
+            ),
+            opt!(complete!(call!(keyword, "transaction")))
+        )),
+        ParsedSQL::Transaction
+    )
+);
+
+// `= VALUE` or `(VALUE)` after the name of a pragma
+named!(parse_pragma_value<Input, Operand>,
+    ws!(alt!(
+        preceded!(tag!("="), parse_operand) |
+        delimited!(tag!("("), parse_operand, call!(expect, EXPECT_CLOSE, close))
+    ))
+);
+
+named!(parse_pragma<Input, ParsedSQL>,
+    map!(
+        ws!(tuple!(
+            call!(keyword, "pragma"),
+            call!(expect, EXPECT_PRAGMA, parse_table_name),
+            opt!(complete!(parse_pragma_value))
+        )),
+        |(_, name, value)| ParsedSQL::Pragma { name, value }
+    )
+);
+
+named!(parse_sql<Input, ParsedSQL>,
+    alt!(
+        parse_select |
+        parse_insert |
+        parse_delete |
+        parse_create_table |
+        parse_create_index |
+        parse_drop_table |
+        parse_pragma |
+        parse_transaction
+    )
+);
+
+/// the statement, or where and why it cannot be parsed.
+pub fn parse(inputs: &[u8]) -> Result<ParsedSQL, ParseError> {
+    check_nesting(inputs)?;
+    match parse_sql(CompleteByteSlice(inputs)) {
+        Result::Ok((rest, parsed)) if is_end_of_statement(rest.0) => Result::Ok(parsed),
+        Result::Ok((rest, _)) => {
+            Result::Err(ParseError::new(inputs, rest.0, "the end of the statement"))
+        }
+        // a statement fails past its first words with the code of what it expected
+        Result::Err(Err::Failure(Context::Code(rest, ErrorKind::Custom(code)))) => {
+            Result::Err(ParseError::new(inputs, rest.0, &expected(code)))
+        }
+        Result::Err(_) => Result::Err(ParseError::new(inputs, inputs, "a statement")),
+    }
+}
+
+/// nothing but spaces left, with a `;` ending the statement the way the ones of a
+/// sqlite3 script do.
+fn is_end_of_statement(rest: &[u8]) -> bool {
+    let rest = rest.trim_ascii();
+    rest.is_empty() || rest == b";"
+}
+
+/// the statement without its `-- line` and `/* block */` comments, every comment
+/// is replaced by as many spaces so offsets into the statement stay the same, its
+/// line breaks stay as well. quoted text is kept as it is.
+pub fn strip_comments(input: &str) -> Result<String, String> {
+    let mut stripped = String::with_capacity(input.len());
+    let mut quote = None;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(open), c) => {
+                if c == open {
+                    quote = None;
+                }
+                stripped.push(c);
+            }
+            (None, '\'') | (None, '"') | (None, '`') => {
+                quote = Some(c);
+                stripped.push(c);
+            }
+            (None, '-') if chars.peek() == Some(&'-') => {
+                // the line ends the comment, the line break stays
+                stripped.push(' ');
+                while let Some(c) = chars.peek().cloned().filter(|&c| c != '\n') {
+                    chars.next();
+                    blank(&mut stripped, c);
+                }
+            }
+            (None, '/') if chars.peek() == Some(&'*') => {
+                chars.next();
+                stripped.push_str("  ");
+                let mut last = None;
+                loop {
+                    match chars.next() {
+                        Some('/') if last == Some('*') => break,
+                        Some(c) => {
+                            blank(&mut stripped, c);
+                            last = Some(c);
+                        }
+                        None => return Result::Err("unterminated comment.".to_owned()),
+                    }
+                }
+                stripped.push(' ');
+            }
+            (None, c) => stripped.push(c),
+        }
+    }
+    Result::Ok(stripped)
+}
+
+/// a comment character as spaces of its length, a line break stays.
+fn blank(stripped: &mut String, c: char) {
+    if c == '\n' {
+        stripped.push(c);
+    } else {
+        stripped.extend(std::iter::repeat_n(' ', c.len_utf8()));
+    }
+}
+
+/// fail on parentheses nested deeper than `MAX_NESTING`, before the parsers run
+/// out of stack on them. quoted text is skipped.
+fn check_nesting(inputs: &[u8]) -> Result<(), ParseError> {
+    let mut depth = 0;
+    let mut quoted = false;
+    for (offset, &byte) in inputs.iter().enumerate() {
+        match byte {
+            b'\'' => quoted = !quoted,
+            b'(' if !quoted => {
+                depth += 1;
+                if depth > MAX_NESTING {
+                    let expected = format!("parentheses nested at most {} levels deep", MAX_NESTING);
+                    return Result::Err(ParseError::new(inputs, &inputs[offset..], &expected));
+                }
+            }
+            b')' if !quoted && depth > 0 => depth -= 1,
+            _ => {}
+        }
+    }
+    Result::Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use sql::operands::{Aggregate, Comparison};
 
-    const EMPTY: &[u8] = &[0u8; 0];
+    const EMPTY: Input = CompleteByteSlice(b"");
+
+    fn parse_sql(input: &[u8]) -> Result<(Input<'_>, ParsedSQL), ()> {
+        super::parse_sql(CompleteByteSlice(input)).map_err(|_| ())
+    }
+
+    #[test]
+    fn comments_are_stripped_outside_of_quotes() {
+        assert_eq!(strip_comments("select 1 -- one\n+ 2").unwrap(), "select 1       \n+ 2");
+        assert_eq!(strip_comments("select/* all */* from users").unwrap(), "select         * from users");
+        assert_eq!(strip_comments("select '--', '/*x*/' /**/").unwrap(), "select '--', '/*x*/'     ");
+        assert_eq!(strip_comments("select 'it''s' -- é").unwrap(), "select 'it''s'      ");
+        assert_eq!(strip_comments("/* a\nb */ 1").unwrap(), "    \n     1");
+        assert_eq!(strip_comments("select 1 /* never closed"), Err("unterminated comment.".to_owned()));
+    }
 
     #[test]
     fn can_recognize_simplest_select_statement() {
         let expected = ParsedSQL::Select {
+            database: None,
             table: None,
+            subquery: None,
+            alias: None,
             operands: vec![Operand::Integer(42)],
+            condition: None,
+            group_by: Vec::new(),
+            having: None,
+            limit: None,
         };
-        assert_eq!(parse_sql(b"select 42"), IResult::Done(EMPTY, expected));
+        assert_eq!(parse_sql(b"select 42"), Result::Ok((EMPTY, expected)));
     }
 
     #[test]
     fn can_recognize_a_select_text_statement() {
         let expected = ParsedSQL::Select {
+            database: None,
             table: None,
+            subquery: None,
+            alias: None,
             operands: vec![Operand::String("nihao, rdb.".to_owned())],
+            condition: None,
+            group_by: Vec::new(),
+            having: None,
+            limit: None,
         };
         assert_eq!(
             parse_sql(b"select 'nihao, rdb.'"),
-            IResult::Done(EMPTY, expected)
+            Result::Ok((EMPTY, expected))
         );
     }
 
     #[test]
     fn can_recognize_a_select_statement_for_multiple_columns() {
         let expected = ParsedSQL::Select {
+            database: None,
             table: None,
+            subquery: None,
+            alias: None,
             operands: vec![
                 Operand::String("nihao, rdb.".to_owned()),
                 Operand::Integer(42),
                 Operand::String("e".to_owned()),
             ],
+            condition: None,
+            group_by: Vec::new(),
+            having: None,
+            limit: None,
         };
         assert_eq!(
             parse_sql(b"select 'nihao, rdb.', 42, 'e'"),
-            IResult::Done(EMPTY, expected)
+            Result::Ok((EMPTY, expected))
         );
     }
 
     #[test]
     fn can_recognize_the_select_all_from_table_statement() {
         let expected = ParsedSQL::Select {
+            database: None,
             table: Some("users".to_owned()),
-            operands: Vec::new(),
+            subquery: None,
+            alias: None,
+            operands: vec![Operand::Star(None)],
+            condition: None,
+            group_by: Vec::new(),
+            having: None,
+            limit: None,
         };
 
         assert_eq!(
             parse_sql(b"select * from users"),
-            IResult::Done(EMPTY, expected)
+            Result::Ok((EMPTY, expected))
         );
+        let parsed = parse(b"select u.*, id * 2, * from users u").unwrap();
+        assert_eq!(format!("{}", parsed), "select u.*, id * 2, * from users as u");
     }
 
     #[test]
     fn can_recognize_the_select_columns_from_table_statement() {
         let expected = ParsedSQL::Select {
+            database: None,
             table: Some("users".to_owned()),
+            subquery: None,
+            alias: None,
             operands: vec![Operand::Column("id".to_owned()), Operand::Integer(42)],
+            condition: None,
+            group_by: Vec::new(),
+            having: None,
+            limit: None,
         };
 
         assert_eq!(
             parse_sql(b"select id, 42 from users"),
-            IResult::Done(EMPTY, expected)
+            Result::Ok((EMPTY, expected))
+        );
+    }
+
+    #[test]
+    fn can_recognize_a_table_of_an_attached_db() {
+        let expected = ParsedSQL::Select {
+            database: Some("aux".to_owned()),
+            table: Some("users".to_owned()),
+            subquery: None,
+            alias: None,
+            operands: vec![Operand::Star(None)],
+            condition: None,
+            group_by: Vec::new(),
+            having: None,
+            limit: None,
+        };
+
+        assert_eq!(
+            parse_sql(b"select * from aux.users"),
+            Result::Ok((EMPTY, expected))
+        );
+    }
+
+    #[test]
+    fn can_recognize_a_where_clause() {
+        let expected = ParsedSQL::Select {
+            database: None,
+            table: Some("users".to_owned()),
+            subquery: None,
+            alias: None,
+            operands: vec![Operand::Star(None)],
+            condition: Some(Operand::Compare(
+                Comparison::Greater,
+                Box::new(Operand::Column("id".to_owned())),
+                Box::new(Operand::Integer(10)),
+            )),
+            group_by: Vec::new(),
+            having: None,
+            limit: None,
+        };
+        assert_eq!(
+            parse_sql(b"select * from users where id > 10"),
+            Result::Ok((EMPTY, expected))
+        );
+
+        let expected = ParsedSQL::Select {
+            database: None,
+            table: None,
+            subquery: None,
+            alias: None,
+            operands: vec![Operand::Integer(1)],
+            condition: Some(Operand::Integer(0)),
+            group_by: Vec::new(),
+            having: None,
+            limit: None,
+        };
+        assert_eq!(parse_sql(b"select 1 where 0"), Result::Ok((EMPTY, expected)));
+    }
+
+    #[test]
+    fn can_recognize_limits_and_offsets() {
+        let select = |limit| ParsedSQL::Select {
+            database: None,
+            table: Some("users".to_owned()),
+            subquery: None,
+            alias: None,
+            operands: vec![Operand::Star(None)],
+            condition: None,
+            group_by: Vec::new(),
+            having: None,
+            limit,
+        };
+        assert_eq!(
+            parse_sql(b"select * from users limit 10"),
+            Result::Ok((EMPTY, select(Some(Limit {count: 10, offset: 0}))))
+        );
+        assert_eq!(
+            parse_sql(b"select * from users limit 10 offset 20"),
+            Result::Ok((EMPTY, select(Some(Limit {count: 10, offset: 20}))))
+        );
+        assert_eq!(
+            parse(b"select * from users limit -1").map_err(String::from),
+            Result::Err("syntax error near -, expected a number of rows.".to_owned())
+        );
+    }
+
+    #[test]
+    fn can_recognize_a_group_by() {
+        let expected = ParsedSQL::Select {
+            database: None,
+            table: Some("users".to_owned()),
+            subquery: None,
+            alias: None,
+            operands: vec![
+                Operand::Column("username".to_owned()),
+                Operand::Aggregate(Aggregate::Count, None),
+            ],
+            condition: None,
+            group_by: vec![Operand::Column("username".to_owned())],
+            having: None,
+            limit: Some(Limit {count: 2, offset: 0}),
+        };
+        assert_eq!(
+            parse_sql(b"select username, count(*) from users group by username limit 2"),
+            Result::Ok((EMPTY, expected))
+        );
+    }
+
+    #[test]
+    fn can_recognize_a_having_clause() {
+        let count = || Operand::Aggregate(Aggregate::Count, None);
+        let expected = ParsedSQL::Select {
+            database: None,
+            table: Some("users".to_owned()),
+            subquery: None,
+            alias: None,
+            operands: vec![count()],
+            condition: None,
+            group_by: vec![Operand::Column("username".to_owned())],
+            having: Some(Operand::Compare(
+                Comparison::Greater,
+                Box::new(count()),
+                Box::new(Operand::Integer(1)),
+            )),
+            limit: None,
+        };
+        assert_eq!(
+            parse_sql(b"select count(*) from users group by username having count(*) > 1"),
+            Result::Ok((EMPTY, expected))
+        );
+    }
+
+    #[test]
+    fn can_recognize_an_insert_statement() {
+        let expected = ParsedSQL::Insert {
+            database: Some("aux".to_owned()),
+            table: "users".to_owned(),
+            columns: vec!["email".to_owned(), "id".to_owned(), "username".to_owned()],
+            values: vec![
+                Operand::String("ada@example.com".to_owned()),
+                Operand::Integer(1),
+                Operand::Parameter(Some(2)),
+            ],
+        };
+        assert_eq!(
+            parse_sql(b"insert into aux.users (email, id, username) values ('ada@example.com', 1, ?2)"),
+            Result::Ok((EMPTY, expected))
+        );
+
+        let expected = ParsedSQL::Insert {
+            database: None,
+            table: "users".to_owned(),
+            columns: Vec::new(),
+            values: vec![Operand::Integer(1), Operand::Parameter(None), Operand::Parameter(None)],
+        };
+        assert_eq!(
+            parse_sql(b"insert into users values(1,?,?)"),
+            Result::Ok((EMPTY, expected))
+        );
+    }
+
+    #[test]
+    fn can_recognize_a_delete_statement() {
+        let expected = ParsedSQL::Delete {
+            database: None,
+            table: "users".to_owned(),
+            condition: Some(Operand::Compare(
+                Comparison::LessOrEqual,
+                Box::new(Operand::Column("id".to_owned())),
+                Box::new(Operand::Integer(3)),
+            )),
+        };
+        assert_eq!(
+            parse_sql(b"delete from users where id <= 3"),
+            Result::Ok((EMPTY, expected))
+        );
+
+        let expected = ParsedSQL::Delete {
+            database: Some("aux".to_owned()),
+            table: "users".to_owned(),
+            condition: None,
+        };
+        assert_eq!(parse_sql(b"delete from aux.users"), Result::Ok((EMPTY, expected)));
+    }
+
+    #[test]
+    fn can_recognize_a_subquery_in_from() {
+        let subquery = ParsedSQL::Select {
+            database: None,
+            table: Some("users".to_owned()),
+            subquery: None,
+            alias: None,
+            operands: vec![Operand::Alias(
+                Box::new(Operand::Column("id".to_owned())),
+                "x".to_owned(),
+            )],
+            condition: None,
+            group_by: Vec::new(),
+            having: None,
+            limit: None,
+        };
+        let expected = ParsedSQL::Select {
+            database: None,
+            table: None,
+            subquery: Some(Box::new(subquery)),
+            alias: None,
+            operands: vec![Operand::Column("x".to_owned())],
+            condition: None,
+            group_by: Vec::new(),
+            having: None,
+            limit: None,
+        };
+        assert_eq!(
+            parse_sql(b"select x from (select id as x from users)"),
+            Result::Ok((EMPTY, expected))
+        );
+    }
+
+    #[test]
+    fn can_recognize_table_aliases() {
+        let select = |alias: Option<&str>, condition| ParsedSQL::Select {
+            database: None,
+            table: Some("users".to_owned()),
+            subquery: None,
+            alias: alias.map(|alias| alias.to_owned()),
+            operands: vec![Operand::QualifiedColumn("u".to_owned(), "id".to_owned())],
+            condition,
+            group_by: Vec::new(),
+            having: None,
+            limit: None,
+        };
+        assert_eq!(parse_sql(b"select u.id from users u"), Result::Ok((EMPTY, select(Some("u"), None))));
+        assert_eq!(
+            parse_sql(b"select u.id from users as u"),
+            Result::Ok((EMPTY, select(Some("u"), None)))
+        );
+        let condition = Operand::Compare(
+            Comparison::Greater,
+            Box::new(Operand::Column("id".to_owned())),
+            Box::new(Operand::Integer(1)),
+        );
+        assert_eq!(
+            parse_sql(b"select u.id from users where id > 1"),
+            Result::Ok((EMPTY, select(None, Some(condition))))
+        );
+    }
+
+    #[test]
+    fn can_recognize_quoted_names() {
+        let expected = ParsedSQL::Select {
+            database: None,
+            table: Some("users".to_owned()),
+            subquery: None,
+            alias: Some("where".to_owned()),
+            operands: vec![
+                Operand::Alias(
+                    Box::new(Operand::Column("first name".to_owned())),
+                    "say \"hi\"".to_owned(),
+                ),
+                Operand::QualifiedColumn("where".to_owned(), "order".to_owned()),
+            ],
+            condition: None,
+            group_by: Vec::new(),
+            having: None,
+            limit: None,
+        };
+        assert_eq!(
+            parse_sql(b"select \"first name\" as \"say \"\"hi\"\"\", `where`.`order` from \"users\" as \"where\""),
+            Result::Ok((EMPTY, expected))
+        );
+        assert_eq!(name(CompleteByteSlice(b"`a``b`")), Result::Ok((EMPTY, "a`b".to_owned())));
+        assert!(name(CompleteByteSlice(b"\"no end")).is_err());
+    }
+
+    #[test]
+    fn can_recognize_a_create_table_statement() {
+        let expected = ParsedSQL::CreateTable {
+            database: None,
+            table: "books".to_owned(),
+            columns: vec![
+                ("id".to_owned(), SQLType::Integer),
+                ("title".to_owned(), SQLType::String),
+                ("pages".to_owned(), SQLType::Integer),
+            ],
+            if_not_exists: false,
+        };
+        assert_eq!(
+            parse_sql(b"create table books ( id integer, title varchar, pages int )"),
+            Result::Ok((EMPTY, expected))
+        );
+        assert!(parse("create table books ()".as_bytes()).is_err());
+        assert!(parse("create table books (id blob)".as_bytes()).is_err());
+        match parse("create table books (price real)".as_bytes()) {
+            Result::Ok(ParsedSQL::CreateTable { columns, .. }) => {
+                assert_eq!(columns, vec![("price".to_owned(), SQLType::Real)])
+            }
+            other => panic!("not a create table: {:?}", other),
+        }
+        match parse("create table if not exists books (id int)".as_bytes()) {
+            Result::Ok(sql) => {
+                assert_eq!(format!("{}", sql), "create table if not exists books (id integer)")
+            }
+            other => panic!("not a create table: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn can_recognize_pragma_statements() {
+        let expected = ParsedSQL::Pragma {
+            name: "table_info".to_owned(),
+            value: Some(Operand::Column("users".to_owned())),
+        };
+        assert_eq!(parse_sql(b"pragma table_info(users)"), Result::Ok((EMPTY, expected)));
+        let expected = ParsedSQL::Pragma {
+            name: "cache_size".to_owned(),
+            value: Some(Operand::Integer(-10)),
+        };
+        assert_eq!(parse_sql(b"pragma cache_size = -10"), Result::Ok((EMPTY, expected)));
+        assert_eq!(format!("{}", parse(b"pragma page_size").unwrap()), "pragma page_size");
+        assert_eq!(
+            format!("{}", parse(b"pragma = 1").unwrap_err()),
+            "syntax error near =, expected a pragma name."
+        );
+    }
+
+    #[test]
+    fn can_recognize_a_drop_table_statement() {
+        let expected = ParsedSQL::DropTable {
+            database: Some("aux".to_owned()),
+            table: "books".to_owned(),
+            if_exists: true,
+        };
+        assert_eq!(parse_sql(b"drop table if exists aux.books"), Result::Ok((EMPTY, expected)));
+        assert_eq!(format!("{}", parse(b"drop table books").unwrap()), "drop table books");
+        assert!(parse(b"drop table").is_err());
+        assert!(parse(b"drop books").is_err());
+    }
+
+    #[test]
+    fn can_recognize_a_create_index_statement() {
+        let expected = ParsedSQL::CreateIndex {
+            database: Some("aux".to_owned()),
+            name: "idx".to_owned(),
+            table: "users".to_owned(),
+            column: "username".to_owned(),
+        };
+        assert_eq!(
+            parse_sql(b"create index idx on aux.users(username)"),
+            Result::Ok((EMPTY, expected))
+        );
+        assert!(parse("create index on users (username)".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn parse_errors_tell_where_and_what_was_expected() {
+        let error = |input: &str| parse(input.as_bytes()).unwrap_err();
+        assert_eq!(
+            error("select * from"),
+            ParseError {
+                offset: 13,
+                found: String::new(),
+                expected: "a table or (subquery)".to_owned(),
+            }
+        );
+        assert_eq!(
+            error("select id from users where"),
+            ParseError {
+                offset: 26,
+                found: String::new(),
+                expected: "a condition".to_owned(),
+            }
+        );
+        // a failure within a subquery is where it is rather than at the subquery
+        assert_eq!(error("select * from (select * from 'x')").offset, 29);
+        assert_eq!(
+            String::from(error("  delete from users where id = 1 limit 2")),
+            "syntax error near limit, expected the end of the statement."
+        );
+        assert_eq!(
+            String::from(error("insert into users (id, username values (1)")),
+            "syntax error near values, expected )."
+        );
+        assert_eq!(
+            String::from(error("update users set id = 1")),
+            "syntax error near update, expected a statement."
+        );
+        assert_eq!(error(&format!("select {}1", "(".repeat(65))).offset, 71);
+        assert!(parse(b"select id from users ; ").is_ok());
+        assert_eq!(
+            String::from(error("select id from users;;")),
+            "syntax error near ;, expected the end of the statement."
+        );
+    }
+
+    #[test]
+    fn operands_nest_at_most_max_depth_levels() {
+        let error = |input: String| String::from(parse(input.as_bytes()).unwrap_err());
+        let too_deep = "syntax error near 1, expected operands nested at most 100 levels deep.";
+        assert_eq!(error(format!("select 1{}", " + 1".repeat(3_000))), too_deep);
+        assert_eq!(error(format!("select 1{}", " * 1".repeat(MAX_DEPTH))), too_deep);
+        assert_eq!(error(format!("select {}1", "- ".repeat(3_000))),
+            "syntax error near -, expected operands nested at most 100 levels deep.");
+        assert_eq!(error(format!("select 1 from users where {}1", "not ".repeat(100_000))),
+            "syntax error near not, expected operands nested at most 100 levels deep.");
+        assert_eq!(error(format!("select {}", "true and ".repeat(MAX_DEPTH) + "true")),
+            "syntax error near true, expected operands nested at most 100 levels deep.");
+
+        let parsed = parse(format!("select 1{}", " + 1".repeat(MAX_DEPTH - 1)).as_bytes()).unwrap();
+        assert_eq!(parsed.depth(), MAX_DEPTH);
+        let parsed = parse(format!("select {}1", "not ".repeat(MAX_DEPTH - 1)).as_bytes()).unwrap();
+        assert_eq!(parsed.depth(), MAX_DEPTH);
+        // a `not` may still be the name of a column
+        assert_eq!(parse(b"select not not").unwrap().to_string(), "select not not");
+    }
+
+    #[test]
+    fn can_recognize_transaction_statements() {
+        let transaction = |input: &str| parse(input.as_bytes());
+        assert_eq!(transaction("begin"), Result::Ok(ParsedSQL::Transaction(Transaction::Begin)));
+        assert_eq!(
+            transaction(" begin transaction "),
+            Result::Ok(ParsedSQL::Transaction(Transaction::Begin))
+        );
+        assert_eq!(transaction("end"), Result::Ok(ParsedSQL::Transaction(Transaction::Commit)));
+        assert_eq!(
+            transaction("rollback transaction"),
+            Result::Ok(ParsedSQL::Transaction(Transaction::Rollback))
+        );
+        assert_eq!(
+            transaction("commit work").map_err(String::from),
+            Result::Err("syntax error near work, expected the end of the statement.".to_owned())
         );
+        assert_eq!(format!("{}", ParsedSQL::Transaction(Transaction::Commit)), "commit");
     }
 }
@@ -5,98 +5,807 @@
 //! - bool expressions
 //! - columns (basic operand)
 
-use std::str::{FromStr, from_utf8};
-use nom::{alphanumeric, digit, IResult};
+use std::cmp::Ordering;
+use std::fmt;
+use std::num::{ParseFloatError, ParseIntError};
+use std::str;
+use nom::types::CompleteByteSlice;
+use nom::{digit, multispace, Context, Err, ErrorKind, IResult};
 
-#[derive(Debug, PartialEq, Eq)]
+use super::{
+    datetime, identifier, input_str, keyword, name, parse_subquery, quote, write_list, Input,
+    ParsedSQL,
+    EXPECT_I64, EXPECT_SHALLOWER, MAX_DEPTH,
+};
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum Operand {
     /// primitive of integer type, size of 64 bits
     Integer(i64),
 
+    /// primitive of real type, like `2.75`, `.5` or `1e3`
+    Float(f64),
+
+    /// `true` or `false`
+    Boolean(bool),
+
+    /// no value, the operands computed from it are null too
+    Null,
+
+    /// `date '2024-01-01'` or `datetime '2024-01-01 12:00:00'`, in seconds since 1970
+    DateTime(i64),
+
     Parentheses(Box<Operand>),
 
     Add(Box<Operand>, Box<Operand>),
 
+    Sub(Box<Operand>, Box<Operand>),
+
+    Mul(Box<Operand>, Box<Operand>),
+
+    /// the quotient truncated toward zero
+    Div(Box<Operand>, Box<Operand>),
+
+    /// the remainder, with the sign of the dividend
+    Mod(Box<Operand>, Box<Operand>),
+
     String(String),
 
     Column(String),
-    // Alias(Operand, String)
+
+    /// `u.id`, a column of the table named or aliased before the dot
+    QualifiedColumn(String, String),
+
+    /// `*` or `u.*` of a select, every column of the table
+    Star(Option<String>),
+
+    /// 1 if the comparison holds, 0 otherwise
+    Compare(Comparison, Box<Operand>, Box<Operand>),
+
+    /// an aggregate over the rows of a group, `None` for the `*` of `count(*)`
+    Aggregate(Aggregate, Option<Box<Operand>>),
+
+    /// `?N`, or `?` for the one after the highest before it
+    Parameter(Option<usize>),
+
+    /// `id as x`, a result column named other than the operand is written
+    Alias(Box<Operand>, String),
+
+    /// 1 if the operand is one of the values of the single column of the select
+    In(Box<Operand>, Box<ParsedSQL>),
+
+    /// 1 if the operand is one of the values listed, like `id in (1, 2, 3)`. a null
+    /// is not listed, the ones not in the list would be null rather than 0
+    InList(Box<Operand>, Vec<Operand>),
+
+    /// 1 if the select has any result row
+    Exists(Box<ParsedSQL>),
+
+    /// 1 if both operands are true, the second is only evaluated if the first is
+    And(Box<Operand>, Box<Operand>),
+
+    /// 1 if either operand is true, the second is only evaluated if the first is not
+    Or(Box<Operand>, Box<Operand>),
+
+    Not(Box<Operand>),
+
+    /// the number with the sign changed, like `-id`
+    Neg(Box<Operand>),
+
+    /// 1 if the string matches the pattern, where `%` is any number of characters
+    /// and `_` is a single one
+    Like(Box<Operand>, Box<Operand>),
+
+    /// a scalar function called with the arguments, like `upper(name)`
+    Function(String, Vec<Operand>),
 }
 
-named!(_parse_i64( &[u8] ) -> i64, ws!(map_res!(map_res!(digit, from_utf8),FromStr::from_str)));
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
 
-named!(_parse_signed_i64( &[u8] ) -> i64,
-    ws!(map!(
-        pair!(alt!(tag!("+") | tag!("-") | value!(&b"+"[..])), _parse_i64),
-        |(sign, value)| match sign {
-            s if s == &b"-"[..] => -value,
-            _ => value
+impl Comparison {
+    /// whether the comparison holds for operands ordered like given.
+    pub fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Equal => ordering == Ordering::Equal,
+            Comparison::NotEqual => ordering != Ordering::Equal,
+            Comparison::Less => ordering == Ordering::Less,
+            Comparison::LessOrEqual => ordering != Ordering::Greater,
+            Comparison::Greater => ordering == Ordering::Greater,
+            Comparison::GreaterOrEqual => ordering != Ordering::Less,
         }
-    ))
-);
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Aggregate {
+    Count,
+    Sum,
+    /// the mean of the values, a real
+    Avg,
+    Min,
+    Max,
+}
+
+impl fmt::Display for Aggregate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Aggregate::Count => f.write_str("count"),
+            Aggregate::Sum => f.write_str("sum"),
+            Aggregate::Avg => f.write_str("avg"),
+            Aggregate::Min => f.write_str("min"),
+            Aggregate::Max => f.write_str("max"),
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            Comparison::Equal => "=",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        };
+        f.write_str(symbol)
+    }
+}
+
+/// the operand as written in sql, which names result columns.
+impl Operand {
+    /// call given function with the operand and then with the operands within it,
+    /// including the ones of its subqueries, in the order they are written.
+    pub fn visit_mut(
+        &mut self,
+        visit: &mut dyn FnMut(&mut Operand) -> Result<(), String>,
+    ) -> Result<(), String> {
+        visit(self)?;
+        match self {
+            Operand::Parentheses(op)
+            | Operand::Alias(op, _)
+            | Operand::Not(op)
+            | Operand::Neg(op)
+            | Operand::Aggregate(_, Some(op)) => op.visit_mut(visit),
+            Operand::Add(op1, op2)
+            | Operand::Sub(op1, op2)
+            | Operand::Mul(op1, op2)
+            | Operand::Div(op1, op2)
+            | Operand::Mod(op1, op2)
+            | Operand::Compare(_, op1, op2)
+            | Operand::And(op1, op2)
+            | Operand::Or(op1, op2)
+            | Operand::Like(op1, op2) => {
+                op1.visit_mut(visit)?;
+                op2.visit_mut(visit)
+            }
+            Operand::In(op, subquery) => {
+                op.visit_mut(visit)?;
+                subquery.visit_operands_mut(visit)
+            }
+            Operand::InList(op, values) => {
+                op.visit_mut(visit)?;
+                for value in values.iter_mut() {
+                    value.visit_mut(visit)?;
+                }
+                Result::Ok(())
+            }
+            Operand::Exists(subquery) => subquery.visit_operands_mut(visit),
+            Operand::Function(_, args) => {
+                for arg in args.iter_mut() {
+                    arg.visit_mut(visit)?;
+                }
+                Result::Ok(())
+            }
+            Operand::Integer(_)
+            | Operand::Float(_)
+            | Operand::Boolean(_)
+            | Operand::Null
+            | Operand::DateTime(_)
+            | Operand::String(_)
+            | Operand::Column(_)
+            | Operand::QualifiedColumn(..)
+            | Operand::Star(_)
+            | Operand::Aggregate(_, None)
+            | Operand::Parameter(_) => Result::Ok(()),
+        }
+    }
 
-named!(parse_integer_operand(&[u8]) -> Operand,
+    /// the levels of operands nested in this one counting itself, the operands of
+    /// its subqueries included.
+    pub fn depth(&self) -> usize {
+        let within = match self {
+            Operand::Parentheses(op)
+            | Operand::Alias(op, _)
+            | Operand::Not(op)
+            | Operand::Neg(op)
+            | Operand::Aggregate(_, Some(op)) => op.depth(),
+            Operand::Add(op1, op2)
+            | Operand::Sub(op1, op2)
+            | Operand::Mul(op1, op2)
+            | Operand::Div(op1, op2)
+            | Operand::Mod(op1, op2)
+            | Operand::Compare(_, op1, op2)
+            | Operand::And(op1, op2)
+            | Operand::Or(op1, op2)
+            | Operand::Like(op1, op2) => op1.depth().max(op2.depth()),
+            Operand::In(op, subquery) => op.depth().max(subquery.depth()),
+            Operand::InList(op, values) => {
+                values.iter().map(Operand::depth).fold(op.depth(), usize::max)
+            }
+            Operand::Exists(subquery) => subquery.depth(),
+            Operand::Function(_, args) => args.iter().map(Operand::depth).max().unwrap_or(0),
+            Operand::Integer(_)
+            | Operand::Float(_)
+            | Operand::Boolean(_)
+            | Operand::Null
+            | Operand::DateTime(_)
+            | Operand::String(_)
+            | Operand::Column(_)
+            | Operand::QualifiedColumn(..)
+            | Operand::Star(_)
+            | Operand::Aggregate(_, None)
+            | Operand::Parameter(_) => 0,
+        };
+        within + 1
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Integer(v) => write!(f, "{}", v),
+            Operand::Float(v) => write!(f, "{:?}", v),
+            Operand::Boolean(v) => write!(f, "{}", v),
+            Operand::Null => f.write_str("null"),
+            Operand::DateTime(v) => write!(f, "datetime '{}'", datetime::format(*v)),
+            Operand::Parentheses(op) => write!(f, "({})", op),
+            Operand::Add(op1, op2) => write!(f, "{} + {}", op1, op2),
+            Operand::Sub(op1, op2) => write!(f, "{} - {}", op1, op2),
+            Operand::Mul(op1, op2) => write!(f, "{} * {}", op1, op2),
+            Operand::Div(op1, op2) => write!(f, "{} / {}", op1, op2),
+            Operand::Mod(op1, op2) => write!(f, "{} % {}", op1, op2),
+            Operand::String(str) => f.write_str(&quote(str)),
+            Operand::Column(column) => write!(f, "{}", column),
+            Operand::QualifiedColumn(table, column) => write!(f, "{}.{}", table, column),
+            Operand::Star(Some(table)) => write!(f, "{}.*", table),
+            Operand::Star(None) => f.write_str("*"),
+            Operand::Compare(comparison, op1, op2) => write!(f, "{} {} {}", op1, comparison, op2),
+            Operand::Aggregate(aggregate, Some(op)) => write!(f, "{}({})", aggregate, op),
+            Operand::Aggregate(aggregate, None) => write!(f, "{}(*)", aggregate),
+            Operand::Parameter(Some(index)) => write!(f, "?{}", index),
+            Operand::Parameter(None) => f.write_str("?"),
+            Operand::Alias(_, name) => f.write_str(name),
+            Operand::In(op, subquery) => write!(f, "{} in ({})", op, subquery),
+            Operand::InList(op, values) => {
+                write!(f, "{} in (", op)?;
+                write_list(f, values)?;
+                f.write_str(")")
+            }
+            Operand::Exists(subquery) => write!(f, "exists ({})", subquery),
+            Operand::And(op1, op2) => write!(f, "{} and {}", op1, op2),
+            Operand::Or(op1, op2) => write!(f, "{} or {}", op1, op2),
+            Operand::Not(op) => write!(f, "not {}", op),
+            // `--` would start a comment
+            Operand::Neg(op) => match op.to_string() {
+                op if op.starts_with('-') => write!(f, "- {}", op),
+                op => write!(f, "-{}", op),
+            },
+            Operand::Like(op, pattern) => write!(f, "{} like {}", op, pattern),
+            Operand::Function(name, args) => {
+                write!(f, "{}(", name)?;
+                write_list(f, args)?;
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+/// an integer with an optional sign, the sign goes with the digits so the
+/// smallest i64 is written as it is. digits out of range fail the statement.
+fn _parse_signed_i64(input: Input) -> IResult<Input, i64> {
+    let (rest, (sign, digits)) = ws!(
+        input,
+        pair!(alt!(tag!("+") | tag!("-") | value!(CompleteByteSlice(b"+"))), digit)
+    )?;
+    let text = format!(
+        "{}{}",
+        String::from_utf8_lossy(sign.0),
+        String::from_utf8_lossy(digits.0)
+    );
+    match text.parse() {
+        Result::Ok(value) => Result::Ok((rest, value)),
+        Result::Err(_) => Result::Err(Err::Failure(Context::Code(digits, ErrorKind::Custom(EXPECT_I64)))),
+    }
+}
+
+named!(parse_integer_operand<Input, Operand>,
     map!(_parse_signed_i64, |v| Operand::Integer(v)));
 
-named!(parse_parens_operand(&[u8]) -> Operand,
+// `2.75`, `5.`, `.5` or with an exponent like `1e3` and `2.5E-3`, the digits
+// alone are an integer
+named!(parse_float_operand<Input, Operand>,
+    ws!(map_res!(
+        map_res!(
+            recognize!(tuple!(
+                opt!(alt!(tag!("+") | tag!("-"))),
+                alt!(
+                    recognize!(pair!(digit, opt!(complete!(pair!(tag!("."), opt!(complete!(digit))))))) |
+                    recognize!(pair!(tag!("."), digit))
+                ),
+                opt!(complete!(tuple!(
+                    alt!(tag!("e") | tag!("E")),
+                    opt!(alt!(tag!("+") | tag!("-"))),
+                    digit
+                )))
+            )),
+            input_str
+        ),
+        |str: &str| if str.contains(['.', 'e', 'E']) {
+            str.parse().map(Operand::Float).map_err(|e: ParseFloatError| e.to_string())
+        } else {
+            Result::Err(format!("{} is an integer", str))
+        }
+    ))
+);
+
+named!(parse_parens_operand<Input, Operand>,
     ws!(map!(
         tuple!(tag!("("), parse_operand, tag!(")")),
         |(_, op, _)| Operand::Parentheses(Box::new(op))
     ))
 );
 
-named!(parse_column_operand(&[u8]) -> Operand,
+named!(parse_column_operand<Input, Operand>,
+    ws!(map!(
+        pair!(name, opt!(complete!(preceded!(tag!("."), name)))),
+        |(first, second): (String, Option<String>)| match second {
+            Some(column) => Operand::QualifiedColumn(first, column),
+            None => Operand::Column(first),
+        }
+    ))
+);
+
+// the text between single quotes, a quote inside is written twice like `'it''s'`
+named!(string_literal<Input, String>,
+    map_res!(
+        delimited!(tag!("'"), recognize!(many0!(alt!(is_not!("'") | tag!("''")))), tag!("'")),
+        |bytes| input_str(bytes).map(|str| str.replace("''", "'"))
+    )
+);
+
+named!(parse_str_operand<Input, Operand>,
+    ws!(map!(string_literal, Operand::String))
+);
+
+// `date 'YYYY-MM-DD'`, `datetime` or `timestamp 'YYYY-MM-DD HH:MM:SS'`, before the
+// columns which would take the keywords as names
+named!(parse_datetime_operand<Input, Operand>,
+    map_res!(
+        preceded!(
+            opt!(multispace),
+            ws!(pair!(
+                alt!(
+                    call!(keyword, "date") |
+                    call!(keyword, "datetime") |
+                    call!(keyword, "timestamp")
+                ),
+                string_literal
+            ))
+        ),
+        |(_, text): (Input, String)| -> Result<Operand, String> {
+            datetime::parse(&text)
+                .map(Operand::DateTime)
+                .ok_or_else(|| format!("invalid datetime '{}'.", text))
+        }
+    )
+);
+
+named!(parse_aggregate<Input, Aggregate>,
+    alt!(
+        value!(Aggregate::Count, call!(keyword, "count")) |
+        value!(Aggregate::Sum, call!(keyword, "sum")) |
+        value!(Aggregate::Avg, call!(keyword, "avg")) |
+        value!(Aggregate::Min, call!(keyword, "min")) |
+        value!(Aggregate::Max, call!(keyword, "max"))
+    )
+);
+
+// `count(*)` or an aggregate of an operand like `max(id)`, it goes before the
+// functions and columns which would take its name
+named!(parse_aggregate_operand<Input, Operand>,
+    preceded!(
+        opt!(multispace),
+        alt!(
+            map!(
+                ws!(tuple!(call!(keyword, "count"), tag!("("), tag!("*"), tag!(")"))),
+                |_| Operand::Aggregate(Aggregate::Count, None)
+            ) |
+            map!(
+                ws!(pair!(parse_aggregate, ws!(delimited!(tag!("("), parse_or_operand, tag!(")"))))),
+                |(aggregate, op)| Operand::Aggregate(aggregate, Some(Box::new(op)))
+            )
+        )
+    )
+);
+
+// `?`, `?N` or `$N`, which is the same parameter as `?N`
+named!(parse_parameter_operand<Input, Operand>,
     ws!(map_res!(
-        alphanumeric,
-        |bytes| from_utf8(bytes).map(|str| Operand::Column(str.to_owned()))
+        alt!(
+            preceded!(tag!("?"), opt!(complete!(digit))) |
+            map!(preceded!(tag!("$"), digit), Some)
+        ),
+        |digits: Option<Input>| match digits {
+            Some(digits) => input_str(digits)
+                .map_err(|e| e.to_string())
+                .and_then(|digits| digits.parse().map_err(|e: ParseIntError| e.to_string()))
+                .map(|index| Operand::Parameter(Some(index))),
+            None => Result::Ok(Operand::Parameter(None)),
+        }
     ))
 );
 
-named!(parse_basic_operand(&[u8]) -> Operand,
-    alt!(parse_integer_operand | parse_parens_operand|parse_column_operand)
+// `true`, `false` and `null`, before the columns which would take them as names
+named!(parse_constant_operand<Input, Operand>,
+    delimited!(
+        opt!(multispace),
+        alt!(
+            value!(Operand::Boolean(true), call!(keyword, "true")) |
+            value!(Operand::Boolean(false), call!(keyword, "false")) |
+            value!(Operand::Null, call!(keyword, "null"))
+        ),
+        opt!(complete!(multispace))
+    )
+);
+
+// `exists (select ...)`, before the columns like `count(*)`
+named!(parse_exists_operand<Input, Operand>,
+    map!(
+        ws!(preceded!(call!(keyword, "exists"), parse_subquery)),
+        |subquery| Operand::Exists(Box::new(subquery))
+    )
 );
 
-named!(parse_str_operand(&[u8]) -> Operand,
+// `upper(name)`, before the columns which would take the name of the function
+named!(parse_function_operand<Input, Operand>,
     ws!(map_res!(
-        delimited!(tag!("'"), is_not!("'"), tag!("'")),
-        |bytes| from_utf8(bytes).map(|str| Operand::String(str.to_owned()))
+        pair!(
+            identifier,
+            ws!(delimited!(
+                tag!("("),
+                separated_list_complete!(tag!(","), parse_or_operand),
+                tag!(")")
+            ))
+        ),
+        |(name, args): (Input, Vec<Operand>)| {
+            input_str(name).map(|name| Operand::Function(name.to_owned(), args))
+        }
     ))
 );
 
-named!(parse_add_operand(&[u8]) -> Operand,
-    map!(tuple!(parse_basic_operand, ws!(tag!("+")), parse_basic_operand),
-        |(v1, _, v2)| Operand::Add(Box::new(v1), Box::new(v2))
+named!(parse_basic_operand<Input, Operand>,
+    alt!(
+        parse_float_operand |
+        parse_integer_operand |
+        parse_parens_operand |
+        parse_str_operand |
+        parse_parameter_operand |
+        parse_aggregate_operand |
+        parse_exists_operand |
+        parse_constant_operand |
+        parse_datetime_operand |
+        parse_function_operand |
+        parse_column_operand
     )
 );
 
-named!(pub parse_operand(&[u8]) -> Operand,
-    alt_complete!(parse_add_operand | parse_basic_operand | parse_str_operand)
+/// the failure of an operand starting at `input` that would nest deeper than
+/// `MAX_DEPTH`, it is never built since dropping it would recurse as deep.
+fn too_deep<O>(input: Input) -> IResult<Input, O> {
+    Result::Err(Err::Failure(Context::Code(input, ErrorKind::Custom(EXPECT_SHALLOWER))))
+}
+
+/// `op` within given number of operands like `not` or `-`, each nesting it one
+/// level deeper. they start at `input`, `rest` is the input after `op`.
+fn wrap<'a>(
+    input: Input<'a>,
+    rest: Input<'a>,
+    levels: usize,
+    op: Operand,
+    wrapper: fn(Box<Operand>) -> Operand,
+) -> IResult<Input<'a>, Operand> {
+    if op.depth() + levels > MAX_DEPTH {
+        return too_deep(input);
+    }
+    Result::Ok((rest, (0..levels).fold(op, |op, _| wrapper(Box::new(op)))))
+}
+
+/// operands joined from the left like `1 + 2 - 3`, the chain starts at `input`.
+fn fold_chain<'a, T>(
+    input: Input<'a>,
+    rest: Input<'a>,
+    first: Operand,
+    chain: Vec<(T, Operand)>,
+    join: fn(T, Operand, Operand) -> Operand,
+) -> IResult<Input<'a>, Operand> {
+    let depth = chain.iter().fold(first.depth(), |depth, (_, op)| depth.max(op.depth()) + 1);
+    if depth > MAX_DEPTH {
+        return too_deep(input);
+    }
+    let op = chain.into_iter().fold(first, |op1, (operator, op2)| join(operator, op1, op2));
+    Result::Ok((rest, op))
+}
+
+/// a basic operand with the signs before it, a sign before a number literal is
+/// taken by the literal. the signs are counted rather than recursed on, there may
+/// be any number of them.
+fn parse_unary_operand(input: Input) -> IResult<Input, Operand> {
+    let mut start = input;
+    let mut signs = 0;
+    loop {
+        match parse_basic_operand(start) {
+            Result::Ok((rest, op)) => return wrap(input, rest, signs, op, Operand::Neg),
+            Result::Err(Err::Error(_)) => match ws!(start, tag!("-")) {
+                Result::Ok((rest, _)) => {
+                    start = rest;
+                    signs += 1;
+                }
+                Result::Err(_) => {
+                    return Result::Err(Err::Error(Context::Code(input, ErrorKind::Alt)));
+                }
+            },
+            Result::Err(e) => return Result::Err(e),
+        }
+    }
+}
+
+/// the operand applying given arithmetic operator to two others.
+fn arithmetic(operator: Input, op1: Operand, op2: Operand) -> Operand {
+    let (op1, op2) = (Box::new(op1), Box::new(op2));
+    match operator.0 {
+        b"+" => Operand::Add(op1, op2),
+        b"-" => Operand::Sub(op1, op2),
+        b"*" => Operand::Mul(op1, op2),
+        b"/" => Operand::Div(op1, op2),
+        _ => Operand::Mod(op1, op2),
+    }
+}
+
+// unary operands multiplied, divided or taken the remainder of, from the left.
+// every operand is parsed once whether or not an operator follows, trying the
+// product before the basic operand would parse nested parentheses an exponential
+// number of times
+fn parse_mul_operand(input: Input) -> IResult<Input, Operand> {
+    let (rest, (first, chain)) = pair!(
+        input,
+        parse_unary_operand,
+        many0!(complete!(pair!(
+            ws!(alt!(tag!("*") | tag!("/") | tag!("%"))),
+            parse_unary_operand
+        )))
+    )?;
+    fold_chain(input, rest, first, chain, arithmetic)
+}
+
+// products added or subtracted from the left, they bind looser than `*`
+fn parse_add_operand(input: Input) -> IResult<Input, Operand> {
+    let (rest, (first, chain)) = pair!(
+        input,
+        parse_mul_operand,
+        many0!(complete!(pair!(ws!(alt!(tag!("+") | tag!("-"))), parse_mul_operand)))
+    )?;
+    fold_chain(input, rest, first, chain, arithmetic)
+}
+
+// the two character operators go first, `<` would take the start of `<=`
+named!(parse_comparison<Input, Comparison>,
+    ws!(alt!(
+        value!(Comparison::LessOrEqual, tag!("<=")) |
+        value!(Comparison::GreaterOrEqual, tag!(">=")) |
+        value!(Comparison::NotEqual, tag!("!=")) |
+        value!(Comparison::NotEqual, tag!("<>")) |
+        value!(Comparison::Equal, tag!("=")) |
+        value!(Comparison::Less, tag!("<")) |
+        value!(Comparison::Greater, tag!(">"))
+    ))
+);
+
+/// what a sum is compared to.
+enum Comparand {
+    Operand(Comparison, Operand),
+    /// the values of a select, with `in`
+    In(Box<ParsedSQL>),
+    /// the values listed, with `in`
+    InList(Vec<Operand>),
+    /// a pattern, with `like`
+    Like(Operand),
+    /// `not in` and `not like`
+    Not(Box<Comparand>),
+}
+
+impl Comparand {
+    /// the operand comparing given sum to this.
+    fn compare(self, op: Operand) -> Operand {
+        match self {
+            Comparand::Operand(comparison, v2) => Operand::Compare(comparison, Box::new(op), Box::new(v2)),
+            Comparand::In(subquery) => Operand::In(Box::new(op), subquery),
+            Comparand::InList(values) => Operand::InList(Box::new(op), values),
+            Comparand::Like(pattern) => Operand::Like(Box::new(op), Box::new(pattern)),
+            Comparand::Not(comparand) => Operand::Not(Box::new(comparand.compare(op))),
+        }
+    }
+}
+
+named!(parse_value_list<Input, Vec<Operand>>,
+    ws!(delimited!(
+        tag!("("),
+        separated_nonempty_list_complete!(tag!(","), parse_add_operand),
+        tag!(")")
+    ))
+);
+
+// the comparands which `not` may go before, a select goes before a list of values
+// which would take its parentheses
+named!(parse_negatable_comparand<Input, Comparand>,
+    alt!(
+        map!(complete!(ws!(preceded!(call!(keyword, "in"), parse_subquery))), |subquery| Comparand::In(Box::new(subquery))) |
+        map!(complete!(ws!(preceded!(call!(keyword, "in"), parse_value_list))), Comparand::InList) |
+        map!(complete!(ws!(preceded!(call!(keyword, "like"), parse_add_operand))), Comparand::Like)
+    )
+);
+
+named!(parse_comparand<Input, Comparand>,
+    alt!(
+        map!(
+            complete!(pair!(parse_comparison, parse_add_operand)),
+            |(comparison, op)| Comparand::Operand(comparison, op)
+        ) |
+        parse_negatable_comparand |
+        map!(
+            complete!(ws!(preceded!(call!(keyword, "not"), parse_negatable_comparand))),
+            |comparand| Comparand::Not(Box::new(comparand))
+        )
+    )
+);
+
+// a sum with an optional comparison to another sum
+named!(parse_compare_operand<Input, Operand>,
+    map!(
+        pair!(parse_add_operand, opt!(complete!(parse_comparand))),
+        |(v1, rest)| match rest {
+            Some(comparand) => comparand.compare(v1),
+            None => v1,
+        }
+    )
+);
+
+/// `not` binds looser than the comparisons, `not a = b` is `not (a = b)`. like
+/// the signs of `parse_unary_operand` the `not`s are counted, the comparison after
+/// the most of them wins.
+fn parse_not_operand(input: Input) -> IResult<Input, Operand> {
+    // where the comparison would start after none, one, two... `not`s
+    let mut starts = vec![input];
+    while let Result::Ok((rest, _)) = ws!(starts[starts.len() - 1], call!(keyword, "not")) {
+        starts.push(rest);
+    }
+    for (nots, &start) in starts.iter().enumerate().rev() {
+        match parse_compare_operand(start) {
+            Result::Ok((rest, op)) => return wrap(input, rest, nots, op, Operand::Not),
+            Result::Err(Err::Error(_)) => continue,
+            Result::Err(e) => return Result::Err(e),
+        }
+    }
+    Result::Err(Err::Error(Context::Code(input, ErrorKind::Alt)))
+}
+
+// `and` binds tighter than `or`, both group from the left
+fn parse_and_operand(input: Input) -> IResult<Input, Operand> {
+    let (rest, (first, chain)) = pair!(
+        input,
+        parse_not_operand,
+        many0!(complete!(map!(
+            ws!(preceded!(call!(keyword, "and"), parse_not_operand)),
+            |op| ((), op)
+        )))
+    )?;
+    fold_chain(input, rest, first, chain, |_, op1, op2| Operand::And(Box::new(op1), Box::new(op2)))
+}
+
+fn parse_or_operand(input: Input) -> IResult<Input, Operand> {
+    let (rest, (first, chain)) = pair!(
+        input,
+        parse_and_operand,
+        many0!(complete!(map!(
+            ws!(preceded!(call!(keyword, "or"), parse_and_operand)),
+            |op| ((), op)
+        )))
+    )?;
+    fold_chain(input, rest, first, chain, |_, op1, op2| Operand::Or(Box::new(op1), Box::new(op2)))
+}
+
+named!(pub parse_operand<Input, Operand>,
+    complete!(parse_or_operand)
 );
 
 #[cfg(test)]
 mod test {
     use super::*;
-    const EMPTY: &[u8] = &[0u8; 0];
+    const EMPTY: Input = CompleteByteSlice(b"");
+
+    #[test]
+    fn operands_display_as_written() {
+        let (_, op) = parse_operand(CompleteByteSlice(b"3 + (4+5)")).unwrap();
+        assert_eq!(format!("{}", op), "3 + (4 + 5)");
+        let (_, op) = parse_operand(CompleteByteSlice(b" 'hello, rdb!' ")).unwrap();
+        assert_eq!(format!("{}", op), "'hello, rdb!'");
+    }
 
     #[test]
     fn can_parse_integer() {
-        assert_eq!(_parse_i64(b"42"), IResult::Done(EMPTY, 42));
-        assert_eq!(_parse_i64(b" 42"), IResult::Done(EMPTY, 42));
+        assert_eq!(_parse_signed_i64(CompleteByteSlice(b"42")), Result::Ok((EMPTY, 42)));
+        assert_eq!(_parse_signed_i64(CompleteByteSlice(b" 42")), Result::Ok((EMPTY, 42)));
     }
 
     #[test]
     fn can_parse_signed_integer() {
-        assert_eq!(_parse_signed_i64(b"+42"), IResult::Done(EMPTY, 42));
-        assert_eq!(_parse_signed_i64(b"-42"), IResult::Done(EMPTY, -42));
-        assert_eq!(_parse_signed_i64(b" - 42 "), IResult::Done(EMPTY, -42));
+        assert_eq!(_parse_signed_i64(CompleteByteSlice(b"+42")), Result::Ok((EMPTY, 42)));
+        assert_eq!(_parse_signed_i64(CompleteByteSlice(b"-42")), Result::Ok((EMPTY, -42)));
+        assert_eq!(_parse_signed_i64(CompleteByteSlice(b" - 42 ")), Result::Ok((EMPTY, -42)));
+        assert_eq!(
+            _parse_signed_i64(CompleteByteSlice(b"-9223372036854775808")),
+            Result::Ok((EMPTY, i64::MIN))
+        );
+        assert_eq!(
+            _parse_signed_i64(CompleteByteSlice(b"9223372036854775808")),
+            Result::Err(Err::Failure(Context::Code(
+                CompleteByteSlice(b"9223372036854775808"),
+                ErrorKind::Custom(EXPECT_I64)
+            )))
+        );
     }
 
     #[test]
     fn can_recognize_a_integer_operand() {
         assert_eq!(
-            parse_integer_operand(b"-42"),
-            IResult::Done(EMPTY, Operand::Integer(-42))
+            parse_integer_operand(CompleteByteSlice(b"-42")),
+            Result::Ok((EMPTY, Operand::Integer(-42)))
+        );
+    }
+
+    #[test]
+    fn can_recognize_a_float_operand() {
+        assert_eq!(
+            parse_basic_operand(CompleteByteSlice(b" -2.75 ")),
+            Result::Ok((EMPTY, Operand::Float(-2.75)))
+        );
+        let float = |input: &'static str| parse_basic_operand(CompleteByteSlice(input.as_bytes()));
+        assert_eq!(float("3."), Result::Ok((EMPTY, Operand::Float(3.0))));
+        assert_eq!(float(".5"), Result::Ok((EMPTY, Operand::Float(0.5))));
+        assert_eq!(float("-.5"), Result::Ok((EMPTY, Operand::Float(-0.5))));
+        assert_eq!(float("1e3"), Result::Ok((EMPTY, Operand::Float(1000.0))));
+        assert_eq!(float("2.5E-3"), Result::Ok((EMPTY, Operand::Float(0.0025))));
+        // an exponent without digits is not one
+        assert_eq!(float("3e"), Result::Ok((CompleteByteSlice(b"e"), Operand::Integer(3))));
+    }
+
+    #[test]
+    fn can_recognize_a_datetime_operand() {
+        assert_eq!(
+            parse_basic_operand(CompleteByteSlice(b" date '1970-01-02' ")),
+            Result::Ok((EMPTY, Operand::DateTime(86_400)))
         );
+        let (_, op) = parse_basic_operand(CompleteByteSlice(b"timestamp '2024-01-01T08:00:00'")).unwrap();
+        assert_eq!(format!("{}", op), "datetime '2024-01-01 08:00:00'");
+        assert!(parse_datetime_operand(CompleteByteSlice(b"date '2024-02-30'")).is_err());
     }
 
     #[test]
@@ -106,17 +815,62 @@ mod test {
             Box::new(Operand::Integer(43)),
         );
         assert_eq!(
-            parse_add_operand(b" 42 + 43 "),
-            IResult::Done(EMPTY, expected)
+            parse_add_operand(CompleteByteSlice(b" 42 + 43 ")),
+            Result::Ok((EMPTY, expected))
         );
     }
 
+    #[test]
+    fn arithmetic_operators_follow_precedence_and_group_from_the_left() {
+        let parse = |input: &str| {
+            let (rest, op) = parse_add_operand(CompleteByteSlice(input.as_bytes())).unwrap();
+            assert_eq!(rest, EMPTY);
+            op
+        };
+        let int = |v| Box::new(Operand::Integer(v));
+        assert_eq!(
+            parse("1 + 2 * 3"),
+            Operand::Add(int(1), Box::new(Operand::Mul(int(2), int(3))))
+        );
+        assert_eq!(
+            parse("8 - 4 - 2"),
+            Operand::Sub(Box::new(Operand::Sub(int(8), int(4))), int(2))
+        );
+        assert_eq!(
+            parse("7 % 4 / 2*id"),
+            Operand::Mul(
+                Box::new(Operand::Div(Box::new(Operand::Mod(int(7), int(4))), int(2))),
+                Box::new(Operand::Column("id".to_owned()))
+            )
+        );
+        assert_eq!(parse("id-1"), Operand::Sub(Box::new(Operand::Column("id".to_owned())), int(1)));
+        assert_eq!(format!("{}", parse("(1 - 2) * 3")), "(1 - 2) * 3");
+    }
+
+    #[test]
+    fn unary_minus_negates_any_operand_and_binds_tighter_than_products() {
+        let parse = |input: &str| {
+            let (rest, op) = parse_add_operand(CompleteByteSlice(input.as_bytes())).unwrap();
+            assert_eq!(rest, EMPTY);
+            op
+        };
+        let id = || Box::new(Operand::Column("id".to_owned()));
+        assert_eq!(parse("-id"), Operand::Neg(id()));
+        assert_eq!(parse(" - id * 2"), Operand::Mul(Box::new(Operand::Neg(id())), Box::new(Operand::Integer(2))));
+        assert_eq!(parse("1 - -id"), Operand::Sub(Box::new(Operand::Integer(1)), Box::new(Operand::Neg(id()))));
+        assert_eq!(parse("- -id"), Operand::Neg(Box::new(Operand::Neg(id()))));
+        assert_eq!(format!("{}", parse("- -id")), "- -id");
+        assert_eq!(format!("{}", parse("-(-1)")), "-(-1)");
+        assert_eq!(parse("-42"), Operand::Integer(-42));
+        assert_eq!(format!("{}", parse("-(id + 1)")), "-(id + 1)");
+    }
+
     #[test]
     fn can_recognize_a_parens_operand() {
         let expected = Operand::Parentheses(Box::new(Operand::Integer(42)));
         assert_eq!(
-            parse_parens_operand(b" ( 42 ) "),
-            IResult::Done(EMPTY, expected)
+            parse_parens_operand(CompleteByteSlice(b" ( 42 ) ")),
+            Result::Ok((EMPTY, expected))
         );
     }
 
@@ -124,34 +878,73 @@ mod test {
     fn can_recognize_a_column_operand() {
         let expected = Operand::Column("name".to_owned());
         assert_eq!(
-            parse_column_operand(b"name"),
-            IResult::Done(EMPTY, expected)
+            parse_column_operand(CompleteByteSlice(b"name")),
+            Result::Ok((EMPTY, expected))
         );
     }
 
     #[test]
     fn can_parse_basic_operands() {
         assert_eq!(
-            parse_basic_operand(b" -42 "),
-            IResult::Done(EMPTY, Operand::Integer(-42))
+            parse_basic_operand(CompleteByteSlice(b" -42 ")),
+            Result::Ok((EMPTY, Operand::Integer(-42)))
         );
 
         let expected = Operand::Parentheses(Box::new(Operand::Integer(-42)));
         assert_eq!(
-            parse_basic_operand(b" (-42 ) "),
-            IResult::Done(EMPTY, expected)
+            parse_basic_operand(CompleteByteSlice(b" (-42 ) ")),
+            Result::Ok((EMPTY, expected))
         );
 
         let expected = Operand::Column("id".to_owned());
-        assert_eq!(parse_basic_operand(b"id"), IResult::Done(EMPTY, expected));
+        assert_eq!(parse_basic_operand(CompleteByteSlice(b"id")), Result::Ok((EMPTY, expected)));
     }
 
     #[test]
     fn can_recognize_a_string_literal() {
         let expected = Operand::String(" as df ".to_owned());
         assert_eq!(
-            parse_str_operand(b" ' as df ' "),
-            IResult::Done(EMPTY, expected)
+            parse_str_operand(CompleteByteSlice(b" ' as df ' ")),
+            Result::Ok((EMPTY, expected))
+        );
+
+        let expected = Operand::String("it's".to_owned());
+        assert_eq!(
+            parse_str_operand(CompleteByteSlice(b"'it''s'")),
+            Result::Ok((EMPTY, expected.clone()))
+        );
+        assert_eq!(format!("{}", expected), "'it''s'");
+        assert_eq!(
+            parse_str_operand(CompleteByteSlice(b"''''")),
+            Result::Ok((EMPTY, Operand::String("'".to_owned())))
+        );
+        assert_eq!(
+            parse_str_operand(CompleteByteSlice(b"''")),
+            Result::Ok((EMPTY, Operand::String(String::new())))
+        );
+        assert!(parse_str_operand(CompleteByteSlice(b"'it''s")).is_err());
+    }
+
+    #[test]
+    fn can_recognize_function_calls() {
+        let expected = Operand::Function(
+            "abs".to_owned(),
+            vec![Operand::Sub(
+                Box::new(Operand::Column("id".to_owned())),
+                Box::new(Operand::Integer(3)),
+            )],
+        );
+        assert_eq!(parse_operand(CompleteByteSlice(b" abs ( id - 3 ) ")), Result::Ok((EMPTY, expected.clone())));
+        assert_eq!(format!("{}", expected), "abs(id - 3)");
+
+        let expected = Operand::Function(
+            "f".to_owned(),
+            vec![Operand::Function("upper".to_owned(), vec![Operand::Column("name".to_owned())]), Operand::Integer(1)],
+        );
+        assert_eq!(parse_operand(CompleteByteSlice(b"f(upper(name), 1)")), Result::Ok((EMPTY, expected)));
+        assert_eq!(
+            parse_operand(CompleteByteSlice(b"now( )")),
+            Result::Ok((EMPTY, Operand::Function("now".to_owned(), Vec::new())))
         );
     }
 
@@ -162,11 +955,152 @@ mod test {
         let parens_ops = Operand::Parentheses(Box::new(add_ops));
         let mut expected = Operand::Add(Box::new(Operand::Integer(-42)), Box::new(parens_ops));
         assert_eq!(
-            parse_operand(b"-42 + (5 + id)"),
-            IResult::Done(EMPTY, expected)
+            parse_operand(CompleteByteSlice(b"-42 + (5 + id)")),
+            Result::Ok((EMPTY, expected))
         );
 
         expected = Operand::String("nihao.".to_owned());
-        assert_eq!(parse_operand(b"'nihao.'"), IResult::Done(EMPTY, expected))
+        assert_eq!(parse_operand(CompleteByteSlice(b"'nihao.'")), Result::Ok((EMPTY, expected)))
+    }
+
+    #[test]
+    fn can_recognize_a_count_operand() {
+        let expected = Operand::Aggregate(Aggregate::Count, None);
+        assert_eq!(
+            parse_aggregate_operand(CompleteByteSlice(b" count ( * ) ")),
+            Result::Ok((EMPTY, expected))
+        );
+        let (_, op) = parse_operand(CompleteByteSlice(b"count(*) + 1")).unwrap();
+        assert_eq!(format!("{}", op), "count(*) + 1");
+        let expected = Operand::Column("counter".to_owned());
+        assert_eq!(parse_basic_operand(CompleteByteSlice(b"counter")), Result::Ok((EMPTY, expected)));
+
+        let expected = Operand::Aggregate(
+            Aggregate::Max,
+            Some(Box::new(Operand::Add(
+                Box::new(Operand::Column("id".to_owned())),
+                Box::new(Operand::Integer(1)),
+            ))),
+        );
+        assert_eq!(parse_operand(CompleteByteSlice(b" max ( id + 1 ) ")), Result::Ok((EMPTY, expected.clone())));
+        assert_eq!(format!("{}", expected), "max(id + 1)");
+        let (_, op) = parse_operand(CompleteByteSlice(b"count(email)")).unwrap();
+        assert_eq!(op, Operand::Aggregate(Aggregate::Count, Some(Box::new(Operand::Column("email".to_owned())))));
+        let expected = Operand::Column("sum".to_owned());
+        assert_eq!(parse_basic_operand(CompleteByteSlice(b"sum")), Result::Ok((EMPTY, expected)));
+    }
+
+    #[test]
+    fn logical_operators_bind_looser_than_comparisons() {
+        let display = |input: &str| {
+            let (rest, op) = parse_operand(CompleteByteSlice(input.as_bytes())).unwrap();
+            assert_eq!(rest, EMPTY);
+            op
+        };
+        let id_is = |v| {
+            Box::new(Operand::Compare(
+                Comparison::Equal,
+                Box::new(Operand::Column("id".to_owned())),
+                Box::new(Operand::Integer(v)),
+            ))
+        };
+        assert_eq!(
+            display("id = 1 or id = 2 and not id = 3"),
+            Operand::Or(id_is(1), Box::new(Operand::And(id_is(2), Box::new(Operand::Not(id_is(3))))))
+        );
+        assert_eq!(
+            display("id = 1 and id = 2 and id = 3"),
+            Operand::And(Box::new(Operand::And(id_is(1), id_is(2))), id_is(3))
+        );
+        // names starting like the operators are columns
+        assert_eq!(
+            display("android or notes"),
+            Operand::Or(
+                Box::new(Operand::Column("android".to_owned())),
+                Box::new(Operand::Column("notes".to_owned()))
+            )
+        );
+        assert_eq!(
+            display("username not like 'a%' and id = 1"),
+            Operand::And(
+                Box::new(Operand::Not(Box::new(Operand::Like(
+                    Box::new(Operand::Column("username".to_owned())),
+                    Box::new(Operand::String("a%".to_owned()))
+                )))),
+                id_is(1)
+            )
+        );
+        let op = display("id not in (1, -2,3)");
+        assert_eq!(format!("{}", op), "not id in (1, -2, 3)");
+        match display("id not in (select id from users)") {
+            Operand::Not(op) => assert_eq!(format!("{}", op), "id in (select id from users)"),
+            op => panic!("not a not: {}", op),
+        }
+    }
+
+    #[test]
+    fn can_recognize_in_and_exists_subqueries() {
+        let (rest, op) =
+            parse_operand(CompleteByteSlice(b"id in (select id from users where id > 2)")).unwrap();
+        assert_eq!(rest, EMPTY);
+        assert_eq!(format!("{}", op), "id in (select id from users where id > 2)");
+        match op {
+            Operand::In(op, _) => assert_eq!(*op, Operand::Column("id".to_owned())),
+            op => panic!("not an in: {}", op),
+        }
+        let (rest, op) = parse_operand(CompleteByteSlice(b"exists ( select * from users )")).unwrap();
+        assert_eq!(rest, EMPTY);
+        assert_eq!(format!("{}", op), "exists (select * from users)");
+    }
+
+    #[test]
+    fn can_recognize_parameters() {
+        assert_eq!(
+            parse_basic_operand(CompleteByteSlice(b" ? ")),
+            Result::Ok((EMPTY, Operand::Parameter(None)))
+        );
+        assert_eq!(
+            parse_basic_operand(CompleteByteSlice(b"?12")),
+            Result::Ok((EMPTY, Operand::Parameter(Some(12))))
+        );
+        assert_eq!(
+            parse_basic_operand(CompleteByteSlice(b" $3 ")),
+            Result::Ok((EMPTY, Operand::Parameter(Some(3))))
+        );
+        assert!(parse_basic_operand(CompleteByteSlice(b"$")).is_err());
+    }
+
+    #[test]
+    fn can_parse_comparisons() {
+        let id_op = || Box::new(Operand::Column("id".to_owned()));
+        let cases = [
+            ("id = 10", Comparison::Equal),
+            ("id != 10", Comparison::NotEqual),
+            ("id <> 10", Comparison::NotEqual),
+            ("id < 10", Comparison::Less),
+            ("id<=10", Comparison::LessOrEqual),
+            ("id > 10", Comparison::Greater),
+            ("id >= 10", Comparison::GreaterOrEqual),
+        ];
+        for (input, comparison) in cases.iter() {
+            let expected = Operand::Compare(*comparison, id_op(), Box::new(Operand::Integer(10)));
+            assert_eq!(
+                parse_operand(CompleteByteSlice(input.as_bytes())),
+                Result::Ok((EMPTY, expected))
+            );
+        }
+
+        let sum = Operand::Add(id_op(), Box::new(Operand::Integer(1)));
+        let expected = Operand::Compare(
+            Comparison::Equal,
+            Box::new(sum),
+            Box::new(Operand::String("a".to_owned())),
+        );
+        assert_eq!(
+            parse_operand(CompleteByteSlice(b"id + 1 = 'a'")),
+            Result::Ok((EMPTY, expected))
+        );
+        let (_, op) = parse_operand(CompleteByteSlice(b"id<>10")).unwrap();
+        assert_eq!(format!("{}", op), "id != 10");
     }
 }
@@ -0,0 +1,203 @@
+//! storage backends of the pager, the pager only deals with byte offsets
+//! and leaves the actual persistence to a `Storage`.
+
+#[cfg(feature = "file")]
+use std::fs::File;
+use std::io::{self, ErrorKind};
+#[cfg(feature = "file")]
+use std::io::{Read, Seek, SeekFrom, Write};
+
+#[cfg(feature = "mmap")]
+use memmap2::MmapMut;
+
+/// the db path that selects the in-memory backend.
+pub const MEMORY_DB: &str = ":memory:";
+
+/// `Send` so a pager can move to another thread.
+pub trait Storage: Send {
+    /// fill `buf` with bytes at `offset`, reading beyond the end is an `UnexpectedEof` error.
+    fn read_page(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()>;
+
+    /// the bytes at `offset` in place instead of a copy, `None` for backends that
+    /// only read into a buffer and for bytes beyond the end. the slice borrows the
+    /// storage, it cannot outlive a write or a resize.
+    fn page_slice(&self, _offset: u64, _len: usize) -> Option<&[u8]> {
+        None
+    }
+
+    fn write_page(&mut self, offset: u64, buf: &[u8]) -> io::Result<()>;
+
+    fn len(&self) -> u64;
+
+    fn set_len(&mut self, len: u64) -> io::Result<()>;
+
+    fn sync(&mut self) -> io::Result<()>;
+}
+
+fn unexpected_eof() -> io::Error {
+    io::Error::new(ErrorKind::UnexpectedEof, "read beyond the end of storage")
+}
+
+#[cfg(feature = "file")]
+pub struct FileStorage {
+    file: File,
+}
+
+#[cfg(feature = "file")]
+impl FileStorage {
+    pub fn new(file: File) -> FileStorage {
+        FileStorage { file }
+    }
+}
+
+#[cfg(feature = "file")]
+impl Storage for FileStorage {
+    fn read_page(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(buf)
+    }
+
+    fn write_page(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(buf)
+    }
+
+    fn len(&self) -> u64 {
+        self.file.metadata().unwrap().len()
+    }
+
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        self.file.set_len(len)
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+}
+
+pub struct MemoryStorage {
+    buf: Vec<u8>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage { buf: Vec::new() }
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn read_page(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let start = offset as usize;
+        match self.buf.get(start..start + buf.len()) {
+            Some(bytes) => {
+                buf.copy_from_slice(bytes);
+                Result::Ok(())
+            }
+            None => Result::Err(unexpected_eof()),
+        }
+    }
+
+    fn write_page(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        let start = offset as usize;
+        let end = start + buf.len();
+        if end > self.buf.len() {
+            self.buf.resize(end, 0u8);
+        }
+        self.buf[start..end].copy_from_slice(buf);
+        Result::Ok(())
+    }
+
+    fn len(&self) -> u64 {
+        self.buf.len() as u64
+    }
+
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        self.buf.resize(len as usize, 0u8);
+        Result::Ok(())
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        Result::Ok(())
+    }
+}
+
+/// file backend that reads and writes pages through a memory mapping of the db file.
+#[cfg(feature = "mmap")]
+pub struct MmapStorage {
+    file: File,
+    mmap: Option<MmapMut>,
+}
+
+#[cfg(feature = "mmap")]
+impl MmapStorage {
+    pub fn new(file: File) -> io::Result<MmapStorage> {
+        let mut storage = MmapStorage { file, mmap: None };
+        storage.remap()?;
+        Result::Ok(storage)
+    }
+
+    /// the mapping has to be recreated whenever the file size changes.
+    fn remap(&mut self) -> io::Result<()> {
+        self.mmap = match self.file.metadata()?.len() {
+            // an empty file cannot be mapped
+            0 => None,
+            _ => Some(unsafe { MmapMut::map_mut(&self.file)? }),
+        };
+        Result::Ok(())
+    }
+
+    fn mapped(&self) -> &[u8] {
+        match self.mmap {
+            Some(ref mmap) => mmap,
+            None => &[],
+        }
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl Storage for MmapStorage {
+    fn read_page(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        match self.page_slice(offset, buf.len()) {
+            Some(bytes) => {
+                buf.copy_from_slice(bytes);
+                Result::Ok(())
+            }
+            None => Result::Err(unexpected_eof()),
+        }
+    }
+
+    fn page_slice(&self, offset: u64, len: usize) -> Option<&[u8]> {
+        let start = offset as usize;
+        self.mapped().get(start..start + len)
+    }
+
+    fn write_page(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        let start = offset as usize;
+        let end = start + buf.len();
+        if end as u64 > self.len() {
+            self.set_len(end as u64)?;
+        }
+        if let Some(ref mut mmap) = self.mmap {
+            mmap[start..end].copy_from_slice(buf);
+        }
+        Result::Ok(())
+    }
+
+    fn len(&self) -> u64 {
+        self.mapped().len() as u64
+    }
+
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        // drop the mapping before the file is truncated
+        self.mmap = None;
+        self.file.set_len(len)?;
+        self.remap()
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        match self.mmap {
+            Some(ref mmap) => mmap.flush(),
+            None => Result::Ok(()),
+        }
+    }
+}
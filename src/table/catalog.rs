@@ -0,0 +1,247 @@
+//! the tables a `create table` adds next to the users table and the indexes of
+//! `create index`. the catalog is a tree of its own whose root page is kept in the
+//! db header, a table or index has a cell in it keyed by its root page that holds
+//! the statement it was made by. the users table is not in the catalog, its rows
+//! are under page 0.
+
+use byteorder::{BigEndian, ByteOrder};
+
+use btree::{BTreeTrait, ROW_SIZE};
+use sql::{self, ParsedSQL};
+use vm::Value;
+
+use super::index::IndexEntry;
+use super::schema::Schema;
+use super::{RowRef, Table, TABLE_NAME};
+
+/// a table of a db, where its rows are and the columns they have.
+#[derive(Clone)]
+pub struct TableEntry {
+    pub name: String,
+    /// the root page of the tree of its rows.
+    pub root: usize,
+    pub schema: Schema,
+}
+
+impl TableEntry {
+    /// the values of a row of the table, in the order of its columns.
+    pub fn row_values(&self, row: &RowRef) -> Vec<Value> {
+        if self.schema.has_rowid() {
+            row.values(&self.schema)
+        } else {
+            row.to_row().values()
+        }
+    }
+}
+
+/// a cell of the catalog.
+pub(super) enum CatalogEntry {
+    Table(TableEntry),
+    Index(IndexEntry),
+}
+
+/// the cell of a statement in the catalog, the length of the statement ahead of
+/// it. `None` if it does not fit.
+pub(super) fn catalog_cell(create: &ParsedSQL) -> Option<Vec<u8>> {
+    let create = create.to_string();
+    if 2 + create.len() > ROW_SIZE {
+        return None;
+    }
+    let mut buf = vec![0u8; ROW_SIZE];
+    BigEndian::write_u16(&mut buf, create.len() as u16);
+    buf[2..2 + create.len()].copy_from_slice(create.as_bytes());
+    Some(buf)
+}
+
+fn read_entry(root: usize, buf: &[u8]) -> Result<CatalogEntry, String> {
+    let len = BigEndian::read_u16(buf) as usize;
+    let corrupted = || format!("the catalog entry of page {} is corrupted.", root);
+    let create = buf.get(2..2 + len).ok_or_else(corrupted)?;
+    match sql::parse(create).map_err(|_| corrupted())? {
+        ParsedSQL::CreateTable { table, columns, .. } => Result::Ok(CatalogEntry::Table(TableEntry {
+            name: table,
+            root,
+            schema: Schema::from_columns(columns)?,
+        })),
+        ParsedSQL::CreateIndex {
+            name,
+            table,
+            column,
+            ..
+        } => Result::Ok(CatalogEntry::Index(IndexEntry {
+            name,
+            table,
+            column,
+            root,
+        })),
+        _ => Result::Err(corrupted()),
+    }
+}
+
+impl Table {
+    /// the cells of the catalog in the order of their root pages.
+    pub(super) fn catalog_entries(&self) -> Result<Vec<CatalogEntry>, String> {
+        let catalog = self.tree.pager.catalog_page();
+        if catalog == 0 {
+            return Result::Ok(Vec::new());
+        }
+        let mut entries = Vec::new();
+        let mut cursor = self.select_cursor_in(catalog, 0)?;
+        while !cursor.end_of_table() {
+            let row = cursor.row();
+            entries.push(read_entry(row.key() as usize, &row.value())?);
+            cursor.advance()?;
+        }
+        Result::Ok(entries)
+    }
+
+    /// add the cell of a table or index to the catalog, the catalog is made with
+    /// the first one.
+    pub(super) fn add_to_catalog(&mut self, root: usize, cell: &[u8]) -> Result<(), String> {
+        let catalog = match self.tree.pager.catalog_page() {
+            0 => {
+                let catalog = self.tree.create_tree()?;
+                self.tree.pager.set_catalog_page(catalog);
+                catalog
+            }
+            catalog => catalog,
+        };
+        self.insert_cell(catalog, root as u32, cell)
+    }
+
+    /// remove the table or index with given root from the catalog and put its
+    /// pages onto the free list.
+    fn remove_from_catalog(&mut self, root: usize) -> Result<(), String> {
+        let catalog = self.tree.pager.catalog_page();
+        self.tree.with_root(catalog, |tree| tree.delete_key(root as u32))?;
+        self.tree.drop_tree(root).map(|_| ())
+    }
+
+    /// the users table, which every db has.
+    pub(super) fn users_table(&self) -> TableEntry {
+        TableEntry {
+            name: TABLE_NAME.to_owned(),
+            root: 0,
+            schema: self.schema.clone(),
+        }
+    }
+
+    /// the tables of the db, the users table first.
+    pub fn tables(&self) -> Result<Vec<TableEntry>, String> {
+        let mut tables = vec![self.users_table()];
+        for entry in self.catalog_entries()? {
+            if let CatalogEntry::Table(table) = entry {
+                tables.push(table);
+            }
+        }
+        Result::Ok(tables)
+    }
+
+    /// the table with given name, `None` if there is no such table.
+    pub fn find_table(&self, name: &str) -> Result<Option<TableEntry>, String> {
+        if name == TABLE_NAME {
+            return Result::Ok(Some(self.users_table()));
+        }
+        Result::Ok(self.tables()?.into_iter().find(|table| table.name == name))
+    }
+
+    /// a created table that the statement prepared with given schema may still run
+    /// on, it fails if the table was dropped or created again with other columns.
+    pub fn prepared_table(&self, name: &str, schema: &Schema) -> Result<TableEntry, String> {
+        let table = self
+            .find_table(name)?
+            .ok_or_else(|| format!("no such table: {}", name))?;
+        if table.schema.columns() != schema.columns() {
+            return Result::Err(format!(
+                "table {} changed since the statement was prepared.",
+                name
+            ));
+        }
+        Result::Ok(table)
+    }
+
+    /// add an empty table of given columns.
+    pub fn create_table(&mut self, name: &str, schema: &Schema) -> Result<(), String> {
+        if self.find_table(name)?.is_some() {
+            return Result::Err(format!("table {} already exists.", name));
+        }
+        let create = ParsedSQL::CreateTable {
+            database: None,
+            table: name.to_owned(),
+            columns: schema.columns().to_vec(),
+            if_not_exists: false,
+        };
+        let cell = catalog_cell(&create).ok_or_else(|| {
+            format!(
+                "the columns of table {} take more than {} bytes to store.",
+                name,
+                ROW_SIZE - 2
+            )
+        })?;
+        let root = self.tree.create_tree()?;
+        self.add_to_catalog(root, &cell)
+    }
+
+    /// remove a created table and its indexes from the catalog, the pages of its
+    /// rows go onto the free list. the users table stays.
+    pub fn drop_table(&mut self, name: &str) -> Result<(), String> {
+        if name == TABLE_NAME {
+            return Result::Err(format!(
+                "cannot drop table {}, every db has the {} table.",
+                name, TABLE_NAME
+            ));
+        }
+        let table = self
+            .find_table(name)?
+            .ok_or_else(|| format!("no such table: {}", name))?;
+        for index in self.indexes_of(name)? {
+            self.remove_from_catalog(index.root)?;
+        }
+        self.remove_from_catalog(table.root)
+    }
+
+    /// insert a row into a created table under the key after the highest one,
+    /// its values are the ones of `Schema::column_value`.
+    pub fn insert_values(&mut self, table: &TableEntry, values: &[Value]) -> Result<(), String> {
+        let key = match self.tree.last_key_from(table.root)? {
+            Some(u32::MAX) => return Result::Err(format!("table {} is full.", table.name)),
+            Some(key) => key + 1,
+            None => 1,
+        };
+        let row = table.schema.write_row(values)?;
+        self.insert_cell(table.root, key, &row)?;
+        self.index_row(table, key, values)
+    }
+
+    /// remove the row with given key from a created table, false if there is none.
+    pub fn delete_from(&mut self, table: &TableEntry, key: u32) -> Result<bool, String> {
+        if self.has_indexes(&table.name)? {
+            let values = {
+                let cursor = self.select_cursor_in(table.root, key)?;
+                if cursor.end_of_table() || cursor.row().key() != key {
+                    return Result::Ok(false);
+                }
+                table.row_values(&cursor.row())
+            };
+            self.unindex_row(table, key, &values)?;
+        }
+        self.tree.with_root(table.root, |tree| tree.delete_key(key))
+    }
+
+    /// the pages of the catalog and of the created tables and indexes.
+    pub(crate) fn catalog_pages(&self) -> Result<Vec<usize>, String> {
+        let catalog = self.tree.pager.catalog_page();
+        if catalog == 0 {
+            return Result::Ok(Vec::new());
+        }
+        let mut pages = self.tree.tree_pages_from(catalog)?;
+        for entry in self.catalog_entries()? {
+            let root = match entry {
+                CatalogEntry::Table(table) => table.root,
+                CatalogEntry::Index(index) => index.root,
+            };
+            pages.extend(self.tree.tree_pages_from(root)?);
+        }
+        Result::Ok(pages)
+    }
+}
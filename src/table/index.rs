@@ -0,0 +1,248 @@
+//! secondary indexes over a column of a table. an index is a tree of its own kept
+//! in the catalog like a created table, it is built by scanning the leaves of the
+//! table once and follows the inserts and deletes of the table after. a cell holds
+//! a value of the column with the key of its row. cells are keyed by a 16 bit hash
+//! of the value in the high half and a sequence number in the low half, so a
+//! lookup scans the keys of a single hash and keeps the cells of the value.
+
+use std::slice;
+
+use btree::BTreeTrait;
+use sql::{ParsedSQL, SQLType};
+use vm::Value;
+
+use super::catalog::{catalog_cell, CatalogEntry, TableEntry};
+use super::schema::Schema;
+use super::Table;
+
+/// an index of a column of a table, where its cells are.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexEntry {
+    pub name: String,
+    pub table: String,
+    pub column: String,
+    /// the root page of the tree of its cells.
+    pub root: usize,
+}
+
+/// the low bits of a key of an index, they number the cells of a hash.
+const SEQUENCE_BITS: u32 = 16;
+
+/// the values of a column of given type as the cells of an index hold them.
+struct Cells {
+    value: Schema,
+    cell: Schema,
+}
+
+impl Cells {
+    fn new(sql_type: SQLType) -> Result<Cells, String> {
+        Result::Ok(Cells {
+            value: Schema::from_columns(vec![("value".to_owned(), sql_type)])?,
+            cell: Schema::from_columns(vec![
+                ("value".to_owned(), sql_type),
+                ("key".to_owned(), SQLType::Integer),
+            ])?,
+        })
+    }
+
+    /// the hash of a value, FNV-1a of its bytes folded to 16 bits. `-0.0` has
+    /// the hash of `0.0` as it equals it.
+    fn hash(&self, value: &Value) -> Result<u32, String> {
+        let value = match *value {
+            Value::Real(0.0) => Value::Real(0.0),
+            ref value => value.clone(),
+        };
+        let mut hash: u32 = 0x811c_9dc5;
+        for &byte in &self.value.write_row(slice::from_ref(&value))? {
+            hash ^= byte as u32;
+            hash = hash.wrapping_mul(0x0100_0193);
+        }
+        Result::Ok((hash >> 16) ^ (hash & 0xffff))
+    }
+
+    fn write(&self, value: Value, key: u32) -> Result<Vec<u8>, String> {
+        self.cell.write_row(&[value, Value::Integer(key as i64)])
+    }
+
+    /// the value and the key of the row of a cell.
+    fn read(&self, buf: &[u8]) -> (Value, u32) {
+        match self.cell.read_row(buf).as_slice() {
+            [value, Value::Integer(key)] => (value.clone(), *key as u32),
+            [value, _] => (value.clone(), 0),
+            _ => (Value::Null, 0),
+        }
+    }
+}
+
+impl Table {
+    /// the indexes of the db in the order of their root pages.
+    pub fn indexes(&self) -> Result<Vec<IndexEntry>, String> {
+        let mut indexes = Vec::new();
+        for entry in self.catalog_entries()? {
+            if let CatalogEntry::Index(index) = entry {
+                indexes.push(index);
+            }
+        }
+        Result::Ok(indexes)
+    }
+
+    /// the indexes of the table with given name.
+    pub fn indexes_of(&self, table: &str) -> Result<Vec<IndexEntry>, String> {
+        let mut indexes = self.indexes()?;
+        indexes.retain(|index| index.table == table);
+        Result::Ok(indexes)
+    }
+
+    pub(super) fn has_indexes(&self, table: &str) -> Result<bool, String> {
+        if self.tree.pager.catalog_page() == 0 {
+            return Result::Ok(false);
+        }
+        self.indexes_of(table).map(|indexes| !indexes.is_empty())
+    }
+
+    pub fn find_index(&self, name: &str) -> Result<Option<IndexEntry>, String> {
+        Result::Ok(self.indexes()?.into_iter().find(|index| index.name == name))
+    }
+
+    /**
+     * build an index over a column of a table with a scan of the leaves of the
+     * table. the rows are read ahead of the inserts into the index, whose tree
+     * takes pages the scan would be reading.
+     **/
+    pub fn create_index(&mut self, name: &str, table: &str, column: &str) -> Result<(), String> {
+        if self.find_index(name)?.is_some() {
+            return Result::Err(format!("index {} already exists.", name));
+        }
+        let table = self
+            .find_table(table)?
+            .ok_or_else(|| format!("no such table: {}", table))?;
+        let position = table
+            .schema
+            .column_index(column)
+            .ok_or_else(|| format!("no such column: {}", column))?;
+        let create = ParsedSQL::CreateIndex {
+            database: None,
+            name: name.to_owned(),
+            table: table.name.clone(),
+            column: column.to_owned(),
+        };
+        let cell = catalog_cell(&create)
+            .ok_or_else(|| format!("the name of index {} is too long to store.", name))?;
+        let mut rows = Vec::new();
+        {
+            let mut cursor = self.select_cursor_in(table.root, 0)?;
+            while !cursor.end_of_table() {
+                let row = cursor.row();
+                rows.push((row.key(), table.row_values(&row).swap_remove(position)));
+                cursor.advance()?;
+            }
+        }
+        let index = IndexEntry {
+            name: name.to_owned(),
+            table: table.name.clone(),
+            column: column.to_owned(),
+            root: self.tree.create_tree()?,
+        };
+        self.add_to_catalog(index.root, &cell)?;
+        let cells = Cells::new(table.schema.columns()[position].1)?;
+        for (key, value) in rows {
+            self.add_index_cell(&index, &cells, value, key)?;
+        }
+        Result::Ok(())
+    }
+
+    /// the keys of the rows of the table of the index whose column holds given
+    /// value, in the order they were added to the index.
+    pub fn index_lookup(&self, index: &IndexEntry, value: &Value) -> Result<Vec<u32>, String> {
+        let cells = self.index_cells(index)?;
+        let hash = cells.hash(value)?;
+        let mut keys = Vec::new();
+        let mut cursor = self.select_cursor_in(index.root, hash << SEQUENCE_BITS)?;
+        while !cursor.end_of_table() && cursor.row().key() >> SEQUENCE_BITS == hash {
+            let (cell_value, key) = cells.read(&cursor.row().value());
+            if cell_value == *value {
+                keys.push(key);
+            }
+            cursor.advance()?;
+        }
+        Result::Ok(keys)
+    }
+
+    /// add a row to the indexes of its table.
+    pub(super) fn index_row(&mut self, table: &TableEntry, key: u32, values: &[Value]) -> Result<(), String> {
+        for index in self.indexes_of(&table.name)? {
+            let (position, cells) = index_column(table, &index)?;
+            self.add_index_cell(&index, &cells, values[position].clone(), key)?;
+        }
+        Result::Ok(())
+    }
+
+    /// remove a row from the indexes of its table.
+    pub(super) fn unindex_row(&mut self, table: &TableEntry, key: u32, values: &[Value]) -> Result<(), String> {
+        for index in self.indexes_of(&table.name)? {
+            let (position, cells) = index_column(table, &index)?;
+            let cell_key = self.find_index_cell(&index, &cells, &values[position], key)?;
+            if let Some(cell_key) = cell_key {
+                self.tree.with_root(index.root, |tree| tree.delete_key(cell_key))?;
+            }
+        }
+        Result::Ok(())
+    }
+
+    fn index_cells(&self, index: &IndexEntry) -> Result<Cells, String> {
+        let table = self
+            .find_table(&index.table)?
+            .ok_or_else(|| format!("no such table: {}", index.table))?;
+        index_column(&table, index).map(|(_, cells)| cells)
+    }
+
+    /// put a cell after the last one of the hash of its value.
+    fn add_index_cell(&mut self, index: &IndexEntry, cells: &Cells, value: Value, key: u32) -> Result<(), String> {
+        let hash = cells.hash(&value)?;
+        let mut cell_key = hash << SEQUENCE_BITS;
+        {
+            let mut cursor = self.select_cursor_in(index.root, cell_key)?;
+            while !cursor.end_of_table() && cursor.row().key() >> SEQUENCE_BITS == hash {
+                cell_key = cursor.row().key() + 1;
+                cursor.advance()?;
+            }
+        }
+        if cell_key >> SEQUENCE_BITS != hash {
+            return Result::Err(format!(
+                "index {} holds too many values like {}.",
+                index.name, value
+            ));
+        }
+        let cell = cells.write(value, key)?;
+        self.insert_cell(index.root, cell_key, &cell)
+    }
+
+    /// the key of the cell of a row in an index, `None` if it has none.
+    fn find_index_cell(
+        &self,
+        index: &IndexEntry,
+        cells: &Cells,
+        value: &Value,
+        key: u32,
+    ) -> Result<Option<u32>, String> {
+        let hash = cells.hash(value)?;
+        let mut cursor = self.select_cursor_in(index.root, hash << SEQUENCE_BITS)?;
+        while !cursor.end_of_table() && cursor.row().key() >> SEQUENCE_BITS == hash {
+            if cells.read(&cursor.row().value()) == (value.clone(), key) {
+                return Result::Ok(Some(cursor.row().key()));
+            }
+            cursor.advance()?;
+        }
+        Result::Ok(None)
+    }
+}
+
+/// the position of the column of an index in the rows of its table with the
+/// cells of its values.
+fn index_column(table: &TableEntry, index: &IndexEntry) -> Result<(usize, Cells), String> {
+    let position = table
+        .schema
+        .column_index(&index.column)
+        .ok_or_else(|| format!("no such column: {}", index.column))?;
+    Cells::new(table.schema.columns()[position].1).map(|cells| (position, cells))
+}
@@ -1,13 +1,27 @@
-use std::ops::{Index, IndexMut, Range, RangeFrom};
+use std::ops::{IndexMut, RangeFrom};
 use byteorder::{BigEndian, ByteOrder};
-use std::cell::RefCell;
-use std::rc::Rc;
-use pager::Page;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::path::Path;
+use error::RdbError;
+use pager::{DbOption, Page, PageGuard, Pager, PagerError, SyncMode};
+use journal::journal_path;
+use storage::MEMORY_DB;
 use btree::{BTree, BTreeLeafPage, BTreePage, BTreeTrait, CellIndex, KEY_SIZE, ROW_SIZE};
+use vm::Value;
 
+pub mod catalog;
+pub mod index;
 pub mod schema;
 use self::schema::Schema;
 
+/// the savepoint each statement of a transaction runs in, so a failed statement is
+/// undone on its own. savepoint names are never empty.
+const STATEMENT_SAVEPOINT: &str = "";
+
+/// the savepoint a `begin` starts a transaction with, no savepoint name has a space.
+const TRANSACTION_SAVEPOINT: &str = "begin transaction";
+
 pub struct Row {
     pub id: u32,
     pub username: String,
@@ -15,61 +29,85 @@ pub struct Row {
 }
 
 impl Row {
+    /// fail for a row the columns cannot hold, like the checks of an insert.
+    pub fn check(&self) -> Result<(), String> {
+        if self.id > i32::MAX as u32 {
+            Result::Err("id out of range".to_owned())
+        } else if self.username.len() > 32 || self.email.len() > 256 {
+            Result::Err("String is too long.".to_owned())
+        } else {
+            Result::Ok(())
+        }
+    }
+
+    /// the values of the columns of the row, in the order of the users table.
+    pub fn values(&self) -> Vec<Value> {
+        vec![
+            Value::Integer(self.id as i64),
+            Value::Text(self.username.clone()),
+            Value::Text(self.email.clone()),
+        ]
+    }
+
     fn serialize(row: &Row, page: &mut Page, pos: usize) {
         BigEndian::write_u32(page.index_mut(RangeFrom { start: pos }), row.id);
         Row::write_string(page, pos + 4, &row.username, 32);
         Row::write_string(page, pos + 36, &row.email, 256);
     }
 
-    fn deserialize(buf: &Vec<u8>, pos: usize) -> Row {
-        let mut bytes = vec![0; ROW_SIZE];
-        bytes.clone_from_slice(buf.index(Range {
-            start: pos,
-            end: pos + ROW_SIZE,
-        }));
-
-        let mut position = 0;
-        let id = BigEndian::read_u32(bytes.as_slice());
-        position += 4;
-        let username = Row::read_string(&bytes, position, 32);
-        position += 32;
-        let email = Row::read_string(&bytes, position, 256);
+    fn deserialize(buf: &[u8], pos: usize) -> Row {
+        debug_assert!(pos + ROW_SIZE <= buf.len());
+        let bytes = &buf[pos..pos + ROW_SIZE];
         Row {
-            id: id,
-            username: username,
-            email: email,
+            id: BigEndian::read_u32(bytes),
+            username: Row::read_string(bytes, 4, 32),
+            email: Row::read_string(bytes, 36, 256),
         }
     }
 
-    fn write_string(buf: &mut Vec<u8>, pos: usize, s: &str, length: usize) {
+    fn write_string(buf: &mut [u8], pos: usize, s: &str, length: usize) {
         let bytes = s.as_bytes();
-
-        let mut i = 0;
-        for b in bytes {
-            buf[pos + i] = *b;
-            i += 1;
-        }
-        while i < length {
-            buf[pos + i] = 0;
-            i += 1;
-        }
+        debug_assert!(bytes.len() <= length && pos + length <= buf.len());
+        buf[pos..pos + bytes.len()].copy_from_slice(bytes);
+        buf[pos + bytes.len()..pos + length].fill(0);
     }
 
-    fn read_string(buf: &Vec<u8>, pos: usize, length: usize) -> String {
-        let mut end = pos;
-        while ((end - pos) < length) && (buf[end] != 0) {
-            end += 1;
-        }
-        let mut bytes = vec![0; end - pos];
-        bytes.clone_from_slice(buf.index(Range {
-            start: pos,
-            end: end,
-        }));
-        return String::from_utf8(bytes).unwrap();
+    fn read_string(buf: &[u8], pos: usize, length: usize) -> String {
+        let field = &buf[pos..pos + length];
+        let end = field.iter().position(|&b| b == 0).unwrap_or(length);
+        String::from_utf8(field[..end].to_vec()).unwrap()
     }
 }
 
 
+/// the name of the table every db has, the others are in the catalog.
+pub const TABLE_NAME: &str = "users";
+
+/// outcome of `Table::insert_many`.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct InsertReport {
+    pub inserted: usize,
+    /// the ids of the rows left out in ascending order, rows the columns cannot
+    /// hold and rows whose id is taken.
+    pub failed_keys: Vec<u32>,
+}
+
+/// outcome of `Table::recover`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct RecoverReport {
+    pub rows_recovered: usize,
+    /// pages that did not look like leaves or could not be read.
+    pub pages_skipped: usize,
+}
+
+/// outcome of `Table::check`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct CheckReport {
+    pub rows: u64,
+    /// the row count kept in the db header before the check.
+    pub row_count_before: u64,
+}
+
 pub struct Table {
     pub tree: BTree,
     pub schema: Schema,
@@ -87,76 +125,563 @@ impl Table {
         };
     }
 
-    pub fn close(self: &mut Table) {
-        for page_index in 0..self.tree.pager.num_pages {
-            self.tree.pager.flush(page_index);
+    /// an empty table over an in-memory db that never syncs, for the tests and
+    /// benches.
+    #[doc(hidden)]
+    pub fn in_memory_for_test() -> Table {
+        let db_option = DbOption {
+            sync_mode: SyncMode::Off,
+            ..Default::default()
+        };
+        Table::new(BTree::new(Pager::new(MEMORY_DB, db_option).unwrap()))
+    }
+
+    /// flush all changes and close the db file, dropping a table flushes as well
+    /// but can only log the errors.
+    pub fn close(self) -> Result<(), PagerError> {
+        self.tree.pager.close()
+    }
+
+    /// write all changes to disk and sync, without closing the table.
+    pub fn sync(&mut self) -> Result<(), String> {
+        self.outside_transaction("sync")?;
+        self.tree.pager.sync_all().map(|_| ()).map_err(|e| format!("{}", e))
+    }
+
+    /// end a statement, its changes are written unless a transaction is open.
+    pub fn commit(&mut self) -> Result<(), String> {
+        if self.in_transaction() {
+            self.restart_statement();
+            return Result::Ok(());
+        }
+        self.tree.pager.flush_all().map(|_| ()).map_err(|e| format!("{}", e))
+    }
+
+    /// undo a statement, the earlier statements of an open transaction are kept.
+    pub fn rollback(&mut self) -> Result<(), String> {
+        match self.tree.pager.find_savepoint(STATEMENT_SAVEPOINT) {
+            Some(index) => self.tree.rollback_to_savepoint(index),
+            None => self.tree.rollback()?,
+        }
+        Result::Ok(())
+    }
+
+    /// roll back a failed statement, gives the error of the statement along with
+    /// the one of the rollback if that fails as well.
+    pub fn rollback_failed(&mut self, error: String) -> String {
+        match self.rollback() {
+            Result::Ok(()) => error,
+            Result::Err(rollback_error) => format!("{} cannot roll back: {}", error, rollback_error),
+        }
+    }
+
+    /// undo the open transaction as a whole, its savepoints are forgotten.
+    pub fn rollback_transaction(&mut self) -> Result<(), String> {
+        self.tree.rollback()
+    }
+
+    /// a savepoint is set, statements are only committed with the release of the
+    /// outermost one.
+    pub fn in_transaction(&self) -> bool {
+        self.tree.pager.num_savepoints() > 0
+    }
+
+    /// set a savepoint, starting a transaction if there is none.
+    pub fn savepoint(&mut self, name: &str) -> Result<(), String> {
+        if self.tree.pager.find_savepoint(name).is_some() {
+            return Result::Err(format!("savepoint {} already exists.", name));
+        }
+        self.end_statement();
+        self.tree.pager.savepoint(name);
+        self.tree.pager.savepoint(STATEMENT_SAVEPOINT);
+        Result::Ok(())
+    }
+
+    /// start a transaction, it lasts until a `commit` or `rollback`.
+    pub fn begin(&mut self) -> Result<(), String> {
+        if self.in_transaction() {
+            return Result::Err("cannot begin a transaction within a transaction.".to_owned());
+        }
+        self.savepoint(TRANSACTION_SAVEPOINT)
+    }
+
+    /// write the changes of the open transaction, its savepoints are released.
+    pub fn commit_transaction(&mut self) -> Result<(), String> {
+        if !self.in_transaction() {
+            return Result::Err("cannot commit: no transaction is active.".to_owned());
+        }
+        self.tree.pager.release_savepoint(0);
+        self.commit()
+    }
+
+    /// forget a savepoint and the ones set after it, releasing the outermost one
+    /// commits the transaction.
+    pub fn release(&mut self, name: &str) -> Result<(), String> {
+        let index = self.named_savepoint("release", name)?;
+        self.tree.pager.release_savepoint(index);
+        if self.in_transaction() {
+            self.tree.pager.savepoint(STATEMENT_SAVEPOINT);
+            Result::Ok(())
+        } else {
+            self.commit()
+        }
+    }
+
+    /// undo the changes made since a savepoint, it stays set.
+    pub fn rollback_to(&mut self, name: &str) -> Result<(), String> {
+        let index = self.named_savepoint("roll back to", name)?;
+        self.tree.rollback_to_savepoint(index);
+        self.tree.pager.savepoint(STATEMENT_SAVEPOINT);
+        Result::Ok(())
+    }
+
+    fn named_savepoint(&self, action: &str, name: &str) -> Result<usize, String> {
+        if !self.in_transaction() {
+            return Result::Err(format!(
+                "cannot {} {}: no transaction is active.",
+                action, name
+            ));
         }
+        self.tree
+            .pager
+            .find_savepoint(name)
+            .ok_or_else(|| format!("cannot {} {}: no such savepoint.", action, name))
     }
 
-    pub fn select_cursor(&self) -> SelectCursor {
+    /// fold the changes of the current statement into the transaction.
+    fn end_statement(&mut self) {
+        if let Some(index) = self.tree.pager.find_savepoint(STATEMENT_SAVEPOINT) {
+            self.tree.pager.release_savepoint(index);
+        }
+    }
+
+    fn restart_statement(&mut self) {
+        self.end_statement();
+        self.tree.pager.savepoint(STATEMENT_SAVEPOINT);
+    }
+
+    fn outside_transaction(&self, action: &str) -> Result<(), String> {
+        if self.in_transaction() {
+            Result::Err(format!(
+                "cannot {} inside a transaction, release its savepoints first.",
+                action
+            ))
+        } else {
+            Result::Ok(())
+        }
+    }
+
+    pub fn select_cursor(&self) -> Result<SelectCursor<'_>, String> {
+        self.select_cursor_from(0)
+    }
+
+    /// a cursor on the first row with an id of at least `key`.
+    pub fn select_cursor_from(&self, key: u32) -> Result<SelectCursor<'_>, String> {
+        self.select_cursor_in(0, key)
+    }
+
+    /// a cursor on the first row with a key of at least `key` in the tree with its
+    /// root at given page, like the one of a created table.
+    pub fn select_cursor_in(&self, root: usize, key: u32) -> Result<SelectCursor<'_>, String> {
         let CellIndex {
             page_index,
             cell_index,
-        } = self.tree.search_key(0);
+        } = self.tree.search_key_from(root, key)?;
         SelectCursor::new(&self.tree, page_index, cell_index)
     }
 
+    /// a cursor on the row with given id, `None` if there is no such row.
+    pub fn select_cursor_at(&self, key: u32) -> Result<Option<SelectCursor<'_>>, String> {
+        if !self.tree.contains_key(key)? {
+            return Result::Ok(None);
+        }
+        let CellIndex {
+            page_index,
+            cell_index,
+        } = self.tree.search_key(key)?;
+        SelectCursor::new(&self.tree, page_index, cell_index).map(Some)
+    }
+
     pub fn insert_cursor(&mut self, key: u32) -> UpdateCursor {
         UpdateCursor::new(&mut self.tree, key)
     }
 
+    pub fn insert(&mut self, row: &Row) -> Result<(), String> {
+        self.insert_cursor(row.id).save(row)?;
+        let users = self.users_table();
+        self.index_row(&users, row.id, &row.values())
+    }
+
+    /// insert a batch of rows as one statement that is committed once all rows are
+    /// in. rows that fail their checks or whose id is taken are left out and
+    /// reported, any other error undoes the whole batch.
+    pub fn insert_many<I: IntoIterator<Item = Row>>(
+        &mut self,
+        rows: I,
+    ) -> Result<InsertReport, RdbError> {
+        let mut report = InsertReport::default();
+        let mut rows: Vec<Row> = rows
+            .into_iter()
+            .filter(|row| match row.check() {
+                Result::Ok(()) => true,
+                Result::Err(_) => {
+                    report.failed_keys.push(row.id);
+                    false
+                }
+            })
+            .collect();
+        // ascending ids mostly go into the leaf of the one before, see
+        // `BTree::insert_key`. the sort is stable so the first of equal ids wins.
+        rows.sort_by_key(|row| row.id);
+        let result = self
+            .insert_rows(&rows, &mut report)
+            .and_then(|_| self.commit())
+            .map_err(|e| self.rollback_failed(e));
+        report.failed_keys.sort_unstable();
+        result.map(|_| report).map_err(RdbError::from)
+    }
+
+    fn insert_rows(&mut self, rows: &[Row], report: &mut InsertReport) -> Result<(), String> {
+        for row in rows {
+            if self.tree.contains_key(row.id)? {
+                report.failed_keys.push(row.id);
+            } else {
+                self.insert(row)?;
+                report.inserted += 1;
+            }
+        }
+        Result::Ok(())
+    }
+
+    /// put a cell into the tree with its root at given page, `value` fills it.
+    fn insert_cell(&mut self, root: usize, key: u32, value: &[u8]) -> Result<(), String> {
+        self.tree.with_root(root, |tree| {
+            let cell_index = tree.insert_key(key)?;
+            let pos = Page::pos_for_cell(cell_index.cell_index) + KEY_SIZE;
+            let rc_page = tree
+                .pager
+                .page_for_write(cell_index.page_index)
+                .map_err(|e| format!("{}", e))?;
+            rc_page.borrow_mut()[pos..pos + ROW_SIZE].copy_from_slice(value);
+            Result::Ok(())
+        })
+    }
+
+    /// remove the row with given id, false if there is none.
+    pub fn delete(&mut self, id: u32) -> Result<bool, String> {
+        if self.has_indexes(TABLE_NAME)? {
+            let values = match self.select_cursor_at(id)? {
+                Some(cursor) => cursor.get().values(),
+                None => return Result::Ok(false),
+            };
+            let users = self.users_table();
+            self.unindex_row(&users, id, &values)?;
+        }
+        let deleted = self.tree.delete_key(id)?;
+        if deleted {
+            self.tree.pager.adjust_row_count(-1);
+        }
+        Result::Ok(deleted)
+    }
+
+    /// a cheap row count for estimates, it may be off after a crash.
+    pub fn estimated_row_count(&self) -> u64 {
+        self.tree.pager.row_count()
+    }
+
+    /// the row count when it can stand in for `select count(*)`, `None` if the rows
+    /// have to be counted since the last shutdown was not clean.
+    pub fn exact_row_count(&self) -> Option<u64> {
+        if self.tree.pager.row_count_is_exact() {
+            Some(self.tree.pager.row_count())
+        } else {
+            None
+        }
+    }
+
+    /**
+     * count the rows with a full scan and commit the result as the new row count.
+     * fails if some pages of the db are neither in a tree of a table or of the
+     * catalog nor on the free list.
+     **/
+    pub fn check(&mut self) -> Result<CheckReport, String> {
+        let pages = self.tree.num_tree_pages()?
+            + self.catalog_pages()?.len()
+            + self.tree.pager.num_free_pages();
+        if pages != self.tree.pager.num_pages {
+            return Result::Err(format!(
+                "{} pages are neither in the tree nor free.",
+                self.tree.pager.num_pages - pages
+            ));
+        }
+        let row_count_before = self.tree.pager.row_count();
+        let mut rows = 0;
+        {
+            let mut cursor = self.select_cursor()?;
+            while !cursor.end_of_table() {
+                rows += 1;
+                cursor.advance()?;
+            }
+        }
+        self.tree.pager.set_row_count(rows);
+        self.commit()?;
+        Result::Ok(CheckReport {
+            rows,
+            row_count_before,
+        })
+    }
+
+    /**
+     * rebuild the table into a new db file and replace the original file with it,
+     * which drops free pages and half empty leaves. the original file is replaced
+     * by a rename after the new one is synced, so either of them is intact if
+     * the vacuum gets interrupted.
+     **/
+    pub fn vacuum(&mut self) -> Result<(), String> {
+        let db_option = self.tree.pager.get_db_option();
+        self.rebuild(db_option)
+    }
+
+    /// encrypt the database with a new key, or decrypt it with `None`.
+    pub fn rekey(&mut self, encryption_key: Option<String>) -> Result<(), String> {
+        let mut db_option = self.tree.pager.get_db_option();
+        db_option.encryption_key = encryption_key;
+        self.rebuild(db_option)
+    }
+
+    /// copy all rows into a new db file created with given options, see `vacuum`.
+    fn rebuild(&mut self, db_option: DbOption) -> Result<(), String> {
+        self.outside_transaction("rebuild the db")?;
+        self.commit()?;
+        let path = self.tree.pager.get_path().map(|path| path.to_owned());
+        let temp_path = match path {
+            Some(ref path) => {
+                let temp_path = format!("{}.vacuum", path);
+                // leftovers of an interrupted vacuum
+                let _ = fs::remove_file(&temp_path);
+                let _ = fs::remove_file(journal_path(&temp_path));
+                temp_path
+            }
+            None => MEMORY_DB.to_owned(),
+        };
+
+        let pager = Pager::new(&temp_path, db_option.clone()).map_err(|e| format!("{}", e))?;
+        let mut new_table = Table::new(BTree::new(pager));
+        {
+            let mut cursor = self.select_cursor()?;
+            while !cursor.end_of_table() {
+                let row = cursor.get();
+                new_table.insert_cursor(row.id).save(&row)?;
+                cursor.advance()?;
+            }
+        }
+        for table in self.tables()?.iter().skip(1) {
+            new_table.create_table(&table.name, &table.schema)?;
+            let new_root = new_table.find_table(&table.name)?.map_or(0, |table| table.root);
+            let mut cursor = self.select_cursor_in(table.root, 0)?;
+            while !cursor.end_of_table() {
+                let row = cursor.row();
+                new_table.insert_cell(new_root, row.key(), &row.value())?;
+                cursor.advance()?;
+            }
+        }
+        for index in self.indexes()? {
+            new_table.create_index(&index.name, &index.table, &index.column)?;
+        }
+        new_table.sync()?;
+
+        match path {
+            Some(path) => {
+                drop(new_table);
+                fs::rename(&temp_path, &path).map_err(|e| format!("vacuum failed: {}", e))?;
+                if let Some(dir) = Path::new(&path).parent() {
+                    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+                    if let Result::Ok(dir) = File::open(dir) {
+                        let _ = dir.sync_all();
+                    }
+                }
+                let pager = Pager::new(&path, db_option).map_err(|e| format!("{}", e))?;
+                self.tree = BTree::new(pager);
+            }
+            None => self.tree = new_table.tree,
+        }
+        Result::Ok(())
+    }
+
+    /**
+     * salvage rows of the users table into a new db file at `path` without trusting
+     * its tree: every page of the db file that looks like a leaf gives its rows, the
+     * first copy of a key wins. the pages of the catalog and of the created tables
+     * that can still be read are left out. nothing is ever written to this table's
+     * db file.
+     **/
+    pub fn recover(&self, path: &str) -> Result<RecoverReport, String> {
+        if path != MEMORY_DB && Path::new(path).exists() {
+            return Result::Err(format!("recover failed: {} already exists.", path));
+        }
+        let other_pages: HashSet<usize> = self.catalog_pages().unwrap_or_default().into_iter().collect();
+        let mut rows = BTreeMap::new();
+        let mut pages_skipped = 0;
+        for (page_index, page) in self.tree.pager.scan_pages() {
+            let page = match page {
+                Result::Ok(_) if other_pages.contains(&page_index) => continue,
+                Result::Ok(ref page) if self.tree.config.is_plausible_leaf(page) => page,
+                Result::Ok(_) => {
+                    pages_skipped += 1;
+                    continue;
+                }
+                Result::Err(e) => {
+                    warn!("skip page {}: {}", page_index, e);
+                    pages_skipped += 1;
+                    continue;
+                }
+            };
+            for cell_index in 0..page.get_num_cells() as usize {
+                let row = Row::deserialize(page, Page::pos_for_cell(cell_index) + KEY_SIZE);
+                rows.entry(row.id).or_insert(row);
+            }
+        }
+
+        let mut db_option = self.tree.pager.get_db_option();
+        db_option.read_only = false;
+        let pager = Pager::new(path, db_option).map_err(|e| format!("{}", e))?;
+        let mut recovered = Table::new(BTree::new(pager));
+        for row in rows.values() {
+            recovered.insert_cursor(row.id).save(row)?;
+        }
+        recovered.sync()?;
+        recovered.close().map_err(|e| format!("{}", e))?;
+        Result::Ok(RecoverReport {
+            rows_recovered: rows.len(),
+            pages_skipped,
+        })
+    }
+
     // TODO: remove this method
-    pub fn debug_print(&self, only_internal: bool) {
-        self.tree.debug_print(only_internal);
+    pub fn debug_print(&self, only_internal: bool) -> Result<(), String> {
+        self.tree.debug_print(only_internal)
     }
 }
 
+/// reads the rows in key order. the leaf under the cursor stays pinned, only moving
+/// on to the next leaf reads a page and may fail.
 pub struct SelectCursor<'a> {
     tree: &'a BTree,
-    page_index: usize,
+    /// `None` for a table without pages.
+    page: Option<PageGuard>,
     cell_index: usize,
 }
 
 impl<'a> SelectCursor<'a> {
-    fn new(tree: &'a BTree, page_index: usize, cell_index: usize) -> SelectCursor<'a> {
-        SelectCursor {
-            tree: tree,
-            page_index: page_index,
-            cell_index: cell_index,
-        }
+    fn new(tree: &'a BTree, page_index: usize, cell_index: usize) -> Result<SelectCursor<'a>, String> {
+        let page = match tree.pager.num_pages {
+            0 => None,
+            _ => Some(SelectCursor::read(tree, page_index)?),
+        };
+        let mut cursor = SelectCursor {
+            tree,
+            page,
+            cell_index,
+        };
+        cursor.skip_passed_leaves()?;
+        Result::Ok(cursor)
     }
 
-    fn get_page(&self) -> Rc<RefCell<Page>> {
-        self.tree.pager.page_for_read(self.page_index)
+    fn read(tree: &BTree, page_index: usize) -> Result<PageGuard, String> {
+        tree.pager
+            .page_for_read(page_index)
+            .map_err(|e| format!("cannot read page {}: {}", page_index, e))
     }
 
     pub fn end_of_table(&self) -> bool {
-        self.tree.pager.num_pages == 0 || self.is_last_page()
+        match self.page {
+            Some(ref rc_page) => {
+                let page = &rc_page.borrow();
+                self.cell_index >= (page.get_num_cells() as usize) && !page.has_next_page()
+            }
+            None => true,
+        }
     }
 
-    fn is_last_page(&self) -> bool {
-        let rc_page = self.get_page();
-        let page = &rc_page.borrow();
-        (self.cell_index >= (page.get_num_cells() as usize) && !page.has_next_page())
+    pub fn advance(&mut self) -> Result<(), String> {
+        self.cell_index += 1;
+        self.skip_passed_leaves()
     }
 
-    pub fn advance(&mut self) {
-        let rc_page = self.get_page();
-        let page = &rc_page.borrow();
-        let num_cells = page.get_num_cells() as usize;
-        self.cell_index += 1;
-        if self.cell_index >= num_cells && page.has_next_page() {
-            let next_page_index = page.get_next_page();
-            self.page_index = next_page_index;
+    /// move on to the next leaf with cells once past the cells of a leaf, the
+    /// leaves emptied by deletes are skipped.
+    fn skip_passed_leaves(&mut self) -> Result<(), String> {
+        while let Some(next_page_index) = self.next_page_index() {
+            self.page = Some(SelectCursor::read(self.tree, next_page_index)?);
             self.cell_index = 0;
         }
+        Result::Ok(())
     }
 
-    pub fn get(&self) -> Row {
-        let cell_pos = Page::pos_for_cell(self.cell_index);
-        let rc_page = self.tree.pager.page_for_read(self.page_index);
+    /// the leaf after the one under the cursor if the cursor is past its cells.
+    fn next_page_index(&self) -> Option<usize> {
+        let rc_page = self.page.as_ref()?;
         let page = &rc_page.borrow();
-        Row::deserialize(page, cell_pos + KEY_SIZE)
+        if self.cell_index < page.get_num_cells() as usize || !page.has_next_page() {
+            None
+        } else {
+            Some(page.get_next_page())
+        }
+    }
+
+    pub fn get(&self) -> Row {
+        self.row().to_row()
+    }
+
+    /// the row under the cursor read in place, for queries that only need some of
+    /// its columns.
+    pub fn row(&self) -> RowRef {
+        let page = self.page.as_ref().expect("read a row past the end of the table");
+        RowRef {
+            page: page.clone(),
+            pos: Page::pos_for_cell(self.cell_index) + KEY_SIZE,
+        }
+    }
+}
+
+/// a row left in its page, a column is only decoded once it is asked for. the page
+/// stays pinned in the cache until the row is dropped.
+#[derive(Clone)]
+pub struct RowRef {
+    page: PageGuard,
+    pos: usize,
+}
+
+impl RowRef {
+    pub fn id(&self) -> u32 {
+        BigEndian::read_u32(&self.page.borrow()[self.pos..])
+    }
+
+    pub fn username(&self) -> String {
+        Row::read_string(&self.page.borrow(), self.pos + 4, 32)
+    }
+
+    pub fn email(&self) -> String {
+        Row::read_string(&self.page.borrow(), self.pos + 36, 256)
+    }
+
+    pub fn to_row(&self) -> Row {
+        Row::deserialize(&self.page.borrow(), self.pos)
+    }
+
+    /// the key the row is stored under, the id of a row of the users table.
+    pub fn key(&self) -> u32 {
+        BigEndian::read_u32(&self.page.borrow()[self.pos - KEY_SIZE..])
+    }
+
+    /// the bytes of the row as they are stored.
+    pub fn value(&self) -> Vec<u8> {
+        self.page.borrow()[self.pos..self.pos + ROW_SIZE].to_vec()
+    }
+
+    /// the values of a row of a created table with given schema.
+    pub fn values(&self, schema: &Schema) -> Vec<Value> {
+        schema.read_row(&self.page.borrow()[self.pos..self.pos + ROW_SIZE])
     }
 }
 
@@ -174,11 +699,676 @@ impl<'a> UpdateCursor<'a> {
     }
 
     pub fn save(&mut self, row: &Row) -> Result<(), String> {
-        self.tree.insert_key(self.key).map(|cell_index| {
-            let cell_pos = Page::pos_for_cell(cell_index.cell_index);
-            let rc_page = self.tree.pager.page_for_write(cell_index.page_index);
-            let page = &mut rc_page.borrow_mut();
-            Row::serialize(row, page, cell_pos + KEY_SIZE);
-        })
+        let cell_index = self.tree.insert_key(self.key)?;
+        let cell_pos = Page::pos_for_cell(cell_index.cell_index);
+        let rc_page = self
+            .tree
+            .pager
+            .page_for_write(cell_index.page_index)
+            .map_err(|e| format!("{}", e))?;
+        Row::serialize(row, &mut rc_page.borrow_mut(), cell_pos + KEY_SIZE);
+        self.tree.pager.adjust_row_count(1);
+        Result::Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sql::SQLType;
+    use std::env;
+
+    fn row(id: u32) -> Row {
+        Row {
+            id,
+            username: format!("user{}", id),
+            email: format!("person{}@example.com", id),
+        }
+    }
+
+    #[test]
+    fn select_cursor_scans_inserted_rows_in_key_order() {
+        let mut table = Table::in_memory_for_test();
+        for id in (1..30).rev() {
+            assert!(table.insert_cursor(id).save(&row(id)).is_ok());
+        }
+
+        let mut cursor = table.select_cursor().unwrap();
+        let mut id = 1;
+        while !cursor.end_of_table() {
+            let r = cursor.get();
+            assert_eq!(r.id, id);
+            assert_eq!(r.username, format!("user{}", id));
+            assert_eq!(r.email, format!("person{}@example.com", id));
+            cursor.advance().unwrap();
+            id += 1;
+        }
+        assert_eq!(id, 30);
+    }
+
+    #[test]
+    fn rollback_discards_inserted_rows() {
+        let mut table = Table::in_memory_for_test();
+        assert!(table.insert_cursor(1).save(&row(1)).is_ok());
+        table.commit().unwrap();
+        assert!(table.insert_cursor(2).save(&row(2)).is_ok());
+        table.rollback().unwrap();
+
+        let mut cursor = table.select_cursor().unwrap();
+        assert_eq!(cursor.get().id, 1);
+        cursor.advance().unwrap();
+        assert!(cursor.end_of_table());
+    }
+
+    fn ids(table: &Table) -> Vec<u32> {
+        let mut ids = Vec::new();
+        let mut cursor = table.select_cursor().unwrap();
+        while !cursor.end_of_table() {
+            ids.push(cursor.get().id);
+            cursor.advance().unwrap();
+        }
+        ids
+    }
+
+    /// insert rows as statements do, each one committed or rolled back on its own.
+    fn insert(table: &mut Table, ids: &[u32]) {
+        for &id in ids {
+            let saved = table.insert_cursor(id).save(&row(id));
+            if saved.is_ok() {
+                table.commit().unwrap();
+            } else {
+                table.rollback().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn rollback_to_an_inner_savepoint_keeps_the_outer_changes() {
+        let path = env::temp_dir().join(format!("rdb-table-savepoint-{}.rdb", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        {
+            let pager = Pager::new(path, row_count_db_option()).unwrap();
+            let mut table = Table::new(BTree::new(pager));
+            insert(&mut table, &[1]);
+            table.savepoint("outer").unwrap();
+            insert(&mut table, &[2, 3]);
+            table.savepoint("inner").unwrap();
+            // enough rows to split pages after the inner savepoint
+            let inner: Vec<u32> = (4..100).collect();
+            insert(&mut table, &inner);
+            assert_eq!(table.exact_row_count(), Some(99));
+            table.rollback_to("inner").unwrap();
+            assert_eq!(ids(&table), vec![1, 2, 3]);
+            assert_eq!(table.exact_row_count(), Some(3));
+
+            // the inner savepoint stays set
+            insert(&mut table, &[4]);
+            table.rollback_to("inner").unwrap();
+            assert_eq!(ids(&table), vec![1, 2, 3]);
+            insert(&mut table, &[5]);
+            table.release("inner").unwrap();
+            assert!(table.in_transaction());
+            table.release("outer").unwrap();
+            assert!(!table.in_transaction());
+            table.close().unwrap();
+        }
+
+        let table = Table::new(BTree::new(Pager::new(path, row_count_db_option()).unwrap()));
+        assert_eq!(ids(&table), vec![1, 2, 3, 5]);
+        assert_eq!(table.exact_row_count(), Some(4));
+        table.close().unwrap();
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn a_failed_statement_in_a_transaction_only_undoes_itself() {
+        let mut table = Table::in_memory_for_test();
+        table.savepoint("sp").unwrap();
+        insert(&mut table, &[1, 2, 1, 3]);
+        assert_eq!(ids(&table), vec![1, 2, 3]);
+        table.rollback_to("sp").unwrap();
+        assert!(ids(&table).is_empty());
+    }
+
+    #[test]
+    fn savepoint_misuse_is_an_error() {
+        let mut table = Table::in_memory_for_test();
+        assert_eq!(
+            table.rollback_to("sp"),
+            Result::Err("cannot roll back to sp: no transaction is active.".to_owned())
+        );
+        assert_eq!(
+            table.release("sp"),
+            Result::Err("cannot release sp: no transaction is active.".to_owned())
+        );
+        table.savepoint("sp").unwrap();
+        assert_eq!(
+            table.savepoint("sp"),
+            Result::Err("savepoint sp already exists.".to_owned())
+        );
+        assert_eq!(
+            table.release("other"),
+            Result::Err("cannot release other: no such savepoint.".to_owned())
+        );
+        assert!(table.vacuum().is_err());
+        assert!(table.sync().is_err());
+        table.release("sp").unwrap();
+        assert!(table.sync().is_ok());
+    }
+
+    #[test]
+    fn closing_rolls_back_an_open_transaction() {
+        let path = env::temp_dir().join(format!("rdb-table-open-tx-{}.rdb", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        {
+            let pager = Pager::new(path, row_count_db_option()).unwrap();
+            let mut table = Table::new(BTree::new(pager));
+            insert(&mut table, &[1]);
+            table.savepoint("sp").unwrap();
+            insert(&mut table, &[2]);
+            table.close().unwrap();
+        }
+
+        let table = Table::new(BTree::new(Pager::new(path, row_count_db_option()).unwrap()));
+        assert_eq!(ids(&table), vec![1]);
+        table.close().unwrap();
+        let _ = fs::remove_file(path);
+    }
+
+
+
+    /// a row with a username and email of every length the columns hold.
+    fn sized_row(id: u32) -> Row {
+        Row {
+            id,
+            username: "u".repeat(id as usize % 33),
+            email: "e".repeat(id as usize * 7 % 256),
+        }
+    }
+
+    #[test]
+    fn flushed_pages_stay_byte_for_byte_the_same() {
+        let path = env::temp_dir().join(format!("rdb-table-bytes-{}.rdb", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        {
+            let pager = Pager::new(path, row_count_db_option()).unwrap();
+            let mut table = Table::new(BTree::new(pager));
+            // a stride coprime to the count fills the leaves out of order
+            for i in 0..10000u32 {
+                let id = i * 7919 % 10000 + 1;
+                assert!(table.insert_cursor(id).save(&sized_row(id)).is_ok());
+            }
+            table.close().unwrap();
+        }
+        let bytes = fs::read(path).unwrap();
+        assert_eq!((bytes.len(), crc32fast::hash(&bytes)), (4452452, 2121118245));
+
+        let table = Table::new(BTree::new(Pager::new(path, row_count_db_option()).unwrap()));
+        let mut cursor = table.select_cursor().unwrap();
+        while !cursor.end_of_table() {
+            let row = cursor.get();
+            let expected = sized_row(row.id);
+            assert_eq!((row.username, row.email), (expected.username, expected.email));
+            cursor.advance().unwrap();
+        }
+        let _ = fs::remove_file(path);
+    }
+
+    fn collect_ids(table: &Table) -> Vec<u32> {
+        let mut ids = Vec::new();
+        let mut cursor = table.select_cursor().unwrap();
+        while !cursor.end_of_table() {
+            ids.push(cursor.get().id);
+            cursor.advance().unwrap();
+        }
+        ids
+    }
+
+    #[test]
+    fn vacuum_shrinks_the_file_and_keeps_all_rows() {
+        let path = env::temp_dir().join(format!("rdb-table-vacuum-{}.rdb", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        let db_option = DbOption {
+            sync_mode: SyncMode::Off,
+            ..Default::default()
+        };
+        let mut table = Table::new(BTree::new(Pager::new(path, db_option).unwrap()));
+        for id in (1..100).rev() {
+            assert!(table.insert_cursor(id).save(&row(id)).is_ok());
+        }
+        table.commit().unwrap();
+        // the lower half of the rows, their leaves go onto the free list
+        for id in 1..50 {
+            assert_eq!(table.delete(id), Result::Ok(true));
+        }
+        table.commit().unwrap();
+        assert!(table.tree.pager.num_free_pages() > 0);
+        let file_size = fs::metadata(path).unwrap().len();
+
+        assert_eq!(table.vacuum(), Result::Ok(()));
+        assert!(fs::metadata(path).unwrap().len() < file_size);
+        assert_eq!(table.tree.pager.num_free_pages(), 0);
+        assert_eq!(collect_ids(&table), (50..100).collect::<Vec<u32>>());
+        assert_eq!(table.exact_row_count(), Some(50));
+        assert!(!Path::new(&format!("{}.vacuum", path)).exists());
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn created_tables_are_checked_and_vacuumed_with_the_users_table() {
+        let mut table = Table::in_memory_for_test();
+        for id in 1..30 {
+            assert!(table.insert_cursor(id).save(&row(id)).is_ok());
+        }
+        let schema = Schema::from_columns(vec![
+            ("title".to_owned(), SQLType::String),
+            ("pages".to_owned(), SQLType::Integer),
+        ])
+        .unwrap();
+        assert_eq!(table.create_table("books", &schema), Result::Ok(()));
+        let books = table.find_table("books").unwrap().unwrap();
+        for pages in 1..100 {
+            let values = [Value::Text(format!("book {}", pages)), Value::Integer(pages)];
+            assert_eq!(table.insert_values(&books, &values), Result::Ok(()));
+        }
+        assert_eq!(table.delete_from(&books, 1), Result::Ok(true));
+        assert_eq!(table.check().map(|report| report.rows), Result::Ok(29));
+
+        assert_eq!(table.vacuum(), Result::Ok(()));
+        assert_eq!(collect_ids(&table), (1..30).collect::<Vec<u32>>());
+        let books = table.find_table("books").unwrap().unwrap();
+        let mut cursor = table.select_cursor_in(books.root, 0).unwrap();
+        let mut rows = Vec::new();
+        while !cursor.end_of_table() {
+            rows.push((cursor.row().key(), cursor.row().values(&books.schema)));
+            cursor.advance().unwrap();
+        }
+        assert_eq!(rows.len(), 98);
+        assert_eq!(
+            rows[0],
+            (2, vec![Value::Text("book 2".to_owned()), Value::Integer(2)])
+        );
+        assert!(table.check().is_ok());
+    }
+
+    #[test]
+    fn dropped_tables_give_their_pages_to_the_free_list() {
+        let mut table = Table::in_memory_for_test();
+        let schema = Schema::from_columns(vec![("pages".to_owned(), SQLType::Integer)]).unwrap();
+        table.create_table("books", &schema).unwrap();
+        let books = table.find_table("books").unwrap().unwrap();
+        for pages in 0..100 {
+            table.insert_values(&books, &[Value::Integer(pages)]).unwrap();
+        }
+        let num_pages = table.tree.pager.num_pages;
+        let tree_pages = table.tree.tree_pages_from(books.root).unwrap().len();
+        assert!(tree_pages > 1);
+
+        assert_eq!(table.drop_table("books"), Result::Ok(()));
+        assert_eq!(table.tree.pager.num_free_pages(), tree_pages);
+        assert!(table.find_table("books").unwrap().is_none());
+        assert!(table.check().is_ok());
+        assert_eq!(
+            table.drop_table("books"),
+            Result::Err("no such table: books".to_owned())
+        );
+
+        // a table created again takes its pages from the free list
+        table.create_table("books", &schema).unwrap();
+        let books = table.find_table("books").unwrap().unwrap();
+        for pages in 0..100 {
+            table.insert_values(&books, &[Value::Integer(pages)]).unwrap();
+        }
+        assert_eq!(table.tree.pager.num_pages, num_pages);
+        assert!(table.check().is_ok());
+    }
+
+    #[test]
+    fn indexes_find_the_rows_of_a_value() {
+        let mut table = Table::in_memory_for_test();
+        for id in 1..200 {
+            table.insert(&row(id)).unwrap();
+        }
+        table.create_index("by_name", TABLE_NAME, "username").unwrap();
+        let index = table.find_index("by_name").unwrap().unwrap();
+        let user = |id| Value::Text(format!("user{}", id));
+        assert_eq!(table.index_lookup(&index, &user(42)), Result::Ok(vec![42]));
+        assert_eq!(table.index_lookup(&index, &user(200)), Result::Ok(vec![]));
+
+        table.insert(&Row { id: 300, ..row(42) }).unwrap();
+        assert_eq!(table.delete(42), Result::Ok(true));
+        assert_eq!(table.index_lookup(&index, &user(42)), Result::Ok(vec![300]));
+        assert!(table.check().is_ok());
+        assert_eq!(table.vacuum(), Result::Ok(()));
+        let index = table.find_index("by_name").unwrap().unwrap();
+        assert_eq!(table.index_lookup(&index, &user(42)), Result::Ok(vec![300]));
+
+        let schema = Schema::from_columns(vec![("pages".to_owned(), SQLType::Integer)]).unwrap();
+        table.create_table("books", &schema).unwrap();
+        table.create_index("by_pages", "books", "pages").unwrap();
+        let books = table.find_table("books").unwrap().unwrap();
+        for pages in 0..100 {
+            table.insert_values(&books, &[Value::Integer(pages % 10)]).unwrap();
+        }
+        let by_pages = table.find_index("by_pages").unwrap().unwrap();
+        assert_eq!(
+            table.index_lookup(&by_pages, &Value::Integer(3)).unwrap().len(),
+            10
+        );
+        assert_eq!(table.delete_from(&books, 4), Result::Ok(true));
+        assert_eq!(
+            table.index_lookup(&by_pages, &Value::Integer(3)).unwrap().len(),
+            9
+        );
+        assert!(table.check().is_ok());
+        table.drop_table("books").unwrap();
+        assert_eq!(table.indexes(), Result::Ok(vec![index]));
+        assert!(table.check().is_ok());
+    }
+
+    #[test]
+    fn vacuum_works_for_memory_db() {
+        let mut table = Table::in_memory_for_test();
+        for id in 1..30 {
+            assert!(table.insert_cursor(id).save(&row(id)).is_ok());
+        }
+        assert_eq!(table.vacuum(), Result::Ok(()));
+        assert_eq!(collect_ids(&table), (1..30).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn recover_salvages_all_rows_around_corrupted_internal_pages() {
+        use header::DB_HEADER_SIZE;
+
+        let path = env::temp_dir().join(format!("rdb-table-recover-{}.rdb", std::process::id()));
+        let path = path.to_str().unwrap();
+        let output = format!("{}.recovered", path);
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(&output);
+        let db_option = DbOption {
+            sync_mode: SyncMode::Off,
+            ..Default::default()
+        };
+        let internal_pages: Vec<usize> = {
+            let mut table = Table::new(BTree::new(Pager::new(path, db_option.clone()).unwrap()));
+            for id in (1..200).rev() {
+                assert!(table.insert_cursor(id).save(&row(id)).is_ok());
+            }
+            table.commit().unwrap();
+            let internal_pages = table
+                .tree
+                .pager
+                .scan_pages()
+                .filter(|(_, page)| page.as_ref().unwrap()[0] == 0)
+                .map(|(page_index, _)| page_index)
+                .collect();
+            table.close().unwrap();
+            internal_pages
+        };
+        assert!(!internal_pages.is_empty());
+        let mut bytes = fs::read(path).unwrap();
+        for page_index in &internal_pages {
+            let offset = DB_HEADER_SIZE + page_index * 4096;
+            for b in &mut bytes[offset..offset + 4096] {
+                *b = 0xff;
+            }
+        }
+        fs::write(path, &bytes).unwrap();
+
+        let table = Table::new(BTree::new(Pager::new(path, db_option.clone()).unwrap()));
+        let report = table.recover(&output).unwrap();
+        assert_eq!(report.rows_recovered, 199);
+        assert_eq!(report.pages_skipped, internal_pages.len());
+        assert!(table.recover(&output).is_err());
+        table.close().unwrap();
+        assert_eq!(fs::read(path).unwrap(), bytes);
+
+        let recovered = Table::new(BTree::new(Pager::new(&output, db_option).unwrap()));
+        assert_eq!(collect_ids(&recovered), (1..200).collect::<Vec<u32>>());
+        let r = {
+            let mut cursor = recovered.select_cursor().unwrap();
+            while cursor.get().id != 150 {
+                cursor.advance().unwrap();
+            }
+            cursor.get()
+        };
+        assert_eq!(r.username, "user150");
+        assert_eq!(r.email, "person150@example.com");
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(&output);
+    }
+
+    #[test]
+    fn dropped_table_flushes_unflushed_inserts() {
+        let path = env::temp_dir().join(format!("rdb-table-drop-{}.rdb", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        let db_option = DbOption {
+            sync_mode: SyncMode::OnClose,
+            ..Default::default()
+        };
+        {
+            let mut table = Table::new(BTree::new(Pager::new(path, db_option.clone()).unwrap()));
+            for id in 1..30 {
+                assert!(table.insert_cursor(id).save(&row(id)).is_ok());
+            }
+        }
+        assert!(!Path::new(&journal_path(path)).exists());
+
+        let table = Table::new(BTree::new(Pager::new(path, db_option).unwrap()));
+        assert_eq!(collect_ids(&table), (1..30).collect::<Vec<u32>>());
+        assert_eq!(table.close(), Result::Ok(()));
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn insert_many_leaves_out_invalid_and_taken_ids() {
+        let mut table = Table::in_memory_for_test();
+        assert!(table.insert_cursor(5).save(&row(5)).is_ok());
+        let mut too_long = row(7);
+        too_long.username = "u".repeat(33);
+        let mut rows: Vec<Row> = (1..200).rev().filter(|&id| id != 7).map(row).collect();
+        rows.push(too_long);
+        rows.push(row(3));
+        let report = table.insert_many(rows).unwrap();
+        assert_eq!(
+            report,
+            InsertReport {
+                inserted: 197,
+                failed_keys: vec![3, 5, 7],
+            }
+        );
+        let ids: Vec<u32> = (1..200).filter(|&id| id != 7).collect();
+        assert_eq!(collect_ids(&table), ids);
+        assert_eq!(table.exact_row_count(), Some(198));
+    }
+
+    #[test]
+    fn insert_many_in_a_transaction_is_undone_with_it() {
+        let mut table = Table::in_memory_for_test();
+        table.savepoint("batch").unwrap();
+        let report = table.insert_many((1..100).map(row)).unwrap();
+        assert_eq!(report.inserted, 99);
+        table.rollback_to("batch").unwrap();
+        table.release("batch").unwrap();
+        assert_eq!(collect_ids(&table), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn row_count_follows_inserts_and_rollbacks() {
+        let mut table = Table::in_memory_for_test();
+        for id in 1..30 {
+            assert!(table.insert_cursor(id).save(&row(id)).is_ok());
+        }
+        assert!(table.insert_cursor(1).save(&row(1)).is_err());
+        assert_eq!(table.exact_row_count(), Some(29));
+        table.commit().unwrap();
+
+        assert!(table.insert_cursor(30).save(&row(30)).is_ok());
+        assert_eq!(table.estimated_row_count(), 30);
+        table.rollback().unwrap();
+        assert_eq!(table.exact_row_count(), Some(29));
+        table.tree.pager.adjust_row_count(-2);
+        assert_eq!(table.estimated_row_count(), 27);
+    }
+
+    fn row_count_db_option() -> DbOption {
+        DbOption {
+            sync_mode: SyncMode::Off,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn row_count_persists_across_reopen() {
+        let path = env::temp_dir().join(format!("rdb-table-count-{}.rdb", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        {
+            let pager = Pager::new(path, row_count_db_option()).unwrap();
+            let mut table = Table::new(BTree::new(pager));
+            for id in 1..30 {
+                assert!(table.insert_cursor(id).save(&row(id)).is_ok());
+            }
+            table.close().unwrap();
+        }
+
+        let table = Table::new(BTree::new(Pager::new(path, row_count_db_option()).unwrap()));
+        assert_eq!(table.exact_row_count(), Some(29));
+        table.close().unwrap();
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn scanning_a_truncated_db_file_is_an_error() {
+        use header::DB_HEADER_SIZE;
+        use std::fs::OpenOptions;
+
+        let path = env::temp_dir().join(format!("rdb-table-truncated-{}.rdb", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        {
+            let pager = Pager::new(path, row_count_db_option()).unwrap();
+            let mut table = Table::new(BTree::new(pager));
+            for id in 1..100 {
+                assert!(table.insert_cursor(id).save(&row(id)).is_ok());
+            }
+            table.close().unwrap();
+        }
+
+        let table = Table::new(BTree::new(Pager::new(path, row_count_db_option()).unwrap()));
+        let file = OpenOptions::new().write(true).open(path).unwrap();
+        file.set_len((DB_HEADER_SIZE + 2 * 4096 + 100) as u64).unwrap();
+        let scan = || -> Result<usize, String> {
+            let mut cursor = table.select_cursor()?;
+            let mut rows = 0;
+            while !cursor.end_of_table() {
+                rows += 1;
+                cursor.advance()?;
+            }
+            Result::Ok(rows)
+        };
+        let error = scan().err().unwrap();
+        assert!(error.ends_with("is truncated, db file is corrupted."), "{}", error);
+        drop(table);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn check_corrects_the_row_count_after_an_unclean_shutdown() {
+        let path = env::temp_dir().join(format!("rdb-table-check-{}.rdb", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        {
+            let pager = Pager::new(path, row_count_db_option()).unwrap();
+            let mut table = Table::new(BTree::new(pager));
+            for id in 1..30 {
+                assert!(table.insert_cursor(id).save(&row(id)).is_ok());
+            }
+            // a count gone stale, like one missing the updates lost in a crash
+            table.tree.pager.adjust_row_count(-5);
+            table.commit().unwrap();
+            table.tree.pager.simulate_crash();
+        }
+
+        let mut table = Table::new(BTree::new(Pager::new(path, row_count_db_option()).unwrap()));
+        assert_eq!(table.exact_row_count(), None);
+        assert_eq!(table.estimated_row_count(), 24);
+        assert_eq!(
+            table.check(),
+            Result::Ok(CheckReport {
+                rows: 29,
+                row_count_before: 24,
+            })
+        );
+        assert_eq!(table.exact_row_count(), Some(29));
+        table.close().unwrap();
+
+        let table = Table::new(BTree::new(Pager::new(path, row_count_db_option()).unwrap()));
+        assert_eq!(table.exact_row_count(), Some(29));
+        table.close().unwrap();
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn pages_of_deleted_rows_are_reused_after_reopen() {
+        let path = env::temp_dir().join(format!("rdb-table-reuse-{}.rdb", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        {
+            let mut table = Table::new(BTree::new(Pager::new(path, row_count_db_option()).unwrap()));
+            for id in 1..200 {
+                table.insert(&row(id)).unwrap();
+            }
+            for id in 50..150 {
+                assert_eq!(table.delete(id), Result::Ok(true));
+            }
+            table.close().unwrap();
+        }
+        let file_size = fs::metadata(path).unwrap().len();
+
+        let mut table = Table::new(BTree::new(Pager::new(path, row_count_db_option()).unwrap()));
+        assert!(table.tree.pager.num_free_pages() > 0);
+        for id in 50..150 {
+            table.insert(&row(id)).unwrap();
+        }
+        assert_eq!(
+            table.check(),
+            Result::Ok(CheckReport {
+                rows: 199,
+                row_count_before: 199,
+            })
+        );
+        table.close().unwrap();
+        assert!(fs::metadata(path).unwrap().len() <= file_size);
+        let _ = fs::remove_file(path);
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn rekey_encrypts_an_existing_db() {
+        let path = env::temp_dir().join(format!("rdb-table-rekey-{}.rdb", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        let db_option = |encryption_key: Option<&str>| DbOption {
+            sync_mode: SyncMode::Off,
+            encryption_key: encryption_key.map(|key| key.to_owned()),
+            ..Default::default()
+        };
+        {
+            let mut table = Table::new(BTree::new(Pager::new(path, db_option(None)).unwrap()));
+            for id in 1..30 {
+                assert!(table.insert_cursor(id).save(&row(id)).is_ok());
+            }
+            assert_eq!(table.rekey(Some("secret".to_owned())), Result::Ok(()));
+        }
+        assert!(Pager::new(path, db_option(None)).is_err());
+        let table = Table::new(BTree::new(Pager::new(path, db_option(Some("secret"))).unwrap()));
+        assert_eq!(collect_ids(&table), (1..30).collect::<Vec<u32>>());
+        let _ = fs::remove_file(path);
     }
 }
@@ -1,20 +1,201 @@
+use byteorder::{BigEndian, ByteOrder};
+
+use btree::ROW_SIZE;
 use sql::SQLType;
-use std::collections::HashMap;
+use vm::Value;
 
+#[derive(Clone)]
 pub struct Schema {
-    columns: HashMap<String, SQLType>,
+    /// the columns in the order they are stored in a row.
+    columns: Vec<(String, SQLType)>,
+    /// rows are keyed by a row id kept apart from their columns, rather than by
+    /// the id column like the rows of the users table.
+    rowid: bool,
+    /// the types of the parameters of the statement the codes are generated for,
+    /// `None` for one whose use gives it no type.
+    parameter_types: Vec<Option<SQLType>>,
+}
+
+impl Default for Schema {
+    fn default() -> Schema {
+        Schema::new()
+    }
 }
 
 impl Schema {
     pub fn new() -> Schema {
-        let mut map = HashMap::new();
-        map.insert("id".to_owned(), SQLType::Integer);
-        map.insert("name".to_owned(), SQLType::String);
-        map.insert("email".to_owned(), SQLType::String);
-        Schema { columns: map }
+        Schema {
+            columns: vec![
+                ("id".to_owned(), SQLType::Integer),
+                ("username".to_owned(), SQLType::String),
+                ("email".to_owned(), SQLType::String),
+            ],
+            rowid: false,
+            parameter_types: Vec::new(),
+        }
+    }
+
+    /// a schema of given columns, like the ones of a `create table`.
+    pub fn from_columns(columns: Vec<(String, SQLType)>) -> Result<Schema, String> {
+        if columns.is_empty() {
+            return Result::Err("a table has at least one column.".to_owned());
+        }
+        for (index, (name, _)) in columns.iter().enumerate() {
+            if columns[..index].iter().any(|(other, _)| other == name) {
+                return Result::Err(format!("duplicate column name: {}", name));
+            }
+        }
+        Result::Ok(Schema {
+            columns,
+            rowid: true,
+            parameter_types: Vec::new(),
+        })
+    }
+
+    /// the schema with the types of the parameters `?1`, `?2`... of a statement.
+    pub fn with_parameter_types(mut self, parameter_types: Vec<Option<SQLType>>) -> Schema {
+        self.parameter_types = parameter_types;
+        self
+    }
+
+    pub fn parameter_types(&self) -> &[Option<SQLType>] {
+        &self.parameter_types
+    }
+
+    /// the type of the parameter at given index, starting at 1.
+    pub fn get_parameter_type(&self, index: usize) -> Option<SQLType> {
+        self.parameter_types.get(index.wrapping_sub(1)).cloned().flatten()
+    }
+
+    /// the names and types of the columns in the order they are stored in a row.
+    pub fn columns(&self) -> &[(String, SQLType)] {
+        &self.columns
+    }
+
+    pub fn get_column_type(&self, column: &str) -> Option<SQLType> {
+        self.column_index(column).map(|index| self.columns[index].1)
+    }
+
+    /// the position of a column in a row.
+    pub fn column_index(&self, column: &str) -> Option<usize> {
+        self.columns.iter().position(|(name, _)| name == column)
+    }
+
+    /// the column names in the order they are stored in a row.
+    pub fn row_columns(&self) -> Vec<String> {
+        self.columns.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// the column names in alphabetical order.
+    pub fn column_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.columns.iter().map(|(name, _)| name.clone()).collect();
+        names.sort();
+        names
+    }
+
+    /// whether rows are keyed by a row id, which the rows of a created table are.
+    pub fn has_rowid(&self) -> bool {
+        self.rowid
+    }
+
+    /// the column the codes read the key of a row from, the row id of a created
+    /// table comes after its columns.
+    pub fn key_column(&self) -> usize {
+        if self.rowid {
+            self.columns.len()
+        } else {
+            0
+        }
+    }
+
+    /// the value as it is stored in the column at given position, an integer
+    /// becomes a real for a real column. the codes have no NULL to read, so a
+    /// column takes none.
+    pub fn column_value(&self, column: usize, value: Value) -> Result<Value, String> {
+        let (ref name, sql_type) = self.columns[column];
+        match (sql_type, value) {
+            (_, Value::Null) => Result::Err(format!("column {} cannot be NULL.", name)),
+            (SQLType::Real, Value::Integer(v)) => Result::Ok(Value::Real(v as f64)),
+            (SQLType::Integer, value @ Value::Integer(_))
+            | (SQLType::Real, value @ Value::Real(_))
+            | (SQLType::Boolean, value @ Value::Boolean(_))
+            | (SQLType::DateTime, value @ Value::DateTime(_))
+            | (SQLType::String, value @ Value::Text(_)) => Result::Ok(value),
+            (sql_type, value) => Result::Err(format!(
+                "column {} takes {} values, not {}.",
+                name,
+                sql_type,
+                value.type_name()
+            )),
+        }
+    }
+
+    /**
+     * the bytes of a row of a created table, its values are the ones of
+     * `column_value`. every value is a byte telling a NULL apart followed by 8
+     * bytes for an integer, a real or a datetime, 1 for a boolean and a 2 byte
+     * length ahead of the bytes of a text. a row takes `ROW_SIZE` bytes at most.
+     **/
+    pub fn write_row(&self, values: &[Value]) -> Result<Vec<u8>, String> {
+        let mut buf = vec![0u8; ROW_SIZE];
+        let mut pos = 0;
+        for value in values {
+            let size = match value {
+                Value::Null => 0,
+                Value::Integer(_) | Value::Real(_) | Value::DateTime(_) => 8,
+                Value::Boolean(_) => 1,
+                Value::Text(text) => 2 + text.len(),
+            };
+            if pos + 1 + size > ROW_SIZE {
+                return Result::Err(format!("a row takes at most {} bytes.", ROW_SIZE));
+            }
+            buf[pos] = match value {
+                Value::Null => 0,
+                _ => 1,
+            };
+            pos += 1;
+            match value {
+                Value::Null => {}
+                Value::Integer(v) | Value::DateTime(v) => BigEndian::write_i64(&mut buf[pos..], *v),
+                Value::Real(v) => BigEndian::write_f64(&mut buf[pos..], *v),
+                Value::Boolean(v) => buf[pos] = *v as u8,
+                Value::Text(text) => {
+                    BigEndian::write_u16(&mut buf[pos..], text.len() as u16);
+                    buf[pos + 2..pos + size].copy_from_slice(text.as_bytes());
+                }
+            }
+            pos += size;
+        }
+        Result::Ok(buf)
     }
 
-    pub fn get_column_type(&self, column: &String) -> Option<SQLType> {
-        self.columns.get(column).map(|t| t.clone())
+    /// the values of a row written by `write_row`.
+    pub fn read_row(&self, buf: &[u8]) -> Vec<Value> {
+        let mut values = Vec::with_capacity(self.columns.len());
+        let mut pos = 0;
+        for &(_, sql_type) in &self.columns {
+            let present = buf[pos] != 0;
+            pos += 1;
+            if !present {
+                values.push(Value::Null);
+                continue;
+            }
+            let value = match sql_type {
+                SQLType::Integer => Value::Integer(BigEndian::read_i64(&buf[pos..])),
+                SQLType::DateTime => Value::DateTime(BigEndian::read_i64(&buf[pos..])),
+                SQLType::Real => Value::Real(BigEndian::read_f64(&buf[pos..])),
+                SQLType::Boolean => Value::Boolean(buf[pos] != 0),
+                SQLType::String => {
+                    let len = BigEndian::read_u16(&buf[pos..]) as usize;
+                    let text = String::from_utf8_lossy(&buf[pos + 2..pos + 2 + len]);
+                    pos += 2 + len;
+                    values.push(Value::Text(text.into_owned()));
+                    continue;
+                }
+            };
+            pos += if sql_type == SQLType::Boolean { 1 } else { 8 };
+            values.push(value);
+        }
+        values
     }
 }
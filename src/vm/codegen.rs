@@ -1,20 +1,141 @@
+use std::collections::{HashMap, HashSet};
 use std::vec::Vec;
 
-use sql::{ParsedSQL, SQLType};
-use sql::operands::Operand;
+use sql::{Limit, ParsedSQL, SQLType};
+use sql::operands::{Aggregate, Comparison, Operand};
 use table::schema::Schema;
 
+use super::functions;
+
 pub type ErrCode = u32;
 
-#[derive(Debug, Eq, PartialEq)]
+/// the truth value of a condition that is null, like `id = 1 and null` for the row
+/// with id 1. the codes of `and`, `or` and `not` load 0, 1 or this.
+pub const UNKNOWN: i64 = -1;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum OpCode {
     /// load a constant integer value into stack
     LoadInt(i64),
-    LoadStr(String),
+    /// load a constant real value into stack, reals take the slots of integers by
+    /// their bits
+    LoadFloat(f64),
+    /// load a string literal by its index in the symbol table of the statement
+    LoadStrIdx(usize),
     /// store integer value in stack to result row buffer
     StoreInt,
     StoreStr,
+    StoreFloat,
+    StoreBool,
+    StoreDateTime,
+    /// store a null in the row buffer, nothing is taken from the stack
+    StoreNull,
+    /// turn the integer on top of the stack into a real, before it is added to or
+    /// compared with one
+    ToFloat,
+    /// load a column of the current row, strings go to the symbol table
+    ColumnRead(usize),
+    /// load the value bound to the parameter at given index as given type, strings
+    /// go to the symbol table and an integer is turned into a real for a real one
+    LoadParameter(usize, SQLType),
+    /// store the value bound to the parameter at given index in the row buffer,
+    /// whatever its type
+    StoreParameter(usize),
     Add,
+    /// the arithmetic of the two integers on top of the stack, the top one is the
+    /// second operand
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    /// the arithmetic of the two reals on top of the stack like `Add`..`Mod`
+    AddFloat,
+    SubFloat,
+    MulFloat,
+    DivFloat,
+    ModFloat,
+    /// put the row into the group of the values stored in the row buffer
+    Group,
+    /// count a row in the aggregate at given index of its group
+    Count(usize),
+    /// take the value of given type on top of the stack into the aggregate at given
+    /// index of the group of the row
+    Accumulate(usize, Aggregate, SQLType),
+    /// the codes of a row end here, the ones after run for every group once the
+    /// rows are scanned
+    EndRow,
+    /// load a value of the key of the group, strings go to the symbol table
+    GroupKey(usize),
+    /// load the result of the aggregate at given index of the group, 0 if it has
+    /// no values
+    GroupAggregate(usize, Aggregate),
+    /// store the result of the aggregate at given index of the group, a null if it
+    /// has no values
+    StoreAggregate(usize, Aggregate),
+    /// compare the two integers on top of the stack, load 1 if it holds or 0 if not
+    Compare(Comparison),
+    /// compare the two strings indexed by the top of the stack like `Compare`
+    CompareStr(Comparison),
+    /// compare the two reals on top of the stack like `Compare`
+    CompareFloat(Comparison),
+    /// compare the two truth values on top of the stack like `Compare`, the result
+    /// is `UNKNOWN` if either is unknown
+    CompareBool(Comparison),
+    /// jump to the code at given index if the top of the stack is 0 or `UNKNOWN`, a
+    /// condition that is null skips the row like a false one
+    JumpIfFalse(usize),
+    Jump(usize),
+    /// jump to the code at given index if the aggregate at given index of the group
+    /// has no values
+    JumpIfNoValues(usize, Aggregate, usize),
+    /// the first operand of an `and`, jump to given index if the top of the stack is
+    /// 0 and leave it as the result, or keep it for `AndValues` once the second
+    /// operand is evaluated
+    And(usize),
+    /// the first operand of an `or`, jump to given index if the top of the stack is
+    /// 1 and leave it as the result, or keep it for `OrValues` once the second
+    /// operand is evaluated
+    Or(usize),
+    /// load the `and` of the two truth values on top of the stack, 0 if either is
+    /// 0, `UNKNOWN` if either is unknown and 1 otherwise
+    AndValues,
+    /// load the `or` of the two truth values on top of the stack, 1 if either is 1,
+    /// `UNKNOWN` if either is unknown and 0 otherwise
+    OrValues,
+    /// load 1 if the integer on top of the stack is not 0, 0 if it is
+    Bool,
+    /// load 1 if the truth value on top of the stack is 0, 0 if it is 1, an unknown
+    /// one stays unknown
+    Not,
+    /// load 1 if the string indexed below the top of the stack matches the pattern
+    /// indexed by the top, 0 if not
+    Like,
+    /// load 1 if the integer on top of the stack is a value of the subquery at given
+    /// index, 0 if not
+    In(usize),
+    /// like `In` for the string indexed by the top of the stack
+    InStr(usize),
+    /// like `In` for the real on top of the stack
+    InFloat(usize),
+    /// like `In` for the boolean on top of the stack
+    InBool(usize),
+    /// like `In` for the datetime on top of the stack
+    InDateTime(usize),
+    /// load 1 if the integer on top of the stack is one of given values, 0 if not
+    InList(HashSet<i64>),
+    /// like `InList` for the string indexed by the top of the stack
+    InListStr(HashSet<String>),
+    /// like `InList` for the real on top of the stack
+    InListFloat(Vec<f64>),
+    /// load 1 if the subquery at given index has any result row, 0 if not
+    Exists(usize),
+    /// call the function at given index of `FUNCTIONS` with the arguments of given
+    /// types on top of the stack, the last one on top, and load its result
+    Call(usize, Vec<SQLType>),
+    /// stop the codes and the scan of the table once given number of rows are flushed
+    Limit(u64),
+    /// count a row, load 0 for the first given number of rows and 1 after them
+    Offset(u64),
     FlushRow,
     Exit(ErrCode),
 }
@@ -23,74 +144,1131 @@ pub enum OpCode {
 pub fn size_of(sql_type: SQLType) -> usize {
     match sql_type {
         SQLType::Integer => 8,
+        SQLType::Real => 8,
+        SQLType::Boolean => 1,
+        SQLType::DateTime => 8,
         SQLType::String => 0,
     }
 }
 
-pub fn gen_code(sql: &ParsedSQL, schema: &Schema) -> Vec<OpCode> {
+/// what the codes refer to by index, resolved once while generating them rather
+/// than every time the codes run.
+struct Symbols<'a> {
+    /// the string literals, one entry for every distinct literal.
+    strings: Vec<String>,
+    string_indexes: HashMap<&'a str, usize>,
+    schema: &'a Schema,
+    /// columns are read from the rows of the table, there are none without one.
+    reads_table: bool,
+    column_indexes: HashMap<&'a str, usize>,
+    /// the aggregates of a query, by the index of their values in a group.
+    aggregates: Vec<&'a Operand>,
+    /// the keys of the groups, while the codes for the result rows of groups are
+    /// generated.
+    group_by: &'a [Operand],
+    grouping: bool,
+    /// the subqueries of `in` and `exists`, see `collect_subqueries`.
+    subqueries: Vec<&'a ParsedSQL>,
+    /// the alias of the table, or its name without one, which qualifies its
+    /// columns like `u.id`.
+    qualifier: Option<&'a str>,
+}
+
+impl<'a> Symbols<'a> {
+    fn new(schema: &'a Schema, reads_table: bool) -> Symbols<'a> {
+        Symbols {
+            strings: Vec::new(),
+            string_indexes: HashMap::new(),
+            schema,
+            reads_table,
+            column_indexes: HashMap::new(),
+            aggregates: Vec::new(),
+            group_by: &[],
+            grouping: false,
+            subqueries: Vec::new(),
+            qualifier: None,
+        }
+    }
+
+    fn string(&mut self, literal: &'a str) -> usize {
+        let strings = &mut self.strings;
+        *self.string_indexes.entry(literal).or_insert_with(|| {
+            strings.push(literal.to_owned());
+            strings.len() - 1
+        })
+    }
+
+    fn subquery(&self, subquery: &ParsedSQL) -> Result<usize, String> {
+        self.subqueries
+            .iter()
+            .position(|other| *other == subquery)
+            .ok_or_else(|| format!("no such subquery: {}", subquery))
+    }
+
+    fn column(&mut self, column: &'a str) -> Result<usize, String> {
+        if let Some(&index) = self.column_indexes.get(column) {
+            return Result::Ok(index);
+        }
+        let index = self
+            .schema
+            .column_index(column)
+            .filter(|_| self.reads_table)
+            .ok_or_else(|| format!("no such column: {}", column))?;
+        self.column_indexes.insert(column, index);
+        Result::Ok(index)
+    }
+}
+
+/// the codes of a statement and the string literals they load, an error for
+/// operands that have no type or cannot be evaluated yet.
+pub fn gen_code(sql: &ParsedSQL, schema: &Schema) -> Result<(Vec<OpCode>, Vec<String>), String> {
     let mut op_codes: Vec<OpCode> = Vec::new();
-    match sql {
+    let operands;
+    let symbols = match sql {
         &ParsedSQL::Select {
             ref table,
-            ref operands,
+            ref subquery,
+            ref alias,
+            ref condition,
+            ref group_by,
+            ref having,
+            ref limit,
+            ..
         } => {
-            // code for all columns
-            for op in operands {
-                translate_operand_to_code(&mut op_codes, &op);
-
-                let store_code = store_code_for_type(type_of(&op, schema).unwrap());
-                op_codes.push(store_code);
+            operands = result_operands(sql, schema)?;
+            let mut symbols = Symbols::new(schema, table.is_some() || subquery.is_some());
+            symbols.subqueries = collect_subqueries(sql);
+            symbols.qualifier = alias.as_deref().or(table.as_deref());
+            for op in operands.iter().chain(having) {
+                collect_aggregates(op, &mut symbols.aggregates);
             }
+            if group_by.is_empty() && symbols.aggregates.is_empty() && having.is_none() {
+                if let Some(limit) = limit {
+                    op_codes.push(OpCode::Limit(limit.count));
+                }
+                // a row not meeting the condition or within the offset skips the
+                // rest of the codes
+                let mut jumps = Vec::new();
+                if let Some(condition) = condition {
+                    jumps.push(gen_condition(&mut op_codes, condition, &mut symbols)?);
+                }
+                jumps.extend(gen_offset(&mut op_codes, limit));
+                gen_result_row(&mut op_codes, &operands, &mut symbols)?;
+                for jump in jumps {
+                    op_codes[jump] = OpCode::JumpIfFalse(op_codes.len());
+                }
+            } else {
+                // every row updates the aggregates of its group
+                let mut jumps = Vec::new();
+                if let Some(condition) = condition {
+                    jumps.push(gen_condition(&mut op_codes, condition, &mut symbols)?);
+                }
+                for op in group_by {
+                    gen_store(&mut op_codes, op, &mut symbols)?;
+                }
+                op_codes.push(OpCode::Group);
+                for index in 0..symbols.aggregates.len() {
+                    let op = symbols.aggregates[index];
+                    match op {
+                        Operand::Aggregate(Aggregate::Count, None) => {
+                            op_codes.push(OpCode::Count(index))
+                        }
+                        // the nulls are left out of an aggregate
+                        Operand::Aggregate(_, Some(arg)) if is_null(arg) => {}
+                        Operand::Aggregate(aggregate, Some(arg)) => {
+                            let sql_type = type_of(arg, schema)
+                                .ok_or_else(|| format!("cannot infer the type of {}.", arg))?;
+                            if type_of(op, schema).is_none() {
+                                return Result::Err(format!(
+                                    "cannot take the {} of {}.",
+                                    aggregate, arg
+                                ));
+                            }
+                            translate_operand_to_code(&mut op_codes, arg, &mut symbols)?;
+                            op_codes.push(OpCode::Accumulate(index, *aggregate, sql_type));
+                        }
+                        op => return Result::Err(format!("{} is not an aggregate.", op)),
+                    }
+                }
+                for jump in jumps {
+                    op_codes[jump] = OpCode::JumpIfFalse(op_codes.len());
+                }
+                op_codes.push(OpCode::EndRow);
 
-            // flush row when all operands' codes finished
+                // and every group becomes a result row once the rows are scanned
+                symbols.group_by = group_by;
+                symbols.grouping = true;
+                if let Some(limit) = limit {
+                    op_codes.push(OpCode::Limit(limit.count));
+                }
+                // a group not meeting the `having` is skipped before it counts
+                // toward the offset
+                let mut jumps = Vec::new();
+                if let Some(having) = having {
+                    jumps.push(gen_condition(&mut op_codes, having, &mut symbols)?);
+                }
+                jumps.extend(gen_offset(&mut op_codes, limit));
+                gen_result_row(&mut op_codes, &operands, &mut symbols)?;
+                for jump in jumps {
+                    op_codes[jump] = OpCode::JumpIfFalse(op_codes.len());
+                }
+            }
+            symbols
+        }
+        // the codes of a delete flush the keys of the rows to remove
+        ParsedSQL::Delete {
+            table, condition, ..
+        } => {
+            let mut symbols = Symbols::new(schema, true);
+            symbols.subqueries = collect_subqueries(sql);
+            symbols.qualifier = Some(table);
+            let jump = match condition {
+                Some(condition) => Some(gen_condition(&mut op_codes, condition, &mut symbols)?),
+                None => None,
+            };
+            op_codes.push(OpCode::ColumnRead(schema.key_column()));
+            op_codes.push(OpCode::StoreInt);
             op_codes.push(OpCode::FlushRow);
+            if let Some(jump) = jump {
+                op_codes[jump] = OpCode::JumpIfFalse(op_codes.len());
+            }
+            symbols
+        }
+        ParsedSQL::Insert { .. } => {
+            return Result::Err("an insert has no codes to generate.".to_owned());
+        }
+        ParsedSQL::CreateTable { .. } | ParsedSQL::CreateIndex { .. } => {
+            return Result::Err("a create statement has no codes to generate.".to_owned());
+        }
+        ParsedSQL::DropTable { .. } => {
+            return Result::Err("a drop statement has no codes to generate.".to_owned());
+        }
+        ParsedSQL::Transaction(_) => {
+            return Result::Err("a transaction statement has no codes to generate.".to_owned());
+        }
+        ParsedSQL::Pragma { .. } => {
+            return Result::Err("a pragma has no codes to generate.".to_owned());
         }
     };
 
-    op_codes
+    Result::Ok((op_codes, symbols.strings))
+}
+
+/// the columns of the result rows of a query, which a query over it reads like
+/// the columns of a table.
+pub fn result_schema(sql: &ParsedSQL, schema: &Schema) -> Result<Schema, String> {
+    match sql {
+        ParsedSQL::Select { subquery, .. } => {
+            let source;
+            let schema = match subquery {
+                Some(subquery) => {
+                    source = result_schema(subquery, schema)?;
+                    &source
+                }
+                None => schema,
+            };
+            let mut columns = Vec::new();
+            for op in result_operands(sql, schema)? {
+                let sql_type =
+                    type_of(&op, schema).ok_or_else(|| format!("cannot infer the type of {}.", op))?;
+                columns.push((format!("{}", op), sql_type));
+            }
+            let parameter_types = schema.parameter_types().to_vec();
+            Schema::from_columns(columns).map(|columns| columns.with_parameter_types(parameter_types))
+        }
+        _ => Result::Err("only a select has result rows.".to_owned()),
+    }
+}
+
+/**
+ * the types the parameters of a statement take from the operands they are used
+ * with, like the type of `id` for `?` in `id = ?` or `id + ?`. a parameter used
+ * with another parameter takes its type once that one has one, a parameter given
+ * no type can only be a result column on its own.
+ **/
+pub fn parameter_types(sql: &ParsedSQL, schema: &Schema, num_parameters: usize) -> Vec<Option<SQLType>> {
+    let mut types = vec![None; num_parameters];
+    loop {
+        let typed = schema.clone().with_parameter_types(types.clone());
+        let mut inferred = types.clone();
+        infer_statement(sql, &typed, &mut inferred);
+        if inferred == types {
+            return types;
+        }
+        types = inferred;
+    }
+}
+
+/// infer the types of the parameters of a statement, see `parameter_types`. the
+/// columns of a subquery in `from` are the ones the operands after it read.
+fn infer_statement(sql: &ParsedSQL, schema: &Schema, types: &mut Vec<Option<SQLType>>) {
+    match sql {
+        ParsedSQL::Select {
+            operands,
+            subquery,
+            condition,
+            group_by,
+            having,
+            ..
+        } => {
+            let source;
+            let columns = match subquery {
+                Some(subquery) => {
+                    infer_statement(subquery, schema, types);
+                    source = result_schema(subquery, schema).ok();
+                    source.as_ref()
+                }
+                None => Some(schema),
+            };
+            for op in operands.iter().chain(condition).chain(group_by).chain(having) {
+                infer_operand(op, columns, schema, types);
+            }
+        }
+        ParsedSQL::Delete {
+            condition: Some(condition),
+            ..
+        } => infer_operand(condition, Some(schema), schema, types),
+        _ => {}
+    }
+}
+
+/// infer the types of the parameters within an operand, the operands beside them
+/// are typed by given columns. subqueries read the table of `schema`.
+fn infer_operand(op: &Operand, columns: Option<&Schema>, schema: &Schema, types: &mut Vec<Option<SQLType>>) {
+    let mut give_type = |parameter: &Operand, sql_type: Option<SQLType>| {
+        if let (Some(index), Some(sql_type)) = (parameter_index(parameter), sql_type) {
+            if let Some(slot) = types.get_mut(index - 1) {
+                slot.get_or_insert(sql_type);
+            }
+        }
+    };
+    let type_in_columns = |op: &Operand| columns.and_then(|columns| type_of(op, columns));
+    match op {
+        Operand::Add(op1, op2)
+        | Operand::Sub(op1, op2)
+        | Operand::Mul(op1, op2)
+        | Operand::Div(op1, op2)
+        | Operand::Mod(op1, op2)
+        | Operand::Compare(_, op1, op2) => {
+            give_type(op1, type_in_columns(op2));
+            give_type(op2, type_in_columns(op1));
+        }
+        Operand::Like(op1, op2) => {
+            give_type(op1, Some(SQLType::String));
+            give_type(op2, Some(SQLType::String));
+        }
+        Operand::InList(op, values) => {
+            give_type(op, values.iter().filter_map(&type_in_columns).next());
+        }
+        Operand::In(op, subquery) => {
+            let subquery_type = result_schema(subquery, schema)
+                .ok()
+                .and_then(|result| result.columns().first().map(|(_, sql_type)| *sql_type));
+            give_type(op, subquery_type);
+        }
+        _ => {}
+    }
+    match op {
+        Operand::Parentheses(op)
+        | Operand::Alias(op, _)
+        | Operand::Not(op)
+        | Operand::Neg(op)
+        | Operand::InList(op, _)
+        | Operand::Aggregate(_, Some(op)) => infer_operand(op, columns, schema, types),
+        Operand::Add(op1, op2)
+        | Operand::Sub(op1, op2)
+        | Operand::Mul(op1, op2)
+        | Operand::Div(op1, op2)
+        | Operand::Mod(op1, op2)
+        | Operand::Compare(_, op1, op2)
+        | Operand::And(op1, op2)
+        | Operand::Or(op1, op2)
+        | Operand::Like(op1, op2) => {
+            infer_operand(op1, columns, schema, types);
+            infer_operand(op2, columns, schema, types);
+        }
+        Operand::In(op, subquery) => {
+            infer_operand(op, columns, schema, types);
+            infer_statement(subquery, schema, types);
+        }
+        Operand::Exists(subquery) => infer_statement(subquery, schema, types),
+        Operand::Function(_, args) => {
+            for arg in args {
+                infer_operand(arg, columns, schema, types);
+            }
+        }
+        Operand::Integer(_)
+        | Operand::Float(_)
+        | Operand::Boolean(_)
+        | Operand::Null
+        | Operand::DateTime(_)
+        | Operand::String(_)
+        | Operand::Column(_)
+        | Operand::QualifiedColumn(..)
+        | Operand::Star(_)
+        | Operand::Aggregate(_, None)
+        | Operand::Parameter(_) => {}
+    }
+}
+
+/// the operands of the result rows of a select, where `*` and `u.*` are every
+/// column of the table or subquery the rows are read from.
+pub fn result_operands(sql: &ParsedSQL, schema: &Schema) -> Result<Vec<Operand>, String> {
+    let (operands, qualifier, reads_table) = match sql {
+        ParsedSQL::Select {
+            operands,
+            table,
+            subquery,
+            alias,
+            ..
+        } => (
+            operands,
+            alias.as_ref().or(table.as_ref()),
+            table.is_some() || subquery.is_some(),
+        ),
+        _ => return Result::Err("only a select has result rows.".to_owned()),
+    };
+    let mut expanded = Vec::new();
+    for op in operands {
+        match op {
+            Operand::Star(_) if !reads_table => {
+                return Result::Err(format!("no table to read the columns of {} from.", op))
+            }
+            Operand::Star(Some(table)) if Some(table) != qualifier => {
+                return Result::Err(format!("no such table: {}", table))
+            }
+            Operand::Star(_) => {
+                expanded.extend(schema.row_columns().into_iter().map(Operand::Column))
+            }
+            op => expanded.push(op.clone()),
+        }
+    }
+    Result::Ok(expanded)
+}
+
+/// the codes of a `where` or `having`, the index of the jump they end with is
+/// returned to be pointed past the codes of the row or group.
+fn gen_condition<'a>(
+    op_codes: &mut Vec<OpCode>,
+    condition: &'a Operand,
+    symbols: &mut Symbols<'a>,
+) -> Result<usize, String> {
+    check_columns(condition, symbols)?;
+    gen_truth_value(op_codes, condition, symbols)?;
+    if !is_condition(condition, symbols.schema) {
+        let filtered = if symbols.grouping { "groups" } else { "rows" };
+        return Result::Err(format!("cannot filter {} by {}.", filtered, condition));
+    }
+    op_codes.push(OpCode::JumpIfFalse(0));
+    Result::Ok(op_codes.len() - 1)
+}
+
+/// the codes skipping the rows within the offset, with the index of their jump.
+fn gen_offset(op_codes: &mut Vec<OpCode>, limit: &Option<Limit>) -> Option<usize> {
+    let limit = limit.filter(|limit| limit.offset > 0)?;
+    op_codes.push(OpCode::Offset(limit.offset));
+    op_codes.push(OpCode::JumpIfFalse(0));
+    Some(op_codes.len() - 1)
+}
+
+/// the codes storing the operands of a result row and flushing it.
+fn gen_result_row<'a>(
+    op_codes: &mut Vec<OpCode>,
+    operands: &'a [Operand],
+    symbols: &mut Symbols<'a>,
+) -> Result<(), String> {
+    // code for all columns
+    for op in operands {
+        gen_store(op_codes, op, symbols)?;
+    }
+
+    // flush row when all operands' codes finished
+    op_codes.push(OpCode::FlushRow);
+    Result::Ok(())
+}
+
+/// the codes storing an operand in the row buffer.
+fn gen_store<'a>(
+    op_codes: &mut Vec<OpCode>,
+    op: &'a Operand,
+    symbols: &mut Symbols<'a>,
+) -> Result<(), String> {
+    if is_null(op) {
+        op_codes.push(OpCode::StoreNull);
+        return Result::Ok(());
+    }
+    if symbols.grouping {
+        let stored = match op {
+            Operand::Alias(op, _) => op,
+            op => op,
+        };
+        let index = symbols.aggregates.iter().position(|aggregate| *aggregate == stored);
+        if let (Operand::Aggregate(aggregate, _), Some(index)) = (stored, index) {
+            op_codes.push(OpCode::StoreAggregate(index, *aggregate));
+            return Result::Ok(());
+        }
+    }
+    // a result column of a parameter alone has the type of the value bound to it
+    if let Some(index) = parameter_index(op) {
+        op_codes.push(OpCode::StoreParameter(index));
+        return Result::Ok(());
+    }
+    check_columns(op, symbols)?;
+    translate_operand_to_code(op_codes, op, symbols)?;
+    let sql_type =
+        type_of(op, symbols.schema).ok_or_else(|| format!("cannot infer the type of {}.", op))?;
+    op_codes.push(store_code_for_type(sql_type));
+    Result::Ok(())
+}
+
+/// the index of a parameter, `(?1)` and `?1 as name` included.
+fn parameter_index(op: &Operand) -> Option<usize> {
+    match op {
+        Operand::Parameter(index) => *index,
+        Operand::Parentheses(op) | Operand::Alias(op, _) => parameter_index(op),
+        _ => None,
+    }
+}
+
+/// an error for the first column of the operand the rows do not have, so it is
+/// reported before the types of the operands are checked. subqueries check their
+/// own columns.
+fn check_columns(op: &Operand, symbols: &Symbols) -> Result<(), String> {
+    match op {
+        Operand::Column(column) | Operand::QualifiedColumn(_, column)
+            if !symbols.reads_table || symbols.schema.column_index(column).is_none() =>
+        {
+            Result::Err(format!("no such column: {}", op))
+        }
+        Operand::Parentheses(op)
+        | Operand::Alias(op, _)
+        | Operand::In(op, _)
+        | Operand::Not(op)
+        | Operand::Neg(op)
+        | Operand::Aggregate(_, Some(op)) => check_columns(op, symbols),
+        Operand::Add(op1, op2)
+        | Operand::Sub(op1, op2)
+        | Operand::Mul(op1, op2)
+        | Operand::Div(op1, op2)
+        | Operand::Mod(op1, op2)
+        | Operand::Compare(_, op1, op2)
+        | Operand::And(op1, op2)
+        | Operand::Or(op1, op2)
+        | Operand::Like(op1, op2) => {
+            check_columns(op1, symbols)?;
+            check_columns(op2, symbols)
+        }
+        Operand::InList(op, values) => {
+            check_columns(op, symbols)?;
+            values.iter().try_for_each(|value| check_columns(value, symbols))
+        }
+        Operand::Function(_, args) => args.iter().try_for_each(|arg| check_columns(arg, symbols)),
+        _ => Result::Ok(()),
+    }
+}
+
+/// the distinct subqueries of `in` and `exists` within a statement, the index of
+/// one in them is the index the codes refer to it by.
+pub fn collect_subqueries(sql: &ParsedSQL) -> Vec<&ParsedSQL> {
+    let mut subqueries = Vec::new();
+    match sql {
+        ParsedSQL::Select {
+            operands,
+            condition,
+            group_by,
+            having,
+            ..
+        } => {
+            let ops = operands.iter().chain(condition).chain(group_by).chain(having);
+            for op in ops {
+                collect_operand_subqueries(op, &mut subqueries);
+            }
+        }
+        ParsedSQL::Delete {
+            condition: Some(condition),
+            ..
+        } => collect_operand_subqueries(condition, &mut subqueries),
+        _ => {}
+    }
+    subqueries
+}
+
+fn collect_operand_subqueries<'a>(op: &'a Operand, subqueries: &mut Vec<&'a ParsedSQL>) {
+    match op {
+        Operand::In(op, subquery) => {
+            collect_operand_subqueries(op, subqueries);
+            if !subqueries.contains(&&**subquery) {
+                subqueries.push(subquery);
+            }
+        }
+        Operand::Exists(subquery) => {
+            if !subqueries.contains(&&**subquery) {
+                subqueries.push(subquery);
+            }
+        }
+        Operand::Parentheses(op) | Operand::Alias(op, _) | Operand::Aggregate(_, Some(op)) => {
+            collect_operand_subqueries(op, subqueries)
+        }
+        Operand::Add(op1, op2)
+        | Operand::Sub(op1, op2)
+        | Operand::Mul(op1, op2)
+        | Operand::Div(op1, op2)
+        | Operand::Mod(op1, op2)
+        | Operand::Compare(_, op1, op2)
+        | Operand::And(op1, op2)
+        | Operand::Or(op1, op2)
+        | Operand::Like(op1, op2) => {
+            collect_operand_subqueries(op1, subqueries);
+            collect_operand_subqueries(op2, subqueries);
+        }
+        Operand::Not(op) | Operand::Neg(op) | Operand::InList(op, _) => {
+            collect_operand_subqueries(op, subqueries)
+        }
+        Operand::Function(_, args) => {
+            for arg in args {
+                collect_operand_subqueries(arg, subqueries);
+            }
+        }
+        Operand::Integer(_)
+        | Operand::Float(_)
+        | Operand::Boolean(_)
+        | Operand::Null
+        | Operand::DateTime(_)
+        | Operand::String(_)
+        | Operand::Column(_)
+        | Operand::QualifiedColumn(..)
+        | Operand::Star(_)
+        | Operand::Aggregate(_, None)
+        | Operand::Parameter(_) => {}
+    }
+}
+
+/// the distinct aggregates within an operand.
+pub fn collect_aggregates<'a>(op: &'a Operand, aggregates: &mut Vec<&'a Operand>) {
+    match op {
+        Operand::Aggregate(..) => {
+            if !aggregates.contains(&op) {
+                aggregates.push(op);
+            }
+        }
+        Operand::Parentheses(op)
+        | Operand::Alias(op, _)
+        | Operand::In(op, _)
+        | Operand::InList(op, _)
+        | Operand::Not(op)
+        | Operand::Neg(op) => collect_aggregates(op, aggregates),
+        Operand::Add(op1, op2)
+        | Operand::Sub(op1, op2)
+        | Operand::Mul(op1, op2)
+        | Operand::Div(op1, op2)
+        | Operand::Mod(op1, op2)
+        | Operand::Compare(_, op1, op2)
+        | Operand::And(op1, op2)
+        | Operand::Or(op1, op2)
+        | Operand::Like(op1, op2) => {
+            collect_aggregates(op1, aggregates);
+            collect_aggregates(op2, aggregates);
+        }
+        Operand::Function(_, args) => {
+            for arg in args {
+                collect_aggregates(arg, aggregates);
+            }
+        }
+        Operand::Integer(_)
+        | Operand::Float(_)
+        | Operand::Boolean(_)
+        | Operand::Null
+        | Operand::DateTime(_)
+        | Operand::String(_)
+        | Operand::Column(_)
+        | Operand::QualifiedColumn(..)
+        | Operand::Star(_)
+        | Operand::Parameter(_)
+        | Operand::Exists(_) => {}
+    }
 }
 
 fn store_code_for_type(sql_type: SQLType) -> OpCode {
     match sql_type {
         SQLType::Integer => OpCode::StoreInt,
+        SQLType::Real => OpCode::StoreFloat,
+        SQLType::Boolean => OpCode::StoreBool,
+        SQLType::DateTime => OpCode::StoreDateTime,
         SQLType::String => OpCode::StoreStr,
         // _ => OpCode::Exit(1),
     }
 }
 
+fn is_numeric(sql_type: SQLType) -> bool {
+    sql_type == SQLType::Integer || sql_type == SQLType::Real
+}
+
+/// whether the operand is null whatever the row, a null is known while the codes
+/// are generated rather than carried on the stack. the operators taking a null
+/// give a null, except `and`, `or` and `exists`.
+fn is_null(op: &Operand) -> bool {
+    match op {
+        Operand::Null => true,
+        Operand::Parentheses(op)
+        | Operand::Alias(op, _)
+        | Operand::Not(op)
+        | Operand::Neg(op)
+        | Operand::In(op, _)
+        | Operand::InList(op, _) => is_null(op),
+        Operand::Add(op1, op2)
+        | Operand::Sub(op1, op2)
+        | Operand::Mul(op1, op2)
+        | Operand::Div(op1, op2)
+        | Operand::Mod(op1, op2)
+        | Operand::Compare(_, op1, op2)
+        | Operand::Like(op1, op2) => is_null(op1) || is_null(op2),
+        Operand::Aggregate(aggregate, Some(op)) => *aggregate != Aggregate::Count && is_null(op),
+        Operand::Function(name, args) if functions::is_coalesce(name) => args.iter().all(is_null),
+        Operand::Function(_, args) => args.iter().any(is_null),
+        _ => false,
+    }
+}
+
+/// booleans are conditions, and so are integers, which hold if they are not 0, and
+/// nulls, which hold for no row.
+fn is_condition(op: &Operand, schema: &Schema) -> bool {
+    let sql_type = type_of(op, schema);
+    is_null(op) || sql_type == Some(SQLType::Boolean) || sql_type == Some(SQLType::Integer)
+}
+
+/// the type two operands are compared as, reals if one of two numbers is.
+fn common_type(type1: Option<SQLType>, type2: Option<SQLType>) -> Option<SQLType> {
+    match (type1?, type2?) {
+        (type1, type2) if type1 == type2 => Some(type1),
+        (type1, type2) if is_numeric(type1) && is_numeric(type2) => Some(SQLType::Real),
+        _ => None,
+    }
+}
+
+/// the codes of an operand as given type, integers are turned into reals.
+fn gen_operand_as<'a>(
+    op_codes: &mut Vec<OpCode>,
+    op: &'a Operand,
+    sql_type: SQLType,
+    symbols: &mut Symbols<'a>,
+) -> Result<(), String> {
+    translate_operand_to_code(op_codes, op, symbols)?;
+    if sql_type == SQLType::Real && type_of(op, symbols.schema) == Some(SQLType::Integer) {
+        op_codes.push(OpCode::ToFloat);
+    }
+    Result::Ok(())
+}
+
 /// type inference for the operand
 fn type_of(op: &Operand, schema: &Schema) -> Option<SQLType> {
     match op {
         &Operand::Integer(_) => Some(SQLType::Integer),
-        &Operand::Add(ref op1, ref op2) => {
-            let type_op1 = type_of(op1, schema);
-            if type_op1 == type_of(op2, schema) {
-                type_op1
+        Operand::Float(_) => Some(SQLType::Real),
+        Operand::Boolean(_) => Some(SQLType::Boolean),
+        Operand::Null => None,
+        Operand::DateTime(_) => Some(SQLType::DateTime),
+        Operand::Add(op1, op2)
+        | Operand::Sub(op1, op2)
+        | Operand::Mul(op1, op2)
+        | Operand::Div(op1, op2)
+        | Operand::Mod(op1, op2) => match (type_of(op1, schema), type_of(op2, schema)) {
+            (Some(SQLType::Integer), Some(SQLType::Integer)) => Some(SQLType::Integer),
+            // an integer with a real is a real
+            (Some(type1), Some(type2)) if is_numeric(type1) && is_numeric(type2) => {
+                Some(SQLType::Real)
+            }
+            // TODO: cast
+            _ => None,
+        },
+        Operand::Compare(_, op1, op2) => {
+            common_type(type_of(op1, schema), type_of(op2, schema)).map(|_| SQLType::Boolean)
+        }
+        &Operand::Parentheses(ref op) => type_of(op, schema),
+        Operand::Alias(op, _) => type_of(op, schema),
+        Operand::In(..) | Operand::InList(..) | Operand::Exists(_) => Some(SQLType::Boolean),
+        Operand::And(op1, op2) | Operand::Or(op1, op2) => {
+            if is_condition(op1, schema) && is_condition(op2, schema) {
+                Some(SQLType::Boolean)
             } else {
-                // TODO: cast
                 None
             }
         }
-        &Operand::Parentheses(ref op) => type_of(op, schema),
+        Operand::Not(op) => Some(SQLType::Boolean).filter(|_| is_condition(op, schema)),
+        Operand::Neg(op) => type_of(op, schema).filter(|sql_type| is_numeric(*sql_type)),
+        Operand::Like(op, pattern) => {
+            let strings = (type_of(op, schema), type_of(pattern, schema));
+            if strings == (Some(SQLType::String), Some(SQLType::String)) {
+                Some(SQLType::Boolean)
+            } else {
+                None
+            }
+        }
+        Operand::Aggregate(Aggregate::Count, _) => Some(SQLType::Integer),
+        Operand::Aggregate(aggregate, Some(op)) => match (aggregate, type_of(op, schema)?) {
+            (Aggregate::Sum, sql_type) if is_numeric(sql_type) => Some(sql_type),
+            (Aggregate::Avg, sql_type) if is_numeric(sql_type) => Some(SQLType::Real),
+            (Aggregate::Min, sql_type) | (Aggregate::Max, sql_type) => Some(sql_type),
+            _ => None,
+        },
+        Operand::Aggregate(_, None) => None,
         &Operand::String(ref str) => Some(SQLType::String),
         &Operand::Column(ref column) => schema.get_column_type(column),
+        Operand::QualifiedColumn(_, column) => schema.get_column_type(column),
+        Operand::Star(_) | Operand::Parameter(None) => None,
+        Operand::Parameter(Some(index)) => schema.get_parameter_type(*index),
+        // the type all the arguments that are not null have
+        Operand::Function(name, args) if functions::is_coalesce(name) => {
+            let arity = if name == "ifnull" { args.len() == 2 } else { args.len() >= 2 };
+            if !arity {
+                return None;
+            }
+            let mut types = args.iter().filter(|arg| !is_null(arg)).map(|arg| type_of(arg, schema));
+            let first = types.next()?;
+            types.fold(first, common_type)
+        }
+        Operand::Function(name, args) => {
+            let (_, function) = functions::lookup(name)?;
+            let types = args
+                .iter()
+                .map(|arg| type_of(arg, schema))
+                .collect::<Option<Vec<_>>>()?;
+            (function.returns)(&types)
+        }
+    }
+}
+
+/// the codes of an arithmetic operator, with the code for integers or the one for
+/// reals.
+fn gen_arithmetic<'a>(
+    op_codes: &mut Vec<OpCode>,
+    op1: &'a Operand,
+    op2: &'a Operand,
+    (code, float_code): (OpCode, OpCode),
+    symbols: &mut Symbols<'a>,
+) -> Result<(), String> {
+    let real = common_type(type_of(op1, symbols.schema), type_of(op2, symbols.schema))
+        == Some(SQLType::Real);
+    if real {
+        gen_operand_as(op_codes, op1, SQLType::Real, symbols)?;
+        gen_operand_as(op_codes, op2, SQLType::Real, symbols)?;
+        op_codes.push(float_code);
+    } else {
+        translate_operand_to_code(op_codes, op1, symbols)?;
+        translate_operand_to_code(op_codes, op2, symbols)?;
+        op_codes.push(code);
+    }
+    Result::Ok(())
+}
+
+/// the codes of an `and` or `or`, the jump made by the codes of the first operand
+/// skips the second.
+fn gen_short_circuit<'a>(
+    op_codes: &mut Vec<OpCode>,
+    op1: &'a Operand,
+    op2: &'a Operand,
+    (jump, combine): (fn(usize) -> OpCode, OpCode),
+    symbols: &mut Symbols<'a>,
+) -> Result<(), String> {
+    for op in &[op1, op2] {
+        if !is_condition(op, symbols.schema) {
+            return Result::Err(format!("cannot use {} as a condition.", op));
+        }
+    }
+    gen_truth_value(op_codes, op1, symbols)?;
+    let index = op_codes.len();
+    op_codes.push(jump(0));
+    gen_truth_value(op_codes, op2, symbols)?;
+    op_codes.push(combine);
+    op_codes[index] = jump(op_codes.len());
+    Result::Ok(())
+}
+
+/// the codes loading the truth value of a condition, an integer holds if it is not
+/// 0 and a null is `UNKNOWN`.
+fn gen_truth_value<'a>(
+    op_codes: &mut Vec<OpCode>,
+    op: &'a Operand,
+    symbols: &mut Symbols<'a>,
+) -> Result<(), String> {
+    if is_null(op) {
+        op_codes.push(OpCode::LoadInt(UNKNOWN));
+        return Result::Ok(());
+    }
+    translate_operand_to_code(op_codes, op, symbols)?;
+    if type_of(op, symbols.schema) == Some(SQLType::Integer) {
+        op_codes.push(OpCode::Bool);
+    }
+    Result::Ok(())
+}
+
+/// the codes of `coalesce` or `ifnull`, which leave out the arguments that are null.
+/// an aggregate of no values jumps to the argument after it, the arguments after
+/// the one loaded are not evaluated.
+fn gen_coalesce<'a>(
+    op_codes: &mut Vec<OpCode>,
+    op: &'a Operand,
+    args: &'a [Operand],
+    symbols: &mut Symbols<'a>,
+) -> Result<(), String> {
+    let sql_type =
+        type_of(op, symbols.schema).ok_or_else(|| format!("wrong arguments for {}.", op))?;
+    let args: Vec<&Operand> = args.iter().filter(|arg| !is_null(arg)).collect();
+    let mut jumps = Vec::new();
+    for (position, &arg) in args.iter().enumerate() {
+        let aggregate = match arg {
+            Operand::Aggregate(aggregate, _) if symbols.grouping && position + 1 < args.len() => {
+                symbols
+                    .aggregates
+                    .iter()
+                    .position(|other| *other == arg)
+                    .map(|index| (index, *aggregate))
+            }
+            _ => None,
+        };
+        let (index, aggregate) = match aggregate {
+            Some(aggregate) => aggregate,
+            None => {
+                gen_operand_as(op_codes, arg, sql_type, symbols)?;
+                break;
+            }
+        };
+        let check = op_codes.len();
+        op_codes.push(OpCode::JumpIfNoValues(index, aggregate, 0));
+        gen_operand_as(op_codes, arg, sql_type, symbols)?;
+        jumps.push(op_codes.len());
+        op_codes.push(OpCode::Jump(0));
+        op_codes[check] = OpCode::JumpIfNoValues(index, aggregate, op_codes.len());
+    }
+    for jump in jumps {
+        op_codes[jump] = OpCode::Jump(op_codes.len());
+    }
+    Result::Ok(())
+}
+
+/// the code checking the values of an `in` list, which are stored in it rather than
+/// loaded every time.
+fn gen_value_list(sql_type: SQLType, values: &[Operand]) -> Result<OpCode, String> {
+    let not_a_literal = |value| format!("the values after in must be literals, not {}.", value);
+    match sql_type {
+        SQLType::Integer => values
+            .iter()
+            .map(|value| match value {
+                Operand::Integer(v) => Result::Ok(*v),
+                value => Result::Err(not_a_literal(value)),
+            })
+            .collect::<Result<_, _>>()
+            .map(OpCode::InList),
+        SQLType::Real => values
+            .iter()
+            .map(|value| match value {
+                Operand::Integer(v) => Result::Ok(*v as f64),
+                Operand::Float(v) => Result::Ok(*v),
+                value => Result::Err(not_a_literal(value)),
+            })
+            .collect::<Result<_, _>>()
+            .map(OpCode::InListFloat),
+        SQLType::DateTime => values
+            .iter()
+            .map(|value| match value {
+                Operand::DateTime(v) => Result::Ok(*v),
+                value => Result::Err(not_a_literal(value)),
+            })
+            .collect::<Result<_, _>>()
+            .map(OpCode::InList),
+        SQLType::Boolean => values
+            .iter()
+            .map(|value| match value {
+                Operand::Boolean(v) => Result::Ok(*v as i64),
+                value => Result::Err(not_a_literal(value)),
+            })
+            .collect::<Result<_, _>>()
+            .map(OpCode::InList),
+        SQLType::String => values
+            .iter()
+            .map(|value| match value {
+                Operand::String(str) => Result::Ok(str.clone()),
+                value => Result::Err(not_a_literal(value)),
+            })
+            .collect::<Result<_, _>>()
+            .map(OpCode::InListStr),
     }
 }
 
-fn translate_operand_to_code(op_codes: &mut Vec<OpCode>, op: &Operand) {
+fn translate_operand_to_code<'a>(
+    op_codes: &mut Vec<OpCode>,
+    op: &'a Operand,
+    symbols: &mut Symbols<'a>,
+) -> Result<(), String> {
+    // the result rows of groups load the values their keys were grouped by
+    if let Some(index) = symbols.group_by.iter().position(|key| key == op) {
+        op_codes.push(OpCode::GroupKey(index));
+        return Result::Ok(());
+    }
+    // a null is false, the operands it goes to are null too and are not evaluated
+    if is_null(op) {
+        op_codes.push(OpCode::LoadInt(0));
+        return Result::Ok(());
+    }
     match op {
         &Operand::Integer(v) => op_codes.push(OpCode::LoadInt(v)),
-        &Operand::Add(ref op1, ref op2) => {
-            translate_operand_to_code(op_codes, op1);
-            translate_operand_to_code(op_codes, op2);
-            op_codes.push(OpCode::Add)
+        Operand::Float(v) => op_codes.push(OpCode::LoadFloat(*v)),
+        Operand::Boolean(v) => op_codes.push(OpCode::LoadInt(*v as i64)),
+        Operand::Null => op_codes.push(OpCode::LoadInt(0)),
+        Operand::DateTime(v) => op_codes.push(OpCode::LoadInt(*v)),
+        Operand::Add(op1, op2) => {
+            gen_arithmetic(op_codes, op1, op2, (OpCode::Add, OpCode::AddFloat), symbols)?
+        }
+        Operand::Sub(op1, op2) => {
+            gen_arithmetic(op_codes, op1, op2, (OpCode::Sub, OpCode::SubFloat), symbols)?
+        }
+        Operand::Mul(op1, op2) => {
+            gen_arithmetic(op_codes, op1, op2, (OpCode::Mul, OpCode::MulFloat), symbols)?
+        }
+        Operand::Div(op1, op2) => {
+            gen_arithmetic(op_codes, op1, op2, (OpCode::Div, OpCode::DivFloat), symbols)?
+        }
+        Operand::Mod(op1, op2) => {
+            gen_arithmetic(op_codes, op1, op2, (OpCode::Mod, OpCode::ModFloat), symbols)?
         }
         &Operand::Parentheses(ref op) => {
-            translate_operand_to_code(op_codes, op);
+            translate_operand_to_code(op_codes, op, symbols)?;
+        }
+        Operand::Alias(op, _) => translate_operand_to_code(op_codes, op, symbols)?,
+        Operand::In(op, subquery) => {
+            match **subquery {
+                ParsedSQL::Select { ref operands, .. } if operands.len() == 1 => {}
+                _ => {
+                    return Result::Err("the select after in must return a single column.".to_owned())
+                }
+            }
+            let index = symbols.subquery(subquery)?;
+            translate_operand_to_code(op_codes, op, symbols)?;
+            match type_of(op, symbols.schema) {
+                Some(SQLType::Integer) => op_codes.push(OpCode::In(index)),
+                Some(SQLType::String) => op_codes.push(OpCode::InStr(index)),
+                Some(SQLType::Real) => op_codes.push(OpCode::InFloat(index)),
+                Some(SQLType::Boolean) => op_codes.push(OpCode::InBool(index)),
+                Some(SQLType::DateTime) => op_codes.push(OpCode::InDateTime(index)),
+                None => return Result::Err(format!("cannot infer the type of {}.", op)),
+            }
+        }
+        Operand::InList(op, values) => {
+            let mut sql_type = type_of(op, symbols.schema)
+                .ok_or_else(|| format!("cannot infer the type of {}.", op))?;
+            for value in values {
+                if is_null(value) {
+                    return Result::Err("the values after in cannot be null.".to_owned());
+                }
+                sql_type = common_type(Some(sql_type), type_of(value, symbols.schema))
+                    .ok_or_else(|| format!("cannot compare {} with {}.", op, value))?;
+            }
+            gen_operand_as(op_codes, op, sql_type, symbols)?;
+            op_codes.push(gen_value_list(sql_type, values)?);
+        }
+        Operand::Exists(subquery) => op_codes.push(OpCode::Exists(symbols.subquery(subquery)?)),
+        Operand::And(op1, op2) => {
+            gen_short_circuit(op_codes, op1, op2, (OpCode::And, OpCode::AndValues), symbols)?
+        }
+        Operand::Or(op1, op2) => {
+            gen_short_circuit(op_codes, op1, op2, (OpCode::Or, OpCode::OrValues), symbols)?
+        }
+        Operand::Not(op) => {
+            if !is_condition(op, symbols.schema) {
+                return Result::Err(format!("cannot use {} as a condition.", op));
+            }
+            gen_truth_value(op_codes, op, symbols)?;
+            op_codes.push(OpCode::Not);
+        }
+        // subtracted from 0, the integer that has no negation overflows like `0 - id`
+        Operand::Neg(op) => match type_of(op, symbols.schema) {
+            Some(SQLType::Integer) => {
+                op_codes.push(OpCode::LoadInt(0));
+                translate_operand_to_code(op_codes, op, symbols)?;
+                op_codes.push(OpCode::Sub);
+            }
+            Some(SQLType::Real) => {
+                op_codes.push(OpCode::LoadFloat(0.0));
+                translate_operand_to_code(op_codes, op, symbols)?;
+                op_codes.push(OpCode::SubFloat);
+            }
+            _ => return Result::Err(format!("cannot negate {}.", op)),
+        },
+        Operand::Like(op1, op2) => {
+            for op in &[op1, op2] {
+                if type_of(op, symbols.schema) != Some(SQLType::String) {
+                    return Result::Err(format!("cannot match {} with like.", op));
+                }
+            }
+            translate_operand_to_code(op_codes, op1, symbols)?;
+            translate_operand_to_code(op_codes, op2, symbols)?;
+            op_codes.push(OpCode::Like);
+        }
+        Operand::Function(name, args) if functions::is_coalesce(name) => {
+            gen_coalesce(op_codes, op, args, symbols)?
+        }
+        Operand::Function(name, args) => {
+            let (index, function) =
+                functions::lookup(name).ok_or_else(|| format!("no such function: {}", name))?;
+            let mut types = Vec::new();
+            for arg in args {
+                translate_operand_to_code(op_codes, arg, symbols)?;
+                types.push(
+                    type_of(arg, symbols.schema)
+                        .ok_or_else(|| format!("cannot infer the type of {}.", arg))?,
+                );
+            }
+            if (function.returns)(&types).is_none() {
+                return Result::Err(format!("wrong arguments for {}.", op));
+            }
+            op_codes.push(OpCode::Call(index, types));
+        }
+        Operand::String(str) => op_codes.push(OpCode::LoadStrIdx(symbols.string(str))),
+        Operand::Column(column) if symbols.grouping => {
+            return Result::Err(format!(
+                "{} must appear in the group by clause or be used in an aggregate.",
+                column
+            ))
+        }
+        Operand::Column(column) => op_codes.push(OpCode::ColumnRead(symbols.column(column)?)),
+        Operand::QualifiedColumn(table, column) => {
+            if symbols.qualifier != Some(table.as_str()) {
+                return Result::Err(format!("no such column: {}", op));
+            }
+            if symbols.grouping {
+                return Result::Err(format!(
+                    "{} must appear in the group by clause or be used in an aggregate.",
+                    op
+                ));
+            }
+            op_codes.push(OpCode::ColumnRead(symbols.column(column)?))
+        }
+        Operand::Aggregate(aggregate, _) if symbols.grouping => {
+            let index = symbols.aggregates.iter().position(|other| *other == op);
+            let index = index.ok_or_else(|| format!("misuse of aggregate {}.", op))?;
+            op_codes.push(OpCode::GroupAggregate(index, *aggregate))
+        }
+        Operand::Aggregate(..) => return Result::Err(format!("misuse of aggregate {}.", op)),
+        Operand::Star(_) => return Result::Err(format!("cannot use {} here.", op)),
+        Operand::Parameter(Some(index)) => match symbols.schema.get_parameter_type(*index) {
+            Some(sql_type) => op_codes.push(OpCode::LoadParameter(*index, sql_type)),
+            None => return Result::Err(format!("cannot infer the type of {}.", op)),
+        },
+        Operand::Parameter(None) => return Result::Err(format!("parameter {} is not numbered.", op)),
+        Operand::Compare(comparison, op1, op2) => {
+            let sql_type = common_type(type_of(op1, symbols.schema), type_of(op2, symbols.schema))
+                .ok_or_else(|| format!("cannot compare {} with {}.", op1, op2))?;
+            gen_operand_as(op_codes, op1, sql_type, symbols)?;
+            gen_operand_as(op_codes, op2, sql_type, symbols)?;
+            op_codes.push(match sql_type {
+                // datetimes are ordered like the seconds they are
+                SQLType::Integer | SQLType::DateTime => OpCode::Compare(*comparison),
+                SQLType::Boolean => OpCode::CompareBool(*comparison),
+                SQLType::Real => OpCode::CompareFloat(*comparison),
+                SQLType::String => OpCode::CompareStr(*comparison),
+            });
         }
-        &Operand::String(ref str) => op_codes.push(OpCode::LoadStr(str.to_owned())),
-        &Operand::Column(ref column) => panic!("not implemented"),
     }
+    Result::Ok(())
 }
 
 #[cfg(test)]
@@ -105,7 +1283,7 @@ mod tests {
     fn gen_codes_for_a_single_load() {
         let mut op_codes = Vec::new();
         let op = Operand::Integer(42);
-        translate_operand_to_code(&mut op_codes, &op);
+        translate_operand_to_code(&mut op_codes, &op, &mut Symbols::new(&get_schema(), false)).unwrap();
 
         let expected = vec![OpCode::LoadInt(42)];
         assert_eq!(op_codes, expected);
@@ -117,7 +1295,7 @@ mod tests {
         // 3 + (4 + 5)
         let add_op = Operand::Add(Box::new(Operand::Integer(4)), Box::new(Operand::Integer(5)));
         let nested_add_op = Operand::Add(Box::new(Operand::Integer(3)), Box::new(add_op));
-        translate_operand_to_code(&mut op_codes, &nested_add_op);
+        translate_operand_to_code(&mut op_codes, &nested_add_op, &mut Symbols::new(&get_schema(), false)).unwrap();
 
         let expected = vec![
             OpCode::LoadInt(3),
@@ -142,29 +1320,361 @@ mod tests {
     fn gen_codes_for_the_simplest_select_statement() {
         let schema = get_schema();
         let sql = ParsedSQL::Select {
+            database: None,
             table: None,
+            subquery: None,
+            alias: None,
             operands: vec![Operand::Integer(42)],
+            condition: None,
+            group_by: Vec::new(),
+            having: None,
+            limit: None,
         };
-        let op_codes = gen_code(&sql, &schema);
+        let (op_codes, strings) = gen_code(&sql, &schema).unwrap();
 
         let expected = vec![OpCode::LoadInt(42), OpCode::StoreInt, OpCode::FlushRow];
         assert_eq!(op_codes, expected);
+        assert!(strings.is_empty());
     }
 
     #[test]
     fn gen_codes_for_select_string_literal() {
         let schema = get_schema();
         let sql = ParsedSQL::Select {
+            database: None,
             table: None,
-            operands: vec![Operand::String("foo, bar".to_owned())],
+            subquery: None,
+            alias: None,
+            operands: vec![
+                Operand::String("foo, bar".to_owned()),
+                Operand::String("baz".to_owned()),
+                Operand::String("foo, bar".to_owned()),
+            ],
+            condition: None,
+            group_by: Vec::new(),
+            having: None,
+            limit: None,
         };
-        let op_codes = gen_code(&sql, &schema);
+        let (op_codes, strings) = gen_code(&sql, &schema).unwrap();
 
         let expected = vec![
-            OpCode::LoadStr("foo, bar".to_owned()),
+            OpCode::LoadStrIdx(0),
+            OpCode::StoreStr,
+            OpCode::LoadStrIdx(1),
+            OpCode::StoreStr,
+            OpCode::LoadStrIdx(0),
             OpCode::StoreStr,
             OpCode::FlushRow,
         ];
         assert_eq!(op_codes, expected);
+        assert_eq!(strings, vec!["foo, bar".to_owned(), "baz".to_owned()]);
+    }
+
+    #[test]
+    fn gen_codes_read_only_the_selected_columns() {
+        let schema = get_schema();
+        let sql = ParsedSQL::Select {
+            database: None,
+            table: Some("users".to_owned()),
+            subquery: None,
+            alias: None,
+            operands: vec![
+                Operand::Column("email".to_owned()),
+                Operand::Add(
+                    Box::new(Operand::Column("id".to_owned())),
+                    Box::new(Operand::Integer(1)),
+                ),
+            ],
+            condition: None,
+            group_by: Vec::new(),
+            having: None,
+            limit: None,
+        };
+        let expected = vec![
+            OpCode::ColumnRead(2),
+            OpCode::StoreStr,
+            OpCode::ColumnRead(0),
+            OpCode::LoadInt(1),
+            OpCode::Add,
+            OpCode::StoreInt,
+            OpCode::FlushRow,
+        ];
+        assert_eq!(gen_code(&sql, &schema), Result::Ok((expected, Vec::new())));
+
+        let sql = ParsedSQL::Select {
+            database: None,
+            table: None,
+            subquery: None,
+            alias: None,
+            operands: vec![Operand::Column("id".to_owned())],
+            condition: None,
+            group_by: Vec::new(),
+            having: None,
+            limit: None,
+        };
+        assert_eq!(gen_code(&sql, &schema), Result::Err("no such column: id".to_owned()));
+    }
+
+    #[test]
+    fn gen_codes_skip_rows_not_meeting_the_condition() {
+        let schema = get_schema();
+        let sql = ParsedSQL::Select {
+            database: None,
+            table: Some("users".to_owned()),
+            subquery: None,
+            alias: None,
+            operands: vec![Operand::Column("id".to_owned())],
+            condition: Some(Operand::Compare(
+                Comparison::Equal,
+                Box::new(Operand::Column("email".to_owned())),
+                Box::new(Operand::String("a@b".to_owned())),
+            )),
+            group_by: Vec::new(),
+            having: None,
+            limit: None,
+        };
+        let expected = vec![
+            OpCode::ColumnRead(2),
+            OpCode::LoadStrIdx(0),
+            OpCode::CompareStr(Comparison::Equal),
+            OpCode::JumpIfFalse(7),
+            OpCode::ColumnRead(0),
+            OpCode::StoreInt,
+            OpCode::FlushRow,
+        ];
+        assert_eq!(gen_code(&sql, &schema), Result::Ok((expected, vec!["a@b".to_owned()])));
+
+        let sql = ParsedSQL::Select {
+            database: None,
+            table: Some("users".to_owned()),
+            subquery: None,
+            alias: None,
+            operands: vec![Operand::Column("id".to_owned())],
+            condition: Some(Operand::Compare(
+                Comparison::Less,
+                Box::new(Operand::Column("id".to_owned())),
+                Box::new(Operand::String("10".to_owned())),
+            )),
+            group_by: Vec::new(),
+            having: None,
+            limit: None,
+        };
+        assert_eq!(
+            gen_code(&sql, &schema),
+            Result::Err("cannot compare id with '10'.".to_owned())
+        );
+
+        let sql = ParsedSQL::Select {
+            database: None,
+            table: Some("users".to_owned()),
+            subquery: None,
+            alias: None,
+            operands: vec![Operand::Column("id".to_owned())],
+            condition: Some(Operand::Column("email".to_owned())),
+            group_by: Vec::new(),
+            having: None,
+            limit: None,
+        };
+        assert_eq!(
+            gen_code(&sql, &schema),
+            Result::Err("cannot filter rows by email.".to_owned())
+        );
+    }
+
+    #[test]
+    fn gen_codes_skip_the_second_operand_of_and_or() {
+        let schema = get_schema();
+        let sql = ::sql::parse(b"select id from users where id > 1 and not id = 3").unwrap();
+        let expected = vec![
+            OpCode::ColumnRead(0),
+            OpCode::LoadInt(1),
+            OpCode::Compare(Comparison::Greater),
+            OpCode::And(9),
+            OpCode::ColumnRead(0),
+            OpCode::LoadInt(3),
+            OpCode::Compare(Comparison::Equal),
+            OpCode::Not,
+            OpCode::AndValues,
+            OpCode::JumpIfFalse(13),
+            OpCode::ColumnRead(0),
+            OpCode::StoreInt,
+            OpCode::FlushRow,
+        ];
+        assert_eq!(gen_code(&sql, &schema), Result::Ok((expected, Vec::new())));
+
+        let sql = ::sql::parse(b"select 1 or 'a'").unwrap();
+        assert_eq!(gen_code(&sql, &schema), Result::Err("cannot use 'a' as a condition.".to_owned()));
+    }
+
+    #[test]
+    fn gen_codes_for_limits_and_offsets() {
+        let schema = get_schema();
+        let sql = ParsedSQL::Select {
+            database: None,
+            table: Some("users".to_owned()),
+            subquery: None,
+            alias: None,
+            operands: vec![Operand::Column("id".to_owned())],
+            condition: None,
+            group_by: Vec::new(),
+            having: None,
+            limit: Some(Limit { count: 10, offset: 20 }),
+        };
+        let expected = vec![
+            OpCode::Limit(10),
+            OpCode::Offset(20),
+            OpCode::JumpIfFalse(6),
+            OpCode::ColumnRead(0),
+            OpCode::StoreInt,
+            OpCode::FlushRow,
+        ];
+        assert_eq!(gen_code(&sql, &schema), Result::Ok((expected, Vec::new())));
+    }
+
+    #[test]
+    fn gen_codes_for_groups_of_rows() {
+        let schema = get_schema();
+        let username = || Operand::Column("username".to_owned());
+        let count = || Operand::Aggregate(Aggregate::Count, None);
+        let sql = ParsedSQL::Select {
+            database: None,
+            table: Some("users".to_owned()),
+            subquery: None,
+            alias: None,
+            operands: vec![
+                username(),
+                Operand::Add(Box::new(count()), Box::new(Operand::Integer(1))),
+                count(),
+            ],
+            condition: None,
+            group_by: vec![username()],
+            having: None,
+            limit: None,
+        };
+        let expected = vec![
+            OpCode::ColumnRead(1),
+            OpCode::StoreStr,
+            OpCode::Group,
+            OpCode::Count(0),
+            OpCode::EndRow,
+            OpCode::GroupKey(0),
+            OpCode::StoreStr,
+            OpCode::GroupAggregate(0, Aggregate::Count),
+            OpCode::LoadInt(1),
+            OpCode::Add,
+            OpCode::StoreInt,
+            OpCode::StoreAggregate(0, Aggregate::Count),
+            OpCode::FlushRow,
+        ];
+        assert_eq!(gen_code(&sql, &schema), Result::Ok((expected, Vec::new())));
+
+        let sql = ParsedSQL::Select {
+            database: None,
+            table: Some("users".to_owned()),
+            subquery: None,
+            alias: None,
+            operands: vec![Operand::Column("email".to_owned()), count()],
+            condition: None,
+            group_by: vec![username()],
+            having: None,
+            limit: None,
+        };
+        assert_eq!(
+            gen_code(&sql, &schema),
+            Result::Err(
+                "email must appear in the group by clause or be used in an aggregate.".to_owned()
+            )
+        );
+
+        let sql = ParsedSQL::Select {
+            database: None,
+            table: Some("users".to_owned()),
+            subquery: None,
+            alias: None,
+            operands: vec![count()],
+            condition: Some(Operand::Compare(
+                Comparison::Greater,
+                Box::new(count()),
+                Box::new(Operand::Integer(1)),
+            )),
+            group_by: Vec::new(),
+            having: None,
+            limit: None,
+        };
+        assert_eq!(
+            gen_code(&sql, &schema),
+            Result::Err("misuse of aggregate count(*).".to_owned())
+        );
+    }
+
+    #[test]
+    fn gen_codes_filter_groups_after_aggregating() {
+        let schema = get_schema();
+        let count = || Operand::Aggregate(Aggregate::Count, None);
+        let sql = ParsedSQL::Select {
+            database: None,
+            table: Some("users".to_owned()),
+            subquery: None,
+            alias: None,
+            operands: vec![Operand::Column("username".to_owned())],
+            condition: Some(Operand::Compare(
+                Comparison::Greater,
+                Box::new(Operand::Column("id".to_owned())),
+                Box::new(Operand::Integer(1)),
+            )),
+            group_by: vec![Operand::Column("username".to_owned())],
+            having: Some(Operand::Compare(
+                Comparison::Greater,
+                Box::new(count()),
+                Box::new(Operand::Integer(2)),
+            )),
+            limit: Some(Limit { count: 5, offset: 1 }),
+        };
+        let expected = vec![
+            OpCode::ColumnRead(0),
+            OpCode::LoadInt(1),
+            OpCode::Compare(Comparison::Greater),
+            OpCode::JumpIfFalse(8),
+            OpCode::ColumnRead(1),
+            OpCode::StoreStr,
+            OpCode::Group,
+            OpCode::Count(0),
+            OpCode::EndRow,
+            OpCode::Limit(5),
+            OpCode::GroupAggregate(0, Aggregate::Count),
+            OpCode::LoadInt(2),
+            OpCode::Compare(Comparison::Greater),
+            OpCode::JumpIfFalse(19),
+            OpCode::Offset(1),
+            OpCode::JumpIfFalse(19),
+            OpCode::GroupKey(0),
+            OpCode::StoreStr,
+            OpCode::FlushRow,
+        ];
+        assert_eq!(gen_code(&sql, &schema), Result::Ok((expected, Vec::new())));
+    }
+
+    #[test]
+    fn gen_codes_flush_the_keys_of_rows_to_delete() {
+        let schema = get_schema();
+        let sql = ParsedSQL::Delete {
+            database: None,
+            table: "users".to_owned(),
+            condition: Some(Operand::Compare(
+                Comparison::Equal,
+                Box::new(Operand::Column("username".to_owned())),
+                Box::new(Operand::String("ada".to_owned())),
+            )),
+        };
+        let expected = vec![
+            OpCode::ColumnRead(1),
+            OpCode::LoadStrIdx(0),
+            OpCode::CompareStr(Comparison::Equal),
+            OpCode::JumpIfFalse(7),
+            OpCode::ColumnRead(0),
+            OpCode::StoreInt,
+            OpCode::FlushRow,
+        ];
+        assert_eq!(gen_code(&sql, &schema), Result::Ok((expected, vec!["ada".to_owned()])));
     }
 }
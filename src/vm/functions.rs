@@ -0,0 +1,123 @@
+//! the scalar functions called like `upper(name)`. the types of the arguments are
+//! checked while the codes are generated, the codes call a function by its index
+//! in `FUNCTIONS` with the values of its arguments.
+
+use sql::SQLType;
+
+use super::Value;
+
+pub struct Function {
+    pub name: &'static str,
+    /// the type of the result for given types of the arguments, `None` if the
+    /// function does not take them.
+    pub returns: fn(&[SQLType]) -> Option<SQLType>,
+    pub call: fn(Vec<Value>) -> Result<Value, String>,
+}
+
+pub static FUNCTIONS: &[Function] = &[
+    Function {
+        name: "upper",
+        returns: text_to_text,
+        call: upper,
+    },
+    Function {
+        name: "lower",
+        returns: text_to_text,
+        call: lower,
+    },
+    Function {
+        name: "length",
+        returns: length_type,
+        call: length,
+    },
+    Function {
+        name: "abs",
+        returns: abs_type,
+        call: abs,
+    },
+];
+
+/// whether the function is `coalesce` or `ifnull`, which give the first of their
+/// arguments that is not null. nulls are known while the codes are generated, so
+/// they pick the argument then rather than being called.
+pub fn is_coalesce(name: &str) -> bool {
+    name == "coalesce" || name == "ifnull"
+}
+
+/// the index of the function with given name and the function.
+pub fn lookup(name: &str) -> Option<(usize, &'static Function)> {
+    FUNCTIONS
+        .iter()
+        .enumerate()
+        .find(|(_, function)| function.name == name)
+}
+
+fn text_to_text(types: &[SQLType]) -> Option<SQLType> {
+    Some(SQLType::String).filter(|_| types == [SQLType::String])
+}
+
+fn length_type(types: &[SQLType]) -> Option<SQLType> {
+    Some(SQLType::Integer).filter(|_| types == [SQLType::String])
+}
+
+fn abs_type(types: &[SQLType]) -> Option<SQLType> {
+    match types {
+        [SQLType::Integer] => Some(SQLType::Integer),
+        [SQLType::Real] => Some(SQLType::Real),
+        _ => None,
+    }
+}
+
+fn upper(args: Vec<Value>) -> Result<Value, String> {
+    match args.as_slice() {
+        [Value::Text(text)] => Result::Ok(Value::Text(text.to_uppercase())),
+        _ => Result::Err("upper takes a text.".to_owned()),
+    }
+}
+
+fn lower(args: Vec<Value>) -> Result<Value, String> {
+    match args.as_slice() {
+        [Value::Text(text)] => Result::Ok(Value::Text(text.to_lowercase())),
+        _ => Result::Err("lower takes a text.".to_owned()),
+    }
+}
+
+/// the number of characters, not bytes.
+fn length(args: Vec<Value>) -> Result<Value, String> {
+    match args.as_slice() {
+        [Value::Text(text)] => Result::Ok(Value::Integer(text.chars().count() as i64)),
+        _ => Result::Err("length takes a text.".to_owned()),
+    }
+}
+
+fn abs(args: Vec<Value>) -> Result<Value, String> {
+    match args.as_slice() {
+        [Value::Integer(v)] => v
+            .checked_abs()
+            .map(Value::Integer)
+            .ok_or_else(|| "integer overflow.".to_owned()),
+        [Value::Real(v)] => Result::Ok(Value::Real(v.abs())),
+        _ => Result::Err("abs takes a number.".to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn functions_check_their_arguments() {
+        let (_, upper) = lookup("upper").unwrap();
+        assert_eq!((upper.returns)(&[SQLType::String]), Some(SQLType::String));
+        assert_eq!((upper.returns)(&[SQLType::Integer]), None);
+        assert_eq!((upper.returns)(&[]), None);
+        assert_eq!((upper.call)(vec![Value::Text("Ab".to_owned())]), Ok(Value::Text("AB".to_owned())));
+
+        let (_, abs) = lookup("abs").unwrap();
+        assert_eq!((abs.returns)(&[SQLType::Real]), Some(SQLType::Real));
+        assert_eq!((abs.call)(vec![Value::Integer(-3)]), Ok(Value::Integer(3)));
+        assert_eq!((abs.call)(vec![Value::Integer(i64::MIN)]), Err("integer overflow.".to_owned()));
+        assert!(lookup("nope").is_none());
+        assert!(is_coalesce("ifnull") && lookup("ifnull").is_none());
+    }
+}
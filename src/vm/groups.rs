@@ -0,0 +1,180 @@
+//! the groups of an aggregate query. rows are put into groups by the values of
+//! the `group by` operands as they are scanned, every group keeps the aggregates
+//! of its rows till the scan ends and the groups become result rows.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use sql::operands::Aggregate;
+
+use super::Value;
+
+/// the state of an aggregate over the rows of a group so far.
+#[derive(Clone, Default)]
+pub struct Accumulator {
+    rows: i64,
+    /// the sum, the minimum or the maximum of the values, `None` till there is one.
+    value: Option<Value>,
+}
+
+impl Accumulator {
+    /// count a row without a value, like `count(*)` does.
+    pub fn count(&mut self) {
+        self.rows += 1;
+    }
+
+    pub fn add(&mut self, aggregate: Aggregate, value: Value) -> Result<(), String> {
+        self.rows += 1;
+        let value = match (aggregate, self.value.take()) {
+            (Aggregate::Count, _) => return Result::Ok(()),
+            (_, None) => value,
+            (Aggregate::Sum, Some(sum)) | (Aggregate::Avg, Some(sum)) => add(sum, value)?,
+            (Aggregate::Min, Some(min)) => match order(&value, &min) {
+                Some(Ordering::Less) => value,
+                _ => min,
+            },
+            (Aggregate::Max, Some(max)) => match order(&value, &max) {
+                Some(Ordering::Greater) => value,
+                _ => max,
+            },
+        };
+        self.value = Some(value);
+        Result::Ok(())
+    }
+
+    /// the result of the aggregate, `None` if there are no values but for `count`.
+    pub fn result(&self, aggregate: Aggregate) -> Option<Value> {
+        match (aggregate, &self.value) {
+            (Aggregate::Count, _) => Some(Value::Integer(self.rows)),
+            (Aggregate::Avg, Some(Value::Integer(sum))) => {
+                Some(Value::Real(*sum as f64 / self.rows as f64))
+            }
+            (Aggregate::Avg, Some(Value::Real(sum))) => Some(Value::Real(sum / self.rows as f64)),
+            (_, value) => value.clone(),
+        }
+    }
+}
+
+fn add(v1: Value, v2: Value) -> Result<Value, String> {
+    match (v1, v2) {
+        (Value::Integer(v1), Value::Integer(v2)) => v1
+            .checked_add(v2)
+            .map(Value::Integer)
+            .ok_or_else(|| "integer overflow.".to_owned()),
+        (Value::Real(v1), Value::Real(v2)) => Result::Ok(Value::Real(v1 + v2)),
+        (v1, v2) => Result::Err(format!("cannot add {} to {}.", v2, v1)),
+    }
+}
+
+/// the order of two values of the same type, `None` for values of different ones.
+fn order(v1: &Value, v2: &Value) -> Option<Ordering> {
+    match (v1, v2) {
+        (Value::Integer(v1), Value::Integer(v2)) | (Value::DateTime(v1), Value::DateTime(v2)) => {
+            Some(v1.cmp(v2))
+        }
+        (Value::Real(v1), Value::Real(v2)) => v1.partial_cmp(v2),
+        (Value::Boolean(v1), Value::Boolean(v2)) => Some(v1.cmp(v2)),
+        (Value::Text(v1), Value::Text(v2)) => Some(v1.cmp(v2)),
+        _ => None,
+    }
+}
+
+#[derive(Clone)]
+struct Group {
+    key: Vec<Value>,
+    aggregates: Vec<Accumulator>,
+}
+
+/// the groups in the order their first rows were scanned.
+#[derive(Clone, Default)]
+pub struct Groups {
+    groups: Vec<Group>,
+    indexes: HashMap<Vec<Value>, usize>,
+    /// the group the codes work on.
+    current: usize,
+}
+
+impl Groups {
+    pub fn new() -> Groups {
+        Groups::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.groups.clear();
+        self.indexes.clear();
+        self.current = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// make the group with given key the current one, it is created for its first row.
+    pub fn enter(&mut self, key: Vec<Value>) {
+        let groups = &mut self.groups;
+        self.current = *self.indexes.entry(key).or_insert_with_key(|key| {
+            groups.push(Group {
+                key: key.clone(),
+                aggregates: Vec::new(),
+            });
+            groups.len() - 1
+        });
+    }
+
+    /// make the group at given position the current one.
+    pub fn select(&mut self, index: usize) {
+        self.current = index;
+    }
+
+    /// a value of the key of the current group.
+    pub fn key(&self, index: usize) -> Option<&Value> {
+        self.groups
+            .get(self.current)
+            .and_then(|group| group.key.get(index))
+    }
+
+    /// the result of an aggregate of the current group, `None` till it has a value
+    /// but for `count`.
+    pub fn aggregate(&self, index: usize, aggregate: Aggregate) -> Option<Value> {
+        let group = self.groups.get(self.current)?;
+        match group.aggregates.get(index) {
+            Some(accumulator) => accumulator.result(aggregate),
+            None => Accumulator::default().result(aggregate),
+        }
+    }
+
+    pub fn aggregate_mut(&mut self, index: usize) -> Option<&mut Accumulator> {
+        let group = self.groups.get_mut(self.current)?;
+        if group.aggregates.len() <= index {
+            group.aggregates.resize_with(index + 1, Accumulator::default);
+        }
+        Some(&mut group.aggregates[index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulators_aggregate_the_values_of_rows() {
+        let mut accumulator = Accumulator::default();
+        assert_eq!(accumulator.result(Aggregate::Count), Some(Value::Integer(0)));
+        assert_eq!(accumulator.result(Aggregate::Max), None);
+        for v in &[3, 1, 2] {
+            accumulator.add(Aggregate::Sum, Value::Integer(*v)).unwrap();
+        }
+        assert_eq!(accumulator.result(Aggregate::Sum), Some(Value::Integer(6)));
+        assert_eq!(accumulator.result(Aggregate::Avg), Some(Value::Real(2.0)));
+
+        let mut accumulator = Accumulator::default();
+        for text in &["bob", "alice", "carol"] {
+            accumulator.add(Aggregate::Min, Value::Text(text.to_string())).unwrap();
+        }
+        assert_eq!(accumulator.result(Aggregate::Min), Some(Value::Text("alice".to_owned())));
+
+        let mut accumulator = Accumulator::default();
+        accumulator.add(Aggregate::Sum, Value::Integer(i64::MAX)).unwrap();
+        assert!(accumulator.add(Aggregate::Sum, Value::Integer(1)).is_err());
+    }
+}
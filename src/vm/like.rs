@@ -0,0 +1,55 @@
+//! the patterns of `like`. `%` matches any number of characters and `_` a single
+//! one, letters match ignoring their ascii case like sqlite does.
+
+/// whether the whole text matches the pattern.
+pub fn matches(text: &str, pattern: &str) -> bool {
+    let text: Vec<char> = text.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+    let (mut t, mut p) = (0, 0);
+    // the position after the last `%` and the text it was tried against, a
+    // mismatch retries with the `%` taking one more character
+    let mut retry: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('%') => {
+                p += 1;
+                retry = Some((p, t));
+            }
+            Some(&c) if c == '_' || c.eq_ignore_ascii_case(&text[t]) => {
+                p += 1;
+                t += 1;
+            }
+            _ => match retry {
+                Some((after, tried)) => {
+                    p = after;
+                    t = tried + 1;
+                    retry = Some((after, t));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '%')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards_match_any_characters() {
+        assert!(matches("alice", "a%"));
+        assert!(matches("alice", "%e"));
+        assert!(matches("alice", "%li%"));
+        assert!(matches("alice", "_lic_"));
+        assert!(matches("alice", "%"));
+        assert!(matches("", "%%"));
+        assert!(matches("ALICE", "a%e"));
+        assert!(matches("abxbyd", "%b_d"));
+        assert!(!matches("abcbd", "%b_d"));
+        assert!(!matches("alice", "b%"));
+        assert!(!matches("alice", "_lic"));
+        assert!(!matches("alice", "alice_"));
+        assert!(!matches("", "_"));
+    }
+}
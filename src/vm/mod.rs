@@ -1,148 +1,1750 @@
-use table::{Row, Table};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::num::IntErrorKind;
+use std::slice;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use table::catalog::TableEntry;
+use table::index::IndexEntry;
+use table::{Row, RowRef, Table, TABLE_NAME};
 use table::schema::Schema;
 use sql;
-use sql::ParsedSQL;
+use sql::{ParsedSQL, Transaction};
+use sql::operands::Operand;
 use sql::SQLType;
 
 mod row_buf;
 use self::row_buf::RowBuf;
 mod codegen;
-use self::codegen::OpCode;
+use self::codegen::{OpCode, UNKNOWN};
+mod functions;
+mod groups;
+use self::groups::Groups;
+mod like;
+mod plan;
+mod settings;
+use self::settings::{Setter, Setting};
+mod value;
+pub use self::value::{FromValue, ToValue, Value};
+
+const INTERRUPTED: &str = "interrupted.";
+
+/// the columns of the legacy insert, in the order of its values.
+pub(crate) const INSERT_COLUMNS: [&str; 3] = ["id", "username", "email"];
 
+/// the highest `?N` a statement may use.
+const MAX_PARAMETERS: usize = 999;
+
+// named after the sql keywords that start them
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone)]
 pub enum StatementType {
     SELECT,
     INSERT,
+    DELETE,
+    CREATE(Creation),
+    /// `drop table [if exists] NAME`.
+    DROP { table: String, if_exists: bool },
+    SAVEPOINT(Savepoint),
+    TRANSACTION(Transaction),
+    ATTACH(Attachment),
+    /// the steps of the plan of an `explain query plan`, its result rows.
+    EXPLAIN(Vec<String>),
+    PRAGMA(Pragma),
+}
+
+/// what a `pragma` reads or writes, see `settings`.
+#[derive(Clone)]
+pub enum Pragma {
+    /// `pragma NAME`, the value of a setting.
+    Get(&'static Setting),
+    /// `pragma NAME = VALUE`, the setter of the setting and the value.
+    Set(Setter, i64),
+    /// `pragma table_info(TABLE)`, a row for each column of the table.
+    TableInfo(String),
+}
+
+/// `savepoint NAME`, `release [savepoint] NAME` and `rollback to [savepoint] NAME`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Savepoint {
+    Set(String),
+    Release(String),
+    RollbackTo(String),
+}
+
+/// `attach [database] 'PATH' as NAME` and `detach [database] NAME`, they change
+/// the dbs of a session rather than a table so the session runs them.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Attachment {
+    Attach { path: String, name: String },
+    Detach(String),
+}
+
+/// what a `create` statement makes.
+#[derive(Clone)]
+pub enum Creation {
+    /// the name of the table, the schema built from its columns and whether the
+    /// table may exist already. it is added to the catalog of the db.
+    Table(String, Schema, bool),
+    /// an index of a column of a table, it is built from the rows of the table
+    /// and added to the catalog of the db.
+    Index {
+        name: String,
+        table: String,
+        column: String,
+    },
+}
+
+/// a value of the legacy insert, written in the statement or bound to a parameter.
+#[derive(Clone)]
+enum InsertValue {
+    Literal(Value),
+    Parameter(usize),
+}
+
+/// a row the codes read columns from.
+#[derive(Clone)]
+enum ScanRow {
+    Table(RowRef),
+    /// a row of a created table, its row id comes after its values.
+    Record(u32, Vec<Value>),
+    /// a result row of a subquery.
+    Values(Vec<Value>),
+}
+
+impl ScanRow {
+    fn column(&self, index: usize) -> Option<Value> {
+        match (self, index) {
+            (ScanRow::Table(row), 0) => Some(Value::Integer(row.id() as i64)),
+            (ScanRow::Table(row), 1) => Some(Value::Text(row.username())),
+            (ScanRow::Table(row), 2) => Some(Value::Text(row.email())),
+            (ScanRow::Table(_), _) => None,
+            (ScanRow::Record(key, values), _) if index == values.len() => {
+                Some(Value::Integer(*key as i64))
+            }
+            (ScanRow::Record(_, values), _) | (ScanRow::Values(values), _) => {
+                values.get(index).cloned()
+            }
+        }
+    }
+}
+
+/// a subquery of `in` or `exists`, its result rows are collected once before the
+/// rows of the statement are read.
+#[derive(Clone)]
+struct Probe {
+    statement: Statement,
+    /// the values of the first column of the result rows.
+    values: HashSet<Value>,
+    rows: usize,
 }
 
+#[derive(Clone)]
 pub struct Statement {
     kind: StatementType,
     parsed: Option<ParsedSQL>,
     codes: Vec<OpCode>,
-    row_to_insert: Option<Row>,
+    insert_values: Vec<InsertValue>,
+    /// the values bound to `?1`, `?2`...
+    parameters: Vec<Option<Value>>,
     // TODO: stack only support i64 now.
     stack: Vec<i64>,
     // TODO: use a bidirectional map sort thing.
     sym_table: Vec<String>,
+    /// the string literals of the codes, interned when they are generated. they
+    /// stay at the start of `sym_table` while the strings read from rows come
+    /// and go.
+    constants: usize,
     pub row_buf: RowBuf,
     pc: usize,
+    /// names of the result columns.
+    columns: Vec<String>,
+    /// raised to stop the statement, checked per row and op code.
+    cancel: Option<Arc<AtomicBool>>,
+    /// the attached db the statement runs on, `None` for the main db.
+    database: Option<String>,
+    /// the created table the statement reads or writes with the schema it was
+    /// prepared for, `None` for the users table.
+    source: Option<(String, Schema)>,
+    /// the index the statement looks its rows up in, see `plan::indexed_key`.
+    index: Option<IndexEntry>,
+    /// the row the codes read columns from, one at a time.
+    row: Option<ScanRow>,
+    /// the select in `from (...)`, its result rows are the rows the codes read.
+    subquery: Option<Box<Statement>>,
+    /// the subqueries the codes refer to by index.
+    probes: Vec<Probe>,
+    /// the rows flushed and the rows counted by `Offset` in a run of the codes.
+    flushed_rows: u64,
+    offset_rows: u64,
+    /// the groups of the rows of an aggregate query.
+    groups: Groups,
+    /// the id of the row a paused scan of the table goes on from, see
+    /// `query_batch`.
+    resume: Option<u32>,
+    /// the rows an insert or delete changed the last time it ran.
+    changed_rows: usize,
 }
 
 #[derive(Debug, Eq, PartialEq)]
 pub enum ExecResult {
     PendingRow,
     Complete,
+    /// no more rows are wanted, the scan of the table stops.
+    Halt,
     Error(String),
 }
 
+/// the keys of the rows a delete removes, they are collected before any is removed.
+#[derive(Default)]
+struct Keys(Vec<u32>);
+
+impl RowSink for Keys {
+    fn columns(&mut self, _names: &[String]) -> Result<(), String> {
+        Result::Ok(())
+    }
+
+    fn row(&mut self, values: &[Value]) -> Result<(), String> {
+        match values {
+            [Value::Integer(key)] => {
+                self.0.push(*key as u32);
+                Result::Ok(())
+            }
+            _ => Result::Err("invalid key of a row.".to_owned()),
+        }
+    }
+}
+
+/// the result rows of a subquery, they are collected before the query over them runs.
+#[derive(Default)]
+struct Collected(Vec<Vec<Value>>);
+
+impl RowSink for Collected {
+    fn columns(&mut self, _names: &[String]) -> Result<(), String> {
+        Result::Ok(())
+    }
+
+    fn row(&mut self, values: &[Value]) -> Result<(), String> {
+        self.0.push(values.to_vec());
+        Result::Ok(())
+    }
+}
+
+/// receives the results of a statement, the column names come before any row.
+pub trait RowSink {
+    fn columns(&mut self, names: &[String]) -> Result<(), String>;
+    fn row(&mut self, values: &[Value]) -> Result<(), String>;
+}
+
+/// the schemas of the tables statements are prepared for.
+pub trait Schemas {
+    /// the schema of the table with given name in given db, the main one for `None`.
+    fn schema(&self, database: Option<&str>, table: &str) -> Result<Schema, String>;
+    /// the indexes of the table with given name in given db.
+    fn indexes(&self, database: Option<&str>, table: &str) -> Result<Vec<IndexEntry>, String>;
+}
+
+/// the users table, the only one of a db without a catalog.
+impl Schemas for Schema {
+    fn schema(&self, _database: Option<&str>, table: &str) -> Result<Schema, String> {
+        check_table_name(table).map(|_| self.clone())
+    }
+
+    fn indexes(&self, _database: Option<&str>, _table: &str) -> Result<Vec<IndexEntry>, String> {
+        Result::Ok(Vec::new())
+    }
+}
+
+/// the users table and the tables of the catalog of the db.
+impl Schemas for Table {
+    fn schema(&self, _database: Option<&str>, table: &str) -> Result<Schema, String> {
+        match self.find_table(table)? {
+            Some(entry) => Result::Ok(entry.schema),
+            None => Result::Err(format!("no such table: {}", table)),
+        }
+    }
+
+    fn indexes(&self, _database: Option<&str>, table: &str) -> Result<Vec<IndexEntry>, String> {
+        self.indexes_of(table)
+    }
+}
+
 pub trait VM {
-    fn execute(&mut self, table: &mut Table) -> Result<(), String>;
+    /// run the statement, result rows are streamed into `sink`.
+    fn execute(&mut self, table: &mut Table, sink: &mut dyn RowSink) -> Result<(), String>;
     fn execute_codes(&mut self) -> ExecResult;
 }
 
 impl Statement {
-    fn new_select_statement() -> Statement {
+    /// the plain `select` of the legacy commands, every row of the table.
+    fn new_legacy_select_statement() -> Statement {
         Statement {
-            kind: StatementType::SELECT,
-            row_to_insert: None,
-            parsed: None,
-            codes: Vec::new(),
-            stack: Vec::new(),
-            sym_table: Vec::new(),
-            row_buf: RowBuf::new(),
-            pc: 0,
+            columns: vec!["id".to_owned(), "username".to_owned(), "email".to_owned()],
+            ..Statement::new_control_statement(StatementType::SELECT)
         }
     }
 
-    fn new_select_statement2(parsed_sql: ParsedSQL, codes: Vec<OpCode>) -> Statement {
+    /// a select of given result columns.
+    fn new_select_statement(
+        parsed_sql: ParsedSQL,
+        columns: Vec<String>,
+        codes: Vec<OpCode>,
+        constants: Vec<String>,
+    ) -> Statement {
+        let database = match parsed_sql {
+            ParsedSQL::Select { ref database, .. }
+            | ParsedSQL::Insert { ref database, .. }
+            | ParsedSQL::Delete { ref database, .. }
+            | ParsedSQL::CreateTable { ref database, .. }
+            | ParsedSQL::DropTable { ref database, .. }
+            | ParsedSQL::CreateIndex { ref database, .. } => database.clone(),
+            ParsedSQL::Transaction(_) | ParsedSQL::Pragma { .. } => None,
+        };
         Statement {
-            kind: StatementType::SELECT,
-            row_to_insert: None,
             parsed: Some(parsed_sql),
-            codes: codes,
+            codes,
+            constants: constants.len(),
+            sym_table: constants,
+            columns,
+            database,
+            ..Statement::new_control_statement(StatementType::SELECT)
+        }
+    }
+
+    fn new_delete_statement(
+        parsed_sql: ParsedSQL,
+        codes: Vec<OpCode>,
+        constants: Vec<String>,
+    ) -> Statement {
+        let database = match parsed_sql {
+            ParsedSQL::Delete { ref database, .. } => database.clone(),
+            _ => None,
+        };
+        Statement {
+            parsed: Some(parsed_sql),
+            codes,
+            constants: constants.len(),
+            sym_table: constants,
+            database,
+            ..Statement::new_control_statement(StatementType::DELETE)
+        }
+    }
+
+    /// the names of the result columns, none unless it is a query.
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// a query returning rows rather than an insert.
+    pub fn is_query(&self) -> bool {
+        match self.kind {
+            StatementType::SELECT
+            | StatementType::EXPLAIN(_)
+            | StatementType::PRAGMA(Pragma::Get(_))
+            | StatementType::PRAGMA(Pragma::TableInfo(_)) => true,
+            StatementType::PRAGMA(Pragma::Set(..))
+            | StatementType::INSERT
+            | StatementType::DELETE
+            | StatementType::CREATE(_)
+            | StatementType::DROP { .. }
+            | StatementType::SAVEPOINT(_)
+            | StatementType::TRANSACTION(_)
+            | StatementType::ATTACH(_) => false,
+        }
+    }
+
+    pub fn is_insert(&self) -> bool {
+        match self.kind {
+            StatementType::INSERT => true,
+            StatementType::SELECT
+            | StatementType::DELETE
+            | StatementType::CREATE(_)
+            | StatementType::DROP { .. }
+            | StatementType::SAVEPOINT(_)
+            | StatementType::TRANSACTION(_)
+            | StatementType::ATTACH(_)
+            | StatementType::EXPLAIN(_)
+            | StatementType::PRAGMA(_) => false,
+        }
+    }
+
+    /// the number of rows an insert or delete changed the last time it ran, `None`
+    /// for other statements.
+    pub fn changed_rows(&self) -> Option<usize> {
+        match self.kind {
+            StatementType::INSERT | StatementType::DELETE => Some(self.changed_rows),
+            _ => None,
+        }
+    }
+
+    /// the schema a `create table` builds, `None` for other statements.
+    pub fn schema(&self) -> Option<&Schema> {
+        match self.kind {
+            StatementType::CREATE(Creation::Table(_, ref schema, _)) => Some(schema),
+            _ => None,
+        }
+    }
+
+    /// the name of the attached db the statement runs on, `None` for the main db.
+    pub fn database(&self) -> Option<&str> {
+        self.database.as_deref()
+    }
+
+    /// what an `attach` or `detach` statement does.
+    pub fn attachment(&self) -> Option<&Attachment> {
+        match self.kind {
+            StatementType::ATTACH(ref attachment) => Some(attachment),
+            _ => None,
+        }
+    }
+
+    fn new_insert_statement(
+        insert_values: Vec<InsertValue>,
+        num_parameters: usize,
+        database: Option<String>,
+    ) -> Statement {
+        Statement {
+            insert_values,
+            parameters: vec![None; num_parameters],
+            database,
+            ..Statement::new_control_statement(StatementType::INSERT)
+        }
+    }
+
+    fn new_attach_statement(attachment: Attachment) -> Statement {
+        Statement::new_control_statement(StatementType::ATTACH(attachment))
+    }
+
+    fn new_create_statement(creation: Creation) -> Statement {
+        Statement::new_control_statement(StatementType::CREATE(creation))
+    }
+
+    fn new_savepoint_statement(savepoint: Savepoint) -> Statement {
+        Statement::new_control_statement(StatementType::SAVEPOINT(savepoint))
+    }
+
+    fn new_transaction_statement(transaction: Transaction) -> Statement {
+        Statement::new_control_statement(StatementType::TRANSACTION(transaction))
+    }
+
+    fn new_explain_statement(plan: Vec<String>) -> Statement {
+        let mut statement = Statement::new_control_statement(StatementType::EXPLAIN(plan));
+        statement.columns = vec!["plan".to_owned()];
+        statement
+    }
+
+    fn new_pragma_statement(pragma: Pragma) -> Statement {
+        let columns = match pragma {
+            Pragma::Get(setting) => vec![setting.name.to_owned()],
+            Pragma::Set(..) => Vec::new(),
+            Pragma::TableInfo(_) => ["cid", "name", "type", "pk"]
+                .iter()
+                .map(|&name| name.to_owned())
+                .collect(),
+        };
+        let mut statement = Statement::new_control_statement(StatementType::PRAGMA(pragma));
+        statement.columns = columns;
+        statement
+    }
+
+    /// a statement run by the table or session as a whole, without codes. the
+    /// other statements start from it and set the fields they need.
+    fn new_control_statement(kind: StatementType) -> Statement {
+        Statement {
+            kind,
+            insert_values: Vec::new(),
+            parameters: Vec::new(),
+            parsed: None,
+            codes: Vec::new(),
             stack: Vec::new(),
             sym_table: Vec::new(),
+            constants: 0,
             row_buf: RowBuf::new(),
             pc: 0,
+            columns: Vec::new(),
+            cancel: None,
+            database: None,
+            source: None,
+            index: None,
+            row: None,
+            subquery: None,
+            probes: Vec::new(),
+            flushed_rows: 0,
+            offset_rows: 0,
+            groups: Groups::new(),
+            resume: None,
+            changed_rows: 0,
+        }
+    }
+
+    /// the highest parameter index of the statement, `?` stands for the one after
+    /// the highest before it.
+    pub fn parameter_count(&self) -> usize {
+        self.parameters.len()
+    }
+
+    /// bind a value to the parameter at given index, starting at 1. it stays bound
+    /// for every execution until it is replaced or cleared.
+    pub fn bind(&mut self, index: usize, value: Value) -> Result<(), String> {
+        if index == 0 || index > self.parameters.len() {
+            return Result::Err(format!(
+                "parameter index {} out of range, the statement has {} parameters.",
+                index,
+                self.parameters.len()
+            ));
+        }
+        self.parameters[index - 1] = Some(value);
+        Result::Ok(())
+    }
+
+    pub fn clear_bindings(&mut self) {
+        for parameter in self.parameters.iter_mut() {
+            *parameter = None;
+        }
+    }
+
+    /// the values of an insert with the parameters bound to it, in the order of
+    /// the columns of the table.
+    fn values_to_insert(&self) -> Result<Vec<Value>, String> {
+        let mut values = Vec::with_capacity(self.insert_values.len());
+        for (column, insert_value) in self.insert_values.iter().enumerate() {
+            let value = match insert_value {
+                InsertValue::Literal(value) => value.clone(),
+                InsertValue::Parameter(index) => {
+                    let value = self.parameters[index - 1]
+                        .clone()
+                        .ok_or_else(|| format!("parameter ?{} is not bound.", index))?;
+                    match self.source {
+                        Some((_, ref schema)) => schema.column_value(column, value)?,
+                        None => {
+                            check_insert_value(column, &value)?;
+                            value
+                        }
+                    }
+                }
+            };
+            values.push(value);
+        }
+        Result::Ok(values)
+    }
+
+    /// the row of an insert into the users table with the parameters bound to it.
+    fn row_to_insert(&self) -> Result<Row, String> {
+        let values = self.values_to_insert()?;
+        match values.as_slice() {
+            [Value::Integer(id), Value::Text(username), Value::Text(email)] => Result::Ok(Row {
+                id: *id as u32,
+                username: username.clone(),
+                email: email.clone(),
+            }),
+            _ => Result::Err("invalid insert values.".to_owned()),
+        }
+    }
+
+    /// run a query, it only reads the table so it may run alongside other queries.
+    pub fn query(&mut self, table: &Table, sink: &mut dyn RowSink) -> Result<(), String> {
+        self.rewind();
+        self.query_batch(table, sink, usize::MAX).map(|_| ())
+    }
+
+    /// run a query till its scan of the table flushed `batch` rows, true once it
+    /// is done. a paused query goes on from the next row of the table the next time
+    /// it runs, so the table may change in between.
+    pub fn query_batch(
+        &mut self,
+        table: &Table,
+        sink: &mut dyn RowSink,
+        batch: usize,
+    ) -> Result<bool, String> {
+        if !self.is_query() {
+            return Result::Err("not a query.".to_owned());
+        }
+        match self.resume.take() {
+            Some(key) => self.scan_table(table, sink, key, batch),
+            None => self.scan_batch(table, sink, batch),
+        }
+    }
+
+    /// forget where a paused query stopped, it runs from the start the next time.
+    pub fn rewind(&mut self) {
+        self.resume = None;
+    }
+
+    /// run the codes over the table, the rows they flush go to the sink.
+    fn scan(&mut self, table: &Table, sink: &mut dyn RowSink) -> Result<(), String> {
+        self.scan_batch(table, sink, usize::MAX).map(|_| ())
+    }
+
+    /// run the codes over the table till `batch` rows are flushed, see
+    /// `query_batch`.
+    fn scan_batch(
+        &mut self,
+        table: &Table,
+        sink: &mut dyn RowSink,
+        batch: usize,
+    ) -> Result<bool, String> {
+        if let Some(index) = self.parameters.iter().position(Option::is_none) {
+            return Result::Err(format!("parameter ?{} is not bound.", index + 1));
+        }
+        // a statement may run more than once
+        self.pc = 0;
+        self.stack.clear();
+        self.sym_table.truncate(self.constants);
+        self.row_buf.reset();
+        self.flushed_rows = 0;
+        self.offset_rows = 0;
+        self.groups.clear();
+        for probe in &mut self.probes {
+            // the codes of subqueries load the parameters of the statement
+            probe.statement.parameters = self.parameters.clone();
+            let mut rows = Collected::default();
+            probe.statement.scan(table, &mut rows)?;
+            probe.rows = rows.0.len();
+            probe.values = rows.0.into_iter().filter_map(|row| row.into_iter().next()).collect();
+        }
+        match self.kind {
+            StatementType::SELECT if self.parsed.is_none() => {
+                sink.columns(&self.columns)?;
+                self.scan_table(table, sink, 0, batch)
+            }
+            StatementType::EXPLAIN(ref plan) => {
+                sink.columns(&self.columns)?;
+                for step in plan {
+                    sink.row(&[Value::Text(step.clone())])?;
+                }
+                Result::Ok(true)
+            }
+            StatementType::PRAGMA(Pragma::Get(setting)) => {
+                sink.columns(&self.columns)?;
+                sink.row(&[(setting.get)(table)])?;
+                Result::Ok(true)
+            }
+            StatementType::PRAGMA(Pragma::TableInfo(ref name)) => {
+                sink.columns(&self.columns)?;
+                let schema = table.schema(None, name)?;
+                for (cid, (name, sql_type)) in schema.columns().iter().enumerate() {
+                    // the rows of the users table are keyed by their id, the ones of
+                    // a created table by a row id that is no column
+                    let pk = (!schema.has_rowid() && name == "id") as i64;
+                    sink.row(&[
+                        Value::Integer(cid as i64),
+                        Value::Text(name.clone()),
+                        Value::Text(sql_type.to_string()),
+                        Value::Integer(pk),
+                    ])?;
+                }
+                Result::Ok(true)
+            }
+            _ if self.reads_table() => {
+                sink.columns(&self.columns)?;
+                match self.subquery.take() {
+                    Some(mut subquery) => {
+                        let result = self.scan_subquery(&mut subquery, table, sink);
+                        self.subquery = Some(subquery);
+                        result?;
+                    }
+                    None => match self.searched_key() {
+                        Some(key) => {
+                            // ids are unsigned, no row has a key out of their range
+                            let cursor = match u32::try_from(key) {
+                                Result::Ok(key) => table.select_cursor_at(key)?,
+                                Result::Err(_) => None,
+                            };
+                            if let Some(cursor) = cursor {
+                                self.run_row(ScanRow::Table(cursor.row()), sink)?;
+                            }
+                        }
+                        None => match self.indexed_keys(table)? {
+                            Some(keys) => self.run_rows(table, &keys, sink)?,
+                            None => return self.scan_table(table, sink, 0, batch),
+                        },
+                    },
+                }
+                self.flush_groups(sink)?;
+                Result::Ok(true)
+            }
+            _ => {
+                sink.columns(&self.columns)?;
+                self.flush_rows(sink)?;
+                self.flush_groups(sink)?;
+                Result::Ok(true)
+            }
         }
     }
 
-    pub fn prepare(input_buffer: &str, schema: &Schema) -> Result<Statement, String> {
+    /// run the codes over the rows of the table from given key on, true once they
+    /// all ran. the scan pauses before the next row once `batch` rows are flushed,
+    /// see `query_batch`.
+    fn scan_table(
+        &mut self,
+        table: &Table,
+        sink: &mut dyn RowSink,
+        from: u32,
+        batch: usize,
+    ) -> Result<bool, String> {
+        let start = self.flushed_rows;
+        let source = self.source_table(table)?;
+        let mut cursor = match source {
+            Some(ref source) => table.select_cursor_in(source.root, from)?,
+            None => table.select_cursor_from(from)?,
+        };
+        while !cursor.end_of_table() {
+            if self.flushed_rows - start >= batch as u64 {
+                self.resume = Some(cursor.row().key());
+                return Result::Ok(false);
+            }
+            let halted = match self.parsed {
+                // the legacy select has no codes, it returns every row as it is
+                None => {
+                    if self.cancelled() {
+                        return Result::Err(INTERRUPTED.to_owned());
+                    }
+                    let row = cursor.get();
+                    sink.row(&[
+                        Value::Integer(row.id as i64),
+                        Value::Text(row.username),
+                        Value::Text(row.email),
+                    ])?;
+                    self.flushed_rows += 1;
+                    false
+                }
+                Some(_) => {
+                    let row = cursor.row();
+                    let row = match source {
+                        Some(ref source) => ScanRow::Record(row.key(), row.values(&source.schema)),
+                        None => ScanRow::Table(row),
+                    };
+                    self.run_row(row, sink)?
+                }
+            };
+            if halted {
+                break;
+            }
+            cursor.advance()?;
+        }
+        self.flush_groups(sink)?;
+        Result::Ok(true)
+    }
+
+    fn scan_subquery(
+        &mut self,
+        subquery: &mut Statement,
+        table: &Table,
+        sink: &mut dyn RowSink,
+    ) -> Result<(), String> {
+        let mut rows = Collected::default();
+        subquery.parameters = self.parameters.clone();
+        subquery.scan(table, &mut rows)?;
+        for row in rows.0 {
+            if self.run_row(ScanRow::Values(row), sink)? {
+                break;
+            }
+        }
+        Result::Ok(())
+    }
+
+    /// the id of the only row the `where` may select, see `plan::primary_key`.
+    fn searched_key(&self) -> Option<i64> {
+        match plan::primary_key(self.parsed.as_ref()?)? {
+            Operand::Integer(key) => Some(*key),
+            Operand::Parameter(Some(index)) => match self.parameters.get(index - 1) {
+                Some(Some(Value::Integer(key))) => Some(*key),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /**
+     * the keys of the rows the `where` may select that the index of the statement
+     * holds, `None` if the rows are to be scanned. they are scanned if the index
+     * is gone or changed since the statement was prepared, or if the value it is
+     * searched for is not one of its column.
+     **/
+    fn indexed_keys(&self, table: &Table) -> Result<Option<Vec<u32>>, String> {
+        let index = match self.index {
+            Some(ref index) => index,
+            None => return Result::Ok(None),
+        };
+        if table.find_index(&index.name)?.as_ref() != Some(index) {
+            return Result::Ok(None);
+        }
+        let parsed = match self.parsed {
+            Some(ref parsed) => parsed,
+            None => return Result::Ok(None),
+        };
+        let value = match plan::indexed_key(parsed, slice::from_ref(index)) {
+            Some((_, Operand::Integer(v))) => Value::Integer(*v),
+            Some((_, Operand::Float(v))) => Value::Real(*v),
+            Some((_, Operand::Boolean(v))) => Value::Boolean(*v),
+            Some((_, Operand::DateTime(v))) => Value::DateTime(*v),
+            Some((_, Operand::String(text))) => Value::Text(text.clone()),
+            Some((_, Operand::Parameter(Some(index)))) => match self.parameters.get(index - 1) {
+                Some(Some(value)) => value.clone(),
+                _ => return Result::Ok(None),
+            },
+            _ => return Result::Ok(None),
+        };
+        let schema = table.schema(None, &index.table)?;
+        let column = match schema.column_index(&index.column) {
+            Some(column) => column,
+            None => return Result::Ok(None),
+        };
+        match schema.column_value(column, value) {
+            Result::Ok(value) => table.index_lookup(index, &value).map(Some),
+            Result::Err(_) => Result::Ok(None),
+        }
+    }
+
+    /// run the codes over the rows with given keys till they halt.
+    fn run_rows(&mut self, table: &Table, keys: &[u32], sink: &mut dyn RowSink) -> Result<(), String> {
+        let source = self.source_table(table)?;
+        for &key in keys {
+            let row = match source {
+                Some(ref source) => {
+                    let cursor = table.select_cursor_in(source.root, key)?;
+                    if cursor.end_of_table() || cursor.row().key() != key {
+                        continue;
+                    }
+                    ScanRow::Record(key, cursor.row().values(&source.schema))
+                }
+                None => match table.select_cursor_at(key)? {
+                    Some(cursor) => ScanRow::Table(cursor.row()),
+                    None => continue,
+                },
+            };
+            if self.run_row(row, sink)? {
+                break;
+            }
+        }
+        Result::Ok(())
+    }
+
+    /// run the codes once for a row, true if they halted the scan.
+    fn run_row(&mut self, row: ScanRow, sink: &mut dyn RowSink) -> Result<bool, String> {
+        if self.cancelled() {
+            return Result::Err(INTERRUPTED.to_owned());
+        }
+        self.pc = 0;
+        self.stack.clear();
+        self.sym_table.truncate(self.constants);
+        self.row = Some(row);
+        let result = self.flush_rows(sink);
+        self.row = None;
+        result
+    }
+
+    /// the created table the statement reads or writes, `None` for the users table.
+    /// it fails if the table is gone or has other columns than it was prepared for.
+    fn source_table(&self, table: &Table) -> Result<Option<TableEntry>, String> {
+        match self.source {
+            Some((ref name, ref schema)) => table.prepared_table(name, schema).map(Some),
+            None => Result::Ok(None),
+        }
+    }
+
+    /// remove the rows the codes flush the keys of.
+    fn delete(&mut self, table: &mut Table) -> Result<(), String> {
+        self.changed_rows = 0;
+        let mut keys = Keys::default();
+        self.scan(table, &mut keys)?;
+        let source = self.source_table(table)?;
+        for key in keys.0 {
+            match source {
+                Some(ref source) => table.delete_from(source, key)?,
+                None => table.delete(key)?,
+            };
+            self.changed_rows += 1;
+        }
+        Result::Ok(())
+    }
+
+    /// insert the values of the statement into the table it names.
+    fn insert(&mut self, table: &mut Table) -> Result<(), String> {
+        self.changed_rows = 0;
+        match self.source_table(table)? {
+            Some(source) => {
+                let values = self.values_to_insert()?;
+                table.insert_values(&source, &values)?;
+            }
+            None => {
+                let row = self.row_to_insert()?;
+                table.insert(&row)?;
+            }
+        }
+        self.changed_rows = 1;
+        Result::Ok(())
+    }
+
+    /// a query with a `from` runs its codes over the rows of the table.
+    fn reads_table(&self) -> bool {
+        match self.parsed {
+            Some(ParsedSQL::Select {
+                ref table,
+                ref subquery,
+                ..
+            }) => table.is_some() || subquery.is_some(),
+            Some(ParsedSQL::Delete { .. }) => true,
+            Some(ParsedSQL::Insert { .. })
+            | Some(ParsedSQL::CreateTable { .. })
+            | Some(ParsedSQL::DropTable { .. })
+            | Some(ParsedSQL::CreateIndex { .. })
+            | Some(ParsedSQL::Transaction(_))
+            | Some(ParsedSQL::Pragma { .. })
+            | None => false,
+        }
+    }
+
+    /// the result rows of the groups of an aggregate query, once its rows are run.
+    fn flush_groups(&mut self, sink: &mut dyn RowSink) -> Result<(), String> {
+        let start = match self.codes.iter().position(|code| *code == OpCode::EndRow) {
+            Some(end_of_row) => end_of_row + 1,
+            None => return Result::Ok(()),
+        };
+        // without a `group by` the rows are one group, even if there are none
+        let keyless = match self.parsed {
+            Some(ParsedSQL::Select { ref group_by, .. }) => group_by.is_empty(),
+            Some(ParsedSQL::Insert { .. })
+            | Some(ParsedSQL::Delete { .. })
+            | Some(ParsedSQL::CreateTable { .. })
+            | Some(ParsedSQL::DropTable { .. })
+            | Some(ParsedSQL::CreateIndex { .. })
+            | Some(ParsedSQL::Transaction(_))
+            | Some(ParsedSQL::Pragma { .. })
+            | None => false,
+        };
+        if keyless && self.groups.len() == 0 {
+            self.groups.enter(Vec::new());
+        }
+        for index in 0..self.groups.len() {
+            self.groups.select(index);
+            self.pc = start;
+            self.stack.clear();
+            self.sym_table.truncate(self.constants);
+            if self.flush_rows(sink)? {
+                break;
+            }
+        }
+        Result::Ok(())
+    }
+
+    /// run the codes till they complete, the rows they flush go to the sink. it is
+    /// true if the codes halted and no more rows are wanted.
+    fn flush_rows(&mut self, sink: &mut dyn RowSink) -> Result<bool, String> {
+        loop {
+            match self.execute_codes() {
+                ExecResult::Complete => return Result::Ok(false),
+                ExecResult::Halt => return Result::Ok(true),
+                ExecResult::PendingRow => {
+                    sink.row(&self.row_buf.values()?)?;
+                    self.row_buf.reset();
+                }
+                ExecResult::Error(error) => {
+                    return Result::Err(format!("vm execute error: {}", error));
+                }
+            }
+        }
+    }
+
+    pub fn set_cancel_flag(&mut self, cancel: Arc<AtomicBool>) {
+        if let Some(ref mut subquery) = self.subquery {
+            subquery.set_cancel_flag(cancel.clone());
+        }
+        for probe in &mut self.probes {
+            probe.statement.set_cancel_flag(cancel.clone());
+        }
+        self.cancel = Some(cancel);
+    }
+
+    /// the flag of `set_cancel_flag`, if any.
+    pub fn cancel_flag(&self) -> Option<&AtomicBool> {
+        self.cancel.as_deref()
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+    }
+
+    /// prepare a statement for the tables of given schemas, a `Schema` alone stands
+    /// for the users table.
+    pub fn prepare(input_buffer: &str, schemas: &dyn Schemas) -> Result<Statement, String> {
+        let input_buffer = sql::strip_comments(input_buffer)?;
+        let input_buffer = input_buffer.trim();
         if input_buffer.eq("select") {
-            Result::Ok(Statement::new_select_statement())
-        } else if input_buffer.starts_with("select") {
-            sql::parse(input_buffer.as_bytes()).map(|parsed_sql| {
-                // TODO: get schema by table name
-                let codes = codegen::gen_code(&parsed_sql, schema);
-                Statement::new_select_statement2(parsed_sql, codes)
-            })
+            Result::Ok(Statement::new_legacy_select_statement())
+        } else if let Some(explained) = explained(input_buffer) {
+            // the statement is prepared as if it was run, so its errors show
+            let statement = Statement::prepare(explained, schemas)?;
+            match statement.kind {
+                StatementType::SELECT | StatementType::DELETE => {
+                    Result::Ok(Statement::new_explain_statement(plan::query_plan(
+                        statement.parsed.as_ref(),
+                        statement.index.as_ref(),
+                    )?))
+                }
+                _ => Result::Err("only a select or a delete has a query plan.".to_owned()),
+            }
+        } else if input_buffer.starts_with("select") || input_buffer.starts_with("delete") {
+            Statement::prepare_parameters(sql::parse(input_buffer.as_bytes())?, schemas)
+        } else if input_buffer.starts_with("create") {
+            match sql::parse(input_buffer.as_bytes())? {
+                ParsedSQL::CreateTable {
+                    database,
+                    table,
+                    columns,
+                    if_not_exists,
+                } => {
+                    let schema = Schema::from_columns(columns)?;
+                    let creation = Creation::Table(table, schema, if_not_exists);
+                    let mut statement = Statement::new_create_statement(creation);
+                    statement.database = database;
+                    Result::Ok(statement)
+                }
+                ParsedSQL::CreateIndex {
+                    database,
+                    name,
+                    table,
+                    column,
+                } => {
+                    let schema = schemas.schema(database.as_deref(), &table)?;
+                    if schema.column_index(&column).is_none() {
+                        return Result::Err(format!("no such column: {}", column));
+                    }
+                    let creation = Creation::Index {
+                        name,
+                        table,
+                        column,
+                    };
+                    let mut statement = Statement::new_create_statement(creation);
+                    statement.database = database;
+                    Result::Ok(statement)
+                }
+                _ => Result::Err(format!("Unrecognized command: {}", input_buffer)),
+            }
+        } else if input_buffer.starts_with("drop") {
+            match sql::parse(input_buffer.as_bytes())? {
+                ParsedSQL::DropTable {
+                    database,
+                    table,
+                    if_exists,
+                } => {
+                    let mut statement =
+                        Statement::new_control_statement(StatementType::DROP { table, if_exists });
+                    statement.database = database;
+                    Result::Ok(statement)
+                }
+                _ => Result::Err(format!("Unrecognized command: {}", input_buffer)),
+            }
+        } else if is_standard_insert(input_buffer) {
+            match sql::parse(input_buffer.as_bytes())? {
+                ParsedSQL::Insert {
+                    database,
+                    table,
+                    columns,
+                    values,
+                } => {
+                    let schema = schemas.schema(database.as_deref(), &table)?;
+                    let (insert_values, num_parameters) =
+                        insert_values(&table, columns, values, &schema)?;
+                    let mut statement =
+                        Statement::new_insert_statement(insert_values, num_parameters, database);
+                    if schema.has_rowid() {
+                        statement.source = Some((table, schema));
+                    }
+                    Result::Ok(statement)
+                }
+                _ => Result::Err(format!("Unrecognized command: {}", input_buffer)),
+            }
         } else if input_buffer.starts_with("insert") {
-            let parts: Vec<&str> = input_buffer.splitn(4, ' ').collect();
-            if parts.len() != 4 {
+            let mut parts = split_tokens(input_buffer)?;
+            // `insert into [DB.]users ...` names the table, the db is the main one without it
+            let mut database = None;
+            if parts.len() > 2 && !parts[1].quoted && parts[1].value == "into" {
+                let (name, table) = split_qualified_name(&parts[2].value);
+                check_table_name(table)?;
+                database = name.map(|name| name.to_owned());
+                parts.drain(1..3);
+            }
+            if parts.len() < 4 {
                 Result::Err(input_buffer.to_owned())
+            } else if parts.len() > 4 {
+                Result::Err(format!(
+                    "unexpected value after the email: {}, quote values with spaces like 'Ada Lovelace'.",
+                    parts[4].value
+                ))
             } else {
-                let id = i32::from_str_radix(parts[1], 10).unwrap();
-                if id < 0 {
-                    return Result::Err("ID must be positive.".to_owned());
-                }
-                let username = String::from(parts[2]);
-                let email = String::from(parts[3]);
-                if username.len() > 32 || email.len() > 256 {
-                    return Result::Err("String is too long.".to_owned());
-                }
-                let statement = Statement {
-                    kind: StatementType::INSERT,
-                    row_to_insert: Some(Row {
-                        id: id as u32,
-                        username: username,
-                        email: email,
-                    }),
-                    parsed: None,
-                    codes: Vec::new(),
-                    stack: Vec::new(),
-                    sym_table: Vec::new(),
-                    row_buf: RowBuf::new(),
-                    pc: 0,
-                };
+                let mut insert_values = Vec::new();
+                let mut num_parameters = 0;
+                for (column, part) in parts[1..].iter().enumerate() {
+                    let insert_value = match parse_parameter(part, num_parameters)? {
+                        Some(index) => {
+                            num_parameters = num_parameters.max(index);
+                            InsertValue::Parameter(index)
+                        }
+                        None if column == 0 => {
+                            InsertValue::Literal(Value::Integer(parse_id(&part.value)? as i64))
+                        }
+                        None => InsertValue::Literal(Value::Text(part.value.clone())),
+                    };
+                    if let InsertValue::Literal(ref value) = insert_value {
+                        check_insert_value(column, value)?;
+                    }
+                    insert_values.push(insert_value);
+                }
+                Result::Ok(Statement::new_insert_statement(
+                    insert_values,
+                    num_parameters,
+                    database,
+                ))
+            }
+        } else if let Some(savepoint) = parse_savepoint(input_buffer)? {
+            Result::Ok(Statement::new_savepoint_statement(savepoint))
+        } else if let Some(attachment) = parse_attachment(input_buffer)? {
+            Result::Ok(Statement::new_attach_statement(attachment))
+        } else if is_transaction(input_buffer) {
+            match sql::parse(input_buffer.as_bytes())? {
+                ParsedSQL::Transaction(transaction) => {
+                    Result::Ok(Statement::new_transaction_statement(transaction))
+                }
+                _ => Result::Err(format!("Unrecognized command: {}", input_buffer)),
+            }
+        } else if input_buffer.starts_with("pragma") {
+            match sql::parse(input_buffer.as_bytes())? {
+                ParsedSQL::Pragma { name, value } => {
+                    Result::Ok(Statement::new_pragma_statement(prepare_pragma(&name, value, schemas)?))
+                }
+                _ => Result::Err(format!("Unrecognized command: {}", input_buffer)),
+            }
+        } else {
+            Result::Err(format!("Unrecognized command: {}", input_buffer).to_owned())
+        }
+    }
+
+    /// the subqueries of `in` and `exists`, in the order the codes refer to them.
+    fn prepare_probes(parsed_sql: &ParsedSQL, schema: &Schema) -> Result<Vec<Probe>, String> {
+        let mut probes = Vec::new();
+        for subquery in codegen::collect_subqueries(parsed_sql) {
+            probes.push(Probe {
+                statement: Statement::prepare_select(subquery.clone(), schema)?,
+                values: HashSet::new(),
+                rows: 0,
+            });
+        }
+        Result::Ok(probes)
+    }
+
+    /// a select or delete, its parameters are typed by the operands they are used
+    /// with, see `codegen::parameter_types`.
+    fn prepare_parameters(mut parsed_sql: ParsedSQL, schemas: &dyn Schemas) -> Result<Statement, String> {
+        let schema = statement_schema(&parsed_sql, schemas)?;
+        let num_parameters = number_parameters(&mut parsed_sql)?;
+        let parameter_types = codegen::parameter_types(&parsed_sql, &schema, num_parameters);
+        let schema = schema.with_parameter_types(parameter_types);
+        let indexes = match parsed_sql {
+            ParsedSQL::Select {
+                ref database,
+                table: Some(ref table),
+                ..
+            }
+            | ParsedSQL::Delete {
+                ref database,
+                ref table,
+                ..
+            } => schemas.indexes(database.as_deref(), table)?,
+            _ => Vec::new(),
+        };
+        let index = plan::indexed_key(&parsed_sql, &indexes).map(|(index, _)| index.clone());
+        let mut statement = Statement::prepare_codes(parsed_sql, &schema)?;
+        statement.parameters = vec![None; num_parameters];
+        statement.index = index;
+        Result::Ok(statement)
+    }
+
+    /// the codes of a select or delete.
+    fn prepare_codes(parsed_sql: ParsedSQL, schema: &Schema) -> Result<Statement, String> {
+        match parsed_sql {
+            ParsedSQL::Select { .. } => Statement::prepare_select(parsed_sql, schema),
+            ParsedSQL::Delete { .. } => {
+                let (codes, constants) = codegen::gen_code(&parsed_sql, schema)?;
+                let probes = Statement::prepare_probes(&parsed_sql, schema)?;
+                let source = source(&parsed_sql, schema);
+                let mut statement = Statement::new_delete_statement(parsed_sql, codes, constants);
+                statement.probes = probes;
+                statement.source = source;
                 Result::Ok(statement)
             }
-        } else {
-            Result::Err(format!("Unrecognized command: {}", input_buffer).to_owned())
+            _ => Result::Err("only a select or a delete has codes.".to_owned()),
+        }
+    }
+
+    fn prepare_select(parsed_sql: ParsedSQL, schema: &Schema) -> Result<Statement, String> {
+        // the rows of a subquery are read like the ones of a table of its result columns
+        let mut subquery = None;
+        if let ParsedSQL::Select {
+            subquery: Some(ref parsed),
+            ..
+        } = parsed_sql
+        {
+            let columns = codegen::result_schema(parsed, schema)?;
+            let statement = Statement::prepare_select((**parsed).clone(), schema)?;
+            subquery = Some((statement, columns));
+        }
+        let probes = Statement::prepare_probes(&parsed_sql, schema)?;
+        let schema = match subquery {
+            Some((_, ref columns)) => columns,
+            None => schema,
+        };
+        let (codes, constants) = codegen::gen_code(&parsed_sql, schema)?;
+        let columns = result_columns(&parsed_sql, schema)?;
+        let source = source(&parsed_sql, schema);
+        let mut statement = Statement::new_select_statement(parsed_sql, columns, codes, constants);
+        statement.probes = probes;
+        statement.source = source;
+        if let Some((subquery, _)) = subquery {
+            // the rows of `from (select ... from aux.users)` are in the attached db
+            statement.database = subquery.database.clone();
+            statement.subquery = Some(Box::new(subquery));
+        }
+        Result::Ok(statement)
+    }
+}
+
+/**
+ * the schema of the table a select or delete reads, the subqueries of the
+ * statement read the same table. a select of no table is prepared for the users
+ * table. the codes take the types of the columns from a single schema, so a
+ * statement reading two tables is refused.
+ **/
+fn statement_schema(parsed_sql: &ParsedSQL, schemas: &dyn Schemas) -> Result<Schema, String> {
+    let mut tables = Vec::new();
+    collect_tables(parsed_sql, &mut tables);
+    let (database, table) = tables.first().cloned().unwrap_or((None, TABLE_NAME));
+    for &(other_database, other) in &tables {
+        if other != table {
+            return Result::Err(format!(
+                "cannot read tables {} and {} in one statement.",
+                table, other
+            ));
+        }
+        schemas.schema(other_database, other)?;
+    }
+    schemas.schema(database, table)
+}
+
+/// the dbs and names of the tables a statement and its subqueries read.
+fn collect_tables<'a>(parsed_sql: &'a ParsedSQL, tables: &mut Vec<(Option<&'a str>, &'a str)>) {
+    match parsed_sql {
+        ParsedSQL::Select {
+            database,
+            table: Some(table),
+            ..
+        }
+        | ParsedSQL::Delete {
+            database, table, ..
+        } => tables.push((database.as_deref(), table)),
+        ParsedSQL::Select {
+            subquery: Some(subquery),
+            ..
+        } => collect_tables(subquery, tables),
+        _ => {}
+    }
+    for subquery in codegen::collect_subqueries(parsed_sql) {
+        collect_tables(subquery, tables);
+    }
+}
+
+/// the created table a select or delete reads with the schema it is prepared
+/// for, `None` for the users table or a select of no table.
+fn source(parsed_sql: &ParsedSQL, schema: &Schema) -> Option<(String, Schema)> {
+    match parsed_sql {
+        ParsedSQL::Select {
+            table: Some(table), ..
+        }
+        | ParsedSQL::Delete { table, .. }
+            if schema.has_rowid() =>
+        {
+            Some((table.clone(), schema.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// the names of the result columns of a select, a `*` is the names of the columns
+/// of the table.
+fn result_columns(parsed_sql: &ParsedSQL, schema: &Schema) -> Result<Vec<String>, String> {
+    let operands = codegen::result_operands(parsed_sql, schema)?;
+    Result::Ok(operands.iter().map(|op| format!("{}", op)).collect())
+}
+
+/// `None` unless the input starts with one of the savepoint keywords.
+fn parse_savepoint(input: &str) -> Result<Option<Savepoint>, String> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    let (usage, savepoint): (&str, fn(String) -> Savepoint) = match words.first() {
+        Some(&"savepoint") => ("savepoint NAME", Savepoint::Set),
+        Some(&"release") => ("release [savepoint] NAME", Savepoint::Release),
+        Some(&"rollback") => ("rollback to [savepoint] NAME", Savepoint::RollbackTo),
+        _ => return Result::Ok(None),
+    };
+    let mut name = &words[1..];
+    if words[0] == "rollback" {
+        // a `rollback` without `to` rolls back the transaction as a whole
+        if name.first() != Some(&"to") {
+            return Result::Ok(None);
+        }
+        name = &name[1..];
+    }
+    if words[0] != "savepoint" && name.len() == 2 && name[0] == "savepoint" {
+        name = &name[1..];
+    }
+    match name {
+        [name] if is_identifier(name) => Result::Ok(Some(savepoint(name.to_string()))),
+        [name] => Result::Err(format!("invalid savepoint name: {}", name)),
+        _ => Result::Err(format!("usage: {}", usage)),
+    }
+}
+
+/// the statement after `explain` or `explain query plan`, `None` without them.
+fn explained(input: &str) -> Option<&str> {
+    let rest = input.strip_prefix("explain")?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let rest = rest.trim_start();
+    let query_plan = rest
+        .strip_prefix("query")
+        .map(str::trim_start)
+        .and_then(|rest| rest.strip_prefix("plan"));
+    match query_plan {
+        Some(statement) if statement.starts_with(char::is_whitespace) => Some(statement.trim_start()),
+        _ => Some(rest),
+    }
+}
+
+/// whether the input starts with `begin`, `commit`, `end` or `rollback`.
+fn is_transaction(input: &str) -> bool {
+    match input.split_whitespace().next() {
+        Some(word) => ["begin", "commit", "end", "rollback"].contains(&word),
+        None => false,
+    }
+}
+
+/// `None` unless the input starts with `attach` or `detach`.
+fn parse_attachment(input: &str) -> Result<Option<Attachment>, String> {
+    let tokens = split_tokens(input)?;
+    let words: Vec<&str> = tokens.iter().map(|token| token.value.as_str()).collect();
+    let usage = match words.first() {
+        Some(&"attach") => "attach [database] 'PATH' as NAME",
+        Some(&"detach") => "detach [database] NAME",
+        _ => return Result::Ok(None),
+    };
+    let mut args = &words[1..];
+    if args.len() > 1 && args[0] == "database" && !tokens[1].quoted {
+        args = &args[1..];
+    }
+    let attachment = match (words[0], args) {
+        ("attach", [path, "as", name]) => Attachment::Attach {
+            path: path.to_string(),
+            name: name.to_string(),
+        },
+        ("detach", [name]) => Attachment::Detach(name.to_string()),
+        _ => return Result::Err(format!("usage: {}", usage)),
+    };
+    match attachment {
+        Attachment::Attach { ref name, .. } | Attachment::Detach(ref name)
+            if !is_identifier(name) =>
+        {
+            Result::Err(format!("invalid database name: {}", name))
+        }
+        attachment => Result::Ok(Some(attachment)),
+    }
+}
+
+/// `aux.users` is the table `users` of the db `aux`.
+fn split_qualified_name(name: &str) -> (Option<&str>, &str) {
+    match name.find('.') {
+        Some(dot) => (Some(&name[..dot]), &name[dot + 1..]),
+        None => (None, name),
+    }
+}
+
+/// the setting a `pragma` reads or writes, or the table it describes.
+fn prepare_pragma(
+    name: &str,
+    value: Option<Operand>,
+    schemas: &dyn Schemas,
+) -> Result<Pragma, String> {
+    if name == "table_info" {
+        return match value {
+            Some(Operand::Column(table)) => {
+                schemas.schema(None, &table).map(|_| Pragma::TableInfo(table))
+            }
+            _ => Result::Err("table_info takes a table, like pragma table_info(users).".to_owned()),
+        };
+    }
+    let setting = settings::lookup(name).ok_or_else(|| format!("no such pragma: {}", name))?;
+    match value {
+        None => Result::Ok(Pragma::Get(setting)),
+        Some(value) => match (setting.set, value) {
+            (Some(set), Operand::Integer(value)) => Result::Ok(Pragma::Set(set, value)),
+            (Some(_), _) => Result::Err(format!("{} takes an integer.", name)),
+            (None, _) => Result::Err(format!("{} cannot be changed.", name)),
+        },
+    }
+}
+
+/// the users table, the only one a legacy insert writes.
+fn check_table_name(table: &str) -> Result<(), String> {
+    if table == TABLE_NAME {
+        Result::Ok(())
+    } else {
+        Result::Err(format!("no such table: {}", table))
+    }
+}
+
+/// letters, digits and underscores, not starting with a digit.
+fn is_identifier(name: &str) -> bool {
+    !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// whether the input is nothing but whitespace and comments, which runs nothing.
+pub fn is_blank(input: &str) -> bool {
+    sql::strip_comments(input).is_ok_and(|stripped| stripped.trim().is_empty())
+}
+
+/// the syntax error preparing the input failed with, its offset is into the input.
+/// `None` if the error did not come from parsing the statement.
+pub fn syntax_error(input: &str, error: &str) -> Option<sql::ParseError> {
+    let stripped = sql::strip_comments(input).ok()?;
+    let trimmed = stripped.trim();
+    let statement = explained(trimmed).unwrap_or(trimmed);
+    let start = stripped.len() - stripped.trim_start().len() + trimmed.len() - statement.len();
+    match sql::parse(statement.as_bytes()) {
+        Result::Err(mut parse_error) if parse_error.to_string() == error => {
+            parse_error.offset += start;
+            Some(parse_error)
+        }
+        _ => None,
+    }
+}
+
+/// the `create table` of the table with given name and schema, as written by `.dump`
+/// ahead of the rows. it is skipped if the table exists, like the users table of a
+/// new db.
+pub fn create_table_statement(table: &str, schema: &Schema) -> String {
+    let create = ParsedSQL::CreateTable {
+        database: None,
+        table: table.to_owned(),
+        columns: schema.columns().to_vec(),
+        if_not_exists: true,
+    };
+    create.to_string()
+}
+
+/// the insert statement `Statement::prepare` reads back into a row of given values
+/// of the table, as written by `.dump`.
+pub fn insert_statement(table: &str, schema: &Schema, values: &[Value]) -> String {
+    let insert = ParsedSQL::Insert {
+        database: None,
+        table: table.to_owned(),
+        columns: schema.row_columns(),
+        values: values.iter().map(literal).collect(),
+    };
+    insert.to_string()
+}
+
+/// a value of a legacy insert as written.
+struct Token {
+    value: String,
+    quoted: bool,
+}
+
+/// the parameter index of an unquoted `?` or `?N`, `?` follows the highest index
+/// before it.
+fn parse_parameter(token: &Token, highest: usize) -> Result<Option<usize>, String> {
+    if token.quoted || !token.value.starts_with('?') {
+        return Result::Ok(None);
+    }
+    let index = match &token.value[1..] {
+        "" => None,
+        digits => match digits.parse::<usize>() {
+            Result::Ok(index) if index > 0 && digits.chars().all(|c| c.is_ascii_digit()) => {
+                Some(index)
+            }
+            _ => return Result::Err(format!("invalid parameter: {}", token.value)),
+        },
+    };
+    parameter_index(index, highest).map(Some)
+}
+
+/// number the `?` parameters of a statement after the highest one before them, the
+/// highest index is the number of parameters.
+fn number_parameters(parsed_sql: &mut ParsedSQL) -> Result<usize, String> {
+    let mut highest = 0;
+    parsed_sql.visit_operands_mut(&mut |op| {
+        if let Operand::Parameter(index) = *op {
+            let index = parameter_index(index, highest)?;
+            highest = highest.max(index);
+            *op = Operand::Parameter(Some(index));
+        }
+        Result::Ok(())
+    })?;
+    Result::Ok(highest)
+}
+
+/// the literal operand of a value bound to a parameter, as it is shown in errors.
+fn literal(value: &Value) -> Operand {
+    match value {
+        Value::Integer(v) => Operand::Integer(*v),
+        Value::Real(v) => Operand::Float(*v),
+        Value::Boolean(v) => Operand::Boolean(*v),
+        Value::DateTime(v) => Operand::DateTime(*v),
+        Value::Text(text) => Operand::String(text.clone()),
+        Value::Null => Operand::Null,
+    }
+}
+
+/// the index of `?N`, or of `?` given `None`.
+fn parameter_index(index: Option<usize>, highest: usize) -> Result<usize, String> {
+    let index = match index {
+        Some(0) => return Result::Err("invalid parameter: ?0".to_owned()),
+        Some(index) => index,
+        None => highest + 1,
+    };
+    if index > MAX_PARAMETERS {
+        return Result::Err(format!(
+            "parameter index {} out of range, at most {}.",
+            index, MAX_PARAMETERS
+        ));
+    }
+    Result::Ok(index)
+}
+
+/// `insert into TABLE (...) values (...)` or `insert into TABLE values (...)`
+/// rather than the legacy `insert [into TABLE] ID USERNAME EMAIL`.
+fn is_standard_insert(input: &str) -> bool {
+    let rest = match input.strip_prefix("insert") {
+        Some(rest) if rest.starts_with(char::is_whitespace) => rest.trim_start(),
+        _ => return false,
+    };
+    let rest = match rest.strip_prefix("into") {
+        Some(rest) if rest.starts_with(char::is_whitespace) => rest.trim_start(),
+        _ => return false,
+    };
+    let rest = skip_table_name(rest).trim_start();
+    rest.starts_with('(') || rest.starts_with("values")
+}
+
+/// the input after a table name, which may be quoted with `"` or `` ` ``.
+fn skip_table_name(input: &str) -> &str {
+    let mut rest = input;
+    loop {
+        rest = match rest.chars().next() {
+            Some(quote) if quote == '"' || quote == '`' => match rest[1..].find(quote) {
+                Some(end) => &rest[end + 2..],
+                None => return "",
+            },
+            Some(c) if c.is_ascii_alphanumeric() || c == '_' || c == '.' => &rest[1..],
+            _ => return rest,
+        };
+    }
+}
+
+/// the values of a standard insert in the order of the columns of the table,
+/// with the number of parameters among them.
+fn insert_values(
+    table: &str,
+    columns: Vec<String>,
+    values: Vec<Operand>,
+    schema: &Schema,
+) -> Result<(Vec<InsertValue>, usize), String> {
+    let row_columns = schema.row_columns();
+    let columns = if columns.is_empty() {
+        schema.row_columns()
+    } else {
+        columns
+    };
+    if columns.len() != values.len() {
+        return Result::Err(format!(
+            "{} values for {} columns.",
+            values.len(),
+            columns.len()
+        ));
+    }
+    let mut insert_values: Vec<Option<InsertValue>> = row_columns.iter().map(|_| None).collect();
+    let mut num_parameters = 0;
+    for (name, value) in columns.iter().zip(values) {
+        let column = schema
+            .column_index(name)
+            .ok_or_else(|| format!("table {} has no column named {}", table, name))?;
+        if insert_values[column].is_some() {
+            return Result::Err(format!("column {} is given more than once.", name));
+        }
+        let insert_value = match value {
+            Operand::Parameter(index) => {
+                let index = parameter_index(index, num_parameters)?;
+                num_parameters = num_parameters.max(index);
+                InsertValue::Parameter(index)
+            }
+            Operand::Integer(v) => InsertValue::Literal(Value::Integer(v)),
+            Operand::Float(v) => InsertValue::Literal(Value::Real(v)),
+            Operand::Boolean(v) => InsertValue::Literal(Value::Boolean(v)),
+            Operand::Null => InsertValue::Literal(Value::Null),
+            Operand::DateTime(v) => InsertValue::Literal(Value::DateTime(v)),
+            Operand::String(text) => InsertValue::Literal(Value::Text(text)),
+            op => {
+                return Result::Err(format!(
+                    "insert values are literals or parameters, got {}.",
+                    op
+                ))
+            }
+        };
+        let insert_value = match insert_value {
+            InsertValue::Literal(value) if schema.has_rowid() => {
+                InsertValue::Literal(schema.column_value(column, value)?)
+            }
+            InsertValue::Literal(value) => {
+                check_insert_value(column, &value)?;
+                InsertValue::Literal(value)
+            }
+            parameter => parameter,
+        };
+        insert_values[column] = Some(insert_value);
+    }
+    insert_values
+        .into_iter()
+        .zip(row_columns.iter())
+        .map(|(value, name)| value.ok_or_else(|| format!("no value for column {}.", name)))
+        .collect::<Result<Vec<_>, _>>()
+        .map(|insert_values| (insert_values, num_parameters))
+}
+
+/// check a value against the column of the legacy insert at given position.
+fn check_insert_value(column: usize, value: &Value) -> Result<(), String> {
+    match (column, value) {
+        (0, Value::Integer(id)) if *id < 0 => Result::Err("ID must be positive.".to_owned()),
+        (0, Value::Integer(id)) if *id > i32::MAX as i64 => {
+            Result::Err("id out of range".to_owned())
+        }
+        (0, Value::Integer(_)) => Result::Ok(()),
+        (1, Value::Text(text)) if text.len() > 32 => {
+            Result::Err("String is too long.".to_owned())
+        }
+        (2, Value::Text(text)) if text.len() > 256 => {
+            Result::Err("String is too long.".to_owned())
+        }
+        (1, Value::Text(_)) | (2, Value::Text(_)) => Result::Ok(()),
+        (column, value) => {
+            let expected = if column == 0 { "an integer" } else { "text" };
+            let got = match value {
+                Value::Integer(v) => format!("integer {}", v),
+                Value::Real(v) => format!("real {:?}", v),
+                Value::Boolean(v) => format!("boolean {}", v),
+                Value::DateTime(_) => format!("datetime '{}'", value),
+                Value::Text(text) => format!("text '{}'", text),
+                Value::Null => "NULL".to_owned(),
+            };
+            Result::Err(format!(
+                "{} expects {}, got {}.",
+                INSERT_COLUMNS[column], expected, got
+            ))
+        }
+    }
+}
+
+/// split a legacy insert or a meta command on whitespace, a value starting with a
+/// single quote runs to the closing quote and a doubled quote inside stands for one
+/// quote.
+pub fn split_values(input: &str) -> Result<Vec<String>, String> {
+    split_tokens(input).map(|tokens| tokens.into_iter().map(|token| token.value).collect())
+}
+
+fn split_tokens(input: &str) -> Result<Vec<Token>, String> {
+    let mut values = Vec::new();
+    let mut chars = input.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        let mut value = String::new();
+        let quoted = chars.peek() == Some(&'\'');
+        match chars.peek() {
+            None => return Result::Ok(values),
+            Some('\'') => {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some('\'') if chars.peek() == Some(&'\'') => {
+                            chars.next();
+                            value.push('\'');
+                        }
+                        Some('\'') => break,
+                        Some(c) => value.push(c),
+                        None => return Result::Err(format!("unterminated string: '{}", value)),
+                    }
+                }
+                if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                    return Result::Err(format!("expected a space after '{}'.", value));
+                }
+            }
+            Some(_) => {
+                while let Some(c) = chars.peek().cloned() {
+                    if c.is_whitespace() {
+                        break;
+                    }
+                    value.push(c);
+                    chars.next();
+                }
+            }
         }
+        values.push(Token { value, quoted });
     }
 }
 
 impl VM for Statement {
-    fn execute(&mut self, table: &mut Table) -> Result<(), String> {
+    fn execute(&mut self, table: &mut Table, sink: &mut dyn RowSink) -> Result<(), String> {
         match self.kind {
-            StatementType::SELECT if self.parsed.is_none() => {
-                let mut cursor = table.select_cursor();
-                while !cursor.end_of_table() {
-                    let row = cursor.get();
-                    println!("({}, {}, {})", row.id, &row.username, &row.email);
-                    cursor.advance();
+            StatementType::SELECT
+            | StatementType::EXPLAIN(_)
+            | StatementType::PRAGMA(Pragma::Get(_))
+            | StatementType::PRAGMA(Pragma::TableInfo(_)) => self.query(table, sink),
+            StatementType::PRAGMA(Pragma::Set(set, value)) => set(table, value),
+            StatementType::INSERT => self.insert(table),
+            StatementType::DELETE => self.delete(table),
+            StatementType::CREATE(Creation::Table(ref name, ref schema, if_not_exists)) => {
+                if if_not_exists && table.find_table(name)?.is_some() {
+                    return Result::Ok(());
                 }
-                Result::Ok(())
+                table.create_table(name, schema)
             }
-            StatementType::SELECT => {
-                loop {
-                    match self.execute_codes() {
-                        ExecResult::Complete => break,
-                        ExecResult::PendingRow => {
-                            println!("{}", self.row_buf);
-                        }
-                        ExecResult::Error(error) => {
-                            return Result::Err(format!("vm execute error: {}", error));
-                        }
-                    }
+            StatementType::DROP {
+                table: ref name,
+                if_exists,
+            } => {
+                if if_exists && table.find_table(name)?.is_none() {
+                    return Result::Ok(());
                 }
-                Result::Ok(())
+                table.drop_table(name)
             }
-            StatementType::INSERT => {
-                if let Some(r) = self.row_to_insert.as_ref() {
-                    table.insert_cursor(r.id).save(r)
-                } else {
-                    Result::Ok(())
-                }
+            StatementType::CREATE(Creation::Index {
+                ref name,
+                table: ref table_name,
+                ref column,
+            }) => table.create_index(name, table_name, column),
+            StatementType::SAVEPOINT(Savepoint::Set(ref name)) => table.savepoint(name),
+            StatementType::SAVEPOINT(Savepoint::Release(ref name)) => table.release(name),
+            StatementType::SAVEPOINT(Savepoint::RollbackTo(ref name)) => table.rollback_to(name),
+            StatementType::TRANSACTION(Transaction::Begin) => table.begin(),
+            StatementType::TRANSACTION(Transaction::Commit) => table.commit_transaction(),
+            StatementType::TRANSACTION(Transaction::Rollback) if !table.in_transaction() => {
+                Result::Err("cannot roll back: no transaction is active.".to_owned())
+            }
+            StatementType::TRANSACTION(Transaction::Rollback) => table.rollback_transaction(),
+            StatementType::ATTACH(_) => {
+                Result::Err("attach and detach change a session, not a table.".to_owned())
             }
         }
     }
@@ -151,18 +1753,77 @@ impl VM for Statement {
         let mut pc = self.pc;
         let mut result = ExecResult::Complete;
         while pc < self.codes.len() {
+            if self.cancelled() {
+                result = ExecResult::Error(INTERRUPTED.to_owned());
+                break;
+            }
             let code = &self.codes[pc];
             pc += 1;
             match code {
                 &OpCode::LoadInt(i) => self.stack.push(i),
-                &OpCode::Add => {
-                    if let (Some(v1), Some(v2)) = (self.stack.pop(), self.stack.pop()) {
-                        self.stack.push(v1 + v2);
+                &OpCode::LoadFloat(v) => self.stack.push(float_slot(v)),
+                &OpCode::ToFloat => match self.stack.pop() {
+                    Some(v) => self.stack.push(float_slot(v as f64)),
+                    None => {
+                        result = ExecResult::Error("invalid state of stack.".to_owned());
+                        break;
+                    }
+                },
+                &OpCode::Add | &OpCode::Sub | &OpCode::Mul | &OpCode::Div | &OpCode::Mod => {
+                    if let (Some(v2), Some(v1)) = (self.stack.pop(), self.stack.pop()) {
+                        match arithmetic(code, v1, v2) {
+                            Result::Ok(v) => self.stack.push(v),
+                            Result::Err(error) => {
+                                result = ExecResult::Error(error);
+                                break;
+                            }
+                        }
+                    } else {
+                        result = ExecResult::Error("invalid state of stack.".to_owned());
+                        break;
+                    }
+                }
+                &OpCode::AddFloat
+                | &OpCode::SubFloat
+                | &OpCode::MulFloat
+                | &OpCode::DivFloat
+                | &OpCode::ModFloat => {
+                    if let (Some(v2), Some(v1)) = (self.stack.pop(), self.stack.pop()) {
+                        match float_arithmetic(code, slot_float(v1), slot_float(v2)) {
+                            Result::Ok(v) => self.stack.push(float_slot(v)),
+                            Result::Err(error) => {
+                                result = ExecResult::Error(error);
+                                break;
+                            }
+                        }
                     } else {
                         result = ExecResult::Error("invalid state of stack.".to_owned());
                         break;
                     }
                 }
+                &OpCode::StoreNull => self.row_buf.write_null(),
+                &OpCode::StoreDateTime => match self.stack.pop() {
+                    Some(v) => self.row_buf.write_datetime(v),
+                    None => {
+                        result = ExecResult::Error("invalid state of stack.".to_owned());
+                        break;
+                    }
+                },
+                &OpCode::StoreBool => match self.stack.pop() {
+                    Some(UNKNOWN) => self.row_buf.write_null(),
+                    Some(v) => self.row_buf.write_bool(v != 0),
+                    None => {
+                        result = ExecResult::Error("invalid state of stack.".to_owned());
+                        break;
+                    }
+                },
+                &OpCode::StoreFloat => match self.stack.pop() {
+                    Some(v) => self.row_buf.write_float(slot_float(v)),
+                    None => {
+                        result = ExecResult::Error("invalid state of stack.".to_owned());
+                        break;
+                    }
+                },
                 &OpCode::StoreInt => {
                     if let Some(v1) = self.stack.pop() {
                         self.row_buf.write_int(v1);
@@ -174,13 +1835,327 @@ impl VM for Statement {
                 &OpCode::FlushRow => {
                     // stop process of codes due to a new row
                     self.pc = pc;
+                    self.flushed_rows += 1;
                     result = ExecResult::PendingRow;
                     break;
                 }
-                &OpCode::LoadStr(ref str) => {
-                    self.stack.push(self.sym_table.len() as i64);
-                    self.sym_table.push(str.to_owned());
+                &OpCode::ColumnRead(column) => match self.row.as_ref().and_then(|row| row.column(column)) {
+                    Some(Value::Integer(v)) => self.stack.push(v),
+                    Some(Value::Real(v)) => self.stack.push(float_slot(v)),
+                    Some(Value::Boolean(v)) => self.stack.push(v as i64),
+                    Some(Value::DateTime(v)) => self.stack.push(v),
+                    Some(Value::Text(text)) => {
+                        self.stack.push(self.sym_table.len() as i64);
+                        self.sym_table.push(text);
+                    }
+                    _ => {
+                        result = ExecResult::Error(format!("no column {} to read.", column));
+                        break;
+                    }
+                },
+                &OpCode::LoadParameter(index, sql_type) => {
+                    let value = match self.parameters.get(index - 1) {
+                        Some(Some(value)) => bound_as(index, value, sql_type),
+                        _ => Result::Err(format!("parameter ?{} is not bound.", index)),
+                    };
+                    let loaded = value.and_then(|value| push_value(value, &mut self.stack, &mut self.sym_table));
+                    if let Result::Err(e) = loaded {
+                        result = ExecResult::Error(e);
+                        break;
+                    }
+                }
+                &OpCode::StoreParameter(index) => match self.parameters.get(index - 1) {
+                    Some(Some(value)) => self.row_buf.write_value(value),
+                    _ => {
+                        result = ExecResult::Error(format!("parameter ?{} is not bound.", index));
+                        break;
+                    }
+                },
+                &OpCode::Compare(comparison) => {
+                    if let (Some(v2), Some(v1)) = (self.stack.pop(), self.stack.pop()) {
+                        self.stack.push(comparison.holds(v1.cmp(&v2)) as i64);
+                    } else {
+                        result = ExecResult::Error("invalid state of stack.".to_owned());
+                        break;
+                    }
+                }
+                &OpCode::CompareBool(comparison) => match (self.stack.pop(), self.stack.pop()) {
+                    (Some(UNKNOWN), Some(_)) | (Some(_), Some(UNKNOWN)) => self.stack.push(UNKNOWN),
+                    (Some(v2), Some(v1)) => self.stack.push(comparison.holds(v1.cmp(&v2)) as i64),
+                    _ => {
+                        result = ExecResult::Error("invalid state of stack.".to_owned());
+                        break;
+                    }
+                },
+                &OpCode::CompareFloat(comparison) => {
+                    if let (Some(v2), Some(v1)) = (self.stack.pop(), self.stack.pop()) {
+                        // nothing holds for a real that is not a number
+                        let holds = slot_float(v1)
+                            .partial_cmp(&slot_float(v2))
+                            .is_some_and(|ordering| comparison.holds(ordering));
+                        self.stack.push(holds as i64);
+                    } else {
+                        result = ExecResult::Error("invalid state of stack.".to_owned());
+                        break;
+                    }
+                }
+                &OpCode::CompareStr(comparison) => {
+                    let len = self.sym_table.len();
+                    match (self.stack.pop(), self.stack.pop()) {
+                        (Some(i2), Some(i1)) if (i1 as usize) < len && (i2 as usize) < len => {
+                            let ordering =
+                                self.sym_table[i1 as usize].cmp(&self.sym_table[i2 as usize]);
+                            self.stack.push(comparison.holds(ordering) as i64);
+                        }
+                        _ => {
+                            result = ExecResult::Error("invalid state of stack.".to_owned());
+                            break;
+                        }
+                    }
+                }
+                &OpCode::Like => {
+                    let len = self.sym_table.len();
+                    match (self.stack.pop(), self.stack.pop()) {
+                        (Some(i2), Some(i1)) if (i1 as usize) < len && (i2 as usize) < len => {
+                            let matched =
+                                like::matches(&self.sym_table[i1 as usize], &self.sym_table[i2 as usize]);
+                            self.stack.push(matched as i64);
+                        }
+                        _ => {
+                            result = ExecResult::Error("invalid state of stack.".to_owned());
+                            break;
+                        }
+                    }
+                }
+                &OpCode::JumpIfFalse(target) => match self.stack.pop() {
+                    Some(0) | Some(UNKNOWN) => pc = target,
+                    Some(_) => {}
+                    None => {
+                        result = ExecResult::Error("invalid state of stack.".to_owned());
+                        break;
+                    }
+                },
+                &OpCode::Jump(target) => pc = target,
+                &OpCode::JumpIfNoValues(index, aggregate, target) => {
+                    if self.groups.aggregate(index, aggregate).is_none() {
+                        pc = target;
+                    }
+                }
+                &OpCode::And(target) => match self.stack.last() {
+                    Some(0) => pc = target,
+                    Some(_) => {}
+                    None => {
+                        result = ExecResult::Error("invalid state of stack.".to_owned());
+                        break;
+                    }
+                },
+                &OpCode::Or(target) => match self.stack.last() {
+                    Some(1) => pc = target,
+                    Some(_) => {}
+                    None => {
+                        result = ExecResult::Error("invalid state of stack.".to_owned());
+                        break;
+                    }
+                },
+                &OpCode::AndValues => match (self.stack.pop(), self.stack.pop()) {
+                    (Some(v2), Some(v1)) => self.stack.push(match (v1, v2) {
+                        (0, _) | (_, 0) => 0,
+                        (UNKNOWN, _) | (_, UNKNOWN) => UNKNOWN,
+                        _ => 1,
+                    }),
+                    _ => {
+                        result = ExecResult::Error("invalid state of stack.".to_owned());
+                        break;
+                    }
+                },
+                &OpCode::OrValues => match (self.stack.pop(), self.stack.pop()) {
+                    (Some(v2), Some(v1)) => self.stack.push(match (v1, v2) {
+                        (1, _) | (_, 1) => 1,
+                        (UNKNOWN, _) | (_, UNKNOWN) => UNKNOWN,
+                        _ => 0,
+                    }),
+                    _ => {
+                        result = ExecResult::Error("invalid state of stack.".to_owned());
+                        break;
+                    }
+                },
+                &OpCode::Bool => match self.stack.pop() {
+                    Some(v) => self.stack.push((v != 0) as i64),
+                    None => {
+                        result = ExecResult::Error("invalid state of stack.".to_owned());
+                        break;
+                    }
+                },
+                &OpCode::Not => match self.stack.pop() {
+                    Some(UNKNOWN) => self.stack.push(UNKNOWN),
+                    Some(v) => self.stack.push((v == 0) as i64),
+                    None => {
+                        result = ExecResult::Error("invalid state of stack.".to_owned());
+                        break;
+                    }
+                },
+                &OpCode::Group => match self.row_buf.values() {
+                    Result::Ok(key) => {
+                        self.row_buf.reset();
+                        self.groups.enter(key);
+                    }
+                    Result::Err(error) => {
+                        result = ExecResult::Error(error);
+                        break;
+                    }
+                },
+                &OpCode::Count(index) => match self.groups.aggregate_mut(index) {
+                    Some(accumulator) => accumulator.count(),
+                    None => {
+                        result = ExecResult::Error("no group to aggregate.".to_owned());
+                        break;
+                    }
+                },
+                &OpCode::Accumulate(index, aggregate, sql_type) => {
+                    let value = match self.stack.pop() {
+                        Some(slot) => slot_value(slot, sql_type, &self.sym_table),
+                        None => Result::Err("invalid state of stack.".to_owned()),
+                    };
+                    let accumulated = match (value, self.groups.aggregate_mut(index)) {
+                        (Result::Ok(value), Some(accumulator)) => accumulator.add(aggregate, value),
+                        (Result::Err(e), _) => Result::Err(e),
+                        (_, None) => Result::Err("no group to aggregate.".to_owned()),
+                    };
+                    if let Result::Err(e) = accumulated {
+                        result = ExecResult::Error(e);
+                        break;
+                    }
+                }
+                &OpCode::EndRow => break,
+                &OpCode::GroupKey(index) => match self.groups.key(index) {
+                    Some(Value::Integer(v)) => self.stack.push(*v),
+                    Some(Value::Real(v)) => self.stack.push(float_slot(*v)),
+                    Some(Value::Boolean(v)) => self.stack.push(*v as i64),
+                    Some(Value::DateTime(v)) => self.stack.push(*v),
+                    Some(Value::Text(str)) => {
+                        self.stack.push(self.sym_table.len() as i64);
+                        self.sym_table.push(str.clone());
+                    }
+                    _ => {
+                        result = ExecResult::Error(format!("no key {} of the group.", index));
+                        break;
+                    }
+                },
+                &OpCode::GroupAggregate(index, aggregate) => {
+                    // an aggregate of no values is a null, which loads as 0
+                    let value = self.groups.aggregate(index, aggregate).unwrap_or(Value::Integer(0));
+                    if let Result::Err(e) = push_value(value, &mut self.stack, &mut self.sym_table) {
+                        result = ExecResult::Error(e);
+                        break;
+                    }
+                }
+                &OpCode::StoreAggregate(index, aggregate) => {
+                    match self.groups.aggregate(index, aggregate) {
+                        Some(value) => self.row_buf.write_value(&value),
+                        None => self.row_buf.write_null(),
+                    }
+                }
+                &OpCode::In(index) => match (self.stack.pop(), self.probes.get(index)) {
+                    (Some(v), Some(probe)) => {
+                        self.stack.push(probe.values.contains(&Value::Integer(v)) as i64)
+                    }
+                    _ => {
+                        result = ExecResult::Error("invalid state of stack.".to_owned());
+                        break;
+                    }
+                },
+                &OpCode::InFloat(index) => match (self.stack.pop(), self.probes.get(index)) {
+                    (Some(v), Some(probe)) => {
+                        self.stack.push(probe.values.contains(&Value::Real(slot_float(v))) as i64)
+                    }
+                    _ => {
+                        result = ExecResult::Error("invalid state of stack.".to_owned());
+                        break;
+                    }
+                },
+                &OpCode::InBool(index) => match (self.stack.pop(), self.probes.get(index)) {
+                    (Some(UNKNOWN), Some(_)) => self.stack.push(UNKNOWN),
+                    (Some(v), Some(probe)) => {
+                        self.stack.push(probe.values.contains(&Value::Boolean(v != 0)) as i64)
+                    }
+                    _ => {
+                        result = ExecResult::Error("invalid state of stack.".to_owned());
+                        break;
+                    }
+                },
+                &OpCode::InDateTime(index) => match (self.stack.pop(), self.probes.get(index)) {
+                    (Some(v), Some(probe)) => {
+                        self.stack.push(probe.values.contains(&Value::DateTime(v)) as i64)
+                    }
+                    _ => {
+                        result = ExecResult::Error("invalid state of stack.".to_owned());
+                        break;
+                    }
+                },
+                &OpCode::InStr(index) => {
+                    let len = self.sym_table.len();
+                    match (self.stack.pop(), self.probes.get(index)) {
+                        (Some(sym_index), Some(probe)) if (sym_index as usize) < len => {
+                            let text = Value::Text(self.sym_table[sym_index as usize].clone());
+                            self.stack.push(probe.values.contains(&text) as i64);
+                        }
+                        _ => {
+                            result = ExecResult::Error("invalid state of stack.".to_owned());
+                            break;
+                        }
+                    }
+                }
+                OpCode::InList(values) => match self.stack.pop() {
+                    Some(v) => self.stack.push(values.contains(&v) as i64),
+                    None => {
+                        result = ExecResult::Error("invalid state of stack.".to_owned());
+                        break;
+                    }
+                },
+                OpCode::InListFloat(values) => match self.stack.pop() {
+                    Some(v) => self.stack.push(values.contains(&slot_float(v)) as i64),
+                    None => {
+                        result = ExecResult::Error("invalid state of stack.".to_owned());
+                        break;
+                    }
+                },
+                OpCode::InListStr(values) => {
+                    let len = self.sym_table.len();
+                    match self.stack.pop() {
+                        Some(sym_index) if (sym_index as usize) < len => {
+                            let text = &self.sym_table[sym_index as usize];
+                            self.stack.push(values.contains(text) as i64);
+                        }
+                        _ => {
+                            result = ExecResult::Error("invalid state of stack.".to_owned());
+                            break;
+                        }
+                    }
+                }
+                &OpCode::Exists(index) => match self.probes.get(index) {
+                    Some(probe) => self.stack.push((probe.rows > 0) as i64),
+                    None => {
+                        result = ExecResult::Error(format!("no subquery {} to probe.", index));
+                        break;
+                    }
+                },
+                OpCode::Call(index, types) => {
+                    if let Err(e) = call_function(*index, types, &mut self.stack, &mut self.sym_table) {
+                        result = ExecResult::Error(e);
+                        break;
+                    }
+                }
+                &OpCode::Limit(count) => {
+                    if self.flushed_rows >= count {
+                        result = ExecResult::Halt;
+                        break;
+                    }
                 }
+                &OpCode::Offset(offset) => {
+                    self.offset_rows += 1;
+                    self.stack.push((self.offset_rows > offset) as i64);
+                }
+                &OpCode::LoadStrIdx(index) => self.stack.push(index as i64),
                 &OpCode::StoreStr => {
                     let len = self.sym_table.len();
                     match self.stack.pop() {
@@ -212,6 +2187,122 @@ impl VM for Statement {
     }
 }
 
+/// the slot of the stack holding a real, by its bits.
+fn float_slot(v: f64) -> i64 {
+    v.to_bits() as i64
+}
+
+fn slot_float(slot: i64) -> f64 {
+    f64::from_bits(slot as u64)
+}
+
+/// call a function with the arguments of given types on top of the stack, which
+/// are replaced by its result.
+fn call_function(
+    index: usize,
+    types: &[SQLType],
+    stack: &mut Vec<i64>,
+    sym_table: &mut Vec<String>,
+) -> Result<(), String> {
+    let function = functions::FUNCTIONS
+        .get(index)
+        .ok_or_else(|| format!("no function {} to call.", index))?;
+    if stack.len() < types.len() {
+        return Result::Err("invalid state of stack.".to_owned());
+    }
+    let slots = stack.split_off(stack.len() - types.len());
+    let mut args = Vec::new();
+    for (&slot, &sql_type) in slots.iter().zip(types) {
+        args.push(slot_value(slot, sql_type, sym_table)?);
+    }
+    push_value((function.call)(args)?, stack, sym_table)
+}
+
+/// the value in a slot of the stack holding given type.
+fn slot_value(slot: i64, sql_type: SQLType, sym_table: &[String]) -> Result<Value, String> {
+    match sql_type {
+        SQLType::Integer => Result::Ok(Value::Integer(slot)),
+        SQLType::Real => Result::Ok(Value::Real(slot_float(slot))),
+        SQLType::Boolean if slot == UNKNOWN => Result::Ok(Value::Null),
+        SQLType::Boolean => Result::Ok(Value::Boolean(slot != 0)),
+        SQLType::DateTime => Result::Ok(Value::DateTime(slot)),
+        SQLType::String => match sym_table.get(slot as usize) {
+            Some(text) => Result::Ok(Value::Text(text.clone())),
+            None => Result::Err("invalid state of stack.".to_owned()),
+        },
+    }
+}
+
+/// load a value onto the stack, strings go to the symbol table.
+fn push_value(value: Value, stack: &mut Vec<i64>, sym_table: &mut Vec<String>) -> Result<(), String> {
+    match value {
+        Value::Integer(v) | Value::DateTime(v) => stack.push(v),
+        Value::Real(v) => stack.push(float_slot(v)),
+        Value::Boolean(v) => stack.push(v as i64),
+        Value::Text(text) => {
+            stack.push(sym_table.len() as i64);
+            sym_table.push(text);
+        }
+        Value::Null => return Result::Err("cannot load a null.".to_owned()),
+    }
+    Result::Ok(())
+}
+
+/// the value bound to a parameter as the type its codes take, an integer is taken
+/// for a real like the literals are.
+fn bound_as(index: usize, value: &Value, sql_type: SQLType) -> Result<Value, String> {
+    match (value, sql_type) {
+        (Value::Integer(v), SQLType::Real) => Result::Ok(Value::Real(*v as f64)),
+        (Value::Integer(_), SQLType::Integer)
+        | (Value::Real(_), SQLType::Real)
+        | (Value::Boolean(_), SQLType::Boolean)
+        | (Value::DateTime(_), SQLType::DateTime)
+        | (Value::Text(_), SQLType::String) => Result::Ok(value.clone()),
+        _ => Result::Err(format!(
+            "parameter ?{} takes {} values, not {}.",
+            index,
+            sql_type,
+            literal(value)
+        )),
+    }
+}
+
+/// the result of an arithmetic code on two reals.
+fn float_arithmetic(code: &OpCode, v1: f64, v2: f64) -> Result<f64, String> {
+    match code {
+        OpCode::AddFloat => Result::Ok(v1 + v2),
+        OpCode::SubFloat => Result::Ok(v1 - v2),
+        OpCode::MulFloat => Result::Ok(v1 * v2),
+        OpCode::DivFloat | OpCode::ModFloat if v2 == 0.0 => {
+            Result::Err("division by zero.".to_owned())
+        }
+        OpCode::DivFloat => Result::Ok(v1 / v2),
+        OpCode::ModFloat => Result::Ok(v1 % v2),
+        code => Result::Err(format!("{:?} is not arithmetic.", code)),
+    }
+}
+
+/// the result of an arithmetic code on two integers.
+fn arithmetic(code: &OpCode, v1: i64, v2: i64) -> Result<i64, String> {
+    let result = match code {
+        OpCode::Add => v1.checked_add(v2),
+        OpCode::Sub => v1.checked_sub(v2),
+        OpCode::Mul => v1.checked_mul(v2),
+        OpCode::Div | OpCode::Mod if v2 == 0 => return Result::Err("division by zero.".to_owned()),
+        OpCode::Div => v1.checked_div(v2),
+        OpCode::Mod => v1.checked_rem(v2),
+        code => return Result::Err(format!("{:?} is not arithmetic.", code)),
+    };
+    result.ok_or_else(|| "integer overflow.".to_owned())
+}
+
+fn parse_id(id: &str) -> Result<i32, String> {
+    let id = id.trim();
+    id.parse::<i32>().map_err(|e| match e.kind() {
+        IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => "id out of range".to_owned(),
+        _ => format!("invalid id '{}': not a number", id),
+    })
+}
 
 #[cfg(test)]
 mod tests {
@@ -240,6 +2331,341 @@ mod tests {
         }
     }
 
+    #[test]
+    fn cancelled_statements_stop_executing() {
+        let schema = get_schema();
+        let mut statement = Statement::prepare("select 40 + 2", &schema).unwrap();
+        let cancel = Arc::new(AtomicBool::new(false));
+        statement.set_cancel_flag(cancel.clone());
+        cancel.store(true, Ordering::SeqCst);
+        assert_eq!(
+            statement.execute_codes(),
+            ExecResult::Error("interrupted.".to_owned())
+        );
+        cancel.store(false, Ordering::SeqCst);
+        assert_eq!(statement.execute_codes(), ExecResult::PendingRow);
+    }
+
+    #[test]
+    fn parameters_are_loaded_by_the_codes_prepared_once() {
+        let schema = get_schema();
+        let mut statement =
+            Statement::prepare("select ?, username from users where id > ? and email like ?", &schema)
+                .unwrap();
+        assert_eq!(statement.parameter_count(), 3);
+        assert!(statement.codes.contains(&OpCode::StoreParameter(1)));
+        assert!(statement.codes.contains(&OpCode::LoadParameter(2, SQLType::Integer)));
+        assert!(statement.codes.contains(&OpCode::LoadParameter(3, SQLType::String)));
+
+        let codes = format!("{:?}", statement.codes);
+        statement.bind(1, Value::Real(0.5)).unwrap();
+        statement.bind(2, Value::Integer(1)).unwrap();
+        statement.clear_bindings();
+        assert_eq!(format!("{:?}", statement.codes), codes);
+
+        assert_eq!(
+            Statement::prepare("select ? + ?", &schema).err(),
+            Some("cannot infer the type of ?1.".to_owned())
+        );
+    }
+
+    #[test]
+    fn overflowing_additions_are_errors() {
+        let schema = get_schema();
+        let mut statement = Statement::prepare("select 9223372036854775807 + 1", &schema).unwrap();
+        assert_eq!(
+            statement.execute_codes(),
+            ExecResult::Error("integer overflow.".to_owned())
+        );
+    }
+
+    #[test]
+    fn integers_out_of_range_are_errors() {
+        let schema = get_schema();
+        let mut statement = Statement::prepare("select -9223372036854775808 / -1", &schema).unwrap();
+        assert_eq!(
+            statement.execute_codes(),
+            ExecResult::Error("integer overflow.".to_owned())
+        );
+        assert_eq!(
+            Statement::prepare("select 9223372036854775808", &schema).err(),
+            Some(
+                "syntax error near 9223372036854775808, expected an integer from \
+                 -9223372036854775808 to 9223372036854775807."
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            Statement::prepare("select 1abc from users", &schema).err(),
+            Some("syntax error near abc, expected the end of the statement.".to_owned())
+        );
+    }
+
+    fn prepare_insert(input: &str) -> Result<Row, String> {
+        Statement::prepare(input, &get_schema()).map(|statement| statement.row_to_insert().unwrap())
+    }
+
+    #[test]
+    fn insert_ids_must_be_numbers() {
+        assert_eq!(
+            prepare_insert("insert abc foo bar@x.com").err(),
+            Some("invalid id 'abc': not a number".to_owned())
+        );
+        assert_eq!(
+            prepare_insert("insert 1x foo bar@x.com").err(),
+            Some("invalid id '1x': not a number".to_owned())
+        );
+    }
+
+    #[test]
+    fn insert_ids_must_be_positive() {
+        assert_eq!(
+            prepare_insert("insert -1 foo bar@x.com").err(),
+            Some("ID must be positive.".to_owned())
+        );
+    }
+
+    #[test]
+    fn insert_ids_must_fit() {
+        assert_eq!(
+            prepare_insert("insert 99999999999 foo bar@x.com").err(),
+            Some("id out of range".to_owned())
+        );
+        assert_eq!(
+            prepare_insert("insert -99999999999 foo bar@x.com").err(),
+            Some("id out of range".to_owned())
+        );
+        assert_eq!(prepare_insert("insert 2147483647 foo bar@x.com").unwrap().id, 2147483647);
+    }
+
+    #[test]
+    fn insert_ignores_surrounding_whitespace() {
+        let row = prepare_insert("  insert \t7 foo bar@x.com \n").unwrap();
+        assert_eq!(row.id, 7);
+        assert_eq!(row.username, "foo");
+        assert_eq!(row.email, "bar@x.com");
+    }
+
+    #[test]
+    fn insert_values_can_be_quoted() {
+        let row = prepare_insert("insert 1 'Ada Lovelace' 'ada @example.com'").unwrap();
+        assert_eq!(row.username, "Ada Lovelace");
+        assert_eq!(row.email, "ada @example.com");
+        let row = prepare_insert("insert 2 'O''Brien' o'brien@example.com").unwrap();
+        assert_eq!(row.username, "O'Brien");
+        assert_eq!(row.email, "o'brien@example.com");
+        let row = prepare_insert("insert 3 '' ''''").unwrap();
+        assert_eq!(row.username, "");
+        assert_eq!(row.email, "'");
+    }
+
+    #[test]
+    fn insert_rejects_extra_values() {
+        assert_eq!(
+            prepare_insert("insert 1 Ada Lovelace ada@example.com").err(),
+            Some(
+                "unexpected value after the email: ada@example.com, quote values with spaces like 'Ada Lovelace'."
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            prepare_insert("insert 1 'Ada Lovelace ada@example.com").err(),
+            Some("unterminated string: 'Ada Lovelace ada@example.com".to_owned())
+        );
+        assert_eq!(
+            prepare_insert("insert 1 'Ada'Lovelace ada@example.com").err(),
+            Some("expected a space after 'Ada'.".to_owned())
+        );
+    }
+
+    #[test]
+    fn quoted_values_are_limited_by_their_content() {
+        let name = format!("'{}'", "a b".repeat(10) + "cd");
+        let email = format!("'{}'", "x'".repeat(128).replace('\'', "''"));
+        let row = prepare_insert(&format!("insert 1 {} {}", name, email)).unwrap();
+        assert_eq!(row.username.len(), 32);
+        assert_eq!(row.email.len(), 256);
+        assert_eq!(
+            prepare_insert(&format!("insert 1 '{} ' a", "a".repeat(32))).err(),
+            Some("String is too long.".to_owned())
+        );
+        assert_eq!(
+            prepare_insert(&format!("insert 1 a '{}'''", "a".repeat(256))).err(),
+            Some("String is too long.".to_owned())
+        );
+    }
+
+    #[test]
+    fn insert_statements_read_back() {
+        for &(username, email) in &[
+            ("Ada Lovelace", "ada@example.com"),
+            ("O'Brien", "o'brien@example.com"),
+            ("", "tab\there"),
+            ("'", "''"),
+        ] {
+            let row = Row {
+                id: 1,
+                username: username.to_owned(),
+                email: email.to_owned(),
+            };
+            let insert = insert_statement(TABLE_NAME, &get_schema(), &row.values());
+            let read_back = prepare_insert(&insert).unwrap();
+            assert_eq!((read_back.username.as_str(), read_back.email.as_str()), (username, email));
+        }
+    }
+
+    #[test]
+    fn insert_into_values_follows_the_columns_of_the_table() {
+        let row = prepare_insert(
+            "insert into users (email, id, username) values ('ada@example.com', 1, 'Ada Lovelace')",
+        )
+        .unwrap();
+        assert_eq!(row.id, 1);
+        assert_eq!(row.username, "Ada Lovelace");
+        assert_eq!(row.email, "ada@example.com");
+        let row = prepare_insert("insert into users values(2, 'bob', 'bob@example.com')").unwrap();
+        assert_eq!((row.id, row.username.as_str()), (2, "bob"));
+
+        let statement =
+            Statement::prepare("insert into aux.users values (?, ?3, ?)", &get_schema()).unwrap();
+        assert_eq!(statement.parameters.len(), 4);
+        assert_eq!(statement.database.as_deref(), Some("aux"));
+    }
+
+    #[test]
+    fn insert_into_values_are_checked_against_the_table() {
+        let errors = [
+            (
+                "insert into users (id, name, email) values (1, 'ada', 'a@b')",
+                "table users has no column named name",
+            ),
+            ("insert into users (id, email) values (1, 'a@b')", "no value for column username."),
+            ("insert into users (id) values (1, 'ada')", "2 values for 1 columns."),
+            (
+                "insert into users (id, id, email) values (1, 2, 'a@b')",
+                "column id is given more than once.",
+            ),
+            ("insert into users values ('1', 'ada', 'a@b')", "id expects an integer, got text '1'."),
+            ("insert into users values (-1, 'ada', 'a@b')", "ID must be positive."),
+            (
+                "insert into users values (1 + 1, 'ada', 'a@b')",
+                "insert values are literals or parameters, got 1 + 1.",
+            ),
+            ("insert into people values (1, 'ada', 'a@b')", "no such table: people"),
+            ("insert into users values (1, 'ada', 'a@b'", "syntax error at the end, expected )."),
+        ];
+        for &(input, error) in errors.iter() {
+            assert_eq!(prepare_insert(input).err(), Some(error.to_owned()), "{}", input);
+        }
+    }
+
+    fn prepare_savepoint(input: &str) -> Result<Savepoint, String> {
+        Statement::prepare(input, &get_schema()).map(|statement| match statement.kind {
+            StatementType::SAVEPOINT(savepoint) => savepoint,
+            _ => panic!("not a savepoint statement: {}", input),
+        })
+    }
+
+    #[test]
+    fn savepoint_statements_take_a_name() {
+        assert_eq!(prepare_savepoint("savepoint a"), Result::Ok(Savepoint::Set("a".to_owned())));
+        assert_eq!(
+            prepare_savepoint("release savepoint a"),
+            Result::Ok(Savepoint::Release("a".to_owned()))
+        );
+        assert_eq!(
+            prepare_savepoint("release savepoint"),
+            Result::Ok(Savepoint::Release("savepoint".to_owned()))
+        );
+        assert_eq!(
+            prepare_savepoint("rollback to a_1"),
+            Result::Ok(Savepoint::RollbackTo("a_1".to_owned()))
+        );
+        assert_eq!(
+            prepare_savepoint("rollback to savepoint a"),
+            Result::Ok(Savepoint::RollbackTo("a".to_owned()))
+        );
+        assert_eq!(prepare_savepoint("savepoint"), Result::Err("usage: savepoint NAME".to_owned()));
+        assert_eq!(
+            prepare_savepoint("rollback a"),
+            Result::Err("syntax error near a, expected the end of the statement.".to_owned())
+        );
+        assert_eq!(
+            prepare_savepoint("savepoint 1a"),
+            Result::Err("invalid savepoint name: 1a".to_owned())
+        );
+    }
+
+    #[test]
+    fn create_table_statements_build_a_schema() {
+        let statement =
+            Statement::prepare("create table books (id integer, title text, pages int)", &get_schema())
+                .unwrap();
+        let schema = statement.schema().unwrap();
+        assert_eq!(schema.row_columns(), vec!["id", "title", "pages"]);
+        assert_eq!(schema.get_column_type("title"), Some(SQLType::String));
+        assert_eq!(schema.get_column_type("pages"), Some(SQLType::Integer));
+        assert!(!statement.is_query());
+        assert_eq!(
+            Statement::prepare("create table books (id integer, id text)", &get_schema()).err(),
+            Some("duplicate column name: id".to_owned())
+        );
+    }
+
+    #[test]
+    fn attach_statements_take_a_path_and_a_name() {
+        let prepare_attachment = |input: &str| {
+            Statement::prepare(input, &get_schema())
+                .map(|statement| statement.attachment().cloned())
+        };
+        assert_eq!(
+            prepare_attachment("attach database 'aux db.rdb' as aux"),
+            Result::Ok(Some(Attachment::Attach {
+                path: "aux db.rdb".to_owned(),
+                name: "aux".to_owned(),
+            }))
+        );
+        assert_eq!(
+            prepare_attachment("attach aux.rdb as aux"),
+            Result::Ok(Some(Attachment::Attach {
+                path: "aux.rdb".to_owned(),
+                name: "aux".to_owned(),
+            }))
+        );
+        assert_eq!(
+            prepare_attachment("detach database aux"),
+            Result::Ok(Some(Attachment::Detach("aux".to_owned())))
+        );
+        assert_eq!(
+            prepare_attachment("attach aux.rdb"),
+            Result::Err("usage: attach [database] 'PATH' as NAME".to_owned())
+        );
+        assert_eq!(
+            prepare_attachment("detach aux.rdb"),
+            Result::Err("invalid database name: aux.rdb".to_owned())
+        );
+    }
+
+    #[test]
+    fn tables_may_be_qualified_with_a_db() {
+        let prepare = |input: &str| Statement::prepare(input, &get_schema());
+        let select = prepare("select * from aux.users").unwrap();
+        assert!(select.is_query());
+        assert_eq!(select.database(), Some("aux"));
+        assert_eq!(select.columns(), ["id", "username", "email"]);
+        assert_eq!(prepare("select * from users").unwrap().database(), None);
+        assert_eq!(prepare("select 1 from aux.users").unwrap().database(), Some("aux"));
+
+        let insert = prepare("insert into aux.users 1 ada ada@example.com").unwrap();
+        assert!(insert.is_insert());
+        assert_eq!(insert.database(), Some("aux"));
+        assert_eq!(prepare("insert into users 1 ada ada@example.com").unwrap().database(), None);
+        assert_eq!(
+            prepare("insert into aux.accounts 1 ada ada@example.com").err(),
+            Some("no such table: accounts".to_owned())
+        );
+    }
+
     #[test]
     fn vm_works() {
         verify_vm_execution("select 41 + 1", "(42)");
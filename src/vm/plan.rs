@@ -0,0 +1,299 @@
+//! the plan `explain query plan` shows, how a select or delete reads its rows.
+//! a `where` comparing `id` to a value looks the row up by its key, one comparing
+//! an indexed column to a value looks the rows up in the index, otherwise the
+//! table is scanned in the order of its keys. there are no sorts yet.
+
+use sql::operands::{Comparison, Operand};
+use sql::ParsedSQL;
+use table::index::IndexEntry;
+use table::TABLE_NAME;
+
+use super::codegen;
+
+/// the steps of the plan, a subquery's steps are indented under the step running it.
+/// `index` is the index the statement searches, see `indexed_key`.
+pub fn query_plan(sql: Option<&ParsedSQL>, index: Option<&IndexEntry>) -> Result<Vec<String>, String> {
+    let mut steps = Vec::new();
+    match sql {
+        Some(sql) => add_steps(sql, index, 0, &mut steps)?,
+        // the legacy `select` reads every row
+        None => steps.push(format!("scan table {}", TABLE_NAME)),
+    }
+    Result::Ok(steps)
+}
+
+fn add_steps(
+    sql: &ParsedSQL,
+    index: Option<&IndexEntry>,
+    depth: usize,
+    steps: &mut Vec<String>,
+) -> Result<(), String> {
+    match sql {
+        ParsedSQL::Select {
+            database,
+            table,
+            subquery,
+            alias,
+            ..
+        } => {
+            let alias = match alias {
+                Some(alias) => format!(" as {}", alias),
+                None => String::new(),
+            };
+            match (table, subquery) {
+                (Some(table), _) => push(
+                    steps,
+                    depth,
+                    format!("{}{}{}", access(sql, index), qualified(database, table), alias),
+                ),
+                (None, Some(subquery)) => {
+                    push(steps, depth, format!("scan subquery{}", alias));
+                    add_steps(subquery, None, depth + 1, steps)?;
+                }
+                (None, None) => push(steps, depth, "constant row".to_owned()),
+            }
+        }
+        ParsedSQL::Delete {
+            database, table, ..
+        } => push(
+            steps,
+            depth,
+            format!("{}{}", access(sql, index), qualified(database, table)),
+        ),
+        _ => return Result::Err("only a select or a delete has a query plan.".to_owned()),
+    }
+    for (index, subquery) in codegen::collect_subqueries(sql).into_iter().enumerate() {
+        push(steps, depth, format!("collect subquery {} before the scan", index + 1));
+        add_steps(subquery, None, depth + 1, steps)?;
+    }
+    if let ParsedSQL::Select {
+        operands,
+        group_by,
+        having,
+        limit,
+        ..
+    } = sql
+    {
+        let mut aggregates = Vec::new();
+        for op in operands.iter().chain(having) {
+            codegen::collect_aggregates(op, &mut aggregates);
+        }
+        if !group_by.is_empty() {
+            let keys: Vec<String> = group_by.iter().map(|op| format!("{}", op)).collect();
+            push(steps, depth, format!("group rows by {}", keys.join(", ")));
+        } else if !aggregates.is_empty() || having.is_some() {
+            push(steps, depth, "aggregate all rows into one group".to_owned());
+        }
+        if let Some(limit) = limit {
+            push(
+                steps,
+                depth,
+                format!("stop after {} rows, skipping {}", limit.count, limit.offset),
+            );
+        }
+    }
+    Result::Ok(())
+}
+
+/// the operand `id` is compared to with `=` in the `where` of a select or delete
+/// from the users table, either side of an `and` counts. a row is looked up by its
+/// key when the operand is an integer or a parameter, other operands leave the
+/// table to a scan. the rows of a created table are keyed by a row id and always
+/// scanned.
+pub fn primary_key(sql: &ParsedSQL) -> Option<&Operand> {
+    match read_table(sql)? {
+        (TABLE_NAME, condition) => compared_to(condition?, "id", is_key),
+        _ => None,
+    }
+}
+
+/**
+ * the index of the first column of the `where` of a select or delete from a table
+ * that is compared to a value with `=`, and that value. the value is a literal or
+ * a parameter, either side of an `and` counts. a statement looking its row up by
+ * the primary key searches no index.
+ **/
+pub fn indexed_key<'a>(
+    sql: &'a ParsedSQL,
+    indexes: &'a [IndexEntry],
+) -> Option<(&'a IndexEntry, &'a Operand)> {
+    let (_, condition) = read_table(sql)?;
+    if primary_key(sql).is_some() {
+        return None;
+    }
+    indexes.iter().find_map(|index| {
+        compared_to(condition?, &index.column, is_value).map(|value| (index, value))
+    })
+}
+
+/// the name of the table a select or delete reads and its `where`.
+fn read_table(sql: &ParsedSQL) -> Option<(&str, Option<&Operand>)> {
+    match sql {
+        ParsedSQL::Select {
+            table: Some(table),
+            condition,
+            ..
+        }
+        | ParsedSQL::Delete {
+            table, condition, ..
+        } => Some((table, condition.as_ref())),
+        _ => None,
+    }
+}
+
+/// the operand given column is compared to with `=` in a condition, if it passes
+/// the check.
+fn compared_to<'a>(
+    condition: &'a Operand,
+    name: &str,
+    check: fn(&Operand) -> bool,
+) -> Option<&'a Operand> {
+    match condition {
+        Operand::Parentheses(op) => compared_to(op, name, check),
+        Operand::And(left, right) => {
+            compared_to(left, name, check).or_else(|| compared_to(right, name, check))
+        }
+        Operand::Compare(Comparison::Equal, left, right) => {
+            match (left.as_ref(), right.as_ref()) {
+                (column, value) | (value, column) if is_column(column, name) && check(value) => {
+                    Some(value)
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn is_column(op: &Operand, column: &str) -> bool {
+    match op {
+        Operand::Column(name) | Operand::QualifiedColumn(_, name) => name == column,
+        _ => false,
+    }
+}
+
+fn is_key(op: &Operand) -> bool {
+    matches!(op, Operand::Integer(_) | Operand::Parameter(_))
+}
+
+fn is_value(op: &Operand) -> bool {
+    matches!(
+        op,
+        Operand::Integer(_)
+            | Operand::Float(_)
+            | Operand::Boolean(_)
+            | Operand::DateTime(_)
+            | Operand::String(_)
+            | Operand::Parameter(_)
+    )
+}
+
+/// how the rows of the table are read, the start of the step.
+fn access(sql: &ParsedSQL, index: Option<&IndexEntry>) -> String {
+    if let Some(key) = primary_key(sql) {
+        return format!("search by the primary key id = {} in table ", key);
+    }
+    match index.and_then(|index| indexed_key(sql, std::slice::from_ref(index))) {
+        Some((index, value)) => format!(
+            "search by the index {} {} = {} in table ",
+            index.name, index.column, value
+        ),
+        None => "scan table ".to_owned(),
+    }
+}
+
+fn push(steps: &mut Vec<String>, depth: usize, step: String) {
+    steps.push(format!("{}{}", "  ".repeat(depth), step));
+}
+
+fn qualified(database: &Option<String>, table: &str) -> String {
+    match database {
+        Some(database) => format!("{}.{}", database, table),
+        None => table.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sql;
+
+    fn plan(input: &str) -> Vec<String> {
+        query_plan(Some(&sql::parse(input.as_bytes()).unwrap()), None).unwrap()
+    }
+
+    #[test]
+    fn plans_show_scans_subqueries_and_groups() {
+        assert_eq!(plan("select 1"), vec!["constant row"]);
+        assert_eq!(
+            plan("select id from aux.users u where id in (select id from users) limit 2"),
+            vec![
+                "scan table aux.users as u",
+                "collect subquery 1 before the scan",
+                "  scan table users",
+                "stop after 2 rows, skipping 0",
+            ]
+        );
+        assert_eq!(
+            plan("select username, count(*) from (select * from users) t group by username"),
+            vec!["scan subquery as t", "  scan table users", "group rows by username"]
+        );
+        assert_eq!(
+            query_plan(Some(&sql::parse(b"create index i on users (id)").unwrap()), None),
+            Result::Err("only a select or a delete has a query plan.".to_owned())
+        );
+    }
+
+    #[test]
+    fn plans_look_rows_up_by_the_primary_key() {
+        assert_eq!(
+            plan("select * from users u where u.id = 2"),
+            vec!["search by the primary key id = 2 in table users as u"]
+        );
+        assert_eq!(
+            plan("delete from users where username = 'ada' and (?1 = id)"),
+            vec!["search by the primary key id = ?1 in table users"]
+        );
+        assert_eq!(
+            plan("select id from users where id = 2 or id = 3"),
+            vec!["scan table users"]
+        );
+        assert_eq!(plan("select id from users where id = 2.5"), vec!["scan table users"]);
+        assert_eq!(plan("delete from users where id > 2"), vec!["scan table users"]);
+    }
+
+    #[test]
+    fn plans_search_the_index_of_a_compared_column() {
+        let index = IndexEntry {
+            name: "by_name".to_owned(),
+            table: TABLE_NAME.to_owned(),
+            column: "username".to_owned(),
+            root: 2,
+        };
+        let plan = |input: &str| {
+            let sql = sql::parse(input.as_bytes()).unwrap();
+            let index = indexed_key(&sql, std::slice::from_ref(&index)).map(|(index, _)| index);
+            query_plan(Some(&sql), index).unwrap()
+        };
+        assert_eq!(
+            plan("select id from users where id > 2 and username = 'ada'"),
+            vec!["search by the index by_name username = 'ada' in table users"]
+        );
+        assert_eq!(
+            plan("delete from users where ?1 = username"),
+            vec!["search by the index by_name username = ?1 in table users"]
+        );
+        assert_eq!(
+            plan("select id from users where username = 'ada' and id = 2"),
+            vec!["search by the primary key id = 2 in table users"]
+        );
+        assert_eq!(
+            plan("select id from users where username = email"),
+            vec!["scan table users"]
+        );
+        assert_eq!(
+            plan("select id from users where username = 'ada' or id = 2"),
+            vec!["scan table users"]
+        );
+    }
+}
@@ -5,10 +5,13 @@ use std::cmp;
 use std::fmt;
 use super::codegen::OpCode;
 use super::codegen;
+use super::Value;
 
+#[derive(Clone)]
 pub struct RowBuf {
     buf: Vec<u8>,
-    column_types: Vec<SQLType>,
+    /// the types of the columns written, `None` for a null.
+    column_types: Vec<Option<SQLType>>,
     buf_index: usize,
 }
 
@@ -39,7 +42,11 @@ impl RowBuf {
         }
         let mut offset = 0;
         for i in 0..column_index {
-            let mut column_size = codegen::size_of(self.column_types[i]);
+            let mut column_size = match self.column_types[i] {
+                Some(column_type) => codegen::size_of(column_type),
+                // a null takes no bytes
+                None => continue,
+            };
             // check if this column is variable length encoded
             if column_size == 0 {
                 column_size =
@@ -52,7 +59,7 @@ impl RowBuf {
 
     pub fn write_int(&mut self, value: i64) {
         let column_size = codegen::size_of(SQLType::Integer);
-        self.column_types.push(SQLType::Integer);
+        self.column_types.push(Some(SQLType::Integer));
         self.resize(column_size);
         BigEndian::write_i64(
             self.buf.index_mut(RangeFrom {
@@ -68,10 +75,59 @@ impl RowBuf {
             .map(|offset| BigEndian::read_i64(self.buf.index(RangeFrom { start: offset })))
     }
 
+    pub fn write_float(&mut self, value: f64) {
+        let column_size = codegen::size_of(SQLType::Real);
+        self.column_types.push(Some(SQLType::Real));
+        self.resize(column_size);
+        BigEndian::write_f64(
+            self.buf.index_mut(RangeFrom {
+                start: self.buf_index,
+            }),
+            value,
+        );
+        self.buf_index += column_size;
+    }
+
+    pub fn read_float(&self, column_index: usize) -> Result<f64, String> {
+        self.column_offset(column_index)
+            .map(|offset| BigEndian::read_f64(self.buf.index(RangeFrom { start: offset })))
+    }
+
+    pub fn write_datetime(&mut self, value: i64) {
+        let column_size = codegen::size_of(SQLType::DateTime);
+        self.column_types.push(Some(SQLType::DateTime));
+        self.resize(column_size);
+        BigEndian::write_i64(
+            self.buf.index_mut(RangeFrom {
+                start: self.buf_index,
+            }),
+            value,
+        );
+        self.buf_index += column_size;
+    }
+
+    /// datetimes are stored like integers.
+    pub fn read_datetime(&self, column_index: usize) -> Result<i64, String> {
+        self.read_int(column_index)
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        let column_size = codegen::size_of(SQLType::Boolean);
+        self.column_types.push(Some(SQLType::Boolean));
+        self.resize(column_size);
+        self.buf[self.buf_index] = value as u8;
+        self.buf_index += column_size;
+    }
+
+    pub fn read_bool(&self, column_index: usize) -> Result<bool, String> {
+        self.column_offset(column_index)
+            .map(|offset| self.buf[offset] != 0)
+    }
+
     pub fn write_str(&mut self, value: &str) {
         let bytes = value.as_bytes();
         let num_bytes = bytes.len();
-        self.column_types.push(SQLType::String);
+        self.column_types.push(Some(SQLType::String));
         self.resize(num_bytes + 4);
 
         BigEndian::write_u32(
@@ -89,6 +145,37 @@ impl RowBuf {
         self.buf_index = index;
     }
 
+    pub fn write_null(&mut self) {
+        self.column_types.push(None);
+    }
+
+    pub fn write_value(&mut self, value: &Value) {
+        match value {
+            Value::Integer(v) => self.write_int(*v),
+            Value::Real(v) => self.write_float(*v),
+            Value::Boolean(v) => self.write_bool(*v),
+            Value::DateTime(v) => self.write_datetime(*v),
+            Value::Text(text) => self.write_str(text),
+            Value::Null => self.write_null(),
+        }
+    }
+
+    /// all values of the row.
+    pub fn values(&self) -> Result<Vec<Value>, String> {
+        let mut values = Vec::with_capacity(self.column_types.len());
+        for (column_index, column_type) in self.column_types.iter().enumerate() {
+            values.push(match column_type {
+                Some(SQLType::Integer) => Value::Integer(self.read_int(column_index)?),
+                Some(SQLType::Real) => Value::Real(self.read_float(column_index)?),
+                Some(SQLType::Boolean) => Value::Boolean(self.read_bool(column_index)?),
+                Some(SQLType::DateTime) => Value::DateTime(self.read_datetime(column_index)?),
+                Some(SQLType::String) => Value::Text(self.read_str(column_index)?),
+                None => Value::Null,
+            });
+        }
+        Result::Ok(values)
+    }
+
     pub fn read_str(&self, column_index: usize) -> Result<String, String> {
         self.column_offset(column_index).and_then(|offset| {
             let num_bytes =
@@ -112,7 +199,37 @@ impl fmt::Display for RowBuf {
                 line = format!("{}, ", line);
             }
             match self.column_types[column_index] {
-                SQLType::Integer => match self.read_int(column_index) {
+                None => {
+                    line = format!("{}NULL", line);
+                }
+                Some(SQLType::Integer) => match self.read_int(column_index) {
+                    Result::Ok(v) => {
+                        line = format!("{}{}", line, v);
+                    }
+                    Result::Err(str) => {
+                        line = format!("{}{}", line, &str);
+                        break;
+                    }
+                },
+                Some(SQLType::Real) => match self.read_float(column_index) {
+                    Result::Ok(v) => {
+                        line = format!("{}{:?}", line, v);
+                    }
+                    Result::Err(str) => {
+                        line = format!("{}{}", line, &str);
+                        break;
+                    }
+                },
+                Some(SQLType::DateTime) => match self.read_datetime(column_index) {
+                    Result::Ok(v) => {
+                        line = format!("{}'{}'", line, Value::DateTime(v));
+                    }
+                    Result::Err(str) => {
+                        line = format!("{}{}", line, &str);
+                        break;
+                    }
+                },
+                Some(SQLType::Boolean) => match self.read_bool(column_index) {
                     Result::Ok(v) => {
                         line = format!("{}{}", line, v);
                     }
@@ -121,7 +238,7 @@ impl fmt::Display for RowBuf {
                         break;
                     }
                 },
-                SQLType::String => match self.read_str(column_index) {
+                Some(SQLType::String) => match self.read_str(column_index) {
                     Result::Ok(str) => {
                         line = format!("{}'{}'", line, &str);
                     }
@@ -167,6 +284,54 @@ mod tests {
         assert_eq!(row_buf.read_str(101), Result::Ok("bar".to_owned()));
     }
 
+    #[test]
+    fn values_follow_the_written_columns() {
+        let mut row_buf = RowBuf::new();
+        row_buf.write_int(42);
+        row_buf.write_str("rdb");
+        assert_eq!(
+            row_buf.values(),
+            Result::Ok(vec![Value::Integer(42), Value::Text("rdb".to_owned())])
+        );
+    }
+
+    #[test]
+    fn can_read_float_from_row_buf() {
+        let mut row_buf = RowBuf::new();
+        row_buf.write_float(2.75);
+        row_buf.write_int(42);
+        assert_eq!(row_buf.read_float(0), Result::Ok(2.75));
+        assert_eq!(row_buf.values(), Result::Ok(vec![Value::Real(2.75), Value::Integer(42)]));
+    }
+
+    #[test]
+    fn can_read_bool_from_row_buf() {
+        let mut row_buf = RowBuf::new();
+        row_buf.write_bool(true);
+        row_buf.write_bool(false);
+        row_buf.write_str("rdb");
+        assert_eq!(
+            row_buf.values(),
+            Result::Ok(vec![Value::Boolean(true), Value::Boolean(false), Value::Text("rdb".to_owned())])
+        );
+    }
+
+    #[test]
+    fn nulls_take_no_bytes() {
+        let mut row_buf = RowBuf::new();
+        row_buf.write_null();
+        row_buf.write_int(1);
+        row_buf.write_null();
+        row_buf.write_str("rdb");
+        assert_eq!(row_buf.read_int(1), Result::Ok(1));
+        assert_eq!(row_buf.read_str(3), Result::Ok("rdb".to_owned()));
+        assert_eq!(
+            row_buf.values(),
+            Result::Ok(vec![Value::Null, Value::Integer(1), Value::Null, Value::Text("rdb".to_owned())])
+        );
+        assert_eq!(format!("{}", row_buf), "(NULL, 1, NULL, 'rdb')");
+    }
+
     #[test]
     fn can_read_string_from_row_buf() {
         let mut row_buf = RowBuf::new();
@@ -0,0 +1,62 @@
+//! the engine settings a `pragma NAME` reads and a `pragma NAME = VALUE` writes.
+//! a setting is looked up by its name while the statement is prepared, it runs
+//! against the table of the db.
+
+use table::Table;
+
+use super::Value;
+
+/// writes a setting, it checks the value given.
+pub type Setter = fn(&mut Table, i64) -> Result<(), String>;
+
+pub struct Setting {
+    pub name: &'static str,
+    pub get: fn(&Table) -> Value,
+    /// `None` for a setting that cannot be changed once the db is open.
+    pub set: Option<Setter>,
+}
+
+pub static SETTINGS: &[Setting] = &[
+    Setting {
+        name: "page_size",
+        get: page_size,
+        set: None,
+    },
+    Setting {
+        name: "cache_size",
+        get: cache_size,
+        set: Some(set_cache_size),
+    },
+    Setting {
+        name: "freelist_count",
+        get: freelist_count,
+        set: None,
+    },
+];
+
+pub fn lookup(name: &str) -> Option<&'static Setting> {
+    SETTINGS.iter().find(|setting| setting.name == name)
+}
+
+/// fixed when the db is created.
+fn page_size(table: &Table) -> Value {
+    Value::Integer(table.tree.pager.get_page_size() as i64)
+}
+
+/// the pages kept in the cache, 0 for no limit.
+fn cache_size(table: &Table) -> Value {
+    Value::Integer(table.tree.pager.get_db_option().cache_pages as i64)
+}
+
+fn set_cache_size(table: &mut Table, value: i64) -> Result<(), String> {
+    if value < 0 {
+        return Result::Err("cache_size takes a number of pages, 0 for no limit.".to_owned());
+    }
+    table.tree.pager.set_cache_pages(value as usize);
+    Result::Ok(())
+}
+
+/// the pages freed by deletes, reused before the db file grows.
+fn freelist_count(table: &Table) -> Value {
+    Value::Integer(table.tree.pager.num_free_pages() as i64)
+}
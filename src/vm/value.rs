@@ -0,0 +1,251 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use sql::datetime;
+
+/// a value of a result row.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Integer(i64),
+    Real(f64),
+    Boolean(bool),
+    /// seconds since 1970, shown as `YYYY-MM-DD HH:MM:SS`
+    DateTime(i64),
+    Text(String),
+    Null,
+}
+
+/// the bits reals are compared and hashed by, so values can key the groups and the
+/// values of subqueries. both zeros are the same real.
+fn real_bits(v: f64) -> u64 {
+    if v == 0.0 {
+        0
+    } else {
+        v.to_bits()
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Integer(v1), Value::Integer(v2)) => v1 == v2,
+            (Value::Real(v1), Value::Real(v2)) => real_bits(*v1) == real_bits(*v2),
+            (Value::Boolean(v1), Value::Boolean(v2)) => v1 == v2,
+            (Value::DateTime(v1), Value::DateTime(v2)) => v1 == v2,
+            (Value::Text(v1), Value::Text(v2)) => v1 == v2,
+            (Value::Null, Value::Null) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Value::Integer(v) => (0u8, v).hash(state),
+            Value::Real(v) => (1u8, real_bits(*v)).hash(state),
+            Value::Boolean(v) => (2u8, v).hash(state),
+            Value::DateTime(v) => (3u8, v).hash(state),
+            Value::Text(text) => (4u8, text).hash(state),
+            Value::Null => 5u8.hash(state),
+        }
+    }
+}
+
+impl Value {
+    /// the type of the value for error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Integer(_) => "integer",
+            Value::Real(_) => "real",
+            Value::Boolean(_) => "boolean",
+            Value::DateTime(_) => "datetime",
+            Value::Text(_) => "text",
+            Value::Null => "NULL",
+        }
+    }
+}
+
+/// what can be bound to the parameters of a statement.
+pub trait ToValue {
+    fn to_value(&self) -> Value;
+}
+
+impl ToValue for Value {
+    fn to_value(&self) -> Value {
+        self.clone()
+    }
+}
+
+impl ToValue for i64 {
+    fn to_value(&self) -> Value {
+        Value::Integer(*self)
+    }
+}
+
+impl ToValue for i32 {
+    fn to_value(&self) -> Value {
+        Value::Integer(*self as i64)
+    }
+}
+
+impl ToValue for u32 {
+    fn to_value(&self) -> Value {
+        Value::Integer(*self as i64)
+    }
+}
+
+impl ToValue for f64 {
+    fn to_value(&self) -> Value {
+        Value::Real(*self)
+    }
+}
+
+impl ToValue for bool {
+    fn to_value(&self) -> Value {
+        Value::Boolean(*self)
+    }
+}
+
+impl ToValue for &str {
+    fn to_value(&self) -> Value {
+        Value::Text((*self).to_owned())
+    }
+}
+
+impl ToValue for String {
+    fn to_value(&self) -> Value {
+        Value::Text(self.clone())
+    }
+}
+
+/// `None` binds NULL.
+impl<T: ToValue> ToValue for Option<T> {
+    fn to_value(&self) -> Value {
+        match self {
+            Some(value) => value.to_value(),
+            None => Value::Null,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Integer(v) => write!(f, "{}", v),
+            // with the fraction even if it is 0, `1.0` rather than `1`
+            Value::Real(v) => write!(f, "{:?}", v),
+            Value::Boolean(v) => write!(f, "{}", v),
+            Value::DateTime(v) => f.write_str(&datetime::format(*v)),
+            Value::Text(str) => write!(f, "{}", str),
+            Value::Null => write!(f, "NULL"),
+        }
+    }
+}
+
+/// what the values of a result row can be read as, see `RowView::get`.
+pub trait FromValue: Sized {
+    /// `None` if the value is of another type or out of range.
+    fn from_value(value: &Value) -> Option<Self>;
+
+    fn type_name() -> &'static str;
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Option<Value> {
+        Some(value.clone())
+    }
+
+    fn type_name() -> &'static str {
+        "a value"
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Option<i64> {
+        match value {
+            Value::Integer(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    fn type_name() -> &'static str {
+        "i64"
+    }
+}
+
+impl FromValue for i32 {
+    fn from_value(value: &Value) -> Option<i32> {
+        i64::from_value(value).and_then(|v| i32::try_from(v).ok())
+    }
+
+    fn type_name() -> &'static str {
+        "i32"
+    }
+}
+
+impl FromValue for u32 {
+    fn from_value(value: &Value) -> Option<u32> {
+        i64::from_value(value).and_then(|v| u32::try_from(v).ok())
+    }
+
+    fn type_name() -> &'static str {
+        "u32"
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Option<f64> {
+        match value {
+            Value::Real(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    fn type_name() -> &'static str {
+        "f64"
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Option<bool> {
+        match value {
+            Value::Boolean(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    fn type_name() -> &'static str {
+        "bool"
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Option<String> {
+        match value {
+            Value::Text(text) => Some(text.clone()),
+            _ => None,
+        }
+    }
+
+    fn type_name() -> &'static str {
+        "String"
+    }
+}
+
+/// NULL reads as `None`.
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Option<Option<T>> {
+        match value {
+            Value::Null => Some(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+
+    fn type_name() -> &'static str {
+        T::type_name()
+    }
+}
@@ -0,0 +1,60 @@
+//! the bindings of the `wasm` feature: javascript opens an in-memory db with
+//! `new Database()` and runs statements with `exec(sql)`.
+
+use wasm_bindgen::prelude::*;
+
+use database::{Connection, Database, Rows};
+use render::{self, Mode};
+use storage::MEMORY_DB;
+
+/// an in-memory db with a connection to it, `Database` in javascript.
+#[wasm_bindgen(js_name = Database)]
+pub struct WasmDatabase {
+    connection: Connection,
+}
+
+#[wasm_bindgen(js_class = Database)]
+impl WasmDatabase {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Result<WasmDatabase, JsValue> {
+        let database = Database::open(MEMORY_DB).map_err(|e| JsValue::from(String::from(e)))?;
+        Result::Ok(WasmDatabase {
+            connection: database.connect(),
+        })
+    }
+
+    /// run a statement, its rows come back as a JSON array of objects keyed by
+    /// the column names. errors are thrown as strings.
+    pub fn exec(&mut self, sql: &str) -> Result<String, JsValue> {
+        let rows = self.connection.query(sql).map_err(|e| JsValue::from(String::from(e)))?;
+        to_json(rows).map_err(JsValue::from)
+    }
+}
+
+/// the rows as a JSON array of the lines of the json mode.
+fn to_json(mut rows: Rows) -> Result<String, String> {
+    let mut out = Vec::new();
+    render::write_rows(Mode::Json, &mut rows, &mut out)?;
+    let lines = String::from_utf8(out).map_err(|e| format!("cannot render rows: {}", e))?;
+    Result::Ok(format!("[{}]", lines.lines().collect::<Vec<_>>().join(",")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statements_return_json_arrays() {
+        let mut db = WasmDatabase::new().unwrap();
+        assert_eq!(db.exec("insert 1 ada ada@example.com").unwrap(), "[]");
+        assert_eq!(db.exec("insert 2 bob bob@example.com").unwrap(), "[]");
+        assert_eq!(
+            db.exec("select").unwrap(),
+            concat!(
+                r#"[{"id":1,"username":"ada","email":"ada@example.com"},"#,
+                r#"{"id":2,"username":"bob","email":"bob@example.com"}]"#
+            )
+        );
+        assert_eq!(db.exec("select 1 + 1").unwrap(), r#"[{"1 + 1":2}]"#);
+    }
+}
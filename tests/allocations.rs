@@ -0,0 +1,87 @@
+//! counts the heap allocations of queries, a query over a table should only
+//! allocate per row for the values it returns.
+
+extern crate rdb;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rdb::internals::table::schema::Schema;
+use rdb::internals::table::{Row, Table};
+use rdb::internals::vm::{RowSink, Statement, Value};
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// drops the rows, so only the query allocates.
+struct Discard;
+
+impl RowSink for Discard {
+    fn columns(&mut self, _: &[String]) -> Result<(), String> {
+        Result::Ok(())
+    }
+
+    fn row(&mut self, _: &[Value]) -> Result<(), String> {
+        Result::Ok(())
+    }
+}
+
+fn table(num_rows: u32) -> Table {
+    let mut table = Table::in_memory_for_test();
+    for id in 1..=num_rows {
+        let row = Row {
+            id,
+            username: format!("user{}", id),
+            email: format!("person{}@example.com", id),
+        };
+        table.insert_cursor(id).save(&row).unwrap();
+    }
+    table
+}
+
+/// the allocations a query makes for every row of a table.
+fn allocations_per_row(sql: &str) -> f64 {
+    let mut allocations = Vec::new();
+    for &num_rows in &[1000, 2000] {
+        let table = table(num_rows);
+        let mut statement = Statement::prepare(sql, &Schema::new()).unwrap();
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        statement.query(&table, &mut Discard).unwrap();
+        allocations.push(ALLOCATIONS.load(Ordering::Relaxed) - before);
+    }
+    (allocations[1] - allocations[0]) as f64 / 1000.0
+}
+
+#[test]
+fn string_literals_are_not_copied_for_every_row() {
+    let integers = allocations_per_row("select 1, id from users");
+    // one allocation for every returned string, but no copy of the literal
+    assert_eq!(
+        allocations_per_row("select 'x', id from users"),
+        integers + 1.0
+    );
+    assert_eq!(
+        allocations_per_row("select 'x', 'x', 'y' from users"),
+        integers + 3.0
+    );
+    // the username is read from the row and returned
+    assert_eq!(
+        allocations_per_row("select 'x', username from users"),
+        integers + 3.0
+    );
+}
@@ -0,0 +1,49 @@
+//! builds tests/ffi/roundtrip.c against the static library and runs it.
+
+#![cfg(unix)]
+
+extern crate cc;
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// the system libraries the static library links to, see
+/// `rustc --print native-static-libs`.
+const NATIVE_LIBS: &[&str] = &["-lpthread", "-ldl", "-lm"];
+
+#[test]
+fn c_programs_embed_rdb() {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    // target/debug/deps/ffi-* sits next to target/debug/librdb.a
+    let exe = env::current_exe().unwrap();
+    let library = exe.parent().unwrap().parent().unwrap().join("librdb.a");
+    assert!(library.exists(), "{} was not built", library.display());
+    let program = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("ffi_roundtrip");
+
+    let compiler = cc::Build::new()
+        .target(env!("RDB_TARGET"))
+        .host(env!("RDB_TARGET"))
+        .opt_level(0)
+        .cargo_metadata(false)
+        .get_compiler();
+    let compiled = compiler
+        .to_command()
+        .arg("-I")
+        .arg(manifest_dir.join("include"))
+        .arg(manifest_dir.join("tests/ffi/roundtrip.c"))
+        .arg(&library)
+        .args(NATIVE_LIBS)
+        .arg("-o")
+        .arg(&program)
+        .status()
+        .unwrap();
+    assert!(compiled.success(), "cannot compile the C program");
+
+    let output = Command::new(&program).output().unwrap();
+    assert!(
+        output.status.success(),
+        "the C program failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
@@ -0,0 +1,94 @@
+/* a C program embedding rdb through include/rdb.h, run by tests/ffi.rs. it
+ * exits with 0 if every check passes and names the first failing one otherwise. */
+
+#include <stdio.h>
+#include <string.h>
+
+#include "rdb.h"
+
+#define CHECK(cond)                                                        \
+    do {                                                                   \
+        if (!(cond)) {                                                     \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond); \
+            return 1;                                                      \
+        }                                                                  \
+    } while (0)
+
+static int count_rows(void *userdata, int columns, char **values, char **names) {
+    int *rows = userdata;
+    (void)values;
+    if (columns != 3 || strcmp(names[1], "username") != 0) {
+        return 1;
+    }
+    *rows += 1;
+    return 0;
+}
+
+static int stop_at_first_row(void *userdata, int columns, char **values, char **names) {
+    (void)userdata;
+    (void)columns;
+    (void)values;
+    (void)names;
+    return 1;
+}
+
+int main(void) {
+    rdb *db = NULL;
+    rdb_stmt *stmt = NULL;
+    int rows = 0;
+    int id;
+
+    CHECK(rdb_open(":memory:", &db) == RDB_OK);
+    CHECK(rdb_exec(db, "insert 1 ada ada@example.com", NULL, NULL) == RDB_OK);
+
+    CHECK(rdb_prepare(db, "insert ? ? ?", &stmt) == RDB_OK);
+    for (id = 2; id <= 4; id++) {
+        CHECK(rdb_bind_int(stmt, 1, id) == RDB_OK);
+        CHECK(rdb_bind_text(stmt, 2, "grace") == RDB_OK);
+        CHECK(rdb_bind_text(stmt, 3, "grace@example.com") == RDB_OK);
+        CHECK(rdb_step(stmt) == RDB_DONE);
+    }
+    CHECK(rdb_bind_int(stmt, 4, 1) == RDB_RANGE);
+    CHECK(strcmp(rdb_errmsg(db), "parameter index 4 out of range, the statement has 3 parameters.") == 0);
+    CHECK(rdb_bind_int(stmt, -1, 1) == RDB_RANGE);
+    CHECK(strcmp(rdb_errmsg(db), "parameter index -1 out of range, the statement has 3 parameters.") == 0);
+    CHECK(rdb_bind_text(stmt, 2, "\xff") == RDB_MISUSE);
+    CHECK(rdb_step(stmt) == RDB_ERROR);
+    CHECK(strcmp(rdb_errmsg(db), "Error: Duplicate key.") == 0);
+    CHECK(rdb_finalize(stmt) == RDB_OK);
+
+    CHECK(rdb_prepare(db, "select", &stmt) == RDB_OK);
+    CHECK(rdb_column_count(stmt) == 3);
+    CHECK(rdb_step(stmt) == RDB_ROW);
+    CHECK(rdb_column_int(stmt, 0) == 1);
+    CHECK(strcmp(rdb_column_text(stmt, 1), "ada") == 0);
+    CHECK(strcmp(rdb_column_text(stmt, 0), "1") == 0);
+    CHECK(rdb_column_text(stmt, 3) == NULL);
+    CHECK(rdb_column_int(stmt, -1) == 0);
+    CHECK(rdb_column_text(stmt, -1) == NULL);
+    for (id = 2; id <= 4; id++) {
+        CHECK(rdb_step(stmt) == RDB_ROW);
+        CHECK(rdb_column_int(stmt, 0) == id);
+        CHECK(rdb_column_int(stmt, 1) == 0);
+    }
+    CHECK(rdb_step(stmt) == RDB_DONE);
+    /* a finished statement runs again */
+    CHECK(rdb_step(stmt) == RDB_ROW);
+    CHECK(rdb_finalize(stmt) == RDB_OK);
+
+    CHECK(rdb_exec(db, "select", count_rows, &rows) == RDB_OK);
+    CHECK(rows == 4);
+    CHECK(rdb_exec(db, "select", stop_at_first_row, NULL) == RDB_ABORT);
+
+    CHECK(rdb_prepare(db, "selec", &stmt) == RDB_ERROR);
+    CHECK(stmt == NULL);
+    CHECK(strcmp(rdb_errmsg(db), "Unrecognized command: selec") == 0);
+    CHECK(rdb_exec(NULL, "select", NULL, NULL) == RDB_MISUSE);
+    CHECK(rdb_step(NULL) == RDB_MISUSE);
+    CHECK(rdb_close(db) == RDB_OK);
+
+    CHECK(rdb_open("/nonexistent/dir/test.rdb", &db) == RDB_ERROR);
+    CHECK(strlen(rdb_errmsg(db)) > 0);
+    CHECK(rdb_close(db) == RDB_OK);
+    return 0;
+}
@@ -0,0 +1,117 @@
+//! replays inputs that once hung or crashed the parser, preparing a statement
+//! must fail with an error rather than panic or overflow the stack. see
+//! fuzz/fuzz_targets/prepare.rs for the fuzz target that looks for more.
+
+extern crate rdb;
+
+use rdb::internals::table::schema::Schema;
+use rdb::internals::vm::Statement;
+
+fn nasty_inputs() -> Vec<String> {
+    let mut inputs: Vec<String> = [
+        "",
+        "\0\0\0",
+        "select 'abc",
+        "select 'abc' + ",
+        "select 1 +",
+        "select 1 + 'a'",
+        "select -",
+        "select (",
+        "select )",
+        "select 99999999999999999999999",
+        "select * from",
+        "select 1 from aux.",
+        "select * from .users",
+        "insert",
+        "insert 1 'ab",
+        "insert 99999999999999999999 name email",
+        "insert into users values (",
+        "attach",
+        "attach database 'x.rdb'",
+        "detach a b",
+        "savepoint",
+        "release savepoint 1",
+        "rollback to",
+    ]
+    .iter()
+    .map(|input| input.to_string())
+    .collect();
+    inputs.push(String::from_utf8_lossy(b"select \xff\xfe\xfd").into_owned());
+    inputs.push(String::from_utf8_lossy(b"insert \xc3\x28 a b").into_owned());
+    inputs.push(format!("select {}", "(".repeat(100_000)));
+    inputs.push(format!(
+        "select {}1{}",
+        "(".repeat(100_000),
+        ")".repeat(100_000)
+    ));
+    inputs.push(format!("select 1{}", " +".repeat(100_000)));
+    inputs.push(format!("select 1{}", " + 1".repeat(3_000)));
+    inputs.push(format!("select {}1", "- ".repeat(100_000)));
+    inputs.push(format!("select 1 where {}1", "not ".repeat(100_000)));
+    inputs.push(format!("select {}", "'".repeat(100_001)));
+    inputs
+}
+
+#[test]
+fn nasty_inputs_are_rejected() {
+    let schema = Schema::new();
+    for input in nasty_inputs() {
+        let shown: String = input.chars().take(40).collect();
+        assert!(
+            Statement::prepare(&input, &schema).is_err(),
+            "{:?} was prepared",
+            shown
+        );
+    }
+}
+
+/// sql-ish token soup from a fixed seed, these may or may not prepare.
+#[test]
+fn token_soup_never_panics() {
+    let tokens = [
+        "select",
+        "insert",
+        "into",
+        "from",
+        "values",
+        "attach",
+        "database",
+        "as",
+        "detach",
+        "savepoint",
+        "release",
+        "rollback",
+        "to",
+        "*",
+        "+",
+        "-",
+        "(",
+        ")",
+        "'",
+        ",",
+        ".",
+        "1",
+        "9223372036854775807",
+        "users",
+        "aux",
+        " ",
+        "\n",
+        "é",
+    ];
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let schema = Schema::new();
+    for _ in 0..5_000 {
+        let mut input = String::new();
+        for _ in 0..(seed % 16) {
+            // xorshift64
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            input.push_str(tokens[(seed % tokens.len() as u64) as usize]);
+        }
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        let _ = Statement::prepare(&input, &schema);
+    }
+}
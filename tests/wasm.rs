@@ -0,0 +1,132 @@
+//! the library builds for wasm32 with the in-memory backend only, so no file,
+//! process or signal api creeps into it, and its bindings run statements under
+//! node. the native tests drive the wasm32 build and are ignored by default, they
+//! fail right away naming what is missing. the build check needs the wasm32
+//! target and runs in CI, see .travis.yml:
+//!
+//!     rustup target add wasm32-unknown-unknown
+//!     cargo test --test wasm -- --ignored builds_for_wasm32
+//!
+//! running the bindings needs `wasm-bindgen-test-runner` as well:
+//!
+//!     cargo install wasm-bindgen-cli
+//!     cargo test --test wasm -- --ignored
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use std::env;
+    use std::path::{Path, PathBuf};
+    use std::process::{Command, Output};
+
+    const TARGET: &str = "wasm32-unknown-unknown";
+    const RUNNER: &str = "wasm-bindgen-test-runner";
+
+    fn cargo() -> String {
+        env::var("CARGO").unwrap_or_else(|_| "cargo".to_owned())
+    }
+
+    fn target_installed() -> bool {
+        let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+        let sysroot = Command::new(rustc)
+            .args(["--print", "sysroot"])
+            .output()
+            .ok()
+            .and_then(|output| String::from_utf8(output.stdout).ok());
+        match sysroot {
+            Some(sysroot) => Path::new(sysroot.trim())
+                .join("lib/rustlib")
+                .join(TARGET)
+                .exists(),
+            None => false,
+        }
+    }
+
+    fn runner_installed() -> bool {
+        Command::new(RUNNER).arg("--version").output().is_ok()
+    }
+
+    /// run cargo for the wasm32 target, without the default features.
+    fn cargo_wasm32(command: &[&str], features: &[&str]) -> Output {
+        let target_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("wasm");
+        Command::new(cargo())
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .args(command)
+            .args(["--target", TARGET, "--no-default-features"])
+            .args(features)
+            .arg("--target-dir")
+            .arg(&target_dir)
+            .env("CARGO_TARGET_WASM32_UNKNOWN_UNKNOWN_RUNNER", RUNNER)
+            .output()
+            .unwrap()
+    }
+
+    fn assert_success(output: Output, what: &str) {
+        assert!(
+            output.status.success(),
+            "{} for {} failed:\n{}{}",
+            what,
+            TARGET,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    fn assert_target_installed() {
+        assert!(
+            target_installed(),
+            "the {} target is missing, add it with `rustup target add {}`",
+            TARGET,
+            TARGET
+        );
+    }
+
+    #[test]
+    #[ignore = "needs the wasm32-unknown-unknown target, run in CI"]
+    fn the_library_builds_for_wasm32() {
+        assert_target_installed();
+        assert_success(cargo_wasm32(&["check", "--lib"], &[]), "cargo check");
+    }
+
+    #[test]
+    #[ignore = "needs the wasm32-unknown-unknown target and wasm-bindgen-test-runner"]
+    fn the_bindings_run_statements_on_wasm32() {
+        assert_target_installed();
+        assert!(
+            runner_installed(),
+            "{} is missing, install it with `cargo install wasm-bindgen-cli`",
+            RUNNER
+        );
+        assert_success(
+            cargo_wasm32(&["test", "--test", "wasm"], &["--features", "wasm"]),
+            "cargo test",
+        );
+    }
+}
+
+/// the bindings as javascript calls them, run by `wasm-bindgen-test-runner`.
+#[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+mod bindings {
+    extern crate rdb;
+    extern crate wasm_bindgen_test;
+
+    use self::rdb::WasmDatabase;
+    use self::wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn exec_runs_statements() {
+        let mut db = WasmDatabase::new().unwrap();
+        assert_eq!(db.exec("insert 1 ada ada@example.com").unwrap(), "[]");
+        assert_eq!(
+            db.exec("select").unwrap(),
+            r#"[{"id":1,"username":"ada","email":"ada@example.com"}]"#
+        );
+        assert_eq!(db.exec("select 1 + 1").unwrap(), r#"[{"1 + 1":2}]"#);
+    }
+
+    #[wasm_bindgen_test]
+    fn exec_throws_errors() {
+        let mut db = WasmDatabase::new().unwrap();
+        let error = db.exec("select nope from users").unwrap_err();
+        assert_eq!(error.as_string().unwrap(), "no such column: nope");
+    }
+}